@@ -93,6 +93,24 @@ pub mod sp1 {
 
     use super::*;
 
+    pub struct SP1Prover<'a> {
+        binary: &'a [u8],
+    }
+    impl<'a> SP1Prover<'a> {
+        pub fn new(binary: &'a [u8]) -> Self {
+            Self { binary }
+        }
+    }
+
+    impl ClientSdkProver for SP1Prover<'_> {
+        fn prove(
+            &self,
+            contract_input: ContractInput,
+        ) -> Pin<Box<dyn std::future::Future<Output = Result<ProofData>> + Send + '_>> {
+            Box::pin(async move { prove(self.binary, &contract_input).map(|(proof, _)| proof) })
+        }
+    }
+
     pub fn execute(binary: &[u8], contract_input: &ContractInput) -> Result<HyleOutput> {
         let client = ProverClient::from_env();
         let mut stdin = SP1Stdin::new();
@@ -0,0 +1,350 @@
+//! High-level client of a node and its indexer, for applications sending transactions and
+//! following their settlement without handling the REST endpoints themselves.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+
+use sdk::{
+    api::{APIBlobTxSimulation, APISimulateBlobTx, APITransaction, TransactionStatus},
+    errors::{ErrorCode, HyleError},
+    BlobTransaction, Contract, ContractName, Hashable, HyleOutput, ProofTransaction, StateDigest,
+    TxHash,
+};
+
+use crate::rest_client::{IndexerApiHttpClient, NodeApiHttpClient};
+
+#[derive(Debug, Clone)]
+pub struct HyleClientConfig {
+    /// Timeout of each request
+    pub request_timeout: Duration,
+    /// Attempts of a request failing on a network error or an overloaded node
+    pub max_attempts: u32,
+    /// Delay before the first retry, doubled at each attempt
+    pub retry_delay: Duration,
+    /// Interval between two queries of the indexer while waiting for a settlement
+    pub poll_interval: Duration,
+}
+
+impl Default for HyleClientConfig {
+    fn default() -> Self {
+        Self {
+            request_timeout: Duration::from_secs(10),
+            max_attempts: 3,
+            retry_delay: Duration::from_millis(200),
+            poll_interval: Duration::from_millis(500),
+        }
+    }
+}
+
+pub struct HyleClient {
+    pub node: NodeApiHttpClient,
+    /// Needed to follow settlements and subscribe to transactions
+    pub indexer: Option<IndexerApiHttpClient>,
+    pub config: HyleClientConfig,
+}
+
+impl HyleClient {
+    pub fn new(node_url: String, indexer_url: Option<String>) -> Result<Self> {
+        Self::with_config(node_url, indexer_url, HyleClientConfig::default())
+    }
+
+    pub fn with_config(
+        node_url: String,
+        indexer_url: Option<String>,
+        config: HyleClientConfig,
+    ) -> Result<Self> {
+        let reqwest_client = reqwest::Client::builder()
+            .timeout(config.request_timeout)
+            .build()
+            .context("Building http client")?;
+        let mut node = NodeApiHttpClient::new(node_url)?;
+        node.reqwest_client = reqwest_client.clone();
+        let indexer = indexer_url
+            .map(|url| {
+                IndexerApiHttpClient::new(url).map(|mut indexer| {
+                    indexer.reqwest_client = reqwest_client;
+                    indexer
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            node,
+            indexer,
+            config,
+        })
+    }
+
+    fn indexer(&self) -> Result<&IndexerApiHttpClient> {
+        self.indexer
+            .as_ref()
+            .context("No indexer url given to the client")
+    }
+
+    /// Sends the transaction, retrying on transient failures.
+    /// A transaction the node already has, e.g. from an attempt whose answer was lost,
+    /// counts as sent.
+    pub async fn send_blob_tx(&self, tx: &BlobTransaction) -> Result<TxHash> {
+        let tx_hash = tx.hash();
+        self.retry("Sending blob transaction", || self.node.send_tx_blob(tx))
+            .await
+            .or_else(|e| already_sent(e, tx_hash))
+    }
+
+    /// Asks the node how the transaction would settle, given the outputs of its blobs that
+    /// the node doesn't verify natively.
+    pub async fn simulate_blob_tx(
+        &self,
+        tx: &BlobTransaction,
+        hyle_outputs: Vec<HyleOutput>,
+    ) -> Result<APIBlobTxSimulation> {
+        let query = APISimulateBlobTx {
+            tx: tx.clone(),
+            hyle_outputs,
+        };
+        self.retry("Simulating blob transaction", || {
+            self.node.simulate_tx_blob(&query)
+        })
+        .await
+    }
+
+    pub async fn send_proof_tx(&self, tx: &ProofTransaction) -> Result<TxHash> {
+        let tx_hash = tx.hash();
+        self.retry("Sending proof transaction", || self.node.send_tx_proof(tx))
+            .await
+            .or_else(|e| already_sent(e, tx_hash))
+    }
+
+    pub async fn get_contract(&self, contract_name: &ContractName) -> Result<Contract> {
+        self.retry("Getting contract", || self.node.get_contract(contract_name))
+            .await
+    }
+
+    /// The current state of the contract, decoded from its on-chain digest.
+    pub async fn get_contract_state<State>(&self, contract_name: &ContractName) -> Result<State>
+    where
+        State: TryFrom<StateDigest>,
+    {
+        let contract = self.get_contract(contract_name).await?;
+        contract
+            .state
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("Failed to decode state of contract {contract_name}"))
+    }
+
+    /// The transaction as known by the indexer, `None` if it wasn't indexed yet.
+    pub async fn get_transaction(&self, tx_hash: &TxHash) -> Result<Option<APITransaction>> {
+        let indexer = self.indexer()?;
+        match self
+            .retry("Getting transaction", || {
+                indexer.get_transaction_with_hash(tx_hash)
+            })
+            .await
+        {
+            Ok(tx) => Ok(Some(tx)),
+            Err(e) if error_code(&e) == Some(ErrorCode::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Polls the indexer until the blob transaction settles or times out on chain,
+    /// and returns its final status.
+    pub async fn poll_settlement(
+        &self,
+        tx_hash: &TxHash,
+        timeout: Duration,
+    ) -> Result<TransactionStatus> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(tx) = self.get_transaction(tx_hash).await? {
+                if is_final(&tx.transaction_status) {
+                    return Ok(tx.transaction_status);
+                }
+            }
+            if Instant::now() + self.config.poll_interval > deadline {
+                bail!("Transaction {tx_hash} not settled after {timeout:?}");
+            }
+            tokio::time::sleep(self.config.poll_interval).await;
+        }
+    }
+
+    /// Waits for the final status of the blob transaction. Without websocket support, or
+    /// without a contract of the transaction to subscribe to, the indexer is polled.
+    #[cfg(not(feature = "ws"))]
+    pub async fn wait_for_final_status(
+        &self,
+        tx_hash: &TxHash,
+        _contract_name: Option<&ContractName>,
+        timeout: Duration,
+    ) -> Result<TransactionStatus> {
+        self.poll_settlement(tx_hash, timeout).await
+    }
+
+    async fn retry<T, F, Fut>(&self, context_msg: &str, f: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = Result<T>>,
+    {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            match f().await {
+                Ok(res) => return Ok(res),
+                Err(e) if attempt < self.config.max_attempts && is_transient(&e) => {
+                    tracing::warn!("{context_msg} failed (attempt {attempt}): {e:#}");
+                    tokio::time::sleep(self.config.retry_delay * 2u32.pow(attempt - 1)).await;
+                }
+                Err(e) => return Err(e.context(context_msg.to_string())),
+            }
+        }
+    }
+}
+
+pub fn is_final(status: &TransactionStatus) -> bool {
+    matches!(
+        status,
+        TransactionStatus::Success | TransactionStatus::Failure | TransactionStatus::TimedOut
+    )
+}
+
+fn error_code(err: &anyhow::Error) -> Option<ErrorCode> {
+    err.downcast_ref::<HyleError>().map(|e| e.code)
+}
+
+/// Network errors, and answers of a node that is unavailable or overloaded
+fn is_transient(err: &anyhow::Error) -> bool {
+    if let Some(code) = error_code(err) {
+        let status = code.http_status();
+        return status >= 500 || status == 429;
+    }
+    err.chain()
+        .filter_map(|e| e.downcast_ref::<reqwest::Error>())
+        .any(|e| e.is_timeout() || e.is_connect() || e.is_request())
+}
+
+fn already_sent(err: anyhow::Error, tx_hash: TxHash) -> Result<TxHash> {
+    if error_code(&err) == Some(ErrorCode::DuplicateTransaction) {
+        return Ok(tx_hash);
+    }
+    Err(err)
+}
+
+#[cfg(feature = "ws")]
+mod ws {
+    use std::time::{Duration, Instant};
+
+    use anyhow::{bail, Context, Result};
+    use futures::{Stream, StreamExt};
+    use sdk::{
+        api::{APIValidatorEvent, TransactionStatus, TransactionWithBlobs},
+        ContractName, TxHash,
+    };
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::{is_final, HyleClient};
+
+    impl HyleClient {
+        /// Waits for the final status of the blob transaction, pushed by the indexer on the
+        /// websocket of `contract_name`. The indexer is still polled in case the
+        /// websocket misses it, and is the only source if the subscription fails.
+        pub async fn wait_for_final_status(
+            &self,
+            tx_hash: &TxHash,
+            contract_name: Option<&ContractName>,
+            timeout: Duration,
+        ) -> Result<TransactionStatus> {
+            let deadline = Instant::now() + timeout;
+            // Subscribing before the first poll, so that no status falls in between
+            let mut stream = match contract_name {
+                Some(contract_name) => match self.subscribe_contract_txs(contract_name).await {
+                    Ok(stream) => Some(Box::pin(stream)),
+                    Err(e) => {
+                        tracing::warn!("Falling back to polling for {tx_hash}: {e:#}");
+                        None
+                    }
+                },
+                None => None,
+            };
+            loop {
+                if let Some(tx) = self.get_transaction(tx_hash).await? {
+                    if is_final(&tx.transaction_status) {
+                        return Ok(tx.transaction_status);
+                    }
+                }
+                let now = Instant::now();
+                if now >= deadline {
+                    bail!("Transaction {tx_hash} not settled after {timeout:?}");
+                }
+                let wait = self.config.poll_interval.min(deadline - now);
+                let Some(txs) = stream.as_mut() else {
+                    tokio::time::sleep(wait).await;
+                    continue;
+                };
+                let pushed = async {
+                    while let Some(tx) = txs.next().await {
+                        match tx {
+                            Ok(tx)
+                                if &tx.tx_hash == tx_hash && is_final(&tx.transaction_status) =>
+                            {
+                                return Some(tx.transaction_status);
+                            }
+                            Ok(_) => {}
+                            Err(e) => tracing::warn!("Websocket message for {tx_hash}: {e:#}"),
+                        }
+                    }
+                    None
+                };
+                match tokio::time::timeout(wait, pushed).await {
+                    Ok(Some(status)) => return Ok(status),
+                    Ok(None) => {
+                        tracing::warn!("Websocket closed, falling back to polling for {tx_hash}");
+                        stream = None;
+                    }
+                    Err(_) => {}
+                }
+            }
+        }
+
+        /// Blob transactions on the contract, as the indexer processes them.
+        pub async fn subscribe_contract_txs(
+            &self,
+            contract_name: &ContractName,
+        ) -> Result<impl Stream<Item = Result<TransactionWithBlobs>>> {
+            self.subscribe(&format!(
+                "v1/indexer/blob_transactions/contract/{contract_name}/ws"
+            ))
+            .await
+        }
+
+        pub async fn subscribe_validator_events(
+            &self,
+        ) -> Result<impl Stream<Item = Result<APIValidatorEvent>>> {
+            self.subscribe("v1/indexer/validators/ws").await
+        }
+
+        async fn subscribe<T: serde::de::DeserializeOwned>(
+            &self,
+            endpoint: &str,
+        ) -> Result<impl Stream<Item = Result<T>>> {
+            let mut url = self.indexer()?.url.join(endpoint)?;
+            let scheme = if url.scheme() == "https" { "wss" } else { "ws" };
+            url.set_scheme(scheme)
+                .map_err(|_| anyhow::anyhow!("Invalid websocket url {url}"))?;
+            let (stream, _) = tokio_tungstenite::connect_async(url.as_str())
+                .await
+                .context(format!("Connecting to {url}"))?;
+            Ok(stream.filter_map(|msg| async move {
+                match msg {
+                    Ok(Message::Binary(data)) => {
+                        Some(serde_json::from_slice(&data).context("Decoding websocket message"))
+                    }
+                    Ok(Message::Text(text)) => Some(
+                        serde_json::from_str(text.as_str()).context("Decoding websocket message"),
+                    ),
+                    Ok(_) => None,
+                    Err(e) => Some(Err(e.into())),
+                }
+            }))
+        }
+    }
+}
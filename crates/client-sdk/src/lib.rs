@@ -1,5 +1,9 @@
 pub mod helpers;
 #[cfg(feature = "rest")]
+pub mod hyle_client;
+#[cfg(feature = "proving")]
+pub mod proving;
+#[cfg(feature = "rest")]
 pub mod rest_client;
 #[cfg(feature = "tcp")]
 pub mod tcp_client;
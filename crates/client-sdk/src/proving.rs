@@ -0,0 +1,186 @@
+//! Proving of many blobs at once: proofs are generated in parallel up to a limit shared by all
+//! the batches of the queue, and proofs of inputs that were already proved are reused.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use anyhow::{anyhow, Result};
+use sdk::{ContractInput, ContractName, ProgramId, ProofData, ProofTransaction};
+use sha3::{Digest, Sha3_256};
+use tokio::sync::Semaphore;
+
+use crate::helpers::ClientSdkProver;
+
+pub struct ProofJob {
+    pub contract_name: ContractName,
+    pub program_id: ProgramId,
+    pub contract_input: ContractInput,
+    pub prover: Arc<dyn ClientSdkProver + Sync + Send>,
+}
+
+/// Reported each time a job of a batch is done.
+#[derive(Debug, Clone)]
+pub struct ProvingProgress {
+    pub contract_name: ContractName,
+    /// Index of the job in its batch
+    pub index: usize,
+    /// Jobs of the batch done so far, this one included
+    pub done: usize,
+    pub total: usize,
+    /// The proof was reused from a previous job
+    pub cached: bool,
+    pub success: bool,
+}
+
+type CacheKey = (ProgramId, [u8; 32]);
+
+#[derive(Default)]
+struct ProofCache {
+    proofs: HashMap<CacheKey, ProofData>,
+    /// Insertion order, the oldest proofs are dropped first
+    order: VecDeque<CacheKey>,
+}
+
+#[derive(Clone)]
+pub struct ProvingQueue {
+    semaphore: Arc<Semaphore>,
+    cache: Arc<Mutex<ProofCache>>,
+    cache_size: usize,
+}
+
+impl ProvingQueue {
+    /// A queue running at most `max_parallel` proofs at a time, and keeping the last
+    /// `cache_size` proofs.
+    pub fn new(max_parallel: usize, cache_size: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(max_parallel.max(1))),
+            cache: Arc::new(Mutex::new(ProofCache::default())),
+            cache_size,
+        }
+    }
+
+    /// Proves the jobs and returns their proof transactions in the order of the jobs.
+    pub async fn prove_all<F>(
+        &self,
+        jobs: Vec<ProofJob>,
+        on_progress: F,
+    ) -> Vec<Result<ProofTransaction>>
+    where
+        F: Fn(&ProvingProgress) + Send + Sync + 'static,
+    {
+        let total = jobs.len();
+        let done = Arc::new(AtomicUsize::new(0));
+        let on_progress = Arc::new(on_progress);
+
+        let handles: Vec<_> = jobs
+            .into_iter()
+            .enumerate()
+            .map(|(index, job)| {
+                let contract_name = job.contract_name.clone();
+                let queue = self.clone();
+                let done = done.clone();
+                let on_progress = on_progress.clone();
+                let handle = tokio::spawn(async move {
+                    let (proof, cached) = queue
+                        .prove(
+                            &job.contract_name,
+                            job.program_id,
+                            job.contract_input,
+                            job.prover,
+                        )
+                        .await;
+                    on_progress(&ProvingProgress {
+                        contract_name: job.contract_name.clone(),
+                        index,
+                        done: done.fetch_add(1, Ordering::Relaxed) + 1,
+                        total,
+                        cached,
+                        success: proof.is_ok(),
+                    });
+                    proof.map(|proof| ProofTransaction {
+                        contract_name: job.contract_name,
+                        proof,
+                    })
+                });
+                (contract_name, handle)
+            })
+            .collect();
+
+        let mut results = Vec::with_capacity(total);
+        for (contract_name, handle) in handles {
+            results.push(match handle.await {
+                Ok(result) => result,
+                Err(e) => Err(anyhow!("Proving task for {contract_name} failed: {e}")),
+            });
+        }
+        results
+    }
+
+    /// The proof of the input, and whether it came from the cache.
+    async fn prove(
+        &self,
+        contract_name: &ContractName,
+        program_id: ProgramId,
+        contract_input: ContractInput,
+        prover: Arc<dyn ClientSdkProver + Sync + Send>,
+    ) -> (Result<ProofData>, bool) {
+        let key = match input_hash(&contract_input) {
+            Ok(hash) => (program_id, hash),
+            Err(e) => return (Err(e), false),
+        };
+        if let Some(proof) = self.cached(&key) {
+            return (Ok(proof), true);
+        }
+
+        let _permit = match self.semaphore.acquire().await {
+            Ok(permit) => permit,
+            Err(e) => return (Err(e.into()), false),
+        };
+        // An identical job may have finished while this one was waiting
+        if let Some(proof) = self.cached(&key) {
+            return (Ok(proof), true);
+        }
+        tracing::info!("Proving transition for {contract_name}...");
+        let proof = prover.prove(contract_input).await;
+        if let Ok(proof) = &proof {
+            self.cache_proof(key, proof.clone());
+        }
+        (proof, false)
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<ProofData> {
+        let cache = match self.cache.lock() {
+            Ok(cache) => cache,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        cache.proofs.get(key).cloned()
+    }
+
+    fn cache_proof(&self, key: CacheKey, proof: ProofData) {
+        if self.cache_size == 0 {
+            return;
+        }
+        let mut cache = match self.cache.lock() {
+            Ok(cache) => cache,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if cache.proofs.insert(key.clone(), proof).is_none() {
+            cache.order.push_back(key);
+        }
+        while cache.order.len() > self.cache_size {
+            if let Some(oldest) = cache.order.pop_front() {
+                cache.proofs.remove(&oldest);
+            }
+        }
+    }
+}
+
+fn input_hash(contract_input: &ContractInput) -> Result<[u8; 32]> {
+    let encoded = bincode::serde::encode_to_vec(contract_input, bincode::config::standard())?;
+    Ok(Sha3_256::digest(encoded).into())
+}
@@ -1,11 +1,18 @@
-use anyhow::{Context, Result};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
 use reqwest::Url;
 
 use sdk::{
-    api::*, BlobIndex, BlobTransaction, BlockHash, BlockHeight, ConsensusInfo, Contract,
-    ContractName, ProofTransaction, StateDigest, TxHash, UnsettledBlobTransaction,
+    api::*,
+    errors::{ErrorCode, HyleError, ProblemDetails},
+    BlobIndex, BlobTransaction, BlockHash, BlockHeight, ConsensusInfo, Contract, ContractName,
+    Hashable, ProofTransaction, SignedBlock, StateDigest, TxHash, UnsettledBlobTransaction,
 };
 
+/// Number of times a proof chunk upload is attempted before giving up
+const PROOF_CHUNK_RETRIES: u32 = 5;
+
 pub struct NodeApiHttpClient {
     pub url: Url,
     pub reqwest_client: reqwest::Client,
@@ -37,6 +44,97 @@ impl NodeApiHttpClient {
         self.post("v1/tx/send/proof", tx, "Sending tx proof").await
     }
 
+    /// How the transaction would settle with the given outputs, without sending it
+    pub async fn simulate_tx_blob(&self, query: &APISimulateBlobTx) -> Result<APIBlobTxSimulation> {
+        self.post("v1/tx/simulate/blob", query, "Simulating tx blob")
+            .await
+    }
+
+    /// Uploads the proof in chunks of `chunk_size` bytes, then sends the proof transaction.
+    /// Failed chunks are retried, and an interrupted upload of the same proof resumes
+    /// where the node left it.
+    pub async fn send_tx_proof_chunked(
+        &self,
+        tx: &ProofTransaction,
+        chunk_size: usize,
+    ) -> Result<TxHash> {
+        if chunk_size == 0 {
+            bail!("Chunk size must be positive");
+        }
+        let proof = &tx.proof.0;
+        let proof_hash = tx.proof.hash().0;
+        let endpoint = format!("v1/tx/send/proof/chunked/{proof_hash}");
+
+        let status: APIProofUploadStatus =
+            self.get(&endpoint, "getting proof upload status").await?;
+        let mut offset = status.received_bytes.min(proof.len());
+
+        while offset < proof.len() {
+            let end = (offset + chunk_size).min(proof.len());
+            #[allow(clippy::indexing_slicing, reason = "offset < end <= proof.len()")]
+            let chunk = &proof[offset..end];
+            offset = self
+                .upload_proof_chunk(&endpoint, offset, chunk)
+                .await
+                .context(format!("Uploading proof chunk at offset {offset}"))?;
+        }
+
+        self.post(
+            &format!("{endpoint}/finalize"),
+            &APIProofUploadFinalize {
+                contract_name: tx.contract_name.clone(),
+            },
+            "Finalizing proof upload",
+        )
+        .await
+    }
+
+    /// Returns the number of bytes the node has received after this chunk
+    async fn upload_proof_chunk(
+        &self,
+        endpoint: &str,
+        offset: usize,
+        chunk: &[u8],
+    ) -> Result<usize> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            let res = self
+                .reqwest_client
+                .put(format!("{}{}", self.url, endpoint))
+                .query(&[("offset", offset)])
+                .header("Content-Type", "application/octet-stream")
+                .body(chunk.to_vec())
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status());
+
+            match res {
+                Ok(resp) => {
+                    let status: APIProofUploadStatus = resp
+                        .json()
+                        .await
+                        .context("Failed to deserialize proof upload status")?;
+                    return Ok(status.received_bytes);
+                }
+                Err(e) if attempt < PROOF_CHUNK_RETRIES => {
+                    tracing::warn!("Proof chunk upload failed (attempt {attempt}): {e}");
+                    tokio::time::sleep(Duration::from_millis(200 * 2u64.pow(attempt))).await;
+                    // The node may have received more than we think, resume from there.
+                    if let Ok(status) = self
+                        .get::<APIProofUploadStatus>(endpoint, "getting proof upload status")
+                        .await
+                    {
+                        if status.received_bytes != offset {
+                            return Ok(status.received_bytes);
+                        }
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
     pub async fn get_consensus_info(&self) -> Result<ConsensusInfo> {
         self.get("v1/consensus/info", "getting consensus info")
             .await
@@ -70,6 +168,23 @@ impl NodeApiHttpClient {
         self.get("v1/da/block/height", "getting block height").await
     }
 
+    pub async fn get_block(&self, height: BlockHeight) -> Result<SignedBlock> {
+        self.get(
+            &format!("v1/da/block/{}", height),
+            &format!("getting block {}", height),
+        )
+        .await
+    }
+
+    /// Stored blocks from `from` to `to` excluded, at most 100 of them
+    pub async fn get_blocks(&self, from: BlockHeight, to: BlockHeight) -> Result<Vec<SignedBlock>> {
+        self.get(
+            &format!("v1/da/blocks?from={}&to={}", from, to),
+            &format!("getting blocks from {} to {}", from, to),
+        )
+        .await
+    }
+
     pub async fn get_contract(&self, contract_name: &ContractName) -> Result<Contract> {
         self.get(
             &format!("v1/contract/{}", contract_name),
@@ -93,10 +208,14 @@ impl NodeApiHttpClient {
     where
         T: serde::de::DeserializeOwned,
     {
-        self.reqwest_client
+        let resp = self
+            .reqwest_client
             .get(format!("{}{}", self.url, endpoint))
             .header("Content-Type", "application/json")
             .send()
+            .await
+            .context(format!("{} request failed", context_msg))?;
+        check_response(resp)
             .await
             .context(format!("{} request failed", context_msg))?
             .json::<T>()
@@ -109,11 +228,15 @@ impl NodeApiHttpClient {
         T: serde::Serialize,
         R: serde::de::DeserializeOwned,
     {
-        self.reqwest_client
+        let resp = self
+            .reqwest_client
             .post(format!("{}{}", self.url, endpoint))
             .body(serde_json::to_string(body)?)
             .header("Content-Type", "application/json")
             .send()
+            .await
+            .context(format!("{} request failed", context_msg))?;
+        check_response(resp)
             .await
             .context(format!("{} request failed", context_msg))?
             .json::<R>()
@@ -122,6 +245,20 @@ impl NodeApiHttpClient {
     }
 }
 
+/// Turns error responses, plain or `application/problem+json`, into a `HyleError` carrying
+/// the node's error code, which callers get back with `err.downcast_ref::<HyleError>()`.
+async fn check_response(resp: reqwest::Response) -> Result<reqwest::Response> {
+    let status = resp.status();
+    if status.is_success() {
+        return Ok(resp);
+    }
+    let body = resp.text().await.unwrap_or_default();
+    let err = serde_json::from_str::<HyleError>(&body)
+        .or_else(|_| serde_json::from_str::<ProblemDetails>(&body).map(HyleError::from))
+        .unwrap_or_else(|_| HyleError::new(ErrorCode::from_http_status(status.as_u16()), body));
+    Err(err.into())
+}
+
 impl IndexerApiHttpClient {
     pub fn new(url: String) -> Result<Self> {
         Ok(Self {
@@ -157,6 +294,10 @@ impl IndexerApiHttpClient {
         self.get("v1/indexer/blocks", "getting blocks").await
     }
 
+    pub async fn get_epochs(&self) -> Result<Vec<APIEpoch>> {
+        self.get("v1/indexer/epochs", "getting epochs").await
+    }
+
     pub async fn get_last_block(&self) -> Result<APIBlock> {
         self.get("v1/indexer/block/last", "getting last block")
             .await
@@ -213,6 +354,19 @@ impl IndexerApiHttpClient {
         .await
     }
 
+    /// Statuses of the given transactions, leaving out the ones the indexer doesn't know
+    pub async fn get_transactions_status(
+        &self,
+        tx_hashes: Vec<TxHash>,
+    ) -> Result<Vec<APITransactionStatus>> {
+        self.post(
+            "v1/indexer/transactions/status",
+            &APITransactionStatusQuery { tx_hashes },
+            "getting transactions status",
+        )
+        .await
+    }
+
     pub async fn get_blob_transactions_by_contract(
         &self,
         contract_name: &ContractName,
@@ -244,14 +398,38 @@ impl IndexerApiHttpClient {
     where
         T: serde::de::DeserializeOwned,
     {
-        self.reqwest_client
+        let resp = self
+            .reqwest_client
             .get(format!("{}{}", self.url, endpoint))
             .header("Content-Type", "application/json")
             .send()
+            .await
+            .context(format!("{} request failed", context_msg))?;
+        check_response(resp)
             .await
             .context(format!("{} request failed", context_msg))?
             .json::<T>()
             .await
             .context(format!("Failed to deserialize {}", context_msg))
     }
+
+    async fn post<T, R>(&self, endpoint: &str, body: &T, context_msg: &str) -> Result<R>
+    where
+        T: serde::Serialize,
+        R: serde::de::DeserializeOwned,
+    {
+        let resp = self
+            .reqwest_client
+            .post(format!("{}{}", self.url, endpoint))
+            .json(body)
+            .send()
+            .await
+            .context(format!("{} request failed", context_msg))?;
+        check_response(resp)
+            .await
+            .context(format!("{} request failed", context_msg))?
+            .json::<R>()
+            .await
+            .context(format!("Failed to deserialize {}", context_msg))
+    }
 }
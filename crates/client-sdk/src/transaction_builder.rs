@@ -1,18 +1,25 @@
 use std::{
     any::Any,
-    collections::BTreeMap,
+    collections::{BTreeMap, VecDeque},
     future::Future,
     ops::{Deref, DerefMut},
     sync::{Arc, OnceLock},
 };
 
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use sdk::{
     Blob, BlobIndex, BlobTransaction, ContractAction, ContractInput, ContractName, Hashable,
-    HyleOutput, Identity, ProofTransaction, StateDigest, TxContext,
+    HyleOutput, Identity, ProofTransaction, StateDigest, TxContext, TxHash,
 };
 
 use crate::helpers::{ClientSdkExecutor, ClientSdkProver};
+#[cfg(feature = "rest")]
+use crate::hyle_client::HyleClient;
+#[cfg(feature = "rest")]
+use sdk::api::{APIBlobTxSimulation, TransactionStatus};
+
+/// Outputs of at most this many processed transactions are kept for their settlement.
+const MAX_PROCESSED_OUTPUTS: usize = 1000;
 
 pub struct ProvableBlobTx {
     pub identity: Identity,
@@ -109,6 +116,36 @@ impl ProofTxBuilder {
         })
     }
 
+    /// Jobs to prove the blobs with a `ProvingQueue`, given the program of each contract.
+    #[cfg(feature = "proving")]
+    pub fn into_proof_jobs(
+        self,
+        program_ids: &BTreeMap<ContractName, sdk::ProgramId>,
+    ) -> Result<Vec<crate::proving::ProofJob>> {
+        self.runners
+            .into_iter()
+            .map(|mut runner| {
+                let contract_name = runner.contract_name;
+                Ok(crate::proving::ProofJob {
+                    program_id: program_ids
+                        .get(&contract_name)
+                        .cloned()
+                        .context(format!("No program id for contract {contract_name}"))?,
+                    contract_input: runner
+                        .contract_input
+                        .take()
+                        .context("Contract input not built")?,
+                    prover: self
+                        .provers
+                        .get(&contract_name)
+                        .cloned()
+                        .context(format!("No prover for contract {contract_name}"))?,
+                    contract_name,
+                })
+            })
+            .collect()
+    }
+
     pub fn to_blob_tx(&self) -> BlobTransaction {
         BlobTransaction {
             identity: self.identity.clone(),
@@ -132,6 +169,10 @@ pub struct TxExecutor<S: StateUpdater> {
     on_chain_states: BTreeMap<ContractName, StateDigest>,
     executors: BTreeMap<ContractName, Box<dyn ClientSdkExecutor + Sync + Send>>,
     provers: BTreeMap<ContractName, Arc<dyn ClientSdkProver + Sync + Send>>,
+    /// Outputs of the processed transactions, until their settlement is waited for
+    processed: VecDeque<(TxHash, Vec<(ContractName, HyleOutput)>)>,
+    #[cfg(feature = "rest")]
+    client: Option<Arc<HyleClient>>,
 }
 
 impl<S: StateUpdater> Deref for TxExecutor<S> {
@@ -152,6 +193,8 @@ pub struct TxExecutorBuilder<S> {
     on_chain_states: BTreeMap<ContractName, StateDigest>,
     executors: BTreeMap<ContractName, Box<dyn ClientSdkExecutor + Sync + Send>>,
     provers: BTreeMap<ContractName, Arc<dyn ClientSdkProver + Sync + Send>>,
+    #[cfg(feature = "rest")]
+    client: Option<Arc<HyleClient>>,
 }
 
 impl<S: StateUpdater> TxExecutorBuilder<S> {
@@ -161,6 +204,8 @@ impl<S: StateUpdater> TxExecutorBuilder<S> {
             on_chain_states: BTreeMap::new(),
             executors: BTreeMap::new(),
             provers: BTreeMap::new(),
+            #[cfg(feature = "rest")]
+            client: None,
         };
         full_states.setup(&mut ret);
         ret.full_states = Some(full_states);
@@ -174,6 +219,9 @@ impl<S: StateUpdater> TxExecutorBuilder<S> {
             on_chain_states: self.on_chain_states,
             executors: self.executors,
             provers: self.provers,
+            processed: VecDeque::new(),
+            #[cfg(feature = "rest")]
+            client: self.client,
         }
     }
 
@@ -218,9 +266,16 @@ impl<S: StateUpdater> TxExecutorBuilder<S> {
         self.provers.insert(contract_name, Arc::new(prover));
         self
     }
+
+    /// Client used to wait for the settlement of the processed transactions.
+    #[cfg(feature = "rest")]
+    pub fn with_client(mut self, client: Arc<HyleClient>) -> Self {
+        self.client = Some(client);
+        self
+    }
 }
 
-impl<S: StateUpdater> TxExecutor<S> {
+impl<S: StateUpdater + 'static> TxExecutor<S> {
     pub fn process_all<I>(
         &mut self,
         iter: I,
@@ -231,7 +286,11 @@ impl<S: StateUpdater> TxExecutor<S> {
         iter.into_iter().map(move |tx| self.process(tx))
     }
 
+    /// Executes the blobs of the transaction in order, each one seeing the states left by the
+    /// previous ones.
     pub fn process(&mut self, mut tx: ProvableBlobTx) -> Result<ProofTxBuilder> {
+        self.check_registered(&tx)?;
+
         let mut outputs = vec![];
         for runner in tx.runners.iter_mut() {
             let on_chain_state = self
@@ -241,7 +300,7 @@ impl<S: StateUpdater> TxExecutor<S> {
                 .ok_or(anyhow::anyhow!("State not found"))?;
             let full_state = self.full_states.get(&runner.contract_name)?;
 
-            let private_input = runner.private_input(&full_state)?;
+            let private_input = runner.private_input(&full_state, &self.full_states)?;
 
             runner.build_input(
                 tx.tx_context.clone(),
@@ -251,11 +310,16 @@ impl<S: StateUpdater> TxExecutor<S> {
             );
 
             tracing::info!("Checking transition for {}...", runner.contract_name);
-            let (mut full_state, out) = self
+            let executor = self
                 .executors
                 .get(&runner.contract_name)
-                .unwrap()
-                .execute(runner.contract_input.get().unwrap())?;
+                .context("Executor not found")?;
+            let (mut full_state, out) = executor.execute(
+                runner
+                    .contract_input
+                    .get()
+                    .context("Contract input not built")?,
+            )?;
 
             if !out.success {
                 let program_error = std::str::from_utf8(&out.program_outputs).unwrap();
@@ -272,6 +336,16 @@ impl<S: StateUpdater> TxExecutor<S> {
             outputs.push((runner.contract_name.clone(), out));
         }
 
+        let tx_hash = BlobTransaction {
+            identity: tx.identity.clone(),
+            blobs: tx.blobs.clone(),
+        }
+        .hash();
+        if self.processed.len() >= MAX_PROCESSED_OUTPUTS {
+            self.processed.pop_front();
+        }
+        self.processed.push_back((tx_hash, outputs.clone()));
+
         Ok(ProofTxBuilder {
             identity: tx.identity,
             blobs: tx.blobs,
@@ -280,15 +354,113 @@ impl<S: StateUpdater> TxExecutor<S> {
             provers: self.provers.clone(),
         })
     }
+
+    /// Fails before executing anything if a blob of the transaction targets a contract the
+    /// executor can't execute or prove, so that no state is updated for a partial transaction.
+    fn check_registered(&self, tx: &ProvableBlobTx) -> Result<()> {
+        for runner in tx.runners.iter() {
+            let contract_name = &runner.contract_name;
+            if !self.on_chain_states.contains_key(contract_name) {
+                bail!("No on-chain state registered for contract {contract_name}");
+            }
+            if !self.executors.contains_key(contract_name) {
+                bail!("No executor registered for contract {contract_name}");
+            }
+            if !self.provers.contains_key(contract_name) {
+                bail!("No prover registered for contract {contract_name}");
+            }
+            if runner.identity != tx.identity {
+                bail!(
+                    "Blob {} of contract {contract_name} has identity {} instead of {}",
+                    runner.index,
+                    runner.identity,
+                    tx.identity
+                );
+            }
+        }
+        Ok(())
+    }
 }
 
-#[allow(clippy::type_complexity)]
+/// Final status of a blob transaction, with the outputs of its blobs if it settled.
+#[cfg(feature = "rest")]
+#[derive(Debug, Clone)]
+pub struct Settlement {
+    pub status: TransactionStatus,
+    pub outputs: Vec<(ContractName, HyleOutput)>,
+}
+
+#[cfg(feature = "rest")]
+impl<S: StateUpdater> TxExecutor<S> {
+    /// Waits until a transaction processed by this executor settles, fails or times out.
+    /// The indexer websocket is followed when available, the indexer is polled otherwise.
+    pub async fn wait_for_settlement(
+        &mut self,
+        tx_hash: &TxHash,
+        timeout: std::time::Duration,
+    ) -> Result<Settlement> {
+        let Some(client) = self.client.clone() else {
+            bail!("No client given to the executor");
+        };
+        let position = self.processed.iter().position(|(hash, _)| hash == tx_hash);
+        let contract_name = position
+            .and_then(|i| self.processed.get(i))
+            .and_then(|(_, outputs)| outputs.first())
+            .map(|(contract_name, _)| contract_name.clone());
+        let status = client
+            .wait_for_final_status(tx_hash, contract_name.as_ref(), timeout)
+            .await?;
+        let outputs = position
+            .and_then(|i| self.processed.remove(i))
+            .map(|(_, outputs)| outputs)
+            .unwrap_or_default();
+        Ok(Settlement {
+            outputs: match status {
+                TransactionStatus::Success => outputs,
+                _ => vec![],
+            },
+            status,
+        })
+    }
+
+    /// Processes the transaction, then asks the node how it would settle with the outputs of
+    /// its blobs, to catch failures before paying for proving. The states of the executor are
+    /// updated as by [TxExecutor::process].
+    pub async fn simulate(
+        &mut self,
+        tx: ProvableBlobTx,
+    ) -> Result<(ProofTxBuilder, APIBlobTxSimulation)>
+    where
+        S: 'static,
+    {
+        let Some(client) = self.client.clone() else {
+            bail!("No client given to the executor");
+        };
+        let builder = self.process(tx)?;
+        let simulation = client
+            .simulate_blob_tx(
+                &builder.to_blob_tx(),
+                builder
+                    .outputs
+                    .iter()
+                    .map(|(_, output)| output.clone())
+                    .collect(),
+            )
+            .await?;
+        Ok((builder, simulation))
+    }
+}
+
+/// Builds the private input of a blob from the full state of its contract, and the full
+/// states of all the contracts of the executor.
+type PrivateInputCb = Box<dyn Fn(&Box<dyn Any>, &dyn Any) -> Result<Vec<u8>> + Send + Sync>;
+
 pub struct ContractRunner {
     pub contract_name: ContractName,
     identity: Identity,
     index: BlobIndex,
     contract_input: OnceLock<ContractInput>,
-    private_input_cb: Option<Box<dyn Fn(&Box<dyn Any>) -> Result<Vec<u8>> + Send + Sync>>,
+    private_input_cb: Option<PrivateInputCb>,
 }
 
 impl ContractRunner {
@@ -306,7 +478,7 @@ impl ContractRunner {
     where
         F: Fn(&T) -> Result<Vec<u8>> + Send + Sync + 'static,
     {
-        self.private_input_cb = Some(Box::new(move |a: &Box<dyn Any>| {
+        self.private_input_cb = Some(Box::new(move |a: &Box<dyn Any>, _: &dyn Any| {
             let a = a
                 .downcast_ref::<T>()
                 .expect("cannot cast full state to private input callback type");
@@ -315,10 +487,25 @@ impl ContractRunner {
         self
     }
 
-    fn private_input(&self, state: &Box<dyn Any>) -> Result<Vec<u8>> {
+    /// Like `with_private_input`, for a private input that depends on the states of other
+    /// contracts of the transaction. `S` is the full state of the `TxExecutor`.
+    pub fn with_private_input_from_states<S: Any, F>(&mut self, f: F) -> &mut Self
+    where
+        F: Fn(&S) -> Result<Vec<u8>> + Send + Sync + 'static,
+    {
+        self.private_input_cb = Some(Box::new(move |_: &Box<dyn Any>, states: &dyn Any| {
+            let Some(states) = states.downcast_ref::<S>() else {
+                bail!("Full states of the executor aren't of the private input callback type");
+            };
+            f(states)
+        }));
+        self
+    }
+
+    fn private_input(&self, state: &Box<dyn Any>, states: &dyn Any) -> Result<Vec<u8>> {
         self.private_input_cb
             .as_ref()
-            .map(|cb| cb(state))
+            .map(|cb| cb(state, states))
             .map_or(Ok(Default::default()), |v| v)
     }
 
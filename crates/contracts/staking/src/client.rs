@@ -1,12 +1,12 @@
 use std::any::Any;
 
 use client_sdk::{
-    helpers::{risc0::Risc0Prover, ClientSdkExecutor},
+    helpers::{risc0::Risc0Prover, ClientSdkExecutor, ClientSdkProver},
     transaction_builder::{ProvableBlobTx, StateUpdater, TxExecutorBuilder},
 };
 use sdk::{
-    api::APIStaking, utils::as_hyle_output, ContractName, Digestable, HyleOutput, StakingAction,
-    ValidatorPublicKey,
+    api::APIStaking, erc20::ERC20Action, utils::as_hyle_output, BlobIndex, ContractName,
+    Digestable, HyleOutput, StakingAction, ValidatorPublicKey,
 };
 
 use crate::{execute, state::Staking};
@@ -33,16 +33,28 @@ impl ClientSdkExecutor for StakingPseudoExecutor {
 }
 
 impl Staking {
+    /// Proves the staking transactions with RISC Zero.
     pub fn setup_builder<S: StateUpdater>(
         &self,
         contract_name: ContractName,
         builder: &mut TxExecutorBuilder<S>,
+    ) {
+        self.setup_builder_with_prover(contract_name, builder, Risc0Prover::new(STAKING_ELF));
+    }
+
+    /// Proves the staking transactions with `prover`, e.g. an `SP1Prover` of the contract
+    /// compiled for SP1, or a `TestProver` for nodes that don't verify proofs.
+    pub fn setup_builder_with_prover<S: StateUpdater>(
+        &self,
+        contract_name: ContractName,
+        builder: &mut TxExecutorBuilder<S>,
+        prover: impl ClientSdkProver + Sync + Send + 'static,
     ) {
         builder.init_with(
             contract_name,
             self.as_digest(),
             StakingPseudoExecutor {},
-            Risc0Prover::new(STAKING_ELF),
+            prover,
         );
     }
 }
@@ -55,6 +67,14 @@ impl From<Staking> for APIStaking {
             bonded: val.bonded,
             delegations: val.delegations,
             total_bond: val.total_bond,
+            emission: val.emission,
+            rewards_epoch: val.rewards_epoch,
+            rewards: val.rewards,
+            unbonding_delay: val.unbonding_delay,
+            unbondings: val.unbondings,
+            jail_duration: val.jail_duration,
+            jailed: val.jailed,
+            slashed: val.slashed,
         }
     }
 }
@@ -67,6 +87,14 @@ impl From<APIStaking> for Staking {
             bonded: val.bonded,
             delegations: val.delegations,
             total_bond: val.total_bond,
+            emission: val.emission,
+            rewards_epoch: val.rewards_epoch,
+            rewards: val.rewards,
+            unbonding_delay: val.unbonding_delay,
+            unbondings: val.unbondings,
+            jail_duration: val.jail_duration,
+            jailed: val.jailed,
+            slashed: val.slashed,
         }
     }
 }
@@ -106,3 +134,72 @@ pub fn delegate(
         .with_private_input(|state: &Staking| -> anyhow::Result<Vec<u8>> { Ok(state.to_bytes()) });
     Ok(())
 }
+
+/// Claims `amount` of the rewards accrued to the identity of the transaction,
+/// transferred by `token_contract_name` from the staking contract.
+pub fn claim_rewards(
+    builder: &mut ProvableBlobTx,
+    contract_name: ContractName,
+    token_contract_name: ContractName,
+    amount: u128,
+) -> anyhow::Result<()> {
+    let idx = builder.blobs.len();
+    builder
+        .add_action(
+            contract_name,
+            StakingAction::ClaimRewards { amount },
+            None,
+            Some(vec![BlobIndex(idx + 1)]),
+        )?
+        .with_private_input(|state: &Staking| -> anyhow::Result<Vec<u8>> { Ok(state.to_bytes()) });
+    builder.add_action(
+        token_contract_name,
+        ERC20Action::Transfer {
+            recipient: builder.identity.0.clone(),
+            amount,
+        },
+        Some(BlobIndex(idx)),
+        None,
+    )?;
+    Ok(())
+}
+
+pub fn unbond(
+    builder: &mut ProvableBlobTx,
+    contract_name: ContractName,
+    amount: u128,
+) -> anyhow::Result<()> {
+    builder
+        .add_action(contract_name, StakingAction::Unbond { amount }, None, None)?
+        .with_private_input(|state: &Staking| -> anyhow::Result<Vec<u8>> { Ok(state.to_bytes()) });
+    Ok(())
+}
+
+/// Withdraws `amount` of the unbonded stake of the identity of the transaction,
+/// transferred by `token_contract_name` from the staking contract.
+pub fn withdraw(
+    builder: &mut ProvableBlobTx,
+    contract_name: ContractName,
+    token_contract_name: ContractName,
+    amount: u128,
+) -> anyhow::Result<()> {
+    let idx = builder.blobs.len();
+    builder
+        .add_action(
+            contract_name,
+            StakingAction::Withdraw { amount },
+            None,
+            Some(vec![BlobIndex(idx + 1)]),
+        )?
+        .with_private_input(|state: &Staking| -> anyhow::Result<Vec<u8>> { Ok(state.to_bytes()) });
+    builder.add_action(
+        token_contract_name,
+        ERC20Action::Transfer {
+            recipient: builder.identity.0.clone(),
+            amount,
+        },
+        Some(BlobIndex(idx)),
+        None,
+    )?;
+    Ok(())
+}
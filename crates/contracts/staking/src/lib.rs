@@ -1,11 +1,11 @@
 use anyhow::Result;
 use sdk::{
-    caller::{CalleeBlobs, CallerCallee, ExecutionContext, MutCalleeBlobs},
+    caller::{CalleeBlobs, CallerCallee, CheckCalleeBlobs, ExecutionContext, MutCalleeBlobs},
     erc20::ERC20Action,
-    info, Blob, BlobIndex, ContractInput, Digestable, Identity, RunResult, StakingAction,
-    StructuredBlobData,
+    info, Blob, BlobIndex, BlockHeight, ContractInput, ContractName, Digestable, Identity,
+    RunResult, StakingAction, StructuredBlobData,
 };
-use state::Staking;
+use state::{Staking, HYLE_IDENTITY};
 
 #[cfg(feature = "client")]
 pub mod client;
@@ -15,6 +15,8 @@ pub mod state;
 pub struct StakingContract {
     exec_ctx: ExecutionContext,
     state: state::Staking,
+    /// Height of the block the transaction was sequenced in, if known
+    block_height: Option<BlockHeight>,
 }
 
 impl CallerCallee for StakingContract {
@@ -30,8 +32,21 @@ impl CallerCallee for StakingContract {
 }
 
 impl StakingContract {
-    pub fn new(exec_ctx: ExecutionContext, state: state::Staking) -> Self {
-        StakingContract { exec_ctx, state }
+    pub fn new(
+        exec_ctx: ExecutionContext,
+        state: state::Staking,
+        block_height: Option<BlockHeight>,
+    ) -> Self {
+        StakingContract {
+            exec_ctx,
+            state,
+            block_height,
+        }
+    }
+
+    fn block_height(&self) -> Result<BlockHeight, String> {
+        self.block_height
+            .ok_or("The block height is needed to unbond, withdraw or slash".to_string())
     }
 
     pub fn execute_action(
@@ -84,6 +99,47 @@ impl StakingContract {
                 self.state.delegate_to(self.caller().clone(), validator)
             }
             StakingAction::Distribute { claim: _ } => todo!(),
+            StakingAction::ClaimRewards { amount } => {
+                // The rewards are paid by a transfer from the staking contract to the caller
+                self.is_in_callee_blobs(
+                    &ContractName::new("hyllar"),
+                    ERC20Action::Transfer {
+                        recipient: self.caller().0.clone(),
+                        amount,
+                    },
+                )?;
+                self.state.claim_rewards(self.caller().clone(), amount)
+            }
+            StakingAction::Unbond { amount } => {
+                let height = self.block_height()?;
+                self.state.unbond(self.caller().clone(), amount, height)
+            }
+            StakingAction::Slash {
+                validator,
+                fraction,
+                evidence_hash,
+            } => {
+                if self.caller().0 != HYLE_IDENTITY {
+                    return Err(format!(
+                        "Only {HYLE_IDENTITY} can slash, not {}",
+                        self.caller()
+                    ));
+                }
+                let height = self.block_height()?;
+                self.state.slash(validator, fraction, evidence_hash, height)
+            }
+            StakingAction::Withdraw { amount } => {
+                let height = self.block_height()?;
+                // The unbonded stake is paid by a transfer from the staking contract to the caller
+                self.is_in_callee_blobs(
+                    &ContractName::new("hyllar"),
+                    ERC20Action::Transfer {
+                        recipient: self.caller().0.clone(),
+                        amount,
+                    },
+                )?;
+                self.state.withdraw(self.caller().clone(), amount, height)
+            }
         }
     }
 
@@ -112,7 +168,7 @@ pub fn execute(contract_input: ContractInput) -> RunResult<Staking> {
         };
     }
 
-    let (state, _): (Staking, _) =
+    let (mut state, _): (Staking, _) =
         bincode::decode_from_slice(input.private_input.as_slice(), bincode::config::standard())
             .expect("Failed to decode payload");
 
@@ -125,11 +181,17 @@ pub fn execute(contract_input: ContractInput) -> RunResult<Staking> {
         panic!("State mismatch");
     }
 
+    let block_height = input.tx_ctx.as_ref().map(|tx_ctx| tx_ctx.block_height);
+    if let Some(height) = block_height {
+        state.accrue_rewards(height);
+        state.release_jailed(height);
+    }
+
     let ctx = ExecutionContext {
         callees_blobs: callees_blobs.into(),
         caller,
     };
-    let mut contract = StakingContract::new(ctx, state);
+    let mut contract = StakingContract::new(ctx, state, block_height);
 
     let action = parsed_blob.data.parameters;
 
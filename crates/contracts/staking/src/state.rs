@@ -1,8 +1,11 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use anyhow::Result;
 use bincode::{Decode, Encode};
-use sdk::{info, BlockHeight, Digestable, Identity, StateDigest, ValidatorPublicKey};
+use sdk::{
+    info, BlockHeight, Digestable, EmissionSchedule, Identity, StateDigest, Unbonding,
+    ValidatorPublicKey,
+};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 
@@ -17,22 +20,77 @@ pub struct Staking {
     /// List of validators that are part of consensus
     pub(crate) bonded: Vec<ValidatorPublicKey>,
     pub(crate) total_bond: u128,
+
+    pub(crate) emission: EmissionSchedule,
+    /// Epochs before this one have had their rewards accrued
+    pub(crate) rewards_epoch: u64,
+    /// Rewards accrued to each delegator and not claimed yet
+    pub(crate) rewards: BTreeMap<Identity, u128>,
+
+    /// Blocks between an unbonding and its withdrawal
+    pub(crate) unbonding_delay: u64,
+    /// Unbonded stakes not withdrawn yet, oldest first
+    pub(crate) unbondings: BTreeMap<Identity, Vec<Unbonding>>,
+
+    /// Blocks a slashed validator is jailed for
+    pub(crate) jail_duration: u64,
+    /// Slashed validators with the first block at which they can bond again
+    pub(crate) jailed: BTreeMap<ValidatorPublicKey, BlockHeight>,
+    /// Hashes of the evidence validators were slashed for
+    pub(crate) slashed: BTreeSet<String>,
 }
 
 /// Minimal stake necessary to be part of consensus
 pub const MIN_STAKE: u128 = 32;
 
+/// Blocks an unbonded stake waits for before it can be withdrawn, unless configured otherwise
+pub const DEFAULT_UNBONDING_DELAY: u64 = 1000;
+
+/// Blocks a slashed validator can't bond for, unless configured otherwise
+pub const DEFAULT_JAIL_DURATION: u64 = 10_000;
+
+/// Slash fractions are in basis points of the stake
+pub const SLASH_FRACTION_BASE: u32 = 10_000;
+
+/// Fraction of the stake burnt when a validator signs conflicting consensus messages
+pub const EQUIVOCATION_SLASH_FRACTION: u32 = 1_000;
+
+/// Identity of the transactions sent on behalf of consensus, the only one allowed to slash
+pub const HYLE_IDENTITY: &str = "hyle.hyle";
+
 impl Staking {
     pub fn new() -> Self {
+        Self::with_emission(EmissionSchedule::default())
+    }
+
+    pub fn with_emission(emission: EmissionSchedule) -> Self {
         Staking {
             stakes: BTreeMap::new(),
             delegations: BTreeMap::new(),
             rewarded: BTreeMap::new(),
             bonded: Vec::new(),
             total_bond: 0,
+            emission,
+            rewards_epoch: 0,
+            rewards: BTreeMap::new(),
+            unbonding_delay: DEFAULT_UNBONDING_DELAY,
+            unbondings: BTreeMap::new(),
+            jail_duration: DEFAULT_JAIL_DURATION,
+            jailed: BTreeMap::new(),
+            slashed: BTreeSet::new(),
         }
     }
 
+    pub fn with_jail_duration(mut self, jail_duration: u64) -> Self {
+        self.jail_duration = jail_duration;
+        self
+    }
+
+    pub fn with_unbonding_delay(mut self, unbonding_delay: u64) -> Self {
+        self.unbonding_delay = unbonding_delay;
+        self
+    }
+
     pub fn bonded(&self) -> &Vec<ValidatorPublicKey> {
         &self.bonded
     }
@@ -43,12 +101,21 @@ impl Staking {
     pub fn is_bonded(&self, pubkey: &ValidatorPublicKey) -> bool {
         self.bonded.iter().any(|v| v == pubkey)
     }
+    pub fn is_jailed(&self, pubkey: &ValidatorPublicKey) -> bool {
+        self.jailed.contains_key(pubkey)
+    }
+    pub fn is_slashed_for(&self, evidence_hash: &str) -> bool {
+        self.slashed.contains(evidence_hash)
+    }
 
     /// Bond a staking validator
     pub fn bond(&mut self, validator: ValidatorPublicKey) -> Result<(), String> {
         if self.is_bonded(&validator) {
             return Err("Validator already bonded".to_string());
         }
+        if self.is_jailed(&validator) {
+            return Err("Validator is jailed".to_string());
+        }
 
         info!("🔐 Bonded validator {}", validator);
         if let Some(stake) = self.get_stake(&validator) {
@@ -64,6 +131,19 @@ impl Staking {
         }
     }
 
+    /// Removes a validator from consensus, the stake delegated to it stays staked.
+    pub fn leave(&mut self, validator: &ValidatorPublicKey) -> Result<(), String> {
+        if !self.is_bonded(validator) {
+            return Err("Validator is not bonded".to_string());
+        }
+        info!("👋 Validator {} left consensus", validator);
+        self.bonded.retain(|v| v != validator);
+        self.total_bond = self
+            .total_bond
+            .saturating_sub(self.get_stake(validator).unwrap_or(0));
+        Ok(())
+    }
+
     /// Compute f value
     pub fn compute_f(&self) -> u128 {
         self.total_bond().div_ceil(3)
@@ -111,6 +191,193 @@ impl Staking {
             .or_insert_with(|| vec![staker]);
         Ok("Delegated".to_string())
     }
+
+    /// Removes `amount` from the stake of `staker`, to be withdrawn once the unbonding delay
+    /// after `height` is over.
+    pub fn unbond(
+        &mut self,
+        staker: Identity,
+        amount: u128,
+        height: BlockHeight,
+    ) -> Result<String, String> {
+        let stake = self.stakes.get(&staker).copied().unwrap_or(0);
+        if amount == 0 || amount > stake {
+            return Err(format!("Cannot unbond {amount}, {stake} staked"));
+        }
+        info!("🔓 Unbonding {} from stake of {}", amount, staker);
+        if amount == stake {
+            self.stakes.remove(&staker);
+        } else {
+            self.stakes.insert(staker.clone(), stake - amount);
+        }
+        // The bonded validator this stake was delegated to loses that much voting power
+        let delegated_to_bonded = self.delegations.iter().any(|(validator, delegators)| {
+            delegators.contains(&staker) && self.is_bonded(validator)
+        });
+        if delegated_to_bonded {
+            self.total_bond = self.total_bond.saturating_sub(amount);
+        }
+        let unlock_height = BlockHeight(height.0.saturating_add(self.unbonding_delay));
+        self.unbondings.entry(staker).or_default().push(Unbonding {
+            amount,
+            unlock_height,
+        });
+        Ok(format!("Unbonded, withdrawable at {}", unlock_height.0))
+    }
+
+    pub fn unbondings(&self, staker: &Identity) -> &[Unbonding] {
+        self.unbondings
+            .get(staker)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Withdraws `amount` from the unbondings of `staker` whose delay is over at `height`.
+    pub fn withdraw(
+        &mut self,
+        staker: Identity,
+        amount: u128,
+        height: BlockHeight,
+    ) -> Result<String, String> {
+        let withdrawable: u128 = self
+            .unbondings(&staker)
+            .iter()
+            .filter(|u| u.unlock_height.0 <= height.0)
+            .map(|u| u.amount)
+            .sum();
+        if amount == 0 || amount > withdrawable {
+            return Err(format!(
+                "Cannot withdraw {amount}, {withdrawable} withdrawable"
+            ));
+        }
+        self.settle_withdrawal(staker, amount)
+    }
+
+    /// Removes `amount` from the oldest unbondings of `staker`, without checking the delay.
+    /// Used by the nodes tracking the state, the contract having checked it when executing.
+    pub fn settle_withdrawal(&mut self, staker: Identity, amount: u128) -> Result<String, String> {
+        let Some(unbondings) = self.unbondings.get_mut(&staker) else {
+            return Err(format!("No unbonding to withdraw for {staker}"));
+        };
+        info!("💸 {} withdrew {}", staker, amount);
+        let mut left = amount;
+        for unbonding in unbondings.iter_mut() {
+            let taken = unbonding.amount.min(left);
+            unbonding.amount -= taken;
+            left -= taken;
+            if left == 0 {
+                break;
+            }
+        }
+        unbondings.retain(|u| u.amount > 0);
+        if unbondings.is_empty() {
+            self.unbondings.remove(&staker);
+        }
+        if left > 0 {
+            return Err(format!("Withdrew {left} more than unbonded"));
+        }
+        Ok("Withdrawn".to_string())
+    }
+
+    /// Burns `fraction` of the stakes delegated to `validator`, pending unbondings included,
+    /// then unbonds it and jails it until `jail_duration` blocks after `height`.
+    pub fn slash(
+        &mut self,
+        validator: ValidatorPublicKey,
+        fraction: u32,
+        evidence_hash: String,
+        height: BlockHeight,
+    ) -> Result<String, String> {
+        if fraction == 0 || fraction > SLASH_FRACTION_BASE {
+            return Err(format!("Invalid slash fraction {fraction}"));
+        }
+        if self.slashed.contains(&evidence_hash) {
+            return Err(format!("Already slashed for evidence {evidence_hash}"));
+        }
+        info!(
+            "⚔️ Slashing {}/{} of the stake of validator {}",
+            fraction, SLASH_FRACTION_BASE, validator
+        );
+        let slash = |amount: &mut u128| {
+            *amount -= amount.saturating_mul(fraction as u128) / SLASH_FRACTION_BASE as u128;
+        };
+        let stake = self.get_stake(&validator).unwrap_or(0);
+        for delegator in self.delegations.get(&validator).into_iter().flatten() {
+            if let Some(amount) = self.stakes.get_mut(delegator) {
+                slash(amount);
+            }
+            if let Some(unbondings) = self.unbondings.get_mut(delegator) {
+                unbondings.iter_mut().for_each(|u| slash(&mut u.amount));
+            }
+        }
+        if self.is_bonded(&validator) {
+            self.bonded.retain(|v| v != &validator);
+            self.total_bond = self.total_bond.saturating_sub(stake);
+        }
+        let jailed_until = BlockHeight(height.0.saturating_add(self.jail_duration));
+        self.jailed.insert(validator, jailed_until);
+        self.slashed.insert(evidence_hash);
+        Ok(format!("Slashed, jailed until {}", jailed_until.0))
+    }
+
+    /// Releases the validators whose jail time is over at `height`.
+    pub fn release_jailed(&mut self, height: BlockHeight) {
+        self.jailed.retain(|_, until| until.0 > height.0);
+    }
+
+    pub fn rewards(&self, identity: &Identity) -> u128 {
+        self.rewards.get(identity).copied().unwrap_or(0)
+    }
+
+    /// Shares the emission of the epochs ended before `height` between the delegators,
+    /// proportionally to their stake.
+    /// Delegators are rewarded whether their validator is bonded or not, as the bonded
+    /// validators are tracked by consensus and not part of the on-chain state.
+    pub fn accrue_rewards(&mut self, height: BlockHeight) {
+        let epoch = self.emission.epoch(height);
+        if epoch <= self.rewards_epoch {
+            return;
+        }
+        let emitted = self.emission.emitted(self.rewards_epoch, epoch);
+        self.rewards_epoch = epoch;
+
+        let delegators: Vec<(Identity, u128)> = self
+            .delegations
+            .values()
+            .flatten()
+            .map(|delegator| {
+                let stake = self.stakes.get(delegator).copied().unwrap_or(0);
+                (delegator.clone(), stake)
+            })
+            .collect();
+        let total_stake: u128 = delegators.iter().map(|(_, stake)| stake).sum();
+        if total_stake == 0 {
+            return;
+        }
+        info!("🎁 Accrued {} rewards up to epoch {}", emitted, epoch);
+        for (delegator, stake) in delegators {
+            let reward = emitted.saturating_mul(stake) / total_stake;
+            if reward > 0 {
+                *self.rewards.entry(delegator).or_default() += reward;
+            }
+        }
+    }
+
+    pub fn claim_rewards(&mut self, claimer: Identity, amount: u128) -> Result<String, String> {
+        let available = self.rewards(&claimer);
+        if amount == 0 || amount > available {
+            return Err(format!(
+                "Cannot claim {amount} rewards, {available} available"
+            ));
+        }
+        info!("🎁 {} claimed {} rewards", claimer, amount);
+        if amount == available {
+            self.rewards.remove(&claimer);
+        } else {
+            self.rewards.insert(claimer, available - amount);
+        }
+        Ok("Rewards claimed".to_string())
+    }
 }
 
 impl Default for Staking {
@@ -141,6 +408,180 @@ impl Digestable for Staking {
                 hasher.update(i.0.to_le_bytes());
             }
         }
+        hasher.update(self.emission.epoch_length.to_le_bytes());
+        hasher.update(self.emission.initial_reward.to_le_bytes());
+        hasher.update(self.emission.halving_interval.to_le_bytes());
+        hasher.update(self.rewards_epoch.to_le_bytes());
+        for r in self.rewards.iter() {
+            hasher.update(&r.0 .0);
+            hasher.update(r.1.to_le_bytes());
+        }
+        hasher.update(self.unbonding_delay.to_le_bytes());
+        for u in self.unbondings.iter() {
+            hasher.update(&u.0 .0);
+            for i in u.1 {
+                hasher.update(i.amount.to_le_bytes());
+                hasher.update(i.unlock_height.0.to_le_bytes());
+            }
+        }
+        hasher.update(self.jail_duration.to_le_bytes());
+        for j in self.jailed.iter() {
+            hasher.update(&j.0 .0);
+            hasher.update(j.1 .0.to_le_bytes());
+        }
+        for e in self.slashed.iter() {
+            hasher.update(e.as_bytes());
+        }
         StateDigest(hasher.finalize().to_vec())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rewards_accrual_and_claim() {
+        let mut staking = Staking::with_emission(EmissionSchedule {
+            epoch_length: 10,
+            initial_reward: 300,
+            halving_interval: 2,
+        });
+        let validator = ValidatorPublicKey(vec![1]);
+        let (alice, bob) = (Identity::new("alice"), Identity::new("bob"));
+        staking.stake(alice.clone(), 100).unwrap();
+        staking.stake(bob.clone(), 200).unwrap();
+        staking
+            .delegate_to(alice.clone(), validator.clone())
+            .unwrap();
+
+        // Nothing emitted during the first epoch
+        staking.accrue_rewards(BlockHeight(9));
+        assert_eq!(staking.rewards(&alice), 0);
+
+        // Alice is the only delegator for the first epoch
+        staking.accrue_rewards(BlockHeight(10));
+        assert_eq!(staking.rewards(&alice), 300);
+        staking.delegate_to(bob.clone(), validator).unwrap();
+
+        // Epochs 1 to 3 emit 300 + 150 + 150, shared by stake
+        let digest = staking.as_digest();
+        staking.accrue_rewards(BlockHeight(45));
+        assert_ne!(staking.as_digest(), digest);
+        assert_eq!(staking.rewards(&alice), 500);
+        assert_eq!(staking.rewards(&bob), 400);
+        // Accrued once per epoch
+        staking.accrue_rewards(BlockHeight(49));
+        assert_eq!(staking.rewards(&bob), 400);
+
+        assert!(staking.claim_rewards(bob.clone(), 401).is_err());
+        staking.claim_rewards(bob.clone(), 150).unwrap();
+        assert_eq!(staking.rewards(&bob), 250);
+        staking.claim_rewards(bob.clone(), 250).unwrap();
+        assert!(!staking.rewards.contains_key(&bob));
+        assert!(staking.claim_rewards(bob, 1).is_err());
+    }
+
+    #[test]
+    fn test_unbond_and_withdraw() {
+        let mut staking = Staking::new().with_unbonding_delay(10);
+        let validator = ValidatorPublicKey(vec![1]);
+        let alice = Identity::new("alice");
+        staking.stake(alice.clone(), 100).unwrap();
+        staking
+            .delegate_to(alice.clone(), validator.clone())
+            .unwrap();
+        staking.bond(validator.clone()).unwrap();
+
+        assert!(staking.unbond(alice.clone(), 101, BlockHeight(5)).is_err());
+        staking.unbond(alice.clone(), 40, BlockHeight(5)).unwrap();
+        staking.unbond(alice.clone(), 20, BlockHeight(8)).unwrap();
+        assert_eq!(staking.get_stake(&validator), Some(40));
+        assert_eq!(staking.total_bond(), 40);
+        assert_eq!(
+            staking.unbondings(&alice),
+            &[
+                Unbonding {
+                    amount: 40,
+                    unlock_height: BlockHeight(15)
+                },
+                Unbonding {
+                    amount: 20,
+                    unlock_height: BlockHeight(18)
+                }
+            ]
+        );
+
+        // Only the unbondings past their delay can be withdrawn
+        assert!(staking
+            .withdraw(alice.clone(), 10, BlockHeight(14))
+            .is_err());
+        assert!(staking
+            .withdraw(alice.clone(), 50, BlockHeight(15))
+            .is_err());
+        staking
+            .withdraw(alice.clone(), 30, BlockHeight(15))
+            .unwrap();
+        staking
+            .withdraw(alice.clone(), 30, BlockHeight(18))
+            .unwrap();
+        assert!(staking.unbondings(&alice).is_empty());
+        assert!(staking.settle_withdrawal(alice, 1).is_err());
+    }
+
+    #[test]
+    fn test_slash_and_jail() {
+        let mut staking = Staking::new()
+            .with_unbonding_delay(10)
+            .with_jail_duration(100);
+        let validator = ValidatorPublicKey(vec![1]);
+        let alice = Identity::new("alice");
+        staking.stake(alice.clone(), 200).unwrap();
+        staking
+            .delegate_to(alice.clone(), validator.clone())
+            .unwrap();
+        staking.bond(validator.clone()).unwrap();
+        staking.unbond(alice.clone(), 100, BlockHeight(1)).unwrap();
+
+        assert!(staking
+            .slash(validator.clone(), 0, "evidence".into(), BlockHeight(5))
+            .is_err());
+        // A quarter of the stake is burnt, unbonding included
+        staking
+            .slash(validator.clone(), 2_500, "evidence".into(), BlockHeight(5))
+            .unwrap();
+        assert_eq!(staking.get_stake(&validator), Some(75));
+        assert_eq!(staking.unbondings(&alice)[0].amount, 75);
+        assert!(!staking.is_bonded(&validator));
+        assert_eq!(staking.total_bond(), 0);
+        // Once per evidence
+        assert!(staking
+            .slash(validator.clone(), 2_500, "evidence".into(), BlockHeight(6))
+            .is_err());
+
+        // Jailed validators can't bond until released
+        assert!(staking.bond(validator.clone()).is_err());
+        staking.release_jailed(BlockHeight(104));
+        assert!(staking.is_jailed(&validator));
+        staking.release_jailed(BlockHeight(105));
+        staking.bond(validator).unwrap();
+    }
+
+    #[test]
+    fn test_leave() {
+        let mut staking = Staking::new();
+        let validator = ValidatorPublicKey(vec![1]);
+        let alice = Identity::new("alice");
+        assert!(staking.leave(&validator).is_err());
+        staking.stake(alice.clone(), 100).unwrap();
+        staking.delegate_to(alice, validator.clone()).unwrap();
+        staking.bond(validator.clone()).unwrap();
+
+        staking.leave(&validator).unwrap();
+        assert!(!staking.is_bonded(&validator));
+        assert_eq!(staking.total_bond(), 0);
+        // The stake stays, the validator can bond again
+        assert_eq!(staking.get_stake(&validator), Some(100));
+        staking.bond(validator).unwrap();
+    }
+}
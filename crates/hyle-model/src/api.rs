@@ -1,12 +1,15 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
+use base64::prelude::*;
 use serde::{Deserialize, Serialize};
 use serde_with::serde_as;
 use utoipa::ToSchema;
 
 use crate::{
-    BlockHeight, ConsensusProposalHash, ContractName, Identity, ProgramId, StateDigest,
-    Transaction, TransactionData, TxHash, ValidatorPublicKey, Verifier,
+    BlobTransaction, BlockHeight, BlockProductionReason, ConsensusProposalHash, ContractName,
+    ContractStateProof, EmissionSchedule, Epoch, HyleOutput, Identity, ProgramId, StateDigest,
+    StateRoot, Transaction, TransactionData, TxContext, TxHash, TxInclusionProof, TxRoot,
+    Unbonding, ValidatorPublicKey, Verifier,
 };
 
 #[derive(Clone, Serialize, Deserialize, ToSchema)]
@@ -24,6 +27,264 @@ pub struct APIRegisterContract {
     pub contract_name: ContractName,
 }
 
+/// Blob transaction to simulate, with the outputs its blobs are expected to be proven with.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APISimulateBlobTx {
+    pub tx: BlobTransaction,
+    /// Outputs of the blobs the node doesn't verify natively, as computed by their executors
+    #[serde(default)]
+    pub hyle_outputs: Vec<HyleOutput>,
+}
+
+/// How a blob transaction would settle on the current contract states.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIBlobTxSimulation {
+    pub tx_hash: TxHash,
+    /// Whether the transaction would settle as a success, none if some blobs lack a valid output
+    pub success: Option<bool>,
+    /// Output settling each blob, or the first one given for blobs that don't settle
+    pub hyle_outputs: Vec<Option<HyleOutput>>,
+    /// Why some of the given outputs were rejected
+    pub errors: Vec<String>,
+}
+
+/// Whether every module running on the node answers on the bus.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APINodeHealth {
+    pub healthy: bool,
+    /// Modules running on the node, and whether they answered in time
+    pub modules: BTreeMap<String, bool>,
+}
+
+/// Sync status of the node. Values from modules that aren't running, or didn't answer, are unset.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APINodeStatus {
+    pub health: APINodeHealth,
+    /// Slot of the consensus
+    pub consensus_height: Option<BlockHeight>,
+    /// Height of the last block stored by the data availability module
+    pub da_height: Option<BlockHeight>,
+    /// Blocks the data availability module is behind consensus
+    pub da_lag: Option<u64>,
+    /// Bytes the blocks take on disk, as last measured
+    pub da_disk_usage: Option<u64>,
+    pub node_state_height: Option<BlockHeight>,
+    /// Height of the last indexed block
+    pub indexer_height: Option<BlockHeight>,
+    /// Blocks the indexer is behind the node state
+    pub indexer_lag: Option<u64>,
+    pub mempool_pending_txs: Option<usize>,
+    pub mempool_pending_bytes: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum APIModuleState {
+    /// Waiting for its dependencies, or getting ready
+    Starting,
+    Running,
+    /// Exited, and waiting to be restarted
+    Restarting,
+    /// Exited for good
+    Exited,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum APIMempoolTxStatus {
+    /// Received, not in a data proposal yet
+    Pending,
+    /// In a data proposal that no cut includes yet
+    Proposed,
+    /// In a data proposal included in a cut, on its way to a block
+    Included,
+}
+
+/// Whether the validator run by a node takes part in consensus, as set by its operator.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum APIValidatorMode {
+    /// Votes and proposes blocks whenever bonded
+    Active,
+    /// Follows the chain without voting nor proposing blocks
+    Stopped,
+    /// Stopped after handing over its duties to another key
+    HandedOver,
+}
+
+/// Handover of the duties of a validator to another key, once the new key is bonded.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIKeyRotation {
+    pub new_pubkey: ValidatorPublicKey,
+    /// The validator stops at the first slot from this one where the new key is bonded
+    pub handover_slot: u64,
+}
+
+/// Status of the validator run by a node.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIValidatorStatus {
+    pub pubkey: ValidatorPublicKey,
+    pub mode: APIValidatorMode,
+    /// Whether the validator is part of the bonded set
+    pub bonded: bool,
+    pub stake: Option<u128>,
+    /// Current consensus slot
+    pub slot: u64,
+    pub key_rotation: Option<APIKeyRotation>,
+    /// Validators currently bonded
+    pub validators: Vec<ValidatorPublicKey>,
+}
+
+/// Two conflicting consensus messages signed by the same validator for the same slot and view.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIEquivocationEvidence {
+    pub validator: ValidatorPublicKey,
+    pub slot: u64,
+    pub view: u64,
+    /// Kind of the conflicting messages: `prepare`, `prepare_vote` or `confirm_ack`
+    pub kind: String,
+    /// Both signed messages, bincode encoded in hex, so that their signatures can be checked
+    pub first: String,
+    pub second: String,
+    /// Slot of the node when it detected the equivocation
+    pub detected_at_slot: u64,
+}
+
+/// Transactions an external block builder wants in the node's next data proposal, in order.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIBlockBuilderProposal {
+    /// Block the proposal is for: it is used while this block is the next one, and dropped after
+    pub height: BlockHeight,
+    /// Hashes of transactions pending in the mempool, as listed by `/v1/mempool/txs`
+    pub tx_hashes: Vec<TxHash>,
+}
+
+/// A transaction held by the mempool.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIMempoolTx {
+    /// Proofs are stored, and hashed, once verified
+    pub tx_hash: TxHash,
+    pub contract_names: Vec<ContractName>,
+    pub status: APIMempoolTxStatus,
+    /// Lane of the data proposal holding the transaction
+    pub lane: Option<ValidatorPublicKey>,
+    pub data_proposal_hash: Option<String>,
+}
+
+/// Receive statistics of a bus channel, for one kind of client listening on it.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIBusChannel {
+    /// Type of the messages
+    pub message: String,
+    /// Bus client receiving them
+    pub client: String,
+    pub received: u64,
+    /// Messages the client missed because it fell too far behind
+    pub lagged: u64,
+    /// Messages left to read after the last one received
+    pub depth: usize,
+    pub max_depth: usize,
+}
+
+/// A module run by the node, as tracked by its modules handler.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIModuleStatus {
+    pub name: String,
+    pub state: APIModuleState,
+    /// Seconds since the module last started running
+    pub uptime: Option<u64>,
+    pub restarts: u32,
+    /// Error the module last exited with
+    pub last_error: Option<String>,
+}
+
+/// A change of the program and verifier of a contract.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIContractUpdate {
+    pub contract_name: String,
+    /// Transaction that scheduled the update
+    pub tx_hash: TxHash,
+    /// Block where the update took effect
+    pub block_hash: ConsensusProposalHash,
+    pub block_height: BlockHeight,
+    pub verifier: String,
+    #[serde_as(as = "serde_with::hex::Hex")]
+    pub program_id: Vec<u8>,
+}
+
+/// Validator set of an epoch, certifying the blocks from its first one until the next epoch.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIEpoch {
+    pub epoch: Epoch,
+    /// First block of the epoch
+    pub block_hash: ConsensusProposalHash,
+    pub start_height: BlockHeight,
+    /// Bonded validators and their stake
+    pub validators: BTreeMap<ValidatorPublicKey, u128>,
+}
+
+/// An identity that sent blob transactions, and the contracts it used.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIIdentity {
+    pub identity: String,
+    /// Block of the first blob transaction of the identity
+    pub first_seen_block_height: BlockHeight,
+    pub tx_count: u64,
+    pub contracts: Vec<String>,
+}
+
+/// Size of the proofs verified for a contract, in total and over the most recent blocks.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIContractProofStats {
+    pub contract_name: String,
+    pub proofs: u64,
+    pub proof_bytes: u64,
+    pub proof_outputs: u64,
+    /// Blocks with proofs for the contract, most recent first
+    pub blocks: Vec<APIBlockProofStats>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIBlockProofStats {
+    pub block_hash: ConsensusProposalHash,
+    pub block_height: BlockHeight,
+    pub proofs: u32,
+    pub proof_bytes: u64,
+    pub proof_outputs: u32,
+}
+
+/// Proof of a contract's state digest against the state root of a block.
+/// The state root is only attested by the node answering, see [StateRoot].
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIContractStateProof {
+    pub block_height: BlockHeight,
+    pub state_root: StateRoot,
+    pub proof: ContractStateProof,
+}
+
+/// Proof of a transaction's inclusion against the transaction root of a block.
+/// The transaction root is not signed by validators, see [TxRoot].
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APITxInclusionProof {
+    pub block_hash: ConsensusProposalHash,
+    pub block_height: BlockHeight,
+    pub tx_root: TxRoot,
+    pub proof: TxInclusionProof,
+}
+
+/// Progress of a chunked proof upload, identified by the proof's hash.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIProofUploadStatus {
+    pub proof_hash: String,
+    pub received_bytes: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct APIProofUploadFinalize {
+    pub contract_name: ContractName,
+}
+
 /// Copy from Staking contract
 #[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
 pub struct APIStaking {
@@ -36,6 +297,24 @@ pub struct APIStaking {
     /// List of validators that are part of consensus
     pub bonded: Vec<ValidatorPublicKey>,
     pub total_bond: u128,
+
+    pub emission: EmissionSchedule,
+    /// Epochs before this one have had their rewards accrued
+    pub rewards_epoch: u64,
+    /// Rewards accrued to each delegator and not claimed yet
+    pub rewards: BTreeMap<Identity, u128>,
+
+    /// Blocks between an unbonding and its withdrawal
+    pub unbonding_delay: u64,
+    /// Unbonded stakes not withdrawn yet
+    pub unbondings: BTreeMap<Identity, Vec<Unbonding>>,
+
+    /// Blocks a slashed validator is jailed for
+    pub jail_duration: u64,
+    /// Slashed validators with the first block at which they can bond again
+    pub jailed: BTreeMap<ValidatorPublicKey, BlockHeight>,
+    /// Hashes of the evidence validators were slashed for
+    pub slashed: BTreeSet<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
@@ -45,6 +324,8 @@ pub struct APIBlock {
     pub parent_hash: ConsensusProposalHash,
     pub height: u64,    // Corresponds to BlockHeight
     pub timestamp: i64, // UNIX timestamp
+    pub production_reason: BlockProductionReason,
+    pub state_root: StateRoot,
 }
 
 #[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
@@ -92,6 +373,20 @@ pub struct APITransaction {
     pub version: u32,                          // Transaction version
     pub transaction_type: TransactionType,     // Type of transaction
     pub transaction_status: TransactionStatus, // Status of the transaction
+    pub tx_context: Option<TxContext>, // Context of blob transactions, checked by their proofs
+}
+
+/// Transactions to look up the status of at once
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct APITransactionStatusQuery {
+    pub tx_hashes: Vec<TxHash>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct APITransactionStatus {
+    pub tx_hash: TxHash,
+    pub block_hash: ConsensusProposalHash,
+    pub transaction_status: TransactionStatus,
 }
 
 #[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq)]
@@ -104,17 +399,113 @@ pub struct TransactionWithBlobs {
     pub transaction_status: TransactionStatus,
     pub identity: String,
     pub blobs: Vec<BlobWithStatus>,
+    /// Context the transaction was sequenced with, which its proofs were verified against
+    pub tx_context: Option<TxContext>,
+}
+
+/// Change to the validator set or to stakes, as seen in a block.
+#[derive(Serialize, Deserialize, ToSchema, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub enum APIValidatorEvent {
+    Bonded {
+        block_height: BlockHeight,
+        validator: ValidatorPublicKey,
+    },
+    Staked {
+        block_height: BlockHeight,
+        identity: Identity,
+        amount: u128,
+    },
+    Delegated {
+        block_height: BlockHeight,
+        identity: Identity,
+        validator: ValidatorPublicKey,
+    },
+    Unbonded {
+        block_height: BlockHeight,
+        identity: Identity,
+        amount: u128,
+    },
+    Withdrawn {
+        block_height: BlockHeight,
+        identity: Identity,
+        amount: u128,
+    },
+    Slashed {
+        block_height: BlockHeight,
+        validator: ValidatorPublicKey,
+        fraction: u32,
+        evidence_hash: String,
+    },
+    /// The validator left consensus after handing its duties over to another key
+    Left {
+        block_height: BlockHeight,
+        validator: ValidatorPublicKey,
+    },
 }
 
-#[serde_as]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct BlobWithStatus {
     pub contract_name: String, // Contract name associated with the blob
-    #[serde_as(as = "serde_with::hex::Hex")]
-    pub data: Vec<u8>, // Actual blob data
+    #[serde(flatten)]
+    pub data: EncodedBlobData, // Actual blob data
     pub proof_outputs: Vec<serde_json::Value>, // outputs of proofs
 }
 
+/// How the indexer writes blob data in its responses
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BlobDataEncoding {
+    #[default]
+    Hex,
+    Base64,
+    /// The data is left out, only its size is given
+    None,
+}
+
+/// Blob data as served by the indexer, possibly truncated to keep responses small.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct EncodedBlobData {
+    /// Absent with [BlobDataEncoding::None]
+    pub data: Option<String>,
+    pub data_encoding: BlobDataEncoding,
+    /// Size of the whole blob data, in bytes
+    pub data_size: usize,
+    /// Set when `data` only holds the first bytes of the blob
+    pub data_truncated: bool,
+}
+
+impl EncodedBlobData {
+    /// Encodes the data, keeping at most `max_size` bytes of it.
+    pub fn new(data: &[u8], encoding: BlobDataEncoding, max_size: Option<usize>) -> Self {
+        let kept = max_size
+            .and_then(|max_size| data.get(..max_size))
+            .unwrap_or(data);
+        EncodedBlobData {
+            data: match encoding {
+                BlobDataEncoding::Hex => Some(hex::encode(kept)),
+                BlobDataEncoding::Base64 => Some(BASE64_STANDARD.encode(kept)),
+                BlobDataEncoding::None => None,
+            },
+            data_encoding: encoding,
+            data_size: data.len(),
+            data_truncated: kept.len() < data.len(),
+        }
+    }
+
+    /// Bytes held by `data`, empty when it was left out.
+    pub fn decode(&self) -> anyhow::Result<Vec<u8>> {
+        let Some(data) = &self.data else {
+            return Ok(vec![]);
+        };
+        Ok(match self.data_encoding {
+            BlobDataEncoding::Hex => hex::decode(data)?,
+            BlobDataEncoding::Base64 => BASE64_STANDARD.decode(data)?,
+            BlobDataEncoding::None => vec![],
+        })
+    }
+}
+
 #[serde_as]
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
 pub struct APIContract {
@@ -136,12 +527,59 @@ pub struct APIContractState {
     pub state_digest: Vec<u8>,             // The contract state stored in JSON format
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema, Clone, PartialEq, Eq)]
+pub struct APISettlementSummary {
+    pub contract_name: String,
+    pub settled_blobs: u32,
+    pub failed_txs: u32,
+    pub initial_state_digest: Option<Vec<u8>>, // None if the contract was registered in this block
+    pub final_state_digest: Option<Vec<u8>>,
+}
+
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct APIBlob {
     pub tx_hash: TxHash,       // Corresponds to the transaction hash
     pub blob_index: u32,       // Index of the blob within the transaction
     pub identity: String,      // Identity of the blob
     pub contract_name: String, // Contract name associated with the blob
-    pub data: Vec<u8>,         // Actual blob data
+    #[serde(flatten)]
+    pub data: EncodedBlobData, // Actual blob data
     pub verified: bool,        // Verification status
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encoded_blob_data() {
+        let data = b"blob data".to_vec();
+        for encoding in [BlobDataEncoding::Hex, BlobDataEncoding::Base64] {
+            let encoded = EncodedBlobData::new(&data, encoding, None);
+            assert!(!encoded.data_truncated);
+            assert_eq!(encoded.decode().unwrap(), data);
+
+            let truncated = EncodedBlobData::new(&data, encoding, Some(4));
+            assert!(truncated.data_truncated);
+            assert_eq!(truncated.data_size, data.len());
+            assert_eq!(truncated.decode().unwrap(), b"blob".to_vec());
+        }
+        let left_out = EncodedBlobData::new(&data, BlobDataEncoding::None, None);
+        assert_eq!(left_out.data, None);
+        assert_eq!(left_out.data_size, data.len());
+        assert_eq!(
+            serde_json::to_value(EncodedBlobData::new(
+                &data,
+                BlobDataEncoding::Hex,
+                Some(100)
+            ))
+            .unwrap(),
+            serde_json::json!({
+                "data": hex::encode(&data),
+                "data_encoding": "hex",
+                "data_size": 9,
+                "data_truncated": false,
+            })
+        );
+    }
+}
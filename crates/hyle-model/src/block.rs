@@ -19,15 +19,47 @@ pub struct Block {
     pub blob_proof_outputs: Vec<HandledBlobProofOutput>,
     pub verified_blobs: Vec<(TxHash, BlobIndex, Option<usize>)>,
     pub new_bounded_validators: Vec<ValidatorPublicKey>,
+    /// Validators that left consensus after handing their duties over to another key
+    pub left_validators: Vec<ValidatorPublicKey>,
     pub staking_actions: Vec<(Identity, StakingAction)>,
     pub registered_contracts: Vec<(TxHash, RegisterContractEffect)>,
+    /// Contract updates taking effect in this block, with the transaction that scheduled them
+    pub updated_contracts: Vec<(TxHash, UpdateContractEffect)>,
     pub updated_states: BTreeMap<ContractName, StateDigest>,
+    /// Settlement activity of the block, for each contract involved
+    pub contract_stats: BTreeMap<ContractName, ContractSettlementStats>,
+    /// Root over the state digests of all contracts once the block is processed, see [ContractsMerkleTree].
+    /// Computed by this node, validators don't sign it: it can't be verified from the header.
+    pub state_root: StateRoot,
+    pub production_reason: BlockProductionReason,
+    /// Validator set of the epoch starting at this block
+    pub epoch: Option<EpochSnapshot>,
+}
+
+/// Settlement activity of a contract in a block. A transaction counts for each contract it has blobs for.
+#[derive(Debug, Default, Clone, Serialize, Deserialize, Encode, Decode, Eq, PartialEq)]
+pub struct ContractSettlementStats {
+    pub settled_txs: u32,
+    pub failed_txs: u32,
+    pub timed_out_txs: u32,
+    /// Blob proof outputs verified for the contract's blobs
+    pub verified_proofs: u32,
 }
 
 impl Block {
     pub fn total_txs(&self) -> usize {
         self.txs.len()
     }
+
+    /// Context the blob transactions of this block are sequenced with, that their proofs must match.
+    pub fn tx_context(&self) -> TxContext {
+        TxContext {
+            block_hash: self.hash.clone(),
+            block_height: self.block_height,
+            timestamp: self.block_timestamp.into(),
+            chain_id: HYLE_TESTNET_CHAIN_ID,
+        }
+    }
 }
 
 impl Ord for Block {
@@ -66,6 +98,16 @@ impl SignedBlock {
             .flat_map(|dp| dp.txs.clone())
             .collect()
     }
+
+    /// Merkle tree over the transactions of the block, see [TxsMerkleTree].
+    pub fn txs_tree(&self) -> TxsMerkleTree {
+        TxsMerkleTree::new(
+            self.data_proposals
+                .iter()
+                .flat_map(|(_, dps)| dps)
+                .flat_map(|dp| dp.txs.iter().map(|tx| tx.hash())),
+        )
+    }
 }
 impl Hashable<ConsensusProposalHash> for SignedBlock {
     fn hash(&self) -> ConsensusProposalHash {
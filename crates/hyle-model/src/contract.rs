@@ -414,6 +414,81 @@ impl ContractAction for RegisterContractAction {
     }
 }
 
+/// Blocks between the settlement of a contract update and the update taking effect,
+/// so that users of the contract can react to it.
+pub const CONTRACT_UPDATE_DELAY: u64 = 100;
+
+/// Changes the program and verifier of a registered contract, keeping its state.
+/// It is sent as a blob to the 'hyle' contract, in a transaction that also has a blob for the
+/// updated contract, whose current program has to approve the update: its output for that blob
+/// must list the [approval](UpdateContractAction::approval) in its `registered_contracts`.
+/// Contracts whose program never outputs it can't be updated.
+/// The update takes effect [CONTRACT_UPDATE_DELAY] blocks after the transaction settles.
+///
+/// Blobs for 'hyle' are first read as a [RegisterContractAction], which has more fields.
+#[derive(Debug, Serialize, Deserialize, Default, Clone, PartialEq, Eq, Encode, Decode)]
+pub struct UpdateContractAction {
+    pub contract_name: ContractName,
+    pub verifier: Verifier,
+    pub program_id: ProgramId,
+}
+
+impl UpdateContractAction {
+    /// What the program of the updated contract outputs to approve the update.
+    /// Its state digest is ignored, the contract keeps its state.
+    pub fn approval(&self) -> RegisterContractEffect {
+        RegisterContractEffect {
+            contract_name: self.contract_name.clone(),
+            verifier: self.verifier.clone(),
+            program_id: self.program_id.clone(),
+            state_digest: StateDigest::default(),
+        }
+    }
+
+    pub fn is_approved_by(&self, effect: &RegisterContractEffect) -> bool {
+        effect.contract_name == self.contract_name
+            && effect.verifier == self.verifier
+            && effect.program_id == self.program_id
+    }
+}
+
+impl ContractAction for UpdateContractAction {
+    fn as_blob(
+        &self,
+        contract_name: ContractName,
+        caller: Option<BlobIndex>,
+        callees: Option<Vec<BlobIndex>>,
+    ) -> Blob {
+        Blob {
+            contract_name,
+            data: BlobData::from(StructuredBlobData {
+                caller,
+                callees,
+                parameters: self.clone(),
+            }),
+        }
+    }
+}
+
+/// New program and verifier of a contract, once its update took effect.
+#[derive(Default, Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash, Encode, Decode)]
+#[cfg_attr(feature = "full", derive(utoipa::ToSchema))]
+pub struct UpdateContractEffect {
+    pub contract_name: ContractName,
+    pub verifier: Verifier,
+    pub program_id: ProgramId,
+}
+
+impl From<UpdateContractAction> for UpdateContractEffect {
+    fn from(action: UpdateContractAction) -> Self {
+        UpdateContractEffect {
+            contract_name: action.contract_name,
+            verifier: action.verifier,
+            program_id: action.program_id,
+        }
+    }
+}
+
 /// Used by the Hylé node to recognize contract registration.
 /// Simply output this struct in your HyleOutput registered_contracts.
 /// See uuid-tld for examples.
@@ -0,0 +1,261 @@
+//! Stable error codes shared by the node and its clients.
+//!
+//! Codes never change meaning once released, so that applications can branch
+//! on them instead of matching error messages.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum ErrorCode {
+    // Generic
+    Internal,
+    BadRequest,
+    NotFound,
+    Conflict,
+    PayloadTooLarge,
+    TooManyRequests,
+    Unauthorized,
+    // Mempool admission
+    InvalidIdentity,
+    UnknownContract,
+    InvalidProof,
+    ProofHashMismatch,
+    ProofUploadOffsetMismatch,
+    InvalidContractRegistration,
+    BlobTooLarge,
+    AdmissionQuotaExceeded,
+    DuplicateTransaction,
+    // Websockets
+    TooManyConnections,
+    SubscriptionQueueFull,
+    // Indexer
+    Database,
+}
+
+/// Websocket close codes in the private range (4000-4999) are `WS_CLOSE_CODE_BASE + number`.
+pub const WS_CLOSE_CODE_BASE: u16 = 4000;
+
+impl ErrorCode {
+    const ALL: [ErrorCode; 19] = [
+        ErrorCode::Internal,
+        ErrorCode::BadRequest,
+        ErrorCode::NotFound,
+        ErrorCode::Conflict,
+        ErrorCode::PayloadTooLarge,
+        ErrorCode::TooManyRequests,
+        ErrorCode::Unauthorized,
+        ErrorCode::InvalidIdentity,
+        ErrorCode::UnknownContract,
+        ErrorCode::InvalidProof,
+        ErrorCode::ProofHashMismatch,
+        ErrorCode::ProofUploadOffsetMismatch,
+        ErrorCode::InvalidContractRegistration,
+        ErrorCode::BlobTooLarge,
+        ErrorCode::AdmissionQuotaExceeded,
+        ErrorCode::DuplicateTransaction,
+        ErrorCode::TooManyConnections,
+        ErrorCode::SubscriptionQueueFull,
+        ErrorCode::Database,
+    ];
+
+    /// Stable numeric identifier of the code.
+    pub fn number(&self) -> u16 {
+        match self {
+            ErrorCode::Internal => 0,
+            ErrorCode::BadRequest => 1,
+            ErrorCode::NotFound => 2,
+            ErrorCode::Conflict => 3,
+            ErrorCode::PayloadTooLarge => 4,
+            ErrorCode::TooManyRequests => 5,
+            ErrorCode::Unauthorized => 6,
+            ErrorCode::InvalidIdentity => 100,
+            ErrorCode::UnknownContract => 101,
+            ErrorCode::InvalidProof => 102,
+            ErrorCode::ProofHashMismatch => 103,
+            ErrorCode::ProofUploadOffsetMismatch => 104,
+            ErrorCode::InvalidContractRegistration => 105,
+            ErrorCode::BlobTooLarge => 106,
+            ErrorCode::AdmissionQuotaExceeded => 107,
+            ErrorCode::DuplicateTransaction => 108,
+            ErrorCode::TooManyConnections => 200,
+            ErrorCode::SubscriptionQueueFull => 201,
+            ErrorCode::Database => 300,
+        }
+    }
+
+    pub fn from_number(number: u16) -> Option<Self> {
+        Self::ALL.into_iter().find(|code| code.number() == number)
+    }
+
+    pub fn ws_close_code(&self) -> u16 {
+        WS_CLOSE_CODE_BASE + self.number()
+    }
+
+    pub fn from_ws_close_code(close_code: u16) -> Option<Self> {
+        close_code
+            .checked_sub(WS_CLOSE_CODE_BASE)
+            .and_then(Self::from_number)
+    }
+
+    /// HTTP status returned alongside this code.
+    pub fn http_status(&self) -> u16 {
+        match self {
+            ErrorCode::Internal | ErrorCode::Database => 500,
+            ErrorCode::BadRequest
+            | ErrorCode::InvalidIdentity
+            | ErrorCode::InvalidProof
+            | ErrorCode::ProofHashMismatch
+            | ErrorCode::InvalidContractRegistration => 400,
+            ErrorCode::Unauthorized => 401,
+            ErrorCode::NotFound | ErrorCode::UnknownContract => 404,
+            ErrorCode::Conflict
+            | ErrorCode::ProofUploadOffsetMismatch
+            | ErrorCode::DuplicateTransaction => 409,
+            ErrorCode::PayloadTooLarge | ErrorCode::BlobTooLarge => 413,
+            ErrorCode::TooManyRequests
+            | ErrorCode::AdmissionQuotaExceeded
+            | ErrorCode::TooManyConnections
+            | ErrorCode::SubscriptionQueueFull => 429,
+        }
+    }
+
+    /// Generic code for an HTTP status, for errors that don't carry a more specific one.
+    pub fn from_http_status(status: u16) -> Self {
+        match status {
+            400 => ErrorCode::BadRequest,
+            401 | 403 => ErrorCode::Unauthorized,
+            404 => ErrorCode::NotFound,
+            409 => ErrorCode::Conflict,
+            413 => ErrorCode::PayloadTooLarge,
+            429 => ErrorCode::TooManyRequests,
+            _ => ErrorCode::Internal,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match serde_json::to_value(self) {
+            Ok(serde_json::Value::String(s)) => write!(f, "{s}"),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+/// Error carrying a stable code, as returned in REST error bodies.
+/// Wrap it in an `anyhow::Error` to have it reach clients with its code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct HyleError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl HyleError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl std::fmt::Display for HyleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}", self.code, self.message)
+    }
+}
+
+impl std::error::Error for HyleError {}
+
+/// Media type of [ProblemDetails] bodies.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// Error body following RFC 7807, as returned by the indexer API.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct ProblemDetails {
+    /// URI identifying the kind of problem, `urn:hyle:error:<code>`
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    /// HTTP status of the response
+    pub status: u16,
+    /// Explanation specific to this occurrence of the problem
+    pub detail: String,
+    pub code: ErrorCode,
+    /// Additional context, such as the parameter that couldn't be parsed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+}
+
+impl ProblemDetails {
+    pub fn new(code: ErrorCode, detail: impl Into<String>) -> Self {
+        Self {
+            problem_type: format!("urn:hyle:error:{code}"),
+            title: code.to_string(),
+            status: code.http_status(),
+            detail: detail.into(),
+            code,
+            details: None,
+        }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+}
+
+impl From<ProblemDetails> for HyleError {
+    fn from(problem: ProblemDetails) -> Self {
+        HyleError::new(problem.code, problem.detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_codes_round_trip() {
+        for code in ErrorCode::ALL {
+            assert_eq!(ErrorCode::from_number(code.number()), Some(code));
+            assert_eq!(
+                ErrorCode::from_ws_close_code(code.ws_close_code()),
+                Some(code)
+            );
+        }
+        assert_eq!(ErrorCode::from_ws_close_code(1000), None);
+        assert_eq!(
+            serde_json::to_string(&ErrorCode::ProofUploadOffsetMismatch).unwrap(),
+            "\"PROOF_UPLOAD_OFFSET_MISMATCH\""
+        );
+        assert_eq!(
+            HyleError::new(ErrorCode::NotFound, "no such block").to_string(),
+            "[NOT_FOUND] no such block"
+        );
+    }
+
+    #[test]
+    fn test_problem_details() {
+        let problem = ProblemDetails::new(ErrorCode::BadRequest, "invalid height")
+            .with_details(serde_json::json!({ "parameter": "height" }));
+        assert_eq!(
+            serde_json::to_value(&problem).unwrap(),
+            serde_json::json!({
+                "type": "urn:hyle:error:BAD_REQUEST",
+                "title": "BAD_REQUEST",
+                "status": 400,
+                "detail": "invalid height",
+                "code": "BAD_REQUEST",
+                "details": { "parameter": "height" },
+            })
+        );
+        assert_eq!(
+            HyleError::from(ProblemDetails::new(ErrorCode::Database, "down")),
+            HyleError::new(ErrorCode::Database, "down")
+        );
+    }
+}
@@ -1,6 +1,8 @@
 #[cfg(feature = "full")]
 mod block;
 #[cfg(feature = "full")]
+mod merkle;
+#[cfg(feature = "full")]
 mod node;
 #[cfg(feature = "full")]
 mod transaction;
@@ -10,12 +12,16 @@ pub mod utils;
 #[cfg(feature = "full")]
 pub use block::*;
 #[cfg(feature = "full")]
+pub use merkle::*;
+#[cfg(feature = "full")]
 pub use node::*;
 #[cfg(feature = "full")]
 pub use transaction::*;
 
 #[cfg(feature = "full")]
 pub mod api;
+#[cfg(feature = "full")]
+pub mod errors;
 
 mod contract;
 mod staking;
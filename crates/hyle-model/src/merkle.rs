@@ -0,0 +1,309 @@
+use std::fmt::Display;
+
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use utoipa::ToSchema;
+
+use crate::{ContractName, StateDigest, TxHash};
+
+type Node = [u8; 32];
+
+/// Root of the Merkle tree over the state digests of all registered contracts.
+///
+/// It results from executing a block and is not part of the signed consensus proposal: it is
+/// only attested by the node serving it, and clients should cross-check it with other nodes.
+/// Light clients can't verify contract states from block headers alone, as headers don't commit
+/// to it.
+#[derive(
+    Debug, Default, Clone, Serialize, Deserialize, Encode, Decode, PartialEq, Eq, Hash, ToSchema,
+)]
+pub struct StateRoot(pub String);
+
+impl Display for StateRoot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.0)
+    }
+}
+
+/// Root of the Merkle tree over the transactions of a block, see [TxsMerkleTree].
+///
+/// It is not part of the signed consensus proposal. The transactions it is computed from are
+/// covered by the certified data proposal hashes, so clients that don't trust the node serving
+/// it can recompute it from the signed block.
+#[derive(
+    Debug, Default, Clone, Serialize, Deserialize, Encode, Decode, PartialEq, Eq, Hash, ToSchema,
+)]
+pub struct TxRoot(pub String);
+
+impl Display for TxRoot {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", &self.0)
+    }
+}
+
+/// One level of a [ContractStateProof] or [TxInclusionProof], from the leaf up to the root.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct MerkleProofStep {
+    /// Hex-encoded hash of the sibling node
+    pub sibling: String,
+    /// Whether the sibling is hashed before the current node
+    pub sibling_on_left: bool,
+}
+
+/// Proves the state digest of a contract against a [StateRoot].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct ContractStateProof {
+    pub contract_name: ContractName,
+    pub state_digest: StateDigest,
+    pub steps: Vec<MerkleProofStep>,
+}
+
+impl ContractStateProof {
+    /// Root obtained by hashing the leaf with each step of the proof.
+    pub fn root(&self) -> Option<StateRoot> {
+        let leaf = hash_leaf(&self.contract_name, &self.state_digest);
+        fold_steps(leaf, &self.steps).map(|root| StateRoot(hex::encode(root)))
+    }
+
+    pub fn verify(&self, root: &StateRoot) -> bool {
+        self.root().as_ref() == Some(root)
+    }
+}
+
+/// Proves a transaction is at some position among the transactions of a block, against its [TxRoot].
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, ToSchema)]
+pub struct TxInclusionProof {
+    pub tx_hash: TxHash,
+    /// Position of the transaction in the block
+    pub index: u32,
+    pub steps: Vec<MerkleProofStep>,
+}
+
+impl TxInclusionProof {
+    /// Root obtained by hashing the leaf with each step of the proof.
+    pub fn root(&self) -> Option<TxRoot> {
+        let leaf = hash_tx_leaf(&self.tx_hash);
+        fold_steps(leaf, &self.steps).map(|root| TxRoot(hex::encode(root)))
+    }
+
+    pub fn verify(&self, root: &TxRoot) -> bool {
+        self.root().as_ref() == Some(root)
+    }
+}
+
+/// Merkle tree over contract state digests, with leaves sorted by contract name.
+///
+/// Leaves and inner nodes are hashed with distinct prefixes. A node without a sibling
+/// is moved up to the next level as is.
+pub struct ContractsMerkleTree {
+    names: Vec<ContractName>,
+    digests: Vec<StateDigest>,
+    levels: Vec<Vec<Node>>,
+}
+
+impl ContractsMerkleTree {
+    pub fn new<'a>(contracts: impl Iterator<Item = (&'a ContractName, &'a StateDigest)>) -> Self {
+        let mut leaves: Vec<_> = contracts.collect();
+        leaves.sort_by(|a, b| a.0.cmp(b.0));
+
+        let levels = build_levels(
+            leaves
+                .iter()
+                .map(|(name, digest)| hash_leaf(name, digest))
+                .collect(),
+        );
+
+        Self {
+            names: leaves.iter().map(|(name, _)| (*name).clone()).collect(),
+            digests: leaves.iter().map(|(_, digest)| (*digest).clone()).collect(),
+            levels,
+        }
+    }
+
+    /// Root of the tree. The root of an empty tree is all zeroes.
+    pub fn root(&self) -> StateRoot {
+        StateRoot(hex::encode(levels_root(&self.levels)))
+    }
+
+    pub fn proof(&self, contract_name: &ContractName) -> Option<ContractStateProof> {
+        let index = self.names.binary_search(contract_name).ok()?;
+        let state_digest = self.digests.get(index)?.clone();
+
+        Some(ContractStateProof {
+            contract_name: contract_name.clone(),
+            state_digest,
+            steps: proof_steps(&self.levels, index),
+        })
+    }
+}
+
+/// Merkle tree over the hashes of the transactions of a block, in block order.
+///
+/// Built like [ContractsMerkleTree], with leaves hashed with their own prefix.
+pub struct TxsMerkleTree {
+    tx_hashes: Vec<TxHash>,
+    levels: Vec<Vec<Node>>,
+}
+
+impl TxsMerkleTree {
+    pub fn new(tx_hashes: impl Iterator<Item = TxHash>) -> Self {
+        let tx_hashes: Vec<_> = tx_hashes.collect();
+        let levels = build_levels(tx_hashes.iter().map(hash_tx_leaf).collect());
+        Self { tx_hashes, levels }
+    }
+
+    /// Root of the tree. The root of a block without transactions is all zeroes.
+    pub fn root(&self) -> TxRoot {
+        TxRoot(hex::encode(levels_root(&self.levels)))
+    }
+
+    /// Proof of the first transaction with this hash.
+    pub fn proof(&self, tx_hash: &TxHash) -> Option<TxInclusionProof> {
+        let index = self.tx_hashes.iter().position(|hash| hash == tx_hash)?;
+        Some(TxInclusionProof {
+            tx_hash: tx_hash.clone(),
+            index: u32::try_from(index).ok()?,
+            steps: proof_steps(&self.levels, index),
+        })
+    }
+}
+
+/// Levels of a tree, from the leaves up to the root.
+fn build_levels(leaves: Vec<Node>) -> Vec<Vec<Node>> {
+    let mut levels = vec![leaves];
+    while let Some(level) = levels.last().filter(|level| level.len() > 1) {
+        let next = level
+            .chunks(2)
+            .filter_map(|pair| match pair {
+                [left, right] => Some(hash_pair(left, right)),
+                [single] => Some(*single),
+                _ => None,
+            })
+            .collect();
+        levels.push(next);
+    }
+    levels
+}
+
+fn levels_root(levels: &[Vec<Node>]) -> Node {
+    levels
+        .last()
+        .and_then(|level| level.first())
+        .copied()
+        .unwrap_or_default()
+}
+
+fn proof_steps(levels: &[Vec<Node>], mut index: usize) -> Vec<MerkleProofStep> {
+    let mut steps = vec![];
+    for level in levels.iter() {
+        let sibling_index = index ^ 1;
+        if let Some(sibling) = level.get(sibling_index) {
+            steps.push(MerkleProofStep {
+                sibling: hex::encode(sibling),
+                sibling_on_left: sibling_index < index,
+            });
+        }
+        index /= 2;
+    }
+    steps
+}
+
+fn fold_steps(leaf: Node, steps: &[MerkleProofStep]) -> Option<Node> {
+    let mut node = leaf;
+    for step in steps.iter() {
+        let sibling: Node = hex::decode(&step.sibling).ok()?.try_into().ok()?;
+        node = if step.sibling_on_left {
+            hash_pair(&sibling, &node)
+        } else {
+            hash_pair(&node, &sibling)
+        };
+    }
+    Some(node)
+}
+
+fn hash_leaf(name: &ContractName, digest: &StateDigest) -> Node {
+    let mut hasher = Sha3_256::new();
+    hasher.update([0u8]);
+    hasher.update((name.0.len() as u64).to_le_bytes());
+    hasher.update(name.0.as_bytes());
+    hasher.update(&digest.0);
+    hasher.finalize().into()
+}
+
+fn hash_tx_leaf(tx_hash: &TxHash) -> Node {
+    let mut hasher = Sha3_256::new();
+    hasher.update([2u8]);
+    hasher.update(tx_hash.0.as_bytes());
+    hasher.finalize().into()
+}
+
+fn hash_pair(left: &Node, right: &Node) -> Node {
+    let mut hasher = Sha3_256::new();
+    hasher.update([1u8]);
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contracts(n: usize) -> Vec<(ContractName, StateDigest)> {
+        (0..n)
+            .map(|i| (ContractName(format!("c{i}")), StateDigest(vec![i as u8])))
+            .collect()
+    }
+
+    #[test]
+    fn test_proofs_verify_against_root() {
+        for n in 1..=9 {
+            let contracts = contracts(n);
+            let tree = ContractsMerkleTree::new(contracts.iter().map(|(n, d)| (n, d)));
+            let root = tree.root();
+            for (name, digest) in contracts.iter() {
+                let proof = tree.proof(name).unwrap();
+                assert_eq!(&proof.state_digest, digest);
+                assert!(proof.verify(&root), "{n} contracts, proof of {name}");
+
+                let mut forged = proof.clone();
+                forged.state_digest = StateDigest(vec![42, 42]);
+                assert!(!forged.verify(&root));
+            }
+        }
+    }
+
+    #[test]
+    fn test_root_is_order_independent() {
+        let contracts = contracts(5);
+        let tree = ContractsMerkleTree::new(contracts.iter().map(|(n, d)| (n, d)));
+        let reversed = ContractsMerkleTree::new(contracts.iter().rev().map(|(n, d)| (n, d)));
+        assert_eq!(tree.root(), reversed.root());
+        assert!(tree.proof(&ContractName("unknown".into())).is_none());
+
+        let empty = ContractsMerkleTree::new(std::iter::empty());
+        assert_eq!(empty.root(), StateRoot(hex::encode([0u8; 32])));
+    }
+
+    #[test]
+    fn test_tx_proofs_verify_against_root() {
+        for n in 1..=9 {
+            let tx_hashes: Vec<_> = (0..n).map(|i| TxHash(format!("tx{i}"))).collect();
+            let tree = TxsMerkleTree::new(tx_hashes.clone().into_iter());
+            let root = tree.root();
+            for (i, tx_hash) in tx_hashes.iter().enumerate() {
+                let proof = tree.proof(tx_hash).unwrap();
+                assert_eq!(proof.index as usize, i);
+                assert!(proof.verify(&root), "{n} txs, proof of {tx_hash}");
+
+                let mut forged = proof.clone();
+                forged.tx_hash = TxHash("forged".into());
+                assert!(!forged.verify(&root));
+            }
+        }
+        let empty = TxsMerkleTree::new(std::iter::empty());
+        assert_eq!(empty.root(), TxRoot(hex::encode([0u8; 32])));
+        assert!(empty.proof(&TxHash("tx0".into())).is_none());
+    }
+}
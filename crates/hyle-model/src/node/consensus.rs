@@ -29,12 +29,28 @@ pub struct ValidatorCandidacy {
     pub peer_address: String,
 }
 
+/// Sent by a validator leaving consensus, once it handed its duties over to another key.
+/// It can only be included in a proposal for a few slots after `slot`, so that it can't be
+/// replayed once the key is bonded again.
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq, Eq, Hash)]
+pub struct ValidatorLeave {
+    pub pubkey: ValidatorPublicKey,
+    pub slot: Slot,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode, PartialEq, Eq)]
 pub struct NewValidatorCandidate {
     pub pubkey: ValidatorPublicKey, // TODO: possible optim: the pubkey is already present in the msg,
     pub msg: SignedByValidator<ConsensusNetMessage>,
 }
 
+/// A validator leaving consensus, with the [ValidatorLeave] message it signed.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode, PartialEq, Eq)]
+pub struct LeavingValidator {
+    pub pubkey: ValidatorPublicKey,
+    pub msg: SignedByValidator<ConsensusNetMessage>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, PartialEq, Eq, Default)]
 pub struct QuorumCertificateHash(pub Vec<u8>);
 
@@ -100,6 +116,29 @@ pub struct ConsensusProposal {
     pub staking_actions: Vec<ConsensusStakingAction>,
     pub timestamp: u64,
     pub parent_hash: ConsensusProposalHash,
+    pub production_reason: BlockProductionReason,
+    /// Validator set certifying this block, when it differs from the previous block's one
+    pub epoch: Option<EpochSnapshot>,
+}
+
+/// Why the leader proposed a block when it did.
+#[derive(
+    Debug, Clone, Copy, Default, Serialize, Deserialize, Encode, Decode, PartialEq, Eq, ToSchema,
+)]
+#[cfg_attr(feature = "sqlx", derive(sqlx::Type))]
+#[cfg_attr(
+    feature = "sqlx",
+    sqlx(type_name = "block_production_reason", rename_all = "snake_case")
+)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockProductionReason {
+    /// The slot duration elapsed
+    #[default]
+    Interval = 0,
+    /// Pending data proposals exceeded the configured size
+    PendingBytes = 1,
+    /// Pending data proposals exceeded the configured number of transactions
+    PendingTxs = 2,
 }
 
 /// This is the hash of the proposal, signed by validators
@@ -116,9 +155,29 @@ impl Hashable<ConsensusProposalHash> for ConsensusProposal {
         });
         self.staking_actions.iter().for_each(|val| match val {
             ConsensusStakingAction::Bond { candidate } => hasher.update(&candidate.pubkey.0),
+            ConsensusStakingAction::Slash { evidence } => {
+                hasher.update(&evidence.first.signature.signature.0);
+                hasher.update(&evidence.second.signature.signature.0);
+            }
+            ConsensusStakingAction::Leave { validator } => {
+                hasher.update(&validator.pubkey.0);
+                hasher.update(&validator.msg.signature.signature.0);
+            }
         });
         hasher.update(self.timestamp.to_le_bytes());
         hasher.update(self.parent_hash.0.as_bytes());
+        // Blocks produced on schedule hash as they did before the reason was recorded.
+        if self.production_reason != BlockProductionReason::Interval {
+            hasher.update([self.production_reason as u8]);
+        }
+        if let Some(epoch) = &self.epoch {
+            hasher.update(epoch.epoch.to_le_bytes());
+            hasher.update(epoch.start_height.0.to_le_bytes());
+            epoch.validators.iter().for_each(|(pubkey, stake)| {
+                hasher.update(&pubkey.0);
+                hasher.update(stake.to_le_bytes());
+            });
+        }
         ConsensusProposalHash(hex::encode(hasher.finalize()))
     }
 }
@@ -133,6 +192,74 @@ impl std::hash::Hash for ConsensusProposal {
 #[derive(Encode, Decode, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub enum ConsensusStakingAction {
     Bond { candidate: NewValidatorCandidate }, // Bonding a new validator candidate
+    Slash { evidence: SlashEvidence },         // Slashing a validator that equivocated
+    Leave { validator: LeavingValidator },     // Unbonding a validator that asked to leave
+}
+
+/// Two conflicting messages of the same kind signed by a validator for the same slot and view.
+/// Votes only name the proposal they are for, so the proposals come along to show their slot
+/// and view.
+#[derive(Encode, Decode, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SlashEvidence {
+    pub first: SignedByValidator<ConsensusNetMessage>,
+    pub second: SignedByValidator<ConsensusNetMessage>,
+    pub proposals: Vec<ConsensusProposal>,
+}
+
+impl SlashEvidence {
+    pub fn validator(&self) -> &ValidatorPublicKey {
+        &self.first.signature.validator
+    }
+
+    /// Checks that the messages conflict, their signatures are left to the caller.
+    /// Returns the hash of the offense, a validator is slashed once for each.
+    pub fn offense_hash(&self) -> Result<String, String> {
+        if self.second.signature.validator != *self.validator() {
+            return Err("Messages signed by different validators".to_string());
+        }
+        let (kind, first_hash, first_slot) = self.placed(&self.first.msg)?;
+        let (second_kind, second_hash, second_slot) = self.placed(&self.second.msg)?;
+        if kind != second_kind {
+            return Err(format!(
+                "Messages of different kinds {kind} and {second_kind}"
+            ));
+        }
+        if first_slot != second_slot {
+            return Err(format!(
+                "Messages for different slots and views {:?} and {:?}",
+                first_slot, second_slot
+            ));
+        }
+        if first_hash == second_hash {
+            return Err(format!("Both messages are for proposal {first_hash}"));
+        }
+        let mut hasher = Sha3_256::new();
+        hasher.update(&self.validator().0);
+        hasher.update(kind.as_bytes());
+        hasher.update(first_slot.0.to_le_bytes());
+        hasher.update(first_slot.1.to_le_bytes());
+        Ok(hex::encode(hasher.finalize()))
+    }
+
+    /// Kind of the message, proposal it is for, and the slot and view of that proposal.
+    fn placed(
+        &self,
+        msg: &ConsensusNetMessage,
+    ) -> Result<(&'static str, ConsensusProposalHash, (Slot, View)), String> {
+        let kind: &'static str = msg.into();
+        let hash = match msg {
+            ConsensusNetMessage::Prepare(proposal, _) => {
+                return Ok((kind, proposal.hash(), (proposal.slot, proposal.view)));
+            }
+            ConsensusNetMessage::PrepareVote(hash) | ConsensusNetMessage::ConfirmAck(hash) => hash,
+            _ => return Err(format!("{kind} messages can't be conflicting")),
+        };
+        self.proposals
+            .iter()
+            .find(|proposal| proposal.hash() == *hash)
+            .map(|proposal| (kind, hash.clone(), (proposal.slot, proposal.view)))
+            .ok_or_else(|| format!("Missing proposal {hash} of a {kind} message"))
+    }
 }
 
 impl From<NewValidatorCandidate> for ConsensusStakingAction {
@@ -153,6 +280,7 @@ pub enum ConsensusNetMessage {
     Timeout(Slot, View),
     TimeoutCertificate(QuorumCertificate, Slot, View),
     ValidatorCandidacy(ValidatorCandidacy),
+    ValidatorLeave(ValidatorLeave),
 }
 
 impl Hashable<QuorumCertificateHash> for QuorumCertificate {
@@ -173,6 +301,12 @@ impl Display for ValidatorCandidacy {
     }
 }
 
+impl Display for ValidatorLeave {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pubkey: {}, Slot: {}", self.pubkey, self.slot)
+    }
+}
+
 impl Display for Ticket {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "Ticket: {:?}", self)
@@ -243,6 +377,9 @@ impl Display for ConsensusNetMessage {
             ConsensusNetMessage::ValidatorCandidacy(candidacy) => {
                 write!(f, "{} (CP hash {})", enum_variant, candidacy)
             }
+            ConsensusNetMessage::ValidatorLeave(leave) => {
+                write!(f, "{} - {}", enum_variant, leave)
+            }
             ConsensusNetMessage::Timeout(slot, view) => {
                 write!(f, "{} - Slot: {} View: {}", enum_variant, slot, view)
             }
@@ -272,6 +409,8 @@ mod tests {
             staking_actions: vec![],
             timestamp: 1,
             parent_hash: ConsensusProposalHash("".to_string()),
+            production_reason: Default::default(),
+            epoch: None,
         };
         let hash = proposal.hash();
         assert_eq!(hash.0.len(), 64);
@@ -302,6 +441,8 @@ mod tests {
             .into()],
             timestamp: 1,
             parent_hash: ConsensusProposalHash("parent".to_string()),
+            production_reason: Default::default(),
+            epoch: None,
         };
         let mut b = ConsensusProposal {
             slot: 1,
@@ -331,6 +472,8 @@ mod tests {
             .into()],
             timestamp: 1,
             parent_hash: ConsensusProposalHash("parent".to_string()),
+            production_reason: Default::default(),
+            epoch: None,
         };
         assert_eq!(a.hash(), b.hash());
         a.timestamp = 2;
@@ -357,5 +500,15 @@ mod tests {
         assert_ne!(a.hash(), b.hash());
         b.parent_hash = ConsensusProposalHash("different".to_string());
         assert_eq!(a.hash(), b.hash());
+
+        let epoch = EpochSnapshot {
+            epoch: 1,
+            start_height: BlockHeight(2),
+            validators: [(ValidatorPublicKey(vec![4, 5, 6]), 100)].into(),
+        };
+        a.epoch = Some(epoch.clone());
+        assert_ne!(a.hash(), b.hash());
+        b.epoch = Some(epoch);
+        assert_eq!(a.hash(), b.hash());
     }
 }
@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use bincode::{Decode, Encode};
 use serde::{
     de::{self, Visitor},
@@ -14,9 +16,152 @@ pub struct RewardsClaim {
 /// Enum representing the actions that can be performed by the IdentityVerification contract.
 #[derive(Encode, Decode, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub enum StakingAction {
-    Stake { amount: u128 },
-    Delegate { validator: ValidatorPublicKey },
-    Distribute { claim: RewardsClaim },
+    Stake {
+        amount: u128,
+    },
+    Delegate {
+        validator: ValidatorPublicKey,
+    },
+    Distribute {
+        claim: RewardsClaim,
+    },
+    /// Withdraws `amount` of the rewards accrued to the caller, paid by a hyllar transfer
+    ClaimRewards {
+        amount: u128,
+    },
+    /// Removes `amount` from the caller's stake, withdrawable once the unbonding delay is over
+    Unbond {
+        amount: u128,
+    },
+    /// Withdraws `amount` of the caller's unbonded stake, paid by a hyllar transfer
+    Withdraw {
+        amount: u128,
+    },
+    /// Burns part of the stake delegated to `validator` and jails it, for the misbehavior
+    /// proven by the evidence. Only accepted from the hyle identity.
+    Slash {
+        validator: ValidatorPublicKey,
+        /// Part of the stake slashed, in basis points
+        fraction: u32,
+        /// Hash of the evidence, a validator is slashed once per evidence
+        evidence_hash: String,
+    },
+}
+
+/// Stake removed by an `Unbond`, waiting for the end of the unbonding delay to be withdrawn
+#[derive(Encode, Decode, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(utoipa::ToSchema))]
+pub struct Unbonding {
+    pub amount: u128,
+    /// First block at which the amount can be withdrawn
+    pub unlock_height: BlockHeight,
+}
+
+/// Hyllar emitted to the delegators at the end of every epoch
+#[derive(Encode, Decode, Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(utoipa::ToSchema))]
+pub struct EmissionSchedule {
+    /// Length of an epoch, in blocks
+    pub epoch_length: u64,
+    /// Emitted at the end of each of the first epochs
+    pub initial_reward: u128,
+    /// The emission halves every `halving_interval` epochs, 0 to keep it constant
+    pub halving_interval: u64,
+}
+
+impl EmissionSchedule {
+    pub fn epoch(&self, height: BlockHeight) -> u64 {
+        height.0 / self.epoch_length.max(1)
+    }
+
+    /// Total emitted at the end of the epochs `from..to`.
+    pub fn emitted(&self, from: u64, to: u64) -> u128 {
+        let mut total: u128 = 0;
+        let mut epoch = from;
+        // Summed by halving period, the reward being constant within one
+        while epoch < to {
+            let reward = self.reward(epoch);
+            if reward == 0 {
+                break;
+            }
+            let next = match self.halving_interval {
+                0 => to,
+                interval => (epoch / interval)
+                    .saturating_add(1)
+                    .saturating_mul(interval)
+                    .min(to),
+            };
+            total = total.saturating_add(reward.saturating_mul((next - epoch) as u128));
+            epoch = next;
+        }
+        total
+    }
+
+    pub fn reward(&self, epoch: u64) -> u128 {
+        if self.halving_interval == 0 {
+            return self.initial_reward;
+        }
+        self.initial_reward
+            .checked_shr((epoch / self.halving_interval).min(u32::MAX as u64) as u32)
+            .unwrap_or(0)
+    }
+}
+
+impl Default for EmissionSchedule {
+    fn default() -> Self {
+        EmissionSchedule {
+            epoch_length: 1000,
+            initial_reward: 10_000,
+            halving_interval: 1000,
+        }
+    }
+}
+
+/// Number of an epoch: a span of blocks certified by the same validator set
+pub type Epoch = u64;
+
+/// Validator set certifying the blocks of an epoch, from its first block until the next epoch
+/// starts. Carried by the proposal of the first block of the epoch.
+#[derive(Encode, Decode, Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[cfg_attr(feature = "full", derive(utoipa::ToSchema))]
+pub struct EpochSnapshot {
+    pub epoch: Epoch,
+    /// First block of the epoch
+    pub start_height: BlockHeight,
+    /// Bonded validators and their stake
+    pub validators: BTreeMap<ValidatorPublicKey, u128>,
+}
+
+impl EpochSnapshot {
+    pub fn total_bond(&self) -> u128 {
+        self.validators.values().sum()
+    }
+
+    /// Whether both snapshots hold the same validators, whatever their stakes.
+    pub fn same_validators(&self, other: &EpochSnapshot) -> bool {
+        self.validators.keys().eq(other.validators.keys())
+    }
+
+    /// Checks that the signers are bonded in this epoch and hold a quorum of its stake.
+    pub fn verify_signers(&self, signers: &[ValidatorPublicKey]) -> Result<(), String> {
+        let mut voting_power: u128 = 0;
+        for signer in signers {
+            match self.validators.get(signer) {
+                Some(stake) => voting_power = voting_power.saturating_add(*stake),
+                None => return Err(format!("{} is not bonded in epoch {}", signer, self.epoch)),
+            }
+        }
+        let f = self.total_bond().div_ceil(3);
+        if voting_power < 2 * f + 1 {
+            return Err(format!(
+                "Voting power {} is below quorum {} in epoch {}",
+                voting_power,
+                2 * f + 1,
+                self.epoch
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl ContractAction for StakingAction {
@@ -105,3 +250,28 @@ impl std::fmt::Display for ValidatorPublicKey {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_epoch_snapshot_signers() {
+        let key = |i: u8| ValidatorPublicKey(vec![i]);
+        let epoch = EpochSnapshot {
+            epoch: 2,
+            start_height: BlockHeight(10),
+            validators: (1..=4).map(|i| (key(i), 100)).collect(),
+        };
+        assert_eq!(epoch.total_bond(), 400);
+        assert!(epoch.verify_signers(&[key(1), key(2), key(3)]).is_ok());
+        assert!(epoch.verify_signers(&[key(1), key(3)]).is_err());
+        assert!(epoch.verify_signers(&[key(1), key(2), key(5)]).is_err());
+
+        let mut other = epoch.clone();
+        other.validators.insert(key(1), 50);
+        assert!(epoch.same_validators(&other));
+        other.validators.insert(key(5), 50);
+        assert!(!epoch.same_validators(&other));
+    }
+}
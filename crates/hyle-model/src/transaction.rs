@@ -46,6 +46,8 @@ pub struct VerifiedProofTransaction {
     pub contract_name: ContractName,
     pub proof: Option<ProofData>, // Kept only on the local lane for indexing purposes
     pub proof_hash: ProofDataHash,
+    /// Size of the proof in bytes, known even where the proof isn't kept
+    pub proof_size: usize,
     pub proven_blobs: Vec<BlobProofOutput>,
     pub is_recursive: bool,
 }
@@ -56,13 +58,7 @@ impl std::fmt::Debug for VerifiedProofTransaction {
             .field("contract_name", &self.contract_name)
             .field("proof_hash", &self.proof_hash)
             .field("proof", &"[HIDDEN]")
-            .field(
-                "proof_len",
-                &match &self.proof {
-                    Some(v) => v.0.len(),
-                    None => 0,
-                },
-            )
+            .field("proof_size", &self.proof_size)
             .field("proven_blobs", &self.proven_blobs)
             .finish()
     }
@@ -149,7 +145,7 @@ impl Hashable<TxHash> for VerifiedProofTransaction {
 )]
 pub struct ProofData(#[serde(with = "base64_field")] pub Vec<u8>);
 
-#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Encode, Decode)]
+#[derive(Debug, Default, Serialize, Deserialize, Clone, PartialEq, Eq, Hash, Encode, Decode)]
 pub struct ProofDataHash(pub String);
 
 impl Hashable<ProofDataHash> for ProofData {
@@ -1,16 +1,18 @@
 use anyhow::{bail, Context, Result};
 use axum::Router;
 use axum_otel_metrics::HttpMetricsLayerBuilder;
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use hydentity::Hydentity;
 use hyle::{
     bus::{metrics::BusMetrics, SharedMessageBus},
     consensus::Consensus,
-    data_availability::DataAvailability,
+    data_availability::{maintenance, DataAvailability},
     genesis::Genesis,
     indexer::{
+        contract_registry::ContractRegistryIndexer,
         contract_state_indexer::{ContractStateIndexer, ContractStateIndexerCtx},
-        Indexer,
+        staking_indexer::StakingIndexer,
+        Indexer, MIGRATOR,
     },
     mempool::Mempool,
     model::{api::NodeInfo, CommonRunContext, NodeRunContext, SharedRunContext},
@@ -23,12 +25,14 @@ use hyle::{
     utils::{
         conf,
         crypto::BlstCrypto,
-        logger::{setup_tracing, TracingMode},
+        logger::{setup_tracing, shutdown_tracing, TracingMode},
         modules::ModulesHandler,
     },
 };
 use hyllar::HyllarToken;
+use sqlx::postgres::PgPoolOptions;
 use std::{
+    path::PathBuf,
     sync::{Arc, Mutex},
     time::Duration,
 };
@@ -56,6 +60,119 @@ pub struct Args {
 
     #[arg(long, default_value = "config.ron")]
     pub config_file: Option<String>,
+
+    /// Overrides a configuration value, e.g. `--set da_stream.ping_interval=5`. Can be repeated.
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+
+    /// Runs a single-node devnet with a generated validator key, see `dev_mode` in the config.
+    #[clap(long, action)]
+    pub dev: bool,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// Maintenance tasks, run instead of the node. Tasks on the data directory need the node stopped.
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Indexer database
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Blocks stored by the data availability module
+    Da {
+        #[command(subcommand)]
+        command: DaCommand,
+    },
+    /// Validator keys
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DbCommand {
+    /// Applies the pending migrations to the database at `database_url`
+    Migrate,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum DaCommand {
+    /// Writes all stored blocks to a snapshot file
+    Export {
+        #[arg(long)]
+        to: PathBuf,
+    },
+    /// Checks that the stored blocks form a chain, and their certificates if `da_verify_blocks` is set
+    Verify,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum KeysCommand {
+    /// Generates a validator key, in `validator.key` of the data directory by default
+    Generate {
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+}
+
+async fn run_command(command: Command, config: &conf::Conf) -> Result<()> {
+    match command {
+        Command::Db {
+            command: DbCommand::Migrate,
+        } => {
+            let pool = PgPoolOptions::new()
+                .max_connections(1)
+                .connect(&config.database_url)
+                .await
+                .context("Failed to connect to the database")?;
+            MIGRATOR.run(&pool).await.context("Running migrations")?;
+            println!("Database migrated");
+        }
+        Command::Da {
+            command: DaCommand::Export { to },
+        } => {
+            let written = maintenance::export_blocks(config, &to)?;
+            println!("Exported {} block(s) to {}", written, to.display());
+        }
+        Command::Da {
+            command: DaCommand::Verify,
+        } => {
+            let report = maintenance::verify_blocks(config)?;
+            for (height, error) in report.errors.iter() {
+                println!("Block {}: {}", height, error);
+            }
+            println!(
+                "Checked {} block(s) up to height {}, {} error(s)",
+                report.blocks,
+                report
+                    .last_height
+                    .map_or("-".to_string(), |h| h.to_string()),
+                report.errors.len()
+            );
+            if !report.errors.is_empty() {
+                bail!("The block store is inconsistent");
+            }
+        }
+        Command::Keys {
+            command: KeysCommand::Generate { out },
+        } => {
+            let path = out.unwrap_or_else(|| config.data_directory.join("validator.key"));
+            if path.exists() {
+                bail!("{} already exists", path.display());
+            }
+            let crypto = BlstCrypto::load_or_generate(&path)?;
+            println!(
+                "Generated validator key {} in {}",
+                crypto.validator_pubkey(),
+                path.display()
+            );
+        }
+    }
+    Ok(())
 }
 
 #[cfg(feature = "dhat")]
@@ -72,14 +189,40 @@ async fn main() -> Result<()> {
     };
 
     let args = Args::parse();
-    let mut config = conf::Conf::new(args.config_file, args.data_directory, args.run_indexer)
-        .context("reading config file")?;
+    let command = args.command;
+    let mut overrides = args.overrides;
+    if args.dev {
+        overrides.push("dev_mode=true".to_string());
+    }
+    let mut config = conf::Conf::with_overrides(
+        args.config_file,
+        args.data_directory,
+        args.run_indexer,
+        &overrides,
+    )
+    .context("reading config file")?;
+
+    if let Some(command) = command {
+        return run_command(command, &config).await;
+    }
 
-    let crypto = Arc::new(BlstCrypto::new(config.id.clone()).context("Could not create crypto")?);
+    if args.pg && std::fs::metadata(&config.data_directory).is_ok() {
+        bail!(
+            "Data directory {} exists. --pg flag is given, please clean data dir first.",
+            config.data_directory.display()
+        );
+    }
+
+    let crypto = Arc::new(if config.dev_mode {
+        BlstCrypto::load_or_generate(&config.data_directory.join("validator.key"))
+            .context("Could not load or generate validator key")?
+    } else {
+        BlstCrypto::new(config.id.clone()).context("Could not create crypto")?
+    });
     let pubkey = Some(crypto.validator_pubkey().clone());
 
     setup_tracing(
-        match config.log_format.as_str() {
+        match config.log.format.as_str() {
             "json" => TracingMode::Json,
             "node" => TracingMode::NodeName,
             _ => TracingMode::Full,
@@ -89,16 +232,12 @@ async fn main() -> Result<()> {
             config.id.clone(),
             pubkey.clone().unwrap_or_default()
         ),
+        config.otlp_endpoint.clone(),
+        &config.log.modules,
     )?;
 
     let pg;
     if args.pg {
-        if std::fs::metadata(&config.data_directory).is_ok() {
-            bail!(
-                "Data directory {} exists. --pg flag is given, please clean data dir first.",
-                config.data_directory.display()
-            );
-        }
         info!("🐘 Starting postgres DB with default settings for the indexer");
         pg = Postgres::default()
             .with_cmd(["postgres", "-c", "log_statement=all"])
@@ -114,7 +253,7 @@ async fn main() -> Result<()> {
 
     let config = Arc::new(config);
 
-    info!("Starting node with config: {:?}", &config);
+    info!("Starting node with config: {:?}", &config.redacted());
 
     // Init global metrics meter we expose as an endpoint
     let metrics_layer = HttpMetricsLayerBuilder::new()
@@ -146,6 +285,7 @@ async fn main() -> Result<()> {
     };
 
     let mut handler = ModulesHandler::new(&bus).await;
+    handler.set_restart_policies(&config);
     handler.build_module::<Mempool>(ctx.clone()).await?;
 
     handler.build_module::<Genesis>(ctx.clone()).await?;
@@ -180,6 +320,12 @@ async fn main() -> Result<()> {
                 common: ctx.common.clone(),
             })
             .await?;
+        handler
+            .build_module::<ContractRegistryIndexer>(ctx.common.clone())
+            .await?;
+        handler
+            .build_module::<StakingIndexer>(ctx.common.clone())
+            .await?;
     }
     handler
         .build_module::<DataAvailability>(ctx.clone())
@@ -208,6 +354,10 @@ async fn main() -> Result<()> {
         .clone();
     handler
         .build_module::<RestApi>(RestApiRunContext {
+            auth: ctx.common.config.rest_auth.clone(),
+            cors: ctx.common.config.rest_cors.clone(),
+            rate_limit: ctx.common.config.rest_rate_limit.clone(),
+            config: ctx.common.config.clone(),
             rest_addr: ctx.common.config.rest.clone(),
             max_body_size: ctx.common.config.rest_max_body_size,
             info: NodeInfo {
@@ -261,5 +411,7 @@ async fn main() -> Result<()> {
         std::fs::remove_dir_all(&config.data_directory).context("removing data directory")?;
     }
 
+    shutdown_tracing();
+
     Ok(())
 }
@@ -6,15 +6,17 @@ use hydentity::Hydentity;
 use hyle::{
     bus::{metrics::BusMetrics, SharedMessageBus},
     indexer::{
+        contract_registry::ContractRegistryIndexer,
         contract_state_indexer::{ContractStateIndexer, ContractStateIndexerCtx},
         da_listener::{DAListener, DAListenerCtx},
+        staking_indexer::StakingIndexer,
         Indexer,
     },
     model::{api::NodeInfo, BlockHeight, CommonRunContext},
-    rest::{RestApi, RestApiRunContext},
+    rest::{ApiDoc, RestApi, RestApiRunContext},
     utils::{
         conf,
-        logger::{setup_tracing, TracingMode},
+        logger::{setup_tracing, shutdown_tracing, TracingMode},
         modules::{Module, ModulesHandler},
     },
 };
@@ -28,6 +30,7 @@ use testcontainers_modules::{
     testcontainers::{runners::AsyncRunner, ImageExt},
 };
 use tracing::{error, info, warn};
+use utoipa::OpenApi;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -37,6 +40,14 @@ pub struct Args {
 
     #[clap(long, action)]
     pub pg: bool,
+
+    /// DA address of the node to follow. Overrides `da_address` from the configuration.
+    #[arg(long)]
+    pub da_address: Option<String>,
+
+    /// Overrides a configuration value, e.g. `--set da_stream.ping_interval=5`. Can be repeated.
+    #[arg(long = "set", value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
 }
 
 #[cfg(feature = "dhat")]
@@ -54,15 +65,21 @@ async fn main() -> Result<()> {
 
     let args = Args::parse();
     let mut config =
-        conf::Conf::new(args.config_file, None, Some(true)).context("reading config file")?;
+        conf::Conf::with_overrides(args.config_file, None, Some(true), &args.overrides)
+            .context("reading config file")?;
+    if let Some(da_address) = args.da_address {
+        config.da_address = da_address;
+    }
 
     setup_tracing(
-        match config.log_format.as_str() {
+        match config.log.format.as_str() {
             "json" => TracingMode::Json,
             "node" => TracingMode::NodeName,
             _ => TracingMode::Full,
         },
         format!("{}(nopkey)", config.id.clone(),),
+        config.otlp_endpoint.clone(),
+        &config.log.modules,
     )?;
 
     let pg;
@@ -88,7 +105,7 @@ async fn main() -> Result<()> {
 
     let config = Arc::new(config);
 
-    info!("Starting indexer with config: {:?}", &config);
+    info!("Starting indexer with config: {:?}", &config.redacted());
 
     // Init global metrics meter we expose as an endpoint
     let metrics_layer = HttpMetricsLayerBuilder::new()
@@ -106,12 +123,13 @@ async fn main() -> Result<()> {
     std::fs::create_dir_all(&config.data_directory).context("creating data directory")?;
 
     let mut handler = ModulesHandler::new(&bus).await;
+    handler.set_restart_policies(&config);
 
     let ctx = Arc::new(CommonRunContext {
         bus: bus.new_handle(),
         config: config.clone(),
         router: Mutex::new(Some(Router::new())),
-        openapi: Default::default(),
+        openapi: Mutex::new(ApiDoc::openapi()),
     });
 
     handler
@@ -132,6 +150,10 @@ async fn main() -> Result<()> {
             common: ctx.clone(),
         })
         .await?;
+    handler
+        .build_module::<ContractRegistryIndexer>(ctx.clone())
+        .await?;
+    handler.build_module::<StakingIndexer>(ctx.clone()).await?;
 
     let indexer = Indexer::build(ctx.clone()).await?;
     //let last_block: Option<BlockHeight> = None;
@@ -153,15 +175,25 @@ async fn main() -> Result<()> {
         .expect("Context router should be available")
         .take()
         .expect("Context router should be available");
+    #[allow(clippy::expect_used, reason = "Fail on misconfiguration")]
+    let openapi = ctx
+        .openapi
+        .lock()
+        .expect("OpenAPI should be available")
+        .clone();
 
     handler
         .build_module::<RestApi>(RestApiRunContext {
+            auth: ctx.config.rest_auth.clone(),
+            cors: ctx.config.rest_cors.clone(),
+            rate_limit: ctx.config.rest_rate_limit.clone(),
+            config: ctx.config.clone(),
             rest_addr: ctx.config.rest.clone(),
             max_body_size: ctx.config.rest_max_body_size,
             bus: ctx.bus.new_handle(),
             metrics_layer: Some(metrics_layer),
             router: router.clone(),
-            openapi: Default::default(),
+            openapi,
             info: NodeInfo {
                 id: ctx.config.id.clone(),
                 da_address: ctx.config.da_address.clone(),
@@ -205,5 +237,7 @@ async fn main() -> Result<()> {
         std::fs::remove_dir_all(&config.data_directory).context("removing data directory")?;
     }
 
+    shutdown_tracing();
+
     Ok(())
 }
@@ -1,3 +1,5 @@
+use std::any::type_name;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -5,11 +7,17 @@ use super::BusMessage;
 use anyhow::bail;
 use anyhow::Result;
 use tokio::sync::Mutex;
+use tokio::time::Instant;
 
 use crate::bus::BusClientSender;
 
 pub const CLIENT_TIMEOUT_SECONDS: u64 = 10;
 
+/// Identifies a query in logs, from the request to its answer.
+pub type QueryId = u64;
+
+static NEXT_QUERY_ID: AtomicU64 = AtomicU64::new(0);
+
 #[derive(Clone, Debug)]
 pub struct Query<Type, Answer>(Arc<Mutex<Option<InnerQuery<Type, Answer>>>>);
 impl<Type, Answer> Query<Type, Answer> {
@@ -26,11 +34,19 @@ impl<Type, Answer> Query<Type, Answer> {
 
 #[derive(Debug)]
 pub struct InnerQuery<Type, Answer> {
+    pub id: QueryId,
+    /// Past this instant, the requester no longer waits for the answer
+    pub deadline: Instant,
     pub callback: tokio::sync::oneshot::Sender<Result<Answer>>,
     pub data: Type,
 }
 impl<Cmd, Res> BusMessage for Query<Cmd, Res> {}
 impl<Cmd, Res> InnerQuery<Cmd, Res> {
+    /// Whether nobody waits for the answer anymore, so the query can be skipped.
+    pub fn is_expired(&self) -> bool {
+        self.callback.is_closed() || Instant::now() >= self.deadline
+    }
+
     pub fn answer(self, data: Res) -> Result<()> {
         self.callback
             .send(Ok(data))
@@ -52,6 +68,14 @@ where
     Res: Clone + Send + Sync + 'static,
 {
     fn request(&mut self, cmd: Cmd) -> impl std::future::Future<Output = Result<Res>> + Send;
+
+    /// Sends the query and waits for its answer until the timeout.
+    /// Fails right away if no module answers this kind of query.
+    fn request_with_timeout(
+        &mut self,
+        cmd: Cmd,
+        timeout: Duration,
+    ) -> impl std::future::Future<Output = Result<Res>> + Send;
 }
 
 impl<Cmd, Res, T: BusClientSender<Query<Cmd, Res>> + Send> CmdRespClient<Cmd, Res> for T
@@ -60,33 +84,70 @@ where
     Res: Clone + Send + Sync + 'static,
 {
     async fn request(&mut self, cmd: Cmd) -> Result<Res> {
+        self.request_with_timeout(cmd, Duration::from_secs(CLIENT_TIMEOUT_SECONDS))
+            .await
+    }
+
+    async fn request_with_timeout(&mut self, cmd: Cmd, timeout: Duration) -> Result<Res> {
+        let id = NEXT_QUERY_ID.fetch_add(1, Ordering::Relaxed);
         let (tx, rx) = tokio::sync::oneshot::channel();
         let query_cmd = Query(Arc::new(Mutex::new(Some(InnerQuery {
+            id,
+            deadline: Instant::now() + timeout,
             callback: tx,
             data: cmd,
         }))));
 
-        _ = self.send(query_cmd);
+        if !matches!(self.send(query_cmd), Ok(receivers) if receivers > 0) {
+            bail!("No module answers {} queries", type_name::<Cmd>());
+        }
 
-        match tokio::time::timeout(Duration::from_secs(CLIENT_TIMEOUT_SECONDS), rx).await {
+        match tokio::time::timeout(timeout, rx).await {
             Ok(Ok(res)) => res,
-            Ok(Err(e)) => bail!("Error while calling topic: {}", e),
-            Err(timeouterror) => bail!(
-                "Timeout triggered while calling topic with query: {}",
-                timeouterror.to_string()
+            Ok(Err(e)) => bail!("Error while calling topic with query #{}: {}", id, e),
+            Err(_) => bail!(
+                "Timeout triggered while calling topic with query #{} after {:?}",
+                id,
+                timeout
             ),
         }
     }
 }
 
 pub mod handle_messages_helpers {
-    use crate::bus::metrics::BusMetrics;
+    use tokio::sync::broadcast::{self, error::RecvError};
+
+    use crate::bus::metrics::{BusMetrics, ReceiveRecorder};
     use crate::utils::static_type_map::Pick;
     pub fn receive_bus_metrics<Msg: 'static, Client: Pick<BusMetrics> + 'static>(
         _bus: &mut Client,
     ) {
         Pick::<BusMetrics>::get_mut(_bus).receive::<Msg, Client>();
     }
+
+    pub fn receive_recorder<Msg: 'static, Client: Pick<BusMetrics> + 'static>(
+        bus: &mut Client,
+    ) -> ReceiveRecorder {
+        Pick::<BusMetrics>::get_mut(bus).receive_recorder::<Msg, Client>()
+    }
+
+    /// Receives the next message, recording how far behind the receiver is.
+    /// Messages missed because the receiver lagged are recorded instead of being silently skipped.
+    pub async fn recv_recording_lag<Msg: Clone>(
+        receiver: &mut broadcast::Receiver<Msg>,
+        recorder: &ReceiveRecorder,
+    ) -> Result<Msg, RecvError> {
+        loop {
+            match receiver.recv().await {
+                Ok(msg) => {
+                    recorder.received(receiver.len());
+                    return Ok(msg);
+                }
+                Err(RecvError::Lagged(skipped)) => recorder.lagged(skipped),
+                Err(e) => return Err(e),
+            }
+        }
+    }
 }
 
 #[macro_export]
@@ -96,7 +157,7 @@ macro_rules! handle_messages {
         #[allow(unused_imports)]
         use $crate::utils::static_type_map::Pick;
         #[allow(unused_imports)]
-        use $crate::bus::command_response::handle_messages_helpers::receive_bus_metrics;
+        use $crate::bus::command_response::handle_messages_helpers::{receive_bus_metrics, receive_recorder, recv_recording_lag};
         $crate::handle_messages! {
             bus($bus) index(bus_receiver) $($rest)*
         }
@@ -106,28 +167,34 @@ macro_rules! handle_messages {
         // Create a receiver with a unique variable $index
         let $index = unsafe { &mut *Pick::<tokio::sync::broadcast::Receiver<Query<$command, $response>>>::splitting_get_mut(&mut $bus) };
         paste::paste! {
+        let [<$index _recorder>] = receive_recorder::<Query<$command, $response>, _>(&mut $bus);
         $crate::handle_messages! {
             bus($bus) index([<$index a>]) $($rest)*
             // Listen on receiver
-            Ok(_raw_query) = #[allow(clippy::macro_metavars_in_unsafe)] $index.recv() => {
+            Ok(_raw_query) = #[allow(clippy::macro_metavars_in_unsafe)] recv_recording_lag($index, &[<$index _recorder>]) => {
                 receive_bus_metrics::<Query<$command, $response>,_>(&mut $bus);
-                if let Ok(mut _value) = _raw_query.take() {
-                    let $res = &mut _value.data;
-                    let res: Result<$response> = $handler;
-                    match res {
-                        Ok(res) => {
-                            if let Err(e) = _value.answer(res) {
-                                tracing::error!("Error while answering query: {}", e);
+                match _raw_query.take() {
+                    Ok(_value) if _value.is_expired() => {
+                        tracing::debug!("Skipping expired query #{}", _value.id);
+                    }
+                    Ok(mut _value) => {
+                        let query_id = _value.id;
+                        let $res = &mut _value.data;
+                        let res: Result<$response> = $handler;
+                        match res {
+                            Ok(res) => {
+                                if let Err(e) = _value.answer(res) {
+                                    tracing::error!("Error while answering query #{}: {}", query_id, e);
+                                }
                             }
-                        }
-                        Err(e) => {
-                            if let Err(e) = _value.bail(e) {
-                                tracing::error!("Error while answering query: {}", e);
+                            Err(e) => {
+                                if let Err(e) = _value.bail(e) {
+                                    tracing::error!("Error while answering query #{}: {}", query_id, e);
+                                }
                             }
                         }
                     }
-                } else {
-                    tracing::error!("Query already answered");
+                    Err(_) => tracing::error!("Query already answered"),
                 }
             }
         }
@@ -136,23 +203,28 @@ macro_rules! handle_messages {
         // That is the purpose of this try_recv loop, empty the topic
         while let Ok(_raw_query) = $index.try_recv() {
             receive_bus_metrics::<Query<$command, $response>,_>(&mut $bus);
-            if let Ok(mut _value) = _raw_query.take() {
-                let $res = &mut _value.data;
-                let res: Result<$response> = $handler;
-                match res {
-                    Ok(res) => {
-                        if let Err(e) = _value.answer(res) {
-                            tracing::error!("Error while answering query: {}", e);
+            match _raw_query.take() {
+                Ok(_value) if _value.is_expired() => {
+                    tracing::debug!("Skipping expired query #{}", _value.id);
+                }
+                Ok(mut _value) => {
+                    let query_id = _value.id;
+                    let $res = &mut _value.data;
+                    let res: Result<$response> = $handler;
+                    match res {
+                        Ok(res) => {
+                            if let Err(e) = _value.answer(res) {
+                                tracing::error!("Error while answering query #{}: {}", query_id, e);
+                            }
                         }
-                    }
-                    Err(e) => {
-                        if let Err(e) = _value.bail(e) {
-                            tracing::error!("Error while answering query: {}", e);
+                        Err(e) => {
+                            if let Err(e) = _value.bail(e) {
+                                tracing::error!("Error while answering query #{}: {}", query_id, e);
+                            }
                         }
                     }
                 }
-            } else {
-                tracing::error!("Query already answered");
+                Err(_) => tracing::error!("Query already answered"),
             }
         };
     };
@@ -160,9 +232,10 @@ macro_rules! handle_messages {
     (bus($bus:expr) index($index:ident) listen<$message:ty> $res:pat => $handler:block $($rest:tt)*) => {
         let $index = unsafe { &mut *Pick::<tokio::sync::broadcast::Receiver<$message>>::splitting_get_mut(&mut $bus) };
         paste::paste! {
+        let [<$index _recorder>] = receive_recorder::<$message, _>(&mut $bus);
         $crate::handle_messages! {
             bus($bus) index([<$index a>]) $($rest)*
-            Ok($res) = $index.recv()  => {
+            Ok($res) = recv_recording_lag($index, &[<$index _recorder>]) => {
                 receive_bus_metrics::<$message, _>(&mut $bus);
                 $handler
             }
@@ -204,7 +277,7 @@ pub use handle_messages;
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::bus::{bus_client, SharedMessageBus};
+    use crate::bus::{bus_client, metrics::BusMetrics, SharedMessageBus};
 
     bus_client!(
         struct TestBusClient {
@@ -232,4 +305,71 @@ mod test {
 
         assert_eq!(res.await.unwrap(), 3);
     }
+
+    bus_client!(
+        struct TestRequester {
+            sender(Query<i32, u8>),
+        }
+    );
+
+    #[tokio::test]
+    async fn test_request_timeouts() {
+        let shared_bus = SharedMessageBus::default();
+        let mut requester = TestRequester::new_from_bus(shared_bus.new_handle()).await;
+        let timeout = Duration::from_millis(50);
+
+        let err = requester
+            .request_with_timeout(1, timeout)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("No module answers"), "{err}");
+
+        // Not handled in time, the query is then skipped by the receiver
+        let mut receiver = TestBusClient::new_from_bus(shared_bus).await;
+        let err = requester
+            .request_with_timeout(1, timeout)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().starts_with("Timeout triggered"), "{err}");
+
+        let handled = Arc::new(AtomicU64::new(0));
+        let counter = handled.clone();
+        tokio::spawn(async move {
+            handle_messages! {
+                on_bus receiver,
+                command_response<i32, u8> query => {
+                    counter.fetch_add(1, Ordering::Relaxed);
+                    Ok(*query as u8 * 2)
+                }
+            }
+        });
+        assert_eq!(requester.request_with_timeout(2, timeout).await.unwrap(), 4);
+        assert_eq!(handled.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_records_lagging_receivers() {
+        let mut metrics = BusMetrics::global("test".to_string());
+        let recorder = metrics.receive_recorder::<u32, TestBusClient>();
+        let (sender, mut receiver) = tokio::sync::broadcast::channel(2);
+        for i in 0..5u32 {
+            sender.send(i).unwrap();
+        }
+
+        let recv = handle_messages_helpers::recv_recording_lag;
+        // The 3 oldest messages were overwritten
+        assert_eq!(recv(&mut receiver, &recorder).await.unwrap(), 3);
+        assert_eq!(recv(&mut receiver, &recorder).await.unwrap(), 4);
+
+        let channels = metrics.diagnostics().channels();
+        assert_eq!(channels.len(), 1);
+        let channel = &channels[0];
+        assert_eq!(
+            (channel.message.as_str(), channel.client.as_str()),
+            ("u32", "TestBusClient")
+        );
+        assert_eq!(channel.received, 2);
+        assert_eq!(channel.lagged, 3);
+        assert_eq!((channel.depth, channel.max_depth), (0, 1));
+    }
 }
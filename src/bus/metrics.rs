@@ -1,17 +1,85 @@
 use std::{
     any::{type_name, TypeId},
     collections::HashMap,
+    sync::{Arc, Mutex},
 };
 
-use opentelemetry::{InstrumentationScope, KeyValue};
+use hyle_model::api::APIBusChannel;
+use opentelemetry::{
+    metrics::{Counter, Gauge},
+    InstrumentationScope, KeyValue,
+};
 use quote::ToTokens;
 use syn::{parse_str, Type};
+use tracing::warn;
 
 #[derive(Debug, Clone)]
 pub struct BusMetrics {
     labels: HashMap<(TypeId, TypeId), [KeyValue; 2]>,
     send: opentelemetry::metrics::Counter<u64>,
     receive: opentelemetry::metrics::Counter<u64>,
+    lagged: Counter<u64>,
+    depth: Gauge<u64>,
+    diagnostics: BusDiagnostics,
+}
+
+/// Receive statistics of every channel of a bus, by message and client type.
+#[derive(Debug, Clone, Default)]
+pub struct BusDiagnostics(Arc<Mutex<HashMap<(TypeId, TypeId), APIBusChannel>>>);
+
+impl BusDiagnostics {
+    /// Channels sorted by message, then client.
+    pub fn channels(&self) -> Vec<APIBusChannel> {
+        let mut channels: Vec<_> = match self.0.lock() {
+            Ok(channels) => channels.values().cloned().collect(),
+            Err(poisoned) => poisoned.into_inner().values().cloned().collect(),
+        };
+        channels.sort_by(|a, b| (&a.message, &a.client).cmp(&(&b.message, &b.client)));
+        channels
+    }
+
+    fn update(&self, key: &(TypeId, TypeId), update: impl FnOnce(&mut APIBusChannel)) {
+        let mut channels = match self.0.lock() {
+            Ok(channels) => channels,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if let Some(channel) = channels.get_mut(key) {
+            update(channel);
+        }
+    }
+}
+
+/// Records what a client receives from a channel: how many messages are left to read, and
+/// how many were missed because the client lagged behind.
+pub struct ReceiveRecorder {
+    key: (TypeId, TypeId),
+    message: String,
+    client: String,
+    labels: [KeyValue; 2],
+    lagged: Counter<u64>,
+    depth: Gauge<u64>,
+    diagnostics: BusDiagnostics,
+}
+
+impl ReceiveRecorder {
+    pub fn received(&self, depth: usize) {
+        self.depth.record(depth as u64, &self.labels);
+        self.diagnostics.update(&self.key, |channel| {
+            channel.received += 1;
+            channel.depth = depth;
+            channel.max_depth = channel.max_depth.max(depth);
+        });
+    }
+
+    pub fn lagged(&self, skipped: u64) {
+        warn!(
+            "Bus client {} lagged behind and missed {} {} message(s)",
+            self.client, skipped, self.message
+        );
+        self.lagged.add(skipped, &self.labels);
+        self.diagnostics
+            .update(&self.key, |channel| channel.lagged += skipped);
+    }
 }
 
 #[allow(clippy::unwrap_used, clippy::expect_used)]
@@ -24,6 +92,42 @@ impl BusMetrics {
             labels: HashMap::new(),
             send: my_meter.u64_counter("send").build(),
             receive: my_meter.u64_counter("receive").build(),
+            lagged: my_meter.u64_counter("receive_lagged").build(),
+            depth: my_meter.u64_gauge("receive_depth").build(),
+            diagnostics: BusDiagnostics::default(),
+        }
+    }
+
+    pub fn diagnostics(&self) -> BusDiagnostics {
+        self.diagnostics.clone()
+    }
+
+    pub fn receive_recorder<Msg: 'static, Client: 'static>(&mut self) -> ReceiveRecorder {
+        let key = self.get_key::<Msg, Client>();
+        self.get_or_insert_labels::<Msg, Client>(&key);
+        let labels = self.labels.get(&key).unwrap().clone();
+
+        let mut channels = match self.diagnostics.0.lock() {
+            Ok(channels) => channels,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let channel = channels.entry(key).or_insert_with(|| APIBusChannel {
+            message: labels[0].value.to_string(),
+            client: labels[1].value.to_string(),
+            received: 0,
+            lagged: 0,
+            depth: 0,
+            max_depth: 0,
+        });
+
+        ReceiveRecorder {
+            key,
+            message: channel.message.clone(),
+            client: channel.client.clone(),
+            labels,
+            lagged: self.lagged.clone(),
+            depth: self.depth.clone(),
+            diagnostics: self.diagnostics.clone(),
         }
     }
 
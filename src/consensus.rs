@@ -8,17 +8,19 @@ use crate::{bus::BusClientSender, utils::logger::LogMe};
 use crate::{
     bus::{command_response::Query, BusMessage},
     genesis::GenesisEvent,
-    mempool::QueryNewCut,
+    mempool::{PendingData, QueryNewCut, QueryPendingData},
     model::{Cut, Hashable, StakingAction, ValidatorPublicKey},
     p2p::{network::OutboundMessage, P2PCommand},
     utils::{
-        conf::SharedConf,
+        conf::{EarlyBlockConf, SharedConf},
         crypto::{BlstCrypto, SharedBlstCrypto},
         modules::Module,
     },
 };
 use anyhow::{anyhow, bail, Context, Error, Result};
 use bincode::{Decode, Encode};
+use evidence::{EquivocationEvidence, EvidenceStore};
+use hyle_model::api::{APIEquivocationEvidence, APIValidatorStatus};
 use hyle_model::utils::get_current_timestamp;
 use hyle_model::utils::get_current_timestamp_ms;
 use metrics::ConsensusMetrics;
@@ -26,27 +28,43 @@ use role_follower::{FollowerRole, FollowerState};
 use role_leader::{LeaderRole, LeaderState};
 use role_timeout::{TimeoutRole, TimeoutRoleState, TimeoutState};
 use serde::{Deserialize, Serialize};
-use staking::state::{Staking, MIN_STAKE};
+use staking::state::{Staking, EQUIVOCATION_SLASH_FRACTION, MIN_STAKE};
 use std::ops::Deref;
 use std::ops::DerefMut;
 use std::time::Duration;
-use std::{collections::HashMap, default::Default, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet},
+    default::Default,
+    path::PathBuf,
+};
 use tokio::time::interval;
 #[cfg(not(test))]
 use tokio::{sync::broadcast, time::sleep};
-use tracing::{debug, info, trace, warn};
+use tracing::{debug, info, instrument, trace, warn};
+use validator_admin::{KeyRotation, ValidatorAdminCommand, ValidatorMode, LEAVE_VALIDITY};
 
 pub mod api;
+pub mod evidence;
 pub mod metrics;
 pub mod module;
 pub mod role_follower;
 pub mod role_leader;
 pub mod role_timeout;
+pub mod validator_admin;
 
 // -----------------------------
 // ------ Consensus bus --------
 // -----------------------------
 
+/// Whether a block with this cut holds no new data: each lane is where the last cut left it.
+pub fn is_empty_cut(cut: &Cut, last_cut: &Cut) -> bool {
+    cut.iter().all(|(validator, dp_hash, ..)| {
+        last_cut.iter().any(|(last_validator, last_hash, ..)| {
+            last_validator == validator && last_hash == dp_hash
+        })
+    })
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ConsensusCommand {
     TimeoutTick,
@@ -63,6 +81,8 @@ pub struct CommittedConsensusProposal {
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ConsensusEvent {
     CommitConsensusProposal(CommittedConsensusProposal),
+    /// A bonded validator signed conflicting messages
+    Equivocation(EquivocationEvidence),
 }
 
 #[derive(Clone)]
@@ -71,6 +91,9 @@ pub struct QueryConsensusInfo {}
 #[derive(Clone)]
 pub struct QueryConsensusStakingState {}
 
+#[derive(Clone)]
+pub struct QueryConsensusEvidence {}
+
 impl BusMessage for ConsensusCommand {}
 impl BusMessage for ConsensusEvent {}
 impl BusMessage for ConsensusNetMessage {}
@@ -84,12 +107,15 @@ sender(ConsensusEvent),
 sender(ConsensusCommand),
 sender(P2PCommand),
 sender(Query<QueryNewCut, Cut>),
+sender(Query<QueryPendingData, PendingData>),
 receiver(ConsensusCommand),
 receiver(GenesisEvent),
 receiver(NodeStateEvent),
 receiver(SignedByValidator<ConsensusNetMessage>),
 receiver(Query<QueryConsensusInfo, ConsensusInfo>),
 receiver(Query<QueryConsensusStakingState, Staking>),
+receiver(Query<QueryConsensusEvidence, Vec<APIEquivocationEvidence>>),
+receiver(Query<ValidatorAdminCommand, APIValidatorStatus>),
 }
 }
 
@@ -99,6 +125,8 @@ pub struct BFTRoundState {
     consensus_proposal: ConsensusProposal,
     last_cut: Cut,
     staking: Staking,
+    /// Validator set of the current epoch
+    epoch: EpochSnapshot,
 
     leader: LeaderState,
     follower: FollowerState,
@@ -131,6 +159,13 @@ pub struct ConsensusStore {
     bft_round_state: BFTRoundState,
     /// Validators that asked to be part of consensus
     validator_candidates: Vec<NewValidatorCandidate>,
+    /// Validators that asked to leave consensus, until they are unbonded
+    validator_leaves: Vec<LeavingValidator>,
+    /// Whether the operator stopped the validator
+    validator_mode: ValidatorMode,
+    key_rotation: Option<KeyRotation>,
+    /// Conflicting messages signed by validators
+    evidence: EvidenceStore,
 }
 
 pub struct Consensus {
@@ -141,6 +176,10 @@ pub struct Consensus {
     #[allow(dead_code)]
     config: SharedConf,
     crypto: SharedBlstCrypto,
+    /// Task sending StartNewSlot at the end of the slot, aborted when starting it early
+    slot_timer: Option<tokio::task::JoinHandle<()>>,
+    /// Reason recorded in the next proposal
+    production_reason: BlockProductionReason,
 }
 
 impl Deref for Consensus {
@@ -192,6 +231,7 @@ impl Consensus {
 
         let staking_actions =
             std::mem::take(&mut self.bft_round_state.consensus_proposal.staking_actions);
+        let new_epoch = self.bft_round_state.consensus_proposal.epoch.take();
 
         // Reset round state, carrying over staking and current proposal.
         self.bft_round_state = BFTRoundState {
@@ -206,6 +246,7 @@ impl Consensus {
                 ..ConsensusProposal::default()
             },
             staking: std::mem::take(&mut self.bft_round_state.staking),
+            epoch: std::mem::take(&mut self.bft_round_state.epoch),
             ..BFTRoundState::default()
         };
 
@@ -216,6 +257,15 @@ impl Consensus {
                 self.bft_round_state.consensus_proposal.slot += 1;
                 self.bft_round_state.consensus_proposal.view = 0;
                 self.bft_round_state.follower.buffered_quorum_certificate = Some(qc);
+                if let Some(epoch) = new_epoch {
+                    info!(
+                        "🗓️ Epoch {} started at block {} with {} validators",
+                        epoch.epoch,
+                        epoch.start_height,
+                        epoch.validators.len()
+                    );
+                    self.bft_round_state.epoch = epoch;
+                }
                 // Any new validators are added to the consensus and removed from candidates.
                 for action in staking_actions {
                     match action {
@@ -227,6 +277,36 @@ impl Consensus {
                                 .bond(candidate.pubkey)
                                 .map_err(|e| anyhow::anyhow!(e))?;
                         }
+                        // Slashed as the proposal commits, so that all validators agree on
+                        // the bonded validators of the next slot
+                        ConsensusStakingAction::Slash { evidence } => {
+                            let offense = evidence.offense_hash().map_err(|e| anyhow!(e))?;
+                            warn!(
+                                "⚔️ Validator {} slashed for offense {}",
+                                evidence.validator(),
+                                offense
+                            );
+                            self.store
+                                .bft_round_state
+                                .staking
+                                .slash(
+                                    evidence.validator().clone(),
+                                    EQUIVOCATION_SLASH_FRACTION,
+                                    offense,
+                                    BlockHeight(self.bft_round_state.consensus_proposal.slot - 1),
+                                )
+                                .map_err(|e| anyhow!(e))?;
+                        }
+                        ConsensusStakingAction::Leave { validator } => {
+                            self.store
+                                .validator_leaves
+                                .retain(|v| v.pubkey != validator.pubkey);
+                            self.store
+                                .bft_round_state
+                                .staking
+                                .leave(&validator.pubkey)
+                                .map_err(|e| anyhow!(e))?;
+                        }
                     }
                 }
             }
@@ -255,12 +335,34 @@ impl Consensus {
             self.bft_round_state
                 .timeout
                 .state
-                .schedule_next(get_current_timestamp());
+                .schedule_next(get_current_timestamp(), self.timeout_secs());
         }
 
         Ok(())
     }
 
+    /// Seconds followers wait for the leader, including the time it may skip empty blocks.
+    fn timeout_secs(&self) -> u64 {
+        TimeoutState::TIMEOUT_SECS + self.config.consensus.empty_block_interval.div_ceil(1000)
+    }
+
+    /// Whether the leader waits before proposing a block: nothing new to commit, and the last
+    /// block is more recent than the configured interval between empty blocks.
+    fn should_skip_empty_block(
+        &self,
+        ticket: &Ticket,
+        cut: &Cut,
+        staking_actions: &[ConsensusStakingAction],
+        current_timestamp: u64,
+    ) -> bool {
+        let interval = self.config.consensus.empty_block_interval;
+        interval > 0
+            && matches!(ticket, Ticket::CommitQC(_))
+            && staking_actions.is_empty()
+            && is_empty_cut(cut, &self.bft_round_state.last_cut)
+            && current_timestamp < self.bft_round_state.consensus_proposal.timestamp + interval
+    }
+
     /// Verify that quorum certificate includes only validators that are part of the consensus
     fn verify_quorum_signers_part_of_consensus(
         &self,
@@ -275,17 +377,108 @@ impl Consensus {
         })
     }
 
+    /// Snapshot of the bonded validators if they changed since the current epoch started, in which
+    /// case the proposal of this slot starts a new epoch.
+    fn next_epoch(&self) -> Option<EpochSnapshot> {
+        let staking = &self.bft_round_state.staking;
+        let current = &self.bft_round_state.epoch;
+        let snapshot = EpochSnapshot {
+            epoch: current.epoch + 1,
+            start_height: BlockHeight(self.bft_round_state.consensus_proposal.slot),
+            validators: staking
+                .bonded()
+                .iter()
+                .map(|v| (v.clone(), staking.get_stake(v).unwrap_or(0)))
+                .collect(),
+        };
+        (!snapshot.same_validators(current)).then_some(snapshot)
+    }
+
+    /// Verify that the proposal starts a new epoch exactly when the bonded validators changed,
+    /// with their stakes, as certificates of the epoch are checked against them.
+    fn verify_epoch(&self, proposal: &ConsensusProposal) -> Result<()> {
+        match (self.next_epoch(), &proposal.epoch) {
+            (None, None) => Ok(()),
+            (Some(expected), Some(epoch)) if &expected == epoch => Ok(()),
+            (expected, epoch) => bail!(
+                "Proposal epoch {:?} does not match the bonded validators, expected {:?}",
+                epoch,
+                expected
+            ),
+        }
+    }
+
     fn verify_staking_actions(&mut self, proposal: &ConsensusProposal) -> Result<()> {
+        let mut offenses = HashSet::new();
+        let mut leaving = HashSet::new();
         for action in &proposal.staking_actions {
             match action {
                 ConsensusStakingAction::Bond { candidate } => {
                     self.verify_new_validators_to_bond(candidate)?;
                 }
+                ConsensusStakingAction::Slash { evidence } => {
+                    let offense = self.verify_slash_evidence(evidence)?;
+                    if !offenses.insert(offense) {
+                        bail!(
+                            "Validator {} slashed twice for the same offense",
+                            evidence.validator()
+                        );
+                    }
+                }
+                ConsensusStakingAction::Leave { validator } => {
+                    self.verify_validator_leave(validator, proposal.slot)?;
+                    if !leaving.insert(&validator.pubkey) {
+                        bail!("Validator {} leaves twice", validator.pubkey);
+                    }
+                }
             }
         }
         Ok(())
     }
 
+    /// Verify that a bonded validator signed the leave message, for a slot close enough to `slot`.
+    fn verify_validator_leave(&self, leaving: &LeavingValidator, slot: Slot) -> Result<()> {
+        if !BlstCrypto::verify(&leaving.msg)? {
+            bail!("Leaving validator has an invalid signature");
+        }
+        let ConsensusNetMessage::ValidatorLeave(leave) = &leaving.msg.msg else {
+            bail!("Leaving validator forwarded signed message is not a leave message");
+        };
+        if leave.pubkey != leaving.pubkey || leaving.msg.signature.validator != leaving.pubkey {
+            bail!("Leave message of {} is not signed by it", leaving.pubkey);
+        }
+        if leave.slot > slot || slot - leave.slot > LEAVE_VALIDITY {
+            bail!(
+                "Leave message of slot {} can't be included at slot {}",
+                leave.slot,
+                slot
+            );
+        }
+        if !self.is_part_of_consensus(&leaving.pubkey) {
+            bail!("Leaving validator {} is not bonded", leaving.pubkey);
+        }
+        Ok(())
+    }
+
+    /// Verify that the validator signed both conflicting messages and wasn't slashed for them yet.
+    /// Returns the hash of the offense.
+    fn verify_slash_evidence(&self, evidence: &SlashEvidence) -> Result<String> {
+        let offense = evidence
+            .offense_hash()
+            .map_err(|e| anyhow!("Invalid slash evidence: {e}"))?;
+        if !BlstCrypto::verify(&evidence.first)? || !BlstCrypto::verify(&evidence.second)? {
+            bail!("Slash evidence has an invalid signature");
+        }
+        if self.bft_round_state.staking.is_slashed_for(&offense) {
+            bail!(
+                "Validator {} already slashed for offense {}",
+                evidence.validator(),
+                offense
+            );
+        }
+        Ok(offense)
+    }
+
     /// Verify that new validators have enough stake
     /// and have a valid signature so can be bonded.
     fn verify_new_validators_to_bond(
@@ -304,6 +497,13 @@ impl Consensus {
         } else {
             bail!("New bonded validator has no stake");
         }
+        if self
+            .bft_round_state
+            .staking
+            .is_jailed(&new_validator.pubkey)
+        {
+            bail!("New bonded validator is jailed");
+        }
         // Verify that the new validator has a valid signature
         if !BlstCrypto::verify(&new_validator.msg)? {
             bail!("New bonded validator has an invalid signature");
@@ -324,9 +524,12 @@ impl Consensus {
 
             self.validator_candidates
                 .retain(|v| v.pubkey != new_validator.pubkey);
-            self.bus.send(P2PCommand::ConnectTo {
-                peer: peer_address.clone(),
-            })?;
+            // Outbound-only validators connect to us instead
+            if !peer_address.is_empty() {
+                self.bus.send(P2PCommand::ConnectTo {
+                    peer: peer_address.clone(),
+                })?;
+            }
         } else {
             bail!("New bonded validator forwarded signed message is not a candidacy message");
         }
@@ -352,7 +555,7 @@ impl Consensus {
             >::get(&self.bus)
             .clone();
             let interval = self.config.consensus.slot_duration;
-            tokio::task::Builder::new()
+            let timer = tokio::task::Builder::new()
                 .name("sleep-consensus")
                 .spawn(async move {
                     debug!(
@@ -365,6 +568,7 @@ impl Consensus {
                         .send(ConsensusCommand::StartNewSlot)
                         .log_error("Cannot send StartNewSlot message over channel");
                 })?;
+            self.slot_timer = Some(timer);
             Ok(())
         }
         #[cfg(test)]
@@ -373,6 +577,56 @@ impl Consensus {
         }
     }
 
+    /// Starts the pending slot right away when mempool holds more data than configured,
+    /// instead of waiting for the end of the slot.
+    async fn start_round_early_if_needed(&mut self) -> Result<()> {
+        let conf = &self.config.consensus.early_block;
+        if (conf.max_pending_txs == 0 && conf.max_pending_bytes == 0)
+            || !matches!(self.bft_round_state.state_tag, StateTag::Leader)
+            || !matches!(
+                self.bft_round_state.leader.step,
+                role_leader::Step::StartNewSlot
+            )
+            || self.bft_round_state.leader.pending_ticket.is_none()
+        {
+            return Ok(());
+        }
+        let now = get_current_timestamp_ms();
+        if now < self.bft_round_state.consensus_proposal.timestamp + conf.min_interval {
+            return Ok(());
+        }
+
+        let pending = self.bus.request(QueryPendingData {}).await?;
+        let reason = match Self::early_block_reason(conf, &pending) {
+            Some(reason) => reason,
+            None => return Ok(()),
+        };
+
+        if let Some(timer) = self.slot_timer.take() {
+            timer.abort();
+        }
+        info!(
+            "⏩ Starting slot early ({:?}): {} pending txs, {} pending bytes",
+            reason, pending.txs, pending.bytes
+        );
+        self.metrics.start_new_round("early_block");
+        self.production_reason = reason;
+        self.start_round(now).await
+    }
+
+    fn early_block_reason(
+        conf: &EarlyBlockConf,
+        pending: &PendingData,
+    ) -> Option<BlockProductionReason> {
+        if conf.max_pending_bytes > 0 && pending.bytes >= conf.max_pending_bytes {
+            Some(BlockProductionReason::PendingBytes)
+        } else if conf.max_pending_txs > 0 && pending.txs >= conf.max_pending_txs {
+            Some(BlockProductionReason::PendingTxs)
+        } else {
+            None
+        }
+    }
+
     fn get_own_voting_power(&self) -> u128 {
         if self.is_part_of_consensus(self.crypto.validator_pubkey()) {
             if let Some(my_stake) = self
@@ -455,7 +709,7 @@ impl Consensus {
     fn send_candidacy(&mut self) -> Result<()> {
         let candidacy = ValidatorCandidacy {
             pubkey: self.crypto.validator_pubkey().clone(),
-            peer_address: self.config.host.clone(),
+            peer_address: self.config.advertised_p2p_address().unwrap_or_default(),
         };
         info!(
             "📝 Sending candidacy message to be part of consensus.  {}",
@@ -484,6 +738,8 @@ impl Consensus {
             ..
         } = msg.clone();
 
+        self.record_evidence(&msg);
+
         match net_message {
             ConsensusNetMessage::Prepare(consensus_proposal, ticket) => {
                 self.on_prepare(sender, consensus_proposal, ticket)
@@ -508,6 +764,7 @@ impl Consensus {
             ConsensusNetMessage::ValidatorCandidacy(candidacy) => {
                 self.on_validator_candidacy(msg, candidacy)
             }
+            ConsensusNetMessage::ValidatorLeave(leave) => self.on_validator_leave(msg, leave),
         }
     }
 
@@ -605,8 +862,14 @@ impl Consensus {
 
     fn carry_on_with_ticket(&mut self, ticket: Ticket) -> Result<()> {
         self.finish_round(Some(ticket.clone()))?;
+        self.try_hand_over()?;
 
-        if self.is_round_leader() {
+        if self.validator_mode != ValidatorMode::Active {
+            if self.is_round_leader() {
+                info!("⏸️ Validator stopped, not proposing slot as leader");
+            }
+            Ok(())
+        } else if self.is_round_leader() {
             // Setup our ticket for the next round
             // Send Prepare message to all validators
             self.delay_start_new_round(ticket)
@@ -629,6 +892,7 @@ impl Consensus {
         }
     }
 
+    #[instrument(skip_all, fields(block_height = self.bft_round_state.consensus_proposal.slot))]
     fn try_commit_current_proposal(
         &mut self,
         commit_quorum_certificate: QuorumCertificate,
@@ -641,7 +905,10 @@ impl Consensus {
             &commit_quorum_certificate,
         )?;
 
-        self.metrics.commit();
+        self.metrics.commit(
+            self.bft_round_state.consensus_proposal.slot,
+            self.bft_round_state.consensus_proposal.timestamp,
+        );
 
         _ = self
             .bus
@@ -662,6 +929,29 @@ impl Consensus {
         self.carry_on_with_ticket(Ticket::CommitQC(commit_quorum_certificate.clone()))
     }
 
+    /// Message received by leader & follower, from a validator that handed its duties over.
+    fn on_validator_leave(
+        &mut self,
+        msg: SignedByValidator<ConsensusNetMessage>,
+        leave: ValidatorLeave,
+    ) -> Result<()> {
+        info!("👋 Received leave message: {}", leave);
+        if msg.signature.validator != leave.pubkey {
+            bail!("🛑 Leave message is not signed by the leaving validator");
+        }
+        if !self.is_part_of_consensus(&leave.pubkey) {
+            debug!("Validator is not part of the consensus. Ignoring leave");
+            return Ok(());
+        }
+        // Only the latest message of a validator is kept, older ones may not be valid anymore
+        self.validator_leaves.retain(|v| v.pubkey != leave.pubkey);
+        self.validator_leaves.push(LeavingValidator {
+            pubkey: leave.pubkey,
+            msg,
+        });
+        Ok(())
+    }
+
     /// Message received by leader & follower.
     fn on_validator_candidacy(
         &mut self,
@@ -686,6 +976,10 @@ impl Consensus {
             return Ok(());
         }
 
+        if self.bft_round_state.staking.is_jailed(&candidacy.pubkey) {
+            bail!("🛑 Candidate validator is jailed");
+        }
+
         // Verify that the candidate has enough stake
         if let Some(stake) = self.bft_round_state.staking.get_stake(&candidacy.pubkey) {
             if stake < staking::state::MIN_STAKE {
@@ -724,6 +1018,59 @@ impl Consensus {
                                 .map_err(|e| anyhow!(e))?;
                         }
                         (_identity, StakingAction::Distribute { claim: _ }) => todo!(),
+                        // Rewards don't change the stakes
+                        (_identity, StakingAction::ClaimRewards { .. }) => {}
+                        (identity, StakingAction::Unbond { amount }) => {
+                            self.store
+                                .bft_round_state
+                                .staking
+                                .unbond(identity, amount, block.block_height)
+                                .map_err(|e| anyhow!(e))?;
+                        }
+                        (
+                            _identity,
+                            StakingAction::Slash {
+                                validator,
+                                fraction,
+                                evidence_hash,
+                            },
+                        ) => {
+                            // Slashes come from consensus, which applied them on commit
+                            if self
+                                .store
+                                .bft_round_state
+                                .staking
+                                .is_slashed_for(&evidence_hash)
+                            {
+                                continue;
+                            }
+                            warn!(
+                                "⚔️ Validator {} slashed for evidence {}",
+                                validator, evidence_hash
+                            );
+                            self.store
+                                .bft_round_state
+                                .staking
+                                .slash(validator, fraction, evidence_hash, block.block_height)
+                                .map_err(|e| anyhow!(e))?;
+                        }
+                        (identity, StakingAction::Withdraw { amount }) => {
+                            self.store
+                                .bft_round_state
+                                .staking
+                                .settle_withdrawal(identity, amount)
+                                .map_err(|e| anyhow!(e))?;
+                        }
+                    }
+                }
+                self.store
+                    .bft_round_state
+                    .staking
+                    .release_jailed(block.block_height);
+                // Nodes joining consensus learn the current epoch from the blocks
+                if let Some(epoch) = block.epoch {
+                    if epoch.epoch > self.bft_round_state.epoch.epoch {
+                        self.store.bft_round_state.epoch = epoch;
                     }
                 }
                 for validator in block.new_bounded_validators.iter() {
@@ -733,6 +1080,16 @@ impl Consensus {
                         .bond(validator.clone())
                         .map_err(|e| anyhow!(e))?;
                 }
+                // Consensus unbonds the validators that leave on commit
+                for validator in block.left_validators.iter() {
+                    if self.bft_round_state.staking.is_bonded(validator) {
+                        self.store
+                            .bft_round_state
+                            .staking
+                            .leave(validator)
+                            .map_err(|e| anyhow!(e))?;
+                    }
+                }
 
                 if let StateTag::Joining = self.bft_round_state.state_tag {
                     if self.store.bft_round_state.joining.staking_updated_to < block.block_height.0
@@ -747,13 +1104,17 @@ impl Consensus {
                 }
                 Ok(())
             }
+            _ => Ok(()),
         }
     }
 
     async fn handle_command(&mut self, msg: ConsensusCommand) -> Result<()> {
         match msg {
             ConsensusCommand::TimeoutTick => match &self.bft_round_state.timeout.state {
-                TimeoutState::Scheduled { timestamp } if get_current_timestamp() >= *timestamp => {
+                TimeoutState::Scheduled { timestamp }
+                    if get_current_timestamp() >= *timestamp
+                        && self.validator_mode == ValidatorMode::Active =>
+                {
                     // Trigger state transition to mutiny
                     info!(
                         "⏰ Trigger timeout for slot {} and view {}",
@@ -848,7 +1209,9 @@ impl Consensus {
                         module_handle_messages! {
                             on_bus self.bus,
                             listen<NodeStateEvent> event => {
-                                let NodeStateEvent::NewBlock(block) = &event;
+                                let NodeStateEvent::NewBlock(block) = &event else {
+                                    continue;
+                                };
                                 if block.block_height.0 != 0 {
                                     bail!("Non-genesis block received during consensus genesis");
                                 }
@@ -930,9 +1293,17 @@ impl Consensus {
             command_response<QueryConsensusStakingState, Staking> _ => {
                 Ok(self.bft_round_state.staking.clone())
             }
+            command_response<QueryConsensusEvidence, Vec<APIEquivocationEvidence>> _ => {
+                Ok(self.store.evidence.evidence().iter().map(Into::into).collect())
+            }
+            command_response<ValidatorAdminCommand, APIValidatorStatus> command => {
+                self.handle_validator_admin(command.clone())
+            }
             _ = timeout_ticker.tick() => {
                 self.bus.send(ConsensusCommand::TimeoutTick)
                     .log_error("Cannot send message over channel")?;
+                _ = self.start_round_early_if_needed().await
+                    .log_warn("Starting slot early");
             }
         };
 
@@ -1032,6 +1403,8 @@ pub mod test {
                 store,
                 config: Arc::new(conf),
                 crypto: Arc::new(crypto),
+                slot_timer: None,
+                production_reason: BlockProductionReason::Interval,
             }
         }
 
@@ -1108,7 +1481,7 @@ pub mod test {
                     .bft_round_state
                     .timeout
                     .state
-                    .schedule_next(get_current_timestamp() - 10);
+                    .schedule_next(get_current_timestamp() - 10, TimeoutState::TIMEOUT_SECS);
                 n.consensus
                     .handle_command(ConsensusCommand::TimeoutTick)
                     .await
@@ -1358,6 +1731,31 @@ pub mod test {
             }
         }
     }
+
+    #[test]
+    fn test_early_block_reason() {
+        let conf = EarlyBlockConf {
+            max_pending_txs: 10,
+            max_pending_bytes: 1000,
+            min_interval: 0,
+        };
+        let pending = |txs, bytes| PendingData { txs, bytes };
+
+        assert_eq!(Consensus::early_block_reason(&conf, &pending(9, 999)), None);
+        assert_eq!(
+            Consensus::early_block_reason(&conf, &pending(10, 0)),
+            Some(BlockProductionReason::PendingTxs)
+        );
+        assert_eq!(
+            Consensus::early_block_reason(&conf, &pending(10, 1000)),
+            Some(BlockProductionReason::PendingBytes)
+        );
+        assert_eq!(
+            Consensus::early_block_reason(&EarlyBlockConf::default(), &pending(1000, 1000000)),
+            None
+        );
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_happy_path() {
         let (mut node1, mut node2): (ConsensusTestCtx, ConsensusTestCtx) = build_nodes!(2).await;
@@ -1463,6 +1861,113 @@ pub mod test {
         };
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_validator_admin() {
+        use hyle_model::{
+            api::APIValidatorMode,
+            errors::{ErrorCode, HyleError},
+        };
+
+        let (mut node1, mut node2, mut node3, mut node4): (
+            ConsensusTestCtx,
+            ConsensusTestCtx,
+            ConsensusTestCtx,
+            ConsensusTestCtx,
+        ) = build_nodes!(4).await;
+
+        // The 3 other validators hold enough stake to go on
+        let status = node4
+            .consensus
+            .handle_validator_admin(ValidatorAdminCommand::Stop { force: false })
+            .unwrap();
+        assert_eq!(status.mode, APIValidatorMode::Stopped);
+        assert!(status.bonded);
+
+        node1.start_round().await;
+        broadcast! {
+            description: "Leader - Prepare",
+            from: node1, to: [node2, node3, node4],
+            message_matches: ConsensusNetMessage::Prepare(_, _)
+        };
+        node2.assert_send(&node1.validator_pubkey(), "Follower - PrepareVote");
+        node3.assert_send(&node1.validator_pubkey(), "Follower - PrepareVote");
+        assert!(node4.out_receiver.try_recv().is_err());
+
+        // Handing over to a bonded key from the current slot stops right away
+        let new_pubkey = node3.validator_pubkey();
+        let status = node4
+            .consensus
+            .handle_validator_admin(ValidatorAdminCommand::RotateKey {
+                new_pubkey,
+                handover_slot: status.slot,
+            })
+            .unwrap();
+        assert_eq!(status.mode, APIValidatorMode::HandedOver);
+        assert!(node4
+            .consensus
+            .handle_validator_admin(ValidatorAdminCommand::Start)
+            .is_err());
+
+        // The old key then asks to leave consensus
+        broadcast! {
+            description: "Handed over - Leave",
+            from: node4, to: [node1, node2, node3],
+            message_matches: ConsensusNetMessage::ValidatorLeave(_)
+        };
+        let leaving = node1.consensus.validator_leaves[0].clone();
+        assert_eq!(leaving.pubkey, node4.validator_pubkey());
+        let proposal = |slot, validator| ConsensusProposal {
+            slot,
+            staking_actions: vec![ConsensusStakingAction::Leave { validator }],
+            ..ConsensusProposal::default()
+        };
+        node2
+            .consensus
+            .verify_staking_actions(&proposal(status.slot, leaving.clone()))
+            .unwrap();
+        // Too late to be included, or on behalf of another validator
+        assert!(node2
+            .consensus
+            .verify_staking_actions(&proposal(status.slot + LEAVE_VALIDITY + 1, leaving.clone()))
+            .is_err());
+        assert!(node2
+            .consensus
+            .verify_staking_actions(&proposal(
+                status.slot,
+                LeavingValidator {
+                    pubkey: node3.validator_pubkey(),
+                    msg: leaving.msg.clone(),
+                },
+            ))
+            .is_err());
+
+        let status = node2
+            .consensus
+            .handle_validator_admin(ValidatorAdminCommand::RotateKey {
+                new_pubkey: node1.validator_pubkey(),
+                handover_slot: status.slot + 10,
+            })
+            .unwrap();
+        assert_eq!(status.mode, APIValidatorMode::Active);
+        assert!(status.key_rotation.is_some());
+
+        // Consensus can't go on with a single validator out of 2
+        let (mut node5, _node6): (ConsensusTestCtx, ConsensusTestCtx) = build_nodes!(2).await;
+        let err = node5
+            .consensus
+            .handle_validator_admin(ValidatorAdminCommand::Stop { force: false })
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<HyleError>().map(|e| e.code),
+            Some(ErrorCode::Conflict)
+        );
+        let status = node5
+            .consensus
+            .handle_validator_admin(ValidatorAdminCommand::Stop { force: true })
+            .unwrap();
+        assert_eq!(status.mode, APIValidatorMode::Stopped);
+    }
+
     #[test_log::test(tokio::test)]
     async fn prepare_wrong_slot() {
         let (mut node1, mut node2, mut node3, mut node4): (
@@ -1491,6 +1996,8 @@ pub mod test {
                     )],
                     staking_actions: vec![],
                     parent_hash: ConsensusProposalHash("hash".into()),
+                    production_reason: Default::default(),
+                    epoch: None,
                 },
                 Ticket::Genesis,
             ))
@@ -1532,6 +2039,8 @@ pub mod test {
                     )],
                     staking_actions: vec![],
                     parent_hash: ConsensusProposalHash("hash".into()),
+                    production_reason: Default::default(),
+                    epoch: None,
                 },
                 Ticket::Genesis,
             ))
@@ -1582,6 +2091,8 @@ pub mod test {
                     )],
                     staking_actions: vec![],
                     parent_hash: ConsensusProposalHash("hash".into()),
+                    production_reason: Default::default(),
+                    epoch: None,
                 },
                 Ticket::Genesis,
             ))
@@ -1,6 +1,16 @@
 use anyhow::anyhow;
-use axum::{debug_handler, extract::State, http::StatusCode, response::IntoResponse, Json, Router};
-use hyle_model::api::APIStaking;
+use axum::{
+    debug_handler,
+    extract::{Query as QueryParams, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json, Router,
+};
+use hyle_model::{
+    api::{APIEquivocationEvidence, APIKeyRotation, APIStaking, APIValidatorStatus},
+    errors::HyleError,
+};
+use serde::Deserialize;
 use staking::state::Staking;
 use tracing::error;
 use utoipa::OpenApi;
@@ -16,12 +26,17 @@ use crate::{
     rest::AppError,
 };
 
-use super::{QueryConsensusInfo, QueryConsensusStakingState};
+use super::{
+    validator_admin::ValidatorAdminCommand, QueryConsensusEvidence, QueryConsensusInfo,
+    QueryConsensusStakingState,
+};
 
 bus_client! {
 struct RestBusClient {
     sender(Query<QueryConsensusInfo, ConsensusInfo>),
     sender(Query<QueryConsensusStakingState, Staking>),
+    sender(Query<QueryConsensusEvidence, Vec<APIEquivocationEvidence>>),
+    sender(Query<ValidatorAdminCommand, APIValidatorStatus>),
 }
 }
 
@@ -40,6 +55,7 @@ pub async fn api(ctx: &CommonRunContext) -> Router<()> {
     let (router, api) = OpenApiRouter::with_openapi(ConsensusAPI::openapi())
         .routes(routes!(get_consensus_state))
         .routes(routes!(get_consensus_staking_state))
+        .routes(routes!(get_equivocation_evidence))
         .split_for_parts();
 
     if let Ok(mut o) = ctx.openapi.lock() {
@@ -49,6 +65,30 @@ pub async fn api(ctx: &CommonRunContext) -> Router<()> {
     router.with_state(state)
 }
 
+#[derive(OpenApi)]
+struct ValidatorAdminAPI;
+
+/// Routes managing the validator run by the node, nested under the admin prefix.
+/// Only mounted when admin credentials are configured.
+pub async fn admin_api(ctx: &CommonRunContext) -> Router<()> {
+    let state = RouterState {
+        bus: RestBusClient::new_from_bus(ctx.bus.new_handle()).await,
+    };
+
+    let (router, api) = OpenApiRouter::with_openapi(ValidatorAdminAPI::openapi())
+        .routes(routes!(get_validator_status))
+        .routes(routes!(start_validator))
+        .routes(routes!(stop_validator))
+        .routes(routes!(rotate_validator_key))
+        .split_for_parts();
+
+    if let Ok(mut o) = ctx.openapi.lock() {
+        *o = o.clone().nest("/v1/admin/consensus", api);
+    }
+
+    router.with_state(state)
+}
+
 #[utoipa::path(
     get,
     path = "/info",
@@ -102,6 +142,127 @@ pub async fn get_consensus_staking_state(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/evidence",
+    tag = "Consensus",
+    responses(
+        (status = OK, description = "Conflicting messages signed by validators, oldest first", body = [APIEquivocationEvidence])
+    )
+)]
+#[debug_handler]
+pub async fn get_equivocation_evidence(
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    match state.bus.request(QueryConsensusEvidence {}).await {
+        Ok(evidence) => Ok(Json(evidence)),
+        Err(err) => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Error while getting equivocation evidence: {err}"),
+            ))
+        }
+    }
+}
+
+async fn validator_admin(
+    state: &mut RouterState,
+    command: ValidatorAdminCommand,
+) -> Result<Json<APIValidatorStatus>, AppError> {
+    state.bus.request(command).await.map(Json).map_err(|err| {
+        let status = err
+            .downcast_ref::<HyleError>()
+            .and_then(|e| StatusCode::from_u16(e.code.http_status()).ok())
+            .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        AppError(status, err)
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/validator",
+    tag = "Consensus",
+    responses(
+        (status = OK, body = APIValidatorStatus)
+    )
+)]
+pub async fn get_validator_status(
+    State(mut state): State<RouterState>,
+) -> Result<Json<APIValidatorStatus>, AppError> {
+    validator_admin(&mut state, ValidatorAdminCommand::Status).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/validator/start",
+    tag = "Consensus",
+    responses(
+        (status = OK, body = APIValidatorStatus)
+    )
+)]
+pub async fn start_validator(
+    State(mut state): State<RouterState>,
+) -> Result<Json<APIValidatorStatus>, AppError> {
+    validator_admin(&mut state, ValidatorAdminCommand::Start).await
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct StopValidatorParams {
+    /// Stop even if the other validators can't commit blocks without this one
+    #[serde(default)]
+    force: bool,
+}
+
+#[utoipa::path(
+    post,
+    path = "/validator/stop",
+    params(
+        ("force" = Option<bool>, Query, description = "Stop even if consensus can't go on without this validator")
+    ),
+    tag = "Consensus",
+    responses(
+        (status = OK, body = APIValidatorStatus),
+        (status = CONFLICT, description = "The other validators don't hold enough stake to go on")
+    )
+)]
+pub async fn stop_validator(
+    QueryParams(params): QueryParams<StopValidatorParams>,
+    State(mut state): State<RouterState>,
+) -> Result<Json<APIValidatorStatus>, AppError> {
+    validator_admin(
+        &mut state,
+        ValidatorAdminCommand::Stop {
+            force: params.force,
+        },
+    )
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/validator/rotate_key",
+    request_body = APIKeyRotation,
+    tag = "Consensus",
+    responses(
+        (status = OK, body = APIValidatorStatus)
+    )
+)]
+pub async fn rotate_validator_key(
+    State(mut state): State<RouterState>,
+    Json(rotation): Json<APIKeyRotation>,
+) -> Result<Json<APIValidatorStatus>, AppError> {
+    validator_admin(
+        &mut state,
+        ValidatorAdminCommand::RotateKey {
+            new_pubkey: rotation.new_pubkey,
+            handover_slot: rotation.handover_slot,
+        },
+    )
+    .await
+}
+
 impl Clone for RouterState {
     fn clone(&self) -> Self {
         use crate::utils::static_type_map::Pick;
@@ -116,6 +277,14 @@ impl Clone for RouterState {
                     &self.bus,
                 )
                 .clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<QueryConsensusEvidence, Vec<APIEquivocationEvidence>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<ValidatorAdminCommand, APIValidatorStatus>>>::get(
+                    &self.bus,
+                )
+                .clone(),
             )
         }
     }
@@ -0,0 +1,288 @@
+//! Detection of validators signing conflicting consensus messages for the same slot and view,
+//! kept as evidence so that they can eventually be slashed.
+
+use std::collections::{BTreeMap, HashMap};
+
+use bincode::{Decode, Encode};
+use hyle_model::api::APIEquivocationEvidence;
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::{Consensus, ConsensusEvent};
+use crate::{
+    bus::BusClientSender,
+    model::*,
+    utils::{logger::LogMe, modules::Module},
+};
+
+/// Messages are kept for this many slots around the current one to detect equivocations.
+const EVIDENCE_SLOTS: u64 = 100;
+/// Past this many pieces of evidence, the oldest ones are dropped.
+const MAX_EVIDENCE: usize = 1000;
+
+#[derive(
+    Debug, Clone, Copy, Serialize, Deserialize, Encode, Decode, PartialEq, Eq, PartialOrd, Ord,
+)]
+pub enum MessageKind {
+    Prepare,
+    PrepareVote,
+    ConfirmAck,
+}
+
+impl MessageKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MessageKind::Prepare => "prepare",
+            MessageKind::PrepareVote => "prepare_vote",
+            MessageKind::ConfirmAck => "confirm_ack",
+        }
+    }
+}
+
+/// Two messages of the same kind signed by `validator` for different proposals of a slot and view.
+#[derive(Debug, Clone, Serialize, Deserialize, Encode, Decode, PartialEq, Eq)]
+pub struct EquivocationEvidence {
+    pub validator: ValidatorPublicKey,
+    pub slot: Slot,
+    pub view: View,
+    pub kind: MessageKind,
+    pub first: SignedByValidator<ConsensusNetMessage>,
+    pub second: SignedByValidator<ConsensusNetMessage>,
+    /// Proposals the votes are for, to place them in their slot and view
+    pub proposals: Vec<ConsensusProposal>,
+    pub detected_at_slot: Slot,
+}
+
+impl EquivocationEvidence {
+    /// The evidence as it is included in a proposal to slash the validator.
+    pub fn slash_evidence(&self) -> SlashEvidence {
+        SlashEvidence {
+            first: self.first.clone(),
+            second: self.second.clone(),
+            proposals: self.proposals.clone(),
+        }
+    }
+}
+
+impl From<&EquivocationEvidence> for APIEquivocationEvidence {
+    fn from(evidence: &EquivocationEvidence) -> Self {
+        let encode = |msg: &SignedByValidator<ConsensusNetMessage>| {
+            hex::encode(
+                bincode::encode_to_vec(msg, bincode::config::standard()).unwrap_or_default(),
+            )
+        };
+        APIEquivocationEvidence {
+            validator: evidence.validator.clone(),
+            slot: evidence.slot,
+            view: evidence.view,
+            kind: evidence.kind.as_str().to_string(),
+            first: encode(&evidence.first),
+            second: encode(&evidence.second),
+            detected_at_slot: evidence.detected_at_slot,
+        }
+    }
+}
+
+type MessageKey = (Slot, View, ValidatorPublicKey, MessageKind);
+
+#[derive(Debug, Default, Encode, Decode)]
+pub struct EvidenceStore {
+    /// Proposals seen, as votes only refer to the proposal hash
+    proposals: HashMap<ConsensusProposalHash, ConsensusProposal>,
+    /// First message of each kind signed by a validator for a slot and view
+    messages: BTreeMap<
+        MessageKey,
+        (
+            ConsensusProposalHash,
+            SignedByValidator<ConsensusNetMessage>,
+        ),
+    >,
+    evidence: Vec<EquivocationEvidence>,
+    pruned_at: Slot,
+}
+
+impl EvidenceStore {
+    pub fn evidence(&self) -> &[EquivocationEvidence] {
+        &self.evidence
+    }
+
+    /// Records a message signed by a bonded validator.
+    /// Returns the evidence if it conflicts with a message the validator signed before.
+    pub fn record(
+        &mut self,
+        msg: &SignedByValidator<ConsensusNetMessage>,
+        current_slot: Slot,
+    ) -> Option<EquivocationEvidence> {
+        self.prune(current_slot);
+        let (kind, hash) = match &msg.msg {
+            ConsensusNetMessage::Prepare(proposal, _) => {
+                let hash = proposal.hash();
+                if proposal.slot.abs_diff(current_slot) <= EVIDENCE_SLOTS {
+                    self.proposals.insert(hash.clone(), proposal.clone());
+                }
+                (MessageKind::Prepare, hash)
+            }
+            ConsensusNetMessage::PrepareVote(hash) => (MessageKind::PrepareVote, hash.clone()),
+            ConsensusNetMessage::ConfirmAck(hash) => (MessageKind::ConfirmAck, hash.clone()),
+            _ => return None,
+        };
+        // Votes for unknown proposals can't be placed
+        let proposal = self.proposals.get(&hash)?;
+        let (slot, view) = (proposal.slot, proposal.view);
+
+        let key = (slot, view, msg.signature.validator.clone(), kind);
+        let Some((first_hash, first)) = self.messages.get(&key) else {
+            self.messages.insert(key, (hash, msg.clone()));
+            return None;
+        };
+        if first_hash == &hash
+            || self
+                .evidence
+                .iter()
+                .any(|e| e.slot == slot && e.view == view && e.kind == kind && e.validator == key.2)
+        {
+            return None;
+        }
+
+        let proposals = match kind {
+            MessageKind::Prepare => vec![],
+            MessageKind::PrepareVote | MessageKind::ConfirmAck => [first_hash, &hash]
+                .into_iter()
+                .filter_map(|hash| self.proposals.get(hash).cloned())
+                .collect(),
+        };
+        let evidence = EquivocationEvidence {
+            validator: key.2.clone(),
+            slot,
+            view,
+            kind,
+            first: first.clone(),
+            second: msg.clone(),
+            proposals,
+            detected_at_slot: current_slot,
+        };
+        self.evidence.push(evidence.clone());
+        if self.evidence.len() > MAX_EVIDENCE {
+            self.evidence.remove(0);
+        }
+        Some(evidence)
+    }
+
+    /// Forgets the messages of slots that are too old to matter.
+    fn prune(&mut self, current_slot: Slot) {
+        if current_slot <= self.pruned_at {
+            return;
+        }
+        self.pruned_at = current_slot;
+        let oldest = current_slot.saturating_sub(EVIDENCE_SLOTS);
+        self.proposals.retain(|_, proposal| proposal.slot >= oldest);
+        self.messages = self.messages.split_off(&(
+            oldest,
+            0,
+            ValidatorPublicKey::default(),
+            MessageKind::Prepare,
+        ));
+    }
+}
+
+impl Consensus {
+    /// Checks messages of bonded validators against those they signed before.
+    pub(super) fn record_evidence(&mut self, msg: &SignedByValidator<ConsensusNetMessage>) {
+        if !self.is_part_of_consensus(&msg.signature.validator) {
+            return;
+        }
+        let current_slot = self.bft_round_state.consensus_proposal.slot;
+        let Some(evidence) = self.store.evidence.record(msg, current_slot) else {
+            return;
+        };
+        warn!(
+            "🚨 Validator {} signed conflicting {} messages for slot {} view {}",
+            evidence.validator,
+            evidence.kind.as_str(),
+            evidence.slot,
+            evidence.view
+        );
+        self.metrics.equivocation(evidence.kind.as_str());
+        if let Some(file) = &self.file {
+            _ = Self::save_on_disk(file.as_path(), &self.store)
+                .log_error("Saving consensus storage with new evidence");
+        }
+        _ = self
+            .bus
+            .send(ConsensusEvent::Equivocation(evidence))
+            .log_error("Sending equivocation evidence");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::crypto::BlstCrypto;
+
+    fn prepare(
+        crypto: &BlstCrypto,
+        slot: Slot,
+        timestamp: u64,
+    ) -> SignedByValidator<ConsensusNetMessage> {
+        let proposal = ConsensusProposal {
+            slot,
+            timestamp,
+            round_leader: crypto.validator_pubkey().clone(),
+            ..ConsensusProposal::default()
+        };
+        crypto
+            .sign(ConsensusNetMessage::Prepare(proposal, Ticket::Genesis))
+            .unwrap()
+    }
+
+    fn proposal_hash(msg: &SignedByValidator<ConsensusNetMessage>) -> ConsensusProposalHash {
+        match &msg.msg {
+            ConsensusNetMessage::Prepare(proposal, _) => proposal.hash(),
+            _ => panic!("not a prepare"),
+        }
+    }
+
+    #[test]
+    fn test_equivocations() {
+        let leader = BlstCrypto::new("leader".into()).unwrap();
+        let voter = BlstCrypto::new("voter".into()).unwrap();
+        let mut store = EvidenceStore::default();
+
+        let first = prepare(&leader, 5, 1);
+        let second = prepare(&leader, 5, 2);
+        assert_eq!(store.record(&first, 5), None);
+        // The same proposal again is fine
+        assert_eq!(store.record(&first, 5), None);
+        let evidence = store.record(&second, 5).unwrap();
+        assert_eq!(evidence.validator, leader.validator_pubkey().clone());
+        assert_eq!((evidence.slot, evidence.kind), (5, MessageKind::Prepare));
+        assert!(evidence.slash_evidence().offense_hash().is_ok());
+        assert_eq!(
+            (evidence.first, evidence.second),
+            (first.clone(), second.clone())
+        );
+        // The same message twice is no evidence
+        let same = SlashEvidence {
+            first: first.clone(),
+            second: first.clone(),
+            proposals: vec![],
+        };
+        assert!(same.offense_hash().is_err());
+        // Only reported once
+        assert_eq!(store.record(&prepare(&leader, 5, 3), 5), None);
+        // Another slot is another proposal
+        assert_eq!(store.record(&prepare(&leader, 6, 3), 5), None);
+
+        // Votes are placed by the proposal they refer to
+        let vote = |hash| voter.sign(ConsensusNetMessage::PrepareVote(hash)).unwrap();
+        assert_eq!(store.record(&vote(proposal_hash(&first)), 5), None);
+        let evidence = store.record(&vote(proposal_hash(&second)), 5).unwrap();
+        assert_eq!(evidence.kind, MessageKind::PrepareVote);
+        assert!(evidence.slash_evidence().offense_hash().is_ok());
+        assert_eq!(store.evidence().len(), 2);
+
+        // Old messages are forgotten, the evidence is kept
+        assert_eq!(store.record(&prepare(&leader, 6, 4), 200), None);
+        assert_eq!(store.evidence().len(), 2);
+    }
+}
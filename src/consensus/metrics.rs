@@ -1,5 +1,5 @@
 use opentelemetry::{
-    metrics::{Counter, Gauge},
+    metrics::{Counter, Gauge, Histogram},
     InstrumentationScope, KeyValue,
 };
 
@@ -21,6 +21,11 @@ pub struct ConsensusMetrics {
     confirmed_ack_gauge: Gauge<u64>,
     prepare_votes_gauge: Gauge<u64>,
     prepare_votes_aggregation: Counter<u64>,
+    equivocation: Counter<u64>,
+    slot: Gauge<u64>,
+    slot_duration: Histogram<u64>,
+    /// Timestamp of the last committed proposal, to time the slots
+    last_commit_timestamp: Option<u64>,
 }
 
 impl ConsensusMetrics {
@@ -48,6 +53,13 @@ impl ConsensusMetrics {
             confirmed_ack_gauge: my_meter.u64_gauge("confirmed_ack_gauge").build(),
             prepare_votes_gauge: my_meter.u64_gauge("prepare_votes_gauge").build(),
             prepare_votes_aggregation: my_meter.u64_counter("prepare_votes_aggregation").build(),
+            equivocation: my_meter.u64_counter("equivocation").build(),
+            slot: my_meter.u64_gauge("slot").build(),
+            slot_duration: my_meter
+                .u64_histogram("slot_duration")
+                .with_unit("ms")
+                .build(),
+            last_commit_timestamp: None,
         }
     }
 
@@ -76,6 +88,10 @@ impl ConsensusMetrics {
             .add(1, &[KeyValue::new("kind", kind)]);
     }
 
+    pub fn equivocation(&self, kind: &'static str) {
+        self.equivocation.add(1, &[KeyValue::new("kind", kind)]);
+    }
+
     pub fn prepare_votes_aggregation(&self) {
         self.prepare_votes_aggregation.add(1, &[]);
     }
@@ -105,8 +121,15 @@ impl ConsensusMetrics {
         self.prepare_votes_gauge.record(nb, &[])
     }
 
-    pub fn commit(&self) {
+    /// Records the committed slot, and the time since the previous committed proposal.
+    pub fn commit(&mut self, slot: u64, timestamp: u64) {
         self.commit.add(1, &[]);
+        self.slot.record(slot, &[]);
+        if let Some(last) = self.last_commit_timestamp {
+            self.slot_duration
+                .record(timestamp.saturating_sub(last), &[]);
+        }
+        self.last_commit_timestamp = Some(timestamp);
     }
     pub fn commit_error(&self, kind: &'static str) {
         self.commit_error.add(1, &[KeyValue::new("kind", kind)]);
@@ -1,6 +1,10 @@
 use anyhow::Result;
 
-use crate::{model::SharedRunContext, utils::modules::Module};
+use crate::{
+    model::{BlockProductionReason, SharedRunContext},
+    rest::auth::ApiAuth,
+    utils::modules::Module,
+};
 
 use super::{
     api, consensus_bus_client::ConsensusBusClient, metrics::ConsensusMetrics, Consensus,
@@ -22,9 +26,19 @@ impl Module for Consensus {
         let bus = ConsensusBusClient::new_from_bus(ctx.common.bus.new_handle()).await;
 
         let api = api::api(&ctx.common).await;
+        // Validators can only be started, stopped or get their key rotated by admins
+        let admin_api = if ApiAuth::new(&ctx.common.config.rest_auth).is_enabled() {
+            Some(api::admin_api(&ctx.common).await)
+        } else {
+            None
+        };
         if let Ok(mut guard) = ctx.common.router.lock() {
             if let Some(router) = guard.take() {
-                guard.replace(router.nest("/v1/consensus", api));
+                let router = router.nest("/v1/consensus", api);
+                guard.replace(match admin_api {
+                    Some(admin_api) => router.nest("/v1/admin/consensus", admin_api),
+                    None => router,
+                });
             }
         }
 
@@ -35,6 +49,8 @@ impl Module for Consensus {
             store,
             config: ctx.common.config.clone(),
             crypto: ctx.node.crypto.clone(),
+            slot_timer: None,
+            production_reason: BlockProductionReason::Interval,
         })
     }
 
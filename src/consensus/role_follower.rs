@@ -118,13 +118,15 @@ impl FollowerRole for Consensus {
 
         self.verify_staking_actions(&consensus_proposal)?;
 
+        self.verify_epoch(&consensus_proposal)?;
+
         self.verify_timestamp(&consensus_proposal)?;
 
         // At this point we are OK with this new consensus proposal, update locally and vote.
         self.bft_round_state.consensus_proposal = consensus_proposal.clone();
 
         // Responds PrepareVote message to leader with validator's vote on this proposal
-        if self.is_part_of_consensus(self.crypto.validator_pubkey()) {
+        if self.is_validating() {
             debug!(
                 proposal_hash = %consensus_proposal.hash(),
                 "📤 Slot {} Prepare message validated. Sending PrepareVote to leader",
@@ -136,7 +138,7 @@ impl FollowerRole for Consensus {
             )?;
         } else {
             info!(
-                "😥 Not part of consensus ({}), not sending PrepareVote",
+                "😥 Not validating ({}), not sending PrepareVote",
                 self.crypto.validator_pubkey()
             );
         }
@@ -165,7 +167,7 @@ impl FollowerRole for Consensus {
         )?;
 
         // Responds ConfirmAck to leader
-        if self.is_part_of_consensus(self.crypto.validator_pubkey()) {
+        if self.is_validating() {
             debug!(
                 proposal_hash = %consensus_proposal_hash,
                 "📤 Slot {} Confirm message validated. Sending ConfirmAck to leader",
@@ -176,7 +178,7 @@ impl FollowerRole for Consensus {
                 ConsensusNetMessage::ConfirmAck(consensus_proposal_hash.clone()),
             )?;
         } else {
-            info!("😥 Not part of consensus, not sending ConfirmAck");
+            info!("😥 Not validating, not sending ConfirmAck");
         }
         Ok(())
     }
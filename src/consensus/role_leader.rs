@@ -5,8 +5,8 @@ use crate::{
     consensus::StateTag,
     mempool::QueryNewCut,
     model::{
-        ConsensusNetMessage, ConsensusProposalHash, Hashable, SignedByValidator, Ticket,
-        ValidatorPublicKey,
+        ConsensusNetMessage, ConsensusProposalHash, ConsensusStakingAction, Hashable,
+        SignedByValidator, Ticket, ValidatorPublicKey,
     },
 };
 use anyhow::{anyhow, bail, Result};
@@ -85,6 +85,39 @@ impl LeaderRole for Consensus {
             new_validators_to_bond.len()
         );
 
+        // Validators caught equivocating are slashed once per offense
+        let mut offenses = HashSet::new();
+        let slashes: Vec<ConsensusStakingAction> = self
+            .store
+            .evidence
+            .evidence()
+            .iter()
+            .map(|e| e.slash_evidence())
+            .filter(|evidence| {
+                evidence.offense_hash().is_ok_and(|offense| {
+                    !self.bft_round_state.staking.is_slashed_for(&offense)
+                        && offenses.insert(offense)
+                })
+            })
+            .map(|evidence| ConsensusStakingAction::Slash { evidence })
+            .collect();
+        // Validators that handed their duties over leave until they are unbonded
+        let slot = self.bft_round_state.consensus_proposal.slot;
+        let leaves: Vec<ConsensusStakingAction> = self
+            .validator_leaves
+            .iter()
+            .filter(|v| self.verify_validator_leave(v, slot).is_ok())
+            .map(|v| ConsensusStakingAction::Leave {
+                validator: v.clone(),
+            })
+            .collect();
+        let staking_actions: Vec<ConsensusStakingAction> = new_validators_to_bond
+            .into_iter()
+            .map(|v| v.into())
+            .chain(slashes)
+            .chain(leaves)
+            .collect();
+
         // Creates ConsensusProposal
         // Query new cut to Mempool
         trace!(
@@ -108,21 +141,29 @@ impl LeaderRole for Consensus {
             }
         };
 
+        if self.should_skip_empty_block(&ticket, &cut, &staking_actions, current_timestamp) {
+            trace!(
+                "💤 Nothing new to commit, not proposing slot {} yet",
+                self.bft_round_state.consensus_proposal.slot
+            );
+            return self.delay_start_new_round(ticket);
+        }
+
         for tx in cut.iter() {
             debug!("📦 Lane {} transited {}", tx.0, tx.2);
         }
 
         self.bft_round_state.leader.step = Step::PrepareVote;
 
-        let staking_actions = new_validators_to_bond
-            .into_iter()
-            .map(|v| v.into())
-            .collect();
-
         // Start Consensus with following cut
         self.bft_round_state.consensus_proposal.cut = cut;
         self.bft_round_state.consensus_proposal.staking_actions = staking_actions;
+        self.bft_round_state.consensus_proposal.epoch = self.next_epoch();
         self.bft_round_state.consensus_proposal.timestamp = current_timestamp;
+        self.bft_round_state.consensus_proposal.production_reason =
+            std::mem::take(&mut self.production_reason);
+        // The slot timer, if any, either fired or was aborted
+        self.slot_timer = None;
 
         self.metrics.start_new_round("consensus_proposal");
 
@@ -3,7 +3,7 @@ use std::collections::HashSet;
 use bincode::{Decode, Encode};
 use tracing::{debug, info, trace, warn};
 
-use super::Consensus;
+use super::{validator_admin::ValidatorMode, Consensus};
 use crate::model::{
     utils::get_current_timestamp, ConsensusNetMessage, QuorumCertificate, SignedByValidator, Slot,
     Ticket, ValidatorPublicKey, View,
@@ -24,7 +24,8 @@ pub(super) enum TimeoutState {
 
 impl TimeoutState {
     pub const TIMEOUT_SECS: u64 = 5;
-    pub fn schedule_next(&mut self, timestamp: u64) {
+    /// Schedules the timeout `timeout` seconds after `timestamp`.
+    pub fn schedule_next(&mut self, timestamp: u64, timeout: u64) {
         match self {
             TimeoutState::Inactive => {
                 trace!("⏲️ Scheduling timeout");
@@ -37,7 +38,7 @@ impl TimeoutState {
             }
         }
         *self = TimeoutState::Scheduled {
-            timestamp: timestamp + TimeoutState::TIMEOUT_SECS,
+            timestamp: timestamp + timeout,
         };
     }
     pub fn cancel(&mut self) {
@@ -198,7 +199,10 @@ impl TimeoutRole for Consensus {
         info!("Got {voting_power} voting power with {len} timeout requests for the same view {}. f is {f}", self.store.bft_round_state.consensus_proposal.view);
 
         // Count requests and if f+1 requests, and not already part of it, join the mutiny
-        if voting_power > f && !timeout_validators.contains(self.crypto.validator_pubkey()) {
+        if voting_power > f
+            && !timeout_validators.contains(self.crypto.validator_pubkey())
+            && self.validator_mode == ValidatorMode::Active
+        {
             info!("Joining timeout mutiny!");
 
             let timeout_message = ConsensusNetMessage::Timeout(received_slot, received_view);
@@ -223,7 +227,7 @@ impl TimeoutRole for Consensus {
             self.bft_round_state
                 .timeout
                 .state
-                .schedule_next(get_current_timestamp());
+                .schedule_next(get_current_timestamp(), self.timeout_secs());
         }
 
         // Create TC if applicable
@@ -251,7 +255,7 @@ impl TimeoutRole for Consensus {
             self.bft_round_state
                 .timeout
                 .state
-                .schedule_next(get_current_timestamp());
+                .schedule_next(get_current_timestamp(), self.timeout_secs());
 
             if &self.next_leader()? == self.crypto.validator_pubkey() {
                 self.carry_on_with_ticket(Ticket::TimeoutQC(timeout_certificate))?;
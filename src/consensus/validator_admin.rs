@@ -0,0 +1,212 @@
+//! Operator controls on the validator run by the node: stopping and resuming its participation
+//! in consensus, and handing its duties over to another key.
+
+use anyhow::{bail, Result};
+use bincode::{Decode, Encode};
+use hyle_model::{
+    api::{APIKeyRotation, APIValidatorMode, APIValidatorStatus},
+    errors::{ErrorCode, HyleError},
+    ConsensusNetMessage, ValidatorLeave,
+};
+use staking::state::MIN_STAKE;
+use tracing::info;
+
+use super::{Consensus, StateTag};
+use crate::model::{Slot, ValidatorPublicKey};
+
+/// Operator commands on the validator run by the node, answered with its status.
+#[derive(Debug, Clone)]
+pub enum ValidatorAdminCommand {
+    Status,
+    /// Votes and proposes blocks again, sending a candidacy if not bonded yet
+    Start,
+    /// Stops voting and proposing blocks. Unless forced, refused when the other validators
+    /// wouldn't hold enough stake to commit blocks on their own.
+    Stop {
+        force: bool,
+    },
+    /// Hands the duties of the validator over to `new_pubkey`, see [KeyRotation]
+    RotateKey {
+        new_pubkey: ValidatorPublicKey,
+        handover_slot: Slot,
+    },
+}
+
+#[derive(Encode, Decode, Default, Debug, Clone, PartialEq, Eq)]
+pub enum ValidatorMode {
+    #[default]
+    Active,
+    Stopped,
+    /// Stopped for good, the duties were handed over to this key
+    HandedOver(ValidatorPublicKey),
+}
+
+/// Slots after the one it was signed for during which a leave message can be included
+pub const LEAVE_VALIDITY: Slot = 100;
+
+/// The validator stops at the first slot from `handover_slot` where `new_pubkey` is bonded,
+/// so that the new key, run by another node, takes over without a gap. It then leaves
+/// consensus, so that its stake no longer counts towards the quorums.
+#[derive(Encode, Decode, Debug, Clone, PartialEq, Eq)]
+pub struct KeyRotation {
+    pub new_pubkey: ValidatorPublicKey,
+    pub handover_slot: Slot,
+}
+
+impl Consensus {
+    /// Whether the node votes and proposes blocks: bonded, and not stopped by its operator.
+    pub(super) fn is_validating(&self) -> bool {
+        self.validator_mode == ValidatorMode::Active
+            && self.is_part_of_consensus(self.crypto.validator_pubkey())
+    }
+
+    pub(super) fn handle_validator_admin(
+        &mut self,
+        command: ValidatorAdminCommand,
+    ) -> Result<APIValidatorStatus> {
+        match command {
+            ValidatorAdminCommand::Status => {}
+            ValidatorAdminCommand::Start => {
+                if let ValidatorMode::HandedOver(new_pubkey) = &self.validator_mode {
+                    bail!(HyleError::new(
+                        ErrorCode::Conflict,
+                        format!("Validator duties were handed over to {}", new_pubkey)
+                    ));
+                }
+                info!("▶️ Validator started by operator");
+                self.validator_mode = ValidatorMode::Active;
+                let own_pubkey = self.crypto.validator_pubkey();
+                if !self.is_part_of_consensus(own_pubkey)
+                    && !matches!(self.bft_round_state.state_tag, StateTag::Joining)
+                    && self
+                        .bft_round_state
+                        .staking
+                        .get_stake(own_pubkey)
+                        .unwrap_or(0)
+                        > MIN_STAKE
+                {
+                    self.send_candidacy()?;
+                }
+            }
+            ValidatorAdminCommand::Stop { force } => {
+                if !force {
+                    self.check_can_stop()?;
+                }
+                info!("⏸️ Validator stopped by operator");
+                if self.validator_mode == ValidatorMode::Active {
+                    self.validator_mode = ValidatorMode::Stopped;
+                }
+            }
+            ValidatorAdminCommand::RotateKey {
+                new_pubkey,
+                handover_slot,
+            } => {
+                if &new_pubkey == self.crypto.validator_pubkey() {
+                    bail!(HyleError::new(
+                        ErrorCode::BadRequest,
+                        "The new key is the current one"
+                    ));
+                }
+                if handover_slot < self.bft_round_state.consensus_proposal.slot {
+                    bail!(HyleError::new(
+                        ErrorCode::BadRequest,
+                        format!(
+                            "Handover slot {} is in the past, current slot is {}",
+                            handover_slot, self.bft_round_state.consensus_proposal.slot
+                        )
+                    ));
+                }
+                info!(
+                    "🔑 Handing validator duties over to {} from slot {}",
+                    new_pubkey, handover_slot
+                );
+                self.key_rotation = Some(KeyRotation {
+                    new_pubkey,
+                    handover_slot,
+                });
+                self.try_hand_over()?;
+            }
+        }
+        Ok(self.validator_status())
+    }
+
+    /// Stopping a validator that holds more than f of the bonded stake would halt consensus.
+    fn check_can_stop(&self) -> Result<()> {
+        let staking = &self.bft_round_state.staking;
+        let own_power = self.get_own_voting_power();
+        let others_power = staking.total_bond().saturating_sub(own_power);
+        if own_power > 0 && others_power < 2 * staking.compute_f() + 1 {
+            bail!(HyleError::new(
+                ErrorCode::Conflict,
+                format!(
+                    "Other validators hold {} of the {} stake needed to commit blocks",
+                    others_power,
+                    2 * staking.compute_f() + 1
+                )
+            ));
+        }
+        Ok(())
+    }
+
+    /// Stops the validator once the handover slot is reached and the new key is bonded, as long
+    /// as the other validators can commit blocks without it. It then asks to leave consensus
+    /// at each slot, until it is unbonded.
+    pub(super) fn try_hand_over(&mut self) -> Result<()> {
+        if let ValidatorMode::HandedOver(_) = self.validator_mode {
+            if self.is_part_of_consensus(self.crypto.validator_pubkey()) {
+                self.send_leave()?;
+            }
+            return Ok(());
+        }
+        let Some(rotation) = &self.key_rotation else {
+            return Ok(());
+        };
+        if self.bft_round_state.consensus_proposal.slot < rotation.handover_slot
+            || !self.is_part_of_consensus(&rotation.new_pubkey)
+        {
+            return Ok(());
+        }
+        if let Err(e) = self.check_can_stop() {
+            info!("🔑 Not handing validator duties over yet: {:#}", e);
+            return Ok(());
+        }
+        info!(
+            "🔑 Validator duties handed over to {} at slot {}",
+            rotation.new_pubkey, self.bft_round_state.consensus_proposal.slot
+        );
+        self.validator_mode = ValidatorMode::HandedOver(rotation.new_pubkey.clone());
+        self.key_rotation = None;
+        self.send_leave()
+    }
+
+    /// Asks the other validators to unbond this one.
+    fn send_leave(&mut self) -> Result<()> {
+        let leave = ValidatorLeave {
+            pubkey: self.crypto.validator_pubkey().clone(),
+            slot: self.bft_round_state.consensus_proposal.slot,
+        };
+        info!("👋 Sending leave message: {}", leave);
+        self.broadcast_net_message(ConsensusNetMessage::ValidatorLeave(leave))
+    }
+
+    pub(super) fn validator_status(&self) -> APIValidatorStatus {
+        let pubkey = self.crypto.validator_pubkey();
+        let staking = &self.bft_round_state.staking;
+        APIValidatorStatus {
+            pubkey: pubkey.clone(),
+            mode: match self.validator_mode {
+                ValidatorMode::Active => APIValidatorMode::Active,
+                ValidatorMode::Stopped => APIValidatorMode::Stopped,
+                ValidatorMode::HandedOver(_) => APIValidatorMode::HandedOver,
+            },
+            bonded: staking.is_bonded(pubkey),
+            stake: staking.get_stake(pubkey),
+            slot: self.bft_round_state.consensus_proposal.slot,
+            key_rotation: self.key_rotation.as_ref().map(|rotation| APIKeyRotation {
+                new_pubkey: rotation.new_pubkey.clone(),
+                handover_slot: rotation.handover_slot,
+            }),
+            validators: staking.bonded().clone(),
+        }
+    }
+}
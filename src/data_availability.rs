@@ -1,19 +1,40 @@
 //! Minimal block storage layer for data availability.
 
+pub mod api;
 pub mod codec;
+mod metrics;
 
+mod block_cache;
+mod block_store;
 mod blocks_fjall;
 mod blocks_memory;
+#[cfg(feature = "rocksdb")]
+mod blocks_rocksdb;
+mod light_sync;
+pub mod maintenance;
+mod snapshot;
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+
+pub use api::{
+    DaPeerInfo, QueryDaBlockRange, QueryDaBlocks, QueryDaDiskUsage, QueryDaLastHeight,
+    QueryDaPeers, QueryDaSnapshotImport, QueryDaTxProof,
+};
+use block_cache::BlockCache;
+use block_store::open_block_store;
+pub use block_store::BlockStore;
 
-// Pick one of the two implementations
-use blocks_fjall::Blocks;
-//use blocks_memory::Blocks;
-
-use codec::{DataAvailabilityServerCodec, DataAvailabilityServerRequest};
+use codec::{
+    BlockEnvelope, DataAvailabilityEvent, DataAvailabilityServerCodec,
+    DataAvailabilityServerRequest,
+};
+use light_sync::{CertificateChain, LightSyncAnchor};
+use metrics::DaMetrics;
+use snapshot::{snapshot_path, SnapshotReader};
 use utils::get_current_timestamp;
 
 use crate::{
-    bus::{BusClientSender, BusMessage},
+    bus::{command_response::Query, BusClientSender, BusMessage},
     consensus::{ConsensusCommand, ConsensusEvent},
     genesis::GenesisEvent,
     indexer::da_listener::RawDAListener,
@@ -22,9 +43,11 @@ use crate::{
     module_handle_messages,
     p2p::network::{OutboundMessage, PeerEvent},
     utils::{
-        conf::SharedConf,
+        conf::{Conf, DaDiskUsageConf, SharedConf, SlowPeerPolicy},
+        crypto::{constant_time_eq, BlstCrypto, SharedBlstCrypto},
         logger::LogMe,
         modules::{module_bus_client, Module},
+        noise::NodeStream,
     },
 };
 use anyhow::{bail, Context, Error, Result};
@@ -34,23 +57,84 @@ use futures::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
+use hyle_model::api::APITxInclusionProof;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use tokio::{
-    net::{TcpListener, TcpStream},
+    net::TcpListener,
     task::{JoinHandle, JoinSet},
 };
 use tokio_util::codec::Framed;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, error, info, instrument, trace, warn};
+
+/// What a peer asked for once connected to the DA server.
+enum PeerRequest {
+    /// Stream the blocks, or only their headers, from this height
+    Stream {
+        start_height: BlockHeight,
+        headers_only: bool,
+        version: u8,
+    },
+    /// Answer with a single transaction, then close the connection
+    Transaction(TxHash),
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, Eq, PartialEq)]
 pub enum DataEvent {
     OrderedSignedBlock(SignedBlock),
+    /// Height reached while catching up. The target is unknown until Mempool starts building blocks.
+    CatchupProgress {
+        current: BlockHeight,
+        target: Option<BlockHeight>,
+    },
+    /// Catchup is over, new blocks come from consensus.
+    CatchupDone,
+    /// The block store grew past one of the `da_disk_usage` thresholds.
+    DiskUsageAlert {
+        used_bytes: u64,
+        level: DiskUsageLevel,
+    },
 }
 
 impl BusMessage for DataEvent {}
 
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Serialize,
+    Deserialize,
+    Encode,
+    Decode,
+    Eq,
+    PartialEq,
+    Ord,
+    PartialOrd,
+)]
+pub enum DiskUsageLevel {
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl DiskUsageLevel {
+    pub fn of(used_bytes: u64, conf: &DaDiskUsageConf) -> Self {
+        let above = |threshold: u64| threshold > 0 && used_bytes >= threshold;
+        if above(conf.critical_bytes) {
+            DiskUsageLevel::Critical
+        } else if above(conf.warning_bytes) {
+            DiskUsageLevel::Warning
+        } else {
+            DiskUsageLevel::Normal
+        }
+    }
+}
+
 module_bus_client! {
 #[derive(Debug)]
 struct DABusClient {
@@ -61,34 +145,177 @@ struct DABusClient {
     receiver(MempoolEvent),
     receiver(GenesisEvent),
     receiver(PeerEvent),
+    receiver(Query<QueryDaSnapshotImport, u64>),
+    receiver(Query<QueryDaBlocks, Vec<SignedBlock>>),
+    receiver(Query<QueryDaBlockRange, Vec<SignedBlock>>),
+    receiver(Query<QueryDaPeers, Vec<DaPeerInfo>>),
+    receiver(Query<QueryDaTxProof, APITxInclusionProof>),
+    receiver(Query<QueryDaLastHeight, Option<BlockHeight>>),
+    receiver(Query<QueryDaDiskUsage, Option<u64>>),
+}
 }
+
+/// Blocks streamed from a peer to fill a gap before the buffered blocks, apart from catchup
+#[derive(Debug)]
+struct GapFill {
+    until: BlockHeight,
+    task: tokio::task::JoinHandle<()>,
 }
 
 /// A peer we are streaming blocks to
 #[derive(Debug)]
 struct BlockStreamPeer {
     /// Last timestamp we received a ping from the peer.
-    last_ping: u64,
-    /// Sender to stream blocks to the peer
-    sender: SplitSink<Framed<TcpStream, DataAvailabilityServerCodec>, SignedBlock>,
+    last_seen: u64,
+    /// Bounded queue of blocks (and pongs) to stream to the peer
+    sender: tokio::sync::mpsc::Sender<DataAvailabilityEvent>,
+    /// Handle to abort the task writing queued blocks to the peer
+    send_abort: JoinHandle<()>,
     /// Handle to abort the receiving side of the stream
     keepalive_abort: JoinHandle<()>,
+    /// The peer only asked for the headers of past blocks
+    headers_only: bool,
+    /// Blocks queued for the peer since the stream started
+    sent_blocks: u64,
+    /// Blocks the peer acknowledged, `None` if its protocol version has no acknowledgements
+    acked_blocks: Option<u64>,
+    /// Blocks the catchup can send ahead of the acknowledgements
+    window: u64,
+    /// Blocks left to catch up, waiting for room in the window
+    paused_catchup: Option<Vec<ConsensusProposalHash>>,
+}
+
+impl BlockStreamPeer {
+    fn abort(&self) {
+        self.send_abort.abort();
+        self.keepalive_abort.abort();
+    }
+
+    fn window_full(&self) -> bool {
+        self.acked_blocks
+            .is_some_and(|acked| self.sent_blocks.saturating_sub(acked) >= self.window)
+    }
+
+    /// Slow start: the window doubles with each acknowledgement, up to `max_window`.
+    fn ack(&mut self, received: u64, max_window: u64) {
+        self.acked_blocks = Some(self.acked_blocks.unwrap_or(0).max(received));
+        self.window = (self.window * 2).min(max_window.max(self.window));
+    }
+}
+
+/// Sent by the keepalive task of a peer to the main loop.
+#[derive(Debug)]
+enum PeerKeepalive {
+    /// The peer pinged us
+    Seen(String),
+    /// The peer acknowledged receiving this many blocks
+    Ack(String, u64),
+    /// The peer timed out or broke the protocol, with the reason
+    Disconnect(String, &'static str),
+}
+
+/// Writes queued blocks to a peer, at most `max_bytes_per_sec` (0 for unlimited).
+/// Blocks are sent in their envelope to peers speaking protocol version 4 or later.
+async fn send_blocks_to_peer(
+    mut sink: SplitSink<Framed<NodeStream, DataAvailabilityServerCodec>, DataAvailabilityEvent>,
+    mut queue: tokio::sync::mpsc::Receiver<DataAvailabilityEvent>,
+    max_bytes_per_sec: u64,
+    version: u8,
+) {
+    let mut next_send = tokio::time::Instant::now();
+    while let Some(event) = queue.recv().await {
+        tokio::time::sleep_until(next_send).await;
+
+        let event = match event {
+            DataAvailabilityEvent::SignedBlock(block) if version >= 4 => {
+                match BlockEnvelope::wrap(&block) {
+                    Ok(envelope) => DataAvailabilityEvent::Block(envelope),
+                    Err(e) => {
+                        error!(
+                            "Couldn't wrap block {}, stopping streaming: {:?}",
+                            block.hash(),
+                            e
+                        );
+                        return;
+                    }
+                }
+            }
+            event => event,
+        };
+
+        let mut size = bincode::enc::write::SizeWriter::default();
+        if max_bytes_per_sec > 0
+            && bincode::encode_into_writer(&event, &mut size, bincode::config::standard()).is_ok()
+        {
+            next_send = next_send.max(tokio::time::Instant::now())
+                + std::time::Duration::from_secs_f64(
+                    size.bytes_written as f64 / max_bytes_per_sec as f64,
+                );
+        }
+
+        if let Err(e) = sink.send(event).await {
+            debug!("Couldn't send block to peer, stopping streaming: {:?}", e);
+            return;
+        }
+    }
+    _ = sink.close().await;
+}
+
+/// Progress of an ongoing catchup, persisted so that it can resume after a restart.
+#[derive(Debug, Default, Clone, Encode, Decode, PartialEq, Eq)]
+struct CatchupCheckpoint {
+    /// DA address of the peer we are catching up from
+    peer: Option<String>,
+    /// Height after which catchup is done, as communicated by Mempool
+    target_height: Option<BlockHeight>,
+    /// Highest height among blocks buffered while catching up
+    buffered_watermark: Option<BlockHeight>,
+    /// Set when the history was skipped by a light sync
+    light_sync_anchor: Option<LightSyncAnchor>,
 }
 
 #[derive(Debug)]
 pub struct DataAvailability {
     config: SharedConf,
     bus: DABusClient,
-    pub blocks: Blocks,
+    // Proves the node's identity to stream clients over encrypted connections
+    crypto: SharedBlstCrypto,
+    pub blocks: Box<dyn BlockStore>,
 
     buffered_signed_blocks: BTreeSet<SignedBlock>,
+    // Validators certifying the next block, tracked along the stored chain
+    certificates: CertificateChain,
+    // DA addresses of known peers, used to fill gaps in the chain
+    da_peers: Vec<String>,
+    last_gap_fill_request: Option<std::time::Instant>,
+    gap_fill: Option<GapFill>,
 
     // Peers subscribed to block streaming
     stream_peer_metadata: HashMap<String, BlockStreamPeer>,
+    // New blocks, for websocket subscribers
+    ws_blocks: tokio::sync::broadcast::Sender<SignedBlock>,
+    metrics: DaMetrics,
 
     need_catchup: bool,
     catchup_task: Option<tokio::task::JoinHandle<()>>,
     catchup_height: Option<BlockHeight>,
+    catchup_checkpoint: CatchupCheckpoint,
+
+    // Bound when the module gets ready, so that stream clients can connect as soon as it is started
+    stream_request_receiver: Option<TcpListener>,
+
+    // Last measured size of the block store, and the alert level it reached
+    disk_usage: Option<u64>,
+    disk_usage_level: DiskUsageLevel,
+}
+
+/// Opens the block store of the node, as configured.
+pub fn open_blocks(config: &Conf) -> Result<Box<dyn BlockStore>> {
+    open_block_store(
+        config.da_storage,
+        &config.data_directory.join("data_availability.db"),
+        BlockCache::new(config.da_block_cache_size, config.id.clone()),
+    )
 }
 
 impl Module for DataAvailability {
@@ -97,31 +324,100 @@ impl Module for DataAvailability {
     async fn build(ctx: Self::Context) -> Result<Self> {
         let bus = DABusClient::new_from_bus(ctx.common.bus.new_handle()).await;
 
-        Ok(DataAvailability {
-            config: ctx.common.config.clone(),
-            bus,
-            blocks: Blocks::new(
+        let ws_blocks = tokio::sync::broadcast::channel(100).0;
+        let api = api::api(&ctx.common, ws_blocks.clone()).await;
+        if let Ok(mut guard) = ctx.common.router.lock() {
+            if let Some(router) = guard.take() {
+                guard.replace(router.nest("/v1/", api));
+            }
+        }
+
+        let catchup_checkpoint: CatchupCheckpoint = Self::load_from_disk_or_default(
+            &ctx.common
+                .config
+                .data_directory
+                .join("da_catchup_checkpoint.bin"),
+        );
+        let mut blocks = open_blocks(&ctx.common.config)?;
+        let certificates = Self::restore_certificates(
+            Self::load_from_disk_or_default(
                 &ctx.common
                     .config
                     .data_directory
-                    .join("data_availability.db"),
-            )?,
+                    .join("da_certificate_chain.bin"),
+            ),
+            blocks.as_mut(),
+        );
+
+        Ok(DataAvailability {
+            config: ctx.common.config.clone(),
+            bus,
+            crypto: ctx.node.crypto.clone(),
+            blocks,
             buffered_signed_blocks: BTreeSet::new(),
+            certificates,
+            da_peers: Vec::new(),
+            last_gap_fill_request: None,
+            gap_fill: None,
             stream_peer_metadata: HashMap::new(),
-            need_catchup: false,
+            ws_blocks,
+            metrics: DaMetrics::global(ctx.common.config.id.clone()),
+            // Resume an interrupted catchup if there was one
+            need_catchup: catchup_checkpoint.peer.is_some(),
             catchup_task: None,
-            catchup_height: None,
+            catchup_height: catchup_checkpoint.target_height,
+            catchup_checkpoint,
+            stream_request_receiver: None,
+            disk_usage: None,
+            disk_usage_level: DiskUsageLevel::Normal,
         })
     }
 
+    async fn ready(&mut self) -> Result<()> {
+        self.stream_request_receiver = Some(TcpListener::bind(&self.config.da_address).await?);
+        Ok(())
+    }
+
     fn run(&mut self) -> impl futures::Future<Output = Result<()>> + Send {
         self.start()
     }
+
+    async fn on_shutdown(&mut self) -> Result<()> {
+        if let Some(handle) = self.catchup_task.take() {
+            handle.abort();
+        }
+        if let Some(gap_fill) = self.gap_fill.take() {
+            gap_fill.task.abort();
+        }
+        // Closing the queues lets the send tasks stream what is left, then close the sockets.
+        for (peer_ip, peer) in self.stream_peer_metadata.drain() {
+            let BlockStreamPeer {
+                sender,
+                mut send_abort,
+                keepalive_abort,
+                ..
+            } = peer;
+            keepalive_abort.abort();
+            drop(sender);
+            if tokio::time::timeout(std::time::Duration::from_secs(1), &mut send_abort)
+                .await
+                .is_err()
+            {
+                debug!("Timed out closing the stream to peer {}", peer_ip);
+                send_abort.abort();
+            }
+        }
+        self.save_certificates();
+        self.blocks.persist().context("Persisting blocks")
+    }
 }
 
 impl DataAvailability {
     pub async fn start(&mut self) -> Result<()> {
-        let stream_request_receiver = TcpListener::bind(&self.config.da_address).await?;
+        let stream_request_receiver = match self.stream_request_receiver.take() {
+            Some(listener) => listener,
+            None => TcpListener::bind(&self.config.da_address).await?,
+        };
         info!(
             "📡  Starting DataAvailability module, listening for stream requests on {}",
             &self.config.da_address
@@ -131,15 +427,37 @@ impl DataAvailability {
 
         let (catchup_block_sender, mut catchup_block_receiver) =
             tokio::sync::mpsc::channel::<SignedBlock>(100);
+        let (snapshot_block_sender, mut snapshot_block_receiver) =
+            tokio::sync::mpsc::channel::<SignedBlock>(100);
+        let (gap_fill_block_sender, mut gap_fill_block_receiver) =
+            tokio::sync::mpsc::channel::<SignedBlock>(100);
 
         // TODO: this is a soft cap on the number of peers we can stream to.
-        let (ping_sender, mut ping_receiver) = tokio::sync::mpsc::channel(100);
+        let (keepalive_sender, mut keepalive_receiver) = tokio::sync::mpsc::channel(100);
         let (catchup_sender, mut catchup_receiver) = tokio::sync::mpsc::channel(100);
+        let mut disk_usage_interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.da_disk_usage.check_interval.max(1),
+        ));
+
+        if let Some(peer) = self.catchup_checkpoint.peer.clone() {
+            info!(
+                "📡 Resuming catchup from {} (target height {:?}, buffered up to {:?})",
+                peer,
+                self.catchup_checkpoint.target_height,
+                self.catchup_checkpoint.buffered_watermark
+            );
+            // If the peer is gone, we'll wait for a new one like a regular catchup.
+            _ = self
+                .ask_for_catchup_blocks(peer, catchup_block_sender.clone())
+                .await
+                .log_warn("Resuming catchup");
+        }
 
         module_handle_messages! {
             on_bus self.bus,
             listen<MempoolEvent> evt => {
                 _ = self.handle_mempool_event(evt).await.log_error("Handling Mempool Event");
+                self.request_gap_fill(gap_fill_block_sender.clone()).await;
             }
 
             listen<GenesisEvent> cmd => {
@@ -152,7 +470,55 @@ impl DataAvailability {
                     // This also triggers when restarting from serialized state, which seems fine.
                 }
             }
+            listen<Query<QueryDaSnapshotImport, u64>> query => {
+                _ = self.import_snapshot(query, snapshot_block_sender.clone())
+                    .log_error("Importing snapshot");
+            }
+            Some(block) = snapshot_block_receiver.recv() => {
+                self.handle_signed_block(block).await;
+            }
+            Some(block) = gap_fill_block_receiver.recv() => {
+                let height = block.height();
+                self.handle_signed_block(block).await;
+                if self.gap_fill.as_ref().is_some_and(|gap_fill| gap_fill.until.0 <= height.0) {
+                    if let Some(gap_fill) = self.gap_fill.take() {
+                        gap_fill.task.abort();
+                        info!("🕳️ Gap filled up to block {}", height);
+                    }
+                }
+            }
+            command_response<QueryDaBlocks, Vec<SignedBlock>> query => {
+                let to = query.0 + api::MAX_BLOCKS_PER_QUERY;
+                self.blocks.range(query.0, to).collect()
+            }
+            command_response<QueryDaBlockRange, Vec<SignedBlock>> query => {
+                let to = BlockHeight(query.to.0.min(query.from.0.saturating_add(api::MAX_BLOCKS_PER_QUERY)));
+                self.blocks.range(query.from, to).collect()
+            }
+            command_response<QueryDaPeers, Vec<DaPeerInfo>> _ => {
+                Ok(self.peers_info())
+            }
+            command_response<QueryDaTxProof, APITxInclusionProof> query => {
+                self.tx_inclusion_proof(&query.0)
+            }
+            command_response<QueryDaLastHeight, Option<BlockHeight>> _ => {
+                Ok(self.blocks.last().map(|block| block.height()))
+            }
+            command_response<QueryDaDiskUsage, Option<u64>> _ => {
+                Ok(self.disk_usage)
+            }
+            _ = disk_usage_interval.tick() => {
+                self.check_disk_usage();
+            }
             listen<PeerEvent> msg => {
+                let PeerEvent::NewPeer { da_address, .. } = &msg;
+                // Outbound-only peers don't serve their blocks
+                if da_address.is_empty() {
+                    continue;
+                }
+                if !self.da_peers.contains(da_address) {
+                    self.da_peers.push(da_address.clone());
+                }
                 if !self.need_catchup || self.catchup_task.is_some() {
                     continue;
                 }
@@ -173,30 +539,85 @@ impl DataAvailability {
                         if let Some(t) = self.catchup_task.take() {
                             t.abort();
                             info!("Stopped streaming since received height {} and until {}", height, until_height.0);
-                            self.need_catchup = false;
+                            self.end_catchup();
                         } else {
                             info!("Did not stop streaming (received height {} and until {}) since no catchup task was running", height, until_height.0);
                         }
+                        continue;
                     }
                 }
+
+                if self.need_catchup && height % 100 == 0 {
+                    self.save_catchup_checkpoint();
+                    _ = self
+                        .bus
+                        .send(DataEvent::CatchupProgress {
+                            current: BlockHeight(height),
+                            target: self.catchup_height,
+                        })
+                        .log_error("Sending catchup progress");
+                }
             }
 
             // Handle new TCP connections to stream data to peers
             // We spawn an async task that waits for the start height as the first message.
             Ok((stream, addr)) = stream_request_receiver.accept() => {
+                let crypto = self.crypto.clone();
+                let encrypted = self.config.da_stream.encrypted;
                 // This handler is defined inline so I don't have to give a type to pending_stream_requests
                 pending_stream_requests.spawn(async move {
-                    let (sender, mut receiver) = Framed::new(stream, DataAvailabilityServerCodec::default()).split();
-                    // Read the start height from the peer.
-                    match receiver.next().await {
-                        Some(Ok(data)) => {
-                            if let DataAvailabilityServerRequest::BlockHeight(start_height) = data {
-                                Ok((start_height, sender, receiver, addr.to_string()))
-                            } else {
-                                Err(anyhow::anyhow!("Got a ping instead of a block height"))
+                    let (stream, _) = NodeStream::accept(stream, encrypted, Some(crypto.as_ref()))
+                        .await
+                        .context("Securing DA stream")?;
+                    let (mut sender, mut receiver) = Framed::new(stream, DataAvailabilityServerCodec::default()).split();
+                    // Agree on a protocol version first. Clients predating the handshake send
+                    // their start height right away, and are served the oldest version.
+                    let mut first = receiver.next().await;
+                    let version = match first {
+                        Some(Ok(DataAvailabilityServerRequest::Hello { version })) => {
+                            first = None;
+                            let Some(version) = codec::negotiate_version(version) else {
+                                _ = sender.send(DataAvailabilityEvent::UnsupportedVersion {
+                                    min: codec::MIN_DA_PROTOCOL_VERSION,
+                                    max: codec::DA_PROTOCOL_VERSION,
+                                }).await;
+                                return Err(anyhow::anyhow!("Unsupported protocol version {}", version));
+                            };
+                            sender.send(DataAvailabilityEvent::Welcome { version }).await?;
+                            version
+                        }
+                        Some(Ok(DataAvailabilityServerRequest::BlockHeight(_) | DataAvailabilityServerRequest::Auth(_))) => {
+                            codec::MIN_DA_PROTOCOL_VERSION
+                        }
+                        _ => return Err(anyhow::anyhow!("Missing protocol handshake")),
+                    };
+                    // Read the start height or transaction from the peer, optionally preceded by an auth token.
+                    let mut auth_token = None;
+                    loop {
+                        let request = match first.take() {
+                            Some(request) => Some(request),
+                            None => receiver.next().await,
+                        };
+                        match request {
+                            Some(Ok(DataAvailabilityServerRequest::Auth(token))) if auth_token.is_none() => {
+                                auth_token = Some(token);
+                            }
+                            Some(Ok(DataAvailabilityServerRequest::BlockHeight(start_height))) => {
+                                let request = PeerRequest::Stream { start_height, headers_only: false, version };
+                                break Ok((request, auth_token, sender, receiver, addr));
+                            }
+                            Some(Ok(DataAvailabilityServerRequest::Headers(start_height))) if version >= 2 => {
+                                let request = PeerRequest::Stream { start_height, headers_only: true, version };
+                                break Ok((request, auth_token, sender, receiver, addr));
                             }
+                            Some(Ok(DataAvailabilityServerRequest::GetTransaction(tx_hash))) if version >= 3 => {
+                                break Ok((PeerRequest::Transaction(tx_hash), auth_token, sender, receiver, addr));
+                            }
+                            Some(Ok(data)) => {
+                                break Err(anyhow::anyhow!("Got {:?} instead of a block height", data));
+                            }
+                            _ => break Err(anyhow::anyhow!("no start height")),
                         }
-                        _ => Err(anyhow::anyhow!("no start height")),
                     }
                 });
             }
@@ -204,11 +625,18 @@ impl DataAvailability {
             // Actually connect to a peer and start streaming data.
             Some(Ok(cmd)) = pending_stream_requests.join_next() => {
                 match cmd {
-                    Ok((start_height, sender, receiver, peer_ip)) => {
-                        if let Err(e) = self.start_streaming_to_peer(start_height, ping_sender.clone(), catchup_sender.clone(), sender, receiver, &peer_ip).await {
+                    Ok((PeerRequest::Transaction(tx_hash), auth_token, sender, _, addr)) => {
+                        if let Err(e) = self.answer_transaction_request(&tx_hash, auth_token, sender, addr) {
+                            error!("Error while answering transaction request of peer {}: {:?}", addr, e)
+                        }
+                    }
+                    Ok((PeerRequest::Stream { start_height, headers_only, version }, auth_token, sender, receiver, addr)) => {
+                        let peer_ip = addr.to_string();
+                        if let Err(e) = self.start_streaming_to_peer(start_height, headers_only, version, auth_token, keepalive_sender.clone(), catchup_sender.clone(), sender, receiver, addr).await {
                             error!("Error while starting stream to peer {}: {:?}", &peer_ip, e)
+                        } else {
+                            info!("📡 Started streaming to peer {}", &peer_ip);
                         }
-                        info!("📡 Started streaming to peer {}", &peer_ip);
                     }
                     Err(e) => {
                         error!("Error while handling stream request: {:?}", e);
@@ -225,22 +653,80 @@ impl DataAvailability {
                 if let Some(hash) = hash {
                     if let Ok(Some(signed_block)) = self.blocks.get(&hash)
                     {
+                        let Some(peer) = self.stream_peer_metadata.get_mut(&peer_ip) else {
+                            continue;
+                        };
+                        if peer.window_full() {
+                            // Resumed when the peer acknowledges the blocks in flight
+                            block_hashes.push(hash);
+                            peer.paused_catchup = Some(block_hashes);
+                            continue;
+                        }
+                        let signed_block = if peer.headers_only {
+                            SignedBlock { data_proposals: vec![], ..signed_block }
+                        } else {
+                            signed_block
+                        };
                         // Errors will be handled when sending new blocks, ignore here.
-                        if self.stream_peer_metadata
-                            .get_mut(&peer_ip)
-                            .context("peer not found")?
-                            .sender
-                            .send(signed_block)
-                            .await.is_ok() {
-                            let _ = catchup_sender.send((block_hashes, peer_ip)).await;
+                        match peer.sender.try_send(DataAvailabilityEvent::SignedBlock(signed_block)) {
+                            Ok(()) => {
+                                peer.sent_blocks += 1;
+                                let _ = catchup_sender.send((block_hashes, peer_ip)).await;
+                            }
+                            Err(tokio::sync::mpsc::error::TrySendError::Full(_)) => {
+                                // The peer is lagging behind, retry this block a bit later
+                                block_hashes.push(hash);
+                                let catchup_sender = catchup_sender.clone();
+                                tokio::spawn(async move {
+                                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                                    let _ = catchup_sender.send((block_hashes, peer_ip)).await;
+                                });
+                            }
+                            Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {}
                         }
                     }
+                } else if self.stream_peer_metadata.get(&peer_ip).is_some_and(|peer| peer.headers_only) {
+                    // New blocks aren't streamed with headers: the connection is closed once
+                    // the queued headers are written.
+                    if let Some(peer) = self.stream_peer_metadata.remove(&peer_ip) {
+                        peer.keepalive_abort.abort();
+                        let sender = peer.sender;
+                        tokio::spawn(async move {
+                            _ = sender.send(DataAvailabilityEvent::HeadersEnd).await;
+                        });
+                        self.metrics
+                            .snapshot_streaming_peers(self.stream_peer_metadata.len());
+                    }
                 }
             }
 
-            Some(peer_id) = ping_receiver.recv() => {
-                if let Some(peer) = self.stream_peer_metadata.get_mut(&peer_id) {
-                    peer.last_ping = get_current_timestamp();
+            Some(keepalive) = keepalive_receiver.recv() => {
+                match keepalive {
+                    PeerKeepalive::Seen(peer_id) => {
+                        if let Some(peer) = self.stream_peer_metadata.get_mut(&peer_id) {
+                            peer.last_seen = get_current_timestamp();
+                        }
+                    }
+                    PeerKeepalive::Ack(peer_id, received) => {
+                        if let Some(peer) = self.stream_peer_metadata.get_mut(&peer_id) {
+                            peer.last_seen = get_current_timestamp();
+                            peer.ack(received, self.config.da_stream.catchup_window);
+                            if !peer.window_full() {
+                                if let Some(block_hashes) = peer.paused_catchup.take() {
+                                    let _ = catchup_sender.send((block_hashes, peer_id)).await;
+                                }
+                            }
+                        }
+                    }
+                    PeerKeepalive::Disconnect(peer_id, reason) => {
+                        if let Some(peer) = self.stream_peer_metadata.remove(&peer_id) {
+                            info!("Closing stream to peer {}: {}", &peer_id, reason);
+                            peer.abort();
+                            self.metrics.peer_disconnected(reason);
+                            self.metrics
+                                .snapshot_streaming_peers(self.stream_peer_metadata.len());
+                        }
+                    }
                 }
             }
         };
@@ -255,6 +741,10 @@ impl DataAvailability {
             }
             MempoolEvent::StartedBuildingBlocks(height) => {
                 self.catchup_height = Some(height - 1);
+                if self.need_catchup {
+                    self.catchup_checkpoint.target_height = self.catchup_height;
+                    self.save_catchup_checkpoint();
+                }
                 if let Some(handle) = self.catchup_task.as_ref() {
                     if self
                         .blocks
@@ -266,7 +756,7 @@ impl DataAvailability {
                     {
                         info!("🏁 Stopped streaming blocks until height {}.", height);
                         handle.abort();
-                        self.need_catchup = false;
+                        self.end_catchup();
                     }
                 }
             }
@@ -282,6 +772,16 @@ impl DataAvailability {
             warn!("Block {} {} already exists !", block.height(), block.hash());
             return;
         }
+        if let Err(e) = self.verify_certificate_signature(&block) {
+            warn!(
+                "Rejecting block {} {}: {:#}",
+                block.height(),
+                block.hash(),
+                e
+            );
+            self.metrics.rejected_block();
+            return;
+        }
         // if new block is not the next block in the chain, buffer
         if !self.blocks.is_empty() {
             if !self.blocks.contains(block.parent_hash()) {
@@ -292,17 +792,19 @@ impl DataAvailability {
                     block.height()
                 );
                 debug!("Buffering block {}", block.hash());
-                self.buffered_signed_blocks.insert(block);
+                self.update_buffered_watermark(block.height());
+                self.buffer_block(block);
                 return;
             }
         // if genesis block is missing, buffer
-        } else if block.height() != BlockHeight(0) {
+        } else if block.height() != BlockHeight(0) && !self.follows_light_sync_anchor(&block) {
             trace!(
                 "Received block with height {} but genesis block is missing",
                 block.height()
             );
             trace!("Buffering block {}", block.hash());
-            self.buffered_signed_blocks.insert(block);
+            self.update_buffered_watermark(block.height());
+            self.buffer_block(block);
             return;
         }
 
@@ -312,6 +814,225 @@ impl DataAvailability {
         _ = self.blocks.persist().log_error("Persisting blocks");
     }
 
+    /// Reads the snapshot on a blocking task, which hands its blocks to the module as if they
+    /// were streamed by a peer, so they are verified and forwarded to the rest of the node.
+    /// The query is answered with the number of blocks once the whole file is read.
+    fn import_snapshot(
+        &self,
+        query: Query<QueryDaSnapshotImport, u64>,
+        sender: tokio::sync::mpsc::Sender<SignedBlock>,
+    ) -> Result<()> {
+        let query = query.take()?;
+        if query.is_expired() {
+            debug!("Skipping expired query #{}", query.id);
+            return Ok(());
+        }
+        let path = match snapshot_path(&self.config.data_directory, &query.data.0) {
+            Ok(path) => path,
+            Err(e) => return query.bail(e),
+        };
+        tokio::task::spawn_blocking(move || {
+            let read = || -> Result<u64> {
+                let mut read = 0;
+                for block in SnapshotReader::open(&path)? {
+                    if sender.blocking_send(block?).is_err() {
+                        bail!("DataAvailability module stopped");
+                    }
+                    read += 1;
+                }
+                Ok(read)
+            };
+            match read() {
+                Ok(read) => {
+                    info!("📦 Imported {} block(s) from {}", read, path.display());
+                    _ = query.answer(read).log_warn("Answering snapshot import");
+                }
+                Err(e) => {
+                    warn!("Importing snapshot {}: {:#}", path.display(), e);
+                    _ = query.bail(e).log_warn("Answering snapshot import");
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Catches the validators certifying the next block up with the stored chain, from the ones
+    /// saved on shutdown. Without them, the chain is replayed from genesis. If the genesis block
+    /// isn't stored either, as after a light sync, the validators stay unknown and blocks are
+    /// rejected until the chain is synced again.
+    fn restore_certificates(
+        mut certificates: CertificateChain,
+        blocks: &mut dyn BlockStore,
+    ) -> CertificateChain {
+        let Some(last) = blocks.last() else {
+            return certificates;
+        };
+        if certificates
+            .last()
+            .is_some_and(|anchor| anchor.height.0 > last.height().0)
+        {
+            certificates = CertificateChain::default();
+        }
+        let from = certificates
+            .last()
+            .map_or(BlockHeight(0), |anchor| anchor.height + 1);
+        for block in blocks.range(from, last.height() + 1) {
+            let Ok(block) = block.log_error("Reading stored block") else {
+                break;
+            };
+            let follows = match certificates.last() {
+                Some(anchor) => block.parent_hash() == &anchor.hash,
+                None => block.height() == BlockHeight(0),
+            };
+            if !follows {
+                warn!(
+                    "Stored block {} does not follow the certificate chain, validators unknown",
+                    block.height()
+                );
+                return CertificateChain::default();
+            }
+            certificates.advance(&block);
+        }
+        certificates
+    }
+
+    fn save_certificates(&self) {
+        _ = Self::save_on_disk(
+            &self.config.data_directory.join("da_certificate_chain.bin"),
+            &self.certificates,
+        )
+        .log_error("Saving certificate chain");
+    }
+
+    /// Checks that the certificate is a valid aggregate signature of the block, whoever signed it.
+    /// The signers are checked once the block follows the stored chain.
+    fn verify_certificate_signature(&self, block: &SignedBlock) -> Result<()> {
+        if !self.config.da_verify_blocks || block.height() == BlockHeight(0) {
+            return Ok(());
+        }
+        let signed = Signed {
+            msg: ConsensusNetMessage::ConfirmAck(block.hash()),
+            signature: block.certificate.clone(),
+        };
+        if !BlstCrypto::verify_aggregate(&signed).context("Verifying certificate")? {
+            bail!("Invalid certificate signature");
+        }
+        Ok(())
+    }
+
+    /// Checks that the block was committed by the validators bonded after its parent, and
+    /// applies its validator set changes. The genesis block is trusted as it is built from the
+    /// configuration. Fails when the validators are unknown.
+    fn verify_certificate(&mut self, block: &SignedBlock) -> Result<()> {
+        if !self.config.da_verify_blocks {
+            self.certificates.advance(block);
+            return Ok(());
+        }
+        self.certificates.verify(block)
+    }
+
+    /// Checks that the block follows its parent.
+    fn verify_parent(&self, block: &SignedBlock) -> Result<()> {
+        if !self.config.da_verify_blocks
+            || block.height() == BlockHeight(0)
+            || self.follows_light_sync_anchor(block)
+        {
+            return Ok(());
+        }
+        let parent = self
+            .blocks
+            .get(block.parent_hash())?
+            .context("Parent block not found")?;
+        if parent.height() + 1 != block.height() {
+            bail!(
+                "Height {} does not follow parent height {}",
+                block.height(),
+                parent.height()
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the block is the first one fetched in full after a light sync.
+    fn follows_light_sync_anchor(&self, block: &SignedBlock) -> bool {
+        self.catchup_checkpoint
+            .light_sync_anchor
+            .as_ref()
+            .is_some_and(|anchor| {
+                anchor.height + 1 == block.height() && &anchor.hash == block.parent_hash()
+            })
+    }
+
+    fn buffer_block(&mut self, block: SignedBlock) {
+        self.buffered_signed_blocks.insert(block);
+        let max = self.config.da_max_buffered_blocks;
+        // Drop the highest blocks first: they are the furthest from being usable
+        // and will be streamed again once the gap is filled.
+        while max > 0 && self.buffered_signed_blocks.len() > max {
+            if let Some(dropped) = self.buffered_signed_blocks.pop_last() {
+                debug!(
+                    "Buffer full, dropping block {} {}",
+                    dropped.height(),
+                    dropped.hash()
+                );
+            }
+        }
+        self.metrics
+            .snapshot_buffered_blocks(self.buffered_signed_blocks.len());
+    }
+
+    /// If blocks are waiting for missing parents, stream the missing heights from a known peer.
+    /// Catchup is left untouched: the missing blocks are streamed on their own, and the stream
+    /// is closed once they are received.
+    async fn request_gap_fill(&mut self, sender: tokio::sync::mpsc::Sender<SignedBlock>) {
+        if self.catchup_task.is_some()
+            || self
+                .gap_fill
+                .as_ref()
+                .is_some_and(|gap_fill| !gap_fill.task.is_finished())
+        {
+            return;
+        }
+        let Some(first_buffered) = self
+            .buffered_signed_blocks
+            .first()
+            .filter(|b| b.height().0 > 0)
+        else {
+            return;
+        };
+        if self
+            .last_gap_fill_request
+            .is_some_and(|last| last.elapsed() < std::time::Duration::from_secs(10))
+        {
+            return;
+        }
+        let Some(peer) = self.da_peers.first().cloned() else {
+            return;
+        };
+        let Some(start) = self.blocks.last().map(|block| block.height() + 1) else {
+            return;
+        };
+        // Try another peer next time
+        self.da_peers.rotate_left(1);
+        self.last_gap_fill_request = Some(std::time::Instant::now());
+
+        let until = first_buffered.height() - 1;
+        info!(
+            "🕳️ Gap detected before block {}, requesting missing blocks from {}",
+            first_buffered.height(),
+            peer
+        );
+        match RawDAListener::new(&peer, start, &self.config).await {
+            Ok(stream) => {
+                self.gap_fill = Some(GapFill {
+                    until,
+                    task: Self::forward_streamed_blocks(stream, sender),
+                });
+            }
+            Err(e) => warn!("Requesting missing blocks from {}: {:#}", peer, e),
+        }
+    }
+
     async fn pop_buffer(&mut self, mut last_block_hash: ConsensusProposalHash) {
         // Iterative loop to avoid stack overflows
         while let Some(first_buffered) = self.buffered_signed_blocks.first() {
@@ -331,9 +1052,25 @@ impl DataAvailability {
             last_block_hash = first_buffered.hash();
             self.add_processed_block(first_buffered).await;
         }
+        self.metrics
+            .snapshot_buffered_blocks(self.buffered_signed_blocks.len());
     }
 
+    #[instrument(skip_all, fields(block_height = block.height().0, block_hash = %block.hash()))]
     async fn add_processed_block(&mut self, block: SignedBlock) {
+        if let Err(e) = self
+            .verify_parent(&block)
+            .and_then(|_| self.verify_certificate(&block))
+        {
+            warn!(
+                "Rejecting block {} {}: {:#}",
+                block.height(),
+                block.hash(),
+                e
+            );
+            self.metrics.rejected_block();
+            return;
+        }
         // TODO: if we don't have streaming peers, we could just pass the block here
         // and avoid a clone + drop cost (which can be substantial for large blocks).
         if let Err(e) = self.blocks.put(block.clone()) {
@@ -358,33 +1095,59 @@ impl DataAvailability {
             block.txs().iter().map(|tx| tx.hash().0).collect::<Vec<_>>()
         );
 
-        // Stream block to all peers
+        // No websocket subscribers is fine
+        _ = self.ws_blocks.send(block.clone());
+
+        // Queue block for all peers, each peer has its own sending task.
         // TODO: use retain once async closures are supported ?
+        let slow_peer_policy = self.config.da_stream.slow_peer_policy;
         let mut to_remove = Vec::new();
         for (peer_id, peer) in self.stream_peer_metadata.iter_mut() {
-            let last_ping = peer.last_ping;
-            if last_ping + 60 * 5 < get_current_timestamp() {
-                info!("peer {} timed out", &peer_id);
-                peer.keepalive_abort.abort();
-                to_remove.push(peer_id.clone());
-            } else {
-                info!("streaming block {} to peer {}", block.hash(), &peer_id);
-                match peer.sender.send(block.clone()).await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        debug!(
-                            "Couldn't send new block to peer {}, stopping streaming  : {:?}",
-                            &peer_id, e
-                        );
-                        peer.keepalive_abort.abort();
-                        to_remove.push(peer_id.clone());
+            if peer.headers_only {
+                continue;
+            }
+            info!("streaming block {} to peer {}", block.hash(), &peer_id);
+            match peer
+                .sender
+                .try_send(DataAvailabilityEvent::SignedBlock(block.clone()))
+            {
+                Ok(_) => peer.sent_blocks += 1,
+                Err(tokio::sync::mpsc::error::TrySendError::Full(block)) => {
+                    self.metrics.slow_peer(peer_id);
+                    if slow_peer_policy == SlowPeerPolicy::Wait {
+                        warn!("Peer {} is slow, waiting for its queue to drain", &peer_id);
+                        if peer.sender.send(block).await.is_ok() {
+                            peer.sent_blocks += 1;
+                            continue;
+                        }
+                    } else {
+                        warn!("Peer {} is too slow, stopping streaming", &peer_id);
                     }
+                    self.metrics.peer_disconnected("slow");
+                    to_remove.push(peer_id.clone());
+                }
+                Err(tokio::sync::mpsc::error::TrySendError::Closed(_)) => {
+                    debug!(
+                        "Couldn't send new block to peer {}, stopping streaming",
+                        &peer_id
+                    );
+                    self.metrics.peer_disconnected("closed");
+                    to_remove.push(peer_id.clone());
                 }
             }
         }
         for peer_id in to_remove {
-            self.stream_peer_metadata.remove(&peer_id);
+            if let Some(peer) = self.stream_peer_metadata.remove(&peer_id) {
+                peer.abort();
+            }
         }
+        self.metrics
+            .snapshot_streaming_peers(self.stream_peer_metadata.len());
+        for peer in self.peers_info() {
+            self.metrics
+                .snapshot_peer_queued_messages(&peer.address, peer.queued_messages);
+        }
+        self.metrics.snapshot_block_height(block.height().0);
 
         // Send the block to NodeState for processing
         _ = self
@@ -393,37 +1156,194 @@ impl DataAvailability {
             .log_error("Sending OrderedSignedBlock");
     }
 
+    /// Peers we are streaming to, by address.
+    fn peers_info(&self) -> Vec<DaPeerInfo> {
+        let mut peers: Vec<DaPeerInfo> = self
+            .stream_peer_metadata
+            .iter()
+            .map(|(address, peer)| DaPeerInfo {
+                address: address.clone(),
+                last_seen: peer.last_seen,
+                queued_messages: peer
+                    .sender
+                    .max_capacity()
+                    .saturating_sub(peer.sender.capacity()),
+            })
+            .collect();
+        peers.sort_by(|a, b| a.address.cmp(&b.address));
+        peers
+    }
+
+    /// Checks the peer against the configured IP allow/deny lists and auth token.
+    fn check_stream_access(&self, addr: &SocketAddr, auth_token: Option<&str>) -> Result<()> {
+        let conf = &self.config.da_stream;
+        let ip = addr.ip().to_string();
+        if conf.ip_denylist.contains(&ip) {
+            bail!("IP {} is denylisted", ip);
+        }
+        if !conf.ip_allowlist.is_empty() && !conf.ip_allowlist.contains(&ip) {
+            bail!("IP {} is not allowlisted", ip);
+        }
+        if let Some(expected) = conf.auth_token.as_deref() {
+            if !auth_token
+                .is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+            {
+                bail!("Invalid or missing auth token");
+            }
+        }
+        Ok(())
+    }
+
+    /// Proof of a transaction's inclusion in the first stored block including it.
+    fn tx_inclusion_proof(&mut self, tx_hash: &TxHash) -> Result<APITxInclusionProof> {
+        let inclusion = self
+            .blocks
+            .get_transaction(tx_hash)?
+            .context("Transaction not found")?;
+        let block = self
+            .blocks
+            .get(&inclusion.block_hash)?
+            .context("Block not found")?;
+        let tree = block.txs_tree();
+        let proof = tree.proof(tx_hash).context("Transaction not in block")?;
+        Ok(APITxInclusionProof {
+            block_hash: inclusion.block_hash,
+            block_height: inclusion.block_height,
+            tx_root: tree.root(),
+            proof,
+        })
+    }
+
+    /// Sends a peer the transaction it asked for, if a stored block includes it, then closes the
+    /// connection.
+    fn answer_transaction_request(
+        &mut self,
+        tx_hash: &TxHash,
+        auth_token: Option<String>,
+        mut sender: SplitSink<
+            Framed<NodeStream, DataAvailabilityServerCodec>,
+            DataAvailabilityEvent,
+        >,
+        addr: SocketAddr,
+    ) -> Result<()> {
+        self.check_stream_access(&addr, auth_token.as_deref())
+            .context("Refusing to answer peer")?;
+        let inclusion = self.blocks.get_transaction(tx_hash)?;
+        debug!(
+            "Peer {} asked for transaction {}, found: {}",
+            addr,
+            tx_hash,
+            inclusion.is_some()
+        );
+        tokio::task::Builder::new()
+            .name("da-send-transaction")
+            .spawn(async move {
+                _ = sender
+                    .send(DataAvailabilityEvent::Transaction(inclusion))
+                    .await;
+                _ = sender.close().await;
+            })?;
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
     async fn start_streaming_to_peer(
         &mut self,
         start_height: BlockHeight,
-        ping_sender: tokio::sync::mpsc::Sender<String>,
+        headers_only: bool,
+        version: u8,
+        auth_token: Option<String>,
+        keepalive_sender: tokio::sync::mpsc::Sender<PeerKeepalive>,
         catchup_sender: tokio::sync::mpsc::Sender<(Vec<ConsensusProposalHash>, String)>,
-        sender: SplitSink<Framed<TcpStream, DataAvailabilityServerCodec>, SignedBlock>,
-        mut receiver: SplitStream<Framed<TcpStream, DataAvailabilityServerCodec>>,
-        peer_ip: &String,
+        sender: SplitSink<Framed<NodeStream, DataAvailabilityServerCodec>, DataAvailabilityEvent>,
+        mut receiver: SplitStream<Framed<NodeStream, DataAvailabilityServerCodec>>,
+        addr: SocketAddr,
     ) -> Result<()> {
+        // Dropping the sink and stream closes the connection.
+        self.check_stream_access(&addr, auth_token.as_deref())
+            .context("Refusing to stream to peer")?;
+
+        let peer_ip = &addr.to_string();
+
+        // Blocks are written to the peer by a dedicated task so a slow peer doesn't stall others.
+        let (queue_sender, queue_receiver) =
+            tokio::sync::mpsc::channel(self.config.da_stream.peer_send_queue_size.max(1));
+
         // Start a task to process pings from the peer.
         // We do the processing in the main select! loop to keep things synchronous.
         // This makes it easier to store data in the same struct without mutexing.
+        // Anything but a ping, or no ping for `ping_timeout`, closes the stream.
         let peer_ip_keepalive = peer_ip.to_string();
+        let pong_sender = queue_sender.clone();
+        let ping_timeout =
+            std::time::Duration::from_secs(self.config.da_stream.ping_timeout.max(1));
         let keepalive_abort = tokio::task::Builder::new()
             .name("da-keep-alive-abort")
             .spawn(async move {
-                loop {
-                    receiver.next().await;
-                    let _ = ping_sender.send(peer_ip_keepalive.clone()).await;
-                }
+                let reason = loop {
+                    match tokio::time::timeout(ping_timeout, receiver.next()).await {
+                        Ok(Some(Ok(DataAvailabilityServerRequest::Ping))) => {
+                            // A full queue means the peer has blocks to read, no need to pong.
+                            _ = pong_sender.try_send(DataAvailabilityEvent::Pong);
+                            let _ = keepalive_sender
+                                .send(PeerKeepalive::Seen(peer_ip_keepalive.clone()))
+                                .await;
+                        }
+                        Ok(Some(Ok(DataAvailabilityServerRequest::Ack(received))))
+                            if version >= 5 =>
+                        {
+                            let _ = keepalive_sender
+                                .send(PeerKeepalive::Ack(peer_ip_keepalive.clone(), received))
+                                .await;
+                        }
+                        Ok(Some(Ok(request))) => {
+                            warn!(
+                                "Peer {} sent {:?} while streaming",
+                                peer_ip_keepalive, request
+                            );
+                            break "protocol";
+                        }
+                        Ok(Some(Err(e))) => {
+                            warn!("Invalid message from peer {}: {:#}", peer_ip_keepalive, e);
+                            break "protocol";
+                        }
+                        Ok(None) => break "closed",
+                        Err(_) => break "timeout",
+                    }
+                };
+                let _ = keepalive_sender
+                    .send(PeerKeepalive::Disconnect(peer_ip_keepalive, reason))
+                    .await;
             })?;
+        let send_abort =
+            tokio::task::Builder::new()
+                .name("da-send-to-peer")
+                .spawn(send_blocks_to_peer(
+                    sender,
+                    queue_receiver,
+                    self.config.da_stream.peer_max_bytes_per_sec,
+                    version,
+                ))?;
 
         // Then store data so we can send new blocks as they come.
-        self.stream_peer_metadata.insert(
+        if let Some(previous) = self.stream_peer_metadata.insert(
             peer_ip.to_string(),
             BlockStreamPeer {
-                last_ping: get_current_timestamp(),
-                sender,
+                last_seen: get_current_timestamp(),
+                sender: queue_sender,
+                send_abort,
                 keepalive_abort,
+                headers_only,
+                sent_blocks: 0,
+                acked_blocks: (version >= 5).then_some(0),
+                window: 2 * codec::DA_ACK_INTERVAL,
+                paused_catchup: None,
             },
-        );
+        ) {
+            previous.abort();
+        }
+        self.metrics
+            .snapshot_streaming_peers(self.stream_peer_metadata.len());
 
         // Finally, stream past blocks as required.
         // We'll create a copy of the range so we don't stream everything.
@@ -456,31 +1376,62 @@ impl DataAvailability {
         sender: tokio::sync::mpsc::Sender<SignedBlock>,
     ) -> Result<(), Error> {
         info!("📡 Streaming data from {ip}");
-        let start = self
-            .blocks
-            .last()
-            .map(|block| block.height() + 1)
-            .unwrap_or(BlockHeight(0));
-        let Ok(mut stream) = RawDAListener::new(&ip, start).await else {
+        let start = match self.blocks.last() {
+            Some(block) => block.height() + 1,
+            None => {
+                // Nothing to serve yet, so the module can wait for the headers to be verified.
+                if self.config.da_light_sync.enabled
+                    && self.catchup_checkpoint.light_sync_anchor.is_none()
+                {
+                    if let Some(certificates) = light_sync::sync_headers(&ip, &self.config)
+                        .await
+                        .log_warn("Light sync failed, fetching all blocks")
+                        .ok()
+                        .flatten()
+                    {
+                        self.catchup_checkpoint.light_sync_anchor = certificates.last().cloned();
+                        self.certificates = certificates;
+                        self.save_certificates();
+                    }
+                }
+                self.catchup_checkpoint
+                    .light_sync_anchor
+                    .as_ref()
+                    .map_or(BlockHeight(0), |anchor| anchor.height + 1)
+            }
+        };
+        let Ok(stream) = RawDAListener::new(&ip, start, &self.config).await else {
             bail!("Error occured setting up the DA listener");
         };
-        self.catchup_task = Some(tokio::spawn(async move {
+        self.catchup_checkpoint.peer = Some(ip);
+        self.catchup_checkpoint.target_height = self.catchup_height;
+        self.save_catchup_checkpoint();
+        self.catchup_task = Some(Self::forward_streamed_blocks(stream, sender));
+        Ok(())
+    }
+
+    /// Hands the blocks streamed by a peer to the module, until the stream ends.
+    fn forward_streamed_blocks(
+        mut stream: RawDAListener,
+        sender: tokio::sync::mpsc::Sender<SignedBlock>,
+    ) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
             loop {
-                match stream.next().await {
-                    None => {
+                match stream.next_block().await {
+                    Ok(None) => {
                         warn!("End of stream");
                         break;
                     }
-                    Some(Err(e)) => {
+                    Err(e) => {
                         warn!("Error while streaming data from peer: {:#}", e);
                         break;
                     }
-                    Some(Ok(streamed_block)) => {
+                    Ok(Some(streamed_block)) => {
                         info!(
                             "📦 Received block (height {}) from stream",
                             streamed_block.consensus_proposal.slot
                         );
-                        // TODO: we should wait if the stream is full.
+                        // Waiting here delays our acknowledgements, pausing the server's catchup
                         if let Err(e) = sender.send(streamed_block).await {
                             tracing::error!("Error while sending block over channel: {:#}", e);
                             break;
@@ -488,8 +1439,75 @@ impl DataAvailability {
                     }
                 }
             }
-        }));
-        Ok(())
+        })
+    }
+
+    fn catchup_checkpoint_path(&self) -> PathBuf {
+        self.config.data_directory.join("da_catchup_checkpoint.bin")
+    }
+
+    fn update_buffered_watermark(&mut self, height: BlockHeight) {
+        if !self.need_catchup {
+            return;
+        }
+        if self
+            .catchup_checkpoint
+            .buffered_watermark
+            .is_none_or(|watermark| watermark.0 < height.0)
+        {
+            self.catchup_checkpoint.buffered_watermark = Some(height);
+        }
+    }
+
+    /// Measures the block store, and alerts when its size reaches a higher threshold.
+    fn check_disk_usage(&mut self) {
+        let Some(used_bytes) = self.blocks.disk_usage() else {
+            return;
+        };
+        self.disk_usage = Some(used_bytes);
+        self.metrics.snapshot_disk_usage(used_bytes);
+
+        let level = DiskUsageLevel::of(used_bytes, &self.config.da_disk_usage);
+        if level > self.disk_usage_level {
+            match level {
+                DiskUsageLevel::Critical => error!(
+                    "💾 Block store takes {} bytes, above the critical threshold of {}",
+                    used_bytes, self.config.da_disk_usage.critical_bytes
+                ),
+                _ => warn!(
+                    "💾 Block store takes {} bytes, above the warning threshold of {}",
+                    used_bytes, self.config.da_disk_usage.warning_bytes
+                ),
+            }
+            _ = self
+                .bus
+                .send(DataEvent::DiskUsageAlert { used_bytes, level })
+                .log_error("Sending disk usage alert");
+        }
+        self.disk_usage_level = level;
+    }
+
+    fn save_catchup_checkpoint(&self) {
+        _ = Self::save_on_disk(&self.catchup_checkpoint_path(), &self.catchup_checkpoint)
+            .log_error("Saving catchup checkpoint");
+    }
+
+    /// Marks catchup as over and lets other modules know.
+    fn end_catchup(&mut self) {
+        self.need_catchup = false;
+        self.clear_catchup_checkpoint();
+        _ = self
+            .bus
+            .send(DataEvent::CatchupDone)
+            .log_error("Sending catchup done");
+    }
+
+    fn clear_catchup_checkpoint(&mut self) {
+        self.catchup_checkpoint = CatchupCheckpoint::default();
+        let path = self.catchup_checkpoint_path();
+        if path.exists() {
+            _ = std::fs::remove_file(path).log_error("Removing catchup checkpoint");
+        }
     }
 }
 
@@ -501,86 +1519,126 @@ pub mod tests {
     use crate::{
         bus::BusClientSender,
         consensus::CommittedConsensusProposal,
+        data_availability::codec::DataAvailabilityEvent,
         mempool::MempoolEvent,
         model::*,
-        node_state::{
-            module::{NodeStateBusClient, NodeStateEvent},
-            NodeState,
+        utils::{
+            conf::{Conf, DaStorage},
+            crypto::BlstCrypto,
+            integration_test::find_available_port,
         },
-        utils::{conf::Conf, integration_test::find_available_port},
     };
     use futures::{SinkExt, StreamExt};
-    use staking::state::Staking;
+    use std::sync::Arc;
     use tokio::io::AsyncWriteExt;
     use tokio_util::codec::{Framed, LengthDelimitedCodec};
 
+    use super::blocks_fjall::Blocks;
     use super::module_bus_client;
-    use super::Blocks;
+    use super::testkit::DataAvailabilityTestCtx;
     use anyhow::Result;
 
-    /// For use in integration tests
-    pub struct DataAvailabilityTestCtx {
-        pub node_state_bus: NodeStateBusClient,
-        pub da: super::DataAvailability,
-        pub node_state: NodeState,
+    fn test_cache() -> super::BlockCache {
+        super::BlockCache::new(16, "test".to_string())
     }
 
-    impl DataAvailabilityTestCtx {
-        pub async fn new(shared_bus: crate::bus::SharedMessageBus) -> Self {
+    #[test_log::test]
+    fn test_blocks() -> Result<()> {
+        for storage in [
+            DaStorage::Fjall,
+            DaStorage::Memory,
+            #[cfg(feature = "rocksdb")]
+            DaStorage::Rocksdb,
+        ] {
             let tmpdir = tempfile::tempdir().unwrap().into_path();
-            let blocks = Blocks::new(&tmpdir).unwrap();
-
-            let bus = super::DABusClient::new_from_bus(shared_bus.new_handle()).await;
-            let node_state_bus = NodeStateBusClient::new_from_bus(shared_bus).await;
-
-            let mut config: Conf = Conf::new(None, None, None).unwrap();
-            config.da_address = format!("127.0.0.1:{}", find_available_port().await);
-            let da = super::DataAvailability {
-                config: config.into(),
-                bus,
-                blocks,
-                buffered_signed_blocks: Default::default(),
-                stream_peer_metadata: Default::default(),
-                need_catchup: false,
-                catchup_task: None,
-                catchup_height: None,
-            };
-
-            let node_state = NodeState::default();
-
-            DataAvailabilityTestCtx {
-                node_state_bus,
-                da,
-                node_state,
-            }
+            let mut blocks = super::open_block_store(storage, &tmpdir, test_cache())?;
+            let block = SignedBlock::default();
+            blocks.put(block.clone())?;
+            assert!(blocks.last().unwrap().height() == block.height());
+            let last = blocks.get(&block.hash())?;
+            assert!(last.is_some());
+            assert!(last.unwrap().height() == BlockHeight(0));
+            let range = blocks
+                .range(BlockHeight(0), BlockHeight(1))
+                .map(|b| b.map(|b| b.hash()))
+                .collect::<Result<Vec<_>>>()?;
+            assert_eq!(range, vec![block.hash()]);
+            assert_eq!(blocks.range(BlockHeight(1), BlockHeight(5)).count(), 0);
         }
+        Ok(())
+    }
 
-        pub async fn handle_signed_block(&mut self, block: SignedBlock) {
-            self.da.handle_signed_block(block.clone()).await;
-            let full_block = self.node_state.handle_signed_block(&block);
-            self.node_state_bus
-                .send(NodeStateEvent::NewBlock(Box::new(full_block)))
-                .unwrap();
+    #[test_log::test]
+    fn test_get_transaction() -> Result<()> {
+        for storage in [
+            DaStorage::Fjall,
+            DaStorage::Memory,
+            #[cfg(feature = "rocksdb")]
+            DaStorage::Rocksdb,
+        ] {
+            let tmpdir = tempfile::tempdir().unwrap().into_path();
+            let mut blocks = super::open_block_store(storage, &tmpdir, test_cache())?;
+            let txs: Vec<Transaction> = ["alice.id", "bob.id"]
+                .into_iter()
+                .map(|identity| {
+                    BlobTransaction {
+                        identity: identity.into(),
+                        blobs: vec![],
+                    }
+                    .into()
+                })
+                .collect();
+            let block = SignedBlock {
+                data_proposals: vec![(
+                    ValidatorPublicKey::default(),
+                    vec![DataProposal {
+                        id: 0,
+                        parent_data_proposal_hash: None,
+                        txs: txs.clone(),
+                    }],
+                )],
+                ..SignedBlock::default()
+            };
+            blocks.put(block.clone())?;
+
+            let inclusion = blocks.get_transaction(&txs[1].hash())?.unwrap();
+            assert_eq!(inclusion.tx, txs[1]);
+            assert_eq!(inclusion.block_hash, block.hash());
+            assert_eq!(inclusion.index, 1);
+            assert!(blocks
+                .get_transaction(&TxHash("unknown".to_string()))?
+                .is_none());
         }
+        Ok(())
     }
 
     #[test_log::test]
-    fn test_blocks() -> Result<()> {
+    fn test_snapshot_round_trip() -> Result<()> {
         let tmpdir = tempfile::tempdir().unwrap().into_path();
-        let mut blocks = Blocks::new(&tmpdir).unwrap();
-        let block = SignedBlock::default();
-        blocks.put(block.clone())?;
-        assert!(blocks.last().unwrap().height() == block.height());
-        let last = blocks.get(&block.hash())?;
-        assert!(last.is_some());
-        assert!(last.unwrap().height() == BlockHeight(0));
+        let mut source = super::open_block_store(DaStorage::Memory, &tmpdir, test_cache())?;
+        let mut block = SignedBlock::default();
+        for i in 1..10 {
+            source.put(block.clone())?;
+            block.consensus_proposal.parent_hash = block.hash();
+            block.consensus_proposal.slot = i;
+        }
+
+        let path = super::snapshot_path(&tmpdir, "blocks.snapshot")?;
+        assert_eq!(source.export_snapshot(&path)?, 9);
+
+        let mut target =
+            super::open_block_store(DaStorage::Fjall, &tmpdir.join("target"), test_cache())?;
+        assert_eq!(target.import_snapshot(&path)?, 9);
+        assert_eq!(target.last_block_hash(), source.last_block_hash());
+
+        assert!(super::snapshot_path(&tmpdir, "../node_state.bin").is_err());
         Ok(())
     }
 
     #[tokio::test]
     async fn test_pop_buffer_large() {
         let tmpdir = tempfile::tempdir().unwrap().into_path();
-        let blocks = Blocks::new(&tmpdir).unwrap();
+        let blocks = Box::new(Blocks::new(&tmpdir, test_cache()).unwrap());
 
         let bus = super::DABusClient::new_from_bus(crate::bus::SharedMessageBus::new(
             crate::bus::metrics::BusMetrics::global("global".to_string()),
@@ -589,12 +1647,23 @@ pub mod tests {
         let mut da = super::DataAvailability {
             config: Default::default(),
             bus,
+            crypto: Arc::new(BlstCrypto::new_random().unwrap()),
             blocks,
             buffered_signed_blocks: Default::default(),
+            certificates: Default::default(),
+            da_peers: Default::default(),
+            last_gap_fill_request: None,
+            gap_fill: None,
             stream_peer_metadata: Default::default(),
+            ws_blocks: tokio::sync::broadcast::channel(100).0,
+            metrics: super::DaMetrics::global("test".to_string()),
             need_catchup: false,
             catchup_task: None,
             catchup_height: None,
+            catchup_checkpoint: Default::default(),
+            stream_request_receiver: None,
+            disk_usage: None,
+            disk_usage_level: Default::default(),
         };
         let mut block = SignedBlock::default();
         let mut blocks = vec![];
@@ -609,6 +1678,252 @@ pub mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_rejects_invalid_blocks() {
+        let mut ctx = DataAvailabilityTestCtx::new(crate::bus::SharedMessageBus::default()).await;
+        let mut config = (*ctx.da.config).clone();
+        config.da_verify_blocks = true;
+        ctx.da.config = config.into();
+
+        let crypto = crate::utils::crypto::BlstCrypto::new("validator".into()).unwrap();
+        let mut genesis = SignedBlock::default();
+        genesis
+            .consensus_proposal
+            .staking_actions
+            .push(ConsensusStakingAction::Bond {
+                candidate: NewValidatorCandidate {
+                    pubkey: crypto.validator_pubkey().clone(),
+                    msg: crypto
+                        .sign(ConsensusNetMessage::ValidatorCandidacy(
+                            ValidatorCandidacy {
+                                pubkey: crypto.validator_pubkey().clone(),
+                                peer_address: String::new(),
+                            },
+                        ))
+                        .unwrap(),
+                },
+            });
+        ctx.da.handle_signed_block(genesis.clone()).await;
+
+        // Not signed
+        let mut block = SignedBlock::default();
+        block.consensus_proposal.parent_hash = genesis.hash();
+        block.consensus_proposal.slot = 1;
+        ctx.da.handle_signed_block(block.clone()).await;
+        assert!(!ctx.da.blocks.contains(&block.hash()));
+
+        // Signed by a validator that isn't bonded
+        let outsider = crate::utils::crypto::BlstCrypto::new("outsider".into()).unwrap();
+        block.certificate = outsider
+            .sign_aggregate(ConsensusNetMessage::ConfirmAck(block.hash()), &[])
+            .unwrap()
+            .signature;
+        ctx.da.handle_signed_block(block.clone()).await;
+        assert!(!ctx.da.blocks.contains(&block.hash()));
+
+        // Properly signed
+        block.certificate = crypto
+            .sign_aggregate(ConsensusNetMessage::ConfirmAck(block.hash()), &[])
+            .unwrap()
+            .signature;
+        ctx.da.handle_signed_block(block.clone()).await;
+        assert!(ctx.da.blocks.contains(&block.hash()));
+
+        // Signed, but skipping heights
+        let mut block_skipping = SignedBlock::default();
+        block_skipping.consensus_proposal.parent_hash = block.hash();
+        block_skipping.consensus_proposal.slot = 5;
+        block_skipping.certificate = crypto
+            .sign_aggregate(ConsensusNetMessage::ConfirmAck(block_skipping.hash()), &[])
+            .unwrap()
+            .signature;
+        ctx.da.handle_signed_block(block_skipping.clone()).await;
+        assert!(!ctx.da.blocks.contains(&block_skipping.hash()));
+
+        // The validators are restored from the stored chain after a restart
+        let certificates = super::DataAvailability::restore_certificates(
+            Default::default(),
+            ctx.da.blocks.as_mut(),
+        );
+        assert_eq!(
+            certificates.last().map(|anchor| anchor.hash.clone()),
+            Some(block.hash())
+        );
+
+        // Without the genesis block, they are unknown and blocks are rejected
+        let mut next = SignedBlock::default();
+        next.consensus_proposal.parent_hash = block.hash();
+        next.consensus_proposal.slot = 2;
+        next.certificate = crypto
+            .sign_aggregate(ConsensusNetMessage::ConfirmAck(next.hash()), &[])
+            .unwrap()
+            .signature;
+        let mut blocks: Box<dyn super::BlockStore> =
+            Box::new(super::blocks_memory::Blocks::new(&ctx.da.config.data_directory).unwrap());
+        blocks.put(block.clone()).unwrap();
+        ctx.da.certificates =
+            super::DataAvailability::restore_certificates(Default::default(), blocks.as_mut());
+        ctx.da.handle_signed_block(next.clone()).await;
+        assert!(!ctx.da.blocks.contains(&next.hash()));
+    }
+
+    #[tokio::test]
+    async fn test_buffer_is_bounded() {
+        let mut ctx = DataAvailabilityTestCtx::new(crate::bus::SharedMessageBus::default()).await;
+        let mut config = (*ctx.da.config).clone();
+        config.da_max_buffered_blocks = 3;
+        ctx.da.config = config.into();
+
+        let mut block = SignedBlock::default();
+        let mut blocks = vec![];
+        for i in 1..10 {
+            blocks.push(block.clone());
+            block.consensus_proposal.parent_hash = block.hash();
+            block.consensus_proposal.slot = i;
+        }
+        // Genesis first, then everything but block 1 so nothing can be applied
+        ctx.da.handle_signed_block(blocks[0].clone()).await;
+        for block in blocks.iter().skip(2) {
+            ctx.da.handle_signed_block(block.clone()).await;
+        }
+
+        assert_eq!(ctx.da.buffered_signed_blocks.len(), 3);
+        assert_eq!(
+            ctx.da
+                .buffered_signed_blocks
+                .iter()
+                .map(|b| b.height().0)
+                .collect::<Vec<_>>(),
+            vec![2, 3, 4]
+        );
+
+        // Filling the gap applies the buffered blocks
+        ctx.da.handle_signed_block(blocks[1].clone()).await;
+        assert!(ctx.da.buffered_signed_blocks.is_empty());
+        assert_eq!(ctx.da.blocks.last().unwrap().height(), BlockHeight(4));
+    }
+
+    #[tokio::test]
+    async fn test_catchup_checkpoint_persistence() {
+        use crate::utils::modules::Module;
+
+        let mut ctx = DataAvailabilityTestCtx::new(crate::bus::SharedMessageBus::default()).await;
+        ctx.da.need_catchup = true;
+        ctx.da.catchup_checkpoint.peer = Some("127.0.0.1:4141".to_string());
+        ctx.da.catchup_checkpoint.target_height = Some(BlockHeight(42));
+        ctx.da.update_buffered_watermark(BlockHeight(12));
+        ctx.da.update_buffered_watermark(BlockHeight(10));
+        ctx.da.save_catchup_checkpoint();
+
+        let loaded: Option<super::CatchupCheckpoint> =
+            super::DataAvailability::load_from_disk(&ctx.da.catchup_checkpoint_path());
+        assert_eq!(
+            loaded,
+            Some(super::CatchupCheckpoint {
+                peer: Some("127.0.0.1:4141".to_string()),
+                target_height: Some(BlockHeight(42)),
+                buffered_watermark: Some(BlockHeight(12)),
+            })
+        );
+
+        ctx.da.clear_catchup_checkpoint();
+        assert!(!ctx.da.catchup_checkpoint_path().exists());
+    }
+
+    #[tokio::test]
+    async fn test_catchup_done_event() {
+        use crate::bus::BusClientReceiver;
+
+        let mut ctx = DataAvailabilityTestCtx::new(crate::bus::SharedMessageBus::default()).await;
+        let genesis = SignedBlock::default();
+        let mut block = genesis.clone();
+        block.consensus_proposal.parent_hash = genesis.hash();
+        block.consensus_proposal.slot = 1;
+        ctx.handle_signed_block(genesis).await;
+        ctx.handle_signed_block(block).await;
+        ctx.da.need_catchup = true;
+        ctx.da.catchup_task = Some(tokio::spawn(std::future::pending()));
+
+        ctx.da
+            .handle_mempool_event(MempoolEvent::StartedBuildingBlocks(BlockHeight(1)))
+            .await
+            .unwrap();
+
+        assert!(!ctx.da.need_catchup);
+        loop {
+            match BusClientReceiver::<super::DataEvent>::try_recv(&mut ctx.node_state_bus).unwrap()
+            {
+                super::DataEvent::CatchupDone => break,
+                _ => continue,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_disk_usage_alerts() {
+        use crate::bus::BusClientReceiver;
+
+        let mut ctx = DataAvailabilityTestCtx::new(crate::bus::SharedMessageBus::default()).await;
+        // Memory stores don't take disk space
+        ctx.da.check_disk_usage();
+        assert_eq!(ctx.da.disk_usage, None);
+
+        let tmpdir = tempfile::tempdir().unwrap().into_path();
+        ctx.da.blocks = Box::new(Blocks::new(&tmpdir, test_cache()).unwrap());
+        ctx.handle_signed_block(SignedBlock::default()).await;
+        ctx.da.blocks.persist().unwrap();
+        ctx.da.check_disk_usage();
+        let used_bytes = ctx.da.disk_usage.unwrap();
+        assert!(used_bytes > 0);
+        assert_eq!(ctx.da.disk_usage_level, super::DiskUsageLevel::Normal);
+
+        let mut config = (*ctx.da.config).clone();
+        config.da_disk_usage.warning_bytes = 1;
+        config.da_disk_usage.critical_bytes = used_bytes * 1000;
+        ctx.da.config = config.into();
+        ctx.da.check_disk_usage();
+        ctx.da.check_disk_usage();
+
+        let mut alerts = vec![];
+        while let Ok(event) =
+            BusClientReceiver::<super::DataEvent>::try_recv(&mut ctx.node_state_bus)
+        {
+            if let super::DataEvent::DiskUsageAlert { level, .. } = event {
+                alerts.push(level);
+            }
+        }
+        // Only sent when crossing the threshold
+        assert_eq!(alerts, vec![super::DiskUsageLevel::Warning]);
+    }
+
+    #[tokio::test]
+    async fn test_stream_access_control() {
+        let mut ctx = DataAvailabilityTestCtx::new(crate::bus::SharedMessageBus::default()).await;
+        let mut config = (*ctx.da.config).clone();
+        config.da_stream.auth_token = Some("secret".to_string());
+        config.da_stream.ip_denylist = vec!["10.0.0.2".to_string()];
+        ctx.da.config = config.clone().into();
+
+        let allowed: std::net::SocketAddr = "10.0.0.1:1234".parse().unwrap();
+        let denied: std::net::SocketAddr = "10.0.0.2:1234".parse().unwrap();
+
+        assert!(ctx.da.check_stream_access(&allowed, Some("secret")).is_ok());
+        assert!(ctx.da.check_stream_access(&allowed, Some("wrong")).is_err());
+        assert!(ctx.da.check_stream_access(&allowed, None).is_err());
+        assert!(ctx.da.check_stream_access(&denied, Some("secret")).is_err());
+
+        config.da_stream.ip_allowlist = vec!["10.0.0.3".to_string()];
+        ctx.da.config = config.into();
+        assert!(ctx
+            .da
+            .check_stream_access(&allowed, Some("secret"))
+            .is_err());
+        assert!(ctx
+            .da
+            .check_stream_access(&"10.0.0.3:1".parse().unwrap(), Some("secret"))
+            .is_ok());
+    }
+
     module_bus_client! {
     #[derive(Debug)]
     struct TestBusClient {
@@ -619,7 +1934,7 @@ pub mod tests {
     #[test_log::test(tokio::test)]
     async fn test_da_streaming() {
         let tmpdir = tempfile::tempdir().unwrap().into_path();
-        let blocks = Blocks::new(&tmpdir).unwrap();
+        let blocks = Box::new(Blocks::new(&tmpdir, test_cache()).unwrap());
 
         let global_bus = crate::bus::SharedMessageBus::new(
             crate::bus::metrics::BusMetrics::global("global".to_string()),
@@ -629,15 +1944,26 @@ pub mod tests {
 
         let mut config: Conf = Conf::new(None, None, None).unwrap();
         config.da_address = format!("127.0.0.1:{}", find_available_port().await);
+        config.da_verify_blocks = false;
         let mut da = super::DataAvailability {
             config: config.clone().into(),
             bus,
             blocks,
             buffered_signed_blocks: Default::default(),
+            certificates: Default::default(),
+            da_peers: Default::default(),
+            last_gap_fill_request: None,
+            gap_fill: None,
             stream_peer_metadata: Default::default(),
+            ws_blocks: tokio::sync::broadcast::channel(100).0,
+            metrics: super::DaMetrics::global("test".to_string()),
             need_catchup: false,
             catchup_task: None,
             catchup_height: None,
+            catchup_checkpoint: Default::default(),
+            stream_request_receiver: None,
+            disk_usage: None,
+            disk_usage_level: Default::default(),
         };
 
         let mut block = SignedBlock::default();
@@ -664,6 +1990,12 @@ pub mod tests {
             .unwrap();
 
         // TODO: figure out why writing doesn't work with da_stream.
+        stream.write_u32(7).await.unwrap();
+        stream.write_all(b"hello:").await.unwrap();
+        stream
+            .write_u8(super::codec::DA_PROTOCOL_VERSION)
+            .await
+            .unwrap();
         stream.write_u32(8).await.unwrap();
         stream.write_u64(0).await.unwrap();
 
@@ -672,11 +2004,16 @@ pub mod tests {
         let mut heights_received = vec![];
         while let Some(Ok(cmd)) = da_stream.next().await {
             let bytes = cmd;
-            let block: SignedBlock =
+            let DataAvailabilityEvent::Block(envelope) =
                 bincode::decode_from_slice(&bytes, bincode::config::standard())
                     .unwrap()
-                    .0;
-            heights_received.push(block.height().0);
+                    .0
+            else {
+                // Handshake answer
+                continue;
+            };
+            // The envelope tells the height without decoding the block
+            heights_received.push(envelope.height.0);
             if heights_received.len() == 14 {
                 break;
             }
@@ -686,7 +2023,7 @@ pub mod tests {
         da_stream.close().await.unwrap();
 
         let mut ccp = CommittedConsensusProposal {
-            staking: Staking::default(),
+            certificates: Default::default(),
             consensus_proposal: ConsensusProposal::default(),
             certificate: AggregateSignature {
                 signature: crate::model::Signature("signature".into()),
@@ -713,6 +2050,12 @@ pub mod tests {
             .unwrap();
 
         // TODO: figure out why writing doesn't work with da_stream.
+        stream.write_u32(7).await.unwrap();
+        stream.write_all(b"hello:").await.unwrap();
+        stream
+            .write_u8(super::codec::DA_PROTOCOL_VERSION)
+            .await
+            .unwrap();
         stream.write_u32(8).await.unwrap();
         stream.write_u64(0).await.unwrap();
 
@@ -721,11 +2064,40 @@ pub mod tests {
         let mut heights_received = vec![];
         while let Some(Ok(cmd)) = da_stream.next().await {
             let bytes = cmd;
-            let block: SignedBlock =
+            let DataAvailabilityEvent::Block(envelope) =
+                bincode::decode_from_slice(&bytes, bincode::config::standard())
+                    .unwrap()
+                    .0
+            else {
+                // Handshake answer
+                continue;
+            };
+            heights_received.push(envelope.height.0);
+            if heights_received.len() == 18 {
+                break;
+            }
+        }
+
+        assert_eq!(heights_received, (0..18).collect::<Vec<u64>>());
+
+        // Clients predating the handshake only send their start height, and get bare blocks
+        let mut stream = tokio::net::TcpStream::connect(config.da_address.clone())
+            .await
+            .unwrap();
+        stream.write_u32(8).await.unwrap();
+        stream.write_u64(0).await.unwrap();
+
+        let mut da_stream = Framed::new(stream, LengthDelimitedCodec::new());
+
+        let mut heights_received = vec![];
+        while let Some(Ok(bytes)) = da_stream.next().await {
+            let DataAvailabilityEvent::SignedBlock(block) =
                 bincode::decode_from_slice(&bytes, bincode::config::standard())
                     .unwrap()
-                    .0;
-            dbg!(&block);
+                    .0
+            else {
+                panic!("Legacy clients only get blocks");
+            };
             heights_received.push(block.height().0);
             if heights_received.len() == 18 {
                 break;
@@ -734,6 +2106,41 @@ pub mod tests {
 
         assert_eq!(heights_received, (0..18).collect::<Vec<u64>>());
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_catchup_window() {
+        let mut peer = super::BlockStreamPeer {
+            last_seen: 0,
+            sender: tokio::sync::mpsc::channel(1).0,
+            send_abort: tokio::spawn(async {}),
+            keepalive_abort: tokio::spawn(async {}),
+            headers_only: false,
+            sent_blocks: 32,
+            acked_blocks: Some(0),
+            window: 32,
+            paused_catchup: None,
+        };
+        assert!(peer.window_full());
+
+        // Each acknowledgement doubles the window, up to the configured maximum
+        peer.ack(16, 100);
+        assert_eq!(peer.window, 64);
+        assert!(!peer.window_full());
+        peer.sent_blocks = 80;
+        assert!(peer.window_full());
+        peer.ack(32, 100);
+        assert_eq!(peer.window, 100);
+        assert!(!peer.window_full());
+        // Late acknowledgements don't move the window back
+        peer.ack(16, 100);
+        assert_eq!(peer.acked_blocks, Some(32));
+
+        // Peers without acknowledgements are never paused
+        peer.acked_blocks = None;
+        peer.sent_blocks = 10_000;
+        assert!(!peer.window_full());
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_da_catchup() {
         let sender_global_bus = crate::bus::SharedMessageBus::new(
@@ -793,7 +2200,7 @@ pub mod tests {
 
         // Add a few blocks (via bus to avoid mutex)
         let mut ccp = CommittedConsensusProposal {
-            staking: Staking::default(),
+            certificates: Default::default(),
             consensus_proposal: ConsensusProposal::default(),
             certificate: AggregateSignature::default(),
         };
@@ -829,7 +2236,7 @@ pub mod tests {
 
         // Add a few blocks (via bus to avoid mutex)
         let mut ccp = CommittedConsensusProposal {
-            staking: Staking::default(),
+            certificates: Default::default(),
             consensus_proposal: ConsensusProposal::default(),
             certificate: AggregateSignature::default(),
         };
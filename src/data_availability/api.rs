@@ -0,0 +1,645 @@
+use std::{net::SocketAddr, path::PathBuf, sync::Arc, time::Duration};
+
+use anyhow::{anyhow, Result};
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        ConnectInfo, Path, Query as QueryParams, State,
+    },
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use hyle_model::{api::APITxInclusionProof, errors::ErrorCode};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tracing::{debug, error, info, warn};
+use utoipa::{OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    bus::{
+        bus_client,
+        command_response::{CmdRespClient, Query},
+        metrics::BusMetrics,
+    },
+    model::{BlockHeight, CommonRunContext, SignedBlock, TxHash},
+    rest::AppError,
+    utils::ws_limits::{WsConnectionLimiter, WsConnectionPermit},
+};
+
+use super::snapshot::{snapshot_path, SnapshotWriter};
+
+/// Feeds the blocks of the named snapshot file to the DA module. Answers the number of blocks read.
+#[derive(Clone)]
+pub struct QueryDaSnapshotImport(pub String);
+
+/// Maximum time an import waits for the DA module to take all the blocks of the snapshot
+const SNAPSHOT_IMPORT_TIMEOUT: Duration = Duration::from_secs(3600);
+
+/// Stored blocks from the given height, at most [MAX_BLOCKS_PER_QUERY] of them.
+#[derive(Clone)]
+pub struct QueryDaBlocks(pub BlockHeight);
+
+pub const MAX_BLOCKS_PER_QUERY: u64 = 100;
+
+/// Stored blocks from `from` to `to` excluded, at most [MAX_BLOCKS_PER_QUERY] of them.
+#[derive(Clone)]
+pub struct QueryDaBlockRange {
+    pub from: BlockHeight,
+    pub to: BlockHeight,
+}
+
+/// Height of the last stored block, if any.
+#[derive(Clone)]
+pub struct QueryDaLastHeight;
+
+/// Bytes the block store takes on disk, as last measured.
+#[derive(Clone)]
+pub struct QueryDaDiskUsage;
+
+/// Merkle proof of the inclusion of a transaction in the first stored block including it.
+#[derive(Clone)]
+pub struct QueryDaTxProof(pub TxHash);
+
+/// Peers the DA module is streaming blocks to.
+#[derive(Clone)]
+pub struct QueryDaPeers;
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DaPeerInfo {
+    pub address: String,
+    /// Timestamp (in seconds) of the last ping received from the peer
+    pub last_seen: u64,
+    /// Blocks and pongs waiting to be sent to the peer
+    pub queued_messages: usize,
+}
+
+bus_client! {
+struct RestBusClient {
+    sender(Query<QueryDaSnapshotImport, u64>),
+    sender(Query<QueryDaLastHeight, Option<BlockHeight>>),
+    sender(Query<QueryDaBlocks, Vec<SignedBlock>>),
+    sender(Query<QueryDaBlockRange, Vec<SignedBlock>>),
+    sender(Query<QueryDaPeers, Vec<DaPeerInfo>>),
+    sender(Query<QueryDaTxProof, APITxInclusionProof>),
+}
+}
+
+pub struct RouterState {
+    bus: RestBusClient,
+    /// Where snapshots are written and read from
+    data_directory: PathBuf,
+    /// Blocks added to the store, as they come
+    new_blocks: broadcast::Sender<SignedBlock>,
+    ws_limiter: Arc<WsConnectionLimiter>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BlockFrameFormat {
+    /// Text frames, or bodies, holding the JSON-serialized blocks
+    #[default]
+    Json,
+    /// Binary frames, or bodies, holding the blocks encoded as on the DA TCP protocol
+    Bincode,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockStreamParams {
+    /// Height to start streaming stored blocks from. Only new blocks are sent if unset.
+    from: Option<u64>,
+    #[serde(default)]
+    format: BlockFrameFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockParams {
+    #[serde(default)]
+    format: BlockFrameFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlockRangeParams {
+    from: u64,
+    to: Option<u64>,
+    #[serde(default)]
+    format: BlockFrameFormat,
+}
+
+#[derive(OpenApi)]
+struct DataAvailabilityAPI;
+
+pub async fn api(ctx: &CommonRunContext, new_blocks: broadcast::Sender<SignedBlock>) -> Router<()> {
+    let state = RouterState {
+        bus: RestBusClient::new_from_bus(ctx.bus.new_handle()).await,
+        data_directory: ctx.config.data_directory.clone(),
+        new_blocks,
+        ws_limiter: Arc::new(WsConnectionLimiter::new(
+            ctx.config.id.clone(),
+            "da",
+            &ctx.config.websocket,
+        )),
+    };
+
+    let (router, api) = OpenApiRouter::with_openapi(DataAvailabilityAPI::openapi())
+        .routes(routes!(export_snapshot))
+        .routes(routes!(import_snapshot))
+        .routes(routes!(get_peers))
+        .routes(routes!(get_tx_proof))
+        .routes(routes!(get_block))
+        .routes(routes!(get_blocks))
+        .route("/da/blocks/ws", get(get_blocks_ws_handler))
+        .split_for_parts();
+
+    if let Ok(mut o) = ctx.openapi.lock() {
+        *o = o.clone().nest("/v1", api);
+    }
+
+    router.with_state(state)
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/da/snapshot/export/{name}",
+    params(
+        ("name" = String, Path, description = "Snapshot file name, in the data directory's snapshots folder")
+    ),
+    tag = "Data Availability",
+    responses(
+        (status = OK, body = u64)
+    )
+)]
+pub async fn export_snapshot(
+    Path(name): Path<String>,
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    let path = snapshot_path(&state.data_directory, &name)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, e))?;
+    match write_snapshot(&mut state.bus, path.clone()).await {
+        Ok(written) => {
+            info!("📦 Exported {} block(s) to {}", written, path.display());
+            Ok(Json(written))
+        }
+        err => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Error while exporting snapshot {}", name),
+            ))
+        }
+    }
+}
+
+/// Writes the stored blocks to a snapshot file on a blocking task. The blocks are fetched from
+/// the DA module in batches, so that it keeps handling its other messages meanwhile.
+async fn write_snapshot(bus: &mut RestBusClient, path: PathBuf) -> Result<u64> {
+    let (sender, mut receiver) = tokio::sync::mpsc::channel::<Vec<SignedBlock>>(4);
+    let writer = tokio::task::spawn_blocking(move || {
+        let mut writer = SnapshotWriter::create(&path)?;
+        while let Some(blocks) = receiver.blocking_recv() {
+            for block in &blocks {
+                writer.write(block)?;
+            }
+        }
+        writer.finish()
+    });
+
+    if let Some(last) = bus.request(QueryDaLastHeight).await? {
+        let mut from = BlockHeight(0);
+        while from.0 <= last.0 {
+            let to = from + MAX_BLOCKS_PER_QUERY;
+            let blocks = bus.request(QueryDaBlockRange { from, to }).await?;
+            // If the writer failed, its error is returned below
+            if !blocks.is_empty() && sender.send(blocks).await.is_err() {
+                break;
+            }
+            from = to;
+        }
+    }
+    drop(sender);
+    writer.await?
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/da/snapshot/import/{name}",
+    params(
+        ("name" = String, Path, description = "Snapshot file name, in the data directory's snapshots folder")
+    ),
+    tag = "Data Availability",
+    responses(
+        (status = OK, body = u64)
+    )
+)]
+pub async fn import_snapshot(
+    Path(name): Path<String>,
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    match state
+        .bus
+        .request_with_timeout(QueryDaSnapshotImport(name.clone()), SNAPSHOT_IMPORT_TIMEOUT)
+        .await
+    {
+        Ok(blocks) => Ok(Json(blocks)),
+        err => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Error while importing snapshot {}", name),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/da/peers",
+    tag = "Data Availability",
+    responses(
+        (status = OK, body = [DaPeerInfo])
+    )
+)]
+pub async fn get_peers(
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    match state.bus.request(QueryDaPeers).await {
+        Ok(peers) => Ok(Json(peers)),
+        err => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Error while getting DA peers"),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/da/proof/tx/{hash}",
+    params(
+        ("hash" = String, Path, description = "Tx hash")
+    ),
+    tag = "Data Availability",
+    responses(
+        (status = OK, body = APITxInclusionProof)
+    )
+)]
+pub async fn get_tx_proof(
+    Path(hash): Path<TxHash>,
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    let hash_clone = hash.clone();
+    match state.bus.request(QueryDaTxProof(hash)).await {
+        Ok(proof) => Ok(Json(proof)),
+        err => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::NOT_FOUND,
+                anyhow!("Error while getting inclusion proof of tx {}", hash_clone),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/da/block/{height}",
+    params(
+        ("height" = u64, Path, description = "Block height"),
+        ("format" = Option<String>, Query, description = "`json` (default) or `bincode`, as on the DA TCP protocol")
+    ),
+    tag = "Data Availability",
+    responses(
+        (status = OK, description = "Stored block"),
+        (status = NOT_FOUND, description = "No block stored at this height")
+    )
+)]
+pub async fn get_block(
+    Path(height): Path<u64>,
+    QueryParams(params): QueryParams<BlockParams>,
+    State(mut state): State<RouterState>,
+) -> Result<Response, AppError> {
+    let blocks = read_blocks(
+        &mut state,
+        BlockHeight(height),
+        BlockHeight(height.saturating_add(1)),
+    )
+    .await?;
+    let Some(block) = blocks.into_iter().next() else {
+        return Err(AppError::with_code(
+            ErrorCode::NotFound,
+            format!("No block stored at height {}", height),
+        ));
+    };
+    encode_blocks(&block, params.format)
+}
+
+#[utoipa::path(
+    get,
+    path = "/da/blocks",
+    params(
+        ("from" = u64, Query, description = "Height of the first block"),
+        ("to" = Option<u64>, Query, description = "Height after the last block"),
+        ("format" = Option<String>, Query, description = "`json` (default) or `bincode`, as on the DA TCP protocol")
+    ),
+    tag = "Data Availability",
+    responses(
+        (status = OK, description = "Stored blocks of the range, at most 100 of them: the next ones start after the last height returned"),
+        (status = BAD_REQUEST, description = "Empty range")
+    )
+)]
+pub async fn get_blocks(
+    QueryParams(params): QueryParams<BlockRangeParams>,
+    State(mut state): State<RouterState>,
+) -> Result<Response, AppError> {
+    let to = params
+        .to
+        .unwrap_or(params.from.saturating_add(MAX_BLOCKS_PER_QUERY));
+    if to <= params.from {
+        return Err(AppError::with_code(
+            ErrorCode::BadRequest,
+            format!("Empty range from {} to {}", params.from, to),
+        ));
+    }
+    let blocks = read_blocks(&mut state, BlockHeight(params.from), BlockHeight(to)).await?;
+    encode_blocks(&blocks, params.format)
+}
+
+async fn read_blocks(
+    state: &mut RouterState,
+    from: BlockHeight,
+    to: BlockHeight,
+) -> Result<Vec<SignedBlock>, AppError> {
+    state
+        .bus
+        .request(QueryDaBlockRange { from, to })
+        .await
+        .map_err(|e| {
+            error!(
+                "Error while reading blocks from {} to {}: {:#}",
+                from, to, e
+            );
+            AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Error while reading blocks"),
+            )
+        })
+}
+
+fn encode_blocks<T>(blocks: &T, format: BlockFrameFormat) -> Result<Response, AppError>
+where
+    T: Serialize + bincode::Encode,
+{
+    Ok(match format {
+        BlockFrameFormat::Json => Json(blocks).into_response(),
+        BlockFrameFormat::Bincode => (
+            [(header::CONTENT_TYPE, "application/octet-stream")],
+            bincode::encode_to_vec(blocks, bincode::config::standard())?,
+        )
+            .into_response(),
+    })
+}
+
+/// Streams blocks over a websocket, starting with stored ones when `from` is set.
+/// Clients that can't keep up are disconnected, and can reconnect from their last height.
+async fn get_blocks_ws_handler(
+    ws: WebSocketUpgrade,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    QueryParams(params): QueryParams<BlockStreamParams>,
+    State(state): State<RouterState>,
+) -> impl IntoResponse {
+    let permit = match state.ws_limiter.try_acquire(addr.ip()) {
+        Ok(permit) => permit,
+        Err(e) => {
+            warn!("Rejecting websocket connection from {}: {:?}", addr, e);
+            return AppError::with_code(
+                ErrorCode::TooManyConnections,
+                format!("Websocket connection refused: {:?}", e),
+            )
+            .into_response();
+        }
+    };
+    // Subscribe before reading stored blocks so that none is missed in between
+    let new_blocks = state.new_blocks.subscribe();
+    ws.on_upgrade(move |socket| stream_blocks(socket, state, params, new_blocks, permit))
+}
+
+async fn stream_blocks(
+    mut socket: WebSocket,
+    mut state: RouterState,
+    params: BlockStreamParams,
+    mut new_blocks: broadcast::Receiver<SignedBlock>,
+    _permit: WsConnectionPermit,
+) {
+    let close = |code: ErrorCode, reason: &str| {
+        Message::Close(Some(CloseFrame {
+            code: code.ws_close_code(),
+            reason: reason.to_string().into(),
+        }))
+    };
+
+    // Height of the next block to send, if we started from stored blocks
+    let mut next_height = params.from.map(BlockHeight);
+    while let Some(height) = next_height {
+        let blocks = match state.bus.request(QueryDaBlocks(height)).await {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                error!("Error while reading blocks from {}: {:#}", height, e);
+                _ = socket
+                    .send(close(ErrorCode::Internal, "Error while reading blocks"))
+                    .await;
+                return;
+            }
+        };
+        let Some(last) = blocks.last().map(|b| b.height()) else {
+            break;
+        };
+        for block in blocks {
+            if send_block(&mut socket, &block, params.format)
+                .await
+                .is_err()
+            {
+                return;
+            }
+        }
+        next_height = Some(last + 1);
+    }
+
+    loop {
+        match new_blocks.recv().await {
+            Ok(block) => {
+                if next_height.is_some_and(|h| block.height().0 < h.0) {
+                    continue;
+                }
+                if send_block(&mut socket, &block, params.format)
+                    .await
+                    .is_err()
+                {
+                    return;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                debug!("Block websocket lagged by {} blocks, disconnecting", n);
+                _ = socket
+                    .send(close(
+                        ErrorCode::SubscriptionQueueFull,
+                        "Lagging behind, reconnect from your last height",
+                    ))
+                    .await;
+                return;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        }
+    }
+}
+
+async fn send_block(
+    socket: &mut WebSocket,
+    block: &SignedBlock,
+    format: BlockFrameFormat,
+) -> Result<()> {
+    let message = match format {
+        BlockFrameFormat::Json => Message::Text(serde_json::to_string(block)?.into()),
+        BlockFrameFormat::Bincode => {
+            Message::Binary(bincode::encode_to_vec(block, bincode::config::standard())?.into())
+        }
+    };
+    socket.send(message).await?;
+    Ok(())
+}
+
+impl Clone for RouterState {
+    fn clone(&self) -> Self {
+        use crate::utils::static_type_map::Pick;
+        Self {
+            bus: RestBusClient::new(
+                Pick::<BusMetrics>::get(&self.bus).clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<QueryDaSnapshotImport, u64>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<QueryDaLastHeight, Option<BlockHeight>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<QueryDaBlocks, Vec<SignedBlock>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<QueryDaBlockRange, Vec<SignedBlock>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<QueryDaPeers, Vec<DaPeerInfo>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<tokio::sync::broadcast::Sender<Query<QueryDaTxProof, APITxInclusionProof>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+            ),
+            data_directory: self.data_directory.clone(),
+            new_blocks: self.new_blocks.clone(),
+            ws_limiter: self.ws_limiter.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{future::IntoFuture, net::Ipv4Addr, time::Duration};
+
+    use futures::StreamExt;
+    use tokio_tungstenite::tungstenite::{self, Message};
+
+    use super::*;
+    use crate::{bus::SharedMessageBus, model::Hashable, utils::conf::WebSocketConf};
+
+    /// Serves the block websocket on a local port, accepting a single connection.
+    async fn serve_blocks_ws(new_blocks: broadcast::Sender<SignedBlock>) -> SocketAddr {
+        let state = RouterState {
+            bus: RestBusClient::new_from_bus(SharedMessageBus::default()).await,
+            data_directory: PathBuf::new(),
+            new_blocks,
+            ws_limiter: Arc::new(WsConnectionLimiter::new(
+                "test".to_string(),
+                "da",
+                &WebSocketConf {
+                    max_connections: 1,
+                    max_connections_per_ip: 1,
+                },
+            )),
+        };
+        let router = Router::new()
+            .route("/da/blocks/ws", get(get_blocks_ws_handler))
+            .with_state(state);
+        let listener = tokio::net::TcpListener::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+            .await
+            .unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::serve(
+                listener,
+                router.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .into_future(),
+        );
+        addr
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_blocks_ws() -> Result<()> {
+        let new_blocks = broadcast::channel(100).0;
+        let addr = serve_blocks_ws(new_blocks.clone()).await;
+        let url = format!("ws://{addr}/da/blocks/ws?format=bincode");
+        let (mut ws, _) = tokio_tungstenite::connect_async(&url).await?;
+
+        // Connections over the limit are refused before the upgrade
+        assert!(matches!(
+            tokio_tungstenite::connect_async(&url).await,
+            Err(tungstenite::Error::Http(response)) if response.status() == 429
+        ));
+
+        let mut block = SignedBlock::default();
+        block.consensus_proposal.slot = 1;
+        new_blocks.send(block.clone())?;
+        let message = ws.next().await.expect("block")?;
+        let (received, _): (SignedBlock, _) =
+            bincode::decode_from_slice(&message.into_data(), bincode::config::standard())?;
+        assert_eq!(received.hash(), block.hash());
+
+        // Clients that can't keep up are disconnected
+        for _ in 0..=100 {
+            new_blocks.send(block.clone())?;
+        }
+        let Some(Ok(Message::Close(Some(frame)))) = ws.next().await else {
+            panic!("Expected the lagging client to be disconnected");
+        };
+        assert_eq!(
+            ErrorCode::from_ws_close_code(frame.code.into()),
+            Some(ErrorCode::SubscriptionQueueFull)
+        );
+
+        // Which releases its slot
+        let (_ws, _) = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                match tokio_tungstenite::connect_async(&url).await {
+                    Err(tungstenite::Error::Http(_)) => {
+                        tokio::time::sleep(Duration::from_millis(10)).await
+                    }
+                    res => return res,
+                }
+            }
+        })
+        .await??;
+        Ok(())
+    }
+}
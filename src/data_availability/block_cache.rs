@@ -0,0 +1,111 @@
+//! LRU cache of recently accessed blocks, so that streaming the same recent range
+//! to several peers doesn't decode it from disk each time.
+
+use std::{collections::HashMap, num::NonZeroUsize};
+
+use lru::LruCache;
+
+use super::metrics::BlockCacheMetrics;
+use crate::model::{BlockHeight, ConsensusProposalHash, SignedBlock};
+
+#[derive(Debug)]
+pub struct BlockCache {
+    /// None when the cache is disabled
+    blocks: Option<LruCache<ConsensusProposalHash, SignedBlock>>,
+    hashes_by_height: HashMap<BlockHeight, ConsensusProposalHash>,
+    metrics: BlockCacheMetrics,
+}
+
+impl BlockCache {
+    /// A cache holding at most `capacity` blocks. 0 disables caching.
+    pub fn new(capacity: usize, node_name: String) -> Self {
+        Self {
+            blocks: NonZeroUsize::new(capacity).map(LruCache::new),
+            hashes_by_height: HashMap::new(),
+            metrics: BlockCacheMetrics::global(node_name),
+        }
+    }
+
+    pub fn get(&mut self, hash: &ConsensusProposalHash) -> Option<SignedBlock> {
+        let blocks = self.blocks.as_mut()?;
+        let block = blocks.get(hash).cloned();
+        self.record(block.is_some());
+        block
+    }
+
+    pub fn get_by_height(&mut self, height: BlockHeight) -> Option<SignedBlock> {
+        let blocks = self.blocks.as_mut()?;
+        let block = self
+            .hashes_by_height
+            .get(&height)
+            .and_then(|hash| blocks.get(hash))
+            .cloned();
+        self.record(block.is_some());
+        block
+    }
+
+    /// Like [BlockCache::get_by_height], without updating recency.
+    pub fn peek_by_height(&self, height: BlockHeight) -> Option<SignedBlock> {
+        let blocks = self.blocks.as_ref()?;
+        let block = self
+            .hashes_by_height
+            .get(&height)
+            .and_then(|hash| blocks.peek(hash))
+            .cloned();
+        self.record(block.is_some());
+        block
+    }
+
+    pub fn insert(&mut self, hash: ConsensusProposalHash, block: SignedBlock) {
+        let Some(blocks) = self.blocks.as_mut() else {
+            return;
+        };
+        self.hashes_by_height.insert(block.height(), hash.clone());
+        if let Some((evicted_hash, evicted)) = blocks.push(hash, block) {
+            if self.hashes_by_height.get(&evicted.height()) == Some(&evicted_hash) {
+                self.hashes_by_height.remove(&evicted.height());
+            }
+        }
+    }
+
+    fn record(&self, hit: bool) {
+        if hit {
+            self.metrics.hit();
+        } else {
+            self.metrics.miss();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(slot: u64) -> SignedBlock {
+        let mut block = SignedBlock::default();
+        block.consensus_proposal.slot = slot;
+        block
+    }
+
+    #[test]
+    fn test_block_cache_eviction() {
+        let mut cache = BlockCache::new(2, "test".to_string());
+        for slot in 0..3 {
+            cache.insert(ConsensusProposalHash(format!("block{slot}")), block(slot));
+        }
+
+        assert!(cache.get_by_height(BlockHeight(0)).is_none());
+        assert!(cache
+            .get(&ConsensusProposalHash("block0".to_string()))
+            .is_none());
+        assert_eq!(
+            cache.get_by_height(BlockHeight(2)).map(|b| b.height()),
+            Some(BlockHeight(2))
+        );
+        assert_eq!(cache.hashes_by_height.len(), 2);
+
+        let mut disabled = BlockCache::new(0, "test".to_string());
+        disabled.insert(ConsensusProposalHash("block0".to_string()), block(0));
+        assert!(disabled.get_by_height(BlockHeight(0)).is_none());
+    }
+}
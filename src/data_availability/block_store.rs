@@ -0,0 +1,103 @@
+//! Storage backends for the blocks held by the data availability module.
+
+use std::{fmt::Debug, path::Path};
+
+use anyhow::Result;
+
+use super::{
+    block_cache::BlockCache,
+    blocks_fjall, blocks_memory,
+    codec::TransactionInclusion,
+    snapshot::{SnapshotReader, SnapshotWriter},
+};
+use crate::{
+    model::{BlockHeight, ConsensusProposalHash, Hashable, SignedBlock, TxHash},
+    utils::conf::DaStorage,
+};
+
+pub trait BlockStore: Debug + Send {
+    fn is_empty(&self) -> bool;
+    fn persist(&self) -> Result<()>;
+    fn put(&mut self, block: SignedBlock) -> Result<()>;
+    fn get(&mut self, block_hash: &ConsensusProposalHash) -> Result<Option<SignedBlock>>;
+    fn contains(&mut self, block_hash: &ConsensusProposalHash) -> bool;
+    fn last(&self) -> Option<SignedBlock>;
+    fn last_block_hash(&self) -> Option<ConsensusProposalHash>;
+    /// Bytes the store takes on disk, None for stores that aren't on disk.
+    fn disk_usage(&self) -> Option<u64> {
+        None
+    }
+    /// Blocks with a height in `[min, max)`, in order.
+    fn range(
+        &mut self,
+        min: BlockHeight,
+        max: BlockHeight,
+    ) -> Box<dyn Iterator<Item = Result<SignedBlock>> + '_>;
+
+    /// First stored block including a transaction, with the transaction.
+    /// Stores without an index of the transactions go through all the blocks.
+    fn get_transaction(&mut self, tx_hash: &TxHash) -> Result<Option<TransactionInclusion>> {
+        let Some(last) = self.last() else {
+            return Ok(None);
+        };
+        for block in self.range(BlockHeight(0), last.height() + 1) {
+            let block = block?;
+            if let Some(index) = block.txs().iter().position(|tx| &tx.hash() == tx_hash) {
+                return Ok(transaction_inclusion(block, index));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Writes all blocks, in height order, to a snapshot file. Returns the number of blocks written.
+    fn export_snapshot(&mut self, path: &Path) -> Result<u64> {
+        let mut writer = SnapshotWriter::create(path)?;
+        let Some(last) = self.last() else {
+            return writer.finish();
+        };
+        for block in self.range(BlockHeight(0), last.height() + 1) {
+            writer.write(&block?)?;
+        }
+        writer.finish()
+    }
+
+    /// Stores all blocks of a snapshot file as-is, e.g. before the node starts.
+    /// Returns the number of blocks read.
+    fn import_snapshot(&mut self, path: &Path) -> Result<u64> {
+        let mut read = 0;
+        for block in SnapshotReader::open(path)? {
+            self.put(block?)?;
+            read += 1;
+        }
+        self.persist()?;
+        Ok(read)
+    }
+}
+
+/// The transaction at `index` in a block, and where it is.
+pub fn transaction_inclusion(block: SignedBlock, index: usize) -> Option<TransactionInclusion> {
+    let tx = block.txs().into_iter().nth(index)?;
+    Some(TransactionInclusion {
+        tx,
+        block_hash: block.hash(),
+        block_height: block.height(),
+        index: u32::try_from(index).ok()?,
+    })
+}
+
+/// Open the block store selected in the configuration.
+/// The cache is only used by on-disk stores that decode blocks on every read.
+pub fn open_block_store(
+    storage: DaStorage,
+    path: &Path,
+    cache: BlockCache,
+) -> Result<Box<dyn BlockStore>> {
+    Ok(match storage {
+        DaStorage::Fjall => Box::new(blocks_fjall::Blocks::new(path, cache)?),
+        DaStorage::Memory => Box::new(blocks_memory::Blocks::new(path)?),
+        #[cfg(feature = "rocksdb")]
+        DaStorage::Rocksdb => Box::new(super::blocks_rocksdb::Blocks::new(path)?),
+        #[cfg(not(feature = "rocksdb"))]
+        DaStorage::Rocksdb => anyhow::bail!("RocksDB storage requires the `rocksdb` feature"),
+    })
+}
@@ -3,12 +3,17 @@ use fjall::{Config, Keyspace, PartitionCreateOptions, PartitionHandle, Slice};
 use std::{fmt::Debug, path::Path, sync::Arc};
 use tracing::{error, info, trace};
 
+use super::{
+    block_cache::BlockCache, block_store::transaction_inclusion, codec::TransactionInclusion,
+    BlockStore,
+};
 use crate::{
     model::ConsensusProposalHash,
-    model::{BlockHeight, Hashable, SignedBlock},
+    model::{BlockHeight, Hashable, SignedBlock, TxHash},
 };
 
 struct FjallHashKey(ConsensusProposalHash);
+struct FjallTxKey(TxHash);
 struct FjallHeightKey([u8; 8]);
 struct FjallValue(Vec<u8>);
 
@@ -18,6 +23,12 @@ impl AsRef<[u8]> for FjallHashKey {
     }
 }
 
+impl AsRef<[u8]> for FjallTxKey {
+    fn as_ref(&self) -> &[u8] {
+        self.0 .0.as_bytes()
+    }
+}
+
 impl FjallHeightKey {
     fn new(height: BlockHeight) -> Self {
         Self(height.0.to_be_bytes())
@@ -49,6 +60,9 @@ pub struct Blocks {
     db: Keyspace,
     by_hash: PartitionHandle,
     by_height: PartitionHandle,
+    /// Hash of the block including each transaction, and the position of the transaction in it
+    tx_positions: PartitionHandle,
+    cache: BlockCache,
 }
 
 impl Blocks {
@@ -58,7 +72,41 @@ impl Blocks {
             .map_err(Into::into)
     }
 
-    pub fn new(path: &Path) -> Result<Self> {
+    fn key_height(key: &Slice) -> Option<BlockHeight> {
+        <[u8; 8]>::try_from(key.as_ref())
+            .ok()
+            .map(|k| BlockHeight(u64::from_be_bytes(k)))
+    }
+
+    /// Decodes a block stored by height, unless it is cached.
+    fn cached_by_height(cache: &mut BlockCache, key: Slice, item: Slice) -> Result<SignedBlock> {
+        let height = Self::key_height(&key);
+        if let Some(block) = height.and_then(|h| cache.get_by_height(h)) {
+            return Ok(block);
+        }
+        let block = Self::decode_item(item)?;
+        cache.insert(block.hash(), block.clone());
+        Ok(block)
+    }
+
+    /// Indexes the transactions of a block, keeping the first block including each of them.
+    fn index_transactions(tx_positions: &PartitionHandle, block: &SignedBlock) -> Result<()> {
+        let block_hash = block.hash();
+        for (index, tx) in block.txs().iter().enumerate() {
+            let key = FjallTxKey(tx.hash());
+            if tx_positions.contains_key(&key)? {
+                continue;
+            }
+            let position = bincode::encode_to_vec(
+                (&block_hash, u32::try_from(index)?),
+                bincode::config::standard(),
+            )?;
+            tx_positions.insert(key, position)?;
+        }
+        Ok(())
+    }
+
+    pub fn new(path: &Path, cache: BlockCache) -> Result<Self> {
         let db = Config::new(path)
             .blob_cache(Arc::new(fjall::BlobCache::with_capacity_bytes(
                 128 * 1024 * 1024,
@@ -76,27 +124,46 @@ impl Blocks {
         )?;
         let by_height =
             db.open_partition("block_hashes_by_height", PartitionCreateOptions::default())?;
+        let tx_positions =
+            db.open_partition("tx_positions_by_hash", PartitionCreateOptions::default())?;
 
         info!("{} block(s) available", by_hash.len()?);
 
+        // Stores written before transactions were indexed
+        if tx_positions.is_empty()? && !by_height.is_empty()? {
+            info!("Indexing the transactions of stored blocks");
+            for item in by_height.iter() {
+                let (_, value) = item?;
+                Self::index_transactions(&tx_positions, &Self::decode_item(value)?)?;
+            }
+        }
+
         Ok(Blocks {
             db,
             by_hash,
             by_height,
+            tx_positions,
+            cache,
         })
     }
+}
 
-    pub fn is_empty(&self) -> bool {
+impl BlockStore for Blocks {
+    fn is_empty(&self) -> bool {
         self.by_hash.is_empty().unwrap_or(true)
     }
 
-    pub fn persist(&self) -> Result<()> {
+    fn persist(&self) -> Result<()> {
         self.db
             .persist(fjall::PersistMode::Buffer)
             .map_err(Into::into)
     }
 
-    pub fn put(&mut self, block: SignedBlock) -> Result<()> {
+    fn disk_usage(&self) -> Option<u64> {
+        Some(self.db.disk_space())
+    }
+
+    fn put(&mut self, block: SignedBlock) -> Result<()> {
         let block_hash = block.hash();
         if self.contains(&block_hash) {
             return Ok(());
@@ -110,23 +177,47 @@ impl Blocks {
             FjallHeightKey::new(block.height()).as_ref(),
             FjallValue::new(&block)?.as_ref(),
         )?;
+        Self::index_transactions(&self.tx_positions, &block)?;
+        // New blocks are streamed to peers right away
+        self.cache.insert(block_hash, block);
         Ok(())
     }
 
-    pub fn get(&mut self, block_hash: &ConsensusProposalHash) -> Result<Option<SignedBlock>> {
+    fn get(&mut self, block_hash: &ConsensusProposalHash) -> Result<Option<SignedBlock>> {
+        if let Some(block) = self.cache.get(block_hash) {
+            return Ok(Some(block));
+        }
         let item = self.by_hash.get(FjallHashKey(block_hash.clone()))?;
-        item.map(Self::decode_item).transpose()
+        let block = item.map(Self::decode_item).transpose()?;
+        if let Some(block) = &block {
+            self.cache.insert(block_hash.clone(), block.clone());
+        }
+        Ok(block)
+    }
+
+    fn get_transaction(&mut self, tx_hash: &TxHash) -> Result<Option<TransactionInclusion>> {
+        let Some(position) = self.tx_positions.get(FjallTxKey(tx_hash.clone()))? else {
+            return Ok(None);
+        };
+        let ((block_hash, index), _): ((ConsensusProposalHash, u32), _) =
+            bincode::decode_from_slice(&position, bincode::config::standard())?;
+        let Some(block) = self.get(&block_hash)? else {
+            return Ok(None);
+        };
+        Ok(transaction_inclusion(block, usize::try_from(index)?))
     }
 
-    pub fn contains(&mut self, block: &ConsensusProposalHash) -> bool {
+    fn contains(&mut self, block: &ConsensusProposalHash) -> bool {
         self.by_hash
             .contains_key(FjallHashKey(block.clone()))
             .unwrap_or(false)
     }
 
-    pub fn last(&self) -> Option<SignedBlock> {
+    fn last(&self) -> Option<SignedBlock> {
         match self.by_height.last_key_value() {
-            Ok(Some((_, v))) => Self::decode_item(v).ok(),
+            Ok(Some((k, v))) => Self::key_height(&k)
+                .and_then(|h| self.cache.peek_by_height(h))
+                .or_else(|| Self::decode_item(v).ok()),
             Ok(None) => None,
             Err(e) => {
                 error!("Error getting last block: {:?}", e);
@@ -135,21 +226,24 @@ impl Blocks {
         }
     }
 
-    pub fn last_block_hash(&self) -> Option<ConsensusProposalHash> {
+    fn last_block_hash(&self) -> Option<ConsensusProposalHash> {
         self.last().map(|b| b.hash())
     }
 
-    pub fn range(
+    fn range(
         &mut self,
         min: BlockHeight,
         max: BlockHeight,
-    ) -> impl Iterator<Item = Result<SignedBlock>> {
-        self.by_height
-            .range(FjallHeightKey::new(min)..FjallHeightKey::new(max))
-            .map_while(|maybe_item| match maybe_item {
-                Ok((_, v)) => Some(Self::decode_item(v).map_err(Into::into)),
-                Err(_) => None,
-            })
+    ) -> Box<dyn Iterator<Item = Result<SignedBlock>> + '_> {
+        let cache = &mut self.cache;
+        Box::new(
+            self.by_height
+                .range(FjallHeightKey::new(min)..FjallHeightKey::new(max))
+                .map_while(move |maybe_item| match maybe_item {
+                    Ok((k, v)) => Some(Self::cached_by_height(cache, k, v)),
+                    Err(_) => None,
+                }),
+        )
     }
 }
 
@@ -1,13 +1,13 @@
-#![allow(unused)]
 use std::path::Path;
 
+use super::BlockStore;
 use crate::{
     model::ConsensusProposalHash,
     model::{BlockHeight, Hashable, SignedBlock},
 };
 use anyhow::Result;
 use indexmap::IndexMap;
-use tracing::{info, trace};
+use tracing::trace;
 
 #[derive(Debug)]
 pub struct Blocks {
@@ -20,16 +20,18 @@ impl Blocks {
             data: IndexMap::new(),
         })
     }
+}
 
-    pub fn is_empty(&self) -> bool {
+impl BlockStore for Blocks {
+    fn is_empty(&self) -> bool {
         self.data.is_empty()
     }
 
-    pub fn persist(&self) -> Result<()> {
+    fn persist(&self) -> Result<()> {
         Ok(())
     }
 
-    pub fn put(&mut self, data: SignedBlock) -> Result<()> {
+    fn put(&mut self, data: SignedBlock) -> Result<()> {
         let block_hash = data.hash();
         if self.contains(&block_hash) {
             return Ok(());
@@ -39,41 +41,35 @@ impl Blocks {
         Ok(())
     }
 
-    pub fn get(&mut self, block_hash: &ConsensusProposalHash) -> Result<Option<SignedBlock>> {
+    fn get(&mut self, block_hash: &ConsensusProposalHash) -> Result<Option<SignedBlock>> {
         Ok(self.data.get(block_hash).cloned())
     }
 
-    pub fn contains(&mut self, block_hash: &ConsensusProposalHash) -> bool {
+    fn contains(&mut self, block_hash: &ConsensusProposalHash) -> bool {
         self.data.contains_key(block_hash)
     }
 
-    pub fn last(&self) -> Option<SignedBlock> {
+    fn last(&self) -> Option<SignedBlock> {
         self.data.last().map(|(_, block)| block.clone())
     }
 
-    pub fn last_block_hash(&self) -> Option<ConsensusProposalHash> {
+    fn last_block_hash(&self) -> Option<ConsensusProposalHash> {
         self.last().map(|b| b.hash())
     }
 
-    pub fn range(
-        &self,
+    fn range(
+        &mut self,
         min: BlockHeight,
         max: BlockHeight,
     ) -> Box<dyn Iterator<Item = Result<SignedBlock>> + '_> {
-        // Items are in order but we don't know wher they are. Binary search.
-        let Ok(min) = self
+        // Blocks are stored in height order.
+        let start = self
             .data
-            .binary_search_by(|_, block| block.height().0.cmp(&min.0))
-        else {
-            return Box::new(::std::iter::empty());
-        };
-        let Ok(max) = self
+            .partition_point(|_, block| block.height().0 < min.0);
+        let end = self
             .data
-            .binary_search_by(|_, block| block.height().0.cmp(&(max.0 - 1)))
-        else {
-            return Box::new(::std::iter::empty());
-        };
-        let Some(iter) = self.data.get_range(min..max + 1) else {
+            .partition_point(|_, block| block.height().0 < max.0);
+        let Some(iter) = self.data.get_range(start..end.max(start)) else {
             return Box::new(::std::iter::empty());
         };
         Box::new(iter.values().map(|block| Ok(block.clone())))
@@ -0,0 +1,180 @@
+use anyhow::{Context, Result};
+use rocksdb::{ColumnFamily, ColumnFamilyDescriptor, Direction, IteratorMode, Options, DB};
+use std::{fmt::Debug, path::Path};
+use tracing::{error, info, trace};
+
+use super::BlockStore;
+use crate::{
+    model::ConsensusProposalHash,
+    model::{BlockHeight, Hashable, SignedBlock},
+};
+
+const BLOCKS_BY_HASH: &str = "blocks_by_hash";
+const BLOCK_HASHES_BY_HEIGHT: &str = "block_hashes_by_height";
+
+fn height_key(height: BlockHeight) -> [u8; 8] {
+    height.0.to_be_bytes()
+}
+
+/// Blocks are stored once, by hash. The by-height column family maps heights to hashes.
+pub struct Blocks {
+    db: DB,
+}
+
+impl Blocks {
+    fn decode_block(item: &[u8]) -> Result<SignedBlock> {
+        bincode::decode_from_slice(item, bincode::config::standard())
+            .map(|(b, _)| b)
+            .map_err(Into::into)
+    }
+
+    fn by_hash(&self) -> Result<&ColumnFamily> {
+        self.db
+            .cf_handle(BLOCKS_BY_HASH)
+            .context("Missing blocks_by_hash column family")
+    }
+
+    fn by_height(&self) -> Result<&ColumnFamily> {
+        self.db
+            .cf_handle(BLOCK_HASHES_BY_HEIGHT)
+            .context("Missing block_hashes_by_height column family")
+    }
+
+    fn get_by_hash_key(&self, hash_key: &[u8]) -> Result<Option<SignedBlock>> {
+        self.db
+            .get_pinned_cf(self.by_hash()?, hash_key)?
+            .map(|v| Self::decode_block(&v))
+            .transpose()
+    }
+
+    pub fn new(path: &Path) -> Result<Self> {
+        let mut opts = Options::default();
+        opts.create_if_missing(true);
+        opts.create_missing_column_families(true);
+
+        let db = DB::open_cf_descriptors(
+            &opts,
+            path,
+            vec![
+                ColumnFamilyDescriptor::new(BLOCKS_BY_HASH, Options::default()),
+                ColumnFamilyDescriptor::new(BLOCK_HASHES_BY_HEIGHT, Options::default()),
+            ],
+        )?;
+
+        let blocks = Blocks { db };
+        info!(
+            "{} block(s) available",
+            blocks.last().map_or(0, |b| b.height().0 + 1)
+        );
+        Ok(blocks)
+    }
+}
+
+impl BlockStore for Blocks {
+    fn is_empty(&self) -> bool {
+        match self.by_height() {
+            Ok(cf) => self
+                .db
+                .iterator_cf(cf, IteratorMode::Start)
+                .next()
+                .is_none(),
+            Err(_) => true,
+        }
+    }
+
+    fn persist(&self) -> Result<()> {
+        self.db.flush_wal(true).map_err(Into::into)
+    }
+
+    fn disk_usage(&self) -> Option<u64> {
+        let mut total = 0;
+        for cf in [self.by_hash().ok()?, self.by_height().ok()?] {
+            total += self
+                .db
+                .property_int_value_cf(cf, "rocksdb.total-sst-files-size")
+                .ok()??;
+        }
+        Some(total)
+    }
+
+    fn put(&mut self, block: SignedBlock) -> Result<()> {
+        let block_hash = block.hash();
+        if self.contains(&block_hash) {
+            return Ok(());
+        }
+        trace!("📦 storing block in rocksdb {}", block.height());
+        let value = bincode::encode_to_vec(&block, bincode::config::standard())?;
+        let mut batch = rocksdb::WriteBatch::default();
+        batch.put_cf(self.by_hash()?, block_hash.0.as_bytes(), value);
+        batch.put_cf(
+            self.by_height()?,
+            height_key(block.height()),
+            block_hash.0.as_bytes(),
+        );
+        self.db.write(batch)?;
+        Ok(())
+    }
+
+    fn get(&mut self, block_hash: &ConsensusProposalHash) -> Result<Option<SignedBlock>> {
+        self.get_by_hash_key(block_hash.0.as_bytes())
+    }
+
+    fn contains(&mut self, block_hash: &ConsensusProposalHash) -> bool {
+        match self.by_hash() {
+            Ok(cf) => matches!(
+                self.db.get_pinned_cf(cf, block_hash.0.as_bytes()),
+                Ok(Some(_))
+            ),
+            Err(_) => false,
+        }
+    }
+
+    fn last(&self) -> Option<SignedBlock> {
+        let cf = self.by_height().ok()?;
+        match self.db.iterator_cf(cf, IteratorMode::End).next() {
+            Some(Ok((_, hash))) => self.get_by_hash_key(&hash).ok().flatten(),
+            Some(Err(e)) => {
+                error!("Error getting last block: {:?}", e);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn last_block_hash(&self) -> Option<ConsensusProposalHash> {
+        self.last().map(|b| b.hash())
+    }
+
+    fn range(
+        &mut self,
+        min: BlockHeight,
+        max: BlockHeight,
+    ) -> Box<dyn Iterator<Item = Result<SignedBlock>> + '_> {
+        let this = &*self;
+        let Ok(cf) = this.by_height() else {
+            return Box::new(::std::iter::empty());
+        };
+        let min_key = height_key(min);
+        let max_key = height_key(max);
+        Box::new(
+            this.db
+                .iterator_cf(cf, IteratorMode::From(&min_key, Direction::Forward))
+                .map_while(move |maybe_item| match maybe_item {
+                    Ok((height, hash)) if height.as_ref() < max_key.as_slice() => Some(hash),
+                    _ => None,
+                })
+                .map(move |hash| {
+                    this.get_by_hash_key(&hash)?
+                        .context("Block indexed by height is missing")
+                }),
+        )
+    }
+}
+
+impl Debug for Blocks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Blocks")
+            .field("path", &self.db.path())
+            .finish()
+    }
+}
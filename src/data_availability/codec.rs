@@ -1,7 +1,10 @@
-use anyhow::Context;
+use anyhow::{bail, Context, Result};
+use bincode::{Decode, Encode};
 use tokio_util::codec::{Decoder, Encoder, LengthDelimitedCodec};
 
-use crate::model::{BlockHeight, SignedBlock};
+use crate::model::{
+    BlockHeight, ConsensusProposalHash, Hashable, SignedBlock, Transaction, TxHash,
+};
 
 // Server Side
 #[derive(Debug)]
@@ -17,10 +20,130 @@ impl Default for DataAvailabilityServerCodec {
     }
 }
 
+/// Version of the DA protocol spoken by this node.
+pub const DA_PROTOCOL_VERSION: u8 = 5;
+/// Oldest version of the DA protocol this node still serves. Clients that don't start with a
+/// [DataAvailabilityServerRequest::Hello] predate the handshake, and are served this version.
+pub const MIN_DA_PROTOCOL_VERSION: u8 = 1;
+
+/// Clients acknowledge the blocks they received every this many blocks, since protocol version 5.
+pub const DA_ACK_INTERVAL: u64 = 16;
+
+/// Version to use with a peer speaking versions up to `version`, if we support any of them.
+pub fn negotiate_version(version: u8) -> Option<u8> {
+    let version = version.min(DA_PROTOCOL_VERSION);
+    (version >= MIN_DA_PROTOCOL_VERSION).then_some(version)
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum DataAvailabilityServerRequest {
+    /// First message of a connection, with the highest protocol version the peer speaks.
+    /// Without it, the peer is served [MIN_DA_PROTOCOL_VERSION].
+    Hello {
+        version: u8,
+    },
     BlockHeight(BlockHeight),
+    /// Keeps the stream alive, the server answers with [DataAvailabilityEvent::Pong].
     Ping,
+    /// Shared token authenticating the peer, sent before the start height.
+    Auth(String),
+    /// Asks for the headers of the blocks from this height instead of the blocks, see
+    /// [DataAvailabilityEvent::HeadersEnd]. Since protocol version 2.
+    Headers(BlockHeight),
+    /// Asks for a single transaction instead of a stream, answered with
+    /// [DataAvailabilityEvent::Transaction]. Since protocol version 3.
+    GetTransaction(TxHash),
+    /// Number of blocks received since the stream started, sent every [DA_ACK_INTERVAL] blocks.
+    /// The server pauses the catchup when too many blocks aren't acknowledged. Since protocol
+    /// version 5.
+    Ack(u64),
+}
+
+const AUTH_PREFIX: &[u8] = b"auth:";
+const HELLO_PREFIX: &[u8] = b"hello:";
+const HEADERS_PREFIX: &[u8] = b"headers:";
+const TRANSACTION_PREFIX: &[u8] = b"tx:";
+const ACK_PREFIX: &[u8] = b"ack:";
+
+/// A transaction and where it was included.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct TransactionInclusion {
+    pub tx: Transaction,
+    pub block_hash: ConsensusProposalHash,
+    pub block_height: BlockHeight,
+    /// Position of the transaction among the transactions of the block
+    pub index: u32,
+}
+
+/// A streamed block, with the metadata needed to skip or check it before decoding the block.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub struct BlockEnvelope {
+    pub height: BlockHeight,
+    pub hash: ConsensusProposalHash,
+    /// bincode encoded [SignedBlock], preceded by its length
+    pub payload: Vec<u8>,
+}
+
+impl BlockEnvelope {
+    pub fn wrap(block: &SignedBlock) -> Result<Self> {
+        Ok(BlockEnvelope {
+            height: block.height(),
+            hash: block.hash(),
+            payload: bincode::encode_to_vec(block, bincode::config::standard())?,
+        })
+    }
+
+    /// Decodes the block, checking it matches the envelope.
+    pub fn open(self) -> Result<SignedBlock> {
+        let (block, read): (SignedBlock, usize) =
+            bincode::decode_from_slice(&self.payload, bincode::config::standard())
+                .context(format!("Decoding block from {} bytes", self.payload.len()))?;
+        if read != self.payload.len() {
+            bail!(
+                "Block {} is {} bytes but its envelope holds {}",
+                self.hash,
+                read,
+                self.payload.len()
+            );
+        }
+        if block.height() != self.height || block.hash() != self.hash {
+            bail!(
+                "Block {} at height {} doesn't match its envelope for {} at height {}",
+                block.hash(),
+                block.height(),
+                self.hash,
+                self.height
+            );
+        }
+        Ok(block)
+    }
+}
+
+/// Messages streamed by the server to its peers.
+#[derive(Clone, Debug, PartialEq, Encode, Decode)]
+pub enum DataAvailabilityEvent {
+    SignedBlock(SignedBlock),
+    /// Answer to a [DataAvailabilityServerRequest::Ping]
+    Pong,
+    /// Answer to a [DataAvailabilityServerRequest::Hello], with the version used from then on.
+    Welcome {
+        version: u8,
+    },
+    /// Answer to a [DataAvailabilityServerRequest::Hello] when no version is supported by both sides.
+    /// The server closes the connection right after.
+    UnsupportedVersion {
+        min: u8,
+        max: u8,
+    },
+    /// Sent after the headers requested with [DataAvailabilityServerRequest::Headers], streamed as
+    /// blocks without their data proposals. The server closes the connection right after.
+    HeadersEnd,
+    /// Answer to a [DataAvailabilityServerRequest::GetTransaction], `None` if no stored block
+    /// includes it. The server closes the connection right after.
+    Transaction(Option<TransactionInclusion>),
+    /// A [DataAvailabilityEvent::SignedBlock] in its envelope, how blocks are streamed since protocol
+    /// version 4. [DataAvailabilityClientCodec] opens it.
+    Block(BlockEnvelope),
 }
 
 impl Decoder for DataAvailabilityServerCodec {
@@ -36,6 +159,46 @@ impl Decoder for DataAvailabilityServerCodec {
                 return Ok(Some(DataAvailabilityServerRequest::Ping));
             }
 
+            // A bincode-encoded height is never longer than 9 bytes and can't be mistaken for this
+            if let Some(token) = decoded_bytes.strip_prefix(AUTH_PREFIX) {
+                let token = String::from_utf8(token.to_vec()).context("Decoding auth token")?;
+                return Ok(Some(DataAvailabilityServerRequest::Auth(token)));
+            }
+
+            if let Some(version) = decoded_bytes.strip_prefix(HELLO_PREFIX) {
+                let [version] = version else {
+                    bail!("Invalid hello message of {} bytes", decoded_bytes.len());
+                };
+                return Ok(Some(DataAvailabilityServerRequest::Hello {
+                    version: *version,
+                }));
+            }
+
+            if let Some(height) = decoded_bytes.strip_prefix(HEADERS_PREFIX) {
+                let height: u64 = bincode::decode_from_slice(height, bincode::config::standard())
+                    .context("Decoding headers start height")?
+                    .0;
+                return Ok(Some(DataAvailabilityServerRequest::Headers(BlockHeight(
+                    height,
+                ))));
+            }
+
+            if let Some(tx_hash) = decoded_bytes.strip_prefix(TRANSACTION_PREFIX) {
+                let tx_hash =
+                    String::from_utf8(tx_hash.to_vec()).context("Decoding transaction hash")?;
+                return Ok(Some(DataAvailabilityServerRequest::GetTransaction(TxHash(
+                    tx_hash,
+                ))));
+            }
+
+            if let Some(received) = decoded_bytes.strip_prefix(ACK_PREFIX) {
+                let received: u64 =
+                    bincode::decode_from_slice(received, bincode::config::standard())
+                        .context("Decoding acknowledged blocks")?
+                        .0;
+                return Ok(Some(DataAvailabilityServerRequest::Ack(received)));
+            }
+
             let height: u64 =
                 bincode::decode_from_slice(&decoded_bytes, bincode::config::standard())
                     .context(format!(
@@ -53,16 +216,20 @@ impl Decoder for DataAvailabilityServerCodec {
     }
 }
 
-impl Encoder<SignedBlock> for DataAvailabilityServerCodec {
+impl Encoder<DataAvailabilityEvent> for DataAvailabilityServerCodec {
     type Error = anyhow::Error;
 
-    fn encode(&mut self, block: SignedBlock, dst: &mut bytes::BytesMut) -> Result<(), Self::Error> {
+    fn encode(
+        &mut self,
+        event: DataAvailabilityEvent,
+        dst: &mut bytes::BytesMut,
+    ) -> Result<(), Self::Error> {
         let bytes: bytes::Bytes =
-            bincode::encode_to_vec(block, bincode::config::standard())?.into();
+            bincode::encode_to_vec(event, bincode::config::standard())?.into();
 
         self.ldc
             .encode(bytes, dst)
-            .context("Encoding block bytes as length delimited")
+            .context("Encoding event bytes as length delimited")
     }
 }
 
@@ -73,18 +240,21 @@ pub struct DataAvailabilityClientCodec {
     ldc: LengthDelimitedCodec,
 }
 impl Decoder for DataAvailabilityClientCodec {
-    type Item = SignedBlock;
+    type Item = DataAvailabilityEvent;
     type Error = anyhow::Error;
 
     fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         let decoded_bytes = self.ldc.decode(src)?;
         if let Some(decoded_bytes) = decoded_bytes {
-            let block: Self::Item =
+            let event: Self::Item =
                 bincode::decode_from_slice(&decoded_bytes, bincode::config::standard())
-                    .context(format!("Decoding block from {} bytes", decoded_bytes.len()))?
+                    .context(format!("Decoding event from {} bytes", decoded_bytes.len()))?
                     .0;
 
-            return Ok(Some(block));
+            if let DataAvailabilityEvent::Block(envelope) = event {
+                return Ok(Some(DataAvailabilityEvent::SignedBlock(envelope.open()?)));
+            }
+            return Ok(Some(event));
         }
         Ok(None)
     }
@@ -102,7 +272,30 @@ impl Encoder<DataAvailabilityServerRequest> for DataAvailabilityClientCodec {
             DataAvailabilityServerRequest::BlockHeight(height) => {
                 bincode::encode_to_vec(height, bincode::config::standard())?.into()
             }
+            DataAvailabilityServerRequest::Hello { version } => {
+                bytes::Bytes::from([HELLO_PREFIX, &[version]].concat())
+            }
             DataAvailabilityServerRequest::Ping => bytes::Bytes::from("ok"),
+            DataAvailabilityServerRequest::Auth(token) => {
+                bytes::Bytes::from([AUTH_PREFIX, token.as_bytes()].concat())
+            }
+            DataAvailabilityServerRequest::Headers(height) => bytes::Bytes::from(
+                [
+                    HEADERS_PREFIX,
+                    &bincode::encode_to_vec(height, bincode::config::standard())?,
+                ]
+                .concat(),
+            ),
+            DataAvailabilityServerRequest::GetTransaction(tx_hash) => {
+                bytes::Bytes::from([TRANSACTION_PREFIX, tx_hash.0.as_bytes()].concat())
+            }
+            DataAvailabilityServerRequest::Ack(received) => bytes::Bytes::from(
+                [
+                    ACK_PREFIX,
+                    &bincode::encode_to_vec(received, bincode::config::standard())?,
+                ]
+                .concat(),
+            ),
         };
 
         self.ldc
@@ -119,9 +312,11 @@ mod test {
     use crate::model::{AggregateSignature, ConsensusProposal};
     use crate::{
         data_availability::codec::{
-            DataAvailabilityClientCodec, DataAvailabilityServerCodec, DataAvailabilityServerRequest,
+            negotiate_version, BlockEnvelope, DataAvailabilityClientCodec, DataAvailabilityEvent,
+            DataAvailabilityServerCodec, DataAvailabilityServerRequest, TransactionInclusion,
+            DA_PROTOCOL_VERSION,
         },
-        model::{BlockHeight, SignedBlock},
+        model::{BlockHeight, ConsensusProposalHash, SignedBlock, Transaction, TxHash},
     };
 
     #[tokio::test]
@@ -136,12 +331,64 @@ mod test {
             consensus_proposal: ConsensusProposal::default(),
         };
 
-        server_codec.encode(block.clone(), &mut buffer).unwrap();
+        server_codec
+            .encode(
+                DataAvailabilityEvent::SignedBlock(block.clone()),
+                &mut buffer,
+            )
+            .unwrap();
 
-        let decoded_block: SignedBlock = client_codec.decode(&mut buffer).unwrap().unwrap();
+        let decoded = client_codec.decode(&mut buffer).unwrap().unwrap();
 
         // Vérifiez si le buffer a été correctement consommé
-        assert_eq!(block, decoded_block);
+        assert_eq!(DataAvailabilityEvent::SignedBlock(block), decoded);
+    }
+
+    #[tokio::test]
+    async fn test_block_envelope() {
+        let mut server_codec = DataAvailabilityServerCodec::default();
+        let mut client_codec = DataAvailabilityClientCodec::default();
+        let mut buffer = BytesMut::new();
+
+        let mut block = SignedBlock::default();
+        block.consensus_proposal.slot = 12;
+        let envelope = BlockEnvelope::wrap(&block).unwrap();
+        assert_eq!(envelope.height, BlockHeight(12));
+
+        server_codec
+            .encode(DataAvailabilityEvent::Block(envelope.clone()), &mut buffer)
+            .unwrap();
+        assert_eq!(
+            client_codec.decode(&mut buffer).unwrap().unwrap(),
+            DataAvailabilityEvent::SignedBlock(block)
+        );
+
+        // An envelope that doesn't match its block is rejected
+        server_codec
+            .encode(
+                DataAvailabilityEvent::Block(BlockEnvelope {
+                    height: BlockHeight(13),
+                    ..envelope
+                }),
+                &mut buffer,
+            )
+            .unwrap();
+        assert!(client_codec.decode(&mut buffer).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_da_pong() {
+        let mut server_codec = DataAvailabilityServerCodec::default();
+        let mut client_codec = DataAvailabilityClientCodec::default();
+        let mut buffer = BytesMut::new();
+
+        server_codec
+            .encode(DataAvailabilityEvent::Pong, &mut buffer)
+            .unwrap();
+
+        let decoded = client_codec.decode(&mut buffer).unwrap().unwrap();
+        assert_eq!(DataAvailabilityEvent::Pong, decoded);
+        assert!(buffer.is_empty());
     }
 
     #[tokio::test]
@@ -163,6 +410,35 @@ mod test {
         assert_eq!(block_height, decoded_block_height);
     }
 
+    #[tokio::test]
+    async fn test_da_handshake() {
+        let mut server_codec = DataAvailabilityServerCodec::default();
+        let mut client_codec = DataAvailabilityClientCodec::default();
+        let mut buffer = BytesMut::new();
+
+        let hello = DataAvailabilityServerRequest::Hello {
+            version: DA_PROTOCOL_VERSION,
+        };
+        client_codec.encode(hello.clone(), &mut buffer).unwrap();
+        assert_eq!(server_codec.decode(&mut buffer).unwrap().unwrap(), hello);
+
+        let unsupported = DataAvailabilityEvent::UnsupportedVersion { min: 1, max: 1 };
+        server_codec
+            .encode(unsupported.clone(), &mut buffer)
+            .unwrap();
+        assert_eq!(
+            client_codec.decode(&mut buffer).unwrap().unwrap(),
+            unsupported
+        );
+
+        assert_eq!(negotiate_version(0), None);
+        assert_eq!(
+            negotiate_version(DA_PROTOCOL_VERSION),
+            Some(DA_PROTOCOL_VERSION)
+        );
+        assert_eq!(negotiate_version(u8::MAX), Some(DA_PROTOCOL_VERSION));
+    }
+
     #[tokio::test]
     async fn test_da_request_ping() {
         let mut server_codec = DataAvailabilityServerCodec::default(); // Votre implémentation du codec
@@ -179,4 +455,71 @@ mod test {
         // Vérifiez si le buffer a été correctement consommé
         assert_eq!(ping, decoded_ping);
     }
+
+    #[tokio::test]
+    async fn test_da_request_auth() {
+        let mut server_codec = DataAvailabilityServerCodec::default();
+        let mut client_codec = DataAvailabilityClientCodec::default();
+        let mut buffer = BytesMut::new();
+
+        let auth = DataAvailabilityServerRequest::Auth("secret".to_string());
+        let height = DataAvailabilityServerRequest::BlockHeight(BlockHeight(97));
+
+        client_codec.encode(auth.clone(), &mut buffer).unwrap();
+        client_codec.encode(height.clone(), &mut buffer).unwrap();
+
+        assert_eq!(auth, server_codec.decode(&mut buffer).unwrap().unwrap());
+        assert_eq!(height, server_codec.decode(&mut buffer).unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_da_request_ack() {
+        let mut server_codec = DataAvailabilityServerCodec::default();
+        let mut client_codec = DataAvailabilityClientCodec::default();
+        let mut buffer = BytesMut::new();
+
+        let ack = DataAvailabilityServerRequest::Ack(4096);
+        client_codec.encode(ack.clone(), &mut buffer).unwrap();
+        assert_eq!(ack, server_codec.decode(&mut buffer).unwrap().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_da_request_headers() {
+        let mut server_codec = DataAvailabilityServerCodec::default();
+        let mut client_codec = DataAvailabilityClientCodec::default();
+        let mut buffer = BytesMut::new();
+
+        let headers = DataAvailabilityServerRequest::Headers(BlockHeight(300));
+        client_codec.encode(headers.clone(), &mut buffer).unwrap();
+        assert_eq!(headers, server_codec.decode(&mut buffer).unwrap().unwrap());
+
+        server_codec
+            .encode(DataAvailabilityEvent::HeadersEnd, &mut buffer)
+            .unwrap();
+        assert_eq!(
+            client_codec.decode(&mut buffer).unwrap().unwrap(),
+            DataAvailabilityEvent::HeadersEnd
+        );
+    }
+
+    #[tokio::test]
+    async fn test_da_request_transaction() {
+        let mut server_codec = DataAvailabilityServerCodec::default();
+        let mut client_codec = DataAvailabilityClientCodec::default();
+        let mut buffer = BytesMut::new();
+
+        let request = DataAvailabilityServerRequest::GetTransaction(TxHash("abcd".to_string()));
+        client_codec.encode(request.clone(), &mut buffer).unwrap();
+        assert_eq!(request, server_codec.decode(&mut buffer).unwrap().unwrap());
+
+        let answer = DataAvailabilityEvent::Transaction(Some(TransactionInclusion {
+            tx: Transaction::default(),
+            block_hash: ConsensusProposalHash("block".to_string()),
+            block_height: BlockHeight(3),
+            index: 2,
+        }));
+        server_codec.encode(answer.clone(), &mut buffer).unwrap();
+        assert_eq!(answer, client_codec.decode(&mut buffer).unwrap().unwrap());
+        assert!(buffer.is_empty());
+    }
 }
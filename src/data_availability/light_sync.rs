@@ -0,0 +1,377 @@
+//! Bootstrap from block certificates: the headers of a peer's blocks are verified from genesis,
+//! then only the most recent blocks are fetched in full.
+
+use std::collections::{BTreeSet, VecDeque};
+
+use anyhow::{anyhow, bail, Context, Result};
+use bincode::{Decode, Encode};
+use tracing::{debug, info};
+
+use crate::{
+    genesis::{Genesis, GenesisSpec},
+    indexer::da_listener::RawDAListener,
+    model::*,
+    utils::{conf::Conf, crypto::BlstCrypto},
+};
+
+/// Last verified header before the first block fetched in full, which it replaces as parent.
+#[derive(Debug, Clone, Encode, Decode, PartialEq, Eq)]
+pub struct LightSyncAnchor {
+    pub height: BlockHeight,
+    pub hash: ConsensusProposalHash,
+}
+
+/// Verifies a chain of headers from genesis, tracking the bonded validators.
+/// Also used by the DA module on the blocks it stores, in chain order.
+///
+/// Certificates are checked against the epoch snapshot carried by the header starting the epoch,
+/// which must hold the bonded validators. Before the first epoch, the quorum is checked on the
+/// number of signers: more than two thirds of the bonded validators.
+#[derive(Debug, Default, Clone, Encode, Decode)]
+pub struct CertificateChain {
+    last: Option<LightSyncAnchor>,
+    validators: BTreeSet<ValidatorPublicKey>,
+    epoch: Option<EpochSnapshot>,
+}
+
+impl CertificateChain {
+    pub fn last(&self) -> Option<&LightSyncAnchor> {
+        self.last.as_ref()
+    }
+
+    /// Checks that the header follows the last one and was committed by the validators bonded
+    /// then, and applies its validator set changes. The genesis header is trusted: it is up to
+    /// the caller to check it against its own.
+    pub fn verify(&mut self, header: &SignedBlock) -> Result<()> {
+        let height = header.height();
+        match &self.last {
+            None if height != BlockHeight(0) => bail!("Chain starts at {} instead of 0", height),
+            None => {}
+            Some(last) => {
+                if last.height + 1 != height || &last.hash != header.parent_hash() {
+                    bail!(
+                        "Header {} {} does not follow {} {}",
+                        height,
+                        header.hash(),
+                        last.height,
+                        last.hash
+                    );
+                }
+                if let Some(epoch) = &header.consensus_proposal.epoch {
+                    if !epoch.validators.keys().eq(self.validators.iter()) {
+                        bail!(
+                            "Epoch {} starting at {} does not hold the bonded validators",
+                            epoch.epoch,
+                            height
+                        );
+                    }
+                }
+                self.verify_certificate(header)?;
+            }
+        }
+        self.advance(header);
+        Ok(())
+    }
+
+    /// Applies the validator set changes of a header already verified.
+    pub fn advance(&mut self, header: &SignedBlock) {
+        if let Some(epoch) = &header.consensus_proposal.epoch {
+            self.epoch = Some(epoch.clone());
+        }
+        for action in header.consensus_proposal.staking_actions.iter() {
+            match action {
+                ConsensusStakingAction::Bond { candidate } => {
+                    self.validators.insert(candidate.pubkey.clone());
+                }
+                // Slashed validators are unbonded
+                ConsensusStakingAction::Slash { evidence } => {
+                    self.validators.remove(evidence.validator());
+                }
+                ConsensusStakingAction::Leave { validator } => {
+                    self.validators.remove(&validator.pubkey);
+                }
+            }
+        }
+        self.last = Some(LightSyncAnchor {
+            height: header.height(),
+            hash: header.hash(),
+        });
+    }
+
+    fn verify_certificate(&self, header: &SignedBlock) -> Result<()> {
+        let signed = Signed {
+            msg: ConsensusNetMessage::ConfirmAck(header.hash()),
+            signature: header.certificate.clone(),
+        };
+        if !BlstCrypto::verify_aggregate(&signed).context("Verifying certificate")? {
+            bail!("Invalid certificate signature for {}", header.height());
+        }
+        if let Some(epoch) = header
+            .consensus_proposal
+            .epoch
+            .as_ref()
+            .or(self.epoch.as_ref())
+        {
+            return epoch
+                .verify_signers(&header.certificate.validators)
+                .map_err(|e| anyhow!("Certificate of {}: {}", header.height(), e));
+        }
+        let signers: BTreeSet<_> = header.certificate.validators.iter().collect();
+        if let Some(v) = signers.iter().find(|v| !self.validators.contains(**v)) {
+            bail!(
+                "Certificate of {} signed by non-bonded validator {}",
+                header.height(),
+                v
+            );
+        }
+        if 3 * signers.len() <= 2 * self.validators.len() {
+            bail!(
+                "Certificate of {} signed by {} of {} validators",
+                header.height(),
+                signers.len(),
+                self.validators.len()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Hash of the genesis block the node trusts, computed locally rather than taken from a peer:
+/// built from the genesis spec, or pinned in the configuration.
+pub async fn trusted_genesis(config: &Conf) -> Result<ConsensusProposalHash> {
+    if let Some(path) = &config.consensus.genesis_file {
+        let spec = GenesisSpec::load(path)?;
+        return Ok(Genesis::genesis_block_from_spec(&spec).await?.hash());
+    }
+    match &config.da_light_sync.genesis_hash {
+        Some(hash) => Ok(ConsensusProposalHash(hash.clone())),
+        None => {
+            bail!("No trusted genesis: set consensus.genesis_file or da_light_sync.genesis_hash")
+        }
+    }
+}
+
+/// Verifies the next header of a peer's chain, which must start from the trusted genesis.
+fn verify_header(
+    chain: &mut CertificateChain,
+    header: &SignedBlock,
+    genesis: &ConsensusProposalHash,
+) -> Result<()> {
+    if chain.last().is_none() && &header.hash() != genesis {
+        bail!(
+            "Peer's genesis {} is not the trusted one {}",
+            header.hash(),
+            genesis
+        );
+    }
+    chain.verify(header)
+}
+
+/// Verifies the headers streamed by `target`, and returns the chain up to the anchor from which
+/// the last `recent_blocks` blocks are to be fetched. `None` if the chain is too short to skip
+/// anything.
+pub async fn sync_headers(target: &str, config: &Conf) -> Result<Option<CertificateChain>> {
+    let recent_blocks = config.da_light_sync.recent_blocks;
+    let genesis = trusted_genesis(config).await?;
+    info!(
+        "🪶 Light sync: verifying the block certificates of {}",
+        target
+    );
+    let mut stream = RawDAListener::new_headers(target, config).await?;
+    let mut chain = CertificateChain::default();
+    // Candidate anchors, the oldest one is used once the tip is known
+    let mut anchors = VecDeque::new();
+    while let Some(header) = stream.next_header().await? {
+        verify_header(&mut chain, &header, &genesis)?;
+        anchors.push_back(chain.clone());
+        while anchors.len() as u64 > recent_blocks.saturating_add(1) {
+            anchors.pop_front();
+        }
+        if header.height().0 % 10_000 == 0 {
+            debug!("Light sync: verified headers up to {}", header.height());
+        }
+    }
+    if (anchors.len() as u64) <= recent_blocks {
+        info!("🪶 Light sync: chain too short, fetching all blocks");
+        return Ok(None);
+    }
+    let anchor = anchors.pop_front();
+    if let (Some(anchor), Some(tip)) = (anchor.as_ref().and_then(|a| a.last()), chain.last()) {
+        info!(
+            "🪶 Light sync: verified headers up to {}, fetching blocks from {}",
+            tip.height,
+            anchor.height + 1
+        );
+    }
+    Ok(anchor)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(parent: Option<&SignedBlock>, signers: &[&BlstCrypto]) -> SignedBlock {
+        header_with_epoch(parent, signers, None)
+    }
+
+    fn header_with_epoch(
+        parent: Option<&SignedBlock>,
+        signers: &[&BlstCrypto],
+        epoch: Option<EpochSnapshot>,
+    ) -> SignedBlock {
+        let mut block = SignedBlock::default();
+        if let Some(parent) = parent {
+            block.consensus_proposal.slot = parent.consensus_proposal.slot + 1;
+            block.consensus_proposal.parent_hash = parent.hash();
+        }
+        block.consensus_proposal.epoch = epoch;
+        let msg = ConsensusNetMessage::ConfirmAck(block.hash());
+        let signed: Vec<_> = signers
+            .iter()
+            .map(|c| c.sign(msg.clone()).unwrap())
+            .collect();
+        block.certificate = BlstCrypto::aggregate(msg, &signed.iter().collect::<Vec<_>>())
+            .unwrap()
+            .signature;
+        block
+    }
+
+    fn genesis(validators: &[BlstCrypto]) -> SignedBlock {
+        let mut genesis = SignedBlock::default();
+        for crypto in validators.iter() {
+            genesis
+                .consensus_proposal
+                .staking_actions
+                .push(ConsensusStakingAction::Bond {
+                    candidate: NewValidatorCandidate {
+                        pubkey: crypto.validator_pubkey().clone(),
+                        msg: crypto
+                            .sign(ConsensusNetMessage::ValidatorCandidacy(
+                                ValidatorCandidacy {
+                                    pubkey: crypto.validator_pubkey().clone(),
+                                    peer_address: String::new(),
+                                },
+                            ))
+                            .unwrap(),
+                    },
+                });
+        }
+        genesis
+    }
+
+    #[test]
+    fn test_certificate_chain() {
+        let validators: [BlstCrypto; 4] =
+            std::array::from_fn(|i| BlstCrypto::new(format!("v{i}")).unwrap());
+        let genesis = genesis(&validators);
+        let [v0, v1, v2, v3] = &validators;
+
+        let mut chain = CertificateChain::default();
+        assert!(chain
+            .verify(&header(Some(&genesis), &[v0, v1, v2]))
+            .is_err());
+        chain.verify(&genesis).unwrap();
+
+        let block_1 = header(Some(&genesis), &[v0, v1, v2]);
+        // Two thirds of the validators are not enough
+        let mut weak = header(Some(&genesis), &[v0, v1]);
+        assert!(chain.verify(&weak).is_err());
+        // The certificate must be for this block
+        weak.certificate = block_1.certificate.clone();
+        weak.consensus_proposal.timestamp = 1;
+        assert!(chain.verify(&weak).is_err());
+        chain.verify(&block_1).unwrap();
+
+        // Blocks can't be skipped
+        let block_2 = header(Some(&block_1), &[v1, v2, v3]);
+        let block_3 = header(Some(&block_2), &[v1, v2, v3]);
+        assert!(chain.verify(&block_3).is_err());
+        chain.verify(&block_2).unwrap();
+        chain.verify(&block_3).unwrap();
+        assert_eq!(
+            chain.last(),
+            Some(&LightSyncAnchor {
+                height: BlockHeight(3),
+                hash: block_3.hash(),
+            })
+        );
+
+        // Unknown validators can't sign
+        let outsider = BlstCrypto::new("outsider".into()).unwrap();
+        assert!(chain
+            .verify(&header(Some(&block_3), &[v0, v1, v2, &outsider]))
+            .is_err());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_foreign_genesis() {
+        let validators: [BlstCrypto; 4] =
+            std::array::from_fn(|i| BlstCrypto::new(format!("v{i}")).unwrap());
+        let outsiders: [BlstCrypto; 4] =
+            std::array::from_fn(|i| BlstCrypto::new(format!("outsider{i}")).unwrap());
+        let foreign = genesis(&outsiders);
+        let genesis = genesis(&validators);
+
+        let mut config = Conf::new(None, None, None).unwrap();
+        config.consensus.genesis_file = None;
+        assert!(trusted_genesis(&config).await.is_err());
+        config.da_light_sync.genesis_hash = Some(genesis.hash().0);
+        let trusted = trusted_genesis(&config).await.unwrap();
+
+        // A chain certified by other validators is rejected from its genesis
+        let mut chain = CertificateChain::default();
+        assert!(verify_header(&mut chain, &foreign, &trusted).is_err());
+        assert!(chain.last().is_none());
+
+        verify_header(&mut chain, &genesis, &trusted).unwrap();
+        let [v0, v1, v2, _] = &validators;
+        verify_header(&mut chain, &header(Some(&genesis), &[v0, v1, v2]), &trusted).unwrap();
+    }
+
+    #[test]
+    fn test_certificate_chain_epochs() {
+        let validators: [BlstCrypto; 4] =
+            std::array::from_fn(|i| BlstCrypto::new(format!("v{i}")).unwrap());
+        let genesis = genesis(&validators);
+        let [v0, v1, v2, v3] = &validators;
+        let epoch = EpochSnapshot {
+            epoch: 1,
+            start_height: BlockHeight(1),
+            validators: validators
+                .iter()
+                .zip([1000, 10, 10, 10])
+                .map(|(crypto, stake)| (crypto.validator_pubkey().clone(), stake))
+                .collect(),
+        };
+
+        let mut chain = CertificateChain::default();
+        chain.verify(&genesis).unwrap();
+
+        // The epoch must hold the bonded validators
+        let mut missing = epoch.clone();
+        missing.validators.remove(v3.validator_pubkey());
+        assert!(chain
+            .verify(&header_with_epoch(
+                Some(&genesis),
+                &[v0, v1, v2, v3],
+                Some(missing)
+            ))
+            .is_err());
+
+        // Stakes decide of the quorum rather than the number of signers
+        assert!(chain
+            .verify(&header_with_epoch(
+                Some(&genesis),
+                &[v1, v2, v3],
+                Some(epoch.clone())
+            ))
+            .is_err());
+        let block_1 = header_with_epoch(Some(&genesis), &[v0], Some(epoch));
+        chain.verify(&block_1).unwrap();
+        // The stakes of the epoch keep deciding of the quorum until the next one
+        assert!(chain
+            .verify(&header(Some(&block_1), &[v1, v2, v3]))
+            .is_err());
+        chain.verify(&header(Some(&block_1), &[v0])).unwrap();
+    }
+}
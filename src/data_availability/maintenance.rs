@@ -0,0 +1,153 @@
+//! Offline maintenance of the block store, for the node's CLI subcommands.
+//! The node must be stopped, as the store can only be opened once.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+
+use super::open_blocks;
+use crate::{
+    model::{BlockHeight, ConsensusNetMessage, EpochSnapshot, Hashable, Signed, SignedBlock},
+    utils::{conf::Conf, crypto::BlstCrypto},
+};
+
+/// Writes all stored blocks to a snapshot file, returns the number of blocks written.
+pub fn export_blocks(config: &Conf, to: &Path) -> Result<u64> {
+    let mut blocks = open_blocks(config)?;
+    blocks
+        .export_snapshot(to)
+        .with_context(|| format!("Exporting blocks to {}", to.display()))
+}
+
+/// Outcome of [verify_blocks].
+#[derive(Debug, Default)]
+pub struct BlocksReport {
+    pub blocks: u64,
+    pub last_height: Option<BlockHeight>,
+    /// Problems found, with the height of the block they were found at
+    pub errors: Vec<(BlockHeight, String)>,
+}
+
+/// Checks that the stored blocks can be decoded and form a chain without gaps. Certificates are
+/// checked too when `da_verify_blocks` is set, against the validator set of the block's epoch
+/// once one is known.
+pub fn verify_blocks(config: &Conf) -> Result<BlocksReport> {
+    let mut blocks = open_blocks(config)?;
+    let mut report = BlocksReport::default();
+    let Some(last) = blocks.last() else {
+        return Ok(report);
+    };
+    report.last_height = Some(last.height());
+
+    let mut previous: Option<SignedBlock> = None;
+    let mut epoch: Option<EpochSnapshot> = None;
+    for block in blocks.range(BlockHeight(0), last.height() + 1) {
+        let height = previous
+            .as_ref()
+            .map_or(BlockHeight(0), |previous| previous.height() + 1);
+        let block = match block {
+            Ok(block) => block,
+            Err(e) => {
+                report
+                    .errors
+                    .push((height, format!("Unreadable block: {:#}", e)));
+                continue;
+            }
+        };
+        report.blocks += 1;
+        if let Some(block_epoch) = &block.consensus_proposal.epoch {
+            epoch = Some(block_epoch.clone());
+        }
+
+        if let Some(previous) = &previous {
+            if block.height() != height {
+                report.errors.push((
+                    block.height(),
+                    format!("Missing blocks from height {}", height),
+                ));
+            } else if block.parent_hash() != &previous.hash() {
+                report.errors.push((
+                    block.height(),
+                    format!(
+                        "Parent {} is not the previous block {}",
+                        block.parent_hash(),
+                        previous.hash()
+                    ),
+                ));
+            }
+        }
+
+        if config.da_verify_blocks && block.height() != BlockHeight(0) {
+            let signed = Signed {
+                msg: ConsensusNetMessage::ConfirmAck(block.hash()),
+                signature: block.certificate.clone(),
+            };
+            if !BlstCrypto::verify_aggregate(&signed).unwrap_or(false) {
+                report
+                    .errors
+                    .push((block.height(), "Invalid certificate signature".to_string()));
+            }
+            if let Some(Err(e)) = epoch
+                .as_ref()
+                .map(|epoch| epoch.verify_signers(&block.certificate.validators))
+            {
+                report
+                    .errors
+                    .push((block.height(), format!("Invalid certificate signers: {e}")));
+            }
+        }
+
+        previous = Some(block);
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_availability::snapshot::SnapshotReader;
+
+    #[test]
+    fn test_verify_and_export_blocks() -> Result<()> {
+        let mut config = Conf::new(None, None, None)?;
+        config.data_directory = tempfile::tempdir()?.into_path();
+        config.da_verify_blocks = false;
+
+        let mut block = SignedBlock::default();
+        let mut chain = vec![];
+        for slot in 1..6 {
+            chain.push(block.clone());
+            block.consensus_proposal.parent_hash = block.hash();
+            block.consensus_proposal.slot = slot;
+        }
+        // A block that doesn't follow the chain
+        let mut orphan = block.clone();
+        orphan.consensus_proposal.parent_hash =
+            chain.first().map(|block| block.hash()).unwrap_or_default();
+        chain.push(orphan);
+        {
+            let mut blocks = open_blocks(&config)?;
+            for block in chain {
+                blocks.put(block)?;
+            }
+            blocks.persist()?;
+        }
+
+        let report = verify_blocks(&config)?;
+        assert_eq!(report.blocks, 6);
+        assert_eq!(report.last_height, Some(BlockHeight(5)));
+        assert_eq!(
+            report
+                .errors
+                .iter()
+                .map(|(height, _)| *height)
+                .collect::<Vec<_>>(),
+            vec![BlockHeight(5)]
+        );
+
+        let snapshot = config.data_directory.join("blocks.snapshot");
+        assert_eq!(export_blocks(&config, &snapshot)?, 6);
+        assert_eq!(SnapshotReader::open(&snapshot)?.count(), 6);
+        Ok(())
+    }
+}
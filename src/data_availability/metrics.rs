@@ -0,0 +1,111 @@
+use opentelemetry::{
+    metrics::{Counter, Gauge},
+    InstrumentationScope, KeyValue,
+};
+
+#[derive(Debug)]
+pub struct DaMetrics {
+    slow_peer: Counter<u64>,
+    peer_disconnected: Counter<u64>,
+    streaming_peers: Gauge<u64>,
+    peer_queued_messages: Gauge<u64>,
+    rejected_block: Counter<u64>,
+    block_height: Gauge<u64>,
+    buffered_blocks: Gauge<u64>,
+    disk_usage: Gauge<u64>,
+}
+
+impl DaMetrics {
+    pub fn global(node_name: String) -> DaMetrics {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        let da = "da";
+
+        DaMetrics {
+            slow_peer: my_meter.u64_counter(format!("{da}_slow_peer")).build(),
+            peer_disconnected: my_meter
+                .u64_counter(format!("{da}_peer_disconnected"))
+                .build(),
+            streaming_peers: my_meter.u64_gauge(format!("{da}_streaming_peers")).build(),
+            peer_queued_messages: my_meter
+                .u64_gauge(format!("{da}_peer_queued_messages"))
+                .build(),
+            rejected_block: my_meter.u64_counter(format!("{da}_rejected_block")).build(),
+            block_height: my_meter.u64_gauge(format!("{da}_block_height")).build(),
+            buffered_blocks: my_meter.u64_gauge(format!("{da}_buffered_blocks")).build(),
+            disk_usage: my_meter.u64_gauge(format!("{da}_disk_usage_bytes")).build(),
+        }
+    }
+
+    pub fn slow_peer(&self, peer: &str) {
+        self.slow_peer
+            .add(1, &[KeyValue::new("peer", peer.to_string())]);
+    }
+
+    pub fn peer_disconnected(&self, reason: &'static str) {
+        self.peer_disconnected
+            .add(1, &[KeyValue::new("reason", reason)]);
+    }
+
+    pub fn rejected_block(&self) {
+        self.rejected_block.add(1, &[]);
+    }
+
+    pub fn snapshot_streaming_peers(&self, nb: usize) {
+        self.streaming_peers.record(nb as u64, &[]);
+    }
+
+    /// Blocks waiting in the queue of a streaming peer
+    pub fn snapshot_peer_queued_messages(&self, peer: &str, nb: usize) {
+        self.peer_queued_messages
+            .record(nb as u64, &[KeyValue::new("peer", peer.to_string())]);
+    }
+
+    /// Height of the last stored block
+    pub fn snapshot_block_height(&self, height: u64) {
+        self.block_height.record(height, &[]);
+    }
+
+    /// Blocks waiting for their parent
+    pub fn snapshot_buffered_blocks(&self, nb: usize) {
+        self.buffered_blocks.record(nb as u64, &[]);
+    }
+
+    /// Bytes the block store takes on disk
+    pub fn snapshot_disk_usage(&self, bytes: u64) {
+        self.disk_usage.record(bytes, &[]);
+    }
+}
+
+#[derive(Debug)]
+pub struct BlockCacheMetrics {
+    hit: Counter<u64>,
+    miss: Counter<u64>,
+}
+
+impl BlockCacheMetrics {
+    pub fn global(node_name: String) -> BlockCacheMetrics {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        let da = "da";
+
+        BlockCacheMetrics {
+            hit: my_meter
+                .u64_counter(format!("{da}_block_cache_hit"))
+                .build(),
+            miss: my_meter
+                .u64_counter(format!("{da}_block_cache_miss"))
+                .build(),
+        }
+    }
+
+    pub fn hit(&self) {
+        self.hit.add(1, &[]);
+    }
+
+    pub fn miss(&self) {
+        self.miss.add(1, &[]);
+    }
+}
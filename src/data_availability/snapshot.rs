@@ -0,0 +1,104 @@
+//! Snapshot files of the block store, used to bootstrap nodes without streaming every block.
+//!
+//! Format: a magic header, then each block in height order as a little-endian u32 length
+//! followed by the bincode-encoded `SignedBlock`.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, ErrorKind, Read, Write},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::model::SignedBlock;
+
+const SNAPSHOT_MAGIC: &[u8; 8] = b"HYLEDAS1";
+
+pub struct SnapshotWriter {
+    file: BufWriter<File>,
+    written: u64,
+}
+
+impl SnapshotWriter {
+    pub fn create(path: &Path) -> Result<Self> {
+        let mut file = BufWriter::new(
+            File::create(path).context(format!("Creating snapshot {}", path.display()))?,
+        );
+        file.write_all(SNAPSHOT_MAGIC)?;
+        Ok(Self { file, written: 0 })
+    }
+
+    pub fn write(&mut self, block: &SignedBlock) -> Result<()> {
+        let bytes = bincode::encode_to_vec(block, bincode::config::standard())?;
+        let len = u32::try_from(bytes.len()).context("Block too large for snapshot")?;
+        self.file.write_all(&len.to_le_bytes())?;
+        self.file.write_all(&bytes)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Flushes the file and returns the number of blocks written.
+    pub fn finish(mut self) -> Result<u64> {
+        self.file.flush()?;
+        Ok(self.written)
+    }
+}
+
+/// Iterates over the blocks of a snapshot file.
+pub struct SnapshotReader {
+    file: BufReader<File>,
+}
+
+impl SnapshotReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut file = BufReader::new(
+            File::open(path).context(format!("Opening snapshot {}", path.display()))?,
+        );
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic)
+            .context("Reading snapshot header")?;
+        if &magic != SNAPSHOT_MAGIC {
+            bail!("{} is not a block store snapshot", path.display());
+        }
+        Ok(Self { file })
+    }
+
+    fn read_block(&mut self) -> Result<Option<SignedBlock>> {
+        let mut len = [0u8; 4];
+        match self.file.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let mut bytes = vec![0u8; u32::from_le_bytes(len) as usize];
+        self.file
+            .read_exact(&mut bytes)
+            .context("Snapshot is truncated")?;
+        let (block, _) = bincode::decode_from_slice(&bytes, bincode::config::standard())?;
+        Ok(Some(block))
+    }
+}
+
+impl Iterator for SnapshotReader {
+    type Item = Result<SignedBlock>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_block().transpose()
+    }
+}
+
+/// Snapshots are kept in `<data_directory>/snapshots`, so that admin routes can't touch other files.
+pub fn snapshot_path(data_directory: &Path, name: &str) -> Result<std::path::PathBuf> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        bail!("Invalid snapshot name '{name}'");
+    }
+    let dir = data_directory.join("snapshots");
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(name))
+}
@@ -0,0 +1,91 @@
+//! Runs the data availability module by hand, feeding it blocks without consensus.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+
+use super::{block_store::open_block_store, BlockCache, DABusClient, DaMetrics, DataAvailability};
+use crate::{
+    bus::{BusClientSender, SharedMessageBus},
+    model::SignedBlock,
+    node_state::{
+        module::{NodeStateBusClient, NodeStateEvent},
+        NodeState,
+    },
+    utils::{
+        conf::{Conf, DaStorage},
+        crypto::BlstCrypto,
+        integration_test::find_available_port,
+    },
+};
+
+/// For use in integration tests
+pub struct DataAvailabilityTestCtx {
+    pub node_state_bus: NodeStateBusClient,
+    pub da: DataAvailability,
+    pub node_state: NodeState,
+}
+
+impl DataAvailabilityTestCtx {
+    /// Blocks are kept in memory, the data directory is a fresh temporary one.
+    pub async fn try_new(shared_bus: SharedMessageBus) -> Result<Self> {
+        let mut config: Conf = Conf::new(None, None, None)?;
+        config.da_address = format!("127.0.0.1:{}", find_available_port().await);
+        config.data_directory = tempfile::tempdir()?.into_path();
+        config.da_storage = DaStorage::Memory;
+        // Test blocks don't carry real certificates
+        config.da_verify_blocks = false;
+
+        let blocks = open_block_store(
+            config.da_storage,
+            &config.data_directory,
+            BlockCache::new(16, "test".to_string()),
+        )?;
+        let bus = DABusClient::new_from_bus(shared_bus.new_handle()).await;
+        let node_state_bus = NodeStateBusClient::new_from_bus(shared_bus).await;
+
+        let da = DataAvailability {
+            config: config.into(),
+            bus,
+            crypto: Arc::new(BlstCrypto::new_random()?),
+            blocks,
+            buffered_signed_blocks: Default::default(),
+            certificates: Default::default(),
+            da_peers: Default::default(),
+            last_gap_fill_request: None,
+            stream_peer_metadata: Default::default(),
+            ws_blocks: tokio::sync::broadcast::channel(100).0,
+            metrics: DaMetrics::global("test".to_string()),
+            need_catchup: false,
+            catchup_task: None,
+            catchup_height: None,
+            catchup_checkpoint: Default::default(),
+            stream_request_receiver: None,
+            disk_usage: None,
+            disk_usage_level: Default::default(),
+        };
+
+        Ok(DataAvailabilityTestCtx {
+            node_state_bus,
+            da,
+            node_state: NodeState::default(),
+        })
+    }
+
+    #[allow(clippy::expect_used, reason = "Test harness")]
+    pub async fn new(shared_bus: SharedMessageBus) -> Self {
+        Self::try_new(shared_bus)
+            .await
+            .expect("Setting up the data availability module")
+    }
+
+    /// Stores the block, and sends its processed version as node state would.
+    #[allow(clippy::expect_used, reason = "Test harness")]
+    pub async fn handle_signed_block(&mut self, block: SignedBlock) {
+        self.da.handle_signed_block(block.clone()).await;
+        let full_block = self.node_state.handle_signed_block(&block);
+        self.node_state_bus
+            .send(NodeStateEvent::NewBlock(Box::new(full_block)))
+            .expect("Sending the processed block");
+    }
+}
@@ -7,7 +7,7 @@ use crate::{
     p2p::network::PeerEvent,
     utils::{conf::SharedConf, crypto::SharedBlstCrypto, modules::Module},
 };
-use anyhow::{Error, Result};
+use anyhow::{bail, Error, Result};
 use client_sdk::{
     contract_states,
     helpers::register_hyle_contract,
@@ -28,6 +28,16 @@ use staking::{
 use tracing::{debug, error, info};
 use verifiers::NativeVerifiers;
 
+mod spec;
+
+pub use spec::{GenesisContract, GenesisSpec, GenesisValidator};
+
+/// 1st of Jan 25, for genesis blocks not built from a spec with a timestamp
+const DEFAULT_GENESIS_TIMESTAMP: u64 = 1735689600000;
+
+/// Hyllar given to the staking contract at genesis to pay the rewards
+pub const STAKING_REWARDS_RESERVE: u128 = 1_000_000_000;
+
 #[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq)]
 pub enum GenesisEvent {
     NoGenesis,
@@ -99,6 +109,11 @@ impl Genesis {
     }
 
     pub async fn do_genesis(&mut self) -> Result<()> {
+        if let Some(path) = &self.config.consensus.genesis_file {
+            let spec = GenesisSpec::load(path)?;
+            return self.do_genesis_from_spec(spec).await;
+        }
+
         let single_node = self.config.single_node.unwrap_or(false);
         // Unless we're in single node mode, we must be a genesis staker to start the network.
         if !single_node
@@ -156,6 +171,8 @@ impl Genesis {
         let genesis_txs = match Self::generate_genesis_txs(
             &self.peer_pubkey,
             &self.config.consensus.genesis_stakers,
+            &[],
+            Staking::new(),
         )
         .await
         {
@@ -166,7 +183,8 @@ impl Genesis {
             }
         };
 
-        let signed_block = self.make_genesis_block(genesis_txs, initial_validators);
+        let signed_block =
+            Self::make_genesis_block(genesis_txs, initial_validators, DEFAULT_GENESIS_TIMESTAMP);
 
         // At this point, we can setup the genesis block.
         _ = self.bus.send(GenesisEvent::GenesisBlock(signed_block));
@@ -174,12 +192,58 @@ impl Genesis {
         Ok(())
     }
 
+    /// The validators are known from the spec, so there is no need to wait for them to connect.
+    async fn do_genesis_from_spec(&mut self, spec: GenesisSpec) -> Result<()> {
+        if !spec
+            .validators
+            .iter()
+            .any(|v| &v.pubkey == self.crypto.validator_pubkey())
+        {
+            info!("📡 Not a validator of the genesis spec, need to catchup from peers.");
+            _ = self.bus.send(GenesisEvent::NoGenesis {});
+            return Ok(());
+        }
+
+        info!(
+            "🌱 Building genesis block from spec with {} validators and {} contracts",
+            spec.validators.len(),
+            spec.contracts.len()
+        );
+        self.peer_pubkey = spec.peer_pubkeys();
+        let signed_block = Self::genesis_block_from_spec(&spec).await?;
+        _ = self.bus.send(GenesisEvent::GenesisBlock(signed_block));
+
+        Ok(())
+    }
+
+    pub async fn genesis_block_from_spec(spec: &GenesisSpec) -> Result<SignedBlock> {
+        let peer_pubkey = spec.peer_pubkeys();
+        let mut initial_validators = peer_pubkey.values().cloned().collect::<Vec<_>>();
+        initial_validators.sort();
+
+        let genesis_txs = Self::generate_genesis_txs(
+            &peer_pubkey,
+            &spec.stakes(),
+            &spec.contracts,
+            spec.staking(),
+        )
+        .await?;
+
+        Ok(Self::make_genesis_block(
+            genesis_txs,
+            initial_validators,
+            spec.timestamp.unwrap_or(DEFAULT_GENESIS_TIMESTAMP),
+        ))
+    }
+
     pub async fn generate_genesis_txs(
         peer_pubkey: &PeerPublicKeyMap,
         genesis_stake: &HashMap<String, u64>,
+        contracts: &[GenesisContract],
+        staking: Staking,
     ) -> Result<Vec<Transaction>> {
         let (contract_program_ids, mut genesis_txs, mut tx_executor) =
-            Self::genesis_contracts_txs();
+            Self::genesis_contracts_txs(contracts, staking)?;
 
         let register_txs = Self::generate_register_txs(peer_pubkey, &mut tx_executor).await?;
 
@@ -189,10 +253,13 @@ impl Genesis {
         let stake_txs =
             Self::generate_stake_txs(peer_pubkey, &mut tx_executor, genesis_stake).await?;
 
+        let rewards_tx = Self::generate_rewards_reserve_tx(&mut tx_executor)?;
+
         let builders = register_txs
             .into_iter()
             .chain(faucet_txs.into_iter())
-            .chain(stake_txs.into_iter());
+            .chain(stake_txs.into_iter())
+            .chain(std::iter::once(rewards_tx));
 
         for ProofTxBuilder {
             identity,
@@ -228,6 +295,7 @@ impl Genesis {
                         .collect(),
                     is_recursive: true,
                     proof_hash: ProofData::default().hash(),
+                    proof_size: 0,
                     proof: None,
                 }
                 .into(),
@@ -354,11 +422,36 @@ impl Genesis {
         Ok(txs)
     }
 
-    fn genesis_contracts_txs() -> (
+    /// Funds the staking contract with the hyllar paid out as rewards.
+    fn generate_rewards_reserve_tx(tx_executor: &mut TxExecutor<States>) -> Result<ProofTxBuilder> {
+        info!("🌱  Fauceting {STAKING_REWARDS_RESERVE} hyllar of staking rewards");
+
+        let mut transaction = ProvableBlobTx::new(Identity::new("faucet.hydentity"));
+        verify_identity(
+            &mut transaction,
+            ContractName::new("hydentity"),
+            &tx_executor.hydentity,
+            "password".to_string(),
+        )?;
+        transfer(
+            &mut transaction,
+            ContractName::new("hyllar"),
+            "staking".to_string(),
+            STAKING_REWARDS_RESERVE,
+        )?;
+
+        tx_executor.process(transaction)
+    }
+
+    /// Registers the built-in contracts, starting with the given staking state, then `contracts`.
+    fn genesis_contracts_txs(
+        contracts: &[GenesisContract],
+        staking_state: Staking,
+    ) -> Result<(
         BTreeMap<ContractName, ProgramId>,
         Vec<Transaction>,
         TxExecutor<States>,
-    ) {
+    )> {
         let staking_program_id = hyle_contracts::STAKING_ID.to_vec();
         let hyllar_program_id = hyle_contracts::HYLLAR_ID.to_vec();
         let hydentity_program_id = hyle_contracts::HYDENTITY_ID.to_vec();
@@ -368,8 +461,6 @@ impl Genesis {
             .register_identity("faucet.hydentity", "password")
             .expect("faucet must register");
 
-        let staking_state = staking::state::Staking::new();
-
         let ctx = TxExecutorBuilder::new(States {
             hyllar: hyllar::HyllarToken::new(100_000_000_000, "faucet.hydentity".to_string()),
             hydentity: hydentity_state,
@@ -444,15 +535,31 @@ impl Genesis {
         )
         .expect("register risc0-recursion");
 
+        for contract in contracts {
+            let name: ContractName = contract.name.clone().into();
+            if map.contains_key(&name) {
+                bail!("Contract {} is already registered at genesis", name);
+            }
+            let program_id = contract.program_id()?;
+            register_hyle_contract(
+                &mut register_tx,
+                name.clone(),
+                contract.verifier.clone().into(),
+                program_id.clone(),
+                contract.state_digest()?,
+            )?;
+            map.insert(name, program_id);
+        }
+
         let genesis_tx: BlobTransaction = register_tx.into();
 
-        (map, vec![genesis_tx.into()], ctx)
+        Ok((map, vec![genesis_tx.into()], ctx))
     }
 
     fn make_genesis_block(
-        &self,
         genesis_txs: Vec<Transaction>,
         initial_validators: Vec<ValidatorPublicKey>,
+        timestamp: u64,
     ) -> SignedBlock {
         let dp = DataProposal {
             id: 0,
@@ -476,8 +583,7 @@ impl Genesis {
                 slot: 0,
                 view: 0,
                 round_leader: round_leader.clone(),
-                // TODO: genesis block should have a consistent, up-to-date timestamp
-                timestamp: 1735689600000, // 1st of Jan 25 for now
+                timestamp,
                 // TODO: We aren't actually storing the data proposal above, so we cannot store it here,
                 // or we might mistakenly request data from that cut, but mempool hasn't seen it.
                 // This should be fixed by storing the data proposal in mempool or handling this whole thing differently.
@@ -507,6 +613,8 @@ impl Genesis {
                     })
                     .collect(),
                 parent_hash: ConsensusProposalHash("genesis".into()),
+                production_reason: Default::default(),
+                epoch: None,
             },
         }
     }
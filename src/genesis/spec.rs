@@ -0,0 +1,225 @@
+//! Genesis spec file: the initial validators with their stakes, and the contracts registered at
+//! genesis. Every node given the same spec builds the same genesis block, without waiting for
+//! the other validators to connect.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::Path,
+};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use staking::state::{Staking, DEFAULT_JAIL_DURATION, DEFAULT_UNBONDING_DELAY};
+
+use super::PeerPublicKeyMap;
+use crate::model::*;
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GenesisSpec {
+    pub validators: Vec<GenesisValidator>,
+    /// Contracts registered on top of the built-in ones
+    #[serde(default)]
+    pub contracts: Vec<GenesisContract>,
+    /// Timestamp of the genesis block in milliseconds, a fixed date if unset
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    /// Rewards emitted by the staking contract
+    #[serde(default)]
+    pub emission: EmissionSchedule,
+    /// Blocks an unbonded stake waits for before it can be withdrawn
+    #[serde(default = "default_unbonding_delay")]
+    pub unbonding_delay: u64,
+    /// Blocks a slashed validator can't bond for
+    #[serde(default = "default_jail_duration")]
+    pub jail_duration: u64,
+}
+
+fn default_unbonding_delay() -> u64 {
+    DEFAULT_UNBONDING_DELAY
+}
+
+fn default_jail_duration() -> u64 {
+    DEFAULT_JAIL_DURATION
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GenesisValidator {
+    /// Name of the validator, its identity is `<pubkey>.hydentity`
+    pub name: String,
+    pub pubkey: ValidatorPublicKey,
+    /// Hyllar fauceted to the validator then staked and delegated to it
+    pub stake: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GenesisContract {
+    pub name: String,
+    pub verifier: String,
+    /// Hex encoded
+    pub program_id: String,
+    /// Hex encoded digest of the initial state
+    pub state_digest: String,
+}
+
+impl GenesisContract {
+    pub fn program_id(&self) -> Result<ProgramId> {
+        hex::decode(&self.program_id)
+            .map(ProgramId)
+            .context(format!("Decoding program id of contract {}", self.name))
+    }
+
+    pub fn state_digest(&self) -> Result<StateDigest> {
+        hex::decode(&self.state_digest)
+            .map(StateDigest)
+            .context(format!("Decoding state digest of contract {}", self.name))
+    }
+}
+
+impl GenesisSpec {
+    /// Reads a TOML spec if the file has a `.toml` extension, JSON otherwise.
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .context(format!("Reading genesis spec {}", path.display()))?;
+        let spec: Self = if path.extension().is_some_and(|ext| ext == "toml") {
+            toml::from_str(&content).context("Parsing TOML genesis spec")?
+        } else {
+            serde_json::from_str(&content).context("Parsing JSON genesis spec")?
+        };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.validators.is_empty() {
+            bail!("Genesis spec has no validators");
+        }
+        let mut names = BTreeSet::new();
+        let mut pubkeys = BTreeSet::new();
+        for validator in self.validators.iter() {
+            if !names.insert(&validator.name) || !pubkeys.insert(&validator.pubkey) {
+                bail!("Validator {} appears twice in genesis spec", validator.name);
+            }
+            if validator.stake == 0 {
+                bail!("Validator {} has no stake in genesis spec", validator.name);
+            }
+        }
+        let mut contracts = BTreeSet::new();
+        for contract in self.contracts.iter() {
+            if !contracts.insert(&contract.name) {
+                bail!("Contract {} appears twice in genesis spec", contract.name);
+            }
+            contract.program_id()?;
+            contract.state_digest()?;
+        }
+        Ok(())
+    }
+
+    pub fn peer_pubkeys(&self) -> PeerPublicKeyMap {
+        self.validators
+            .iter()
+            .map(|v| (v.name.clone(), v.pubkey.clone()))
+            .collect()
+    }
+
+    /// Initial state of the staking contract.
+    pub fn staking(&self) -> Staking {
+        Staking::with_emission(self.emission.clone())
+            .with_unbonding_delay(self.unbonding_delay)
+            .with_jail_duration(self.jail_duration)
+    }
+
+    pub fn stakes(&self) -> HashMap<String, u64> {
+        self.validators
+            .iter()
+            .map(|v| (v.name.clone(), v.stake))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::genesis::Genesis;
+    use crate::utils::crypto::BlstCrypto;
+
+    fn pubkey_hex(name: &str) -> String {
+        hex::encode(&BlstCrypto::new(name.into()).unwrap().validator_pubkey().0)
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_genesis_spec() {
+        let json = format!(
+            r#"{{
+                "validators": [
+                    {{ "name": "node-1", "pubkey": "{}", "stake": 100 }},
+                    {{ "name": "node-2", "pubkey": "{}", "stake": 200 }}
+                ],
+                "contracts": [
+                    {{ "name": "counter", "verifier": "risc0", "program_id": "0a0b", "state_digest": "00" }}
+                ]
+            }}"#,
+            pubkey_hex("node-1"),
+            pubkey_hex("node-2")
+        );
+        let toml = format!(
+            r#"
+            [[validators]]
+            name = "node-1"
+            pubkey = "{}"
+            stake = 100
+
+            [[validators]]
+            name = "node-2"
+            pubkey = "{}"
+            stake = 200
+
+            [[contracts]]
+            name = "counter"
+            verifier = "risc0"
+            program_id = "0a0b"
+            state_digest = "00"
+            "#,
+            pubkey_hex("node-1"),
+            pubkey_hex("node-2")
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let (json_path, toml_path) = (
+            dir.path().join("genesis.json"),
+            dir.path().join("genesis.toml"),
+        );
+        std::fs::write(&json_path, json).unwrap();
+        std::fs::write(&toml_path, toml).unwrap();
+        let spec = GenesisSpec::load(&json_path).unwrap();
+        assert_eq!(GenesisSpec::load(&toml_path).unwrap(), spec);
+        assert_eq!(spec.stakes().get("node-2"), Some(&200));
+
+        // Built the same way every time
+        let block = Genesis::genesis_block_from_spec(&spec).await.unwrap();
+        assert_eq!(
+            Genesis::genesis_block_from_spec(&spec).await.unwrap(),
+            block
+        );
+        assert_eq!(block.consensus_proposal.staking_actions.len(), 2);
+        let registers_counter = block.txs().iter().any(|tx| match &tx.transaction_data {
+            TransactionData::Blob(blob_tx) => blob_tx.blobs.iter().any(|blob| {
+                StructuredBlobData::<RegisterContractAction>::try_from(blob.data.clone())
+                    .is_ok_and(|data| data.parameters.contract_name.0 == "counter")
+            }),
+            _ => false,
+        });
+        assert!(registers_counter);
+
+        // Built-in contracts can't be replaced
+        let mut invalid = spec.clone();
+        invalid.contracts[0].name = "hyllar".into();
+        assert!(Genesis::genesis_block_from_spec(&invalid).await.is_err());
+
+        let mut invalid = spec.clone();
+        invalid.validators[1].name = "node-1".into();
+        assert!(invalid.validate().is_err());
+        let mut invalid = spec.clone();
+        invalid.contracts[0].program_id = "not hex".into();
+        assert!(invalid.validate().is_err());
+    }
+}
@@ -1,19 +1,110 @@
-use crate::utils::logger::LogMe;
-
 use super::IndexerApiState;
+use anyhow::Context;
 use api::{
-    APIBlob, APIBlock, APIContract, APIContractState, APITransaction, BlobWithStatus,
+    APIBlob, APIBlock, APIContract, APIContractProofStats, APIContractState, APIContractUpdate,
+    APIEpoch, APIIdentity, APISettlementSummary, APITransaction, APITransactionStatus,
+    APITransactionStatusQuery, BlobDataEncoding, BlobWithStatus, EncodedBlobData,
     TransactionStatus, TransactionType, TransactionWithBlobs,
 };
 use axum::{
-    extract::{Path, Query, State},
-    http::StatusCode,
+    extract::{
+        rejection::{JsonRejection, PathRejection, QueryRejection},
+        FromRequest, FromRequestParts, State,
+    },
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
+use hyle_model::errors::{ErrorCode, ProblemDetails, PROBLEM_JSON};
 use sqlx::Row;
 use utoipa::OpenApi;
 
 use crate::model::*;
+use crate::utils::conf::IndexerBlobDataConf;
+
+/// Error of the indexer API, served as `application/problem+json`
+#[derive(Debug)]
+pub struct ApiError(pub ProblemDetails);
+
+impl ApiError {
+    pub fn not_found(detail: impl Into<String>) -> Self {
+        Self(ProblemDetails::new(ErrorCode::NotFound, detail))
+    }
+
+    pub fn bad_request(detail: impl Into<String>) -> Self {
+        Self(ProblemDetails::new(ErrorCode::BadRequest, detail))
+    }
+
+    fn with_details(self, details: serde_json::Value) -> Self {
+        Self(self.0.with_details(details))
+    }
+}
+
+// Database errors are logged here, clients only learn the query failed
+impl From<sqlx::Error> for ApiError {
+    fn from(err: sqlx::Error) -> Self {
+        tracing::error!("Indexer API query failed: {:#}", err);
+        Self(ProblemDetails::new(
+            ErrorCode::Database,
+            "Database query failed",
+        ))
+    }
+}
+
+// Same for other errors, whose chain may tell about the indexer's internals
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        tracing::error!("Indexer API error: {:#}", err);
+        Self(ProblemDetails::new(ErrorCode::Internal, "Internal error"))
+    }
+}
+
+impl From<PathRejection> for ApiError {
+    fn from(rejection: PathRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+            .with_details(serde_json::json!({ "source": "path" }))
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+            .with_details(serde_json::json!({ "source": "query" }))
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        Self::bad_request(rejection.body_text())
+            .with_details(serde_json::json!({ "source": "body" }))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status =
+            StatusCode::from_u16(self.0.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status, [(header::CONTENT_TYPE, PROBLEM_JSON)], Json(self.0)).into_response()
+    }
+}
+
+/// Path parameters, rejected with an [ApiError]
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
+
+/// Query parameters, rejected with an [ApiError]
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+/// JSON request body, rejected with an [ApiError]
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct JsonBody<T>(pub T);
+
+/// Maximum transactions looked up by a single [get_transactions_status] request
+pub const MAX_STATUS_LOOKUP: usize = 1000;
 
 #[derive(Debug, serde::Deserialize)]
 pub struct BlockPagination {
@@ -21,6 +112,33 @@ pub struct BlockPagination {
     pub nb_results: Option<i64>,
 }
 
+/// How blob data is written in the response, defaults to the node's configuration
+#[derive(Debug, serde::Deserialize)]
+pub struct BlobDataQuery {
+    pub data_encoding: Option<BlobDataEncoding>,
+    /// Serve the whole data of large blobs instead of truncating it
+    #[serde(default)]
+    pub full: bool,
+}
+
+impl BlobDataQuery {
+    /// Encoding and size limit to apply, given the node's defaults
+    fn options(&self, conf: &IndexerBlobDataConf) -> (BlobDataEncoding, Option<usize>) {
+        (
+            self.data_encoding.unwrap_or(conf.encoding),
+            (!self.full).then_some(conf.max_size),
+        )
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct IdentitySearch {
+    /// Only identities containing this string
+    pub search: Option<String>,
+    pub offset: Option<i64>,
+    pub nb_results: Option<i64>,
+}
+
 #[derive(OpenApi)]
 #[openapi(paths(get_blocks))]
 pub(super) struct IndexerAPI;
@@ -30,13 +148,15 @@ pub(super) struct IndexerAPI;
     tag = "Indexer",
     path = "/blocks",
     responses(
-        (status = OK, body = [APIBlock])
+        (status = OK, body = [APIBlock]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_blocks(
     Query(pagination): Query<BlockPagination>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<Vec<APIBlock>>, StatusCode> {
+) -> Result<Json<Vec<APIBlock>>, ApiError> {
     let blocks = match pagination.start_block {
         Some(start_block) => sqlx::query_as::<_, BlockDb>(
             "SELECT * FROM blocks WHERE height <= $1 and height > $2 ORDER BY height DESC LIMIT $3",
@@ -47,10 +167,9 @@ pub async fn get_blocks(
         None => sqlx::query_as::<_, BlockDb>("SELECT * FROM blocks ORDER BY height DESC LIMIT $1")
             .bind(pagination.nb_results.unwrap_or(10)),
     }
-    .fetch_all(&state.db)
+    .fetch_all(state.read_db())
     .await
-    .map(|db| db.into_iter().map(Into::<APIBlock>::into).collect())
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map(|db| db.into_iter().map(Into::<APIBlock>::into).collect())?;
 
     Ok(Json(blocks))
 }
@@ -60,21 +179,22 @@ pub async fn get_blocks(
     tag = "Indexer",
     path = "/block/last",
     responses(
-        (status = OK, body = APIBlock)
+        (status = OK, body = APIBlock),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_last_block(
     State(state): State<IndexerApiState>,
-) -> Result<Json<APIBlock>, StatusCode> {
+) -> Result<Json<APIBlock>, ApiError> {
     let block = sqlx::query_as::<_, BlockDb>("SELECT * FROM blocks ORDER BY height DESC LIMIT 1")
-        .fetch_optional(&state.db)
+        .fetch_optional(state.read_db())
         .await
-        .map(|db| db.map(Into::<APIBlock>::into))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map(|db| db.map(Into::<APIBlock>::into))?;
 
     match block {
         Some(block) => Ok(Json(block)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found("No block indexed yet")),
     }
 }
 
@@ -86,23 +206,25 @@ pub async fn get_last_block(
         ("height" = String, Path, description = "Block height")
     ),
     responses(
-        (status = OK, body = APIBlock)
+        (status = OK, body = APIBlock),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_block(
     Path(height): Path<i64>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<APIBlock>, StatusCode> {
+) -> Result<Json<APIBlock>, ApiError> {
     let block = sqlx::query_as::<_, BlockDb>("SELECT * FROM blocks WHERE height = $1")
         .bind(height)
-        .fetch_optional(&state.db)
+        .fetch_optional(state.read_db())
         .await
-        .map(|db| db.map(Into::<APIBlock>::into))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map(|db| db.map(Into::<APIBlock>::into))?;
 
     match block {
         Some(block) => Ok(Json(block)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!("No block at height {height}"))),
     }
 }
 
@@ -114,38 +236,75 @@ pub async fn get_block(
     ),
     path = "/block/hash/{hash}",
     responses(
-        (status = OK, body = APIBlock)
+        (status = OK, body = APIBlock),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_block_by_hash(
     Path(hash): Path<String>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<APIBlock>, StatusCode> {
+) -> Result<Json<APIBlock>, ApiError> {
     let block = sqlx::query_as::<_, BlockDb>("SELECT * FROM blocks WHERE hash = $1")
-        .bind(hash)
-        .fetch_optional(&state.db)
+        .bind(&hash)
+        .fetch_optional(state.read_db())
         .await
-        .map(|db| db.map(Into::<APIBlock>::into))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map(|db| db.map(Into::<APIBlock>::into))?;
 
     match block {
         Some(block) => Ok(Json(block)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!("No block with hash {hash}"))),
     }
 }
 
+#[utoipa::path(
+    get,
+    tag = "Indexer",
+    path = "/block/{height}/settlements",
+    params(
+        ("height" = String, Path, description = "Block height")
+    ),
+    responses(
+        (status = OK, body = [APISettlementSummary]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_block_settlements(
+    Path(height): Path<i64>,
+    State(state): State<IndexerApiState>,
+) -> Result<Json<Vec<APISettlementSummary>>, ApiError> {
+    let summaries = sqlx::query_as::<_, SettlementSummaryDb>(
+        r#"
+        SELECT ss.contract_name, ss.settled_blobs, ss.failed_txs, ss.initial_state_digest, ss.final_state_digest
+        FROM settlement_summaries ss
+        JOIN blocks b ON ss.block_hash = b.hash
+        WHERE b.height = $1
+        ORDER BY ss.contract_name"#,
+    )
+    .bind(height)
+    .fetch_all(state.read_db())
+    .await
+    .map(|db| db.into_iter().map(Into::<APISettlementSummary>::into).collect())?;
+
+    Ok(Json(summaries))
+}
+
 #[utoipa::path(
     get,
     tag = "Indexer",
     path = "/transactions",
     responses(
-        (status = OK, body = [APITransaction])
+        (status = OK, body = [APITransaction]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_transactions(
     Query(pagination): Query<BlockPagination>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<Vec<APITransaction>>, StatusCode> {
+) -> Result<Json<Vec<APITransaction>>, ApiError> {
     let transactions = match pagination.start_block {
         Some(start_block) => sqlx::query_as::<_, TransactionDb>(
             r#"
@@ -171,10 +330,9 @@ pub async fn get_transactions(
         )
         .bind(pagination.nb_results.unwrap_or(10)),
     }
-    .fetch_all(&state.db)
+    .fetch_all(state.read_db())
     .await
-    .map(|db| db.into_iter().map(Into::<APITransaction>::into).collect())
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map(|db| db.into_iter().map(Into::<APITransaction>::into).collect())?;
 
     Ok(Json(transactions))
 }
@@ -187,14 +345,16 @@ pub async fn get_transactions(
     ),
     path = "/transactions/contract/{contract_name}",
     responses(
-        (status = OK, body = [APITransaction])
+        (status = OK, body = [APITransaction]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_transactions_by_contract(
     Path(contract_name): Path<String>,
     Query(pagination): Query<BlockPagination>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<Vec<APITransaction>>, StatusCode> {
+) -> Result<Json<Vec<APITransaction>>, ApiError> {
     let transactions = match pagination.start_block {
         Some(start_block) => sqlx::query_as::<_, TransactionDb>(
             r#"
@@ -224,10 +384,9 @@ pub async fn get_transactions_by_contract(
         .bind(contract_name)
         .bind(pagination.nb_results.unwrap_or(10)),
     }
-    .fetch_all(&state.db)
+    .fetch_all(state.read_db())
     .await
-    .map(|db| db.into_iter().map(Into::<APITransaction>::into).collect())
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map(|db| db.into_iter().map(Into::<APITransaction>::into).collect())?;
 
     // This could return 404 if the contract doesn't exist,
     // but not done for now as it would take an extra query
@@ -242,14 +401,16 @@ pub async fn get_transactions_by_contract(
     ),
     path = "/transactions/block/{height}",
     responses(
-        (status = OK, body = [APITransaction])
+        (status = OK, body = [APITransaction]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 // TODO: pagination ?
 pub async fn get_transactions_by_height(
     Path(height): Path<i64>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<Vec<APITransaction>>, StatusCode> {
+) -> Result<Json<Vec<APITransaction>>, ApiError> {
     let transactions = sqlx::query_as::<_, TransactionDb>(
         r#"
         SELECT t.*
@@ -260,10 +421,9 @@ pub async fn get_transactions_by_height(
         "#,
     )
     .bind(height)
-    .fetch_all(&state.db)
+    .fetch_all(state.read_db())
     .await
-    .map(|db| db.into_iter().map(Into::<APITransaction>::into).collect())
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map(|db| db.into_iter().map(Into::<APITransaction>::into).collect())?;
 
     Ok(Json(transactions))
 }
@@ -276,13 +436,16 @@ pub async fn get_transactions_by_height(
     ),
     path = "/transaction/hash/{tx_hash}",
     responses(
-        (status = OK, body = APITransaction)
+        (status = OK, body = APITransaction),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_transaction_with_hash(
     Path(tx_hash): Path<String>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<APITransaction>, StatusCode> {
+) -> Result<Json<APITransaction>, ApiError> {
     let transaction = sqlx::query_as::<_, TransactionDb>(
         r#"
         SELECT *
@@ -291,16 +454,54 @@ pub async fn get_transaction_with_hash(
         ORDER BY index ASC
         "#,
     )
-    .bind(tx_hash)
-    .fetch_optional(&state.db)
+    .bind(&tx_hash)
+    .fetch_optional(state.read_db())
     .await
-    .map(|db| db.map(Into::<APITransaction>::into))
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map(|db| db.map(Into::<APITransaction>::into))?;
 
     match transaction {
         Some(tx) => Ok(Json(tx)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!(
+            "No transaction with hash {tx_hash}"
+        ))),
+    }
+}
+
+#[utoipa::path(
+    post,
+    tag = "Indexer",
+    path = "/transactions/status",
+    request_body = APITransactionStatusQuery,
+    responses(
+        (status = OK, description = "Statuses of the transactions found, unknown ones are left out", body = [APITransactionStatus]),
+        (status = BAD_REQUEST, description = "Invalid body or too many transactions", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_transactions_status(
+    State(state): State<IndexerApiState>,
+    JsonBody(query): JsonBody<APITransactionStatusQuery>,
+) -> Result<Json<Vec<APITransactionStatus>>, ApiError> {
+    if query.tx_hashes.len() > MAX_STATUS_LOOKUP {
+        return Err(ApiError::bad_request(format!(
+            "At most {MAX_STATUS_LOOKUP} transactions can be looked up at once, got {}",
+            query.tx_hashes.len()
+        )));
     }
+    let tx_hashes: Vec<String> = query.tx_hashes.into_iter().map(|hash| hash.0).collect();
+    let statuses = sqlx::query_as::<_, TransactionStatusDb>(
+        "SELECT tx_hash, block_hash, transaction_status FROM transactions WHERE tx_hash = ANY($1)",
+    )
+    .bind(tx_hashes)
+    .fetch_all(state.read_db())
+    .await
+    .map(|db| {
+        db.into_iter()
+            .map(Into::<APITransactionStatus>::into)
+            .collect()
+    })?;
+
+    Ok(Json(statuses))
 }
 
 #[utoipa::path(
@@ -308,16 +509,22 @@ pub async fn get_transaction_with_hash(
     tag = "Indexer",
     params(
         ("contract_name" = String, Path, description = "Contract name"),
+        ("data_encoding" = Option<BlobDataEncoding>, Query, description = "Encoding of blob data"),
+        ("full" = Option<bool>, Query, description = "Don't truncate large blobs"),
     ),
     path = "/blob_transactions/contract/{contract_name}",
     responses(
-        (status = OK, body = [TransactionWithBlobs])
+        (status = OK, body = [TransactionWithBlobs]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_blob_transactions_by_contract(
     Path(contract_name): Path<String>,
+    Query(blob_data): Query<BlobDataQuery>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<Vec<TransactionWithBlobs>>, StatusCode> {
+) -> Result<Json<Vec<TransactionWithBlobs>>, ApiError> {
+    let (encoding, max_size) = blob_data.options(&state.blob_data);
     let rows = sqlx::query(
         r#"
         with blobs as (
@@ -325,7 +532,7 @@ pub async fn get_blob_transactions_by_contract(
             FROM blobs
             LEFT JOIN blob_proof_outputs ON blobs.tx_hash = blob_proof_outputs.blob_tx_hash AND blobs.blob_index = blob_proof_outputs.blob_index
             WHERE blobs.contract_name = $1
-            GROUP BY blobs.tx_hash, blobs.blob_index, blobs.identity
+            GROUP BY blobs.tx_hash, blobs.blob_index, blobs.block_height, blobs.identity
         )
         SELECT
             t.tx_hash,
@@ -334,6 +541,7 @@ pub async fn get_blob_transactions_by_contract(
             t.version,
             t.transaction_type,
             t.transaction_status,
+            t.tx_context,
             b.identity,
             array_agg(ROW(b.contract_name, b.data, b.proof_outputs)) AS blobs
         FROM blobs b
@@ -345,14 +553,13 @@ pub async fn get_blob_transactions_by_contract(
             t.version,
             t.transaction_type,
             t.transaction_status,
+            t.tx_context,
             b.identity
         "#,
     )
     .bind(contract_name.clone())
-    .fetch_all(&state.db)
-    .await
-    .log_error("Failed to fetch transactions with blobs")
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .fetch_all(state.read_db())
+    .await?;
 
     let transactions: Result<Vec<TransactionWithBlobs>, anyhow::Error> = rows
         .into_iter()
@@ -363,6 +570,7 @@ pub async fn get_blob_transactions_by_contract(
             let version: i32 = row.try_get("version")?;
             let transaction_type: TransactionType = row.try_get("transaction_type")?;
             let transaction_status: TransactionStatus = row.try_get("transaction_status")?;
+            let tx_context: Option<sqlx::types::Json<TxContext>> = row.try_get("tx_context")?;
             let identity: String = row.try_get("identity")?;
             let blobs: Vec<(String, Vec<u8>, Vec<serde_json::Value>)> = row.try_get("blobs")?;
 
@@ -373,7 +581,7 @@ pub async fn get_blob_transactions_by_contract(
                 .into_iter()
                 .map(|(contract_name, data, proof_outputs)| BlobWithStatus {
                     contract_name,
-                    data,
+                    data: EncodedBlobData::new(&data, encoding, max_size),
                     proof_outputs,
                 })
                 .collect();
@@ -387,16 +595,13 @@ pub async fn get_blob_transactions_by_contract(
                 transaction_status,
                 identity,
                 blobs,
+                tx_context: tx_context.map(|ctx| ctx.0),
             })
         })
         .collect();
-    match transactions {
-        Ok(transactions) => Ok(Json(transactions)),
-        Err(e) => {
-            tracing::warn!("Failed to parse transactions with blobs: {:?}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
+    Ok(Json(
+        transactions.context("Parsing transactions with blobs")?,
+    ))
 }
 
 #[utoipa::path(
@@ -404,23 +609,32 @@ pub async fn get_blob_transactions_by_contract(
     tag = "Indexer",
     params(
         ("tx_hash" = String, Path, description = "Tx hash"),
+        ("data_encoding" = Option<BlobDataEncoding>, Query, description = "Encoding of blob data"),
+        ("full" = Option<bool>, Query, description = "Don't truncate large blobs"),
     ),
     path = "/blobs/hash/{tx_hash}",
     responses(
-        (status = OK, body = [APIBlob])
+        (status = OK, body = [APIBlob]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_blobs_by_tx_hash(
     Path(tx_hash): Path<String>,
+    Query(blob_data): Query<BlobDataQuery>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<Vec<APIBlob>>, StatusCode> {
+) -> Result<Json<Vec<APIBlob>>, ApiError> {
+    let (encoding, max_size) = blob_data.options(&state.blob_data);
     // TODO: Order transaction ?
     let blobs = sqlx::query_as::<_, BlobDb>("SELECT * FROM blobs WHERE tx_hash = $1")
         .bind(tx_hash)
-        .fetch_all(&state.db)
+        .fetch_all(state.read_db())
         .await
-        .map(|db| db.into_iter().map(Into::<APIBlob>::into).collect())
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map(|db| {
+            db.into_iter()
+                .map(|blob| blob.into_api(encoding, max_size))
+                .collect()
+        })?;
 
     // This could return 404 if the transaction doesn't exist,
     // but not done for now as it would take an extra query
@@ -433,28 +647,36 @@ pub async fn get_blobs_by_tx_hash(
     params(
         ("tx_hash" = String, Path, description = "Tx hash"),
         ("blob_index" = String, Path, description = "Blob index"),
+        ("data_encoding" = Option<BlobDataEncoding>, Query, description = "Encoding of blob data"),
+        ("full" = Option<bool>, Query, description = "Don't truncate large blobs"),
     ),
     path = "/blob/hash/{tx_hash}/index/{blob_index}",
     responses(
-        (status = OK, body = APIBlob)
+        (status = OK, body = APIBlob),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_blob(
     Path((tx_hash, blob_index)): Path<(String, i32)>,
+    Query(blob_data): Query<BlobDataQuery>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<APIBlob>, StatusCode> {
+) -> Result<Json<APIBlob>, ApiError> {
+    let (encoding, max_size) = blob_data.options(&state.blob_data);
     let blob =
         sqlx::query_as::<_, BlobDb>("SELECT * FROM blobs WHERE tx_hash = $1 AND blob_index = $2")
-            .bind(tx_hash)
+            .bind(&tx_hash)
             .bind(blob_index)
-            .fetch_optional(&state.db)
+            .fetch_optional(state.read_db())
             .await
-            .map(|db| db.map(Into::<APIBlob>::into))
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map(|db| db.map(|blob| blob.into_api(encoding, max_size)))?;
 
     match blob {
         Some(blob) => Ok(Json(blob)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!(
+            "No blob {blob_index} in transaction {tx_hash}"
+        ))),
     }
 }
 
@@ -463,17 +685,17 @@ pub async fn get_blob(
     tag = "Indexer",
     path = "/contracts",
     responses(
-        (status = OK, body = [APIContract])
+        (status = OK, body = [APIContract]),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn list_contracts(
     State(state): State<IndexerApiState>,
-) -> Result<Json<Vec<APIContract>>, StatusCode> {
+) -> Result<Json<Vec<APIContract>>, ApiError> {
     let contract = sqlx::query_as::<_, ContractDb>("SELECT * FROM contracts")
-        .fetch_all(&state.db)
+        .fetch_all(state.read_db())
         .await
-        .map(|db| db.into_iter().map(Into::<APIContract>::into).collect())
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+        .map(|db| db.into_iter().map(Into::<APIContract>::into).collect())?;
 
     Ok(Json(contract))
 }
@@ -486,25 +708,175 @@ pub async fn list_contracts(
     ),
     path = "/contract/{contract_name}",
     responses(
-        (status = OK, body = APIContract)
+        (status = OK, body = APIContract),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_contract(
     Path(contract_name): Path<String>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<APIContract>, StatusCode> {
+) -> Result<Json<APIContract>, ApiError> {
     let contract =
         sqlx::query_as::<_, ContractDb>("SELECT * FROM contracts WHERE contract_name = $1")
-            .bind(contract_name)
-            .fetch_optional(&state.db)
+            .bind(&contract_name)
+            .fetch_optional(state.read_db())
             .await
-            .map(|db| db.map(Into::<APIContract>::into))
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+            .map(|db| db.map(Into::<APIContract>::into))?;
 
     match contract {
         Some(contract) => Ok(Json(contract)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!("No contract {contract_name}"))),
+    }
+}
+
+#[utoipa::path(
+    get,
+    tag = "Indexer",
+    params(
+        ("contract_name" = String, Path, description = "Contract name"),
+    ),
+    path = "/contract/{contract_name}/updates",
+    responses(
+        (status = OK, body = [APIContractUpdate]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_contract_updates(
+    Path(contract_name): Path<String>,
+    State(state): State<IndexerApiState>,
+) -> Result<Json<Vec<APIContractUpdate>>, ApiError> {
+    let updates = sqlx::query_as::<_, ContractUpdateDb>(
+        "SELECT * FROM contract_updates WHERE contract_name = $1 ORDER BY block_height ASC",
+    )
+    .bind(contract_name)
+    .fetch_all(state.read_db())
+    .await
+    .map(|db| {
+        db.into_iter()
+            .map(Into::<APIContractUpdate>::into)
+            .collect()
+    })?;
+
+    Ok(Json(updates))
+}
+
+#[utoipa::path(
+    get,
+    tag = "Indexer",
+    params(
+        ("search" = Option<String>, Query, description = "Only identities containing this string"),
+        ("offset" = Option<i64>, Query, description = "Identities to skip"),
+        ("nb_results" = Option<i64>, Query, description = "Maximum identities returned, 10 by default"),
+    ),
+    path = "/identities",
+    responses(
+        (status = OK, body = [APIIdentity]),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_identities(
+    Query(search): Query<IdentitySearch>,
+    State(state): State<IndexerApiState>,
+) -> Result<Json<Vec<APIIdentity>>, ApiError> {
+    let identities = sqlx::query_as::<_, IdentityDb>(
+        r#"
+        SELECT a.identity, a.first_seen_height, a.tx_count,
+            ARRAY(
+                SELECT DISTINCT unnest(ia.contracts)
+                FROM identity_activity ia
+                WHERE ia.identity = a.identity
+                ORDER BY 1
+            ) AS contracts
+        FROM (
+            SELECT identity, MIN(block_height) AS first_seen_height, SUM(tx_count)::BIGINT AS tx_count
+            FROM identity_activity
+            WHERE $1::TEXT IS NULL OR strpos(identity, $1) > 0
+            GROUP BY identity
+        ) a
+        ORDER BY a.first_seen_height DESC, a.identity ASC
+        LIMIT $2 OFFSET $3"#,
+    )
+    .bind(search.search)
+    .bind(search.nb_results.unwrap_or(10))
+    .bind(search.offset.unwrap_or(0))
+    .fetch_all(state.read_db())
+    .await
+    .map(|db| db.into_iter().map(Into::<APIIdentity>::into).collect())?;
+
+    Ok(Json(identities))
+}
+
+#[utoipa::path(
+    get,
+    tag = "Indexer",
+    params(
+        ("contract_name" = String, Path, description = "Contract name"),
+    ),
+    path = "/contract/{contract_name}/stats",
+    responses(
+        (status = OK, body = APIContractProofStats),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_contract_proof_stats(
+    Path(contract_name): Path<String>,
+    Query(pagination): Query<BlockPagination>,
+    State(state): State<IndexerApiState>,
+) -> Result<Json<APIContractProofStats>, ApiError> {
+    let db = state.read_db();
+    let exists: Option<String> =
+        sqlx::query_scalar("SELECT contract_name FROM contracts WHERE contract_name = $1")
+            .bind(&contract_name)
+            .fetch_optional(db)
+            .await?;
+    if exists.is_none() {
+        return Err(ApiError::not_found(format!("No contract {contract_name}")));
     }
+
+    let totals = sqlx::query(
+        r#"
+        SELECT COALESCE(SUM(proofs), 0)::BIGINT AS proofs,
+               COALESCE(SUM(proof_bytes), 0)::BIGINT AS proof_bytes,
+               COALESCE(SUM(proof_outputs), 0)::BIGINT AS proof_outputs
+        FROM contract_proof_stats
+        WHERE contract_name = $1"#,
+    )
+    .bind(&contract_name)
+    .fetch_one(db)
+    .await?;
+    let total = |column: &str| -> Result<u64, ApiError> {
+        let value = totals.try_get::<i64, _>(column)?;
+        Ok(u64::try_from(value).context("Negative proof total")?)
+    };
+
+    let blocks = sqlx::query_as::<_, BlockProofStatsDb>(
+        r#"
+        SELECT block_hash, block_height, proofs, proof_bytes, proof_outputs
+        FROM contract_proof_stats
+        WHERE contract_name = $1 AND block_height <= $2
+        ORDER BY block_height DESC
+        LIMIT $3"#,
+    )
+    .bind(&contract_name)
+    .bind(pagination.start_block.unwrap_or(i64::MAX))
+    .bind(pagination.nb_results.unwrap_or(10))
+    .fetch_all(db)
+    .await
+    .map(|db| db.into_iter().map(Into::into).collect())?;
+
+    Ok(Json(APIContractProofStats {
+        proofs: total("proofs")?,
+        proof_bytes: total("proof_bytes")?,
+        proof_outputs: total("proof_outputs")?,
+        contract_name,
+        blocks,
+    }))
 }
 
 #[utoipa::path(
@@ -516,13 +888,16 @@ pub async fn get_contract(
     ),
     path = "/state/contract/{contract_name}/block/{height}",
     responses(
-        (status = OK, body = APIContractState)
+        (status = OK, body = APIContractState),
+        (status = BAD_REQUEST, description = "Invalid parameters", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = NOT_FOUND, description = "Not found", body = ProblemDetails, content_type = "application/problem+json"),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
     )
 )]
 pub async fn get_contract_state_by_height(
     Path((contract_name, height)): Path<(String, i64)>,
     State(state): State<IndexerApiState>,
-) -> Result<Json<APIContractState>, StatusCode> {
+) -> Result<Json<APIContractState>, ApiError> {
     let contract = sqlx::query_as::<_, ContractStateDb>(
         r#"
         SELECT cs.*
@@ -530,15 +905,36 @@ pub async fn get_contract_state_by_height(
         JOIN blocks b ON cs.block_hash = b.hash
         WHERE contract_name = $1 AND height = $2"#,
     )
-    .bind(contract_name)
+    .bind(&contract_name)
     .bind(height)
-    .fetch_optional(&state.db)
+    .fetch_optional(state.read_db())
     .await
-    .map(|db| db.map(Into::<APIContractState>::into))
-    .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    .map(|db| db.map(Into::<APIContractState>::into))?;
 
     match contract {
         Some(contract) => Ok(Json(contract)),
-        None => Err(StatusCode::NOT_FOUND),
+        None => Err(ApiError::not_found(format!(
+            "No state of contract {contract_name} at height {height}"
+        ))),
     }
 }
+
+#[utoipa::path(
+    get,
+    tag = "Indexer",
+    path = "/epochs",
+    responses(
+        (status = OK, body = [APIEpoch]),
+        (status = INTERNAL_SERVER_ERROR, description = "Database or internal error", body = ProblemDetails, content_type = "application/problem+json")
+    )
+)]
+pub async fn get_epochs(
+    State(state): State<IndexerApiState>,
+) -> Result<Json<Vec<APIEpoch>>, ApiError> {
+    let epochs = sqlx::query_as::<_, EpochDb>("SELECT * FROM epochs ORDER BY epoch ASC")
+        .fetch_all(state.read_db())
+        .await
+        .map(|db| db.into_iter().map(Into::<APIEpoch>::into).collect())?;
+
+    Ok(Json(epochs))
+}
@@ -0,0 +1,570 @@
+//! Cross-check of the indexed blocks against the blocks stored by the DA module. Final
+//! statuses aren't stored with the blocks: they are replayed with a fresh node state from
+//! genesis. Discrepancies can be repaired by re-indexing the replayed blocks from the first
+//! diverging height.
+
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::{bail, Context, Result};
+use axum::{
+    extract::{Query as QueryParams, State},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use tracing::{info, warn};
+use utoipa::{OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::Indexer;
+use crate::{
+    bus::{
+        bus_client,
+        command_response::{CmdRespClient, Query},
+        metrics::BusMetrics,
+    },
+    data_availability::QueryDaBlocks,
+    model::{
+        api::TransactionStatus, Block, BlockHash, BlockHeight, CommonRunContext, Hashable,
+        SignedBlock, TransactionData, TxHash,
+    },
+    node_state::NodeState,
+    rest::AppError,
+    utils::static_type_map::Pick,
+};
+
+/// Blocks checked by a single query
+pub const MAX_CHECKED_BLOCKS: u64 = 10_000;
+
+#[derive(Clone)]
+pub struct QueryConsistencyCheck {
+    pub from: BlockHeight,
+    pub to: BlockHeight,
+    pub repair: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APITxCountMismatch {
+    pub block_height: BlockHeight,
+    pub da_txs: usize,
+    pub indexed_txs: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIBlockTx {
+    pub block_height: BlockHeight,
+    pub tx_hash: TxHash,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APITxStatusMismatch {
+    /// Height of the block the transaction was sequenced in
+    pub block_height: BlockHeight,
+    pub tx_hash: TxHash,
+    pub expected: TransactionStatus,
+    pub indexed: TransactionStatus,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema, PartialEq, Eq)]
+pub struct APIConsistencyReport {
+    pub from: BlockHeight,
+    pub to: BlockHeight,
+    /// Blocks stored by DA but not indexed
+    pub missing_blocks: Vec<BlockHeight>,
+    /// Blocks indexed with another hash than the stored one
+    pub hash_mismatches: Vec<BlockHeight>,
+    pub tx_count_mismatches: Vec<APITxCountMismatch>,
+    /// Transactions of the stored blocks that aren't indexed, or indexed in another block
+    pub missing_txs: Vec<APIBlockTx>,
+    /// Blob transactions indexed with another status than their replayed final status
+    pub status_mismatches: Vec<APITxStatusMismatch>,
+    /// Height the indexer was re-indexed from, if repaired
+    pub reindexed_from: Option<BlockHeight>,
+}
+
+impl APIConsistencyReport {
+    /// Lowest height with a discrepancy
+    pub fn first_divergence(&self) -> Option<BlockHeight> {
+        self.missing_blocks
+            .iter()
+            .chain(self.hash_mismatches.iter())
+            .chain(self.tx_count_mismatches.iter().map(|m| &m.block_height))
+            .chain(self.missing_txs.iter().map(|tx| &tx.block_height))
+            .chain(self.status_mismatches.iter().map(|m| &m.block_height))
+            .map(|height| height.0)
+            .min()
+            .map(BlockHeight)
+    }
+}
+
+/// What the indexer has for the checked blocks
+#[derive(Debug, Default)]
+pub struct IndexedRange {
+    pub blocks: HashMap<BlockHeight, BlockHash>,
+    pub tx_counts: HashMap<BlockHash, usize>,
+    /// Block and status of the transactions of the stored blocks
+    pub txs: HashMap<TxHash, (BlockHash, TransactionStatus)>,
+}
+
+/// Compares the replayed blocks with the indexed ones.
+pub fn compare(
+    from: BlockHeight,
+    to: BlockHeight,
+    blocks: &[Block],
+    indexed: &IndexedRange,
+) -> APIConsistencyReport {
+    let mut report = APIConsistencyReport {
+        from,
+        to,
+        ..Default::default()
+    };
+    let final_statuses: HashMap<&TxHash, TransactionStatus> = blocks
+        .iter()
+        .flat_map(|block| {
+            block
+                .successful_txs
+                .iter()
+                .map(|tx| (tx, TransactionStatus::Success))
+                .chain(
+                    block
+                        .failed_txs
+                        .iter()
+                        .map(|tx| (tx, TransactionStatus::Failure)),
+                )
+                .chain(
+                    block
+                        .timed_out_txs
+                        .iter()
+                        .map(|tx| (tx, TransactionStatus::TimedOut)),
+                )
+        })
+        .collect();
+
+    for block in blocks {
+        let height = block.block_height;
+        match indexed.blocks.get(&height) {
+            None => {
+                report.missing_blocks.push(height);
+                continue;
+            }
+            Some(hash) if hash != &block.hash => {
+                report.hash_mismatches.push(height);
+                continue;
+            }
+            Some(_) => {}
+        }
+        let indexed_txs = indexed.tx_counts.get(&block.hash).copied().unwrap_or(0);
+        if indexed_txs != block.txs.len() {
+            report.tx_count_mismatches.push(APITxCountMismatch {
+                block_height: height,
+                da_txs: block.txs.len(),
+                indexed_txs,
+            });
+        }
+        for tx in block.txs.iter() {
+            let tx_hash = tx.hash();
+            let Some((block_hash, status)) = indexed.txs.get(&tx_hash) else {
+                report.missing_txs.push(APIBlockTx {
+                    block_height: height,
+                    tx_hash,
+                });
+                continue;
+            };
+            if block_hash != &block.hash {
+                report.missing_txs.push(APIBlockTx {
+                    block_height: height,
+                    tx_hash,
+                });
+                continue;
+            }
+            // Transactions still unsettled at the end of the range may have settled since
+            if let TransactionData::Blob(_) = tx.transaction_data {
+                if let Some(expected) = final_statuses.get(&tx_hash) {
+                    if expected != status {
+                        report.status_mismatches.push(APITxStatusMismatch {
+                            block_height: height,
+                            tx_hash: tx_hash.clone(),
+                            expected: expected.clone(),
+                            indexed: status.clone(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    report
+}
+
+impl Indexer {
+    /// Checks the indexed blocks of the range, and re-indexes them from the first divergence if
+    /// asked to. Blocks processed meanwhile by node state and re-indexed are skipped when they
+    /// reach the indexer.
+    pub(super) async fn check_consistency(
+        &mut self,
+        query: QueryConsistencyCheck,
+    ) -> Result<APIConsistencyReport> {
+        if query.to.0 < query.from.0 || query.to.0 - query.from.0 >= MAX_CHECKED_BLOCKS {
+            bail!(
+                "Invalid range {}..={}, at most {MAX_CHECKED_BLOCKS} blocks are checked at once",
+                query.from,
+                query.to
+            );
+        }
+        let mut node_state = NodeState::default();
+        node_state.set_timeout_policy(self.timeout_policy.clone());
+
+        let mut next = BlockHeight(0);
+        let mut range_blocks = vec![];
+        'replay: loop {
+            let signed_blocks = self.stored_blocks(next).await?;
+            if signed_blocks.is_empty() {
+                break;
+            }
+            for signed_block in signed_blocks {
+                if signed_block.height().0 > query.to.0 {
+                    break 'replay;
+                }
+                check_next_height(&signed_block, next)?;
+                next = next + 1;
+                let block = node_state.handle_signed_block(&signed_block);
+                if block.block_height.0 >= query.from.0 {
+                    range_blocks.push(block);
+                }
+            }
+        }
+
+        let indexed = self.indexed_range(&query, &range_blocks).await?;
+        let mut report = compare(query.from, query.to, &range_blocks, &indexed);
+        info!(
+            "🔍 Checked indexed blocks {}..={}: first divergence at {:?}",
+            query.from,
+            query.to,
+            report.first_divergence()
+        );
+
+        let Some(reindex_from) = report.first_divergence().filter(|_| query.repair) else {
+            return Ok(report);
+        };
+        warn!("Re-indexing blocks from height {reindex_from}");
+        sqlx::query("DELETE FROM blocks WHERE height >= $1")
+            .bind(i64::try_from(reindex_from.0)?)
+            .execute(&self.state.db)
+            .await?;
+        for block in range_blocks
+            .into_iter()
+            .filter(|block| block.block_height.0 >= reindex_from.0)
+        {
+            self.handle_processed_block(block).await?;
+        }
+        // Then the blocks stored after the range, up to the last one
+        loop {
+            let signed_blocks = self.stored_blocks(next).await?;
+            if signed_blocks.is_empty() {
+                break;
+            }
+            for signed_block in signed_blocks {
+                check_next_height(&signed_block, next)?;
+                next = next + 1;
+                let block = node_state.handle_signed_block(&signed_block);
+                self.handle_processed_block(block).await?;
+            }
+        }
+        self.reindexed_until = next.0.checked_sub(1).map(BlockHeight);
+        report.reindexed_from = Some(reindex_from);
+        Ok(report)
+    }
+
+    async fn stored_blocks(&mut self, from: BlockHeight) -> Result<Vec<SignedBlock>> {
+        if Pick::<tokio::sync::broadcast::Sender<Query<QueryDaBlocks, Vec<SignedBlock>>>>::get(
+            &self.bus,
+        )
+        .receiver_count()
+            == 0
+        {
+            bail!("No data availability module runs on this node");
+        }
+        self.bus
+            .request(QueryDaBlocks(from))
+            .await
+            .context(format!("Reading stored blocks from height {from}"))
+    }
+
+    async fn indexed_range(
+        &self,
+        query: &QueryConsistencyCheck,
+        blocks: &[Block],
+    ) -> Result<IndexedRange> {
+        let mut indexed = IndexedRange::default();
+        let rows = sqlx::query("SELECT hash, height FROM blocks WHERE height BETWEEN $1 AND $2")
+            .bind(i64::try_from(query.from.0)?)
+            .bind(i64::try_from(query.to.0)?)
+            .fetch_all(&self.state.db)
+            .await?;
+        for row in rows {
+            let height: i64 = row.try_get("height")?;
+            indexed.blocks.insert(
+                BlockHeight(u64::try_from(height)?),
+                BlockHash(row.try_get("hash")?),
+            );
+        }
+
+        let block_hashes: Vec<String> = indexed.blocks.values().map(|h| h.0.clone()).collect();
+        let rows = sqlx::query(
+            "SELECT block_hash, count(*) AS count FROM transactions WHERE block_hash = ANY($1) GROUP BY block_hash",
+        )
+        .bind(block_hashes)
+        .fetch_all(&self.state.db)
+        .await?;
+        for row in rows {
+            let count: i64 = row.try_get("count")?;
+            indexed.tx_counts.insert(
+                BlockHash(row.try_get("block_hash")?),
+                usize::try_from(count)?,
+            );
+        }
+
+        let tx_hashes: Vec<String> = blocks
+            .iter()
+            .flat_map(|block| block.txs.iter().map(|tx| tx.hash().0))
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect();
+        let rows = sqlx::query(
+            "SELECT tx_hash, block_hash, transaction_status FROM transactions WHERE tx_hash = ANY($1)",
+        )
+        .bind(tx_hashes)
+        .fetch_all(&self.state.db)
+        .await?;
+        for row in rows {
+            indexed.txs.insert(
+                TxHash(row.try_get("tx_hash")?),
+                (
+                    BlockHash(row.try_get("block_hash")?),
+                    row.try_get("transaction_status")?,
+                ),
+            );
+        }
+        Ok(indexed)
+    }
+}
+
+/// Statuses can only be replayed from a store holding every block since genesis
+fn check_next_height(signed_block: &SignedBlock, expected: BlockHeight) -> Result<()> {
+    if signed_block.height() != expected {
+        bail!(
+            "Stored blocks jump from height {} to {}, statuses can't be replayed from genesis",
+            expected,
+            signed_block.height()
+        );
+    }
+    Ok(())
+}
+
+bus_client! {
+struct ConsistencyBusClient {
+    sender(Query<QueryConsistencyCheck, APIConsistencyReport>),
+}
+}
+
+pub struct RouterState {
+    bus: ConsistencyBusClient,
+}
+
+impl Clone for RouterState {
+    fn clone(&self) -> Self {
+        Self {
+            bus: ConsistencyBusClient::new(
+                Pick::<BusMetrics>::get(&self.bus).clone(),
+                Pick::<
+                    tokio::sync::broadcast::Sender<
+                        Query<QueryConsistencyCheck, APIConsistencyReport>,
+                    >,
+                >::get(&self.bus)
+                .clone(),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RangeParams {
+    from: u64,
+    to: u64,
+}
+
+#[derive(OpenApi)]
+struct ConsistencyAPI;
+
+/// Admin routes, nested under /v1/admin/indexer.
+pub async fn api(ctx: &CommonRunContext) -> Router<()> {
+    let state = RouterState {
+        bus: ConsistencyBusClient::new_from_bus(ctx.bus.new_handle()).await,
+    };
+
+    let (router, api) = OpenApiRouter::with_openapi(ConsistencyAPI::openapi())
+        .routes(routes!(check_consistency))
+        .routes(routes!(repair_consistency))
+        .split_for_parts();
+
+    if let Ok(mut o) = ctx.openapi.lock() {
+        *o = o.clone().nest("/v1/admin/indexer", api);
+    }
+
+    router.with_state(state)
+}
+
+#[utoipa::path(
+    get,
+    path = "/consistency",
+    params(
+        ("from" = u64, Query, description = "First height checked"),
+        ("to" = u64, Query, description = "Last height checked"),
+    ),
+    tag = "Indexer",
+    responses(
+        (status = OK, body = APIConsistencyReport)
+    )
+)]
+pub async fn check_consistency(
+    QueryParams(params): QueryParams<RangeParams>,
+    State(state): State<RouterState>,
+) -> Result<Json<APIConsistencyReport>, AppError> {
+    run_check(state, params, false).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/consistency/repair",
+    params(
+        ("from" = u64, Query, description = "First height checked"),
+        ("to" = u64, Query, description = "Last height checked"),
+    ),
+    tag = "Indexer",
+    responses(
+        (status = OK, body = APIConsistencyReport)
+    )
+)]
+pub async fn repair_consistency(
+    QueryParams(params): QueryParams<RangeParams>,
+    State(state): State<RouterState>,
+) -> Result<Json<APIConsistencyReport>, AppError> {
+    run_check(state, params, true).await
+}
+
+async fn run_check(
+    mut state: RouterState,
+    params: RangeParams,
+    repair: bool,
+) -> Result<Json<APIConsistencyReport>, AppError> {
+    // Replaying the chain from genesis takes longer than the usual queries
+    let report = state
+        .bus
+        .request_with_timeout(
+            QueryConsistencyCheck {
+                from: BlockHeight(params.from),
+                to: BlockHeight(params.to),
+                repair,
+            },
+            std::time::Duration::from_secs(600),
+        )
+        .await?;
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::make_blob_tx;
+
+    #[test]
+    fn test_compare() {
+        let (tx1, tx2, tx3) = (
+            make_blob_tx("a.c"),
+            make_blob_tx("b.c"),
+            make_blob_tx("c.c"),
+        );
+        let blocks = vec![
+            Block {
+                block_height: BlockHeight(1),
+                hash: BlockHash("h1".into()),
+                txs: vec![tx1.clone(), tx2.clone()],
+                successful_txs: vec![tx1.hash()],
+                ..Default::default()
+            },
+            Block {
+                block_height: BlockHeight(2),
+                hash: BlockHash("h2".into()),
+                txs: vec![tx3.clone()],
+                ..Default::default()
+            },
+            Block {
+                block_height: BlockHeight(3),
+                hash: BlockHash("h3".into()),
+                ..Default::default()
+            },
+        ];
+        let mut indexed = IndexedRange {
+            blocks: HashMap::from([
+                (BlockHeight(1), BlockHash("h1".into())),
+                (BlockHeight(2), BlockHash("h2".into())),
+                (BlockHeight(3), BlockHash("h3".into())),
+            ]),
+            tx_counts: HashMap::from([(BlockHash("h1".into()), 2), (BlockHash("h2".into()), 1)]),
+            txs: HashMap::from([
+                (
+                    tx1.hash(),
+                    (BlockHash("h1".into()), TransactionStatus::Success),
+                ),
+                (
+                    tx2.hash(),
+                    (BlockHash("h1".into()), TransactionStatus::Sequenced),
+                ),
+                (
+                    tx3.hash(),
+                    (BlockHash("h2".into()), TransactionStatus::Sequenced),
+                ),
+            ]),
+        };
+        let report = compare(BlockHeight(1), BlockHeight(3), &blocks, &indexed);
+        assert_eq!(report.first_divergence(), None);
+
+        indexed.blocks.remove(&BlockHeight(3));
+        indexed
+            .blocks
+            .insert(BlockHeight(2), BlockHash("other".into()));
+        indexed.txs.insert(
+            tx1.hash(),
+            (BlockHash("h1".into()), TransactionStatus::Sequenced),
+        );
+        indexed.txs.remove(&tx2.hash());
+        indexed.tx_counts.insert(BlockHash("h1".into()), 1);
+        let report = compare(BlockHeight(1), BlockHeight(3), &blocks, &indexed);
+        assert_eq!(report.missing_blocks, vec![BlockHeight(3)]);
+        assert_eq!(report.hash_mismatches, vec![BlockHeight(2)]);
+        assert_eq!(
+            report.tx_count_mismatches,
+            vec![APITxCountMismatch {
+                block_height: BlockHeight(1),
+                da_txs: 2,
+                indexed_txs: 1,
+            }]
+        );
+        assert_eq!(
+            report.missing_txs,
+            vec![APIBlockTx {
+                block_height: BlockHeight(1),
+                tx_hash: tx2.hash(),
+            }]
+        );
+        assert_eq!(
+            report.status_mismatches,
+            vec![APITxStatusMismatch {
+                block_height: BlockHeight(1),
+                tx_hash: tx1.hash(),
+                expected: TransactionStatus::Success,
+                indexed: TransactionStatus::Sequenced,
+            }]
+        );
+        assert_eq!(report.first_divergence(), Some(BlockHeight(1)));
+    }
+}
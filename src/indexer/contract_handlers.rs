@@ -4,7 +4,7 @@ use super::contract_state_indexer::Store;
 use crate::model::BlobTransaction;
 use crate::rest::AppError;
 use anyhow::{anyhow, Context, Result};
-use axum::extract::Path;
+use axum::extract::{Path, Query};
 use axum::Router;
 use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
 use hydentity::{AccountInfo, Hydentity};
@@ -14,7 +14,7 @@ use hyle_contract_sdk::{
     Blob, BlobIndex, Identity, StructuredBlobData,
 };
 use hyllar::{HyllarToken, HyllarTokenContract};
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tokio::sync::RwLock;
 use tracing::info;
 use utoipa::openapi::OpenApi;
@@ -37,6 +37,7 @@ impl ContractHandler for Hydentity {
     async fn api(store: Arc<RwLock<Store<Self>>>) -> (Router<()>, OpenApi) {
         let (router, api) = OpenApiRouter::default()
             .routes(routes!(get_state))
+            .routes(routes!(get_state_at_height))
             .routes(routes!(get_nonce))
             .split_for_parts();
 
@@ -64,6 +65,7 @@ impl ContractHandler for HyllarToken {
     async fn api(store: Arc<RwLock<Store<HyllarToken>>>) -> (Router<()>, OpenApi) {
         let (router, api) = OpenApiRouter::default()
             .routes(routes!(get_state))
+            .routes(routes!(get_state_at_height))
             .routes(routes!(get_balance))
             .routes(routes!(get_allowance))
             .split_for_parts();
@@ -92,22 +94,88 @@ impl ContractHandler for HyllarToken {
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct StateQuery {
+    /// JSON pointer to a single field of the state, e.g. `/balances/bob.hydentity`
+    pub field: Option<String>,
+}
+
+impl StateQuery {
+    /// The JSON state, or the requested field of it.
+    pub fn select(&self, state: impl Serialize) -> Result<serde_json::Value, AppError> {
+        let state = serde_json::to_value(state)
+            .map_err(|e| AppError(StatusCode::INTERNAL_SERVER_ERROR, e.into()))?;
+        let Some(field) = &self.field else {
+            return Ok(state);
+        };
+        state.pointer(field).cloned().ok_or(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow!("No field '{field}' in state"),
+        ))
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/state",
+    params(
+        ("field" = Option<String>, Query, description = "JSON pointer to a field of the state, e.g. /balances/bob.hydentity")
+    ),
     tag = "Contract",
     responses(
         (status = OK, description = "Get json state of contract")
     )
 )]
 pub async fn get_state<S: Serialize + Clone + 'static>(
+    Query(query): Query<StateQuery>,
     State(state): State<Arc<RwLock<Store<S>>>>,
 ) -> Result<impl IntoResponse, AppError> {
     let store = state.read().await;
-    store.state.clone().map(Json).ok_or(AppError(
+    let state = store.state.as_ref().ok_or(AppError(
         StatusCode::NOT_FOUND,
         anyhow!("No state found for contract '{}'", store.contract_name),
-    ))
+    ))?;
+    query.select(state).map(Json)
+}
+
+#[derive(Serialize)]
+struct StateAtHeightResponse<S> {
+    /// Height of the snapshot, the last one taken at or before the requested height
+    block_height: u64,
+    state: S,
+}
+
+#[utoipa::path(
+    get,
+    path = "/state/{height}",
+    params(
+        ("height" = u64, Path, description = "Block height")
+    ),
+    tag = "Contract",
+    responses(
+        (status = OK, description = "Get json state of contract in the last snapshot at or before the block height")
+    )
+)]
+pub async fn get_state_at_height<S: Serialize + Clone + 'static>(
+    Path(height): Path<u64>,
+    State(state): State<Arc<RwLock<Store<S>>>>,
+) -> Result<impl IntoResponse, AppError> {
+    let store = state.read().await;
+    store
+        .state_at(height)
+        .map(|(block_height, state)| {
+            Json(StateAtHeightResponse {
+                block_height,
+                state: state.clone(),
+            })
+        })
+        .ok_or(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow!(
+                "No snapshot of contract '{}' at or before height {height}",
+                store.contract_name
+            ),
+        ))
 }
 
 #[derive(Serialize, ToSchema)]
@@ -0,0 +1,403 @@
+//! Indexing of contracts without a state indexer compiled for them: the handler of a contract is
+//! picked by its program id when it is registered, and its state is decoded from the digests
+//! settled on chain. Program ids are mapped to handlers in the configuration, or at runtime
+//! through the admin API, so that a newly deployed contract is indexed without a node release.
+
+use std::{collections::BTreeMap, path::PathBuf, sync::Arc};
+
+use anyhow::{anyhow, bail, Error, Result};
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use bincode::{Decode, Encode};
+use hydentity::Hydentity;
+use hyle_contract_sdk::{ContractName, ProgramId, StateDigest};
+use hyle_model::RegisterContractEffect;
+use hyllar::HyllarToken;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::debug;
+use utoipa::{OpenApi, ToSchema};
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::{contract_handlers::StateQuery, indexer_bus_client::IndexerBusClient};
+use crate::{
+    model::{Block, CommonRunContext},
+    module_handle_messages,
+    node_state::module::NodeStateEvent,
+    rest::AppError,
+    utils::modules::Module,
+};
+
+/// Decodes the state digest of a contract into its JSON representation.
+pub type StateDecoder = fn(&StateDigest) -> Result<serde_json::Value>;
+
+/// Handler of the contracts whose program id isn't mapped to another one.
+pub const JSON_HANDLER: &str = "json";
+
+#[derive(Debug, Clone, Encode, Decode)]
+pub struct IndexedContract {
+    pub program_id: ProgramId,
+    pub handler: String,
+    pub state_digest: StateDigest,
+}
+
+#[derive(Default, Encode, Decode)]
+pub struct RegistryStore {
+    /// Handlers by hex encoded program id, as registered through the admin API
+    pub programs: BTreeMap<String, String>,
+    pub contracts: BTreeMap<ContractName, IndexedContract>,
+}
+
+pub struct ContractHandlerRegistry {
+    decoders: BTreeMap<String, StateDecoder>,
+    store: RegistryStore,
+}
+
+impl ContractHandlerRegistry {
+    /// A registry with the built-in handlers.
+    pub fn new(store: RegistryStore) -> Self {
+        let mut registry = Self {
+            decoders: BTreeMap::new(),
+            store,
+        };
+        registry.register_decoder(JSON_HANDLER, decode_json);
+        registry.register_decoder("hyllar", decode_with::<HyllarToken>);
+        registry.register_decoder("hydentity", decode_with::<Hydentity>);
+        registry
+    }
+
+    pub fn register_decoder(&mut self, handler: &str, decoder: StateDecoder) {
+        self.decoders.insert(handler.to_string(), decoder);
+    }
+
+    /// Indexes the contracts of the program with the handler, including those already
+    /// registered on chain.
+    pub fn register_program(&mut self, program_id: &ProgramId, handler: &str) -> Result<()> {
+        if !self.decoders.contains_key(handler) {
+            bail!("Unknown contract handler '{handler}'");
+        }
+        self.store
+            .programs
+            .insert(hex::encode(&program_id.0), handler.to_string());
+        for contract in self.store.contracts.values_mut() {
+            if &contract.program_id == program_id {
+                contract.handler = handler.to_string();
+            }
+        }
+        Ok(())
+    }
+
+    pub fn programs(&self) -> &BTreeMap<String, String> {
+        &self.store.programs
+    }
+
+    pub fn handler_for(&self, program_id: &ProgramId) -> &str {
+        self.store
+            .programs
+            .get(&hex::encode(&program_id.0))
+            .map(String::as_str)
+            .unwrap_or(JSON_HANDLER)
+    }
+
+    pub fn contract(&self, contract_name: &ContractName) -> Option<&IndexedContract> {
+        self.store.contracts.get(contract_name)
+    }
+
+    /// The current state of the contract, decoded by its handler.
+    pub fn state(&self, contract_name: &ContractName) -> Option<Result<serde_json::Value>> {
+        let contract = self.store.contracts.get(contract_name)?;
+        let decoder = self
+            .decoders
+            .get(&contract.handler)
+            .ok_or_else(|| anyhow!("Unknown contract handler '{}'", contract.handler));
+        Some(decoder.and_then(|decode| decode(&contract.state_digest)))
+    }
+
+    pub fn handle_block(&mut self, block: &Block) {
+        for (_, contract) in block.registered_contracts.iter() {
+            self.handle_register_contract(contract);
+        }
+        // The handler follows the new program of an updated contract
+        for (_, update) in block.updated_contracts.iter() {
+            let handler = self.handler_for(&update.program_id).to_string();
+            if let Some(contract) = self.store.contracts.get_mut(&update.contract_name) {
+                contract.program_id = update.program_id.clone();
+                contract.handler = handler;
+            }
+        }
+        for (contract_name, state_digest) in block.updated_states.iter() {
+            if let Some(contract) = self.store.contracts.get_mut(contract_name) {
+                contract.state_digest = state_digest.clone();
+            }
+        }
+    }
+
+    fn handle_register_contract(&mut self, contract: &RegisterContractEffect) {
+        let handler = self.handler_for(&contract.program_id).to_string();
+        debug!(
+            "📝 Indexing contract '{}' with handler '{handler}'",
+            contract.contract_name
+        );
+        self.store.contracts.insert(
+            contract.contract_name.clone(),
+            IndexedContract {
+                program_id: contract.program_id.clone(),
+                handler,
+                state_digest: contract.state_digest.clone(),
+            },
+        );
+    }
+}
+
+fn decode_with<S>(state_digest: &StateDigest) -> Result<serde_json::Value>
+where
+    S: TryFrom<StateDigest, Error = Error> + Serialize,
+{
+    Ok(serde_json::to_value(S::try_from(state_digest.clone())?)?)
+}
+
+/// States serialized as JSON are returned as is, other ones as their hex encoded digest.
+fn decode_json(state_digest: &StateDigest) -> Result<serde_json::Value> {
+    Ok(serde_json::from_slice(&state_digest.0)
+        .unwrap_or_else(|_| serde_json::json!({ "state_digest": hex::encode(&state_digest.0) })))
+}
+
+pub type SharedContractRegistry = Arc<RwLock<ContractHandlerRegistry>>;
+
+pub struct ContractRegistryIndexer {
+    bus: IndexerBusClient,
+    registry: SharedContractRegistry,
+    file: PathBuf,
+}
+
+impl Module for ContractRegistryIndexer {
+    type Context = Arc<CommonRunContext>;
+
+    async fn build(ctx: Self::Context) -> Result<Self> {
+        let bus = IndexerBusClient::new_from_bus(ctx.bus.new_handle()).await;
+        let file = ctx.config.data_directory.join("contract_registry.bin");
+        let mut registry =
+            ContractHandlerRegistry::new(Self::load_from_disk_or_default::<RegistryStore>(&file));
+        for (program_id, handler) in ctx.config.contract_state_indexer.handlers.iter() {
+            let program_id = ProgramId(hex::decode(program_id).map_err(|e| {
+                anyhow!("Invalid program id {program_id} in contract handlers: {e}")
+            })?);
+            registry.register_program(&program_id, handler)?;
+        }
+        let registry = Arc::new(RwLock::new(registry));
+
+        let (router, api) = OpenApiRouter::with_openapi(ContractRegistryAdminAPI::openapi())
+            .routes(routes!(get_contract_handlers, register_contract_handler))
+            .split_for_parts();
+        let (public_router, public_api) =
+            OpenApiRouter::with_openapi(ContractRegistryAPI::openapi())
+                .routes(routes!(get_contract_state))
+                .split_for_parts();
+        if let Ok(mut o) = ctx.openapi.lock() {
+            *o = o
+                .clone()
+                .nest("/v1/admin/indexer", api)
+                .nest("/v1/indexer", public_api);
+        }
+        if let Ok(mut guard) = ctx.router.lock() {
+            if let Some(r) = guard.take() {
+                guard.replace(
+                    r.nest(
+                        "/v1/admin/indexer",
+                        router.with_state(Arc::clone(&registry)),
+                    )
+                    .nest(
+                        "/v1/indexer",
+                        public_router.with_state(Arc::clone(&registry)),
+                    ),
+                );
+            }
+        }
+
+        Ok(Self {
+            bus,
+            registry,
+            file,
+        })
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        module_handle_messages! {
+            on_bus self.bus,
+            listen<NodeStateEvent> event => {
+                if let NodeStateEvent::NewBlock(block) = event {
+                    self.registry.write().await.handle_block(&block);
+                }
+            }
+        };
+        Ok(())
+    }
+
+    async fn on_shutdown(&mut self) -> Result<()> {
+        Self::save_on_disk(self.file.as_path(), &self.registry.read().await.store)
+    }
+}
+
+#[derive(OpenApi)]
+struct ContractRegistryAdminAPI;
+
+#[derive(OpenApi)]
+struct ContractRegistryAPI;
+
+/// Contracts with a state indexer serve their state on a route of their own, that takes
+/// precedence over this one.
+#[utoipa::path(
+    get,
+    path = "/contract/{contract_name}/state",
+    params(
+        ("contract_name" = String, Path, description = "Contract name"),
+        ("field" = Option<String>, Query, description = "JSON pointer to a field of the state, e.g. /balances/bob.hydentity")
+    ),
+    tag = "Indexer",
+    responses(
+        (status = OK, description = "Get json state of contract, decoded by the handler of its program")
+    )
+)]
+pub async fn get_contract_state(
+    Path(contract_name): Path<ContractName>,
+    Query(query): Query<StateQuery>,
+    State(registry): State<SharedContractRegistry>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let registry = registry.read().await;
+    let state = registry
+        .state(&contract_name)
+        .ok_or(AppError(
+            StatusCode::NOT_FOUND,
+            anyhow!("Contract '{contract_name}' not indexed"),
+        ))?
+        .map_err(|e| AppError(StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    query.select(state).map(Json)
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct APIContractHandler {
+    /// Hex encoded
+    pub program_id: String,
+    pub handler: String,
+}
+
+#[utoipa::path(
+    get,
+    path = "/contract_handlers",
+    tag = "Indexer",
+    responses(
+        (status = OK, description = "Handlers registered by program id", body = [APIContractHandler])
+    )
+)]
+pub async fn get_contract_handlers(
+    State(registry): State<SharedContractRegistry>,
+) -> Json<Vec<APIContractHandler>> {
+    Json(
+        registry
+            .read()
+            .await
+            .programs()
+            .iter()
+            .map(|(program_id, handler)| APIContractHandler {
+                program_id: program_id.clone(),
+                handler: handler.clone(),
+            })
+            .collect(),
+    )
+}
+
+#[utoipa::path(
+    post,
+    path = "/contract_handlers",
+    request_body = APIContractHandler,
+    tag = "Indexer",
+    responses(
+        (status = OK, description = "Contracts of the program are indexed with the handler")
+    )
+)]
+pub async fn register_contract_handler(
+    State(registry): State<SharedContractRegistry>,
+    Json(request): Json<APIContractHandler>,
+) -> Result<(), AppError> {
+    let program_id = hex::decode(&request.program_id)
+        .map(ProgramId)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, anyhow!("Invalid program id: {e}")))?;
+    registry
+        .write()
+        .await
+        .register_program(&program_id, &request.handler)
+        .map_err(|e| AppError(StatusCode::BAD_REQUEST, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use hyle_contract_sdk::Digestable;
+
+    use super::*;
+
+    fn register(contract_name: &str, program_id: &[u8], state_digest: StateDigest) -> Block {
+        Block {
+            registered_contracts: vec![(
+                Default::default(),
+                RegisterContractEffect {
+                    verifier: "test".into(),
+                    program_id: ProgramId(program_id.to_vec()),
+                    state_digest,
+                    contract_name: contract_name.into(),
+                },
+            )],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_contract_registry() {
+        let mut registry = ContractHandlerRegistry::new(RegistryStore::default());
+        registry
+            .register_program(&ProgramId(vec![1]), "hyllar")
+            .unwrap();
+        assert!(registry
+            .register_program(&ProgramId(vec![2]), "unknown")
+            .is_err());
+
+        let token = HyllarToken::new(1_000, "faucet.hydentity".to_string());
+        registry.handle_block(&register("token", &[1], token.as_digest()));
+        registry.handle_block(&register(
+            "app",
+            &[2],
+            StateDigest(br#"{"counter":1}"#.to_vec()),
+        ));
+        assert_eq!(
+            registry.state(&"token".into()).unwrap().unwrap(),
+            serde_json::to_value(&token).unwrap()
+        );
+        assert_eq!(
+            registry.state(&"app".into()).unwrap().unwrap(),
+            serde_json::json!({ "counter": 1 })
+        );
+        assert!(registry.state(&"other".into()).is_none());
+
+        // Settled states replace the registered one
+        let mut block = Block::default();
+        block
+            .updated_states
+            .insert("app".into(), StateDigest(vec![0xab]));
+        registry.handle_block(&block);
+        assert_eq!(
+            registry.state(&"app".into()).unwrap().unwrap(),
+            serde_json::json!({ "state_digest": "ab" })
+        );
+
+        // Contracts already indexed follow the handler registered for their program
+        registry
+            .register_program(&ProgramId(vec![2]), "hydentity")
+            .unwrap();
+        assert_eq!(
+            registry.contract(&"app".into()).unwrap().handler,
+            "hydentity"
+        );
+    }
+}
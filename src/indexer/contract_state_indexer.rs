@@ -19,6 +19,9 @@ use crate::{
 
 use super::{contract_handlers::ContractHandler, indexer_bus_client::IndexerBusClient};
 
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum ProverEvent {
     NewTx(Transaction),
@@ -30,6 +33,8 @@ pub struct Store<State> {
     pub state: Option<State>,
     pub contract_name: ContractName,
     pub unsettled_blobs: BTreeMap<TxHash, BlobTransaction>,
+    /// States at the end of past blocks, by block height
+    pub snapshots: BTreeMap<u64, State>,
 }
 
 impl<State> Default for Store<State> {
@@ -38,16 +43,26 @@ impl<State> Default for Store<State> {
             state: None,
             contract_name: Default::default(),
             unsettled_blobs: BTreeMap::new(),
+            snapshots: BTreeMap::new(),
         }
     }
 }
 
+impl<State> Store<State> {
+    /// The last snapshot taken at or before `height`, with its height.
+    pub fn state_at(&self, height: u64) -> Option<(u64, &State)> {
+        self.snapshots
+            .range(..=height)
+            .next_back()
+            .map(|(height, state)| (*height, state))
+    }
+}
+
 pub struct ContractStateIndexer<State> {
     bus: IndexerBusClient,
     store: Arc<RwLock<Store<State>>>,
     contract_name: ContractName,
     file: PathBuf,
-    #[allow(dead_code)]
     config: Arc<Conf>,
 }
 
@@ -157,8 +172,9 @@ where
     /// Note: Each copy of the contract state indexer does the same handle_block on each data event
     /// coming from node state.
     async fn handle_node_state_event(&mut self, event: NodeStateEvent) -> Result<(), Error> {
-        let NodeStateEvent::NewBlock(block) = event;
-        self.handle_processed_block(*block).await?;
+        if let NodeStateEvent::NewBlock(block) = event {
+            self.handle_processed_block(*block).await?;
+        }
 
         Ok(())
     }
@@ -179,9 +195,27 @@ where
         for s_tx in block.successful_txs {
             self.settle_tx(s_tx).await?;
         }
+
+        let conf = &self.config.contract_state_indexer;
+        if conf.snapshot_interval > 0 && block.block_height.0 % conf.snapshot_interval == 0 {
+            self.snapshot(block.block_height.0, conf.max_snapshots)
+                .await;
+        }
         Ok(())
     }
 
+    async fn snapshot(&self, height: u64, max_snapshots: usize) {
+        let mut store = self.store.write().await;
+        let Some(state) = store.state.clone() else {
+            return;
+        };
+        debug!(cn = %self.contract_name, "📸 Snapshot of state at height {height}");
+        store.snapshots.insert(height, state);
+        while store.snapshots.len() > max_snapshots {
+            store.snapshots.pop_first();
+        }
+    }
+
     async fn handle_blob(&mut self, tx: BlobTransaction) -> Result<()> {
         let tx_hash = tx.hash();
         let mut found_supported_blob = false;
@@ -248,7 +282,7 @@ mod tests {
 
     use super::*;
     use crate::bus::metrics::BusMetrics;
-    use crate::model::SignedBlock;
+    use crate::model::{BlockHeight, SignedBlock};
     use crate::node_state::NodeState;
     use crate::utils::conf::Conf;
     use crate::{bus::SharedMessageBus, model::CommonRunContext};
@@ -277,9 +311,16 @@ mod tests {
     }
 
     async fn build_indexer(contract_name: ContractName) -> ContractStateIndexer<MockState> {
+        build_indexer_with_conf(contract_name, Conf::default()).await
+    }
+
+    async fn build_indexer_with_conf(
+        contract_name: ContractName,
+        config: Conf,
+    ) -> ContractStateIndexer<MockState> {
         let common = Arc::new(CommonRunContext {
             bus: SharedMessageBus::new(BusMetrics::global("global".to_string())),
-            config: Arc::new(Conf::default()),
+            config: Arc::new(config),
             router: Default::default(),
             openapi: Default::default(),
         });
@@ -363,6 +404,44 @@ mod tests {
         assert_eq!(store.state.clone().unwrap().0, vec![1, 2, 3]);
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_snapshots() {
+        let contract_name = ContractName::from("test_contract");
+        let mut config = Conf::default();
+        config.data_directory = tempfile::tempdir().unwrap().into_path();
+        config.contract_state_indexer.snapshot_interval = 2;
+        config.contract_state_indexer.max_snapshots = 2;
+        let mut indexer = build_indexer_with_conf(contract_name.clone(), config).await;
+        register_contract(&mut indexer).await;
+
+        for height in 1..=6u8 {
+            let tx = BlobTransaction {
+                blobs: vec![Blob {
+                    contract_name: contract_name.clone(),
+                    data: BlobData(vec![height]),
+                }],
+                identity: "test".into(),
+            };
+            let block = Block {
+                block_height: BlockHeight(height.into()),
+                successful_txs: vec![tx.hash()],
+                txs: vec![tx.into()],
+                ..Default::default()
+            };
+            indexer.handle_processed_block(block).await.unwrap();
+        }
+
+        let store = indexer.store.read().await;
+        // Only the last two snapshots are kept
+        assert_eq!(store.snapshots.keys().collect::<Vec<_>>(), vec![&4, &6]);
+        assert_eq!(
+            store.state_at(5).map(|(h, s)| (h, s.0.clone())),
+            Some((4, vec![4]))
+        );
+        assert_eq!(store.state_at(100).map(|(h, _)| h), Some(6));
+        assert!(store.state_at(3).is_none());
+    }
+
     #[tokio::test]
     async fn test_handle_node_state_event() {
         let contract_name = ContractName::from("test_contract");
@@ -0,0 +1,232 @@
+//! Test-kit for contract handler authors.
+//!
+//! Feeds synthetic blocks to a [ContractStateIndexer] without a bus or a database,
+//! and compares the resulting state against golden JSON files.
+//! Run tests with `UPDATE_GOLDEN=1` to (re)write the golden files after an intended change.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Error, Result};
+use bincode::{Decode, Encode};
+use hyle_contract_sdk::{ContractName, ProgramId, StateDigest};
+use hyle_model::RegisterContractEffect;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use super::{ContractStateIndexer, ContractStateIndexerCtx};
+use crate::{
+    bus::{metrics::BusMetrics, SharedMessageBus},
+    indexer::contract_handlers::ContractHandler,
+    model::{
+        BlobTransaction, Block, BlockHeight, CommonRunContext, ConsensusProposalHash, Hashable,
+        Transaction,
+    },
+    utils::{conf::Conf, modules::Module},
+};
+
+pub struct ContractHandlerTestKit<State> {
+    indexer: ContractStateIndexer<State>,
+    height: BlockHeight,
+}
+
+impl<State> ContractHandlerTestKit<State>
+where
+    State: Serialize
+        + TryFrom<StateDigest, Error = Error>
+        + Clone
+        + Sync
+        + Send
+        + ContractHandler
+        + Encode
+        + Decode
+        + 'static,
+{
+    pub async fn new(contract_name: ContractName) -> Result<Self> {
+        let mut config = Conf::default();
+        config.data_directory = tempfile::tempdir()?.into_path();
+        let common = std::sync::Arc::new(CommonRunContext {
+            bus: SharedMessageBus::new(BusMetrics::global("testkit".to_string())),
+            config: config.into(),
+            router: Default::default(),
+            openapi: Default::default(),
+        });
+        let indexer = ContractStateIndexer::<State>::build(ContractStateIndexerCtx {
+            common,
+            contract_name,
+        })
+        .await?;
+        Ok(Self {
+            indexer,
+            height: BlockHeight(0),
+        })
+    }
+
+    /// Registers the contract with the given initial state, in its own block.
+    pub async fn register(&mut self, state_digest: StateDigest) -> Result<()> {
+        let mut block = self.next_block();
+        block.registered_contracts.push((
+            Default::default(),
+            RegisterContractEffect {
+                verifier: "test".into(),
+                program_id: ProgramId(vec![]),
+                state_digest,
+                contract_name: self.indexer.contract_name.clone(),
+            },
+        ));
+        self.handle_block(block).await
+    }
+
+    /// Sequences the transactions and settles them successfully, in a single block.
+    pub async fn settle(&mut self, txs: Vec<BlobTransaction>) -> Result<()> {
+        let mut block = self.next_block();
+        block.successful_txs = txs.iter().map(|tx| tx.hash()).collect();
+        block.txs = txs.into_iter().map(Transaction::from).collect();
+        self.handle_block(block).await
+    }
+
+    /// Sequences the transactions without settling them.
+    pub async fn sequence(&mut self, txs: Vec<BlobTransaction>) -> Result<()> {
+        let mut block = self.next_block();
+        block.txs = txs.into_iter().map(Transaction::from).collect();
+        self.handle_block(block).await
+    }
+
+    /// Feeds an arbitrary block, as node state would.
+    pub async fn handle_block(&mut self, block: Block) -> Result<()> {
+        self.height = block.block_height;
+        self.indexer.handle_processed_block(block).await
+    }
+
+    pub async fn state(&self) -> Option<State> {
+        self.indexer.store.read().await.state.clone()
+    }
+
+    /// Queryable state of the indexer, as JSON.
+    pub async fn snapshot(&self) -> Result<Value> {
+        let store = self.indexer.store.read().await;
+        Ok(json!({
+            "state": serde_json::to_value(&store.state)?,
+            "unsettled_txs": store.unsettled_blobs.keys().collect::<Vec<_>>(),
+        }))
+    }
+
+    /// Compares the snapshot with the golden file, or writes it when `UPDATE_GOLDEN` is set.
+    pub async fn assert_golden(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let snapshot = self.snapshot().await?;
+
+        if std::env::var("UPDATE_GOLDEN").is_ok_and(|v| v != "0") {
+            if let Some(dir) = path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            std::fs::write(path, serde_json::to_string_pretty(&snapshot)? + "\n")?;
+            return Ok(());
+        }
+
+        let golden: Value = serde_json::from_str(
+            &std::fs::read_to_string(path)
+                .context(format!("Reading golden file {}", path.display()))?,
+        )?;
+        let mut diffs = vec![];
+        diff_json("$", &golden, &snapshot, &mut diffs);
+        if !diffs.is_empty() {
+            bail!(
+                "Snapshot differs from {} (set UPDATE_GOLDEN=1 to update it):\n{}",
+                path.display(),
+                diffs.join("\n")
+            );
+        }
+        Ok(())
+    }
+
+    fn next_block(&self) -> Block {
+        let block_height = self.height + 1;
+        Block {
+            block_height,
+            hash: ConsensusProposalHash(format!("block-{}", block_height.0)),
+            ..Default::default()
+        }
+    }
+}
+
+/// Collects the JSON paths where `actual` differs from `expected`.
+fn diff_json(path: &str, expected: &Value, actual: &Value, diffs: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(e), Value::Object(a)) => {
+            for (k, ev) in e {
+                match a.get(k) {
+                    Some(av) => diff_json(&format!("{path}.{k}"), ev, av, diffs),
+                    None => diffs.push(format!("- {path}.{k}: {ev}")),
+                }
+            }
+            for (k, av) in a {
+                if !e.contains_key(k) {
+                    diffs.push(format!("+ {path}.{k}: {av}"));
+                }
+            }
+        }
+        (Value::Array(e), Value::Array(a)) if e.len() == a.len() => {
+            for (i, (ev, av)) in e.iter().zip(a).enumerate() {
+                diff_json(&format!("{path}[{i}]"), ev, av, diffs);
+            }
+        }
+        _ if expected != actual => diffs.push(format!("~ {path}: {expected} => {actual}")),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyle_contract_sdk::{erc20::ERC20Action, ContractAction};
+    use hyllar::HyllarToken;
+
+    use super::*;
+
+    fn transfer(from: &str, recipient: &str, amount: u128) -> BlobTransaction {
+        BlobTransaction {
+            identity: from.into(),
+            blobs: vec![ERC20Action::Transfer {
+                recipient: recipient.to_string(),
+                amount,
+            }
+            .as_blob("hyllar".into(), None, None)],
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_hyllar_golden() {
+        let mut kit = ContractHandlerTestKit::<HyllarToken>::new("hyllar".into())
+            .await
+            .unwrap();
+        kit.register(HyllarToken::new(1_000, "faucet.hydentity".to_string()).as_digest())
+            .await
+            .unwrap();
+        kit.settle(vec![
+            transfer("faucet.hydentity", "bob.hydentity", 100),
+            transfer("bob.hydentity", "alice.hydentity", 25),
+        ])
+        .await
+        .unwrap();
+        kit.sequence(vec![transfer("alice.hydentity", "bob.hydentity", 5)])
+            .await
+            .unwrap();
+
+        kit.assert_golden(
+            Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden/indexer/hyllar.json"),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[test]
+    fn test_diff_json() {
+        let mut diffs = vec![];
+        diff_json(
+            "$",
+            &json!({"a": 1, "b": [1, 2], "c": true}),
+            &json!({"a": 2, "b": [1, 2], "d": null}),
+            &mut diffs,
+        );
+        assert_eq!(diffs, vec!["~ $.a: 1 => 2", "- $.c: true", "+ $.d: null"]);
+    }
+}
@@ -1,25 +1,32 @@
 use std::{
+    collections::{HashSet, VecDeque},
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::Duration,
 };
 
 use anyhow::{bail, Error, Result};
 use futures::{SinkExt, StreamExt};
 use hyle_model::Hashable;
-use tokio::net::TcpStream;
+use opentelemetry::{metrics::Counter, InstrumentationScope};
+use tokio::{net::TcpStream, time::Instant};
 use tokio_util::codec::Framed;
 use tracing::{debug, info, warn};
 
 use crate::{
     bus::BusClientSender,
-    data_availability::codec::{DataAvailabilityClientCodec, DataAvailabilityServerRequest},
-    model::{BlockHeight, CommonRunContext, SignedBlock},
+    data_availability::codec::{
+        DataAvailabilityClientCodec, DataAvailabilityEvent, DataAvailabilityServerRequest,
+        TransactionInclusion, DA_ACK_INTERVAL, DA_PROTOCOL_VERSION,
+    },
+    model::{BlockHeight, CommonRunContext, ConsensusProposalHash, SignedBlock, TxHash},
     module_handle_messages,
-    node_state::{module::NodeStateEvent, NodeState},
+    node_state::{module::NodeStateEvent, timeouts::TimeoutPolicy, NodeState},
     utils::{
-        conf::SharedConf,
+        conf::{Conf, DaStreamConf, SharedConf},
         logger::LogMe,
         modules::{module_bus_client, Module},
+        noise::NodeStream,
     },
 };
 
@@ -38,13 +45,36 @@ pub struct DAListener {
     listener: RawDAListener,
 }
 
+/// Number of recently received block hashes remembered to drop blocks streamed twice
+const RECENT_BLOCKS: usize = 1000;
+
 /// Implementation of the bit that actually listens to the data availability stream
 pub struct RawDAListener {
-    da_stream: Framed<TcpStream, DataAvailabilityClientCodec>,
+    /// DA server currently streamed from, one of `sources`
+    target: String,
+    /// DA servers to stream from, the primary first then the ones to fail over to
+    sources: Vec<String>,
+    /// Hashes of the last blocks received, oldest first
+    recent_blocks: VecDeque<ConsensusProposalHash>,
+    recent_blocks_set: HashSet<ConsensusProposalHash>,
+    conf: DaStreamConf,
+    metrics: DAListenerMetrics,
+    /// Height to resume from when reconnecting
+    next_height: BlockHeight,
+    da_stream: Framed<NodeStream, DataAvailabilityClientCodec>,
+    /// Protocol version agreed with the current source
+    version: u8,
+    /// Blocks received on the current stream, acknowledged every [DA_ACK_INTERVAL] blocks
+    received_blocks: u64,
+    ping_interval: Duration,
+    ping_timeout: Duration,
+    next_ping: Instant,
+    /// Last time we received anything from the server
+    last_seen: Instant,
 }
 
 impl Deref for RawDAListener {
-    type Target = Framed<TcpStream, DataAvailabilityClientCodec>;
+    type Target = Framed<NodeStream, DataAvailabilityClientCodec>;
     fn deref(&self) -> &Self::Target {
         &self.da_stream
     }
@@ -55,6 +85,25 @@ impl DerefMut for RawDAListener {
     }
 }
 
+struct DAListenerMetrics {
+    reconnect: Counter<u64>,
+}
+
+impl DAListenerMetrics {
+    fn global(node_name: String) -> DAListenerMetrics {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        DAListenerMetrics {
+            reconnect: my_meter.u64_counter("da_listener_reconnect").build(),
+        }
+    }
+
+    fn reconnect(&self) {
+        self.reconnect.add(1, &[]);
+    }
+}
+
 pub struct DAListenerCtx {
     pub common: Arc<CommonRunContext>,
     pub start_block: BlockHeight,
@@ -64,10 +113,16 @@ impl Module for DAListener {
     type Context = DAListenerCtx;
 
     async fn build(ctx: Self::Context) -> Result<Self> {
-        let listener = RawDAListener::new(&ctx.common.config.da_address, ctx.start_block).await?;
+        let listener = RawDAListener::new(
+            &ctx.common.config.da_address,
+            ctx.start_block,
+            &ctx.common.config,
+        )
+        .await?
+        .with_fallbacks(ctx.common.config.da_fallback_addresses.clone());
         let bus = DAListenerBusClient::new_from_bus(ctx.common.bus.new_handle()).await;
 
-        let node_state = Self::load_from_disk_or_default::<NodeState>(
+        let mut node_state = Self::load_from_disk_or_default::<NodeState>(
             ctx.common
                 .config
                 .data_directory
@@ -75,6 +130,8 @@ impl Module for DAListener {
                 .as_path(),
         );
 
+        node_state.set_timeout_policy(TimeoutPolicy::from(&ctx.common.config.node_state));
+
         for name in node_state.contracts.keys() {
             info!("📝 Loaded contract state for {}", name);
         }
@@ -96,13 +153,13 @@ impl DAListener {
     pub async fn start(&mut self) -> Result<(), Error> {
         module_handle_messages! {
             on_bus self.bus,
-            frame = self.listener.next() => {
-                if let Some(Ok(streamed_signed_block)) = frame {
-                    _ = self.processing_next_frame(streamed_signed_block).await.log_error("Consuming da stream");
-                } else if frame.is_none() {
-                    bail!("DA stream closed");
-                } else if let Some(Err(e)) = frame {
-                    bail!("Error while reading DA stream: {}", e);
+            frame = self.listener.next_block() => {
+                match frame {
+                    Ok(Some(streamed_signed_block)) => {
+                        _ = self.processing_next_frame(streamed_signed_block).await.log_error("Consuming da stream");
+                    }
+                    Ok(None) => bail!("DA stream closed"),
+                    Err(e) => bail!("Error while reading DA stream: {}", e),
                 }
             }
         };
@@ -127,18 +184,241 @@ impl DAListener {
         let block = self.node_state.handle_signed_block(&block);
         debug!("📦 Handled block outputs: {:?}", block);
 
+        let events = NodeStateEvent::settlement_events(&block);
         self.bus.send(NodeStateEvent::NewBlock(Box::new(block)))?;
-
-        self.listener.ping().await?;
+        for event in events {
+            self.bus.send(event)?;
+        }
 
         Ok(())
     }
 }
 
 impl RawDAListener {
-    pub async fn new(target: &str, height: BlockHeight) -> Result<Self> {
-        let da_stream = Self::connect_to(target, height).await?;
-        Ok(RawDAListener { da_stream })
+    pub async fn new(target: &str, height: BlockHeight, config: &Conf) -> Result<Self> {
+        Self::with_request(
+            target,
+            height,
+            DataAvailabilityServerRequest::BlockHeight(height),
+            config,
+        )
+        .await
+    }
+
+    /// Streams the headers of the blocks from genesis, read with [RawDAListener::next_header].
+    pub async fn new_headers(target: &str, config: &Conf) -> Result<Self> {
+        Self::with_request(
+            target,
+            BlockHeight(0),
+            DataAvailabilityServerRequest::Headers(BlockHeight(0)),
+            config,
+        )
+        .await
+    }
+
+    /// Fetches a single transaction and where it was included, without streaming blocks.
+    pub async fn fetch_transaction(
+        target: &str,
+        tx_hash: TxHash,
+        config: &Conf,
+    ) -> Result<Option<TransactionInclusion>> {
+        let conf = &config.da_stream;
+        let (mut da_stream, _) = Self::connect_to(
+            target,
+            DataAvailabilityServerRequest::GetTransaction(tx_hash),
+            conf,
+        )
+        .await?;
+        let timeout = Duration::from_secs(conf.ping_timeout.max(1));
+        match tokio::time::timeout(timeout, da_stream.next()).await {
+            Ok(Some(Ok(DataAvailabilityEvent::Transaction(inclusion)))) => Ok(inclusion),
+            Ok(Some(Ok(other))) => bail!("Unexpected message from DA server: {:?}", other),
+            Ok(Some(Err(e))) => Err(e.context("Reading transaction")),
+            Ok(None) => bail!("DA server {} closed the connection", target),
+            Err(_) => bail!("DA server {} did not send the transaction", target),
+        }
+    }
+
+    async fn with_request(
+        target: &str,
+        height: BlockHeight,
+        request: DataAvailabilityServerRequest,
+        config: &Conf,
+    ) -> Result<Self> {
+        let conf = &config.da_stream;
+        let (da_stream, version) = Self::connect_to(target, request, conf).await?;
+        let ping_interval = Duration::from_secs(conf.ping_interval.max(1));
+        Ok(RawDAListener {
+            target: target.to_string(),
+            sources: vec![target.to_string()],
+            recent_blocks: VecDeque::new(),
+            recent_blocks_set: HashSet::new(),
+            conf: conf.clone(),
+            metrics: DAListenerMetrics::global(config.id.clone()),
+            next_height: height,
+            da_stream,
+            version,
+            received_blocks: 0,
+            ping_interval,
+            ping_timeout: Duration::from_secs(conf.ping_timeout.max(1)),
+            next_ping: Instant::now() + ping_interval,
+            last_seen: Instant::now(),
+        })
+    }
+
+    /// Adds DA servers to fail over to, in order, when the stream from the current one breaks.
+    pub fn with_fallbacks(mut self, fallbacks: Vec<String>) -> Self {
+        for fallback in fallbacks {
+            if !self.sources.contains(&fallback) {
+                self.sources.push(fallback);
+            }
+        }
+        self
+    }
+
+    /// Waits for the next block. If the stream breaks, reconnects to the next source (with
+    /// exponential backoff once they all failed) and resumes after the last block received.
+    /// Blocks already received from another source are skipped.
+    pub async fn next_block(&mut self) -> Result<Option<SignedBlock>> {
+        loop {
+            match self.read_block().await {
+                Ok(Some(block)) => {
+                    if !self.remember(block.hash()) {
+                        debug!(
+                            "Skipping block {} already received, from {}",
+                            block.height(),
+                            self.target
+                        );
+                        continue;
+                    }
+                    self.next_height = block.height() + 1;
+                    return Ok(Some(block));
+                }
+                Ok(None) => warn!("DA stream from {} closed", self.target),
+                Err(e) => warn!(
+                    "Error while reading DA stream from {}: {:#}",
+                    self.target, e
+                ),
+            }
+            self.reconnect().await;
+        }
+    }
+
+    /// Waits for the next header, `None` once the server sent them all. Doesn't reconnect.
+    pub async fn next_header(&mut self) -> Result<Option<SignedBlock>> {
+        self.read_block().await
+    }
+
+    /// Records the hash of a received block, false if it was received recently.
+    fn remember(&mut self, hash: ConsensusProposalHash) -> bool {
+        if !self.recent_blocks_set.insert(hash.clone()) {
+            return false;
+        }
+        self.recent_blocks.push_back(hash);
+        if self.recent_blocks.len() > RECENT_BLOCKS {
+            if let Some(oldest) = self.recent_blocks.pop_front() {
+                self.recent_blocks_set.remove(&oldest);
+            }
+        }
+        true
+    }
+
+    /// Source to stream from after `current` broke, wrapping around to the primary.
+    fn next_source(&self, current: &str) -> String {
+        let index = self
+            .sources
+            .iter()
+            .position(|source| source == current)
+            .map_or(0, |index| (index + 1) % self.sources.len());
+        self.sources
+            .get(index)
+            .cloned()
+            .unwrap_or_else(|| current.to_string())
+    }
+
+    async fn reconnect(&mut self) {
+        let max_backoff = Duration::from_secs(self.conf.reconnect_max_backoff.max(1));
+        let mut backoff = Duration::from_secs(1).min(max_backoff);
+        // Sources tried since the stream broke: the other ones are tried right away, the backoff
+        // only applies once we are back to the one that broke
+        let mut tried = 0;
+        loop {
+            if self.sources.len() > 1 {
+                let next = self.next_source(&self.target);
+                warn!("Failing over from DA source {} to {}", self.target, next);
+                self.target = next;
+            }
+            let failing_over = tried + 1 < self.sources.len();
+            if !failing_over {
+                warn!(
+                    "Reconnecting to {} in {:?}, from height {}",
+                    self.target, backoff, self.next_height
+                );
+                tokio::time::sleep(backoff).await;
+            }
+            tried += 1;
+            self.metrics.reconnect();
+            match Self::connect_to(
+                &self.target,
+                DataAvailabilityServerRequest::BlockHeight(self.next_height),
+                &self.conf,
+            )
+            .await
+            {
+                Ok((da_stream, version)) => {
+                    self.da_stream = da_stream;
+                    self.version = version;
+                    self.received_blocks = 0;
+                    self.last_seen = Instant::now();
+                    self.next_ping = Instant::now() + self.ping_interval;
+                    return;
+                }
+                Err(e) => {
+                    warn!("Failed to reconnect to {}: {:#}", self.target, e);
+                    if !failing_over {
+                        backoff = (backoff * 2).min(max_backoff);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits for the next block, pinging the server every `ping_interval` meanwhile.
+    /// Fails if the server stays silent for longer than `ping_timeout`.
+    async fn read_block(&mut self) -> Result<Option<SignedBlock>> {
+        loop {
+            let deadline = self.next_ping.min(self.last_seen + self.ping_timeout);
+            tokio::select! {
+                frame = self.da_stream.next() => match frame {
+                    None => return Ok(None),
+                    Some(Err(e)) => return Err(e),
+                    Some(Ok(event)) => {
+                        self.last_seen = Instant::now();
+                        match event {
+                            DataAvailabilityEvent::SignedBlock(block) => {
+                                self.received_blocks += 1;
+                                if self.version >= 5 && self.received_blocks % DA_ACK_INTERVAL == 0 {
+                                    self.da_stream
+                                        .send(DataAvailabilityServerRequest::Ack(self.received_blocks))
+                                        .await?;
+                                }
+                                return Ok(Some(block));
+                            }
+                            DataAvailabilityEvent::Pong => {}
+                            DataAvailabilityEvent::HeadersEnd => return Ok(None),
+                            other => bail!("Unexpected message from DA server: {:?}", other),
+                        }
+                    }
+                },
+                _ = tokio::time::sleep_until(deadline) => {
+                    if self.last_seen.elapsed() >= self.ping_timeout {
+                        bail!("DA server silent for {:?}", self.ping_timeout);
+                    }
+                    self.ping().await?;
+                    self.next_ping = Instant::now() + self.ping_interval;
+                }
+            }
+        }
     }
 
     async fn ping(&mut self) -> Result<()> {
@@ -149,8 +429,9 @@ impl RawDAListener {
 
     async fn connect_to(
         target: &str,
-        height: BlockHeight,
-    ) -> Result<Framed<TcpStream, DataAvailabilityClientCodec>> {
+        request: DataAvailabilityServerRequest,
+        conf: &DaStreamConf,
+    ) -> Result<(Framed<NodeStream, DataAvailabilityClientCodec>, u8)> {
         info!(
             "Connecting to node for data availability stream on {}",
             &target
@@ -175,15 +456,64 @@ impl RawDAListener {
             }
         };
         let addr = stream.local_addr()?;
+        let (stream, server) = NodeStream::connect(stream, conf.encrypted, None).await?;
+        if let Some(expected) = &conf.server_pubkey {
+            if server.as_ref() != Some(expected) {
+                bail!(
+                    "DA server {} is not validator {}, got {:?}",
+                    target,
+                    expected,
+                    server
+                );
+            }
+        }
         let mut da_stream = Framed::new(stream, DataAvailabilityClientCodec::default());
+        da_stream
+            .send(DataAvailabilityServerRequest::Hello {
+                version: DA_PROTOCOL_VERSION,
+            })
+            .await?;
+        let version = match tokio::time::timeout(timeout, da_stream.next()).await {
+            Ok(Some(Ok(DataAvailabilityEvent::Welcome { version }))) => {
+                debug!("Using DA protocol version {} with {}", version, target);
+                if version < 2 && matches!(request, DataAvailabilityServerRequest::Headers(_)) {
+                    bail!("DA server {} does not stream headers", target);
+                }
+                if version < 3
+                    && matches!(request, DataAvailabilityServerRequest::GetTransaction(_))
+                {
+                    bail!("DA server {} does not serve transactions", target);
+                }
+                version
+            }
+            Ok(Some(Ok(DataAvailabilityEvent::UnsupportedVersion { min, max }))) => {
+                bail!(
+                    "DA server {} supports protocol versions {} to {}, we speak version {}",
+                    target,
+                    min,
+                    max,
+                    DA_PROTOCOL_VERSION
+                );
+            }
+            Ok(Some(Ok(other))) => bail!("Unexpected handshake answer: {:?}", other),
+            Ok(Some(Err(e))) => return Err(e.context("Reading handshake answer")),
+            Ok(None) => bail!(
+                "DA server {} closed the connection during handshake",
+                target
+            ),
+            Err(_) => bail!("DA server {} did not answer the handshake", target),
+        };
         info!(
-            "Connected to data stream to {} on {}. Starting stream from height {}",
-            &target, addr, height
+            "Connected to data stream to {} on {}. Starting stream with {:?}",
+            &target, addr, request
         );
+        if let Some(token) = &conf.auth_token {
+            da_stream
+                .send(DataAvailabilityServerRequest::Auth(token.to_string()))
+                .await?;
+        }
         // Send the start height
-        da_stream
-            .send(DataAvailabilityServerRequest::BlockHeight(height))
-            .await?;
-        Ok(da_stream)
+        da_stream.send(request).await?;
+        Ok((da_stream, version))
     }
 }
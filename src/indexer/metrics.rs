@@ -0,0 +1,32 @@
+use opentelemetry::{metrics::Gauge, InstrumentationScope};
+
+#[derive(Debug)]
+pub struct IndexerMetrics {
+    block_height: Gauge<u64>,
+    lag: Gauge<u64>,
+}
+
+impl IndexerMetrics {
+    pub fn global(node_name: String) -> IndexerMetrics {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        let indexer = "indexer";
+
+        IndexerMetrics {
+            block_height: my_meter
+                .u64_gauge(format!("{indexer}_block_height"))
+                .build(),
+            lag: my_meter
+                .u64_gauge(format!("{indexer}_lag"))
+                .with_unit("ms")
+                .build(),
+        }
+    }
+
+    /// Height of the last indexed block, and how long after its production it was indexed
+    pub fn indexed_block(&self, height: u64, block_timestamp: u64, now: u64) {
+        self.block_height.record(height, &[]);
+        self.lag.record(now.saturating_sub(block_timestamp), &[]);
+    }
+}
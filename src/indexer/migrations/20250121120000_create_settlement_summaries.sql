@@ -0,0 +1,10 @@
+-- Per-block, per-contract summary of settlement activity
+CREATE TABLE settlement_summaries (
+    block_hash TEXT NOT NULL REFERENCES blocks(hash) ON DELETE CASCADE,
+    contract_name TEXT NOT NULL,
+    settled_blobs INT NOT NULL,          -- Number of blobs of this contract settled in the block
+    failed_txs INT NOT NULL,             -- Number of failed blob transactions involving this contract
+    initial_state_digest BYTEA,          -- Contract state before the block (NULL if registered in this block)
+    final_state_digest BYTEA,            -- Contract state after the block
+    PRIMARY KEY (block_hash, contract_name)
+);
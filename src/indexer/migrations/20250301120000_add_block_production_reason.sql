@@ -0,0 +1,4 @@
+-- Why the leader proposed each block when it did
+CREATE TYPE block_production_reason AS ENUM ('interval', 'pending_bytes', 'pending_txs');
+
+ALTER TABLE blocks ADD COLUMN production_reason block_production_reason NOT NULL DEFAULT 'interval';
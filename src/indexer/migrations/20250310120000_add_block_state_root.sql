@@ -0,0 +1,2 @@
+-- Merkle root over the state digests of all contracts after each block, hex-encoded
+ALTER TABLE blocks ADD COLUMN state_root TEXT NOT NULL DEFAULT '';
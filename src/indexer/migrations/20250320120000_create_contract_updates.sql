@@ -0,0 +1,10 @@
+-- Changes of the program and verifier of contracts, once they took effect
+CREATE TABLE contract_updates (
+    contract_name TEXT NOT NULL,
+    tx_hash TEXT NOT NULL,                                                -- Transaction that scheduled the update
+    block_hash TEXT NOT NULL REFERENCES blocks(hash) ON DELETE CASCADE,   -- Block where the update took effect
+    block_height BIGINT NOT NULL,
+    verifier TEXT NOT NULL,
+    program_id BYTEA NOT NULL,
+    PRIMARY KEY (contract_name, block_hash)
+);
@@ -0,0 +1,12 @@
+-- Per-block, per-contract size of the verified proofs
+CREATE TABLE contract_proof_stats (
+    block_hash TEXT NOT NULL REFERENCES blocks(hash) ON DELETE CASCADE,
+    block_height BIGINT NOT NULL,
+    contract_name TEXT NOT NULL,
+    proofs INT NOT NULL,                 -- Number of verified proof transactions for this contract
+    proof_bytes BIGINT NOT NULL,         -- Total size of these proofs, in bytes
+    proof_outputs INT NOT NULL,          -- Number of blob proof outputs carried by these proofs
+    PRIMARY KEY (block_hash, contract_name)
+);
+
+CREATE INDEX idx_contract_proof_stats_contract ON contract_proof_stats (contract_name, block_height);
@@ -0,0 +1,11 @@
+-- Per-block activity of identities, aggregated into the identity registry
+CREATE TABLE identity_activity (
+    identity TEXT NOT NULL,
+    block_hash TEXT NOT NULL REFERENCES blocks(hash) ON DELETE CASCADE,
+    block_height BIGINT NOT NULL,
+    tx_count INT NOT NULL,               -- Number of blob transactions sent by the identity in the block
+    contracts TEXT[] NOT NULL,           -- Contracts of the blobs of these transactions
+    PRIMARY KEY (identity, block_hash)
+);
+
+CREATE INDEX idx_identity_activity_height ON identity_activity (block_height);
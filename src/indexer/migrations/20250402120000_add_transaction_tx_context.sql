@@ -0,0 +1,3 @@
+-- Context blob transactions were sequenced with (block hash, height, timestamp and chain id),
+-- which their proofs are verified against. NULL for other transactions.
+ALTER TABLE transactions ADD COLUMN tx_context JSONB;
@@ -0,0 +1,7 @@
+-- Validator sets certifying the blocks, from the block starting each epoch
+CREATE TABLE epochs (
+    epoch BIGINT PRIMARY KEY,
+    block_hash TEXT NOT NULL REFERENCES blocks(hash) ON DELETE CASCADE,   -- First block of the epoch
+    start_height BIGINT NOT NULL,
+    validators JSONB NOT NULL                                             -- Bonded validators and their stake
+);
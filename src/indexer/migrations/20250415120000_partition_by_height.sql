@@ -0,0 +1,97 @@
+-- Partitions transactions, blobs and blob_proof_outputs by ranges of block heights, so that their
+-- indexes and vacuums stay bounded as the chain grows. The indexer creates partitions ahead of
+-- the last indexed block with ensure_height_partitions.
+-- Unique constraints of partitioned tables must hold the block height: the tables referencing
+-- transactions by hash only (proofs, contracts) lose their foreign key.
+
+-- Creates the missing partitions of `parent`, of `partition_size` blocks each, up to the one
+-- holding height `up_to`. Returns how many were created.
+CREATE FUNCTION ensure_height_partitions(parent TEXT, up_to BIGINT, partition_size BIGINT)
+RETURNS INT AS $$
+DECLARE
+    partition_start BIGINT := 0;
+    partition_name TEXT;
+    created INT := 0;
+BEGIN
+    WHILE partition_start <= up_to LOOP
+        partition_name := format('%s_p%s', parent, partition_start / partition_size);
+        IF to_regclass(partition_name) IS NULL THEN
+            EXECUTE format(
+                'CREATE TABLE %I PARTITION OF %I FOR VALUES FROM (%s) TO (%s)',
+                partition_name, parent, partition_start, partition_start + partition_size
+            );
+            created := created + 1;
+        END IF;
+        partition_start := partition_start + partition_size;
+    END LOOP;
+    RETURN created;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE blob_proof_outputs RENAME TO blob_proof_outputs_unpartitioned;
+ALTER INDEX blob_proof_outputs_pkey RENAME TO blob_proof_outputs_unpartitioned_pkey;
+ALTER TABLE blobs RENAME TO blobs_unpartitioned;
+ALTER INDEX blobs_pkey RENAME TO blobs_unpartitioned_pkey;
+ALTER TABLE transactions RENAME TO transactions_unpartitioned;
+ALTER INDEX transactions_pkey RENAME TO transactions_unpartitioned_pkey;
+
+CREATE TABLE transactions (
+    tx_hash TEXT NOT NULL,
+    block_hash TEXT NOT NULL REFERENCES blocks(hash) ON DELETE CASCADE,
+    block_height BIGINT NOT NULL,                    -- Height of the block, partition key
+    index INT NOT NULL,                              -- Index of the transaction within the block
+    version INT NOT NULL,
+    transaction_type transaction_type NOT NULL,      -- Field to identify the type of transaction (used for joins)
+    transaction_status transaction_status NOT NULL,  -- Field to identify the status of the transaction
+    tx_context JSONB,
+    PRIMARY KEY (tx_hash, block_height),
+    CHECK (length(tx_hash) = 64)                     -- Ensure the hash is exactly 64
+) PARTITION BY RANGE (block_height);
+
+CREATE TABLE blobs (
+    tx_hash TEXT NOT NULL,
+    block_height BIGINT NOT NULL REFERENCES blocks(height) ON DELETE CASCADE,  -- Height of the blob transaction's block, partition key
+    blob_index INT NOT NULL,           -- Index of the blob within the transaction
+    identity TEXT NOT NULL,            -- Identity field from the original BlobTransaction struct
+    contract_name TEXT NOT NULL,       -- Contract name associated with the blob
+    data BYTEA NOT NULL,               -- Actual blob data (stored as binary)
+    verified BOOLEAN NOT NULL,         -- Field to indicate if the blob is verified
+    PRIMARY KEY (tx_hash, blob_index, block_height),
+    CHECK (blob_index >= 0)            -- Ensure the index is positive
+) PARTITION BY RANGE (block_height);
+
+CREATE TABLE blob_proof_outputs (
+    proof_tx_hash TEXT NOT NULL,
+    blob_tx_hash TEXT NOT NULL,
+    block_height BIGINT NOT NULL REFERENCES blocks(height) ON DELETE CASCADE,  -- Height of the block the output was handled in, partition key
+    blob_index INT NOT NULL,            -- Index of the blob within the transaction
+    blob_proof_output_index INT NOT NULL, -- Index of the blob proof output within the proof
+    contract_name TEXT NOT NULL,       -- Contract name associated with the blob
+    hyle_output JSONB NOT NULL,        -- Additional metadata stored in JSONB format
+    settled BOOLEAN NOT NULL,       -- Was this blob proof output used in settlement ?
+    PRIMARY KEY (proof_tx_hash, blob_tx_hash, blob_index, blob_proof_output_index, block_height)
+) PARTITION BY RANGE (block_height);
+CREATE INDEX idx_blob_proof_outputs_blob ON blob_proof_outputs (blob_tx_hash, blob_index, blob_proof_output_index);
+
+SELECT ensure_height_partitions(parent, COALESCE((SELECT max(height) FROM blocks), 0) + 200000, 100000)
+FROM unnest(ARRAY['transactions', 'blobs', 'blob_proof_outputs']) AS parent;
+
+INSERT INTO transactions (tx_hash, block_hash, block_height, index, version, transaction_type, transaction_status, tx_context)
+SELECT t.tx_hash, t.block_hash, b.height, t.index, t.version, t.transaction_type, t.transaction_status, t.tx_context
+FROM transactions_unpartitioned t
+JOIN blocks b ON b.hash = t.block_hash;
+
+INSERT INTO blobs (tx_hash, block_height, blob_index, identity, contract_name, data, verified)
+SELECT bl.tx_hash, t.block_height, bl.blob_index, bl.identity, bl.contract_name, bl.data, bl.verified
+FROM blobs_unpartitioned bl
+JOIN transactions t ON t.tx_hash = bl.tx_hash;
+
+-- Outputs are handled once both the proof and the blob transaction are sequenced
+INSERT INTO blob_proof_outputs (proof_tx_hash, blob_tx_hash, block_height, blob_index, blob_proof_output_index, contract_name, hyle_output, settled)
+SELECT o.proof_tx_hash, o.blob_tx_hash, GREATEST(p.block_height, b.block_height), o.blob_index, o.blob_proof_output_index, o.contract_name, o.hyle_output, o.settled
+FROM blob_proof_outputs_unpartitioned o
+JOIN transactions p ON p.tx_hash = o.proof_tx_hash
+JOIN transactions b ON b.tx_hash = o.blob_tx_hash;
+
+-- Also drops the foreign keys of proofs and contracts to transactions
+DROP TABLE blob_proof_outputs_unpartitioned, blobs_unpartitioned, transactions_unpartitioned CASCADE;
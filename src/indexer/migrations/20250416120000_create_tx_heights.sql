@@ -0,0 +1,13 @@
+-- Height of the block of each indexed transaction. The primary key keeps transaction hashes unique
+-- across the partitions of transactions, updates by hash look up the height of the transaction to
+-- only read its partition, and proofs and contracts get back their foreign key.
+CREATE TABLE tx_heights (
+    tx_hash TEXT PRIMARY KEY,
+    block_height BIGINT NOT NULL REFERENCES blocks(height) ON DELETE CASCADE
+);
+
+INSERT INTO tx_heights (tx_hash, block_height)
+SELECT tx_hash, block_height FROM transactions;
+
+ALTER TABLE proofs ADD FOREIGN KEY (tx_hash) REFERENCES tx_heights(tx_hash) ON DELETE CASCADE;
+ALTER TABLE contracts ADD FOREIGN KEY (tx_hash) REFERENCES tx_heights(tx_hash) ON DELETE CASCADE;
@@ -0,0 +1,38 @@
+//! Creates the block height partitions of the indexer's largest tables ahead of the chain.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use sqlx::PgPool;
+use tracing::info;
+
+use crate::model::BlockHeight;
+
+/// Blocks per partition, must match the partitions created by the migration
+pub const PARTITION_SIZE: u64 = 100_000;
+/// Partitions created in advance of the one holding the last indexed block
+pub const PARTITIONS_AHEAD: u64 = 2;
+/// How often the indexer checks that the partitions ahead exist
+pub const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Tables partitioned by `block_height`
+pub const PARTITIONED_TABLES: [&str; 3] = ["transactions", "blobs", "blob_proof_outputs"];
+
+/// Creates the missing partitions up to [PARTITIONS_AHEAD] partitions after the one of `height`.
+/// Returns the first height without a partition.
+pub async fn ensure_partitions(db: &PgPool, height: BlockHeight) -> Result<BlockHeight> {
+    let up_to = (height.0 / PARTITION_SIZE + PARTITIONS_AHEAD) * PARTITION_SIZE;
+    for table in PARTITIONED_TABLES {
+        let created: i32 = sqlx::query_scalar("SELECT ensure_height_partitions($1, $2, $3)")
+            .bind(table)
+            .bind(i64::try_from(up_to)?)
+            .bind(i64::try_from(PARTITION_SIZE)?)
+            .fetch_one(db)
+            .await
+            .with_context(|| format!("Creating partitions of {table}"))?;
+        if created > 0 {
+            info!("🗂️ Created {created} partitions of {table}, up to height {up_to}");
+        }
+    }
+    Ok(BlockHeight(up_to + PARTITION_SIZE))
+}
@@ -0,0 +1,195 @@
+//! View of the staking contract for explorers: stakes, delegations, unbondings and jailed
+//! validators, updated with the staking actions of the settled transactions of each block.
+
+use std::{path::PathBuf, sync::Arc};
+
+use anyhow::Result;
+use axum::{extract::State, Json};
+use hyle_model::api::APIStaking;
+use staking::state::Staking;
+use tokio::sync::RwLock;
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use super::indexer_bus_client::IndexerBusClient;
+use crate::{
+    genesis::GenesisSpec,
+    model::{Block, CommonRunContext, StakingAction},
+    module_handle_messages,
+    node_state::module::NodeStateEvent,
+    utils::{logger::LogMe, modules::Module},
+};
+
+pub struct StakingIndexer {
+    bus: IndexerBusClient,
+    staking: Arc<RwLock<Staking>>,
+    file: PathBuf,
+}
+
+impl Module for StakingIndexer {
+    type Context = Arc<CommonRunContext>;
+
+    async fn build(ctx: Self::Context) -> Result<Self> {
+        let bus = IndexerBusClient::new_from_bus(ctx.bus.new_handle()).await;
+        let file = ctx.config.data_directory.join("staking_indexer.bin");
+        // Parameters of the staking contract only come from the genesis spec
+        let staking = match Self::load_from_disk::<Staking>(&file) {
+            Some(staking) => staking,
+            None => match &ctx.config.consensus.genesis_file {
+                Some(path) => GenesisSpec::load(path)?.staking(),
+                None => Staking::new(),
+            },
+        };
+        let staking = Arc::new(RwLock::new(staking));
+
+        let (router, api) = OpenApiRouter::with_openapi(StakingIndexerAPI::openapi())
+            .routes(routes!(get_staking_state))
+            .split_for_parts();
+        if let Ok(mut o) = ctx.openapi.lock() {
+            *o = o.clone().nest("/v1/indexer/staking", api);
+        }
+        if let Ok(mut guard) = ctx.router.lock() {
+            if let Some(r) = guard.take() {
+                guard.replace(r.nest(
+                    "/v1/indexer/staking",
+                    router.with_state(Arc::clone(&staking)),
+                ));
+            }
+        }
+
+        Ok(Self { bus, staking, file })
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        module_handle_messages! {
+            on_bus self.bus,
+            listen<NodeStateEvent> event => {
+                if let NodeStateEvent::NewBlock(block) = event {
+                    handle_block(&mut *self.staking.write().await, &block);
+                }
+            }
+        };
+        Ok(())
+    }
+
+    async fn on_shutdown(&mut self) -> Result<()> {
+        Self::save_on_disk(self.file.as_path(), &*self.staking.read().await)
+    }
+}
+
+/// Applies the staking actions of the block, like consensus does for its own copy of the state.
+fn handle_block(staking: &mut Staking, block: &Block) {
+    for (identity, action) in block.staking_actions.iter() {
+        let res = match action {
+            StakingAction::Stake { amount } => staking.stake(identity.clone(), *amount),
+            StakingAction::Delegate { validator } => {
+                staking.delegate_to(identity.clone(), validator.clone())
+            }
+            StakingAction::Unbond { amount } => {
+                staking.unbond(identity.clone(), *amount, block.block_height)
+            }
+            StakingAction::Withdraw { amount } => {
+                staking.settle_withdrawal(identity.clone(), *amount)
+            }
+            StakingAction::Slash {
+                validator,
+                fraction,
+                evidence_hash,
+            } => staking.slash(
+                validator.clone(),
+                *fraction,
+                evidence_hash.clone(),
+                block.block_height,
+            ),
+            StakingAction::Distribute { .. } | StakingAction::ClaimRewards { .. } => continue,
+        };
+        _ = res
+            .map_err(|e| anyhow::anyhow!(e))
+            .log_warn("Indexing staking action");
+    }
+    staking.release_jailed(block.block_height);
+    for validator in block.new_bounded_validators.iter() {
+        _ = staking
+            .bond(validator.clone())
+            .map_err(|e| anyhow::anyhow!(e))
+            .log_warn("Indexing bonded validator");
+    }
+    for validator in block.left_validators.iter() {
+        _ = staking
+            .leave(validator)
+            .map_err(|e| anyhow::anyhow!(e))
+            .log_warn("Indexing validator leaving");
+    }
+}
+
+#[derive(OpenApi)]
+struct StakingIndexerAPI;
+
+#[utoipa::path(
+    get,
+    path = "/state",
+    tag = "Indexer",
+    responses(
+        (status = OK, body = APIStaking)
+    )
+)]
+pub async fn get_staking_state(State(staking): State<Arc<RwLock<Staking>>>) -> Json<APIStaking> {
+    Json(staking.read().await.clone().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{BlockHeight, Identity, ValidatorPublicKey};
+
+    #[test]
+    fn test_staking_view() {
+        let validator = ValidatorPublicKey(vec![1, 2, 3]);
+        let mut staking = Staking::new();
+        handle_block(
+            &mut staking,
+            &Block {
+                block_height: BlockHeight(1),
+                staking_actions: vec![
+                    ("bob.hydentity".into(), StakingAction::Stake { amount: 100 }),
+                    (
+                        "bob.hydentity".into(),
+                        StakingAction::Delegate {
+                            validator: validator.clone(),
+                        },
+                    ),
+                ],
+                new_bounded_validators: vec![validator.clone()],
+                ..Default::default()
+            },
+        );
+        let api: APIStaking = staking.clone().into();
+        assert_eq!(api.stakes.get(&Identity::new("bob.hydentity")), Some(&100));
+        assert_eq!(api.bonded, vec![validator.clone()]);
+        assert_eq!(api.total_bond, 100);
+
+        // Invalid actions are skipped
+        handle_block(
+            &mut staking,
+            &Block {
+                block_height: BlockHeight(2),
+                staking_actions: vec![
+                    (
+                        "bob.hydentity".into(),
+                        StakingAction::Unbond { amount: 1000 },
+                    ),
+                    ("bob.hydentity".into(), StakingAction::Unbond { amount: 40 }),
+                ],
+                ..Default::default()
+            },
+        );
+        let api: APIStaking = staking.into();
+        assert_eq!(api.stakes.get(&Identity::new("bob.hydentity")), Some(&60));
+        assert_eq!(
+            api.unbondings
+                .get(&Identity::new("bob.hydentity"))
+                .map(Vec::len),
+            Some(1)
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Notifies external endpoints of the blob transactions reaching a final status.
+
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use hyle_model::api::{TransactionStatus, TransactionWithBlobs};
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::utils::conf::WebhookConf;
+
+/// Notifications waiting to be delivered before new ones are dropped
+const QUEUE_SIZE: usize = 1000;
+/// Attempts to deliver a notification to a webhook
+const MAX_ATTEMPTS: u32 = 3;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+const RETRY_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Delivers notifications from a background task, so slow endpoints don't hold back indexing.
+#[derive(Debug)]
+pub struct Webhooks {
+    sender: Option<mpsc::Sender<TransactionWithBlobs>>,
+}
+
+impl Webhooks {
+    pub fn new(hooks: Vec<WebhookConf>) -> Result<Self> {
+        if hooks.is_empty() {
+            return Ok(Webhooks { sender: None });
+        }
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .context("Building webhook client")?;
+        let (sender, mut receiver) = mpsc::channel::<TransactionWithBlobs>(QUEUE_SIZE);
+        tokio::task::Builder::new()
+            .name("indexer-webhooks")
+            .spawn(async move {
+                while let Some(tx) = receiver.recv().await {
+                    for hook in hooks.iter().filter(|hook| matches(hook, &tx)) {
+                        deliver(&client, hook, &tx).await;
+                    }
+                }
+            })?;
+        Ok(Webhooks {
+            sender: Some(sender),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.sender.is_some()
+    }
+
+    /// Queues the transaction for the webhooks it matches
+    pub fn notify(&self, tx: &TransactionWithBlobs) {
+        let Some(sender) = &self.sender else {
+            return;
+        };
+        if sender.try_send(tx.clone()).is_err() {
+            warn!(
+                "Webhook queue full, dropping notification for tx {}",
+                tx.tx_hash
+            );
+        }
+    }
+}
+
+fn matches(hook: &WebhookConf, tx: &TransactionWithBlobs) -> bool {
+    let contract_matches = hook.contracts.is_empty()
+        || tx
+            .blobs
+            .iter()
+            .any(|blob| hook.contracts.contains(&blob.contract_name));
+    let status_matches = hook.statuses.is_empty() || hook.statuses.contains(&tx.transaction_status);
+    contract_matches && status_matches && tx.transaction_status != TransactionStatus::Sequenced
+}
+
+async fn deliver(client: &reqwest::Client, hook: &WebhookConf, tx: &TransactionWithBlobs) {
+    let mut backoff = RETRY_BACKOFF;
+    for attempt in 1..=MAX_ATTEMPTS {
+        match client.post(&hook.url).json(tx).send().await {
+            Ok(response) if response.status().is_success() => {
+                debug!("Notified webhook {} of tx {}", hook.url, tx.tx_hash);
+                return;
+            }
+            Ok(response) => warn!(
+                "Webhook {} answered {} for tx {} (attempt {attempt}/{MAX_ATTEMPTS})",
+                hook.url,
+                response.status(),
+                tx.tx_hash
+            ),
+            Err(e) => warn!(
+                "Webhook {} failed for tx {} (attempt {attempt}/{MAX_ATTEMPTS}): {e}",
+                hook.url, tx.tx_hash
+            ),
+        }
+        if attempt < MAX_ATTEMPTS {
+            tokio::time::sleep(backoff).await;
+            backoff *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hyle_model::api::{BlobWithStatus, EncodedBlobData, TransactionType};
+    use hyle_model::{ConsensusProposalHash, TxHash};
+
+    use super::*;
+
+    fn tx(contracts: &[&str], status: TransactionStatus) -> TransactionWithBlobs {
+        TransactionWithBlobs {
+            tx_hash: TxHash::new("tx"),
+            block_hash: ConsensusProposalHash("block".to_string()),
+            index: 0,
+            version: 1,
+            transaction_type: TransactionType::BlobTransaction,
+            transaction_status: status,
+            identity: "bob.hydentity".to_string(),
+            blobs: contracts
+                .iter()
+                .map(|contract| BlobWithStatus {
+                    contract_name: contract.to_string(),
+                    data: EncodedBlobData::new(&[], Default::default(), None),
+                    proof_outputs: vec![],
+                })
+                .collect(),
+            tx_context: None,
+        }
+    }
+
+    #[test]
+    fn test_webhook_filters() {
+        let all = WebhookConf::default();
+        assert!(matches(&all, &tx(&["c1"], TransactionStatus::Success)));
+        assert!(!matches(&all, &tx(&["c1"], TransactionStatus::Sequenced)));
+
+        let failures_of_c1 = WebhookConf {
+            url: "http://localhost".to_string(),
+            contracts: vec!["c1".to_string()],
+            statuses: vec![TransactionStatus::Failure, TransactionStatus::TimedOut],
+        };
+        assert!(matches(
+            &failures_of_c1,
+            &tx(&["c2", "c1"], TransactionStatus::TimedOut)
+        ));
+        assert!(!matches(
+            &failures_of_c1,
+            &tx(&["c1"], TransactionStatus::Success)
+        ));
+        assert!(!matches(
+            &failures_of_c1,
+            &tx(&["c2"], TransactionStatus::Failure)
+        ));
+    }
+}
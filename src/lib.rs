@@ -25,6 +25,8 @@ pub mod single_node_consensus;
 pub mod tcp_server;
 pub mod utils;
 
+#[cfg(any(test, feature = "testkit"))]
+pub mod testkit;
 #[cfg(test)]
 pub mod tests;
 
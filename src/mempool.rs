@@ -1,13 +1,16 @@
 //! Mempool logic & pending transaction management.
 
 use crate::{
-    bus::{command_response::Query, BusClientSender, BusMessage},
+    bus::{
+        command_response::{InnerQuery, Query},
+        BusClientSender, BusMessage,
+    },
     consensus::{CommittedConsensusProposal, ConsensusEvent},
     genesis::GenesisEvent,
     mempool::storage::Storage,
     model::*,
     module_handle_messages,
-    node_state::module::NodeStateEvent,
+    node_state::{module::NodeStateEvent, timeouts::TimeoutPolicy},
     p2p::network::OutboundMessage,
     tcp_server::TcpServerMessage,
     utils::{
@@ -19,11 +22,20 @@ use crate::{
     },
 };
 
+use admission::AdmissionControl;
 use anyhow::{bail, Context, Result};
-use api::RestApiMessage;
+use api::{RestApiMessage, SubmitTx};
 use bincode::{Decode, Encode};
+use block_builder::{BlockBuilder, BlockBuilders, ProposeBlock};
+use erasure::{DataProposalChunk, DataProposalChunks};
 use hyle_contract_sdk::{ContractName, ProgramId, Verifier};
+use hyle_model::{
+    api::{APIBlockBuilderProposal, APIMempoolTx, APIMempoolTxStatus},
+    errors::{ErrorCode, HyleError},
+};
 use metrics::MempoolMetrics;
+use recent_txs::RecentTxs;
+use scheduling::ProofScheduler;
 use serde::{Deserialize, Serialize};
 use staking::state::Staking;
 use std::{
@@ -35,18 +47,48 @@ use std::{
 };
 use storage::{DataProposalVerdict, LaneBytesSize, LaneEntry};
 use strum_macros::IntoStaticStr;
-use tracing::{debug, error, info, trace, warn};
+use tracing::{debug, error, info, instrument, trace, warn};
 
-use verifiers::{verify_proof, verify_recursive_proof};
+use verifiers::{
+    validate_contract_registrations, validate_staking_actions, verify_proof, verify_recursive_proof,
+};
 
+pub mod admission;
 pub mod api;
+pub mod block_builder;
+pub mod erasure;
 pub mod metrics;
+pub mod proof_uploads;
+pub mod recent_txs;
+pub mod scheduling;
 pub mod storage;
 pub mod verifiers;
 
+/// Transactions accepted by the mempool but not in a block yet, saved regularly to survive a crash.
+const UNSEQUENCED_TXS_FILE: &str = "mempool_unsequenced_txs.bin";
+
 #[derive(Debug, Clone)]
 pub struct QueryNewCut(pub Staking);
 
+/// Data waiting to be included in a block, used by consensus to propose blocks early.
+#[derive(Debug, Clone)]
+pub struct QueryPendingData {}
+
+/// Transactions held by the mempool, filtered by hash and contract.
+/// Transactions not in a data proposal yet come first, then those of each lane, the most recent first.
+#[derive(Debug, Clone, Default)]
+pub struct QueryMempoolTxs {
+    pub tx_hash: Option<TxHash>,
+    pub contract_name: Option<ContractName>,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PendingData {
+    pub txs: usize,
+    pub bytes: u64,
+}
+
 #[derive(Debug, Default, Clone, Encode, Decode)]
 pub struct KnownContracts(pub HashMap<ContractName, (Verifier, ProgramId)>);
 
@@ -80,11 +122,15 @@ struct MempoolBusClient {
     receiver(InternalMempoolEvent),
     receiver(SignedByValidator<MempoolNetMessage>),
     receiver(RestApiMessage),
+    receiver(Query<SubmitTx, TxHash>),
     receiver(TcpServerMessage),
     receiver(ConsensusEvent),
     receiver(GenesisEvent),
     receiver(NodeStateEvent),
     receiver(Query<QueryNewCut, Cut>),
+    receiver(Query<QueryPendingData, PendingData>),
+    receiver(Query<QueryMempoolTxs, Vec<APIMempoolTx>>),
+    receiver(Query<ProposeBlock, ()>),
 }
 }
 
@@ -106,9 +152,38 @@ pub struct Mempool {
     conf: SharedConf,
     crypto: SharedBlstCrypto,
     metrics: MempoolMetrics,
+    admission: AdmissionControl,
+    scheduler: ProofScheduler,
+    block_builders: BlockBuilders,
+    chunks: DataProposalChunks,
+    recent_txs: RecentTxs,
+    /// Whether transactions were accepted or sequenced since they were last saved
+    unsequenced_txs_changed: bool,
     inner: MempoolStore,
 }
 
+impl MempoolStore {
+    /// Adds back to the pending transactions those that were saved, but aren't held anymore
+    /// because the node stopped before saving the whole store. Returns how many were added.
+    fn restore_unsequenced_txs(&mut self, txs: Vec<Transaction>) -> usize {
+        let mut known: HashSet<TxHash> = self.pending_txs.iter().map(|tx| tx.hash()).collect();
+        known.extend(
+            self.storage
+                .lanes
+                .values()
+                .flat_map(|lane| lane.data_proposals.values())
+                .flat_map(|entry| entry.data_proposal.txs.iter().map(|tx| tx.hash())),
+        );
+        let missing: Vec<_> = txs
+            .into_iter()
+            .filter(|tx| known.insert(tx.hash()))
+            .collect();
+        let restored = missing.len();
+        self.pending_txs.extend(missing);
+        restored
+    }
+}
+
 impl Deref for Mempool {
     type Target = MempoolStore;
 
@@ -130,6 +205,10 @@ pub enum MempoolNetMessage {
     PoDAUpdate(DataProposalHash, Vec<SignedByValidator<MempoolNetMessage>>),
     SyncRequest(Option<DataProposalHash>, Option<DataProposalHash>),
     SyncReply(Vec<LaneEntry>),
+    /// Chunk of a large data proposal, sent by the lane owner to the validator that relays it
+    DataProposalChunk(DataProposalChunk),
+    /// Chunk as signed by the lane owner, relayed to the other validators
+    RelayedChunk(Box<SignedByValidator<MempoolNetMessage>>),
 }
 
 impl Display for MempoolNetMessage {
@@ -163,9 +242,14 @@ impl Module for Mempool {
         let metrics = MempoolMetrics::global(ctx.common.config.id.clone());
 
         let api = api::api(&ctx.common).await;
+        let admin_api = api::admin_api(&ctx.common).await;
         if let Ok(mut guard) = ctx.common.router.lock() {
             if let Some(router) = guard.take() {
-                guard.replace(router.nest("/v1/", api));
+                guard.replace(
+                    router
+                        .nest("/v1/", api)
+                        .nest("/v1/admin/mempool", admin_api),
+                );
             }
         }
 
@@ -178,7 +262,7 @@ impl Module for Mempool {
         )
         .unwrap_or_default();
 
-        let attributes = Self::load_from_disk::<MempoolStore>(
+        let mut attributes = Self::load_from_disk::<MempoolStore>(
             ctx.common
                 .config
                 .data_directory
@@ -190,6 +274,19 @@ impl Module for Mempool {
             ..MempoolStore::default()
         });
 
+        if let Some(txs) = Self::load_from_disk::<Vec<Transaction>>(
+            ctx.common
+                .config
+                .data_directory
+                .join(UNSEQUENCED_TXS_FILE)
+                .as_path(),
+        ) {
+            let restored = attributes.restore_unsequenced_txs(txs);
+            if restored > 0 {
+                info!("Restored {} transactions not sequenced yet", restored);
+            }
+        }
+
         // Register the Hyle contract to be able to handle registrations.
         #[allow(clippy::expect_used, reason = "not held across await")]
         attributes
@@ -205,6 +302,15 @@ impl Module for Mempool {
             file: Some(ctx.common.config.data_directory.clone()),
             conf: ctx.common.config.clone(),
             metrics,
+            admission: AdmissionControl::new(ctx.common.config.mempool.clone()),
+            scheduler: ProofScheduler::new(
+                ctx.common.config.mempool.clone(),
+                TimeoutPolicy::from(&ctx.common.config.node_state),
+            ),
+            block_builders: BlockBuilders::default(),
+            chunks: DataProposalChunks::default(),
+            recent_txs: RecentTxs::new(ctx.common.config.mempool.duplicate_window),
+            unsequenced_txs_changed: false,
             crypto: Arc::clone(&ctx.node.crypto),
             inner: attributes,
         })
@@ -221,6 +327,10 @@ impl Mempool {
         let tick_time = std::cmp::min(self.conf.consensus.slot_duration / 2, 500);
         let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(tick_time));
         interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        let save_interval_ms = self.conf.mempool.unsequenced_txs_save_interval;
+        let mut save_interval =
+            tokio::time::interval(tokio::time::Duration::from_millis(save_interval_ms.max(1)));
+        save_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
         // TODO: Recompute optimistic node_state for contract registrations.
 
@@ -234,6 +344,10 @@ impl Mempool {
                 let _ = self.handle_api_message(cmd)
                     .log_error("Handling RestApiMessage in Mempool");
             }
+            listen<Query<SubmitTx, TxHash>> query => {
+                let _ = self.on_submit_tx(query)
+                    .log_error("Handling SubmitTx in Mempool");
+            }
             listen<TcpServerMessage> cmd => {
                 let _ = self.handle_tcp_server_message(cmd)
                     .log_error("Handling TcpServerNetMessage in Mempool");
@@ -247,20 +361,40 @@ impl Mempool {
                     .log_error("Handling ConsensusEvent in Mempool");
             }
             listen<NodeStateEvent> cmd => {
-                let NodeStateEvent::NewBlock(block) = cmd;
-                for (_, contract) in block.registered_contracts {
-                    self.handle_contract_registration(contract);
+                if let NodeStateEvent::NewBlock(block) = cmd {
+                    self.scheduler.on_new_block(&block);
+                    self.recent_txs.on_new_block(&block);
+                    for (_, contract) in block.registered_contracts {
+                        self.handle_contract_registration(contract);
+                    }
+                    for (_, update) in block.updated_contracts {
+                        self.handle_contract_update(update);
+                    }
                 }
             }
             command_response<QueryNewCut, Cut> staking => {
                 Ok(self.handle_querynewcut(staking))
             }
+            command_response<QueryPendingData, PendingData> _ => {
+                Ok(self.pending_data())
+            }
+            command_response<QueryMempoolTxs, Vec<APIMempoolTx>> query => {
+                Ok(self.mempool_txs(query))
+            }
+            command_response<ProposeBlock, ()> proposal => {
+                self.propose_block(proposal.0.clone())
+            }
             _ = interval.tick() => {
                 let _ = self.handle_data_proposal_management()
                     .log_error("Creating Data Proposal on tick");
             }
+            _ = save_interval.tick(), if save_interval_ms > 0 => {
+                self.save_unsequenced_txs();
+            }
         };
 
+        self.unsequenced_txs_changed = true;
+        self.save_unsequenced_txs();
         if let Some(file) = &self.file {
             if let Err(e) = Self::save_on_disk(file.join("mempool.bin").as_path(), &self.inner) {
                 warn!("Failed to save mempool storage on disk: {}", e);
@@ -276,6 +410,46 @@ impl Mempool {
         Ok(())
     }
 
+    /// Transactions of the pending data proposals of our lane, then those not in a data
+    /// proposal yet.
+    fn unsequenced_txs(&self) -> Vec<Transaction> {
+        let mut txs: Vec<Transaction> = self
+            .storage
+            .lanes
+            .get(&self.storage.id)
+            .map(|lane| {
+                let last_cut = lane.last_cut.as_ref().map(|(_, hash)| hash);
+                let mut entries: Vec<_> = lane
+                    .iter_reverse()
+                    .take_while(|(hash, _)| Some(*hash) != last_cut)
+                    .map(|(_, entry)| entry)
+                    .collect();
+                entries.reverse();
+                entries
+                    .into_iter()
+                    .flat_map(|entry| entry.data_proposal.txs.iter().cloned())
+                    .collect()
+            })
+            .unwrap_or_default();
+        txs.extend(self.pending_txs.iter().cloned());
+        txs
+    }
+
+    fn save_unsequenced_txs(&mut self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        if !self.unsequenced_txs_changed {
+            return;
+        }
+        let txs = self.unsequenced_txs();
+        if let Err(e) = Self::save_on_disk(file.join(UNSEQUENCED_TXS_FILE).as_path(), &txs) {
+            warn!("Failed to save unsequenced transactions on disk: {}", e);
+            return;
+        }
+        self.unsequenced_txs_changed = false;
+    }
+
     fn handle_contract_registration(&mut self, effect: RegisterContractEffect) {
         #[allow(clippy::expect_used, reason = "not held across await")]
         let mut known_contracts = self.known_contracts.write().expect("logic issue");
@@ -286,6 +460,16 @@ impl Mempool {
         );
     }
 
+    fn handle_contract_update(&mut self, effect: UpdateContractEffect) {
+        #[allow(clippy::expect_used, reason = "not held across await")]
+        let mut known_contracts = self.known_contracts.write().expect("logic issue");
+        known_contracts.register_contract(
+            &effect.contract_name,
+            &effect.verifier,
+            &effect.program_id,
+        );
+    }
+
     // Optimistically parse Hyle tx blobs
     fn handle_hyle_contract_registration(&mut self, blob_tx: &BlobTransaction) {
         #[allow(clippy::expect_used, reason = "not held across await")]
@@ -309,6 +493,90 @@ impl Mempool {
         self.storage.new_cut(&staking.0)
     }
 
+    /// Data in lanes not cut yet, plus transactions not in a data proposal yet.
+    fn pending_data(&self) -> PendingData {
+        let (txs, bytes) = self.storage.pending_data();
+        PendingData {
+            txs: txs + self.pending_txs.len(),
+            bytes: bytes
+                + self
+                    .pending_txs
+                    .iter()
+                    .map(|tx| tx.estimate_size() as u64)
+                    .sum::<u64>(),
+        }
+    }
+
+    /// Registers a block builder choosing the transactions of the node's data proposals,
+    /// in place of the mempool's own scheduling.
+    pub fn set_block_builder(&mut self, builder: Arc<dyn BlockBuilder>) {
+        self.block_builders.set_builder(builder);
+    }
+
+    fn propose_block(&mut self, proposal: APIBlockBuilderProposal) -> Result<()> {
+        self.block_builders
+            .propose(
+                proposal,
+                self.scheduler.current_height() + 1,
+                &self.inner.pending_txs,
+                self.conf.mempool.max_txs_per_data_proposal,
+                self.conf.mempool.external_block_builder,
+            )
+            .map_err(|e| HyleError::new(ErrorCode::BadRequest, format!("{e:#}")).into())
+    }
+
+    fn mempool_txs(&self, query: &QueryMempoolTxs) -> Vec<APIMempoolTx> {
+        let matches = |tx: &Transaction| {
+            query.tx_hash.as_ref().is_none_or(|hash| tx.hash() == *hash)
+                && query
+                    .contract_name
+                    .as_ref()
+                    .is_none_or(|name| tx_contract_names(tx).contains(name))
+        };
+        let matches = &matches;
+
+        let pending = self
+            .pending_txs
+            .iter()
+            .rev()
+            .filter(|tx| matches(tx))
+            .map(|tx| APIMempoolTx {
+                tx_hash: tx.hash(),
+                contract_names: tx_contract_names(tx),
+                status: APIMempoolTxStatus::Pending,
+                lane: None,
+                data_proposal_hash: None,
+            });
+        let in_lanes = self.storage.lanes.iter().flat_map(|(validator, lane)| {
+            let last_cut = lane.last_cut.as_ref().map(|(_, hash)| hash);
+            // Data proposals up to the last cut are included
+            let mut included = false;
+            lane.iter_reverse().flat_map(move |(dp_hash, entry)| {
+                included |= Some(dp_hash) == last_cut;
+                let status = if included {
+                    APIMempoolTxStatus::Included
+                } else {
+                    APIMempoolTxStatus::Proposed
+                };
+                entry
+                    .data_proposal
+                    .txs
+                    .iter()
+                    .rev()
+                    .filter(|tx| matches(tx))
+                    .map(move |tx| APIMempoolTx {
+                        tx_hash: tx.hash(),
+                        contract_names: tx_contract_names(tx),
+                        status,
+                        lane: Some(validator.clone()),
+                        data_proposal_hash: Some(dp_hash.0.clone()),
+                    })
+            })
+        });
+
+        pending.chain(in_lanes).take(query.limit).collect()
+    }
+
     fn handle_api_message(&mut self, command: RestApiMessage) -> Result<()> {
         match command {
             RestApiMessage::NewTx(tx) => self
@@ -341,7 +609,17 @@ impl Mempool {
         trace!("🌝 Handling DataProposal management");
         // Create new DataProposal with pending txs
         let crypto = self.crypto.clone();
-        let new_txs = std::mem::take(&mut self.pending_txs);
+        let pending = std::mem::take(&mut self.pending_txs);
+        let (new_txs, left) = match self.block_builders.schedule(
+            self.scheduler.current_height() + 1,
+            pending,
+            self.conf.mempool.max_txs_per_data_proposal,
+        ) {
+            Ok(scheduled) => scheduled,
+            Err(pending) => self.scheduler.schedule(pending),
+        };
+        self.pending_txs = left;
+        self.metrics.snapshot_pending_tx(self.pending_txs.len());
         self.storage.new_data_proposal(&crypto, new_txs); // TODO: copy crypto in storage
 
         // Check for each pending DataProposal if it has enough signatures
@@ -357,9 +635,7 @@ impl Mempool {
                     );
                     self.metrics.add_data_proposal(&lane_entry.data_proposal);
                     self.metrics.add_proposed_txs(&lane_entry.data_proposal);
-                    self.broadcast_net_message(MempoolNetMessage::DataProposal(
-                        lane_entry.data_proposal.clone(),
-                    ))?;
+                    self.broadcast_data_proposal(lane_entry.data_proposal.clone())?;
                 } else {
                     // If None, rebroadcast it to every validator that has not yet signed it
                     let validator_that_has_signed: HashSet<&ValidatorPublicKey> = lane_entry
@@ -438,6 +714,7 @@ impl Mempool {
         Ok(result)
     }
 
+    #[instrument(skip_all, fields(block_height = buc.ccp.consensus_proposal.slot))]
     fn build_signed_block_and_emit(&mut self, buc: &BlockUnderConstruction) -> Result<()> {
         let block_data = self
             .try_get_full_data_for_signed_block(buc)
@@ -467,6 +744,8 @@ impl Mempool {
                 }
             }
         }
+        self.metrics
+            .snapshot_blocks_under_construction(self.blocks_under_contruction.len());
 
         Ok(())
     }
@@ -543,6 +822,7 @@ impl Mempool {
                 );
 
                 self.staking = cpp.staking.clone();
+                self.admission.on_new_block();
 
                 let cut = cpp.consensus_proposal.cut.clone();
 
@@ -555,11 +835,13 @@ impl Mempool {
 
                 // Update all lanes with the new cut
                 self.storage.update_lanes_with_commited_cut(&cut);
+                self.unsequenced_txs_changed = true;
 
                 self.storage.try_update_lanes_tip(&cut);
 
                 Ok(())
             }
+            ConsensusEvent::Equivocation(_) => Ok(()),
         }
     }
 
@@ -625,6 +907,66 @@ impl Mempool {
             MempoolNetMessage::SyncReply(lane_entries) => {
                 self.on_sync_reply(validator, lane_entries)?;
             }
+            MempoolNetMessage::DataProposalChunk(ref chunk) => {
+                self.on_data_proposal_chunk(&msg, chunk)?;
+            }
+            MempoolNetMessage::RelayedChunk(relayed) => {
+                self.on_relayed_chunk(*relayed)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Relays the chunk of the sender's data proposal to the other validators, and keeps it to
+    /// rebuild the data proposal.
+    fn on_data_proposal_chunk(
+        &mut self,
+        msg: &SignedByValidator<MempoolNetMessage>,
+        chunk: &DataProposalChunk,
+    ) -> Result<()> {
+        if !self.chunks.is_wanted(chunk) {
+            return Ok(());
+        }
+        let lane = &msg.signature.validator;
+        let only_for: HashSet<ValidatorPublicKey> = self
+            .staking
+            .bonded()
+            .iter()
+            .filter(|pubkey| *pubkey != lane && *pubkey != self.crypto.validator_pubkey())
+            .cloned()
+            .collect();
+        if !only_for.is_empty() {
+            self.broadcast_only_for_net_message(
+                only_for,
+                MempoolNetMessage::RelayedChunk(Box::new(msg.clone())),
+            )?;
+        }
+        self.add_data_proposal_chunk(lane, chunk.clone())
+    }
+
+    fn on_relayed_chunk(&mut self, relayed: SignedByValidator<MempoolNetMessage>) -> Result<()> {
+        if !BlstCrypto::verify(&relayed)? {
+            self.metrics.signature_error("mempool");
+            bail!("Invalid signature for relayed chunk {:?}", relayed);
+        }
+        let MempoolNetMessage::DataProposalChunk(chunk) = relayed.msg else {
+            bail!("Relayed message is not a data proposal chunk");
+        };
+        self.add_data_proposal_chunk(&relayed.signature.validator, chunk)
+    }
+
+    fn add_data_proposal_chunk(
+        &mut self,
+        lane: &ValidatorPublicKey,
+        chunk: DataProposalChunk,
+    ) -> Result<()> {
+        if let Some(data_proposal) = self.chunks.add(lane, chunk)? {
+            debug!(
+                "🧩 Rebuilt DataProposal {} of {} from chunks",
+                data_proposal.hash(),
+                lane
+            );
+            self.on_data_proposal(lane, data_proposal)?;
         }
         Ok(())
     }
@@ -794,6 +1136,7 @@ impl Mempool {
             DataProposalVerdict::Vote => {
                 // Normal case, we receive a proposal we already have the parent in store
                 trace!("Send vote for DataProposal");
+                self.recent_txs.record(data_proposal.txs.iter());
                 #[allow(clippy::unwrap_used, reason = "we always have a size for Vote")]
                 self.send_vote(validator, data_proposal_hash, lane_size.unwrap())?;
             }
@@ -866,6 +1209,7 @@ impl Mempool {
             }
             DataProposalVerdict::Vote => {
                 trace!("Send vote for DataProposal");
+                self.recent_txs.record(data_proposal.txs.iter());
                 let crypto = self.crypto.clone();
                 let size = self
                     .storage
@@ -879,30 +1223,30 @@ impl Mempool {
         Ok(())
     }
 
+    #[instrument(skip_all, fields(tx_hash = %tx.hash()))]
     fn on_new_tx(&mut self, tx: Transaction) -> Result<()> {
         // TODO: Verify fees ?
 
         match tx.transaction_data {
             TransactionData::Blob(ref blob_tx) => {
                 debug!("Got new blob tx {}", tx.hash());
+                self.recent_txs.check(&tx)?;
                 if let Err(e) = blob_tx.validate_identity() {
-                    bail!("Invalid identity for blob tx {}: {}", tx.hash(), e);
+                    bail!(HyleError::new(
+                        ErrorCode::InvalidIdentity,
+                        format!("Invalid identity for blob tx {}: {}", tx.hash(), e)
+                    ));
                 }
+                validate_contract_registrations(blob_tx)?;
+                validate_staking_actions(blob_tx)?;
+                self.admission.check(&tx)?;
                 // TODO: we should check if the registration handler contract exists.
                 // TODO: would be good to not need to clone here.
                 self.handle_hyle_contract_registration(blob_tx);
             }
             TransactionData::Proof(_) => {
-                let kc = self.known_contracts.clone();
-                let sender: &tokio::sync::broadcast::Sender<InternalMempoolEvent> = self.bus.get();
-                let sender = sender.clone();
-                tokio::task::spawn_blocking(move || {
-                    let tx =
-                        Self::process_proof_tx(kc, tx).log_error("Error processing proof tx")?;
-                    sender
-                        .send(InternalMempoolEvent::OnProcessedNewTx(tx))
-                        .log_warn("sending processed TX")
-                });
+                self.check_proof_tx(&tx)?;
+                self.verify_proof_tx(tx, None);
                 return Ok(());
             }
             TransactionData::VerifiedProof(ref proof_tx) => {
@@ -911,18 +1255,79 @@ impl Mempool {
                     tx.hash(),
                     proof_tx.contract_name
                 );
+                self.recent_txs.check(&tx)?;
             }
         }
 
         let tx_type: &'static str = (&tx.transaction_data).into();
 
         self.metrics.add_api_tx(tx_type);
+        self.admission.admit(&tx);
+        self.recent_txs.record([&tx]);
         self.pending_txs.push(tx);
+        self.unsequenced_txs_changed = true;
         self.metrics.snapshot_pending_tx(self.pending_txs.len());
 
         Ok(())
     }
 
+    /// Handles a transaction submitted through the API, answering with its hash once admitted or
+    /// with the reason it was refused. Proofs are only answered once verified.
+    fn on_submit_tx(&mut self, query: Query<SubmitTx, TxHash>) -> Result<()> {
+        let query = query.take()?;
+        if query.is_expired() {
+            debug!("Skipping expired query #{}", query.id);
+            return Ok(());
+        }
+        let tx = query.data.0.clone();
+        if let TransactionData::Proof(_) = tx.transaction_data {
+            return match self.check_proof_tx(&tx) {
+                Ok(()) => {
+                    self.verify_proof_tx(tx, Some(query));
+                    Ok(())
+                }
+                Err(e) => query.bail(e),
+            };
+        }
+        let tx_hash = tx.hash();
+        match self.on_new_tx(tx) {
+            Ok(()) => query.answer(tx_hash),
+            Err(e) => query.bail(e),
+        }
+    }
+
+    fn check_proof_tx(&self, tx: &Transaction) -> Result<()> {
+        self.recent_txs.check(tx)?;
+        self.admission.check(tx)
+    }
+
+    /// Verifies the proof off the mempool loop, which then handles the verified proof tx.
+    /// The query of the submitter, if any, is answered with the outcome of the verification.
+    fn verify_proof_tx(&self, tx: Transaction, query: Option<InnerQuery<SubmitTx, TxHash>>) {
+        let kc = self.known_contracts.clone();
+        let sender: &tokio::sync::broadcast::Sender<InternalMempoolEvent> = self.bus.get();
+        let sender = sender.clone();
+        tokio::task::spawn_blocking(move || {
+            let tx_hash = tx.hash();
+            match Self::process_proof_tx(kc, tx) {
+                Ok(tx) => {
+                    let _ = sender
+                        .send(InternalMempoolEvent::OnProcessedNewTx(tx))
+                        .log_warn("sending processed TX");
+                    if let Some(query) = query {
+                        let _ = query.answer(tx_hash).log_warn("Answering SubmitTx");
+                    }
+                }
+                Err(e) => {
+                    warn!("Error processing proof tx: {:#}", e);
+                    if let Some(query) = query {
+                        let _ = query.bail(e).log_warn("Answering SubmitTx");
+                    }
+                }
+            }
+        });
+    }
+
     fn process_proof_tx(
         known_contracts: Arc<std::sync::RwLock<KnownContracts>>,
         mut tx: Transaction,
@@ -937,19 +1342,27 @@ impl Mempool {
             .expect("logic error")
             .0
             .get(&proof_transaction.contract_name)
-            .context("Contract unknown")?
+            .ok_or_else(|| {
+                HyleError::new(
+                    ErrorCode::UnknownContract,
+                    format!("Contract {} unknown", proof_transaction.contract_name),
+                )
+            })?
             .clone();
 
         let is_recursive = proof_transaction.contract_name.0 == "risc0-recursion";
 
         let (hyle_outputs, program_ids) = if is_recursive {
             let (program_ids, hyle_outputs) =
-                verify_recursive_proof(&proof_transaction.proof, &verifier, &program_id)
-                    .context("verify_rec_proof")?;
+                verify_recursive_proof(&proof_transaction.proof, &verifier, &program_id).map_err(
+                    |e| HyleError::new(ErrorCode::InvalidProof, format!("verify_rec_proof: {e:#}")),
+                )?;
             (hyle_outputs, program_ids)
         } else {
             let hyle_outputs = verify_proof(&proof_transaction.proof, &verifier, &program_id)
-                .context("verify_proof")?;
+                .map_err(|e| {
+                    HyleError::new(ErrorCode::InvalidProof, format!("verify_proof: {e:#}"))
+                })?;
             (hyle_outputs, vec![program_id.clone()])
         };
 
@@ -971,6 +1384,7 @@ impl Mempool {
 
         tx.transaction_data = TransactionData::VerifiedProof(VerifiedProofTransaction {
             proof_hash: proof_transaction.proof.hash(),
+            proof_size: proof_transaction.proof.0.len(),
             proof: Some(proof_transaction.proof),
             contract_name: proof_transaction.contract_name.clone(),
             is_recursive,
@@ -1061,6 +1475,37 @@ impl Mempool {
         Ok(())
     }
 
+    /// Sends the data proposal to the other validators, as erasure-coded chunks if it is large
+    /// enough and there are enough validators to relay them.
+    fn broadcast_data_proposal(&mut self, data_proposal: DataProposal) -> Result<()> {
+        let min_size = self.conf.mempool.erasure_coding_min_size;
+        let mut others: Vec<ValidatorPublicKey> = self
+            .staking
+            .bonded()
+            .iter()
+            .filter(|pubkey| *pubkey != self.crypto.validator_pubkey())
+            .cloned()
+            .collect();
+        match erasure::data_shards(others.len()) {
+            Some(data_shards) if min_size > 0 && data_proposal.estimate_size() >= min_size => {
+                others.sort();
+                let chunks =
+                    erasure::encode_data_proposal(&data_proposal, data_shards, others.len())?;
+                debug!(
+                    "🧩 Send DataProposal {} as {} chunks ({} needed)",
+                    data_proposal.id,
+                    chunks.len(),
+                    data_shards
+                );
+                for (validator, chunk) in others.into_iter().zip(chunks) {
+                    self.send_net_message(validator, MempoolNetMessage::DataProposalChunk(chunk))?;
+                }
+                Ok(())
+            }
+            _ => self.broadcast_net_message(MempoolNetMessage::DataProposal(data_proposal)),
+        }
+    }
+
     #[inline(always)]
     fn broadcast_only_for_net_message(
         &mut self,
@@ -1103,6 +1548,22 @@ impl Mempool {
     }
 }
 
+fn tx_contract_names(tx: &Transaction) -> Vec<ContractName> {
+    match &tx.transaction_data {
+        TransactionData::Blob(blob_tx) => {
+            let mut names: Vec<_> = blob_tx
+                .blobs
+                .iter()
+                .map(|blob| blob.contract_name.clone())
+                .collect();
+            names.dedup();
+            names
+        }
+        TransactionData::Proof(proof_tx) => vec![proof_tx.contract_name.clone()],
+        TransactionData::VerifiedProof(proof_tx) => vec![proof_tx.contract_name.clone()],
+    }
+}
+
 #[cfg(test)]
 pub mod test {
 
@@ -1143,6 +1604,12 @@ pub mod test {
                 conf: SharedConf::default(),
                 crypto: Arc::new(crypto),
                 metrics: MempoolMetrics::global("id".to_string()),
+                admission: AdmissionControl::default(),
+                scheduler: ProofScheduler::default(),
+                block_builders: BlockBuilders::default(),
+                chunks: DataProposalChunks::default(),
+                recent_txs: RecentTxs::default(),
+                unsequenced_txs_changed: false,
                 inner: MempoolStore {
                     storage,
                     ..MempoolStore::default()
@@ -1511,6 +1978,180 @@ pub mod test {
         Ok(())
     }
 
+    #[test_log::test(tokio::test)]
+    async fn test_mempool_txs() -> Result<()> {
+        let mut ctx = MempoolTestCtx::new("mempool").await;
+        let all = QueryMempoolTxs {
+            limit: 10,
+            ..Default::default()
+        };
+
+        let tx1 = make_register_contract_tx(ContractName::new("test1"));
+        ctx.submit_tx(&tx1);
+        ctx.mempool.handle_data_proposal_management()?;
+        let dp_hash = ctx
+            .mempool
+            .storage
+            .get_lane_latest_data_proposal_hash(ctx.validator_pubkey())
+            .unwrap()
+            .clone();
+        let tx2 = make_register_contract_tx(ContractName::new("test2"));
+        ctx.submit_tx(&tx2);
+
+        let statuses = |txs: Vec<APIMempoolTx>| {
+            txs.into_iter()
+                .map(|tx| (tx.tx_hash, tx.status))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            statuses(ctx.mempool.mempool_txs(&all)),
+            vec![
+                (tx2.hash(), APIMempoolTxStatus::Pending),
+                (tx1.hash(), APIMempoolTxStatus::Proposed)
+            ]
+        );
+
+        let key = ctx.validator_pubkey().clone();
+        ctx.mempool.storage.lanes.get_mut(&key).unwrap().last_cut =
+            Some((PoDA::default(), dp_hash.clone()));
+        let txs = ctx.mempool.mempool_txs(&QueryMempoolTxs {
+            tx_hash: Some(tx1.hash()),
+            ..all.clone()
+        });
+        assert_eq!(txs.len(), 1);
+        assert_eq!(txs[0].status, APIMempoolTxStatus::Included);
+        assert_eq!(txs[0].lane, Some(key));
+        assert_eq!(txs[0].data_proposal_hash, Some(dp_hash.0));
+        // Registrations are blobs of the hyle contract
+        assert_eq!(txs[0].contract_names, vec![ContractName::new("hyle")]);
+
+        let by_contract = ctx.mempool.mempool_txs(&QueryMempoolTxs {
+            contract_name: Some(ContractName::new("unknown")),
+            ..all.clone()
+        });
+        assert!(by_contract.is_empty());
+        assert_eq!(
+            ctx.mempool
+                .mempool_txs(&QueryMempoolTxs { limit: 1, ..all })
+                .len(),
+            1
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_restore_unsequenced_txs() -> Result<()> {
+        let mut ctx = MempoolTestCtx::new("mempool").await;
+        let tx1 = make_register_contract_tx(ContractName::new("test1"));
+        ctx.submit_tx(&tx1);
+        ctx.mempool.handle_data_proposal_management()?;
+        let tx2 = make_register_contract_tx(ContractName::new("test2"));
+        ctx.submit_tx(&tx2);
+
+        let saved = ctx.mempool.unsequenced_txs();
+        assert_eq!(saved, vec![tx1.clone(), tx2.clone()]);
+        // Nothing to restore if the whole store was saved
+        assert_eq!(ctx.mempool.inner.restore_unsequenced_txs(saved.clone()), 0);
+
+        // After a crash, the store on disk is older
+        let mut store = MempoolStore::default();
+        store.pending_txs.push(tx1.clone());
+        assert_eq!(store.restore_unsequenced_txs(saved), 1);
+        assert_eq!(store.pending_txs, vec![tx1.clone(), tx2.clone()]);
+
+        // Cut data proposals are sequenced
+        let dp_hash = ctx
+            .mempool
+            .storage
+            .get_lane_latest_data_proposal_hash(ctx.validator_pubkey())
+            .unwrap()
+            .clone();
+        let key = ctx.validator_pubkey().clone();
+        ctx.mempool.storage.lanes.get_mut(&key).unwrap().last_cut =
+            Some((PoDA::default(), dp_hash));
+        assert_eq!(ctx.mempool.unsequenced_txs(), vec![tx2]);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_reject_malformed_contract_registration() -> Result<()> {
+        let mut ctx = MempoolTestCtx::new("mempool").await;
+
+        let register = |verifier: &str, program_id: Vec<u8>, name: &str| -> Transaction {
+            BlobTransaction {
+                identity: "hyle.hyle".into(),
+                blobs: vec![RegisterContractAction {
+                    verifier: verifier.into(),
+                    program_id: ProgramId(program_id),
+                    state_digest: StateDigest(vec![0, 1, 2, 3]),
+                    contract_name: name.into(),
+                }
+                .as_blob("hyle".into(), None, None)],
+            }
+            .into()
+        };
+
+        for tx in [
+            register("risc0", vec![1, 2, 3], "short_image_id"),
+            register("sp1", b"not json".to_vec(), "bad_vk"),
+            register("unknown", vec![], "unknown_verifier"),
+            register("test", vec![], "sub.domain"),
+            register("test", vec![], "with space"),
+            register(
+                "test",
+                vec![],
+                &"a".repeat(verifiers::MAX_CONTRACT_NAME_LEN + 1),
+            ),
+        ] {
+            let err = ctx
+                .mempool
+                .handle_api_message(RestApiMessage::NewTx(tx))
+                .unwrap_err();
+            assert_eq!(
+                err.downcast_ref::<HyleError>().map(|e| e.code),
+                Some(ErrorCode::InvalidContractRegistration),
+                "{err:#}"
+            );
+        }
+        assert!(ctx.mempool.pending_txs.is_empty());
+
+        ctx.mempool
+            .handle_api_message(RestApiMessage::NewTx(register(
+                "risc0",
+                vec![0; 32],
+                "valid",
+            )))?;
+        assert_eq!(ctx.mempool.pending_txs.len(), 1);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_reject_user_slash() -> Result<()> {
+        let mut ctx = MempoolTestCtx::new("mempool").await;
+
+        let slash: Transaction = BlobTransaction {
+            identity: "hyle.hyle".into(),
+            blobs: vec![StakingAction::Slash {
+                validator: ctx.validator_pubkey().clone(),
+                fraction: 10_000,
+                evidence_hash: "forged".to_string(),
+            }
+            .as_blob("staking".into(), None, None)],
+        }
+        .into();
+        let err = ctx
+            .mempool
+            .handle_api_message(RestApiMessage::NewTx(slash))
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<HyleError>().map(|e| e.code),
+            Some(ErrorCode::BadRequest),
+            "{err:#}"
+        );
+        assert!(ctx.mempool.pending_txs.is_empty());
+        Ok(())
+    }
+
     #[test_log::test(tokio::test)]
     async fn test_send_poda_update() -> Result<()> {
         let mut ctx = MempoolTestCtx::new("mempool").await;
@@ -1973,6 +2614,8 @@ pub mod test {
                         staking_actions: vec![],
                         timestamp: 777,
                         parent_hash: ConsensusProposalHash("test".to_string()),
+                        production_reason: Default::default(),
+                        epoch: None,
                     },
                     certificate: AggregateSignature::default(),
                 },
@@ -2033,6 +2676,8 @@ pub mod test {
                         staking_actions: vec![],
                         timestamp: 777,
                         parent_hash: ConsensusProposalHash("test".to_string()),
+                        production_reason: Default::default(),
+                        epoch: None,
                     },
                     certificate: AggregateSignature::default(),
                 },
@@ -2064,6 +2709,8 @@ pub mod test {
                         staking_actions: vec![],
                         timestamp: 777,
                         parent_hash: ConsensusProposalHash("test".to_string()),
+                        production_reason: Default::default(),
+                        epoch: None,
                     },
                     certificate: AggregateSignature::default(),
                 },
@@ -2084,6 +2731,8 @@ pub mod test {
                         staking_actions: vec![],
                         timestamp: 777,
                         parent_hash: ConsensusProposalHash("test".to_string()),
+                        production_reason: Default::default(),
+                        epoch: None,
                     },
                     certificate: AggregateSignature::default(),
                 },
@@ -2144,6 +2793,8 @@ pub mod test {
                         staking_actions: vec![],
                         timestamp: 777,
                         parent_hash: ConsensusProposalHash("test".to_string()),
+                        production_reason: Default::default(),
+                        epoch: None,
                     },
                     certificate: AggregateSignature::default(),
                 },
@@ -2197,6 +2848,8 @@ pub mod test {
                         staking_actions: vec![],
                         timestamp: 777,
                         parent_hash: parent_hash.clone(),
+                        production_reason: Default::default(),
+                        epoch: None,
                     },
                     certificate: AggregateSignature::default(),
                 },
@@ -2234,6 +2887,8 @@ pub mod test {
                         staking_actions: vec![],
                         timestamp: 777,
                         parent_hash,
+                        production_reason: Default::default(),
+                        epoch: None,
                     },
                     certificate: AggregateSignature::default(),
                 },
@@ -0,0 +1,154 @@
+//! Limits on the transactions admitted by the mempool between two blocks, so that a single
+//! identity or contract can't fill blocks on its own.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use hyle_model::errors::{ErrorCode, HyleError};
+
+use super::tx_contract_names;
+use crate::{model::*, utils::conf::MempoolConf};
+
+/// Transactions admitted since the last block, by identity and by contract.
+#[derive(Debug, Default)]
+pub struct AdmissionControl {
+    conf: MempoolConf,
+    txs_per_identity: HashMap<Identity, usize>,
+    txs_per_contract: HashMap<ContractName, usize>,
+}
+
+impl AdmissionControl {
+    pub fn new(conf: MempoolConf) -> Self {
+        Self {
+            conf,
+            ..Default::default()
+        }
+    }
+
+    /// Fails with the reason the transaction can't be admitted. Doesn't count it.
+    pub fn check(&self, tx: &Transaction) -> Result<()> {
+        if let TransactionData::Blob(blob_tx) = &tx.transaction_data {
+            let max_blob_size = self.conf.max_blob_size;
+            if let Some(blob) = blob_tx
+                .blobs
+                .iter()
+                .find(|blob| max_blob_size > 0 && blob.data.0.len() > max_blob_size)
+            {
+                bail!(HyleError::new(
+                    ErrorCode::BlobTooLarge,
+                    format!(
+                        "Blob for contract {} is {} bytes, the limit is {}",
+                        blob.contract_name,
+                        blob.data.0.len(),
+                        max_blob_size
+                    )
+                ));
+            }
+
+            let max_txs = self.conf.max_txs_per_identity;
+            let admitted = self.txs_per_identity.get(&blob_tx.identity);
+            if max_txs > 0 && admitted.is_some_and(|admitted| *admitted >= max_txs) {
+                bail!(HyleError::new(
+                    ErrorCode::AdmissionQuotaExceeded,
+                    format!(
+                        "Identity {} can't send more than {} transactions per block",
+                        blob_tx.identity, max_txs
+                    )
+                ));
+            }
+        }
+
+        for contract_name in tx_contract_names(tx) {
+            let Some(quota) = self.conf.contract_quotas.get(&contract_name.0) else {
+                continue;
+            };
+            let admitted = self.txs_per_contract.get(&contract_name);
+            if *quota > 0 && admitted.is_some_and(|admitted| admitted >= quota) {
+                bail!(HyleError::new(
+                    ErrorCode::AdmissionQuotaExceeded,
+                    format!(
+                        "Contract {} can't receive more than {} transactions per block",
+                        contract_name, quota
+                    )
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn admit(&mut self, tx: &Transaction) {
+        if let TransactionData::Blob(blob_tx) = &tx.transaction_data {
+            if self.conf.max_txs_per_identity > 0 {
+                *self
+                    .txs_per_identity
+                    .entry(blob_tx.identity.clone())
+                    .or_default() += 1;
+            }
+        }
+        for contract_name in tx_contract_names(tx) {
+            if self.conf.contract_quotas.contains_key(&contract_name.0) {
+                *self.txs_per_contract.entry(contract_name).or_default() += 1;
+            }
+        }
+    }
+
+    /// Quotas start over with each block.
+    pub fn on_new_block(&mut self) {
+        self.txs_per_identity.clear();
+        self.txs_per_contract.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::make_sized_blob_tx;
+
+    fn code(result: Result<()>) -> Option<ErrorCode> {
+        result
+            .err()
+            .and_then(|e| e.downcast_ref::<HyleError>().map(|e| e.code))
+    }
+
+    #[test]
+    fn test_admission_limits() {
+        let mut admission = AdmissionControl::new(MempoolConf {
+            max_blob_size: 10,
+            max_txs_per_identity: 2,
+            contract_quotas: HashMap::from([("busy".to_string(), 1)]),
+            ..Default::default()
+        });
+
+        assert_eq!(
+            code(admission.check(&make_sized_blob_tx("bob.c1", "c1", 11))),
+            Some(ErrorCode::BlobTooLarge)
+        );
+
+        for _ in 0..2 {
+            let tx = make_sized_blob_tx("bob.c1", "c1", 10);
+            admission.check(&tx).unwrap();
+            admission.admit(&tx);
+        }
+        assert_eq!(
+            code(admission.check(&make_sized_blob_tx("bob.c1", "c1", 1))),
+            Some(ErrorCode::AdmissionQuotaExceeded)
+        );
+        admission
+            .check(&make_sized_blob_tx("alice.c1", "c1", 1))
+            .unwrap();
+
+        admission.admit(&make_sized_blob_tx("alice.busy", "busy", 1));
+        assert_eq!(
+            code(admission.check(&make_sized_blob_tx("carol.busy", "busy", 1))),
+            Some(ErrorCode::AdmissionQuotaExceeded)
+        );
+
+        admission.on_new_block();
+        admission
+            .check(&make_sized_blob_tx("bob.c1", "c1", 1))
+            .unwrap();
+        admission
+            .check(&make_sized_blob_tx("carol.busy", "busy", 1))
+            .unwrap();
+    }
+}
@@ -1,22 +1,51 @@
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
 use anyhow::anyhow;
-use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json, Router,
+};
 use bincode::{Decode, Encode};
 use hyle_contract_sdk::TxHash;
-use hyle_model::{api::APIRegisterContract, ContractAction, RegisterContractAction};
+use hyle_model::{
+    api::{
+        APIBlockBuilderProposal, APIMempoolTx, APIProofUploadFinalize, APIProofUploadStatus,
+        APIRegisterContract,
+    },
+    errors::{ErrorCode, HyleError},
+    ContractAction, ContractName, ProofData, ProofDataHash, RegisterContractAction,
+};
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{error, info};
 use utoipa::OpenApi;
 use utoipa_axum::{router::OpenApiRouter, routes};
 
 use crate::{
-    bus::{bus_client, metrics::BusMetrics, BusClientSender, BusMessage},
+    bus::{
+        bus_client,
+        command_response::{CmdRespClient, Query as BusQuery},
+        metrics::BusMetrics,
+        BusMessage,
+    },
     model::{
         BlobTransaction, CommonRunContext, Hashable, ProofTransaction, Transaction, TransactionData,
     },
     rest::AppError,
+    utils::static_type_map::Pick,
 };
 
-use super::contract_registration::validate_contract_registration;
+use super::{
+    block_builder::ProposeBlock, proof_uploads::ProofUploads,
+    verifiers::validate_contract_registrations, MempoolEvent, QueryMempoolTxs,
+};
 
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
 pub enum RestApiMessage {
@@ -24,14 +53,48 @@ pub enum RestApiMessage {
 }
 impl BusMessage for RestApiMessage {}
 
+/// Submits a transaction to the mempool. Answered with its hash once admitted, or with the
+/// reason it was rejected.
+#[derive(Debug, Clone)]
+pub struct SubmitTx(pub Transaction);
+
 bus_client! {
 struct RestBusClient {
-    sender(RestApiMessage),
+    sender(BusQuery<SubmitTx, TxHash>),
+    sender(MempoolEvent),
+    sender(BusQuery<QueryMempoolTxs, Vec<APIMempoolTx>>),
+    sender(BusQuery<ProposeBlock, ()>),
 }
 }
 
+/// Maximum time a request waits for its transaction to be sequenced
+const WAIT_SEQUENCED_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Maximum number of transactions listed at once
+const MAX_LISTED_TXS: usize = 1000;
+
 pub struct RouterState {
     bus: RestBusClient,
+    proof_uploads: Arc<Mutex<ProofUploads>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChunkOffset {
+    pub offset: usize,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MempoolTxsFilter {
+    pub tx_hash: Option<String>,
+    pub contract_name: Option<String>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SendTxOptions {
+    /// Answer once the transaction is part of a block, rather than when the mempool receives it
+    #[serde(default)]
+    pub wait_sequenced: bool,
 }
 
 #[derive(OpenApi)]
@@ -40,12 +103,16 @@ struct MempoolAPI;
 pub async fn api(ctx: &CommonRunContext) -> Router<()> {
     let state = RouterState {
         bus: RestBusClient::new_from_bus(ctx.bus.new_handle()).await,
+        proof_uploads: Default::default(),
     };
 
     let (router, api) = OpenApiRouter::with_openapi(MempoolAPI::openapi())
         .routes(routes!(register_contract))
         .routes(routes!(send_blob_transaction))
         .routes(routes!(send_proof_transaction))
+        .routes(routes!(get_proof_upload_status, upload_proof_chunk))
+        .routes(routes!(finalize_proof_upload))
+        .routes(routes!(get_mempool_txs))
         .split_for_parts();
 
     if let Ok(mut o) = ctx.openapi.lock() {
@@ -54,50 +121,275 @@ pub async fn api(ctx: &CommonRunContext) -> Router<()> {
     router.with_state(state)
 }
 
+#[derive(OpenApi)]
+struct MempoolAdminAPI;
+
+/// Routes steering the node's data proposals, nested under the admin prefix.
+pub async fn admin_api(ctx: &CommonRunContext) -> Router<()> {
+    let state = RouterState {
+        bus: RestBusClient::new_from_bus(ctx.bus.new_handle()).await,
+        proof_uploads: Default::default(),
+    };
+
+    let (router, api) = OpenApiRouter::with_openapi(MempoolAdminAPI::openapi())
+        .routes(routes!(propose_block))
+        .split_for_parts();
+
+    if let Ok(mut o) = ctx.openapi.lock() {
+        *o = o.clone().nest("/v1/admin/mempool", api);
+    }
+    router.with_state(state)
+}
+
+/// How a sent transaction appears in blocks: proofs are verified by the mempool before
+/// being sequenced, which changes their hash.
+enum SequencedAs {
+    Tx(TxHash),
+    Proof(ContractName, ProofDataHash),
+}
+
+impl SequencedAs {
+    fn new(tx: &Transaction) -> Self {
+        match &tx.transaction_data {
+            TransactionData::Proof(proof) => {
+                SequencedAs::Proof(proof.contract_name.clone(), proof.proof.hash())
+            }
+            _ => SequencedAs::Tx(tx.hash()),
+        }
+    }
+
+    fn matches(&self, tx: &Transaction) -> bool {
+        match (self, &tx.transaction_data) {
+            (
+                SequencedAs::Proof(contract_name, proof_hash),
+                TransactionData::VerifiedProof(proof),
+            ) => proof.contract_name == *contract_name && proof.proof_hash == *proof_hash,
+            (SequencedAs::Tx(tx_hash), _) => tx.hash() == *tx_hash,
+            _ => false,
+        }
+    }
+}
+
+/// Waits for the first block holding the transaction.
+async fn wait_sequenced(
+    events: &mut broadcast::Receiver<MempoolEvent>,
+    sequenced_as: &SequencedAs,
+) -> Result<(), AppError> {
+    loop {
+        match events.recv().await {
+            Ok(MempoolEvent::BuiltSignedBlock(block)) => {
+                let mut txs = block
+                    .data_proposals
+                    .iter()
+                    .flat_map(|(_, dps)| dps)
+                    .flat_map(|dp| dp.txs.iter());
+                if txs.any(|tx| sequenced_as.matches(tx)) {
+                    return Ok(());
+                }
+            }
+            Ok(_) | Err(RecvError::Lagged(_)) => {}
+            Err(RecvError::Closed) => {
+                return Err(AppError(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    anyhow!("Mempool stopped"),
+                ))
+            }
+        }
+    }
+}
+
 async fn handle_send(
     mut state: RouterState,
     payload: TransactionData,
+    options: SendTxOptions,
 ) -> Result<Json<TxHash>, AppError> {
+    // Subscribed before sending, so that the block can't be missed
+    let mut events = options
+        .wait_sequenced
+        .then(|| Pick::<broadcast::Sender<MempoolEvent>>::get(&state.bus).subscribe());
     let tx: Transaction = payload.into();
-    let tx_hash = tx.hash();
-    state
+    let sequenced_as = SequencedAs::new(&tx);
+    // Rejections carry their reason, other errors mean the mempool isn't available
+    let tx_hash = state
         .bus
-        .send(RestApiMessage::NewTx(tx))
-        .map(|_| tx_hash)
-        .map(Json)
-        .map_err(|err| AppError(StatusCode::INTERNAL_SERVER_ERROR, anyhow!(err)))
+        .request(SubmitTx(tx))
+        .await
+        .map_err(|err| coded_error(err, StatusCode::SERVICE_UNAVAILABLE))?;
+
+    if let Some(events) = events.as_mut() {
+        tokio::time::timeout(
+            WAIT_SEQUENCED_TIMEOUT,
+            wait_sequenced(events, &sequenced_as),
+        )
+        .await
+        .map_err(|_| {
+            AppError(
+                StatusCode::GATEWAY_TIMEOUT,
+                anyhow!(
+                    "Transaction {} not sequenced within {:?}",
+                    tx_hash,
+                    WAIT_SEQUENCED_TIMEOUT
+                ),
+            )
+        })??;
+    }
+    Ok(Json(tx_hash))
 }
 
 #[utoipa::path(
     post,
     path = "/tx/send/blob",
     tag = "Mempool",
+    params(
+        ("wait_sequenced" = Option<bool>, Query, description = "Answer once the transaction is part of a block")
+    ),
     responses(
-        (status = OK, description = "Send blob transaction", body = TxHash)
+        (status = OK, description = "Send blob transaction", body = TxHash),
+        (status = GATEWAY_TIMEOUT, description = "Transaction not sequenced in time")
     )
 )]
 pub async fn send_blob_transaction(
     State(state): State<RouterState>,
+    Query(options): Query<SendTxOptions>,
     Json(payload): Json<BlobTransaction>,
 ) -> Result<impl IntoResponse, AppError> {
     info!("Got blob transaction {}", payload.hash());
-    handle_send(state, TransactionData::Blob(payload)).await
+    validate_contract_registrations(&payload)
+        .map_err(|e| coded_error(e, StatusCode::BAD_REQUEST))?;
+    handle_send(state, TransactionData::Blob(payload), options).await
 }
 
 #[utoipa::path(
     post,
     path = "/tx/send/proof",
     tag = "Mempool",
+    params(
+        ("wait_sequenced" = Option<bool>, Query, description = "Answer once the proof is part of a block")
+    ),
     responses(
-        (status = OK, description = "Send proof transaction", body = TxHash)
+        (status = OK, description = "Send proof transaction", body = TxHash),
+        (status = GATEWAY_TIMEOUT, description = "Proof not sequenced in time")
     )
 )]
 pub async fn send_proof_transaction(
     State(state): State<RouterState>,
+    Query(options): Query<SendTxOptions>,
     Json(payload): Json<ProofTransaction>,
 ) -> Result<impl IntoResponse, AppError> {
     info!("Got proof transaction {}", payload.hash());
-    handle_send(state, TransactionData::Proof(payload)).await
+    if payload.proof.0.is_empty() {
+        return Err(AppError(StatusCode::BAD_REQUEST, anyhow!("Empty proof")));
+    }
+    handle_send(state, TransactionData::Proof(payload), options).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/tx/send/proof/chunked/{proof_hash}",
+    tag = "Mempool",
+    params(
+        ("proof_hash" = String, Path, description = "Hash of the complete proof")
+    ),
+    responses(
+        (status = OK, description = "Number of bytes received so far", body = APIProofUploadStatus)
+    )
+)]
+pub async fn get_proof_upload_status(
+    Path(proof_hash): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    let received_bytes = state
+        .proof_uploads
+        .lock()
+        .map_err(|_| anyhow!("Proof uploads lock poisoned"))?
+        .received(addr.ip(), &proof_hash);
+    Ok(Json(APIProofUploadStatus {
+        proof_hash,
+        received_bytes,
+    }))
+}
+
+#[utoipa::path(
+    put,
+    path = "/tx/send/proof/chunked/{proof_hash}",
+    tag = "Mempool",
+    params(
+        ("proof_hash" = String, Path, description = "Hash of the complete proof"),
+        ("offset" = usize, Query, description = "Offset of the chunk in the proof")
+    ),
+    request_body(content = Vec<u8>, content_type = "application/octet-stream"),
+    responses(
+        (status = OK, description = "Chunk stored", body = APIProofUploadStatus),
+        (status = CONFLICT, description = "Chunk offset does not match received data"),
+        (status = TOO_MANY_REQUESTS, description = "Too many proof uploads in progress")
+    )
+)]
+pub async fn upload_proof_chunk(
+    Path(proof_hash): Path<String>,
+    Query(ChunkOffset { offset }): Query<ChunkOffset>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<RouterState>,
+    chunk: Bytes,
+) -> Result<impl IntoResponse, AppError> {
+    let received_bytes = state
+        .proof_uploads
+        .lock()
+        .map_err(|_| anyhow!("Proof uploads lock poisoned"))?
+        .put_chunk(
+            addr.ip(),
+            &proof_hash,
+            offset,
+            &chunk,
+            tokio::time::Instant::now(),
+        )
+        .map_err(|err| coded_error(err, StatusCode::BAD_REQUEST))?;
+
+    Ok(Json(APIProofUploadStatus {
+        proof_hash,
+        received_bytes,
+    }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/tx/send/proof/chunked/{proof_hash}/finalize",
+    tag = "Mempool",
+    params(
+        ("proof_hash" = String, Path, description = "Hash of the complete proof")
+    ),
+    responses(
+        (status = OK, description = "Send assembled proof transaction", body = TxHash),
+        (status = BAD_REQUEST, description = "Assembled proof does not match its hash")
+    )
+)]
+pub async fn finalize_proof_upload(
+    Path(proof_hash): Path<String>,
+    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    State(state): State<RouterState>,
+    Json(payload): Json<APIProofUploadFinalize>,
+) -> Result<impl IntoResponse, AppError> {
+    let data = state
+        .proof_uploads
+        .lock()
+        .map_err(|_| anyhow!("Proof uploads lock poisoned"))?
+        .finish(addr.ip(), &proof_hash)
+        .map_err(|err| coded_error(err, StatusCode::NOT_FOUND))?;
+
+    let proof = ProofData(data);
+    if proof.hash().0 != proof_hash {
+        return Err(AppError::with_code(
+            ErrorCode::ProofHashMismatch,
+            format!("Assembled proof does not match hash {}", proof_hash),
+        ));
+    }
+
+    let tx = ProofTransaction {
+        contract_name: payload.contract_name,
+        proof,
+    };
+    info!("Got chunked proof transaction {}", tx.hash());
+    handle_send(state, TransactionData::Proof(tx), SendTxOptions::default()).await
 }
 
 #[utoipa::path(
@@ -112,8 +404,6 @@ pub async fn register_contract(
     State(state): State<RouterState>,
     Json(payload): Json<APIRegisterContract>,
 ) -> Result<impl IntoResponse, AppError> {
-    let owner = "hyle".into();
-    validate_contract_registration(&owner, &payload.contract_name)?;
     let tx = BlobTransaction {
         identity: "hyle.hyle".into(),
         blobs: vec![RegisterContractAction {
@@ -122,20 +412,132 @@ pub async fn register_contract(
             state_digest: payload.state_digest,
             contract_name: payload.contract_name,
         }
-        .as_blob(owner, None, None)],
+        .as_blob("hyle".into(), None, None)],
     };
+    validate_contract_registrations(&tx).map_err(|e| coded_error(e, StatusCode::BAD_REQUEST))?;
 
-    handle_send(state, TransactionData::Blob(tx)).await
+    handle_send(state, TransactionData::Blob(tx), SendTxOptions::default()).await
+}
+
+#[utoipa::path(
+    get,
+    path = "/mempool/txs",
+    tag = "Mempool",
+    params(
+        ("tx_hash" = Option<String>, Query, description = "Hash of the transaction"),
+        ("contract_name" = Option<String>, Query, description = "Contract the transaction involves"),
+        ("limit" = Option<usize>, Query, description = "Maximum number of transactions, 100 by default")
+    ),
+    responses(
+        (status = OK, description = "Transactions held by the mempool and their status", body = [APIMempoolTx])
+    )
+)]
+pub async fn get_mempool_txs(
+    State(mut state): State<RouterState>,
+    Query(filter): Query<MempoolTxsFilter>,
+) -> Result<impl IntoResponse, AppError> {
+    let query = QueryMempoolTxs {
+        tx_hash: filter.tx_hash.map(TxHash),
+        contract_name: filter.contract_name.map(ContractName),
+        limit: filter.limit.unwrap_or(100).min(MAX_LISTED_TXS),
+    };
+    match state.bus.request(query).await {
+        Ok(txs) => Ok(Json(txs)),
+        err => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Error while listing mempool transactions"),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/builder/proposal",
+    tag = "Mempool",
+    responses(
+        (status = OK, description = "Proposal kept for the node's data proposal built while its block is the next one"),
+        (status = BAD_REQUEST, description = "Proposal for a past block, not holding pending transactions only, or leaving some out without an external block builder configured")
+    )
+)]
+pub async fn propose_block(
+    State(mut state): State<RouterState>,
+    Json(payload): Json<APIBlockBuilderProposal>,
+) -> Result<impl IntoResponse, AppError> {
+    state
+        .bus
+        .request(ProposeBlock(payload))
+        .await
+        .map_err(|err| coded_error(err, StatusCode::SERVICE_UNAVAILABLE))?;
+    Ok(StatusCode::OK)
+}
+
+/// Answers with the HTTP status of the error's code, or `fallback` for errors without one.
+fn coded_error(err: anyhow::Error, fallback: StatusCode) -> AppError {
+    let status = err
+        .downcast_ref::<HyleError>()
+        .and_then(|e| StatusCode::from_u16(e.code.http_status()).ok())
+        .unwrap_or(fallback);
+    AppError(status, err)
 }
 
 impl Clone for RouterState {
     fn clone(&self) -> Self {
-        use crate::utils::static_type_map::Pick;
         Self {
             bus: RestBusClient::new(
                 Pick::<BusMetrics>::get(&self.bus).clone(),
-                Pick::<tokio::sync::broadcast::Sender<RestApiMessage>>::get(&self.bus).clone(),
+                Pick::<broadcast::Sender<BusQuery<SubmitTx, TxHash>>>::get(&self.bus).clone(),
+                Pick::<broadcast::Sender<MempoolEvent>>::get(&self.bus).clone(),
+                Pick::<broadcast::Sender<BusQuery<QueryMempoolTxs, Vec<APIMempoolTx>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<broadcast::Sender<BusQuery<ProposeBlock, ()>>>::get(&self.bus).clone(),
             ),
+            proof_uploads: self.proof_uploads.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use hyle_model::VerifiedProofTransaction;
+
+    use super::*;
+
+    #[test]
+    fn test_sequenced_as() {
+        let proof = ProofTransaction {
+            contract_name: "c1".into(),
+            proof: ProofData(vec![1, 2, 3]),
+        };
+        let verified = |contract_name: &str| -> Transaction {
+            TransactionData::VerifiedProof(VerifiedProofTransaction {
+                contract_name: contract_name.into(),
+                proof: None,
+                proof_hash: proof.proof.hash(),
+                proof_size: proof.proof.0.len(),
+                proven_blobs: vec![],
+                is_recursive: false,
+            })
+            .into()
+        };
+
+        // Proofs are sequenced once verified
+        let sequenced_as = SequencedAs::new(&TransactionData::Proof(proof.clone()).into());
+        assert!(sequenced_as.matches(&verified("c1")));
+        assert!(!sequenced_as.matches(&verified("c2")));
+        assert!(!sequenced_as.matches(&TransactionData::Proof(proof.clone()).into()));
+
+        let blob: Transaction = TransactionData::Blob(BlobTransaction {
+            identity: "id.c1".into(),
+            blobs: vec![],
+        })
+        .into();
+        assert!(SequencedAs::new(&blob).matches(&blob));
+        assert!(!SequencedAs::new(&blob).matches(&verified("c1")));
+    }
+}
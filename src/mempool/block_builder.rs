@@ -0,0 +1,253 @@
+//! Lets an external block builder choose the transactions of the node's next data proposal, and
+//! their order, to experiment with sequencing policies other than the mempool's
+//! [super::scheduling::ProofScheduler].
+//!
+//! A builder either runs in the node, registered with [super::Mempool::set_block_builder], or in
+//! another process sending an [APIBlockBuilderProposal] through the mempool admin API. Its choice
+//! is validated against the pending transactions, the mempool schedules them itself when it isn't.
+//! Unless the node is configured for an external builder, its proposals can only reorder the
+//! pending transactions, not leave some out.
+
+use std::{collections::HashMap, sync::Arc};
+
+use anyhow::{bail, Result};
+use hyle_model::api::APIBlockBuilderProposal;
+use tracing::{debug, warn};
+
+use crate::model::*;
+
+/// Chooses the transactions of the data proposal built while block `height` is the next one.
+pub trait BlockBuilder: Send + Sync {
+    /// Hashes of pending transactions, in the order they go into the data proposal. Transactions
+    /// left out stay pending. None leaves the choice to the mempool.
+    fn build(&self, height: BlockHeight, pending: &[Transaction]) -> Option<Vec<TxHash>>;
+}
+
+/// Submits the proposal of an external block builder to the mempool. Rejected if it is for a
+/// past block or doesn't hold pending transactions only.
+#[derive(Debug, Clone)]
+pub struct ProposeBlock(pub APIBlockBuilderProposal);
+
+/// Registered block builder and the last proposal received from an external one.
+#[derive(Default)]
+pub struct BlockBuilders {
+    builder: Option<Arc<dyn BlockBuilder>>,
+    proposal: Option<APIBlockBuilderProposal>,
+}
+
+impl BlockBuilders {
+    pub fn set_builder(&mut self, builder: Arc<dyn BlockBuilder>) {
+        self.builder = Some(builder);
+    }
+
+    /// Keeps the proposal for when block `proposal.height` is the next one, replacing the previous one.
+    /// Unless `allow_partial`, it must hold all the pending transactions that fit.
+    pub fn propose(
+        &mut self,
+        proposal: APIBlockBuilderProposal,
+        next_height: BlockHeight,
+        pending: &[Transaction],
+        max_txs: usize,
+        allow_partial: bool,
+    ) -> Result<()> {
+        if proposal.height.0 < next_height.0 {
+            bail!(
+                "Block {} is past, the next block is {}",
+                proposal.height,
+                next_height
+            );
+        }
+        select(pending, &proposal.tx_hashes, max_txs)?;
+        let fitting = match max_txs {
+            0 => pending.len(),
+            max_txs => pending.len().min(max_txs),
+        };
+        if !allow_partial && proposal.tx_hashes.len() < fitting {
+            bail!(
+                "{} of {} pending transactions chosen, leaving some out needs an external block builder to be configured",
+                proposal.tx_hashes.len(),
+                fitting
+            );
+        }
+        debug!(
+            "🏗️ Block builder proposal for block {} ({} txs)",
+            proposal.height,
+            proposal.tx_hashes.len()
+        );
+        self.proposal = Some(proposal);
+        Ok(())
+    }
+
+    /// Splits the pending transactions between the next data proposal and those left for later,
+    /// as chosen by the external proposal for `next_height`, or else the registered builder.
+    /// Gives the transactions back when there's no valid choice.
+    pub fn schedule(
+        &mut self,
+        next_height: BlockHeight,
+        pending: Vec<Transaction>,
+        max_txs: usize,
+    ) -> Result<(Vec<Transaction>, Vec<Transaction>), Vec<Transaction>> {
+        if self
+            .proposal
+            .as_ref()
+            .is_some_and(|proposal| proposal.height.0 < next_height.0)
+        {
+            self.proposal = None;
+        }
+        let tx_hashes = match self.proposal.take() {
+            Some(proposal) if proposal.height == next_height => Some(proposal.tx_hashes),
+            proposal => {
+                self.proposal = proposal;
+                self.builder
+                    .as_ref()
+                    .filter(|_| !pending.is_empty())
+                    .and_then(|builder| builder.build(next_height, &pending))
+            }
+        };
+        let Some(tx_hashes) = tx_hashes else {
+            return Err(pending);
+        };
+        match select(&pending, &tx_hashes, max_txs) {
+            Ok(selected) => Ok(split(pending, selected)),
+            Err(e) => {
+                warn!(
+                    "🏗️ Ignoring block builder choice for block {}: {:#}",
+                    next_height, e
+                );
+                Err(pending)
+            }
+        }
+    }
+}
+
+/// Indices of the chosen transactions among the pending ones, in the chosen order.
+fn select(pending: &[Transaction], tx_hashes: &[TxHash], max_txs: usize) -> Result<Vec<usize>> {
+    if max_txs > 0 && tx_hashes.len() > max_txs {
+        bail!(
+            "{} transactions chosen, a data proposal holds at most {}",
+            tx_hashes.len(),
+            max_txs
+        );
+    }
+    let mut indices: HashMap<TxHash, usize> = pending
+        .iter()
+        .enumerate()
+        .map(|(index, tx)| (tx.hash(), index))
+        .collect();
+    tx_hashes
+        .iter()
+        .map(|tx_hash| match indices.remove(tx_hash) {
+            Some(index) => Ok(index),
+            None => bail!("Transaction {} is not pending, or chosen twice", tx_hash),
+        })
+        .collect()
+}
+
+fn split(pending: Vec<Transaction>, selected: Vec<usize>) -> (Vec<Transaction>, Vec<Transaction>) {
+    let mut txs: Vec<Option<Transaction>> = pending.into_iter().map(Some).collect();
+    let scheduled = selected
+        .into_iter()
+        .filter_map(|index| txs.get_mut(index).and_then(Option::take))
+        .collect();
+    let left = txs.into_iter().flatten().collect();
+    (scheduled, left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::make_blob_tx;
+
+    struct Reversed;
+
+    impl BlockBuilder for Reversed {
+        fn build(&self, _height: BlockHeight, pending: &[Transaction]) -> Option<Vec<TxHash>> {
+            Some(pending.iter().rev().map(|tx| tx.hash()).collect())
+        }
+    }
+
+    fn identities(txs: &[Transaction]) -> Vec<String> {
+        txs.iter()
+            .map(|tx| match &tx.transaction_data {
+                TransactionData::Blob(blob_tx) => blob_tx.identity.0.clone(),
+                _ => String::new(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_block_builders() {
+        let (a, b, c) = (
+            &make_blob_tx("a.c"),
+            &make_blob_tx("b.c"),
+            &make_blob_tx("c.c"),
+        );
+        let pending = vec![a.clone(), b.clone(), c.clone()];
+        let proposal = |height: u64, txs: &[&Transaction]| APIBlockBuilderProposal {
+            height: BlockHeight(height),
+            tx_hashes: txs.iter().map(|tx| tx.hash()).collect(),
+        };
+        let mut builders = BlockBuilders::default();
+        assert!(builders
+            .schedule(BlockHeight(2), pending.clone(), 0)
+            .is_err());
+
+        // External proposals are validated
+        assert!(builders
+            .propose(proposal(1, &[c]), BlockHeight(2), &pending, 0, true)
+            .is_err());
+        assert!(builders
+            .propose(proposal(2, &[c, c]), BlockHeight(2), &pending, 0, true)
+            .is_err());
+        assert!(builders
+            .propose(proposal(2, &[c, a]), BlockHeight(2), &pending, 1, true)
+            .is_err());
+        assert!(builders
+            .propose(
+                proposal(2, &[c, a]),
+                BlockHeight(2),
+                &[b.clone(), c.clone()],
+                0,
+                true
+            )
+            .is_err());
+        // Leaving transactions out needs an external builder
+        assert!(builders
+            .propose(proposal(2, &[c, a]), BlockHeight(2), &pending, 0, false)
+            .is_err());
+        assert!(builders
+            .propose(proposal(2, &[]), BlockHeight(2), &pending, 0, false)
+            .is_err());
+        builders
+            .propose(proposal(2, &[c, a]), BlockHeight(2), &pending, 2, false)
+            .expect("all the transactions that fit");
+        builders
+            .propose(proposal(2, &[b, c, a]), BlockHeight(2), &pending, 0, false)
+            .expect("all the transactions");
+
+        // A proposal waits for its block, and is used once
+        builders.set_builder(Arc::new(Reversed));
+        builders
+            .propose(proposal(3, &[c, a]), BlockHeight(2), &pending, 0, true)
+            .expect("valid proposal");
+        let (scheduled, left) = builders
+            .schedule(BlockHeight(2), vec![a.clone(), b.clone()], 0)
+            .expect("builder choice");
+        assert_eq!(identities(&scheduled), vec!["b.c", "a.c"]);
+        assert!(left.is_empty());
+        let (scheduled, left) = builders
+            .schedule(BlockHeight(3), pending.clone(), 0)
+            .expect("proposal");
+        assert_eq!(identities(&scheduled), vec!["c.c", "a.c"]);
+        assert_eq!(identities(&left), vec!["b.c"]);
+
+        // Invalid choices give the transactions back
+        assert_eq!(
+            builders
+                .schedule(BlockHeight(3), pending.clone(), 2)
+                .err()
+                .map(|txs| txs.len()),
+            Some(3)
+        );
+    }
+}
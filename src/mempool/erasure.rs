@@ -0,0 +1,415 @@
+//! Dissemination of large data proposals as erasure-coded chunks, so that the lane owner doesn't
+//! send a full copy to every validator.
+//!
+//! The encoded data proposal is split into one Reed-Solomon shard per other validator, any
+//! `data_shards` of which rebuild it. The lane owner sends each validator its own chunk, which the
+//! validator relays to the others: the owner sends about `1 / data_shards` of the proposal to each
+//! validator, and the relaying is spread over all of them.
+
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use anyhow::{bail, Context, Result};
+use bincode::{Decode, Encode};
+use serde::{Deserialize, Serialize};
+
+use crate::model::*;
+
+/// Shards are indexed by a GF(256) element, distinct for data and parity shards
+pub const MAX_SHARDS: usize = 255;
+/// Data proposals being rebuilt at once, the oldest ones are dropped first
+const MAX_PENDING_PROPOSALS: usize = 1000;
+/// Rebuilt data proposals remembered, to ignore the chunks still arriving for them
+const MAX_REBUILT_PROPOSALS: usize = 1000;
+/// Decoding limit for rebuilt data proposals, as a corrupted length could claim any size
+const MAX_DATA_PROPOSAL_SIZE: usize = 1024 * 1024 * 1024;
+
+/// One shard of an erasure-coded data proposal, signed by the lane owner.
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, Eq, PartialEq)]
+pub struct DataProposalChunk {
+    pub data_proposal_hash: DataProposalHash,
+    /// Size of the encoded data proposal, in bytes
+    pub size: usize,
+    /// Shards needed to rebuild the data proposal
+    pub data_shards: usize,
+    pub total_shards: usize,
+    pub index: usize,
+    pub shard: Vec<u8>,
+}
+
+/// Shards needed to rebuild a data proposal sent as `total_shards` chunks, tolerating the loss of
+/// those of the faulty validators. None if there are too few validators for coding to pay off.
+pub fn data_shards(total_shards: usize) -> Option<usize> {
+    let faulty = total_shards / 3;
+    (faulty > 0 && total_shards <= MAX_SHARDS).then_some(total_shards - faulty)
+}
+
+/// Splits the data proposal into `total_shards` chunks, `data_shards` of which rebuild it.
+pub fn encode_data_proposal(
+    data_proposal: &DataProposal,
+    data_shards: usize,
+    total_shards: usize,
+) -> Result<Vec<DataProposalChunk>> {
+    let data = bincode::encode_to_vec(data_proposal, bincode::config::standard())
+        .context("Encoding data proposal")?;
+    let data_proposal_hash = data_proposal.hash();
+    Ok(encode(&data, data_shards, total_shards)
+        .into_iter()
+        .enumerate()
+        .map(|(index, shard)| DataProposalChunk {
+            data_proposal_hash: data_proposal_hash.clone(),
+            size: data.len(),
+            data_shards,
+            total_shards,
+            index,
+            shard,
+        })
+        .collect())
+}
+
+struct PendingProposal {
+    lane: ValidatorPublicKey,
+    size: usize,
+    data_shards: usize,
+    total_shards: usize,
+    shards: BTreeMap<usize, Vec<u8>>,
+}
+
+/// Chunks received for data proposals not rebuilt yet.
+#[derive(Default)]
+pub struct DataProposalChunks {
+    pending: HashMap<DataProposalHash, PendingProposal>,
+    /// Pending data proposals, oldest first
+    received: VecDeque<DataProposalHash>,
+    rebuilt: HashSet<DataProposalHash>,
+    rebuilt_order: VecDeque<DataProposalHash>,
+}
+
+impl DataProposalChunks {
+    /// Whether a chunk of this data proposal is still useful
+    pub fn is_wanted(&self, chunk: &DataProposalChunk) -> bool {
+        !self.rebuilt.contains(&chunk.data_proposal_hash)
+            && self
+                .pending
+                .get(&chunk.data_proposal_hash)
+                .is_none_or(|pending| !pending.shards.contains_key(&chunk.index))
+    }
+
+    /// Stores a chunk of the lane's data proposal, returns the data proposal once it can be
+    /// rebuilt. Chunks already received, or of rebuilt proposals, are ignored.
+    pub fn add(
+        &mut self,
+        lane: &ValidatorPublicKey,
+        chunk: DataProposalChunk,
+    ) -> Result<Option<DataProposal>> {
+        if !self.is_wanted(&chunk) {
+            return Ok(None);
+        }
+        if chunk.total_shards > MAX_SHARDS
+            || chunk.data_shards == 0
+            || chunk.data_shards > chunk.total_shards
+            || chunk.index >= chunk.total_shards
+        {
+            bail!(
+                "Invalid chunk {}/{} of data proposal {} ({} data shards)",
+                chunk.index,
+                chunk.total_shards,
+                chunk.data_proposal_hash,
+                chunk.data_shards
+            );
+        }
+
+        let hash = chunk.data_proposal_hash.clone();
+        if !self.pending.contains_key(&hash) {
+            while self.received.len() >= MAX_PENDING_PROPOSALS {
+                if let Some(oldest) = self.received.pop_front() {
+                    self.pending.remove(&oldest);
+                }
+            }
+            self.received.push_back(hash.clone());
+        }
+        let pending = self
+            .pending
+            .entry(hash.clone())
+            .or_insert_with(|| PendingProposal {
+                lane: lane.clone(),
+                size: chunk.size,
+                data_shards: chunk.data_shards,
+                total_shards: chunk.total_shards,
+                shards: BTreeMap::new(),
+            });
+        if &pending.lane != lane
+            || pending.size != chunk.size
+            || pending.data_shards != chunk.data_shards
+            || pending.total_shards != chunk.total_shards
+        {
+            bail!(
+                "Chunk {} of data proposal {} does not match the chunks already received",
+                chunk.index,
+                hash
+            );
+        }
+        pending.shards.insert(chunk.index, chunk.shard);
+        if pending.shards.len() < pending.data_shards {
+            return Ok(None);
+        }
+
+        // Whether it succeeds or not, the chunks are dropped: the lane owner sends the whole data
+        // proposal to the validators that don't vote for it.
+        let pending = self.pending.remove(&hash);
+        self.received.retain(|received| received != &hash);
+        let Some(pending) = pending else {
+            return Ok(None);
+        };
+        let data = decode(&pending.shards, pending.data_shards, pending.size)?;
+        let (data_proposal, _): (DataProposal, _) = bincode::decode_from_slice(
+            &data,
+            bincode::config::standard().with_limit::<MAX_DATA_PROPOSAL_SIZE>(),
+        )
+        .context("Decoding rebuilt data proposal")?;
+        if data_proposal.hash() != hash {
+            bail!("Rebuilt data proposal does not match hash {}", hash);
+        }
+
+        if self.rebuilt_order.len() >= MAX_REBUILT_PROPOSALS {
+            if let Some(oldest) = self.rebuilt_order.pop_front() {
+                self.rebuilt.remove(&oldest);
+            }
+        }
+        self.rebuilt.insert(hash.clone());
+        self.rebuilt_order.push_back(hash);
+        Ok(Some(data_proposal))
+    }
+}
+
+/// GF(256) logarithm and exponential tables, for the polynomial x^8 + x^4 + x^3 + x^2 + 1.
+struct Gf {
+    exp: [u8; 512],
+    log: [u8; 256],
+}
+
+#[allow(
+    clippy::indexing_slicing,
+    reason = "indices are within the table sizes by construction"
+)]
+static GF: Gf = {
+    let mut exp = [0u8; 512];
+    let mut log = [0u8; 256];
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x <<= 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11d;
+        }
+        i += 1;
+    }
+    while i < 512 {
+        exp[i] = exp[i - 255];
+        i += 1;
+    }
+    Gf { exp, log }
+};
+
+#[allow(
+    clippy::indexing_slicing,
+    reason = "the sum of two logarithms is below 512"
+)]
+fn mul(a: u8, b: u8) -> u8 {
+    if a == 0 || b == 0 {
+        return 0;
+    }
+    GF.exp[GF.log[a as usize] as usize + GF.log[b as usize] as usize]
+}
+
+/// Inverse of a non-zero element
+#[allow(clippy::indexing_slicing, reason = "logarithms are below 255")]
+fn inv(a: u8) -> u8 {
+    GF.exp[255 - GF.log[a as usize] as usize]
+}
+
+/// Coefficients of the data shards in shard `index`: data shards are kept as is, parity shards
+/// use the rows of a Cauchy matrix, so that any `data_shards` rows are independent.
+fn coefficients(index: usize, data_shards: usize) -> Vec<u8> {
+    (0..data_shards)
+        .map(|i| {
+            if index < data_shards {
+                u8::from(i == index)
+            } else {
+                inv(index as u8 ^ i as u8)
+            }
+        })
+        .collect()
+}
+
+/// Adds `coefficient * shard` to `out`.
+fn mul_add(out: &mut [u8], coefficient: u8, shard: &[u8]) {
+    for (out, byte) in out.iter_mut().zip(shard) {
+        *out ^= mul(coefficient, *byte);
+    }
+}
+
+fn encode(data: &[u8], data_shards: usize, total_shards: usize) -> Vec<Vec<u8>> {
+    let shard_len = data.len().div_ceil(data_shards).max(1);
+    let mut shards: Vec<Vec<u8>> = data
+        .chunks(shard_len)
+        .map(|chunk| {
+            let mut shard = chunk.to_vec();
+            shard.resize(shard_len, 0);
+            shard
+        })
+        .collect();
+    shards.resize(data_shards, vec![0; shard_len]);
+
+    for index in data_shards..total_shards {
+        let mut parity = vec![0; shard_len];
+        for (coefficient, shard) in coefficients(index, data_shards).into_iter().zip(&shards) {
+            mul_add(&mut parity, coefficient, shard);
+        }
+        shards.push(parity);
+    }
+    shards
+}
+
+/// Rebuilds the `size` bytes encoded from the first `data_shards` shards, by index.
+fn decode(shards: &BTreeMap<usize, Vec<u8>>, data_shards: usize, size: usize) -> Result<Vec<u8>> {
+    let picked: Vec<(&usize, &Vec<u8>)> = shards.iter().take(data_shards).collect();
+    let shard_len = picked.first().map_or(0, |(_, shard)| shard.len());
+    if picked.len() < data_shards || picked.iter().any(|(_, shard)| shard.len() != shard_len) {
+        bail!("Missing or inconsistent shards");
+    }
+    let matrix = picked
+        .iter()
+        .map(|(index, _)| coefficients(**index, data_shards))
+        .collect();
+    let inverse = invert(matrix).context("Shards are not independent")?;
+
+    let mut data = Vec::with_capacity(shard_len * data_shards);
+    for row in inverse {
+        let mut shard = vec![0; shard_len];
+        for (coefficient, (_, picked_shard)) in row.into_iter().zip(&picked) {
+            mul_add(&mut shard, coefficient, picked_shard);
+        }
+        data.extend(shard);
+    }
+    if data.len() < size {
+        bail!("Shards hold {} bytes, expected {}", data.len(), size);
+    }
+    data.truncate(size);
+    Ok(data)
+}
+
+/// Inverts a square matrix by Gauss-Jordan elimination, None if it is singular.
+#[allow(
+    clippy::indexing_slicing,
+    reason = "indices are below the size of the square matrix"
+)]
+fn invert(mut matrix: Vec<Vec<u8>>) -> Option<Vec<Vec<u8>>> {
+    let n = matrix.len();
+    let mut inverse: Vec<Vec<u8>> = (0..n)
+        .map(|i| (0..n).map(|j| u8::from(i == j)).collect())
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n).find(|row| matrix[*row][col] != 0)?;
+        matrix.swap(col, pivot);
+        inverse.swap(col, pivot);
+        let factor = inv(matrix[col][col]);
+        for j in 0..n {
+            matrix[col][j] = mul(matrix[col][j], factor);
+            inverse[col][j] = mul(inverse[col][j], factor);
+        }
+        for row in 0..n {
+            let factor = matrix[row][col];
+            if row == col || factor == 0 {
+                continue;
+            }
+            for j in 0..n {
+                matrix[row][j] ^= mul(factor, matrix[col][j]);
+                inverse[row][j] ^= mul(factor, inverse[col][j]);
+            }
+        }
+    }
+    Some(inverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data_proposal() -> DataProposal {
+        DataProposal {
+            id: 1,
+            parent_data_proposal_hash: None,
+            txs: (0..20u8)
+                .map(|i| {
+                    BlobTransaction {
+                        identity: format!("id{i}.c1").into(),
+                        blobs: vec![Blob {
+                            contract_name: "c1".into(),
+                            data: BlobData(vec![i; 100]),
+                        }],
+                    }
+                    .into()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_erasure_coding() {
+        let data: Vec<u8> = (0..1000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let shards = encode(&data, 4, 7);
+        assert_eq!(shards.len(), 7);
+
+        // Any 4 shards rebuild the data
+        for skipped in [[0, 1, 2], [4, 5, 6], [0, 3, 5], [1, 2, 6]] {
+            let kept = shards
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| !skipped.contains(index))
+                .map(|(index, shard)| (index, shard.clone()))
+                .collect();
+            assert_eq!(decode(&kept, 4, data.len()).expect("decode"), data);
+        }
+        let too_few = shards.iter().cloned().enumerate().take(3).collect();
+        assert!(decode(&too_few, 4, data.len()).is_err());
+    }
+
+    #[test]
+    fn test_data_proposal_chunks() {
+        assert_eq!(data_shards(2), None);
+        assert_eq!(data_shards(3), Some(2));
+        assert_eq!(data_shards(6), Some(4));
+
+        let lane = ValidatorPublicKey(vec![1]);
+        let dp = data_proposal();
+        let chunks = encode_data_proposal(&dp, 4, 6).expect("encode");
+        let mut received = DataProposalChunks::default();
+
+        let mut chunks = chunks.into_iter().rev();
+        for chunk in chunks.by_ref().take(3) {
+            assert!(received.add(&lane, chunk.clone()).expect("add").is_none());
+            // Duplicates are ignored
+            assert!(!received.is_wanted(&chunk));
+            assert!(received.add(&lane, chunk).expect("add").is_none());
+        }
+        let chunk = chunks.next().expect("chunk");
+        assert_eq!(received.add(&lane, chunk).expect("add"), Some(dp.clone()));
+        // Chunks arriving once the data proposal is rebuilt are ignored
+        let chunk = chunks.next().expect("chunk");
+        assert!(!received.is_wanted(&chunk));
+        assert!(received.add(&lane, chunk).expect("add").is_none());
+
+        // Tampered chunks don't rebuild the data proposal
+        let mut received = DataProposalChunks::default();
+        let mut chunks = encode_data_proposal(&dp, 2, 3).expect("encode");
+        if let Some(chunk) = chunks.first_mut() {
+            chunk.shard.iter_mut().for_each(|byte| *byte ^= 1);
+        }
+        let mut chunks = chunks.into_iter();
+        let first = chunks.next().expect("chunk");
+        assert!(received.add(&lane, first).expect("add").is_none());
+        assert!(received.add(&lane, chunks.next().expect("chunk")).is_err());
+    }
+}
@@ -16,6 +16,7 @@ pub struct MempoolMetrics {
     sync_request: Counter<u64>,
     sync_reply: Counter<u64>,
     pending_tx: Gauge<u64>,
+    blocks_under_construction: Gauge<u64>,
     new_cut: Counter<u64>,
 }
 
@@ -45,6 +46,9 @@ impl MempoolMetrics {
                 .u64_counter(format!("{mempool}_sync_reply"))
                 .build(),
             pending_tx: my_meter.u64_gauge(format!("{mempool}_pending_tx")).build(),
+            blocks_under_construction: my_meter
+                .u64_gauge(format!("{mempool}_blocks_under_construction"))
+                .build(),
             new_cut: my_meter.u64_counter(format!("{mempool}_new_cut")).build(),
         }
     }
@@ -60,6 +64,10 @@ impl MempoolMetrics {
         self.pending_tx
             .record(nb as u64, &[KeyValue::new("status", "pending")])
     }
+    /// Committed blocks waiting for their data proposals
+    pub fn snapshot_blocks_under_construction(&self, nb: usize) {
+        self.blocks_under_construction.record(nb as u64, &[])
+    }
     pub fn add_new_cut(&self, nc: &QueryNewCut) {
         self.new_cut.add(
             1,
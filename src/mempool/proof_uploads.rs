@@ -0,0 +1,188 @@
+//! Proofs uploaded in chunks through the mempool API, kept until they are complete. Each client
+//! only sees its own uploads, and uploads it gave up on are dropped after a while.
+
+use std::{collections::HashMap, net::IpAddr, time::Duration};
+
+use anyhow::{bail, Result};
+use hyle_model::errors::{ErrorCode, HyleError};
+use tokio::time::Instant;
+
+/// Maximum size of a proof assembled from chunks
+pub const MAX_PROOF_UPLOAD_SIZE: usize = 128 * 1024 * 1024;
+/// Maximum number of chunked proof uploads in progress at once
+pub const MAX_PENDING_PROOF_UPLOADS: usize = 100;
+/// Maximum number of chunked proof uploads in progress for a client
+pub const MAX_PENDING_PROOF_UPLOADS_PER_CLIENT: usize = 10;
+/// Uploads without a new chunk for this long are dropped
+pub const PROOF_UPLOAD_TTL: Duration = Duration::from_secs(600);
+
+#[derive(Debug)]
+struct Upload {
+    data: Vec<u8>,
+    last_chunk: Instant,
+}
+
+/// Partially uploaded proofs, by client and proof hash.
+#[derive(Debug, Default)]
+pub struct ProofUploads {
+    uploads: HashMap<(IpAddr, String), Upload>,
+}
+
+impl ProofUploads {
+    /// Bytes of the proof received so far from `client`.
+    pub fn received(&self, client: IpAddr, proof_hash: &str) -> usize {
+        self.uploads
+            .get(&(client, proof_hash.to_string()))
+            .map_or(0, |upload| upload.data.len())
+    }
+
+    /// Stores a chunk of the proof at `offset`, starting a new upload if needed.
+    /// Chunks already received are accepted again so that clients can safely retry.
+    /// Returns the bytes of the proof received so far.
+    pub fn put_chunk(
+        &mut self,
+        client: IpAddr,
+        proof_hash: &str,
+        offset: usize,
+        chunk: &[u8],
+        now: Instant,
+    ) -> Result<usize> {
+        self.evict_expired(now);
+        let key = (client, proof_hash.to_string());
+        if !self.uploads.contains_key(&key) {
+            if self.uploads.len() >= MAX_PENDING_PROOF_UPLOADS {
+                bail!(HyleError::new(
+                    ErrorCode::TooManyRequests,
+                    "Too many proof uploads in progress"
+                ));
+            }
+            if self.uploads.keys().filter(|(ip, _)| *ip == client).count()
+                >= MAX_PENDING_PROOF_UPLOADS_PER_CLIENT
+            {
+                bail!(HyleError::new(
+                    ErrorCode::TooManyRequests,
+                    format!("Too many proof uploads in progress from {}", client)
+                ));
+            }
+        }
+        let received = self.received(client, proof_hash);
+        if offset > received {
+            bail!(HyleError::new(
+                ErrorCode::ProofUploadOffsetMismatch,
+                format!("Expected offset {}, got {}", received, offset)
+            ));
+        }
+        let new_len = offset.saturating_add(chunk.len());
+        if new_len > MAX_PROOF_UPLOAD_SIZE {
+            bail!(HyleError::new(
+                ErrorCode::PayloadTooLarge,
+                format!("Proof exceeds {} bytes", MAX_PROOF_UPLOAD_SIZE)
+            ));
+        }
+        let upload = self.uploads.entry(key).or_insert_with(|| Upload {
+            data: vec![],
+            last_chunk: now,
+        });
+        upload.last_chunk = now;
+        if new_len > upload.data.len() {
+            upload.data.truncate(offset);
+            upload.data.extend_from_slice(chunk);
+        }
+        Ok(upload.data.len())
+    }
+
+    /// Hands out the proof uploaded by `client`, which is then forgotten.
+    pub fn finish(&mut self, client: IpAddr, proof_hash: &str) -> Result<Vec<u8>> {
+        match self.uploads.remove(&(client, proof_hash.to_string())) {
+            Some(upload) => Ok(upload.data),
+            None => bail!(HyleError::new(
+                ErrorCode::NotFound,
+                format!("No proof upload for {}", proof_hash)
+            )),
+        }
+    }
+
+    fn evict_expired(&mut self, now: Instant) {
+        self.uploads
+            .retain(|_, upload| now.duration_since(upload.last_chunk) < PROOF_UPLOAD_TTL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::{IpAddr, Ipv4Addr};
+
+    use super::*;
+
+    fn code(result: Result<impl std::fmt::Debug>) -> Option<ErrorCode> {
+        result
+            .err()
+            .and_then(|e| e.downcast_ref::<HyleError>().map(|e| e.code))
+    }
+
+    #[test]
+    fn test_chunks_and_finish() {
+        let mut uploads = ProofUploads::default();
+        let (alice, bob) = (
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2)),
+        );
+        let now = Instant::now();
+
+        assert_eq!(uploads.put_chunk(alice, "p", 0, b"abc", now).unwrap(), 3);
+        // Retried chunks are fine, gaps aren't
+        assert_eq!(uploads.put_chunk(alice, "p", 0, b"abc", now).unwrap(), 3);
+        assert_eq!(
+            code(uploads.put_chunk(alice, "p", 4, b"e", now)),
+            Some(ErrorCode::ProofUploadOffsetMismatch)
+        );
+        assert_eq!(uploads.put_chunk(alice, "p", 3, b"de", now).unwrap(), 5);
+
+        // Uploads are only visible to the client that started them
+        assert_eq!(uploads.received(bob, "p"), 0);
+        assert_eq!(code(uploads.finish(bob, "p")), Some(ErrorCode::NotFound));
+        assert_eq!(uploads.finish(alice, "p").unwrap(), b"abcde".to_vec());
+        assert_eq!(code(uploads.finish(alice, "p")), Some(ErrorCode::NotFound));
+
+        assert_eq!(
+            code(uploads.put_chunk(alice, "big", 0, &vec![0; MAX_PROOF_UPLOAD_SIZE + 1], now)),
+            Some(ErrorCode::PayloadTooLarge)
+        );
+    }
+
+    #[test]
+    fn test_upload_limits() {
+        let mut uploads = ProofUploads::default();
+        let client = |i: u32| IpAddr::V4(Ipv4Addr::from(i));
+        let now = Instant::now();
+
+        for i in 0..MAX_PENDING_PROOF_UPLOADS_PER_CLIENT {
+            uploads
+                .put_chunk(client(0), &format!("p{i}"), 0, b"a", now)
+                .unwrap();
+        }
+        assert_eq!(
+            code(uploads.put_chunk(client(0), "one_more", 0, b"a", now)),
+            Some(ErrorCode::TooManyRequests)
+        );
+        // Uploads in progress can go on
+        uploads.put_chunk(client(0), "p0", 1, b"b", now).unwrap();
+
+        for i in 1..(MAX_PENDING_PROOF_UPLOADS - MAX_PENDING_PROOF_UPLOADS_PER_CLIENT + 1) {
+            uploads
+                .put_chunk(client(i as u32), "p", 0, b"a", now)
+                .unwrap();
+        }
+        assert_eq!(
+            code(uploads.put_chunk(client(1000), "p", 0, b"a", now)),
+            Some(ErrorCode::TooManyRequests)
+        );
+
+        // Abandoned uploads expire, making room for new ones
+        let later = now + PROOF_UPLOAD_TTL;
+        uploads
+            .put_chunk(client(1000), "p", 0, b"a", later)
+            .unwrap();
+        assert_eq!(uploads.received(client(0), "p0"), 0);
+    }
+}
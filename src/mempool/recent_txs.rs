@@ -0,0 +1,136 @@
+//! Rolling window of the transactions recently seen by the mempool, to reject duplicate
+//! submissions before they reach data proposals.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+use hyle_model::errors::{ErrorCode, HyleError};
+
+use crate::model::*;
+
+/// What identifies a transaction across its verification: proofs change hash once verified.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum TxKey {
+    Tx(TxHash),
+    Proof(ContractName, ProofDataHash),
+}
+
+impl TxKey {
+    fn new(tx: &Transaction) -> Self {
+        match &tx.transaction_data {
+            TransactionData::Blob(_) => TxKey::Tx(tx.hash()),
+            TransactionData::Proof(proof_tx) => {
+                TxKey::Proof(proof_tx.contract_name.clone(), proof_tx.proof.hash())
+            }
+            TransactionData::VerifiedProof(proof_tx) => {
+                TxKey::Proof(proof_tx.contract_name.clone(), proof_tx.proof_hash.clone())
+            }
+        }
+    }
+}
+
+/// Transactions seen in pending transactions, data proposals or blocks, with the height at which
+/// they were last seen. They are forgotten `window` blocks later.
+#[derive(Debug, Default)]
+pub struct RecentTxs {
+    window: u64,
+    current_height: BlockHeight,
+    seen: HashMap<TxKey, BlockHeight>,
+}
+
+impl RecentTxs {
+    /// A window of 0 blocks disables the detection.
+    pub fn new(window: u64) -> Self {
+        Self {
+            window,
+            ..Default::default()
+        }
+    }
+
+    /// Fails if the transaction was seen within the window.
+    pub fn check(&self, tx: &Transaction) -> Result<()> {
+        if self.window > 0 && self.seen.contains_key(&TxKey::new(tx)) {
+            bail!(HyleError::new(
+                ErrorCode::DuplicateTransaction,
+                format!("Transaction {} was already submitted", tx.hash())
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn record<'a>(&mut self, txs: impl IntoIterator<Item = &'a Transaction>) {
+        if self.window == 0 {
+            return;
+        }
+        for tx in txs {
+            self.seen.insert(TxKey::new(tx), self.current_height);
+        }
+    }
+
+    pub fn on_new_block(&mut self, block: &Block) {
+        self.current_height = block.block_height;
+        self.record(block.txs.iter());
+        let (window, height) = (self.window, self.current_height);
+        self.seen.retain(|_, seen_at| seen_at.0 + window > height.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::make_blob_tx;
+
+    fn code(result: Result<()>) -> Option<ErrorCode> {
+        result
+            .err()
+            .and_then(|e| e.downcast_ref::<HyleError>().map(|e| e.code))
+    }
+
+    #[test]
+    fn test_duplicates_within_window() {
+        let mut recent = RecentTxs::new(2);
+        let tx = make_blob_tx("bob.c1");
+        recent.check(&tx).unwrap();
+        recent.record([&tx]);
+        assert_eq!(
+            code(recent.check(&tx)),
+            Some(ErrorCode::DuplicateTransaction)
+        );
+        recent.check(&make_blob_tx("alice.c1")).unwrap();
+
+        // Being sequenced keeps the transaction in the window
+        let block = |height, txs| Block {
+            block_height: BlockHeight(height),
+            txs,
+            ..Default::default()
+        };
+        recent.on_new_block(&block(5, vec![tx.clone()]));
+        recent.on_new_block(&block(6, vec![]));
+        assert!(recent.check(&tx).is_err());
+        recent.on_new_block(&block(7, vec![]));
+        recent.check(&tx).unwrap();
+
+        // Proofs are recognized once verified
+        let proof = ProofData(vec![1, 2, 3]);
+        let proof_tx: Transaction = ProofTransaction {
+            contract_name: "c1".into(),
+            proof: proof.clone(),
+        }
+        .into();
+        let verified_tx: Transaction = VerifiedProofTransaction {
+            contract_name: "c1".into(),
+            proof: None,
+            proof_hash: proof.hash(),
+            proof_size: proof.0.len(),
+            proven_blobs: vec![],
+            is_recursive: false,
+        }
+        .into();
+        recent.record([&verified_tx]);
+        assert!(recent.check(&proof_tx).is_err());
+
+        let mut disabled = RecentTxs::new(0);
+        disabled.record([&tx]);
+        disabled.check(&tx).unwrap();
+    }
+}
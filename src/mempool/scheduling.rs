@@ -0,0 +1,199 @@
+//! Order in which pending transactions go into data proposals, so that proofs for blob
+//! transactions about to time out aren't stuck behind a flow of new blob transactions.
+
+use std::collections::HashMap;
+
+use crate::{model::*, node_state::timeouts::TimeoutPolicy, utils::conf::MempoolConf};
+
+/// Timeout heights of the blob transactions sequenced but not settled yet.
+#[derive(Debug, Default)]
+pub struct ProofScheduler {
+    conf: MempoolConf,
+    timeout_policy: TimeoutPolicy,
+    current_height: BlockHeight,
+    timeouts: HashMap<TxHash, BlockHeight>,
+}
+
+impl ProofScheduler {
+    pub fn new(conf: MempoolConf, timeout_policy: TimeoutPolicy) -> Self {
+        Self {
+            conf,
+            timeout_policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn current_height(&self) -> BlockHeight {
+        self.current_height
+    }
+
+    pub fn on_new_block(&mut self, block: &Block) {
+        self.current_height = block.block_height;
+        for tx in block.txs.iter() {
+            if let TransactionData::Blob(blob_tx) = &tx.transaction_data {
+                let window = self
+                    .timeout_policy
+                    .window(blob_tx.blobs.iter().map(|blob| &blob.contract_name));
+                self.timeouts.insert(tx.hash(), block.block_height + window);
+            }
+        }
+        for tx_hash in block
+            .successful_txs
+            .iter()
+            .chain(block.failed_txs.iter())
+            .chain(block.timed_out_txs.iter())
+        {
+            self.timeouts.remove(tx_hash);
+        }
+    }
+
+    /// Blocks left before the first blob transaction proven by `tx` times out, if it is a proof
+    /// within the urgency window.
+    fn urgency(&self, tx: &Transaction) -> Option<u64> {
+        let TransactionData::VerifiedProof(proof_tx) = &tx.transaction_data else {
+            return None;
+        };
+        proof_tx
+            .proven_blobs
+            .iter()
+            .filter_map(|output| self.timeouts.get(&output.blob_tx_hash))
+            .map(|timeout| timeout.0.saturating_sub(self.current_height.0))
+            .min()
+            .filter(|left| *left <= self.conf.urgent_proof_window)
+    }
+
+    /// Splits the pending transactions between the next data proposal and those left for later.
+    ///
+    /// Urgent proofs come first, the most urgent ones first. The remaining room is shared between
+    /// the other proofs and blob transactions according to their weights, in arrival order.
+    pub fn schedule(&self, pending: Vec<Transaction>) -> (Vec<Transaction>, Vec<Transaction>) {
+        let max_txs = self.conf.max_txs_per_data_proposal;
+        if max_txs == 0 || pending.len() <= max_txs {
+            return (pending, vec![]);
+        }
+
+        let mut urgent = vec![];
+        let mut proofs = vec![];
+        let mut blobs = vec![];
+        for (index, tx) in pending.iter().enumerate() {
+            match (self.urgency(tx), &tx.transaction_data) {
+                (Some(left), _) => urgent.push((left, index)),
+                (None, TransactionData::Blob(_)) => blobs.push(index),
+                (None, _) => proofs.push(index),
+            }
+        }
+        urgent.sort_by_key(|(left, index)| (*left, *index));
+
+        let mut selected: Vec<usize> = urgent
+            .into_iter()
+            .map(|(_, index)| index)
+            .take(max_txs)
+            .collect();
+        let room = max_txs - selected.len();
+        let proof_weight = u64::from(self.conf.proof_weight);
+        let total_weight = proof_weight + u64::from(self.conf.blob_weight);
+        let proof_share = if total_weight == 0 {
+            room / 2
+        } else {
+            usize::try_from(room as u64 * proof_weight / total_weight).unwrap_or(room)
+        };
+        // Room left unused by one kind goes to the other
+        let proof_room = proof_share.max(room.saturating_sub(blobs.len()));
+        let taken_proofs = proofs.len().min(proof_room);
+        let taken_blobs = blobs.len().min(room - taken_proofs);
+        selected.extend(proofs.into_iter().take(taken_proofs));
+        selected.extend(blobs.into_iter().take(taken_blobs));
+
+        let mut txs: Vec<Option<Transaction>> = pending.into_iter().map(Some).collect();
+        let scheduled = selected
+            .into_iter()
+            .filter_map(|index| txs.get_mut(index).and_then(Option::take))
+            .collect();
+        let left = txs.into_iter().flatten().collect();
+        (scheduled, left)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testkit::make_blob_tx;
+
+    fn proof_tx(blob_tx: &Transaction) -> Transaction {
+        VerifiedProofTransaction {
+            contract_name: "c1".into(),
+            proof: None,
+            proof_hash: ProofDataHash(blob_tx.hash().0),
+            proof_size: 0,
+            proven_blobs: vec![BlobProofOutput {
+                blob_tx_hash: blob_tx.hash(),
+                original_proof_hash: ProofDataHash(blob_tx.hash().0),
+                hyle_output: HyleOutput::default(),
+                program_id: ProgramId(vec![]),
+            }],
+            is_recursive: false,
+        }
+        .into()
+    }
+
+    fn block(height: u64, txs: Vec<Transaction>) -> Block {
+        Block {
+            block_height: BlockHeight(height),
+            txs,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_urgent_proofs_first() {
+        let mut scheduler = ProofScheduler::new(
+            MempoolConf {
+                max_txs_per_data_proposal: 4,
+                urgent_proof_window: 5,
+                proof_weight: 1,
+                blob_weight: 1,
+                ..Default::default()
+            },
+            TimeoutPolicy::new(10, HashMap::new()),
+        );
+        let old_blob = make_blob_tx("old.c1");
+        let recent_blob = make_blob_tx("recent.c1");
+        scheduler.on_new_block(&block(1, vec![old_blob.clone()]));
+        scheduler.on_new_block(&block(8, vec![recent_blob.clone()]));
+
+        let new_blobs: Vec<_> = (0..4)
+            .map(|i| make_blob_tx(&format!("new{i}.c1")))
+            .collect();
+        let mut pending = new_blobs.clone();
+        pending.push(proof_tx(&recent_blob));
+        pending.push(proof_tx(&old_blob));
+
+        // The proof for the blob timing out in 3 blocks goes first, the others share the room
+        let (scheduled, left) = scheduler.schedule(pending.clone());
+        let expected: Vec<_> = [
+            &proof_tx(&old_blob),
+            &proof_tx(&recent_blob),
+            &new_blobs[0],
+            &new_blobs[1],
+        ]
+        .into_iter()
+        .cloned()
+        .collect();
+        assert_eq!(scheduled, expected);
+        assert_eq!(left, vec![new_blobs[2].clone(), new_blobs[3].clone()]);
+
+        // Settled transactions aren't urgent anymore
+        let mut settled = block(9, vec![]);
+        settled.successful_txs = vec![old_blob.hash()];
+        scheduler.on_new_block(&settled);
+        assert_eq!(scheduler.urgency(&proof_tx(&old_blob)), None);
+        assert_eq!(scheduler.urgency(&proof_tx(&recent_blob)), None);
+        scheduler.on_new_block(&block(13, vec![]));
+        assert_eq!(scheduler.urgency(&proof_tx(&recent_blob)), Some(5));
+
+        // Without a limit, everything goes in arrival order
+        let (scheduled, left) = ProofScheduler::default().schedule(pending.clone());
+        assert_eq!(scheduled, pending);
+        assert!(left.is_empty());
+    }
+}
@@ -601,6 +601,14 @@ impl Storage {
             .and_then(|lane| lane.get_last_proposal_hash())
     }
 
+    /// Transactions and bytes in all lanes that no cut includes yet.
+    pub fn pending_data(&self) -> (usize, u64) {
+        self.lanes
+            .values()
+            .map(|lane| lane.pending_size())
+            .fold((0, 0), |(txs, bytes), (t, b)| (txs + t, bytes + b))
+    }
+
     pub fn update_lanes_with_commited_cut(&mut self, committed_cut: &Cut) {
         for (validator, data_proposal_hash, _, poda) in committed_cut.iter() {
             if let Some(lane) = self.lanes.get_mut(validator) {
@@ -714,6 +722,21 @@ impl Lane {
             .unwrap_or_default()
     }
 
+    /// Number of transactions and bytes of the data proposals after the last cut.
+    pub fn pending_size(&self) -> (usize, u64) {
+        let last_cut_hash = self.last_cut.as_ref().map(|(_, hash)| hash);
+        let mut txs = 0;
+        let mut cut_size = LaneBytesSize(0);
+        for (hash, entry) in self.iter_reverse() {
+            if Some(hash) == last_cut_hash {
+                cut_size = entry.cumul_size;
+                break;
+            }
+            txs += entry.data_proposal.txs.len();
+        }
+        (txs, self.get_lane_size().0.saturating_sub(cut_size.0))
+    }
+
     pub fn add_new_proposal(
         &mut self,
         crypto: &BlstCrypto,
@@ -912,6 +935,7 @@ mod tests {
             transaction_data: TransactionData::VerifiedProof(VerifiedProofTransaction {
                 contract_name: contract_name.clone(),
                 proof_hash: proof.hash(),
+                proof_size: proof.0.len(),
                 proven_blobs: vec![BlobProofOutput {
                     program_id: ProgramId(vec![]),
                     blob_tx_hash: TxHash::default(),
@@ -934,6 +958,7 @@ mod tests {
             transaction_data: TransactionData::VerifiedProof(VerifiedProofTransaction {
                 contract_name: contract_name.clone(),
                 proof_hash: proof.hash(),
+                proof_size: proof.0.len(),
                 proven_blobs: vec![BlobProofOutput {
                     program_id: ProgramId(vec![]),
                     blob_tx_hash: TxHash::default(),
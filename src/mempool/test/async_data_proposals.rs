@@ -143,21 +143,20 @@ async fn impl_test_mempool_isnt_blocked_by_proof_verification() -> Result<()> {
                     data_prop_hash = cut.1.clone();
                 }
             }
+            ConsensusEvent::Equivocation(_) => continue,
         }
         let evt: NodeStateEvent = node_client.recv().await?;
-        match evt {
-            NodeStateEvent::NewBlock(block) => {
-                info!("Got Block");
-                if block.txs.iter().any(|tx| {
-                    if let TransactionData::VerifiedProof(data) = &tx.transaction_data {
-                        info!("Got TX in block {}", block.block_height);
-                        data.contract_name == contract_name
-                    } else {
-                        false
-                    }
-                }) {
-                    break;
+        if let NodeStateEvent::NewBlock(block) = evt {
+            info!("Got Block");
+            if block.txs.iter().any(|tx| {
+                if let TransactionData::VerifiedProof(data) = &tx.transaction_data {
+                    info!("Got TX in block {}", block.block_height);
+                    data.contract_name == contract_name
+                } else {
+                    false
                 }
+            }) {
+                break;
             }
         }
     }
@@ -196,6 +195,7 @@ async fn impl_test_mempool_isnt_blocked_by_proof_verification() -> Result<()> {
                 contract_name: contract_name.clone(),
                 proof: Some(proof.clone()),
                 proof_hash: proof_hash.clone(),
+                proof_size: proof.0.len(),
                 proven_blobs: vec![BlobProofOutput {
                     original_proof_hash: proof_hash.clone(),
                     blob_tx_hash: blob_tx_hash.clone(),
@@ -231,18 +231,16 @@ async fn impl_test_mempool_isnt_blocked_by_proof_verification() -> Result<()> {
     // Wait until we commit this TX
     loop {
         let cut: NodeStateEvent = node_client.recv().await?;
-        match cut {
-            NodeStateEvent::NewBlock(block) => {
-                info!("Got block");
-                if block.txs.iter().any(|tx| {
-                    if let TransactionData::VerifiedProof(data) = &tx.transaction_data {
-                        data.contract_name == contract_name
-                    } else {
-                        false
-                    }
-                }) {
-                    break;
+        if let NodeStateEvent::NewBlock(block) = cut {
+            info!("Got block");
+            if block.txs.iter().any(|tx| {
+                if let TransactionData::VerifiedProof(data) = &tx.transaction_data {
+                    data.contract_name == contract_name
+                } else {
+                    false
                 }
+            }) {
+                break;
             }
         }
     }
@@ -1,5 +1,9 @@
-use anyhow::{Context, Result};
-use hyle_model::{Identity, ProofData, Signed, ValidatorSignature};
+use anyhow::{bail, Context, Result};
+use hyle_model::{
+    errors::{ErrorCode, HyleError},
+    BlobTransaction, Identity, ProofData, RegisterContractAction, Signed, StakingAction,
+    StructuredBlobData, ValidatorSignature,
+};
 use sha3::Digest;
 
 use hyle_contract_sdk::{Blob, BlobIndex, HyleOutput, ProgramId, StateDigest, TxHash, Verifier};
@@ -7,10 +11,140 @@ use hyle_contract_sdk::{Blob, BlobIndex, HyleOutput, ProgramId, StateDigest, TxH
 use hyle_verifiers::{noir_proof_verifier, risc0_proof_verifier, sp1_proof_verifier};
 
 use crate::{
-    model::verifiers::{BlstSignatureBlob, NativeVerifiers, ShaBlob},
+    model::{
+        contract_registration::validate_contract_registration,
+        verifiers::{BlstSignatureBlob, NativeVerifiers, ShaBlob},
+    },
     utils::crypto::BlstCrypto,
 };
 
+/// Maximum size of the initial state of a registered contract
+pub const MAX_REGISTRATION_STATE_DIGEST_SIZE: usize = 10 * 1024 * 1024;
+/// Maximum length of a contract name, in bytes
+pub const MAX_CONTRACT_NAME_LEN: usize = 64;
+
+/// Checks that the program id can be used by the verifier, without verifying anything.
+pub fn validate_program_id(verifier: &Verifier, program_id: &ProgramId) -> Result<()> {
+    match verifier.0.as_str() {
+        "test" => {}
+        #[cfg(test)]
+        "test-slow" => {}
+        "risc0" => {
+            if program_id.0.len() != 32 {
+                bail!(
+                    "risc0 program id must be a 32 bytes image id, got {} bytes",
+                    program_id.0.len()
+                );
+            }
+        }
+        "sp1" => {
+            if !serde_json::from_slice::<serde_json::Value>(&program_id.0)
+                .is_ok_and(|v| v.is_object())
+            {
+                bail!("sp1 program id must be a JSON-serialized verifying key");
+            }
+        }
+        "noir" => {
+            if program_id.0.is_empty() {
+                bail!("noir program id must be a non-empty verification key");
+            }
+        }
+        _ => match NativeVerifiers::try_from(verifier) {
+            Ok(native) => {
+                if *program_id != ProgramId::from(native) {
+                    bail!(
+                        "native verifier {} expects program id '{}'",
+                        verifier,
+                        verifier
+                    );
+                }
+            }
+            Err(_) => bail!(
+                "unknown verifier '{}', expected one of risc0, sp1, noir, blst, sha3_256",
+                verifier
+            ),
+        },
+    }
+    Ok(())
+}
+
+/// Static checks of a contract registration, done before it is accepted in the mempool
+/// so that malformed registrations never settle.
+pub fn validate_register_contract_action(action: &RegisterContractAction) -> Result<()> {
+    let name = &action.contract_name;
+    if name.0.len() > MAX_CONTRACT_NAME_LEN {
+        bail!(
+            "contract name is {} bytes long, the maximum is {}",
+            name.0.len(),
+            MAX_CONTRACT_NAME_LEN
+        );
+    }
+    if name.0.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        bail!(
+            "contract name '{}' contains whitespace or control characters",
+            name
+        );
+    }
+    validate_contract_registration(&"hyle".into(), name)?;
+    if action.state_digest.0.len() > MAX_REGISTRATION_STATE_DIGEST_SIZE {
+        bail!(
+            "state digest is {} bytes, the maximum is {}",
+            action.state_digest.0.len(),
+            MAX_REGISTRATION_STATE_DIGEST_SIZE
+        );
+    }
+    validate_program_id(&action.verifier, &action.program_id)
+}
+
+/// Runs [validate_register_contract_action] on the registrations of the transaction.
+/// Blobs for the 'hyle' contract that aren't registrations are left to settlement.
+pub fn validate_contract_registrations(blob_tx: &BlobTransaction) -> Result<()> {
+    for (index, blob) in blob_tx.blobs.iter().enumerate() {
+        if blob.contract_name.0 != "hyle" {
+            continue;
+        }
+        let Ok(registration) =
+            StructuredBlobData::<RegisterContractAction>::try_from(blob.data.clone())
+        else {
+            continue;
+        };
+        validate_register_contract_action(&registration.parameters).map_err(|e| {
+            HyleError::new(
+                ErrorCode::InvalidContractRegistration,
+                format!(
+                    "Invalid registration of contract '{}' in blob {}: {:#}",
+                    registration.parameters.contract_name, index, e
+                ),
+            )
+        })?;
+    }
+    Ok(())
+}
+
+/// Rejects slashes sent to the 'staking' contract, validators are only slashed by consensus
+/// for the equivocations it verified.
+pub fn validate_staking_actions(blob_tx: &BlobTransaction) -> Result<()> {
+    for (index, blob) in blob_tx.blobs.iter().enumerate() {
+        if blob.contract_name.0 != "staking" {
+            continue;
+        }
+        if let Ok(StructuredBlobData {
+            parameters: StakingAction::Slash { validator, .. },
+            ..
+        }) = StructuredBlobData::<StakingAction>::try_from(blob.data.clone())
+        {
+            bail!(HyleError::new(
+                ErrorCode::BadRequest,
+                format!(
+                    "Blob {} slashes validator {}, only consensus can slash",
+                    index, validator
+                ),
+            ));
+        }
+    }
+    Ok(())
+}
+
 pub fn verify_proof(
     proof: &ProofData,
     verifier: &Verifier,
@@ -1,11 +1,17 @@
+use std::collections::BTreeMap;
+
 use hyle_model::api::{
-    APIBlob, APIBlock, APIContract, APIContractState, APITransaction, TransactionStatus,
-    TransactionType,
+    APIBlob, APIBlock, APIBlockProofStats, APIContract, APIContractState, APIContractUpdate,
+    APIEpoch, APIIdentity, APISettlementSummary, APITransaction, APITransactionStatus,
+    BlobDataEncoding, EncodedBlobData, TransactionStatus, TransactionType,
+};
+use hyle_model::{
+    BlockHeight, BlockProductionReason, ConsensusProposalHash, StateRoot, TxContext,
+    ValidatorPublicKey,
 };
-use hyle_model::ConsensusProposalHash;
 use serde::{Deserialize, Serialize};
 
-use sqlx::types::chrono::NaiveDateTime;
+use sqlx::types::{chrono::NaiveDateTime, Json};
 use sqlx::{prelude::Type, Postgres};
 
 use hyle_contract_sdk::TxHash;
@@ -18,6 +24,8 @@ pub struct BlockDb {
     #[sqlx(try_from = "i64")]
     pub height: u64, // Corresponds to BlockHeight
     pub timestamp: NaiveDateTime, // UNIX timestamp
+    pub production_reason: BlockProductionReason,
+    pub state_root: String,
 }
 
 impl From<BlockDb> for APIBlock {
@@ -27,6 +35,8 @@ impl From<BlockDb> for APIBlock {
             parent_hash: value.parent_hash,
             height: value.height,
             timestamp: value.timestamp.and_utc().timestamp(),
+            production_reason: value.production_reason,
+            state_root: StateRoot(value.state_root),
         }
     }
 }
@@ -42,6 +52,7 @@ pub struct TransactionDb {
     pub version: u32, // Transaction version
     pub transaction_type: TransactionType, // Type of transaction
     pub transaction_status: TransactionStatus, // Status of the transaction
+    pub tx_context: Option<Json<TxContext>>, // Only set for blob transactions
 }
 
 impl From<TransactionDb> for APITransaction {
@@ -53,6 +64,24 @@ impl From<TransactionDb> for APITransaction {
             version: val.version,
             transaction_type: val.transaction_type,
             transaction_status: val.transaction_status,
+            tx_context: val.tx_context.map(|ctx| ctx.0),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct TransactionStatusDb {
+    pub tx_hash: TxHashDb,
+    pub block_hash: ConsensusProposalHash,
+    pub transaction_status: TransactionStatus,
+}
+
+impl From<TransactionStatusDb> for APITransactionStatus {
+    fn from(val: TransactionStatusDb) -> Self {
+        APITransactionStatus {
+            tx_hash: val.tx_hash.0,
+            block_hash: val.block_hash,
+            transaction_status: val.transaction_status,
         }
     }
 }
@@ -68,15 +97,15 @@ pub struct BlobDb {
     pub verified: bool,    // Verification status
 }
 
-impl From<BlobDb> for APIBlob {
-    fn from(value: BlobDb) -> Self {
+impl BlobDb {
+    pub fn into_api(self, encoding: BlobDataEncoding, max_size: Option<usize>) -> APIBlob {
         APIBlob {
-            tx_hash: value.tx_hash.0,
-            blob_index: value.blob_index,
-            identity: value.identity,
-            contract_name: value.contract_name,
-            data: value.data,
-            verified: value.verified,
+            tx_hash: self.tx_hash.0,
+            blob_index: self.blob_index,
+            identity: self.identity,
+            contract_name: self.contract_name,
+            data: EncodedBlobData::new(&self.data, encoding, max_size),
+            verified: self.verified,
         }
     }
 }
@@ -111,6 +140,101 @@ impl From<ContractDb> for APIContract {
     }
 }
 
+#[derive(sqlx::FromRow, Debug)]
+pub struct ContractUpdateDb {
+    // Struct for the contract_updates table
+    pub contract_name: String,
+    pub tx_hash: TxHashDb, // Transaction that scheduled the update
+    pub block_hash: ConsensusProposalHash, // Block where the update took effect
+    #[sqlx(try_from = "i64")]
+    pub block_height: u64,
+    pub verifier: String,
+    pub program_id: Vec<u8>,
+}
+
+impl From<ContractUpdateDb> for APIContractUpdate {
+    fn from(val: ContractUpdateDb) -> Self {
+        APIContractUpdate {
+            contract_name: val.contract_name,
+            tx_hash: val.tx_hash.0,
+            block_hash: val.block_hash,
+            block_height: BlockHeight(val.block_height),
+            verifier: val.verifier,
+            program_id: val.program_id,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct EpochDb {
+    // Struct for the epochs table
+    #[sqlx(try_from = "i64")]
+    pub epoch: u64,
+    pub block_hash: ConsensusProposalHash, // First block of the epoch
+    #[sqlx(try_from = "i64")]
+    pub start_height: u64,
+    pub validators: Json<BTreeMap<ValidatorPublicKey, u128>>,
+}
+
+impl From<EpochDb> for APIEpoch {
+    fn from(val: EpochDb) -> Self {
+        APIEpoch {
+            epoch: val.epoch,
+            block_hash: val.block_hash,
+            start_height: BlockHeight(val.start_height),
+            validators: val.validators.0,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct IdentityDb {
+    // Aggregate of the identity_activity table
+    pub identity: String,
+    #[sqlx(try_from = "i64")]
+    pub first_seen_height: u64,
+    #[sqlx(try_from = "i64")]
+    pub tx_count: u64,
+    pub contracts: Vec<String>,
+}
+
+impl From<IdentityDb> for APIIdentity {
+    fn from(val: IdentityDb) -> Self {
+        APIIdentity {
+            identity: val.identity,
+            first_seen_block_height: BlockHeight(val.first_seen_height),
+            tx_count: val.tx_count,
+            contracts: val.contracts,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow, Debug)]
+pub struct BlockProofStatsDb {
+    // Struct for the contract_proof_stats table
+    pub block_hash: ConsensusProposalHash,
+    #[sqlx(try_from = "i64")]
+    pub block_height: u64,
+    #[sqlx(try_from = "i32")]
+    pub proofs: u32,
+    #[sqlx(try_from = "i64")]
+    pub proof_bytes: u64,
+    #[sqlx(try_from = "i32")]
+    pub proof_outputs: u32,
+}
+
+impl From<BlockProofStatsDb> for APIBlockProofStats {
+    fn from(val: BlockProofStatsDb) -> Self {
+        APIBlockProofStats {
+            block_hash: val.block_hash,
+            block_height: BlockHeight(val.block_height),
+            proofs: val.proofs,
+            proof_bytes: val.proof_bytes,
+            proof_outputs: val.proof_outputs,
+        }
+    }
+}
+
 #[derive(sqlx::FromRow, Debug)]
 pub struct ContractStateDb {
     // Struct for the contract_state table
@@ -129,6 +253,30 @@ impl From<ContractStateDb> for APIContractState {
     }
 }
 
+#[derive(sqlx::FromRow, Debug)]
+pub struct SettlementSummaryDb {
+    // Struct for the settlement_summaries table
+    pub contract_name: String,
+    #[sqlx(try_from = "i32")]
+    pub settled_blobs: u32,
+    #[sqlx(try_from = "i32")]
+    pub failed_txs: u32,
+    pub initial_state_digest: Option<Vec<u8>>,
+    pub final_state_digest: Option<Vec<u8>>,
+}
+
+impl From<SettlementSummaryDb> for APISettlementSummary {
+    fn from(value: SettlementSummaryDb) -> Self {
+        APISettlementSummary {
+            contract_name: value.contract_name,
+            settled_blobs: value.settled_blobs,
+            failed_txs: value.failed_txs,
+            initial_state_digest: value.initial_state_digest,
+            final_state_digest: value.final_state_digest,
+        }
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub struct TxHashDb(pub TxHash);
 
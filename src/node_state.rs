@@ -4,21 +4,30 @@ use crate::mempool::verifiers;
 use crate::model::verifiers::NativeVerifiers;
 use crate::model::*;
 use anyhow::{bail, Error, Result};
+use audit::{PendingAudit, SettlementAuditEntry, SettlementOutcome};
 use bincode::{Decode, Encode};
 use contract_registration::validate_contract_registration;
+use hooks::{SettledBlob, SettlementHook, SettlementHooks};
 use hyle_contract_sdk::{utils::parse_structured_blob, BlobIndex, HyleOutput, TxHash};
+use hyle_model::api::{APIBlobTxSimulation, APIContractStateProof};
 use ordered_tx_map::OrderedTxMap;
+use pending_proofs::PendingProofs;
+use staking::state::{EQUIVOCATION_SLASH_FRACTION, HYLE_IDENTITY};
 use std::{
     collections::{BTreeMap, BTreeSet, HashMap},
     sync::Arc,
 };
-use timeouts::Timeouts;
-use tracing::{debug, error, info, trace};
+use timeouts::{TimeoutPolicy, Timeouts};
+use tracing::{debug, error, info, info_span, instrument, trace};
 
 mod api;
+pub mod audit;
+pub mod hooks;
+mod metrics;
 pub mod module;
 mod ordered_tx_map;
-mod timeouts;
+mod pending_proofs;
+pub mod timeouts;
 
 pub struct SettledTxOutput {
     // Original blob transaction, now settled.
@@ -41,6 +50,17 @@ pub struct NodeState {
     // This field is public for testing purposes
     pub contracts: HashMap<ContractName, Contract>,
     unsettled_transactions: OrderedTxMap,
+    /// Audit entries for the transactions removed from the unsettled map, not yet handed out.
+    /// Not persisted.
+    pending_audit: PendingAudit,
+    /// Custom logic run on settled blobs, see [SettlementHook]. Not persisted.
+    settlement_hooks: SettlementHooks,
+    /// How long unsettled transactions wait for proofs. Not persisted.
+    timeout_policy: TimeoutPolicy,
+    /// Settled contract updates, by the height they take effect at
+    pending_contract_updates: BTreeMap<u64, Vec<(TxHash, UpdateContractEffect)>>,
+    /// Proofs of blob transactions not sequenced yet
+    pending_proofs: PendingProofs,
 }
 
 // TODO: we should register the 'hyle' TLD in the genesis block.
@@ -51,6 +71,11 @@ impl Default for NodeState {
             current_height: BlockHeight(0),
             contracts: HashMap::new(),
             unsettled_transactions: OrderedTxMap::default(),
+            pending_audit: PendingAudit::default(),
+            settlement_hooks: SettlementHooks::default(),
+            timeout_policy: TimeoutPolicy::default(),
+            pending_contract_updates: BTreeMap::new(),
+            pending_proofs: PendingProofs::default(),
         };
         // Insert a default hyle-TLD contract
         ret.contracts.insert(
@@ -67,9 +92,60 @@ impl Default for NodeState {
 }
 
 impl NodeState {
+    /// Registers a hook called for every blob of each transaction settled from now on.
+    pub fn register_settlement_hook(&mut self, hook: Arc<dyn SettlementHook>) {
+        self.settlement_hooks.register(hook);
+    }
+
+    /// Sets the timeout policy of transactions received from now on. It must be the same on all nodes.
+    pub fn set_timeout_policy(&mut self, policy: TimeoutPolicy) {
+        self.timeout_policy = policy;
+    }
+
+    pub fn current_height(&self) -> BlockHeight {
+        self.current_height
+    }
+
+    /// Checks the invariants of a node state, e.g. one loaded from a snapshot.
+    pub fn verify(&self) -> Result<()> {
+        if !self.contracts.contains_key(&ContractName::new("hyle")) {
+            bail!("Missing the hyle contract");
+        }
+        self.unsettled_transactions.check_consistency()?;
+        let mut scheduled = BTreeSet::new();
+        for (at, tx) in self.timeouts.scheduled() {
+            if at.0 <= self.current_height.0 {
+                bail!(
+                    "Tx {} times out at {} but the state is at block {}",
+                    tx,
+                    at,
+                    self.current_height
+                );
+            }
+            scheduled.insert(tx);
+        }
+        if let Some(tx) = self
+            .unsettled_transactions
+            .hashes()
+            .find(|tx| !scheduled.contains(tx))
+        {
+            bail!("Unsettled tx {} has no timeout", tx);
+        }
+        Ok(())
+    }
+
+    #[instrument(skip_all, fields(block_height = signed_block.height().0))]
     pub fn handle_signed_block(&mut self, signed_block: &SignedBlock) -> Block {
         self.current_height = signed_block.height();
 
+        let expired = self.pending_proofs.expire(self.current_height);
+        if expired > 0 {
+            debug!(
+                "Dropped {expired} proofs whose blob transaction was never sequenced, {} still pending",
+                self.pending_proofs.len()
+            );
+        }
+
         let mut block_under_construction = Block {
             parent_hash: signed_block.parent_hash().clone(),
             hash: signed_block.hash(),
@@ -80,50 +156,95 @@ impl NodeState {
             blob_proof_outputs: vec![],
             successful_txs: vec![],
             verified_blobs: vec![],
-            staking_actions: vec![],
+            // Slashes come from the equivocations consensus verified, never from transactions
+            staking_actions: signed_block
+                .consensus_proposal
+                .staking_actions
+                .iter()
+                .filter_map(|v| match v {
+                    ConsensusStakingAction::Slash { evidence } => Some((
+                        HYLE_IDENTITY.into(),
+                        StakingAction::Slash {
+                            validator: evidence.validator().clone(),
+                            fraction: EQUIVOCATION_SLASH_FRACTION,
+                            evidence_hash: evidence.offense_hash().ok()?,
+                        },
+                    )),
+                    ConsensusStakingAction::Bond { .. } | ConsensusStakingAction::Leave { .. } => {
+                        None
+                    }
+                })
+                .collect(),
             new_bounded_validators: signed_block
                 .consensus_proposal
                 .staking_actions
                 .iter()
-                .map(|v| match v {
-                    ConsensusStakingAction::Bond { candidate } => candidate.pubkey.clone(),
+                .filter_map(|v| match v {
+                    ConsensusStakingAction::Bond { candidate } => Some(candidate.pubkey.clone()),
+                    ConsensusStakingAction::Slash { .. } | ConsensusStakingAction::Leave { .. } => {
+                        None
+                    }
+                })
+                .collect(),
+            left_validators: signed_block
+                .consensus_proposal
+                .staking_actions
+                .iter()
+                .filter_map(|v| match v {
+                    ConsensusStakingAction::Leave { validator } => Some(validator.pubkey.clone()),
+                    ConsensusStakingAction::Bond { .. } | ConsensusStakingAction::Slash { .. } => {
+                        None
+                    }
                 })
                 .collect(),
             timed_out_txs: vec![], // Added below as it needs the block
             registered_contracts: vec![],
+            updated_contracts: vec![],
             updated_states: BTreeMap::new(),
+            contract_stats: BTreeMap::new(),
+            production_reason: signed_block.consensus_proposal.production_reason,
+            epoch: signed_block.consensus_proposal.epoch.clone(),
+            state_root: StateRoot::default(), // Computed once all transactions are handled
         };
 
         // We'll need to remember some data to validate transactions proofs.
-        let tx_context = Arc::new(TxContext {
-            block_hash: block_under_construction.hash.clone(),
-            block_height: block_under_construction.block_height,
-            timestamp: signed_block.consensus_proposal.timestamp.into(),
-            chain_id: HYLE_TESTNET_CHAIN_ID,
-        });
+        let tx_context = Arc::new(block_under_construction.tx_context());
 
         self.clear_timeouts(&mut block_under_construction);
+        self.apply_contract_updates(&mut block_under_construction);
 
         let txs = signed_block.txs();
         // Handle all transactions
         for tx in txs.iter() {
+            let _span = info_span!("handle_tx", tx_hash = %tx.hash()).entered();
             match &tx.transaction_data {
                 TransactionData::Blob(blob_transaction) => {
                     match self.handle_blob_tx(blob_transaction, tx_context.clone()) {
-                        Ok(Some(tx_hash)) => {
-                            let mut blob_tx_to_try_and_settle = BTreeSet::new();
-                            blob_tx_to_try_and_settle.insert(tx_hash);
+                        Ok(maybe_tx_hash) => {
                             // In case of a BlobTransaction with only native verifies, we need to trigger the
                             // settlement here as we will never get a ProofTransaction
-                            self.settle_txs_until_done(
+                            let mut blob_tx_to_try_and_settle: BTreeSet<TxHash> =
+                                maybe_tx_hash.into_iter().collect();
+                            // Proofs may also have been received before the transaction was sequenced
+                            blob_tx_to_try_and_settle.extend(self.replay_pending_proofs(
+                                &blob_transaction.hash(),
                                 &mut block_under_construction,
-                                blob_tx_to_try_and_settle,
-                            );
+                            ));
+                            if !blob_tx_to_try_and_settle.is_empty() {
+                                self.settle_txs_until_done(
+                                    &mut block_under_construction,
+                                    blob_tx_to_try_and_settle,
+                                );
+                            }
                         }
-                        Ok(None) => {}
                         Err(e) => {
                             error!("Failed to handle blob transaction: {:?}", e);
                             block_under_construction.failed_txs.push(tx.hash());
+                            Self::count_tx_per_contract(
+                                &mut block_under_construction,
+                                blob_transaction.blobs.iter().map(|b| &b.contract_name),
+                                |stats| stats.failed_txs += 1,
+                            );
                         }
                     }
                 }
@@ -162,10 +283,66 @@ impl NodeState {
                 }
             }
         }
+        for output in block_under_construction.blob_proof_outputs.iter() {
+            block_under_construction
+                .contract_stats
+                .entry(output.contract_name.clone())
+                .or_default()
+                .verified_proofs += 1;
+        }
         block_under_construction.txs = txs;
+        block_under_construction.state_root = self.contracts_tree().root();
         block_under_construction
     }
 
+    /// Counts a transaction in the stats of each contract it has blobs for.
+    fn count_tx_per_contract<'a>(
+        block_under_construction: &mut Block,
+        contracts: impl Iterator<Item = &'a ContractName>,
+        count: fn(&mut ContractSettlementStats),
+    ) {
+        for contract_name in contracts.collect::<BTreeSet<_>>() {
+            count(
+                block_under_construction
+                    .contract_stats
+                    .entry(contract_name.clone())
+                    .or_default(),
+            );
+        }
+    }
+
+    fn contracts_tree(&self) -> ContractsMerkleTree {
+        ContractsMerkleTree::new(
+            self.contracts
+                .iter()
+                .map(|(name, contract)| (name, &contract.state)),
+        )
+    }
+
+    /// Proof of a contract's state digest against the state root of the current block.
+    pub fn contract_state_proof(
+        &self,
+        contract_name: &ContractName,
+    ) -> Option<APIContractStateProof> {
+        let tree = self.contracts_tree();
+        Some(APIContractStateProof {
+            block_height: self.current_height,
+            state_root: tree.root(),
+            proof: tree.proof(contract_name)?,
+        })
+    }
+
+    /// Records audit entries for the transactions leaving the unsettled map, to be taken with
+    /// [Self::take_audit_entries] after each block.
+    pub fn enable_audit(&mut self) {
+        self.pending_audit.enable();
+    }
+
+    /// Hand out the audit entries recorded since the last call.
+    pub fn take_audit_entries(&mut self) -> Vec<SettlementAuditEntry> {
+        self.pending_audit.take()
+    }
+
     pub fn handle_register_contract_effect(&mut self, tx: &RegisterContractEffect) {
         info!("📝 Registering contract {}", tx.contract_name);
         self.contracts.insert(
@@ -179,6 +356,62 @@ impl NodeState {
         );
     }
 
+    /// Applies the contract updates taking effect at the current height.
+    fn apply_contract_updates(&mut self, block_under_construction: &mut Block) {
+        let later = self
+            .pending_contract_updates
+            .split_off(&(self.current_height.0 + 1));
+        let due = std::mem::replace(&mut self.pending_contract_updates, later);
+        for (tx_hash, effect) in due.into_values().flatten() {
+            let Some(contract) = self.contracts.get_mut(&effect.contract_name) else {
+                continue;
+            };
+            info!(
+                "📝 Updating contract {} to verifier {}",
+                effect.contract_name, effect.verifier.0
+            );
+            contract.program_id = effect.program_id.clone();
+            contract.verifier = effect.verifier.clone();
+            block_under_construction
+                .updated_contracts
+                .push((tx_hash, effect));
+        }
+    }
+
+    /// The update in a blob for the 'hyle' contract, unless it is a registration.
+    fn parse_contract_update(blob: &Blob) -> Option<UpdateContractAction> {
+        if blob.contract_name.0 != "hyle"
+            || StructuredBlobData::<RegisterContractAction>::try_from(blob.data.clone()).is_ok()
+        {
+            return None;
+        }
+        StructuredBlobData::<UpdateContractAction>::try_from(blob.data.clone())
+            .ok()
+            .map(|data| data.parameters)
+    }
+
+    /// A contract update must come with a blob for the updated contract, so that its current
+    /// program can approve it. The approval itself is only known once proven, see
+    /// [Self::contract_updates_approved].
+    fn validate_contract_updates(tx: &BlobTransaction) -> Result<()> {
+        for update in tx.blobs.iter().filter_map(Self::parse_contract_update) {
+            if update.contract_name.0 == "hyle" {
+                bail!("The hyle contract can't be updated");
+            }
+            if !tx
+                .blobs
+                .iter()
+                .any(|blob| blob.contract_name == update.contract_name)
+            {
+                bail!(
+                    "Update of contract {} without a blob for it",
+                    update.contract_name
+                );
+            }
+        }
+        Ok(())
+    }
+
     /// Returns a TxHash only if the blob transaction calls only native verifiers and thus can be
     /// settled directly (or in the special case of the 'hyle' TLD contract)
     fn handle_blob_tx(
@@ -194,11 +427,131 @@ impl NodeState {
             bail!("Blob Transaction must have at least one blob");
         }
 
+        Self::validate_contract_updates(tx)?;
+        verifiers::validate_staking_actions(tx)?;
+
         let (blob_tx_hash, blobs_hash) = (tx.hash(), tx.blobs_hash());
 
-        let mut should_try_and_settle = true;
+        let (blobs, has_all_outputs) = self.unsettled_blobs(tx, &blob_tx_hash);
 
-        let blobs: Vec<UnsettledBlobMetadata> = tx
+        // If we're behind other pending transactions, we can't settle yet.
+        let should_try_and_settle = self.unsettled_transactions.add(UnsettledBlobTransaction {
+            identity: tx.identity.clone(),
+            hash: blob_tx_hash.clone(),
+            tx_context,
+            blobs_hash,
+            blobs,
+        }) && has_all_outputs;
+
+        // Update timeouts
+        let window = self
+            .timeout_policy
+            .window(tx.blobs.iter().map(|b| &b.contract_name));
+        self.timeouts
+            .set(blob_tx_hash.clone(), self.current_height + window);
+
+        if should_try_and_settle {
+            Ok(Some(blob_tx_hash))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Predicts how a blob transaction would settle on the current contract states, with the
+    /// outputs of its natively verified blobs and the given ones for the others.
+    /// Nothing is sequenced, and the transactions pending before it are not taken into account.
+    pub fn simulate_blob_tx(
+        &self,
+        tx: &BlobTransaction,
+        hyle_outputs: &[HyleOutput],
+    ) -> Result<APIBlobTxSimulation> {
+        tx.validate_identity()?;
+        if tx.blobs.is_empty() {
+            bail!("Blob Transaction must have at least one blob");
+        }
+        Self::validate_contract_updates(tx)?;
+        verifiers::validate_staking_actions(tx)?;
+        if let Some(blob) = tx
+            .blobs
+            .iter()
+            .find(|blob| !self.contracts.contains_key(&blob.contract_name))
+        {
+            bail!("Contract {} is not registered", blob.contract_name);
+        }
+
+        let tx_hash = tx.hash();
+        let (blobs, _) = self.unsettled_blobs(tx, &tx_hash);
+        // The context of the block the transaction will be sequenced in isn't known yet
+        let tx_context = hyle_outputs
+            .iter()
+            .find_map(|output| output.tx_ctx.clone())
+            .unwrap_or_default();
+        let mut unsettled_tx = UnsettledBlobTransaction {
+            identity: tx.identity.clone(),
+            hash: tx_hash.clone(),
+            tx_context: Arc::new(tx_context),
+            blobs_hash: tx.blobs_hash(),
+            blobs,
+        };
+
+        let mut errors = vec![];
+        for hyle_output in hyle_outputs {
+            if let Err(e) = Self::verify_hyle_output(&unsettled_tx, hyle_output) {
+                errors.push(format!("Output of blob {}: {:#}", hyle_output.index, e));
+                continue;
+            }
+            let Some(blob) = unsettled_tx.blobs.get_mut(hyle_output.index.0) else {
+                errors.push(format!("Blob {} not found in the tx", hyle_output.index));
+                continue;
+            };
+            // Executors run the program currently registered for the contract
+            let program_id = self
+                .contracts
+                .get(&blob.blob.contract_name)
+                .map_or(ProgramId(vec![]), |contract| contract.program_id.clone());
+            blob.possible_proofs.push((program_id, hyle_output.clone()));
+        }
+
+        let settlement = Self::settle_blobs_recursively(
+            &self.contracts,
+            BTreeMap::new(),
+            unsettled_tx.blobs.iter(),
+            vec![],
+        );
+        let hyle_outputs = unsettled_tx
+            .blobs
+            .iter()
+            .enumerate()
+            .map(|(i, blob)| {
+                let index = match &settlement {
+                    Some((_, indices, _)) => indices.get(i).copied(),
+                    None => Some(0),
+                };
+                index
+                    .and_then(|index| blob.possible_proofs.get(index))
+                    .map(|(_, output)| output.clone())
+            })
+            .collect();
+
+        Ok(APIBlobTxSimulation {
+            tx_hash,
+            success: settlement.map(|(_, indices, success)| {
+                success && Self::contract_updates_approved(&unsettled_tx, &indices)
+            }),
+            hyle_outputs,
+            errors,
+        })
+    }
+
+    /// Blobs of the transaction, with the outputs of the natively verified ones and the synthetic
+    /// ones of the 'hyle' contract. Also tells whether all blobs got such an output.
+    fn unsettled_blobs(
+        &self,
+        tx: &BlobTransaction,
+        blob_tx_hash: &TxHash,
+    ) -> (Vec<UnsettledBlobMetadata>, bool) {
+        let mut has_all_outputs = true;
+        let blobs = tx
             .blobs
             .iter()
             .enumerate()
@@ -240,8 +593,18 @@ impl NodeState {
                             possible_proofs: vec![(ProgramId(vec![]), synthetic_output)],
                         };
                     }
+                    if Self::parse_contract_update(blob).is_some() {
+                        let synthetic_output = HyleOutput {
+                            success: true,
+                            ..HyleOutput::default()
+                        };
+                        return UnsettledBlobMetadata {
+                            blob: blob.clone(),
+                            possible_proofs: vec![(ProgramId(vec![]), synthetic_output)],
+                        };
+                    }
                 } else {
-                    should_try_and_settle = false;
+                    has_all_outputs = false;
                 }
                 UnsettledBlobMetadata {
                     blob: blob.clone(),
@@ -249,25 +612,31 @@ impl NodeState {
                 }
             })
             .collect();
+        (blobs, has_all_outputs)
+    }
 
-        // If we're behind other pending transactions, we can't settle yet.
-        should_try_and_settle = self.unsettled_transactions.add(UnsettledBlobTransaction {
-            identity: tx.identity.clone(),
-            hash: blob_tx_hash.clone(),
-            tx_context,
-            blobs_hash,
-            blobs,
-        }) && should_try_and_settle;
-
-        // Update timeouts
-        self.timeouts
-            .set(blob_tx_hash.clone(), self.current_height + 100); // TODO: Timeout after 100 blocks, make it configurable !
-
-        if should_try_and_settle {
-            Ok(Some(blob_tx_hash))
-        } else {
-            Ok(None)
+    /// Handles the proofs received before the blob transaction, returns it if it may now settle.
+    fn replay_pending_proofs(
+        &mut self,
+        blob_tx_hash: &TxHash,
+        block_under_construction: &mut Block,
+    ) -> Option<TxHash> {
+        let mut should_settle = None;
+        for (proof_tx_hash, blob_proof_data) in self.pending_proofs.take(blob_tx_hash) {
+            match self.handle_blob_proof(
+                proof_tx_hash.clone(),
+                &mut block_under_construction.blob_proof_outputs,
+                &blob_proof_data,
+            ) {
+                Ok(Some(tx_hash)) => should_settle = Some(tx_hash),
+                Ok(None) => {}
+                Err(err) => info!(
+                    "Failed to handle pending blob #{} of proof transaction {:?}: {err}",
+                    blob_proof_data.hyle_output.index, proof_tx_hash,
+                ),
+            }
         }
+        should_settle
     }
 
     fn handle_blob_proof(
@@ -283,7 +652,17 @@ impl NodeState {
         {
             Some(a) => a,
             _ => {
-                bail!("BlobTx {} not found", blob_proof_data.blob_tx_hash);
+                // The blob transaction may be sequenced in a later block, keep the proof until then.
+                debug!(
+                    "BlobTx {} not found, keeping the proof of blob {} until it is sequenced",
+                    blob_proof_data.blob_tx_hash, blob_proof_data.hyle_output.index
+                );
+                self.pending_proofs.add(
+                    proof_tx_hash,
+                    blob_proof_data.clone(),
+                    self.current_height,
+                )?;
+                return Ok(None);
             }
         };
 
@@ -405,6 +784,8 @@ impl NodeState {
                     bail!("Tx: {} is not ready to settle.", unsettled_tx.hash);
                 }
             };
+        let success =
+            success && Self::contract_updates_approved(unsettled_tx, &blob_proof_output_indices);
 
         // We are OK to settle now.
 
@@ -454,7 +835,10 @@ impl NodeState {
             ) {
                 Ok(contract) => {
                     let mut us = current_contracts.clone();
-                    us.insert(contract.name.clone(), contract);
+                    // Updates only take effect after a delay
+                    if let Some(contract) = contract {
+                        us.insert(contract.name.clone(), contract);
+                    }
                     Self::settle_blobs_recursively(
                         contracts,
                         us,
@@ -520,6 +904,54 @@ impl NodeState {
     /// Handles the multiple side-effects of settling.
     /// This returns the list of new TXs to try and settle next,
     /// i.e. the "next" TXs for each contract.
+    fn run_settlement_hooks(
+        &self,
+        bth: &TxHash,
+        settled_tx: &UnsettledBlobTransaction,
+        blob_proof_output_indices: &[usize],
+        success: bool,
+        block_height: BlockHeight,
+    ) {
+        if self.settlement_hooks.is_empty() {
+            return;
+        }
+        for (i, blob_metadata) in settled_tx.blobs.iter().enumerate() {
+            let hyle_output = if success {
+                blob_proof_output_indices
+                    .get(i)
+                    .and_then(|index| blob_metadata.possible_proofs.get(*index))
+                    .map(|(_, output)| output)
+            } else {
+                None
+            };
+            let settled_blob = SettledBlob {
+                tx_hash: bth,
+                identity: &settled_tx.identity,
+                blob_index: BlobIndex(i),
+                blob: &blob_metadata.blob,
+                hyle_output,
+                success,
+                block_height,
+            };
+            for hook in self.settlement_hooks.iter() {
+                if let Err(e) = hook.on_settled_blob(&settled_blob) {
+                    error!(
+                        "Settlement hook failed on blob {} of tx {}: {:#}",
+                        i, bth, e
+                    );
+                }
+            }
+        }
+    }
+
+    #[instrument(
+        skip_all,
+        fields(
+            tx_hash = %bth,
+            block_height = block_under_construction.block_height.0,
+            success = success
+        )
+    )]
     fn on_settled_blob_tx(
         &mut self,
         block_under_construction: &mut Block,
@@ -530,11 +962,22 @@ impl NodeState {
         success: bool,
     ) -> BTreeSet<TxHash> {
         // Transaction was settled, update our state.
+        self.pending_proofs
+            .settled(bth.clone(), self.current_height);
         if success {
             info!("✨ Settled tx {}", &bth);
         } else {
             info!("⛈️ Settled tx {} has failed", &bth);
         }
+        Self::count_tx_per_contract(
+            block_under_construction,
+            settled_tx.blobs.iter().map(|b| &b.blob.contract_name),
+            if success {
+                |stats| stats.settled_txs += 1
+            } else {
+                |stats| stats.failed_txs += 1
+            },
+        );
 
         // Keep track of which blob proof output we used to settle the TX for each blob.
         // Also note all the TXs that we might want to try and settle next
@@ -555,10 +998,43 @@ impl NodeState {
             })
             .collect::<BTreeSet<_>>();
 
+        // Record how the TX settled before it's gone from memory.
+        self.pending_audit.push(SettlementAuditEntry {
+            tx_hash: bth.clone(),
+            block_height: block_under_construction.block_height,
+            outcome: if success {
+                SettlementOutcome::Success
+            } else {
+                SettlementOutcome::Failure
+            },
+            blob_proof_output_indices: blob_proof_output_indices.clone(),
+            final_states: if success {
+                tx_updated_contracts
+                    .iter()
+                    .map(|(name, contract)| (name.clone(), contract.state.clone()))
+                    .collect()
+            } else {
+                BTreeMap::new()
+            },
+        });
+
+        self.run_settlement_hooks(
+            &bth,
+            &settled_tx,
+            &blob_proof_output_indices,
+            success,
+            block_under_construction.block_height,
+        );
+
         // Handle side-effect of each blobs on the node.
         if !success {
             block_under_construction.failed_txs.push(bth);
         } else {
+            let updates: Vec<UpdateContractAction> = settled_tx
+                .blobs
+                .iter()
+                .filter_map(|blob_metadata| Self::parse_contract_update(&blob_metadata.blob))
+                .collect();
             // Take note of staking and contract registration
             for (i, mut blob_metadata) in settled_tx.blobs.into_iter().enumerate() {
                 #[allow(clippy::indexing_slicing, reason = "all exist by construction")]
@@ -567,12 +1043,28 @@ impl NodeState {
                     .remove(blob_proof_output_indices[i]);
 
                 for rce in settled_proof.1.registered_contracts {
+                    // Approvals of updates only take effect after a delay, see below
+                    if updates.iter().any(|update| update.is_approved_by(&rce)) {
+                        continue;
+                    }
                     self.handle_register_contract_effect(&rce);
                     block_under_construction
                         .registered_contracts
                         .push((bth.clone(), rce));
                 }
 
+                if let Some(update) = Self::parse_contract_update(&blob_metadata.blob) {
+                    let at = self.current_height.0 + CONTRACT_UPDATE_DELAY;
+                    info!(
+                        "📝 Contract {} will be updated at block {}",
+                        update.contract_name, at
+                    );
+                    self.pending_contract_updates
+                        .entry(at)
+                        .or_default()
+                        .push((bth.clone(), update.into()));
+                }
+
                 let blob = blob_metadata.blob;
                 // Keep track of all stakers
                 if blob.contract_name.0 == "staking" {
@@ -618,7 +1110,15 @@ impl NodeState {
         contracts: &HashMap<ContractName, Contract>,
         current_contracts: &BTreeMap<ContractName, Contract>,
         current_blob: &Blob,
-    ) -> Result<Contract> {
+    ) -> Result<Option<Contract>> {
+        if let Some(update) = Self::parse_contract_update(current_blob) {
+            if !contracts.contains_key(&update.contract_name)
+                && !current_contracts.contains_key(&update.contract_name)
+            {
+                bail!("Contract {} is not registered", update.contract_name);
+            }
+            return Ok(None);
+        }
         let Ok(reg) =
             StructuredBlobData::<RegisterContractAction>::try_from(current_blob.data.clone())
         else {
@@ -638,12 +1138,47 @@ impl NodeState {
             );
         }
 
-        Ok(Contract {
+        Ok(Some(Contract {
             name: reg.parameters.contract_name.clone(),
             program_id: reg.parameters.program_id.clone(),
             state: reg.parameters.state_digest.clone(),
             verifier: reg.parameters.verifier.clone(),
-        })
+        }))
+    }
+
+    /// Whether the program of each contract updated by the transaction approved its update, in
+    /// its output for one of the contract's blobs.
+    fn contract_updates_approved(
+        tx: &UnsettledBlobTransaction,
+        blob_proof_output_indices: &[usize],
+    ) -> bool {
+        let approvals: Vec<&RegisterContractEffect> =
+            tx.blobs
+                .iter()
+                .zip(blob_proof_output_indices)
+                .filter_map(|(blob_metadata, index)| {
+                    let (_, output) = blob_metadata.possible_proofs.get(*index)?;
+                    Some(
+                        output.registered_contracts.iter().filter(|effect| {
+                            effect.contract_name == blob_metadata.blob.contract_name
+                        }),
+                    )
+                })
+                .flatten()
+                .collect();
+        tx.blobs
+            .iter()
+            .filter_map(|blob_metadata| Self::parse_contract_update(&blob_metadata.blob))
+            .all(|update| {
+                let approved = approvals.iter().any(|effect| update.is_approved_by(effect));
+                if !approved {
+                    debug!(
+                        "Update of contract {} not approved by its program",
+                        update.contract_name
+                    );
+                }
+                approved
+            })
     }
 
     // Assumes verify_hyle_output was already called
@@ -710,6 +1245,20 @@ impl NodeState {
         txs_at_timeout.retain(|tx| {
             if let Some(mut tx) = self.unsettled_transactions.remove(tx) {
                 info!("⏰ Blob tx timed out: {}", &tx.hash);
+                self.pending_proofs
+                    .settled(tx.hash.clone(), block_under_construction.block_height);
+                Self::count_tx_per_contract(
+                    block_under_construction,
+                    tx.blobs.iter().map(|b| &b.blob.contract_name),
+                    |stats| stats.timed_out_txs += 1,
+                );
+                self.pending_audit.push(SettlementAuditEntry {
+                    tx_hash: tx.hash.clone(),
+                    block_height: block_under_construction.block_height,
+                    outcome: SettlementOutcome::TimedOut,
+                    blob_proof_output_indices: vec![],
+                    final_states: BTreeMap::new(),
+                });
 
                 // Attempt to settle following transactions
                 let mut blob_tx_to_try_and_settle = BTreeSet::new();
@@ -741,8 +1290,11 @@ pub mod test {
     mod native_verifiers;
 
     use super::*;
+    use crate::testkit::{
+        craft_signed_block, make_hyle_output, make_hyle_output_with_state,
+        make_register_contract_tx, new_proof_tx,
+    };
     use assertables::assert_err;
-    use hyle_contract_sdk::flatten_blobs;
     use utils::get_current_timestamp_ms;
 
     async fn new_node_state() -> NodeState {
@@ -756,19 +1308,6 @@ pub mod test {
         }
     }
 
-    pub fn make_register_contract_tx(name: ContractName) -> BlobTransaction {
-        BlobTransaction {
-            identity: "hyle.hyle".into(),
-            blobs: vec![RegisterContractAction {
-                verifier: "test".into(),
-                program_id: ProgramId(vec![]),
-                state_digest: StateDigest(vec![0, 1, 2, 3]),
-                contract_name: name,
-            }
-            .as_blob("hyle".into(), None, None)],
-        }
-    }
-
     fn make_register_contract_effect(contract_name: ContractName) -> RegisterContractEffect {
         RegisterContractEffect {
             verifier: "test".into(),
@@ -778,87 +1317,6 @@ pub mod test {
         }
     }
 
-    pub fn new_proof_tx(
-        contract: &ContractName,
-        hyle_output: &HyleOutput,
-        blob_tx_hash: &TxHash,
-    ) -> VerifiedProofTransaction {
-        let proof = ProofTransaction {
-            contract_name: contract.clone(),
-            proof: ProofData(
-                bincode::encode_to_vec(vec![hyle_output.clone()], bincode::config::standard())
-                    .unwrap(),
-            ),
-        };
-        VerifiedProofTransaction {
-            contract_name: contract.clone(),
-            proven_blobs: vec![BlobProofOutput {
-                hyle_output: hyle_output.clone(),
-                program_id: ProgramId(vec![]),
-                blob_tx_hash: blob_tx_hash.clone(),
-                original_proof_hash: proof.proof.hash(),
-            }],
-            proof_hash: proof.proof.hash(),
-            proof: Some(proof.proof),
-            is_recursive: false,
-        }
-    }
-
-    pub fn make_hyle_output(blob_tx: BlobTransaction, blob_index: BlobIndex) -> HyleOutput {
-        HyleOutput {
-            version: 1,
-            identity: blob_tx.identity.clone(),
-            index: blob_index,
-            blobs: flatten_blobs(&blob_tx.blobs),
-            initial_state: StateDigest(vec![0, 1, 2, 3]),
-            next_state: StateDigest(vec![4, 5, 6]),
-            success: true,
-            tx_hash: blob_tx.hash(),
-            tx_ctx: None,
-            registered_contracts: vec![],
-            program_outputs: vec![],
-        }
-    }
-
-    pub fn make_hyle_output_with_state(
-        blob_tx: BlobTransaction,
-        blob_index: BlobIndex,
-        initial_state: &[u8],
-        next_state: &[u8],
-    ) -> HyleOutput {
-        HyleOutput {
-            version: 1,
-            identity: blob_tx.identity.clone(),
-            index: blob_index,
-            blobs: flatten_blobs(&blob_tx.blobs),
-            initial_state: StateDigest(initial_state.to_vec()),
-            next_state: StateDigest(next_state.to_vec()),
-            success: true,
-            tx_hash: blob_tx.hash(),
-            tx_ctx: None,
-            program_outputs: vec![],
-            registered_contracts: vec![],
-        }
-    }
-
-    fn craft_signed_block(height: u64, txs: Vec<Transaction>) -> SignedBlock {
-        SignedBlock {
-            certificate: AggregateSignature::default(),
-            consensus_proposal: ConsensusProposal {
-                slot: height,
-                ..ConsensusProposal::default()
-            },
-            data_proposals: vec![(
-                ValidatorPublicKey::default(),
-                vec![DataProposal {
-                    id: 1,
-                    parent_data_proposal_hash: None,
-                    txs,
-                }],
-            )],
-        }
-    }
-
     // Small wrapper for the general case until we get a larger refactoring?
     fn handle_verify_proof_transaction(
         state: &mut NodeState,
@@ -936,6 +1394,201 @@ pub mod test {
         assert_eq!(state.contracts.get(&c1).unwrap().state.0, vec![4, 5, 6]);
     }
 
+    #[test_log::test(tokio::test)]
+    async fn simulate_blob_tx() {
+        let mut state = new_node_state().await;
+        let c1 = ContractName::new("c1");
+        state.handle_register_contract_effect(&make_register_contract_effect(c1.clone()));
+
+        let blob_tx = BlobTransaction {
+            identity: Identity::new("test.c1"),
+            blobs: vec![new_blob("c1")],
+        };
+
+        // Without an output, the blob can't settle
+        let simulation = state.simulate_blob_tx(&blob_tx, &[]).unwrap();
+        assert_eq!(simulation.success, None);
+        assert_eq!(simulation.hyle_outputs, vec![None]);
+
+        let hyle_output = make_hyle_output(blob_tx.clone(), BlobIndex(0));
+        let simulation = state
+            .simulate_blob_tx(&blob_tx, &[hyle_output.clone()])
+            .unwrap();
+        assert_eq!(simulation.tx_hash, blob_tx.hash());
+        assert_eq!(simulation.success, Some(true));
+        assert_eq!(simulation.hyle_outputs, vec![Some(hyle_output.clone())]);
+
+        let mut failure = hyle_output.clone();
+        failure.success = false;
+        let simulation = state.simulate_blob_tx(&blob_tx, &[failure]).unwrap();
+        assert_eq!(simulation.success, Some(false));
+
+        // Outputs of another transaction are rejected
+        let mut other = hyle_output;
+        other.tx_hash = TxHash::new("other");
+        let simulation = state.simulate_blob_tx(&blob_tx, &[other]).unwrap();
+        assert_eq!(simulation.success, None);
+        assert_eq!(simulation.errors.len(), 1);
+
+        // Nothing was sequenced
+        assert_eq!(state.contracts.get(&c1).unwrap().state.0, vec![0, 1, 2, 3]);
+        assert!(state.unsettled_transactions.get(&blob_tx.hash()).is_none());
+
+        let unknown = BlobTransaction {
+            identity: Identity::new("test.c1"),
+            blobs: vec![new_blob("unknown")],
+        };
+        assert!(state.simulate_blob_tx(&unknown, &[]).is_err());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn settlement_hooks_see_settled_blobs() {
+        struct RecordingHook(std::sync::Mutex<Vec<(TxHash, BlobIndex, bool, Option<Vec<u8>>)>>);
+        impl SettlementHook for RecordingHook {
+            fn on_settled_blob(&self, blob: &SettledBlob<'_>) -> Result<()> {
+                self.0.lock().unwrap().push((
+                    blob.tx_hash.clone(),
+                    blob.blob_index,
+                    blob.success,
+                    blob.hyle_output.map(|o| o.next_state.0.clone()),
+                ));
+                Ok(())
+            }
+        }
+
+        let mut state = new_node_state().await;
+        let hook = Arc::new(RecordingHook(Default::default()));
+        state.register_settlement_hook(hook.clone());
+
+        let c1 = ContractName::new("c1");
+        let register_c1 = make_register_contract_effect(c1.clone());
+        state.handle_register_contract_effect(&register_c1);
+
+        let blob_tx = BlobTransaction {
+            identity: Identity::new("test.c1"),
+            blobs: vec![new_blob("c1")],
+        };
+        state.handle_blob_tx(&blob_tx, bogus_tx_context()).unwrap();
+        assert!(hook.0.lock().unwrap().is_empty());
+
+        let hyle_output = make_hyle_output(blob_tx.clone(), BlobIndex(0));
+        let verified_proof = new_proof_tx(&c1, &hyle_output, &blob_tx.hash());
+        state.handle_signed_block(&craft_signed_block(1, vec![verified_proof.into()]));
+
+        assert_eq!(
+            *hook.0.lock().unwrap(),
+            vec![(blob_tx.hash(), BlobIndex(0), true, Some(vec![4, 5, 6]))]
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn proof_before_blob_tx() {
+        let mut state = new_node_state().await;
+        let c1 = ContractName::new("c1");
+        state.handle_register_contract_effect(&make_register_contract_effect(c1.clone()));
+
+        let blob_tx = BlobTransaction {
+            identity: Identity::new("test.c1"),
+            blobs: vec![new_blob("c1")],
+        };
+        let hyle_output = make_hyle_output(blob_tx.clone(), BlobIndex(0));
+        let verified_proof = new_proof_tx(&c1, &hyle_output, &blob_tx.hash());
+
+        let block =
+            state.handle_signed_block(&craft_signed_block(1, vec![verified_proof.clone().into()]));
+        assert!(block.blob_proof_outputs.is_empty());
+        assert_eq!(state.pending_proofs.len(), 1);
+
+        let block = state.handle_signed_block(&craft_signed_block(2, vec![blob_tx.clone().into()]));
+        assert_eq!(block.blob_proof_outputs.len(), 1);
+        assert_eq!(block.successful_txs, vec![blob_tx.hash()]);
+        assert!(state.pending_proofs.is_empty());
+
+        // Proofs are dropped if the blob transaction isn't sequenced in time, and never kept
+        // once it settled
+        let other_tx = BlobTransaction {
+            identity: Identity::new("other.c1"),
+            blobs: vec![new_blob("c1")],
+        };
+        let hyle_output = make_hyle_output(other_tx.clone(), BlobIndex(0));
+        let other_proof = new_proof_tx(&c1, &hyle_output, &other_tx.hash());
+        state.handle_signed_block(&craft_signed_block(
+            3,
+            vec![other_proof.into(), verified_proof.into()],
+        ));
+        assert_eq!(state.pending_proofs.len(), 1);
+        let block = state.handle_signed_block(&craft_signed_block(
+            3 + pending_proofs::PENDING_PROOF_WINDOW,
+            vec![other_tx.into()],
+        ));
+        assert!(block.blob_proof_outputs.is_empty());
+        assert!(block.successful_txs.is_empty());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn snapshot_roundtrip_verifies() {
+        let mut state = new_node_state().await;
+        let c1 = ContractName::new("c1");
+        state.handle_register_contract_effect(&make_register_contract_effect(c1.clone()));
+
+        let blob_tx = BlobTransaction {
+            identity: Identity::new("test.c1"),
+            blobs: vec![new_blob("c1")],
+        };
+        state.handle_signed_block(&craft_signed_block(1, vec![blob_tx.clone().into()]));
+        assert!(state.verify().is_ok());
+
+        let encoded = bincode::encode_to_vec(&state, bincode::config::standard()).unwrap();
+        let (mut loaded, _): (NodeState, _) =
+            bincode::decode_from_slice(&encoded, bincode::config::standard()).unwrap();
+        assert!(loaded.verify().is_ok());
+        assert_eq!(loaded.current_height(), BlockHeight(1));
+        assert!(loaded.unsettled_transactions.get(&blob_tx.hash()).is_some());
+
+        // A snapshot older than the pending timeouts it holds is rejected
+        loaded.current_height = BlockHeight(u64::MAX);
+        assert!(loaded.verify().is_err());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn state_root_proves_contract_states() {
+        let mut state = new_node_state().await;
+        let c1 = ContractName::new("c1");
+        let c2 = ContractName::new("c2");
+
+        let block = state.handle_signed_block(&craft_signed_block(
+            1,
+            vec![
+                make_register_contract_tx(c1.clone()).into(),
+                make_register_contract_tx(c2.clone()).into(),
+            ],
+        ));
+        let proof = state.contract_state_proof(&c1).unwrap();
+        assert_eq!(proof.block_height, BlockHeight(1));
+        assert_eq!(proof.state_root, block.state_root);
+        assert!(proof.proof.verify(&block.state_root));
+
+        let blob_tx = BlobTransaction {
+            identity: Identity::new("test.c1"),
+            blobs: vec![new_blob("c1")],
+        };
+        let hyle_output = make_hyle_output(blob_tx.clone(), BlobIndex(0));
+        let proof_tx = new_proof_tx(&c1, &hyle_output, &blob_tx.hash());
+        let next_block = state.handle_signed_block(&craft_signed_block(
+            2,
+            vec![blob_tx.into(), proof_tx.into()],
+        ));
+
+        // c1's state changed, so did the root
+        assert_ne!(next_block.state_root, block.state_root);
+        assert!(!proof.proof.verify(&next_block.state_root));
+        let c2_proof = state.contract_state_proof(&c2).unwrap().proof;
+        assert!(c2_proof.verify(&next_block.state_root));
+        assert!(state
+            .contract_state_proof(&ContractName::new("unknown"))
+            .is_none());
+    }
+
     #[test_log::test(tokio::test)]
     async fn blob_tx_without_blobs() {
         let mut state = new_node_state().await;
@@ -1429,13 +2082,58 @@ pub mod test {
         ));
 
         // This should trigger the timeout
-        let timed_out_tx_hashes = state
-            .handle_signed_block(&craft_signed_block(103, vec![]))
-            .timed_out_txs;
+        let block = state.handle_signed_block(&craft_signed_block(103, vec![]));
+        let timed_out_tx_hashes = block.timed_out_txs;
 
         // Check that the transaction has timed out
         assert!(timed_out_tx_hashes.contains(&blob_tx_hash));
         assert!(state.unsettled_transactions.get(&blob_tx_hash).is_none());
+        assert_eq!(
+            block
+                .contract_stats
+                .get(&c1)
+                .map(|stats| stats.timed_out_txs),
+            Some(1)
+        );
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_tx_timeout_contract_override() {
+        let mut state = new_node_state().await;
+        state.set_timeout_policy(TimeoutPolicy::new(
+            100,
+            HashMap::from([(ContractName::new("slow"), 500)]),
+        ));
+        let c1 = ContractName::new("c1");
+        let slow = ContractName::new("slow");
+
+        let blob_tx = BlobTransaction {
+            identity: Identity::new("test.c1"),
+            blobs: vec![new_blob(&c1.0), new_blob(&slow.0)],
+        };
+        let blob_tx_hash = blob_tx.hash();
+
+        state.handle_signed_block(&craft_signed_block(
+            3,
+            vec![
+                make_register_contract_tx(c1).into(),
+                make_register_contract_tx(slow).into(),
+                blob_tx.into(),
+            ],
+        ));
+
+        assert_eq!(
+            timeouts::tests::get(&state.timeouts, &blob_tx_hash),
+            Some(BlockHeight(503))
+        );
+        assert!(!state
+            .handle_signed_block(&craft_signed_block(103, vec![]))
+            .timed_out_txs
+            .contains(&blob_tx_hash));
+        assert!(state
+            .handle_signed_block(&craft_signed_block(503, vec![]))
+            .timed_out_txs
+            .contains(&blob_tx_hash));
     }
 
     #[test_log::test(tokio::test)]
@@ -1471,6 +2169,8 @@ pub mod test {
         );
 
         assert!(state.unsettled_transactions.get(&blob_tx_hash).is_none());
+        // Audit entries are only collected once enabled
+        assert!(state.take_audit_entries().is_empty());
         // The TX remains in the map
         assert_eq!(
             timeouts::tests::get(&state.timeouts, &blob_tx_hash),
@@ -1491,6 +2191,7 @@ pub mod test {
     #[test_log::test(tokio::test)]
     async fn test_tx_on_timeout_settle_next_txs() {
         let mut state = new_node_state().await;
+        state.enable_audit();
         let c1 = ContractName::new("c1");
         let c2 = ContractName::new("c2");
         let register_c1 = make_register_contract_tx(c1.clone());
@@ -1553,6 +2254,28 @@ pub mod test {
                 assert!(state.unsettled_transactions.get(tx_hash).is_none());
                 assert!(block.successful_txs.contains(tx_hash));
             });
+
+        // Every removal from the unsettled map left an audit entry behind
+        let audit = state.take_audit_entries();
+        let outcome_of = |tx_hash: &TxHash| {
+            audit
+                .iter()
+                .find(|e| &e.tx_hash == tx_hash)
+                .map(|e| (e.block_height, e.outcome))
+        };
+        assert_eq!(
+            outcome_of(&blocking_tx_hash),
+            Some((BlockHeight(204), SettlementOutcome::TimedOut))
+        );
+        assert_eq!(
+            outcome_of(&ready_same_block_hash),
+            Some((BlockHeight(204), SettlementOutcome::Success))
+        );
+        assert_eq!(
+            outcome_of(&ready_later_block_hash),
+            Some((BlockHeight(204), SettlementOutcome::Success))
+        );
+        assert!(state.take_audit_entries().is_empty());
     }
 
     mod contract_registration {
@@ -1716,5 +2439,98 @@ pub mod test {
             );
             assert_eq!(state.contracts.len(), 3);
         }
+
+        #[test_log::test(tokio::test)]
+        async fn test_update_contract() {
+            let mut state = new_node_state().await;
+            let register = make_tx("hyle.hyle".into(), "hyle".into(), "c1".into());
+            state.handle_signed_block(&craft_signed_block(1, vec![register.clone().into()]));
+
+            let update = UpdateContractAction {
+                contract_name: "c1".into(),
+                verifier: "test2".into(),
+                program_id: ProgramId(vec![9]),
+            }
+            .as_blob("hyle".into(), None, None);
+            assert!(register
+                .blobs
+                .iter()
+                .all(|blob| NodeState::parse_contract_update(blob).is_none()));
+            assert!(NodeState::parse_contract_update(&update).is_some());
+
+            // The contract must approve its own update
+            let unapproved_tx = BlobTransaction {
+                identity: "test.hyle".into(),
+                blobs: vec![update.clone()],
+            };
+            let update_tx = BlobTransaction {
+                identity: "test.c1".into(),
+                blobs: vec![update.clone(), new_blob("c1")],
+            };
+            let block = state.handle_signed_block(&craft_signed_block(
+                2,
+                vec![unapproved_tx.clone().into(), update_tx.clone().into()],
+            ));
+            assert_eq!(block.failed_txs, vec![unapproved_tx.hash()]);
+
+            let proof_tx = new_proof_tx(
+                &"c1".into(),
+                &make_hyle_output(update_tx.clone(), BlobIndex(1)),
+                &update_tx.hash(),
+            );
+            // A call to the contract isn't enough: its program has to approve the update
+            let block = state.handle_signed_block(&craft_signed_block(3, vec![proof_tx.into()]));
+            assert_eq!(block.failed_txs, vec![update_tx.hash()]);
+
+            let update_tx = BlobTransaction {
+                identity: "other.c1".into(),
+                blobs: vec![update, new_blob("c1")],
+            };
+            let mut approving_output = make_hyle_output(update_tx.clone(), BlobIndex(1));
+            approving_output.registered_contracts = vec![UpdateContractAction {
+                contract_name: "c1".into(),
+                verifier: "test2".into(),
+                program_id: ProgramId(vec![9]),
+            }
+            .approval()];
+            let proof_tx = new_proof_tx(&"c1".into(), &approving_output, &update_tx.hash());
+            let block = state.handle_signed_block(&craft_signed_block(
+                4,
+                vec![update_tx.clone().into(), proof_tx.into()],
+            ));
+            assert_eq!(block.successful_txs, vec![update_tx.hash()]);
+            assert!(block.registered_contracts.is_empty());
+            assert!(block.updated_contracts.is_empty());
+            assert_eq!(
+                state
+                    .contracts
+                    .get(&ContractName::new("c1"))
+                    .unwrap()
+                    .program_id,
+                ProgramId(vec![])
+            );
+
+            let block =
+                state.handle_signed_block(&craft_signed_block(3 + CONTRACT_UPDATE_DELAY, vec![]));
+            assert!(block.updated_contracts.is_empty());
+
+            let block =
+                state.handle_signed_block(&craft_signed_block(4 + CONTRACT_UPDATE_DELAY, vec![]));
+            assert_eq!(
+                block.updated_contracts,
+                vec![(
+                    update_tx.hash(),
+                    UpdateContractEffect {
+                        contract_name: "c1".into(),
+                        verifier: "test2".into(),
+                        program_id: ProgramId(vec![9]),
+                    }
+                )]
+            );
+            let contract = state.contracts.get(&ContractName::new("c1")).unwrap();
+            assert_eq!(contract.program_id, ProgramId(vec![9]));
+            assert_eq!(contract.verifier, Verifier("test2".into()));
+            assert_eq!(contract.state, StateDigest(vec![4, 5, 6]));
+        }
     }
 }
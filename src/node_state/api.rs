@@ -6,7 +6,11 @@ use axum::{
     Json, Router,
 };
 use hyle_contract_sdk::ContractName;
-use hyle_model::UnsettledBlobTransaction;
+use hyle_model::{
+    api::{APIBlobTxSimulation, APIContractStateProof, APISimulateBlobTx},
+    errors::HyleError,
+    UnsettledBlobTransaction,
+};
 use tracing::error;
 use utoipa::OpenApi;
 use utoipa_axum::{router::OpenApiRouter, routes};
@@ -18,7 +22,13 @@ use crate::{
         metrics::BusMetrics,
     },
     model::{BlockHeight, CommonRunContext, Contract},
-    node_state::module::{QueryBlockHeight, QueryUnsettledTx},
+    node_state::{
+        audit::SettlementAuditEntry,
+        module::{
+            QueryBlockHeight, QueryContractStateProof, QuerySettlementAudit, QuerySimulateBlobTx,
+            QueryUnsettledTx,
+        },
+    },
     rest::AppError,
 };
 
@@ -27,6 +37,9 @@ struct RestBusClient {
     sender(Query<ContractName, Contract>),
     sender(Query<QueryBlockHeight, BlockHeight>),
     sender(Query<QueryUnsettledTx, UnsettledBlobTransaction>),
+    sender(Query<QuerySettlementAudit, Vec<SettlementAuditEntry>>),
+    sender(Query<QueryContractStateProof, APIContractStateProof>),
+    sender(Query<QuerySimulateBlobTx, APIBlobTxSimulation>),
 }
 }
 
@@ -46,8 +59,11 @@ pub async fn api(ctx: &CommonRunContext) -> Router<()> {
         .routes(routes!(get_block_height))
         // FIXME: we expose this endpoint for testing purposes. This should be removed or adapted
         .routes(routes!(get_contract))
+        .routes(routes!(get_contract_state_proof))
         // TODO: figure out if we want to rely on the indexer instead
         .routes(routes!(get_unsettled_tx))
+        .routes(routes!(get_settlement_audit))
+        .routes(routes!(simulate_blob_tx))
         .split_for_parts();
 
     if let Ok(mut o) = ctx.openapi.lock() {
@@ -86,6 +102,37 @@ pub async fn get_contract(
     }
 }
 
+/// The state root is the one computed by this node, not committed to by the signed block headers:
+/// the proof is only as trustworthy as the node serving it.
+#[utoipa::path(
+    get,
+    path = "/contract/{name}/state_proof",
+    params(
+        ("name" = String, Path, description = "Contract name")
+    ),
+    tag = "Node State",
+    responses(
+        (status = OK, body = APIContractStateProof)
+    )
+)]
+pub async fn get_contract_state_proof(
+    Path(name): Path<ContractName>,
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    let name_clone = name.clone();
+    match state.bus.request(QueryContractStateProof(name)).await {
+        Ok(proof) => Ok(Json(proof)),
+        err => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::NOT_FOUND,
+                anyhow!("Error while getting state proof of contract {}", name_clone),
+            ))
+        }
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/unsettled_tx/{blob_tx_hash}",
@@ -118,6 +165,60 @@ pub async fn get_unsettled_tx(
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/settlement_audit/last/{blocks}",
+    params(
+        ("blocks" = u64, Path, description = "Number of most recent blocks to cover")
+    ),
+    tag = "Node State",
+    responses(
+        (status = OK, body = [SettlementAuditEntry])
+    )
+)]
+pub async fn get_settlement_audit(
+    Path(blocks): Path<u64>,
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    match state.bus.request(QuerySettlementAudit(blocks)).await {
+        Ok(entries) => Ok(Json(entries)),
+        err => {
+            error!("{:?}", err);
+
+            Err(AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Error while getting settlement audit trail"),
+            ))
+        }
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/tx/simulate/blob",
+    tag = "Node State",
+    request_body = APISimulateBlobTx,
+    responses(
+        (status = OK, body = APIBlobTxSimulation)
+    )
+)]
+pub async fn simulate_blob_tx(
+    State(mut state): State<RouterState>,
+    Json(payload): Json<APISimulateBlobTx>,
+) -> Result<impl IntoResponse, AppError> {
+    match state.bus.request(QuerySimulateBlobTx(payload)).await {
+        Ok(simulation) => Ok(Json(simulation)),
+        Err(err) => {
+            // Invalid transactions carry their reason, other errors mean the node state isn't available
+            let status = err
+                .downcast_ref::<HyleError>()
+                .and_then(|e| StatusCode::from_u16(e.code.http_status()).ok())
+                .unwrap_or(StatusCode::SERVICE_UNAVAILABLE);
+            Err(AppError(status, err))
+        }
+    }
+}
+
 #[utoipa::path(
     get,
     path = "/da/block/height",
@@ -162,6 +263,22 @@ impl Clone for RouterState {
                     >,
                 >::get(&self.bus)
                 .clone(),
+                Pick::<
+                    tokio::sync::broadcast::Sender<
+                        Query<QuerySettlementAudit, Vec<SettlementAuditEntry>>,
+                    >,
+                >::get(&self.bus)
+                .clone(),
+                Pick::<
+                    tokio::sync::broadcast::Sender<
+                        Query<QueryContractStateProof, APIContractStateProof>,
+                    >,
+                >::get(&self.bus)
+                .clone(),
+                Pick::<
+                    tokio::sync::broadcast::Sender<Query<QuerySimulateBlobTx, APIBlobTxSimulation>>,
+                >::get(&self.bus)
+                .clone(),
             ),
         }
     }
@@ -0,0 +1,144 @@
+//! Compact record of how blob transactions left the unsettled map.
+
+use std::collections::{BTreeMap, VecDeque};
+
+use bincode::{
+    de::{BorrowDecoder, Decoder},
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    BorrowDecode, Decode, Encode,
+};
+use hyle_contract_sdk::TxHash;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::model::{BlockHeight, ContractName, StateDigest};
+
+/// How many blocks worth of audit entries we keep around.
+pub const MAX_AUDIT_BLOCKS: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Encode, Decode, ToSchema)]
+pub enum SettlementOutcome {
+    Success,
+    Failure,
+    TimedOut,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encode, Decode, ToSchema)]
+pub struct SettlementAuditEntry {
+    pub tx_hash: TxHash,
+    pub block_height: BlockHeight,
+    pub outcome: SettlementOutcome,
+    /// Index of the blob proof output used for each blob. Empty for timed out transactions.
+    pub blob_proof_output_indices: Vec<usize>,
+    /// Contract states after settlement. Empty unless the transaction succeeded.
+    pub final_states: BTreeMap<ContractName, StateDigest>,
+}
+
+/// Audit entries recorded by a node state and not handed out yet. Only the node state of the
+/// node state module collects them, to keep its audit log. Not persisted, as they are handed out
+/// after each block.
+#[derive(Debug, Default, Clone)]
+pub struct PendingAudit {
+    enabled: bool,
+    entries: Vec<SettlementAuditEntry>,
+}
+
+impl PendingAudit {
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    pub fn push(&mut self, entry: SettlementAuditEntry) {
+        if self.enabled {
+            self.entries.push(entry);
+        }
+    }
+
+    pub fn take(&mut self) -> Vec<SettlementAuditEntry> {
+        std::mem::take(&mut self.entries)
+    }
+}
+
+impl Encode for PendingAudit {
+    fn encode<E: Encoder>(&self, _: &mut E) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl Decode for PendingAudit {
+    fn decode<D: Decoder>(_: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::default())
+    }
+}
+
+impl<'de> BorrowDecode<'de> for PendingAudit {
+    fn borrow_decode<D: BorrowDecoder<'de>>(_: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::default())
+    }
+}
+
+/// Audit entries of the last `MAX_AUDIT_BLOCKS` blocks, oldest first.
+#[derive(Debug, Default, Clone, Encode, Decode)]
+pub struct SettlementAuditLog {
+    entries: VecDeque<SettlementAuditEntry>,
+}
+
+impl SettlementAuditLog {
+    pub fn extend(&mut self, entries: Vec<SettlementAuditEntry>) {
+        let Some(last_height) = entries.last().map(|e| e.block_height) else {
+            return;
+        };
+        self.entries.extend(entries);
+
+        while let Some(oldest) = self.entries.front() {
+            if oldest.block_height.0 + MAX_AUDIT_BLOCKS > last_height.0 {
+                break;
+            }
+            self.entries.pop_front();
+        }
+    }
+
+    /// Entries for the last `n` blocks up to and including `current_height`.
+    pub fn last_blocks(&self, current_height: BlockHeight, n: u64) -> Vec<SettlementAuditEntry> {
+        let from = current_height.0.saturating_sub(n.saturating_sub(1));
+        self.entries
+            .iter()
+            .filter(|e| n > 0 && e.block_height.0 >= from)
+            .cloned()
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(height: u64) -> SettlementAuditEntry {
+        SettlementAuditEntry {
+            tx_hash: TxHash(format!("tx{height}")),
+            block_height: BlockHeight(height),
+            outcome: SettlementOutcome::Success,
+            blob_proof_output_indices: vec![0],
+            final_states: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_audit_log_is_bounded() {
+        let mut log = SettlementAuditLog::default();
+        log.extend(vec![entry(1), entry(2)]);
+        log.extend(vec![entry(MAX_AUDIT_BLOCKS + 1)]);
+
+        let all = log.last_blocks(BlockHeight(MAX_AUDIT_BLOCKS + 1), u64::MAX);
+        assert_eq!(all, vec![entry(2), entry(MAX_AUDIT_BLOCKS + 1)]);
+
+        assert_eq!(
+            log.last_blocks(BlockHeight(MAX_AUDIT_BLOCKS + 1), 1),
+            vec![entry(MAX_AUDIT_BLOCKS + 1)]
+        );
+        assert!(log
+            .last_blocks(BlockHeight(MAX_AUDIT_BLOCKS + 1), 0)
+            .is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+//! Extension point for embedders to run custom logic on settled blobs.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use bincode::{
+    de::{BorrowDecoder, Decoder},
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    BorrowDecode, Decode, Encode,
+};
+use hyle_contract_sdk::{BlobIndex, HyleOutput, TxHash};
+
+use crate::model::{Blob, BlockHeight, Identity};
+
+/// A blob of a settled transaction, as seen by a [SettlementHook].
+#[derive(Debug)]
+pub struct SettledBlob<'a> {
+    pub tx_hash: &'a TxHash,
+    pub identity: &'a Identity,
+    pub blob_index: BlobIndex,
+    pub blob: &'a Blob,
+    /// Output of the proof the blob settled with. None for failed transactions and `hyle` blobs.
+    pub hyle_output: Option<&'a HyleOutput>,
+    /// Whether the transaction settled as a success
+    pub success: bool,
+    pub block_height: BlockHeight,
+}
+
+/// Custom logic run by [super::NodeState] on every blob of each settled transaction.
+///
+/// Hooks only observe settlement: every node must settle transactions the same way,
+/// so an error is logged but doesn't change the outcome.
+pub trait SettlementHook: Send + Sync {
+    fn on_settled_blob(&self, blob: &SettledBlob<'_>) -> Result<()>;
+}
+
+/// Registered hooks. They are not persisted with the node state and must be registered again after loading it.
+#[derive(Default, Clone)]
+pub struct SettlementHooks(Vec<Arc<dyn SettlementHook>>);
+
+impl SettlementHooks {
+    pub fn register(&mut self, hook: Arc<dyn SettlementHook>) {
+        self.0.push(hook);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Arc<dyn SettlementHook>> {
+        self.0.iter()
+    }
+}
+
+impl std::fmt::Debug for SettlementHooks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SettlementHooks({})", self.0.len())
+    }
+}
+
+impl Encode for SettlementHooks {
+    fn encode<E: Encoder>(&self, _: &mut E) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl Decode for SettlementHooks {
+    fn decode<D: Decoder>(_: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::default())
+    }
+}
+
+impl<'de> BorrowDecode<'de> for SettlementHooks {
+    fn borrow_decode<D: BorrowDecoder<'de>>(_: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::default())
+    }
+}
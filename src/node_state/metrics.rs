@@ -0,0 +1,50 @@
+use opentelemetry::{metrics::Counter, InstrumentationScope, KeyValue};
+
+use crate::model::Block;
+
+#[derive(Debug)]
+pub struct NodeStateMetrics {
+    contract_txs: Counter<u64>,
+    verified_proofs: Counter<u64>,
+}
+
+impl NodeStateMetrics {
+    pub fn global(node_name: String) -> NodeStateMetrics {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        let node_state = "node_state";
+
+        NodeStateMetrics {
+            contract_txs: my_meter
+                .u64_counter(format!("{node_state}_contract_txs"))
+                .build(),
+            verified_proofs: my_meter
+                .u64_counter(format!("{node_state}_verified_proofs"))
+                .build(),
+        }
+    }
+
+    /// Settlement activity of each contract in a processed block
+    pub fn block(&self, block: &Block) {
+        for (contract_name, stats) in block.contract_stats.iter() {
+            let contract = KeyValue::new("contract", contract_name.0.clone());
+            for (outcome, count) in [
+                ("settled", stats.settled_txs),
+                ("failed", stats.failed_txs),
+                ("timed_out", stats.timed_out_txs),
+            ] {
+                if count > 0 {
+                    self.contract_txs.add(
+                        count.into(),
+                        &[contract.clone(), KeyValue::new("outcome", outcome)],
+                    );
+                }
+            }
+            if stats.verified_proofs > 0 {
+                self.verified_proofs
+                    .add(stats.verified_proofs.into(), &[contract]);
+            }
+        }
+    }
+}
@@ -1,20 +1,31 @@
 //! State required for participation in consensus by the node.
 
+use super::audit::{SettlementAuditEntry, SettlementAuditLog};
+use super::hooks::SettlementHook;
+use super::metrics::NodeStateMetrics;
+use super::timeouts::TimeoutPolicy;
 use super::NodeState;
-use crate::bus::{command_response::Query, BusClientSender, BusMessage};
-use crate::data_availability::DataEvent;
+use crate::bus::{
+    command_response::{CmdRespClient, Query},
+    BusClientSender, BusMessage,
+};
+use crate::data_availability::{DataAvailability, DataEvent, QueryDaBlockRange, QueryDaLastHeight};
 use crate::model::Contract;
-use crate::model::{Block, BlockHeight, CommonRunContext, ContractName};
+use crate::model::{Block, BlockHeight, CommonRunContext, ContractName, SignedBlock};
 use crate::module_handle_messages;
 use crate::utils::conf::SharedConf;
 use crate::utils::logger::LogMe;
 use crate::utils::modules::{module_bus_client, Module};
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use bincode::{Decode, Encode};
-use hyle_model::{TxHash, UnsettledBlobTransaction};
+use hyle_model::{
+    api::{APIBlobTxSimulation, APIContractStateProof, APISimulateBlobTx},
+    errors::{ErrorCode, HyleError},
+    StateDigest, TxHash, UnsettledBlobTransaction,
+};
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
-use tracing::info;
+use tracing::{debug, error, info, warn};
 
 /// NodeStateModule maintains a NodeState,
 /// listens to DA, and sends events when it has processed blocks.
@@ -24,34 +35,254 @@ pub struct NodeStateModule {
     config: SharedConf,
     bus: NodeStateBusClient,
     inner: NodeState,
+    audit: SettlementAuditLog,
+    metrics: NodeStateMetrics,
+    /// Height of the next block to process. Unknown when starting without a snapshot: the chain
+    /// then starts at the first block stored by DA, which is not genesis after a light sync.
+    next_height: Option<BlockHeight>,
 }
 
+/// `NewBlock` is sent for every processed block, followed by the granular events derived from it.
 #[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode)]
 pub enum NodeStateEvent {
     NewBlock(Box<Block>),
+    TxSettled {
+        tx_hash: TxHash,
+        block_height: BlockHeight,
+    },
+    TxFailed {
+        tx_hash: TxHash,
+        block_height: BlockHeight,
+    },
+    TxTimedOut {
+        tx_hash: TxHash,
+        block_height: BlockHeight,
+    },
+    ContractRegistered {
+        contract_name: ContractName,
+        tx_hash: TxHash,
+        block_height: BlockHeight,
+    },
+    /// The program and verifier of a contract changed, see [crate::model::UpdateContractAction].
+    ContractUpdated {
+        contract_name: ContractName,
+        tx_hash: TxHash,
+        block_height: BlockHeight,
+    },
+    StateUpdated {
+        contract_name: ContractName,
+        state_digest: StateDigest,
+        block_height: BlockHeight,
+    },
 }
 impl BusMessage for NodeStateEvent {}
 
+impl NodeStateEvent {
+    /// Granular events of a processed block, to be sent after its `NewBlock` event.
+    pub fn settlement_events(block: &Block) -> Vec<NodeStateEvent> {
+        let block_height = block.block_height;
+        let settled = block
+            .successful_txs
+            .iter()
+            .map(|tx_hash| NodeStateEvent::TxSettled {
+                tx_hash: tx_hash.clone(),
+                block_height,
+            });
+        let failed = block
+            .failed_txs
+            .iter()
+            .map(|tx_hash| NodeStateEvent::TxFailed {
+                tx_hash: tx_hash.clone(),
+                block_height,
+            });
+        let timed_out = block
+            .timed_out_txs
+            .iter()
+            .map(|tx_hash| NodeStateEvent::TxTimedOut {
+                tx_hash: tx_hash.clone(),
+                block_height,
+            });
+        let registered = block.registered_contracts.iter().map(|(tx_hash, effect)| {
+            NodeStateEvent::ContractRegistered {
+                contract_name: effect.contract_name.clone(),
+                tx_hash: tx_hash.clone(),
+                block_height,
+            }
+        });
+        let contract_updates = block.updated_contracts.iter().map(|(tx_hash, effect)| {
+            NodeStateEvent::ContractUpdated {
+                contract_name: effect.contract_name.clone(),
+                tx_hash: tx_hash.clone(),
+                block_height,
+            }
+        });
+        let updated = block
+            .updated_states
+            .iter()
+            .map(
+                |(contract_name, state_digest)| NodeStateEvent::StateUpdated {
+                    contract_name: contract_name.clone(),
+                    state_digest: state_digest.clone(),
+                    block_height,
+                },
+            );
+        settled
+            .chain(failed)
+            .chain(timed_out)
+            .chain(registered)
+            .chain(contract_updates)
+            .chain(updated)
+            .collect()
+    }
+}
+
 #[derive(Clone)]
 pub struct QueryBlockHeight {}
 
 #[derive(Clone)]
 pub struct QueryUnsettledTx(pub TxHash);
 
+#[derive(Clone)]
+pub struct QueryContractStateProof(pub ContractName);
+
+/// Audit entries for the last N blocks.
+#[derive(Clone)]
+pub struct QuerySettlementAudit(pub u64);
+
+#[derive(Clone)]
+pub struct QuerySimulateBlobTx(pub APISimulateBlobTx);
+
 module_bus_client! {
 #[derive(Debug)]
 pub struct NodeStateBusClient {
     sender(NodeStateEvent),
+    sender(Query<QueryDaLastHeight, Option<BlockHeight>>),
+    sender(Query<QueryDaBlockRange, Vec<SignedBlock>>),
     receiver(DataEvent),
     receiver(Query<ContractName, Contract>),
     receiver(Query<QueryBlockHeight , BlockHeight>),
     receiver(Query<QueryUnsettledTx, UnsettledBlobTransaction>),
+    receiver(Query<QuerySettlementAudit, Vec<SettlementAuditEntry>>),
+    receiver(Query<QueryContractStateProof, APIContractStateProof>),
+    receiver(Query<QuerySimulateBlobTx, APIBlobTxSimulation>),
 }
 }
 
+impl NodeStateModule {
+    /// Registers a settlement hook on the inner node state, before the module is added to the handler.
+    pub fn register_settlement_hook(&mut self, hook: Arc<dyn SettlementHook>) {
+        self.inner.register_settlement_hook(hook);
+    }
+
+    fn handle_signed_block(&mut self, block: &SignedBlock) {
+        let node_state_block = self.inner.handle_signed_block(block);
+        self.next_height = Some(block.height() + 1);
+        self.audit.extend(self.inner.take_audit_entries());
+        self.metrics.block(&node_state_block);
+        let interval = self.config.node_state.snapshot_interval;
+        if interval > 0 && self.inner.current_height().0 % interval == 0 {
+            self.save_snapshot();
+        }
+        let events = NodeStateEvent::settlement_events(&node_state_block);
+        _ = self
+            .bus
+            .send(NodeStateEvent::NewBlock(Box::new(node_state_block)))
+            .log_error("Sending DataEvent while processing SignedBlock");
+        for event in events {
+            _ = self.bus.send(event).log_error("Sending settlement event");
+        }
+    }
+
+    /// Blocks must be processed in order, without gaps: those already processed are skipped, and
+    /// the missing ones are replayed from DA first. A block still not following is ignored.
+    async fn on_ordered_block(&mut self, block: &SignedBlock) {
+        let height = block.height();
+        if let Some(next) = self.next_height {
+            if height.0 < next.0 {
+                debug!("Block {} already processed, skipping", height);
+                return;
+            }
+            if height.0 > next.0 {
+                _ = self.replay_stored_blocks(height).await.log_error(format!(
+                    "Replaying blocks {} to {} from DA",
+                    next,
+                    height.0 - 1
+                ));
+            }
+        }
+        match self.next_height {
+            Some(next) if next != height => {
+                error!(
+                    "Block {} doesn't follow the node state at {}, ignored",
+                    height,
+                    self.inner.current_height()
+                );
+            }
+            _ => self.handle_signed_block(block),
+        }
+    }
+
+    /// Processes the blocks stored by DA below `until`, from the next one. After a restart, the
+    /// snapshot is behind the blocks processed before the crash or shutdown.
+    async fn replay_stored_blocks(&mut self, until: BlockHeight) -> Result<()> {
+        let mut from = self.next_height.unwrap_or(BlockHeight(0));
+        let mut replayed = 0;
+        while from.0 < until.0 {
+            let blocks = self
+                .bus
+                .request(QueryDaBlockRange { from, to: until })
+                .await?;
+            if blocks.is_empty() {
+                break;
+            }
+            for block in blocks.iter() {
+                if self.next_height.is_some_and(|next| next != block.height()) {
+                    bail!("DA is missing block {}", from);
+                }
+                self.handle_signed_block(block);
+                from = block.height() + 1;
+                replayed += 1;
+            }
+        }
+        if replayed > 0 {
+            info!(
+                "📦 Replayed {} blocks stored by DA, node state at block {}",
+                replayed,
+                self.inner.current_height()
+            );
+        }
+        Ok(())
+    }
+
+    fn save_snapshot(&self) {
+        let _ = Self::save_on_disk::<NodeState>(
+            self.config.data_directory.join("node_state.bin").as_path(),
+            &self.inner,
+        )
+        .log_error("Saving node state");
+
+        let _ = Self::save_on_disk::<SettlementAuditLog>(
+            self.config
+                .data_directory
+                .join("node_state_audit.bin")
+                .as_path(),
+            &self.audit,
+        )
+        .log_error("Saving node state audit trail");
+    }
+}
+
 impl Module for NodeStateModule {
     type Context = Arc<CommonRunContext>;
 
+    fn dependencies() -> Vec<&'static str> {
+        vec![std::any::type_name::<DataAvailability>()]
+    }
+
+    fn drains() -> Vec<&'static str> {
+        vec![std::any::type_name::<DataAvailability>()]
+    }
+
     async fn build(ctx: Self::Context) -> Result<Self> {
         let bus = NodeStateBusClient::new_from_bus(ctx.bus.new_handle()).await;
 
@@ -62,22 +293,63 @@ impl Module for NodeStateModule {
             }
         }
 
-        let storage = Self::load_from_disk_or_default::<NodeState>(
+        let (mut storage, next_height) = match Self::load_from_disk::<NodeState>(
             ctx.config.data_directory.join("node_state.bin").as_path(),
-        );
+        ) {
+            Some(state) => match state.verify() {
+                Ok(()) => {
+                    info!("📦 Resuming node state at block {}", state.current_height());
+                    let next_height = state.current_height() + 1;
+                    (state, Some(next_height))
+                }
+                Err(e) => {
+                    error!(
+                        "Node state snapshot is inconsistent, replaying the chain: {:#}",
+                        e
+                    );
+                    (NodeState::default(), None)
+                }
+            },
+            None => (NodeState::default(), None),
+        };
+        storage.set_timeout_policy(TimeoutPolicy::from(&ctx.config.node_state));
+        storage.enable_audit();
 
         for name in storage.contracts.keys() {
             info!("📝 Loaded contract state for {}", name);
         }
 
+        // Kept apart from the node state so it never weighs on its (de)serialization.
+        let audit = Self::load_from_disk_or_default::<SettlementAuditLog>(
+            ctx.config
+                .data_directory
+                .join("node_state_audit.bin")
+                .as_path(),
+        );
+
         Ok(Self {
             config: ctx.config.clone(),
             bus,
             inner: storage,
+            audit,
+            metrics: NodeStateMetrics::global(ctx.config.id.clone()),
+            next_height,
         })
     }
 
     async fn run(&mut self) -> Result<()> {
+        // Blocks processed since the snapshot, or all of them without one
+        match self.bus.request(QueryDaLastHeight).await {
+            Ok(Some(last)) => {
+                _ = self
+                    .replay_stored_blocks(last + 1)
+                    .await
+                    .log_error("Replaying blocks stored by DA");
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Can't replay blocks stored by DA: {:#}", e),
+        }
+
         module_handle_messages! {
             on_bus self.bus,
             command_response<QueryBlockHeight, BlockHeight> _ => {
@@ -92,25 +364,34 @@ impl Module for NodeStateModule {
                     None => Err(anyhow::anyhow!("Transaction not found")),
                 }
             }
+            command_response<QuerySettlementAudit, Vec<SettlementAuditEntry>> query => {
+                Ok(self.audit.last_blocks(self.inner.current_height, query.0))
+            }
+            command_response<QueryContractStateProof, APIContractStateProof> query => {
+                self.inner.contract_state_proof(&query.0).context("Contract not found")
+            }
+            command_response<QuerySimulateBlobTx, APIBlobTxSimulation> query => {
+                self.inner
+                    .simulate_blob_tx(&query.0.tx, &query.0.hyle_outputs)
+                    .map_err(|e| HyleError::new(ErrorCode::BadRequest, format!("{e:#}")).into())
+            }
             listen<DataEvent> block => {
                 match block {
                     DataEvent::OrderedSignedBlock(block) => {
-                        let node_state_block = self.inner.handle_signed_block(&block);
-                        _ = self
-                            .bus
-                            .send(NodeStateEvent::NewBlock(Box::new(node_state_block)))
-                            .log_error("Sending DataEvent while processing SignedBlock");
+                        self.on_ordered_block(&block).await;
                     }
+                    DataEvent::CatchupProgress { .. }
+                    | DataEvent::CatchupDone
+                    | DataEvent::DiskUsageAlert { .. } => {}
                 }
             }
         };
 
-        let _ = Self::save_on_disk::<NodeState>(
-            self.config.data_directory.join("node_state.bin").as_path(),
-            &self.inner,
-        )
-        .log_error("Saving node state");
+        Ok(())
+    }
 
+    async fn on_shutdown(&mut self) -> Result<()> {
+        self.save_snapshot();
         Ok(())
     }
 }
@@ -1,3 +1,4 @@
+use anyhow::{bail, Result};
 use bincode::{Decode, Encode};
 use tracing::warn;
 
@@ -81,6 +82,41 @@ impl OrderedTxMap {
         is_next
     }
 
+    pub fn hashes(&self) -> impl Iterator<Item = &TxHash> {
+        self.map.keys()
+    }
+
+    /// Checks that both fields agree, e.g. on a map loaded from disk.
+    pub fn check_consistency(&self) -> Result<()> {
+        for (contract, order) in self.tx_order.iter() {
+            for hash in order {
+                let Some(tx) = self.map.get(hash) else {
+                    bail!("Tx {} queued for {} is not in the map", hash, contract);
+                };
+                if !tx.blobs.iter().any(|b| &b.blob.contract_name == contract) {
+                    bail!(
+                        "Tx {} is queued for {} but has no blob for it",
+                        hash,
+                        contract
+                    );
+                }
+            }
+        }
+        for (hash, tx) in self.map.iter() {
+            for blob_metadata in tx.blobs.iter() {
+                let contract = &blob_metadata.blob.contract_name;
+                if !self
+                    .tx_order
+                    .get(contract)
+                    .is_some_and(|order| order.contains(hash))
+                {
+                    bail!("Tx {} is not queued for {}", hash, contract);
+                }
+            }
+        }
+        Ok(())
+    }
+
     pub fn remove(&mut self, hash: &TxHash) -> Option<UnsettledBlobTransaction> {
         if let Some(tx) = self.map.get(hash) {
             for blob_metadata in &tx.blobs {
@@ -146,6 +182,21 @@ mod tests {
         assert_eq!(map.tx_order.len(), 1);
     }
 
+    #[test]
+    fn check_consistency() {
+        let mut map = OrderedTxMap::default();
+        map.add(new_tx("tx1", "c1"));
+        map.add(new_tx("tx2", "c1"));
+        assert!(map.check_consistency().is_ok());
+
+        map.map.remove(&TxHash::new("tx2"));
+        assert!(map.check_consistency().is_err());
+
+        map.add(new_tx("tx2", "c1"));
+        map.tx_order.clear();
+        assert!(map.check_consistency().is_err());
+    }
+
     #[test]
     fn can_get_tx() {
         let mut map = OrderedTxMap::default();
@@ -0,0 +1,246 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::model::{BlobProofOutput, BlockHeight};
+use anyhow::{bail, Result};
+use bincode::{Decode, Encode};
+use hyle_contract_sdk::TxHash;
+
+/// Blob proof outputs kept at most, the oldest ones are dropped first
+pub const MAX_PENDING_PROOFS: usize = 10_000;
+/// Blob proof outputs kept at most for a blob transaction
+pub const MAX_PENDING_PROOFS_PER_BLOB_TX: usize = 50;
+/// Blob proof outputs kept at most from a proof transaction
+pub const MAX_PENDING_PROOFS_PER_PROOF_TX: usize = 100;
+/// Blocks a proof waits for its blob transaction to be sequenced
+pub const PENDING_PROOF_WINDOW: u64 = 100;
+
+/// Proofs received before the blob transaction they prove was sequenced, replayed once it is.
+#[derive(Default, Debug, Clone, Encode, Decode)]
+pub struct PendingProofs {
+    /// Proof tx hash and proven blob, by blob tx hash
+    by_blob_tx: HashMap<TxHash, Vec<(TxHash, BlobProofOutput)>>,
+    /// Blob txs by the height their first proof was received at, oldest first
+    received: VecDeque<(BlockHeight, TxHash)>,
+    /// Pending blob proof outputs of each proof tx
+    by_proof_tx: HashMap<TxHash, usize>,
+    /// Blob txs settled or timed out during the last [PENDING_PROOF_WINDOW] blocks, oldest first.
+    /// Proofs of blob txs settled before can't be told apart from early ones, they expire as such.
+    settled: VecDeque<(BlockHeight, TxHash)>,
+    settled_hashes: HashSet<TxHash>,
+    len: usize,
+}
+
+impl PendingProofs {
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Keeps the proof until its blob transaction is sequenced. Refused for blob transactions
+    /// that were already settled, and past the limits of the blob or proof transaction.
+    pub fn add(
+        &mut self,
+        proof_tx_hash: TxHash,
+        blob_proof_data: BlobProofOutput,
+        received_at: BlockHeight,
+    ) -> Result<()> {
+        let blob_tx_hash = blob_proof_data.blob_tx_hash.clone();
+        if self.settled_hashes.contains(&blob_tx_hash) {
+            bail!("BlobTx {} is already settled", blob_tx_hash);
+        }
+        if self.by_proof_tx.get(&proof_tx_hash).copied().unwrap_or(0)
+            >= MAX_PENDING_PROOFS_PER_PROOF_TX
+        {
+            bail!(
+                "Proof tx {} already has {} proofs waiting for their blob tx",
+                proof_tx_hash,
+                MAX_PENDING_PROOFS_PER_PROOF_TX
+            );
+        }
+        if self.by_blob_tx.get(&blob_tx_hash).map_or(0, Vec::len) >= MAX_PENDING_PROOFS_PER_BLOB_TX
+        {
+            bail!(
+                "BlobTx {} already has {} proofs waiting for it",
+                blob_tx_hash,
+                MAX_PENDING_PROOFS_PER_BLOB_TX
+            );
+        }
+        *self.by_proof_tx.entry(proof_tx_hash.clone()).or_default() += 1;
+        let proofs = self.by_blob_tx.entry(blob_tx_hash.clone()).or_default();
+        if proofs.is_empty() {
+            self.received.push_back((received_at, blob_tx_hash));
+        }
+        proofs.push((proof_tx_hash, blob_proof_data));
+        self.len += 1;
+
+        while self.len > MAX_PENDING_PROOFS {
+            self.drop_oldest();
+        }
+        Ok(())
+    }
+
+    /// Removes the proofs of the blob transaction, in the order they were received.
+    pub fn take(&mut self, blob_tx_hash: &TxHash) -> Vec<(TxHash, BlobProofOutput)> {
+        let Some(proofs) = self.by_blob_tx.remove(blob_tx_hash) else {
+            return vec![];
+        };
+        self.received.retain(|(_, tx_hash)| tx_hash != blob_tx_hash);
+        self.forget(&proofs);
+        proofs
+    }
+
+    /// Refuses the proofs of a blob transaction that settled or timed out at `height`.
+    pub fn settled(&mut self, blob_tx_hash: TxHash, height: BlockHeight) {
+        self.take(&blob_tx_hash);
+        if self.settled_hashes.insert(blob_tx_hash.clone()) {
+            self.settled.push_back((height, blob_tx_hash));
+        }
+    }
+
+    /// Drops the proofs that waited more than [PENDING_PROOF_WINDOW] blocks, returns how many.
+    pub fn expire(&mut self, current_height: BlockHeight) -> usize {
+        let before = self.len;
+        while self
+            .received
+            .front()
+            .is_some_and(|(at, _)| at.0 + PENDING_PROOF_WINDOW <= current_height.0)
+        {
+            self.drop_oldest();
+        }
+        while let Some((at, _)) = self.settled.front() {
+            if at.0 + PENDING_PROOF_WINDOW > current_height.0 {
+                break;
+            }
+            if let Some((_, blob_tx_hash)) = self.settled.pop_front() {
+                self.settled_hashes.remove(&blob_tx_hash);
+            }
+        }
+        before - self.len
+    }
+
+    fn drop_oldest(&mut self) {
+        if let Some((_, blob_tx_hash)) = self.received.pop_front() {
+            if let Some(proofs) = self.by_blob_tx.remove(&blob_tx_hash) {
+                self.forget(&proofs);
+            }
+        }
+    }
+
+    fn forget(&mut self, proofs: &[(TxHash, BlobProofOutput)]) {
+        self.len -= proofs.len();
+        for (proof_tx_hash, _) in proofs {
+            if let Some(count) = self.by_proof_tx.get_mut(proof_tx_hash) {
+                *count -= 1;
+                if *count == 0 {
+                    self.by_proof_tx.remove(proof_tx_hash);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyle_contract_sdk::{BlobIndex, HyleOutput};
+
+    fn proof(blob_tx: &str, index: usize) -> BlobProofOutput {
+        BlobProofOutput {
+            blob_tx_hash: TxHash::new(blob_tx),
+            hyle_output: HyleOutput {
+                index: BlobIndex(index),
+                ..HyleOutput::default()
+            },
+            ..BlobProofOutput::default()
+        }
+    }
+
+    #[test]
+    fn test_pending_proofs() {
+        let mut pending = PendingProofs::default();
+        pending
+            .add(TxHash::new("p1"), proof("tx1", 0), BlockHeight(1))
+            .unwrap();
+        pending
+            .add(TxHash::new("p2"), proof("tx2", 0), BlockHeight(2))
+            .unwrap();
+        pending
+            .add(TxHash::new("p3"), proof("tx1", 1), BlockHeight(3))
+            .unwrap();
+        assert_eq!(pending.len(), 3);
+
+        let proofs = pending.take(&TxHash::new("tx1"));
+        assert_eq!(
+            proofs
+                .iter()
+                .map(|(hash, _)| hash.0.as_str())
+                .collect::<Vec<_>>(),
+            vec!["p1", "p3"]
+        );
+        assert!(pending.take(&TxHash::new("tx1")).is_empty());
+        assert_eq!(pending.len(), 1);
+
+        assert_eq!(pending.expire(BlockHeight(PENDING_PROOF_WINDOW + 1)), 0);
+        assert_eq!(pending.expire(BlockHeight(PENDING_PROOF_WINDOW + 2)), 1);
+        assert!(pending.is_empty());
+
+        for i in 0..=MAX_PENDING_PROOFS {
+            pending
+                .add(
+                    TxHash::new(format!("p{i}")),
+                    proof(&format!("tx{i}"), 0),
+                    BlockHeight(1),
+                )
+                .unwrap();
+        }
+        assert_eq!(pending.len(), MAX_PENDING_PROOFS);
+        assert!(pending.take(&TxHash::new("tx0")).is_empty());
+        assert_eq!(pending.take(&TxHash::new("tx1")).len(), 1);
+    }
+
+    #[test]
+    fn test_pending_proofs_limits() {
+        let mut pending = PendingProofs::default();
+        for i in 0..MAX_PENDING_PROOFS_PER_BLOB_TX {
+            pending
+                .add(
+                    TxHash::new(format!("p{i}")),
+                    proof("tx1", 0),
+                    BlockHeight(1),
+                )
+                .unwrap();
+        }
+        assert!(pending
+            .add(TxHash::new("one_more"), proof("tx1", 0), BlockHeight(1))
+            .is_err());
+
+        for i in 0..MAX_PENDING_PROOFS_PER_PROOF_TX {
+            pending
+                .add(TxHash::new("p"), proof(&format!("b{i}"), 0), BlockHeight(1))
+                .unwrap();
+        }
+        assert!(pending
+            .add(TxHash::new("p"), proof("other", 0), BlockHeight(1))
+            .is_err());
+        // Proofs handed out count no more
+        pending.take(&TxHash::new("b0"));
+        pending
+            .add(TxHash::new("p"), proof("other", 0), BlockHeight(1))
+            .unwrap();
+
+        // Proofs of settled blob txs are refused while they may still arrive
+        pending.settled(TxHash::new("b1"), BlockHeight(2));
+        assert!(pending.take(&TxHash::new("b1")).is_empty());
+        assert!(pending
+            .add(TxHash::new("late"), proof("b1", 0), BlockHeight(3))
+            .is_err());
+        pending.expire(BlockHeight(2 + PENDING_PROOF_WINDOW));
+        pending
+            .add(TxHash::new("late"), proof("b1", 0), BlockHeight(3))
+            .unwrap();
+    }
+}
@@ -86,12 +86,10 @@ async fn scenario(identity: Identity, blob: Blob) -> Result<()> {
     // Wait until we commit this TX
     loop {
         let evt: NodeStateEvent = node_client.recv().await?;
-        match evt {
-            NodeStateEvent::NewBlock(block) => {
-                info!("Got Block");
-                if block.successful_txs.iter().any(|tx| tx == &blob_tx_hash) {
-                    break;
-                }
+        if let NodeStateEvent::NewBlock(block) = evt {
+            info!("Got Block");
+            if block.successful_txs.iter().any(|tx| tx == &blob_tx_hash) {
+                break;
             }
         }
     }
@@ -1,9 +1,76 @@
 use std::collections::HashMap;
 
-use crate::model::BlockHeight;
-use bincode::{Decode, Encode};
+use crate::model::{BlockHeight, ContractName};
+use crate::utils::conf::NodeStateConf;
+use bincode::{
+    de::{BorrowDecoder, Decoder},
+    enc::Encoder,
+    error::{DecodeError, EncodeError},
+    BorrowDecode, Decode, Encode,
+};
 use hyle_contract_sdk::TxHash;
 
+/// Number of blocks an unsettled blob transaction waits for its proofs before timing out.
+/// It comes from the configuration and is not persisted with the node state.
+#[derive(Debug, Clone)]
+pub struct TimeoutPolicy {
+    window: u64,
+    overrides: HashMap<ContractName, u64>,
+}
+
+impl Default for TimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            window: 100,
+            overrides: HashMap::new(),
+        }
+    }
+}
+
+impl TimeoutPolicy {
+    pub fn new(window: u64, overrides: HashMap<ContractName, u64>) -> Self {
+        Self { window, overrides }
+    }
+
+    /// A transaction waits for its slowest contract: the longest window among its blobs applies.
+    pub fn window<'a>(&self, contracts: impl Iterator<Item = &'a ContractName>) -> u64 {
+        contracts
+            .map(|c| self.overrides.get(c).copied().unwrap_or(self.window))
+            .max()
+            .unwrap_or(self.window)
+    }
+}
+
+impl From<&NodeStateConf> for TimeoutPolicy {
+    fn from(conf: &NodeStateConf) -> Self {
+        Self::new(
+            conf.timeout_window,
+            conf.timeout_window_overrides
+                .iter()
+                .map(|(name, window)| (ContractName::new(name), *window))
+                .collect(),
+        )
+    }
+}
+
+impl Encode for TimeoutPolicy {
+    fn encode<E: Encoder>(&self, _: &mut E) -> Result<(), EncodeError> {
+        Ok(())
+    }
+}
+
+impl Decode for TimeoutPolicy {
+    fn decode<D: Decoder>(_: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::default())
+    }
+}
+
+impl<'de> BorrowDecode<'de> for TimeoutPolicy {
+    fn borrow_decode<D: BorrowDecoder<'de>>(_: &mut D) -> Result<Self, DecodeError> {
+        Ok(Self::default())
+    }
+}
+
 #[derive(Default, Debug, Clone, Encode, Decode)]
 pub struct Timeouts {
     by_block: HashMap<BlockHeight, Vec<TxHash>>,
@@ -14,6 +81,13 @@ impl Timeouts {
         self.by_block.remove(at).unwrap_or_default()
     }
 
+    /// Pending timeouts, with the height each tx times out at.
+    pub fn scheduled(&self) -> impl Iterator<Item = (&BlockHeight, &TxHash)> {
+        self.by_block
+            .iter()
+            .flat_map(|(at, txs)| txs.iter().map(move |tx| (at, tx)))
+    }
+
     /// Set timeout for a tx.
     /// This does not check if the TX is already set to timeout at a different (or same) block.
     pub fn set(&mut self, tx: TxHash, at: BlockHeight) {
@@ -46,6 +120,18 @@ pub mod tests {
         })
     }
 
+    #[test]
+    fn timeout_policy_window() {
+        let policy = TimeoutPolicy::new(100, HashMap::from([(ContractName::new("slow"), 1000)]));
+        let fast = ContractName::new("fast");
+        let slow = ContractName::new("slow");
+
+        assert_eq!(policy.window([&fast].into_iter()), 100);
+        assert_eq!(policy.window([&slow].into_iter()), 1000);
+        assert_eq!(policy.window([&fast, &slow].into_iter()), 1000);
+        assert_eq!(policy.window(std::iter::empty()), 100);
+    }
+
     #[test]
     fn timeout() {
         let mut t = Timeouts::default();
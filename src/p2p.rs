@@ -10,14 +10,18 @@ use crate::{
         conf::SharedConf,
         crypto::SharedBlstCrypto,
         modules::{module_bus_client, Module},
+        noise::NodeStream,
     },
 };
 use anyhow::{Context, Result};
+use discovery::{PeerTable, SharedPeerTable};
 use std::{collections::HashSet, sync::Arc, time::Duration};
 use tokio::{net::TcpListener, time::sleep};
 use tracing::{error, info, trace, warn};
 
+mod discovery;
 mod fifo_filter;
+mod metrics;
 pub mod network;
 mod peer;
 pub mod stream;
@@ -41,6 +45,7 @@ pub struct P2P {
     crypto: SharedBlstCrypto,
     peer_id: u64,
     connected_peers: HashSet<String>,
+    peers: SharedPeerTable,
 }
 
 impl Module for P2P {
@@ -52,6 +57,10 @@ impl Module for P2P {
             config: ctx.common.config.clone(),
             bus: ctx.common.bus.new_handle(),
             bus_client,
+            peers: PeerTable::shared(
+                ctx.node.crypto.validator_pubkey().clone(),
+                ctx.common.config.p2p.max_peers,
+            ),
             crypto: ctx.node.crypto.clone(),
             peer_id: 1u64,
             connected_peers: HashSet::default(),
@@ -65,13 +74,17 @@ impl Module for P2P {
 
 impl P2P {
     fn spawn_peer(&mut self, peer_address: String) {
-        if self.connected_peers.contains(&peer_address) || peer_address == self.config.host {
+        if peer_address.is_empty()
+            || self.connected_peers.contains(&peer_address)
+            || peer_address == self.config.host
+        {
             return;
         }
 
         let config = self.config.clone();
         let bus = self.bus.new_handle();
         let crypto = self.crypto.clone();
+        let peers = self.peers.clone();
         let id = self.peer_id;
         self.peer_id += 1;
         self.connected_peers.insert(peer_address.clone());
@@ -80,16 +93,19 @@ impl P2P {
             .name("connect-to-peer")
             .spawn(async move {
                 let mut retry_count = 20;
-                while retry_count > 0 {
+                // Nobody connects to outbound-only nodes, they keep trying
+                while retry_count > 0 || config.p2p.outbound_only {
                     info!("Connecting to peer #{}: {}", id, peer_address);
-                    match peer::Peer::connect(peer_address.as_str()).await {
-                        Ok(stream) => {
+                    match peer::Peer::connect(peer_address.as_str(), &config, &crypto).await {
+                        Ok((stream, authenticated_pubkey)) => {
                             let mut peer = peer::Peer::new(
                                 id,
                                 stream,
+                                authenticated_pubkey,
                                 bus.new_handle(),
                                 crypto.clone(),
                                 config.clone(),
+                                peers.clone(),
                             )
                             .await;
 
@@ -124,14 +140,24 @@ impl P2P {
         }
     }
 
+    /// Connects to the configured peers, and to the bootstrap nodes to discover the others.
+    fn connect_to_configured_peers(&mut self) {
+        let peers = self.config.peers.iter();
+        for peer in peers
+            .chain(self.config.p2p.bootstrap_nodes.iter())
+            .cloned()
+            .collect::<Vec<_>>()
+        {
+            self.spawn_peer(peer);
+        }
+    }
+
     pub async fn p2p_server(&mut self) -> Result<()> {
         // Wait all other threads to start correctly
         sleep(Duration::from_secs(1)).await;
 
-        if !self.config.p2p_listen {
-            for peer in self.config.peers.clone() {
-                self.spawn_peer(peer);
-            }
+        if !self.config.p2p_listen || self.config.p2p.outbound_only {
+            self.connect_to_configured_peers();
             handle_messages! {
                 on_bus self.bus_client,
                 listen<P2PCommand> cmd => {
@@ -151,9 +177,7 @@ impl P2P {
         #[cfg(test)]
         sleep(Duration::from_secs(1)).await;
 
-        for peer in self.config.peers.clone() {
-            self.spawn_peer(peer);
-        }
+        self.connect_to_configured_peers();
 
         module_handle_messages! {
             on_bus self.bus_client,
@@ -162,25 +186,23 @@ impl P2P {
             }
 
             res = listener.accept() => {
-                let (socket, _) = res.context("Accepting connection in P2P server")?;
+                let (socket, addr) = res.context("Accepting connection in P2P server")?;
 
                 let conf = Arc::clone(&self.config);
                 let bus = self.bus.new_handle();
                 let crypto = self.crypto.clone();
+                let peers = self.peers.clone();
                 let id = self.peer_id;
                 self.peer_id += 1;
                 tokio::task::Builder::new()
                     .name(&format!("peer-{}", id))
                     .spawn(async move {
-                        info!(
-                            "New peer #{}: {}",
-                            id,
-                            socket
-                                .peer_addr()
-                                .map(|a| a.to_string())
-                                .unwrap_or("no address".to_string())
-                            );
-                        let mut peer_server = peer::Peer::new(id, socket, bus, crypto, conf).await;
+                        info!("New peer #{}: {}", id, addr);
+                        let (socket, authenticated_pubkey) =
+                            NodeStream::accept(socket, conf.p2p.encrypted, Some(crypto.as_ref()))
+                                .await
+                                .context("Securing connection from peer")?;
+                        let mut peer_server = peer::Peer::new(id, socket, authenticated_pubkey, bus, crypto, conf, peers).await;
                         _ = peer_server.handshake().await;
                         trace!("Handshake done !");
                         match peer_server.start().await {
@@ -0,0 +1,175 @@
+//! Peer discovery: nodes share the addresses of the peers they are connected to, so that a node
+//! configured with a few bootstrap nodes eventually connects to the rest of the network.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use super::network::PeerInfo;
+use crate::model::ValidatorPublicKey;
+
+/// At most this many peers are sent in a peer exchange.
+const MAX_SHARED_PEERS: usize = 100;
+/// At most this many peers are remembered, connected or not.
+const MAX_KNOWN_PEERS: usize = 1000;
+
+pub type SharedPeerTable = Arc<Mutex<PeerTable>>;
+
+#[derive(Debug)]
+struct KnownPeer {
+    info: PeerInfo,
+    /// Open connections, inbound or outbound, with a completed handshake
+    connections: usize,
+}
+
+/// The peers known to the node, shared by the connections to learn and advertise peers.
+///
+/// Addresses received from other nodes aren't trusted: the key of a peer is only known for sure
+/// once its handshake is done, until then it is only a candidate to connect to.
+#[derive(Debug)]
+pub struct PeerTable {
+    self_pubkey: ValidatorPublicKey,
+    /// Connecting to more peers than this stops once discovered
+    max_peers: usize,
+    peers: HashMap<ValidatorPublicKey, KnownPeer>,
+}
+
+impl PeerTable {
+    pub fn new(self_pubkey: ValidatorPublicKey, max_peers: usize) -> Self {
+        Self {
+            self_pubkey,
+            max_peers,
+            peers: HashMap::new(),
+        }
+    }
+
+    pub fn shared(self_pubkey: ValidatorPublicKey, max_peers: usize) -> SharedPeerTable {
+        Arc::new(Mutex::new(Self::new(self_pubkey, max_peers)))
+    }
+
+    fn is_connected(&self, pubkey: &ValidatorPublicKey) -> bool {
+        self.peers.get(pubkey).is_some_and(|p| p.connections > 0)
+    }
+
+    fn connected_count(&self) -> usize {
+        self.peers.values().filter(|p| p.connections > 0).count()
+    }
+
+    /// Records a peer whose handshake is done.
+    pub fn connected(&mut self, info: PeerInfo) {
+        let peer = self
+            .peers
+            .entry(info.pubkey.clone())
+            .or_insert_with(|| KnownPeer {
+                info: info.clone(),
+                connections: 0,
+            });
+        // The peer may not listen, keep the address learned from others then
+        if !info.p2p_address.is_empty() || peer.info.p2p_address.is_empty() {
+            peer.info = info;
+        }
+        peer.connections += 1;
+    }
+
+    pub fn disconnected(&mut self, pubkey: &ValidatorPublicKey) {
+        if let Some(peer) = self.peers.get_mut(pubkey) {
+            peer.connections = peer.connections.saturating_sub(1);
+        }
+    }
+
+    /// The connected peers accepting connections, to advertise to `requester`.
+    pub fn shareable(&self, requester: Option<&ValidatorPublicKey>) -> Vec<PeerInfo> {
+        self.peers
+            .values()
+            .filter(|p| p.connections > 0 && !p.info.p2p_address.is_empty())
+            .filter(|p| Some(&p.info.pubkey) != requester)
+            .take(MAX_SHARED_PEERS)
+            .map(|p| p.info.clone())
+            .collect()
+    }
+
+    /// Records peers advertised by another node, and returns the addresses to connect to.
+    pub fn discovered(&mut self, infos: Vec<PeerInfo>) -> Vec<String> {
+        let mut room = self.max_peers.saturating_sub(self.connected_count());
+        let mut to_connect = vec![];
+        for info in infos.into_iter().take(MAX_SHARED_PEERS) {
+            if info.pubkey == self.self_pubkey
+                || info.p2p_address.is_empty()
+                || self.is_connected(&info.pubkey)
+            {
+                continue;
+            }
+            if !self.peers.contains_key(&info.pubkey) {
+                if self.peers.len() >= MAX_KNOWN_PEERS {
+                    continue;
+                }
+                self.peers.insert(
+                    info.pubkey.clone(),
+                    KnownPeer {
+                        info: info.clone(),
+                        connections: 0,
+                    },
+                );
+            }
+            if room > 0 && !to_connect.contains(&info.p2p_address) {
+                room -= 1;
+                to_connect.push(info.p2p_address);
+            }
+        }
+        to_connect
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(name: &str, p2p_address: &str) -> PeerInfo {
+        PeerInfo {
+            name: name.to_string(),
+            pubkey: ValidatorPublicKey(name.as_bytes().to_vec()),
+            p2p_address: p2p_address.to_string(),
+            da_address: format!("{name}:4141"),
+        }
+    }
+
+    #[test]
+    fn test_peer_exchange() {
+        let mut table = PeerTable::new(ValidatorPublicKey(b"me".to_vec()), 3);
+        table.connected(info("bootstrap", "bootstrap:1231"));
+        // Outbound-only peers aren't advertised
+        table.connected(info("private", ""));
+        assert_eq!(
+            table.shareable(None),
+            vec![info("bootstrap", "bootstrap:1231")]
+        );
+        assert_eq!(
+            table.shareable(Some(&ValidatorPublicKey(b"bootstrap".to_vec()))),
+            vec![]
+        );
+
+        // Ourselves, connected peers and peers without address are skipped,
+        // and only one more peer fits
+        let to_connect = table.discovered(vec![
+            info("me", "me:1231"),
+            info("bootstrap", "bootstrap:1231"),
+            info("hidden", ""),
+            info("a", "a:1231"),
+            info("b", "b:1231"),
+        ]);
+        assert_eq!(to_connect, vec!["a:1231".to_string()]);
+
+        // Once a peer is gone, there is room for another
+        table.disconnected(&ValidatorPublicKey(b"private".to_vec()));
+        assert_eq!(
+            table.discovered(vec![info("b", "b:1231")]),
+            vec!["b:1231".to_string()]
+        );
+
+        // Connecting with the other side's address unknown keeps the advertised one
+        table.connected(info("b", ""));
+        table.disconnected(&ValidatorPublicKey(b"bootstrap".to_vec()));
+        assert_eq!(table.shareable(None), vec![info("b", "b:1231")]);
+    }
+}
@@ -0,0 +1,41 @@
+use opentelemetry::{
+    metrics::{Counter, Gauge},
+    InstrumentationScope, KeyValue,
+};
+
+#[derive(Debug)]
+pub struct P2PMetrics {
+    peer_clock_skew: Gauge<i64>,
+    peer_rtt: Gauge<u64>,
+    clock_skew_warning: Counter<u64>,
+}
+
+impl P2PMetrics {
+    pub fn global(node_name: String) -> P2PMetrics {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        let p2p = "p2p";
+
+        P2PMetrics {
+            peer_clock_skew: my_meter
+                .i64_gauge(format!("{p2p}_peer_clock_skew_ms"))
+                .build(),
+            peer_rtt: my_meter.u64_gauge(format!("{p2p}_peer_rtt_ms")).build(),
+            clock_skew_warning: my_meter
+                .u64_counter(format!("{p2p}_clock_skew_warning"))
+                .build(),
+        }
+    }
+
+    pub fn record_clock_sample(&self, peer: &str, skew_ms: i64, rtt_ms: u64) {
+        let labels = [KeyValue::new("peer", peer.to_string())];
+        self.peer_clock_skew.record(skew_ms, &labels);
+        self.peer_rtt.record(rtt_ms, &labels);
+    }
+
+    pub fn clock_skew_warning(&self, peer: &str) {
+        self.clock_skew_warning
+            .add(1, &[KeyValue::new("peer", peer.to_string())]);
+    }
+}
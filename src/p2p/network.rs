@@ -15,6 +15,17 @@ pub struct Hello {
     pub validator_pubkey: ValidatorPublicKey,
     pub name: String,
     pub da_address: String,
+    /// Address the node accepts p2p connections on, if it listens
+    pub p2p_address: Option<String>,
+}
+
+/// A peer as advertised to other nodes during peer exchange.
+#[derive(Debug, Serialize, Deserialize, Clone, Encode, Decode, Eq, PartialEq)]
+pub struct PeerInfo {
+    pub name: String,
+    pub pubkey: ValidatorPublicKey,
+    pub p2p_address: String,
+    pub da_address: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -87,8 +98,15 @@ pub enum NetMessage {
 pub enum HandshakeNetMessage {
     Hello(Hello),
     Verack,
-    Ping,
-    Pong,
+    /// Carries the sender's timestamp (ms), echoed back in the pong
+    Ping(u64),
+    Pong {
+        ping_timestamp: u64,
+        pong_timestamp: u64,
+    },
+    /// Asks for the peers the node is connected to
+    GetPeers,
+    Peers(Vec<PeerInfo>),
 }
 
 impl From<HandshakeNetMessage> for NetMessage {
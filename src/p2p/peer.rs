@@ -2,7 +2,7 @@ use std::time::Duration;
 use std::time::SystemTime;
 
 use anyhow::Context;
-use anyhow::{Error, Result};
+use anyhow::{bail, Error, Result};
 use tokio::net::TcpStream;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
@@ -10,10 +10,13 @@ use tokio_util::codec::Framed;
 use tokio_util::codec::LengthDelimitedCodec;
 use tracing::{info, trace, warn};
 
+use super::discovery::SharedPeerTable;
 use super::fifo_filter::FifoFilter;
+use super::metrics::P2PMetrics;
 use super::network::HandshakeNetMessage;
 use super::network::OutboundMessage;
 use super::network::PeerEvent;
+use super::network::PeerInfo;
 use super::network::{Hello, NetMessage};
 use super::stream::send_net_message;
 use crate::bus::bus_client;
@@ -25,16 +28,20 @@ use crate::model::SignedByValidator;
 use crate::model::ValidatorPublicKey;
 use crate::module_handle_messages;
 use crate::p2p::stream::read_stream;
+use crate::p2p::P2PCommand;
 use crate::utils::conf::SharedConf;
 use crate::utils::crypto::SharedBlstCrypto;
 use crate::utils::logger::LogMe;
 use crate::utils::modules::signal::ShutdownModule;
+use crate::utils::noise::NodeStream;
+use hyle_model::utils::get_current_timestamp_ms;
 
 bus_client! {
 struct PeerBusClient {
     sender(SignedByValidator<MempoolNetMessage>),
     sender(SignedByValidator<ConsensusNetMessage>),
     sender(PeerEvent),
+    sender(P2PCommand),
     receiver(OutboundMessage),
     receiver(ShutdownModule),
 }
@@ -42,15 +49,22 @@ struct PeerBusClient {
 
 pub struct Peer {
     id: u64,
-    stream: Framed<TcpStream, LengthDelimitedCodec>,
+    stream: Framed<NodeStream, LengthDelimitedCodec>,
     bus: PeerBusClient,
     last_pong: SystemTime,
+    metrics: P2PMetrics,
     conf: SharedConf,
     fifo_filter: FifoFilter<Vec<u8>>,
     self_pubkey: ValidatorPublicKey,
+    /// Validator proven by the peer when setting up an encrypted connection
+    authenticated_pubkey: Option<ValidatorPublicKey>,
     peer_pubkey: Option<ValidatorPublicKey>,
     peer_name: Option<String>,
     peer_da_address: Option<String>,
+    peer_p2p_address: Option<String>,
+    peers: SharedPeerTable,
+    /// Whether the peer was recorded as connected in the peer table
+    registered: bool,
 
     // peer internal channel
     internal_cmd_tx: mpsc::Sender<Cmd>,
@@ -59,15 +73,18 @@ pub struct Peer {
 
 enum Cmd {
     Ping,
+    ExchangePeers,
 }
 
 impl Peer {
     pub async fn new(
         id: u64,
-        stream: TcpStream,
+        stream: NodeStream,
+        authenticated_pubkey: Option<ValidatorPublicKey>,
         bus: SharedMessageBus,
         crypto: SharedBlstCrypto,
         conf: SharedConf,
+        peers: SharedPeerTable,
     ) -> Self {
         let (cmd_tx, cmd_rx) = mpsc::channel::<Cmd>(100);
         let fifo_filter = FifoFilter::new(1000);
@@ -81,14 +98,19 @@ impl Peer {
             stream: framed,
             bus: PeerBusClient::new_from_bus(bus).await,
             last_pong: SystemTime::now(),
+            metrics: P2PMetrics::global(conf.id.clone()),
             conf,
             fifo_filter,
             self_pubkey: self_validator,
+            authenticated_pubkey,
             peer_pubkey: None,
             internal_cmd_tx: cmd_tx,
             internal_cmd_rx: cmd_rx,
             peer_name: None,
             peer_da_address: None,
+            peer_p2p_address: None,
+            peers,
+            registered: false,
         }
     }
 
@@ -126,33 +148,98 @@ impl Peer {
                 self.peer_pubkey = Some(v.validator_pubkey);
                 self.peer_name = Some(v.name);
                 self.peer_da_address = Some(v.da_address);
+                self.peer_p2p_address = v.p2p_address;
                 send_net_message(&mut self.stream, HandshakeNetMessage::Verack.into()).await
             }
             HandshakeNetMessage::Verack => {
                 trace!("Got peer verack message");
                 if let Some(pubkey) = &self.peer_pubkey {
-                    self.bus.send(PeerEvent::NewPeer {
+                    let info = PeerInfo {
                         name: self.peer_name.clone().unwrap_or("unknown".to_string()),
                         pubkey: pubkey.clone(),
-                        da_address: self
-                            .peer_da_address
-                            .clone()
-                            .unwrap_or("unknown".to_string()),
+                        p2p_address: self.peer_p2p_address.clone().unwrap_or_default(),
+                        da_address: self.peer_da_address.clone().unwrap_or_default(),
+                    };
+                    if !self.registered {
+                        if let Ok(mut peers) = self.peers.lock() {
+                            peers.connected(info.clone());
+                            self.registered = true;
+                        }
+                    }
+                    self.bus.send(PeerEvent::NewPeer {
+                        name: info.name,
+                        pubkey: info.pubkey,
+                        da_address: info.da_address,
                     })?;
                 }
                 self.ping_pong();
-                Ok(())
+                self.exchange_peers();
+                send_net_message(&mut self.stream, HandshakeNetMessage::GetPeers.into()).await
             }
-            HandshakeNetMessage::Ping => {
-                send_net_message(&mut self.stream, HandshakeNetMessage::Pong.into()).await
+            HandshakeNetMessage::Ping(ping_timestamp) => {
+                send_net_message(
+                    &mut self.stream,
+                    HandshakeNetMessage::Pong {
+                        ping_timestamp,
+                        pong_timestamp: get_current_timestamp_ms(),
+                    }
+                    .into(),
+                )
+                .await
             }
-            HandshakeNetMessage::Pong => {
+            HandshakeNetMessage::Pong {
+                ping_timestamp,
+                pong_timestamp,
+            } => {
                 self.last_pong = SystemTime::now();
+                self.on_clock_sample(ping_timestamp, pong_timestamp, get_current_timestamp_ms());
+                Ok(())
+            }
+            HandshakeNetMessage::GetPeers => {
+                let peers = match self.peers.lock() {
+                    Ok(peers) => peers.shareable(self.peer_pubkey.as_ref()),
+                    Err(_) => vec![],
+                };
+                send_net_message(&mut self.stream, HandshakeNetMessage::Peers(peers).into()).await
+            }
+            HandshakeNetMessage::Peers(infos) => {
+                let to_connect = match self.peers.lock() {
+                    Ok(mut peers) => peers.discovered(infos),
+                    Err(_) => vec![],
+                };
+                for peer in to_connect {
+                    info!("🔎 Discovered peer {} through #{}", peer, self.id);
+                    self.bus.send(P2PCommand::ConnectTo { peer })?;
+                }
                 Ok(())
             }
         }
     }
 
+    /// Estimates the peer's clock offset from a ping round trip, assuming symmetric latency.
+    fn on_clock_sample(&self, ping_timestamp: u64, pong_timestamp: u64, now: u64) {
+        let (skew_ms, rtt_ms) = clock_offset_ms(ping_timestamp, pong_timestamp, now);
+        let peer = self
+            .peer_name
+            .clone()
+            .unwrap_or_else(|| self.id.to_string());
+        self.metrics.record_clock_sample(&peer, skew_ms, rtt_ms);
+
+        // Proposals are only accepted within a slot of the previous one,
+        // so beyond that timestamps of either node will be rejected.
+        let tolerance = self.conf.consensus.slot_duration;
+        if skew_ms.unsigned_abs() > tolerance {
+            warn!(
+                "⏰ Clock of peer {} is {}ms {} ours (tolerance {}ms). Check NTP on both nodes.",
+                peer,
+                skew_ms.unsigned_abs(),
+                if skew_ms > 0 { "ahead of" } else { "behind" },
+                tolerance
+            );
+            self.metrics.clock_skew_warning(&peer);
+        }
+    }
+
     async fn handle_peer_stream_message(&mut self, msg: NetMessage) -> Result<(), Error> {
         trace!("RECV: {:?}", msg);
         match msg {
@@ -191,6 +278,26 @@ impl Peer {
             });
     }
 
+    /// Periodically asks the peer for the peers it knows, to find those that joined since.
+    fn exchange_peers(&self) {
+        let interval = self.conf.p2p.peer_exchange_interval;
+        if interval == 0 {
+            return;
+        }
+        let tx = self.internal_cmd_tx.clone();
+
+        let _ = tokio::task::Builder::new()
+            .name(&format!("exchange-peers-{}", self.id))
+            .spawn(async move {
+                loop {
+                    sleep(Duration::from_secs(interval)).await;
+                    if tx.send(Cmd::ExchangePeers).await.is_err() {
+                        break;
+                    }
+                }
+            });
+    }
+
     pub async fn start(&mut self) -> Result<()> {
         module_handle_messages! {
             on_bus self.bus,
@@ -219,6 +326,9 @@ impl Peer {
 
             res = read_stream(&mut self.stream) => {
                 let message = res.log_warn("Reading tcp stream")?;
+                if let NetMessage::HandshakeMessage(HandshakeNetMessage::Hello(hello)) = &message {
+                    self.check_identity(&hello.validator_pubkey).log_warn("Checking peer identity")?;
+                }
 
                 _ = self.handle_peer_stream_message(message)
                     .await
@@ -236,7 +346,10 @@ impl Peer {
                                 }
                             }
                             trace!("ping");
-                            send_net_message(&mut self.stream, HandshakeNetMessage::Ping.into()).await
+                            send_net_message(&mut self.stream, HandshakeNetMessage::Ping(get_current_timestamp_ms()).into()).await
+                        }
+                        Cmd::ExchangePeers => {
+                            send_net_message(&mut self.stream, HandshakeNetMessage::GetPeers.into()).await
                         }
                     };
 
@@ -247,10 +360,30 @@ impl Peer {
         Ok(())
     }
 
-    pub async fn connect(addr: &str) -> Result<TcpStream> {
+    /// On encrypted connections, the peer must introduce itself as the validator it proved to be.
+    fn check_identity(&self, pubkey: &ValidatorPublicKey) -> Result<()> {
+        if self.conf.p2p.encrypted && self.authenticated_pubkey.as_ref() != Some(pubkey) {
+            bail!(
+                "Peer #{} introduced itself as {} but authenticated as {:?}",
+                self.id,
+                pubkey,
+                self.authenticated_pubkey
+            );
+        }
+        Ok(())
+    }
+
+    pub async fn connect(
+        addr: &str,
+        conf: &SharedConf,
+        crypto: &SharedBlstCrypto,
+    ) -> Result<(NodeStream, Option<ValidatorPublicKey>)> {
         let conn = TcpStream::connect(addr)
             .await
             .context("Connect to peer with TCP stream")?;
+        let conn = NodeStream::connect(conn, conf.p2p.encrypted, Some(crypto.as_ref()))
+            .await
+            .context("Securing connection to peer")?;
         info!("Connected to peer: {}", addr);
         Ok(conn)
     }
@@ -259,13 +392,48 @@ impl Peer {
         send_net_message(
             &mut self.stream,
             HandshakeNetMessage::Hello(Hello {
-                version: 1,
+                version: 2,
                 validator_pubkey: self.self_pubkey.clone(),
                 name: self.conf.id.clone(),
-                da_address: self.conf.da_address.clone(),
+                da_address: self.conf.advertised_da_address().unwrap_or_default(),
+                p2p_address: self.conf.advertised_p2p_address(),
             })
             .into(),
         )
         .await
     }
 }
+
+impl Drop for Peer {
+    fn drop(&mut self) {
+        if let (true, Some(pubkey)) = (self.registered, &self.peer_pubkey) {
+            if let Ok(mut peers) = self.peers.lock() {
+                peers.disconnected(pubkey);
+            }
+        }
+    }
+}
+
+/// Returns the peer clock's offset relative to ours and the round trip time, both in ms.
+/// A positive offset means the peer's clock is ahead.
+fn clock_offset_ms(ping_timestamp: u64, pong_timestamp: u64, now: u64) -> (i64, u64) {
+    let rtt = now.saturating_sub(ping_timestamp);
+    let midpoint = ping_timestamp + rtt / 2;
+    let offset = pong_timestamp as i128 - midpoint as i128;
+    (offset.clamp(i64::MIN as i128, i64::MAX as i128) as i64, rtt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::clock_offset_ms;
+
+    #[test]
+    fn test_clock_offset() {
+        // Peer answered right in the middle of a 100ms round trip, 500ms ahead of us
+        assert_eq!(clock_offset_ms(1_000, 1_550, 1_100), (500, 100));
+        // Peer is behind
+        assert_eq!(clock_offset_ms(1_000, 800, 1_100), (-250, 100));
+        // Our clock went backwards during the round trip
+        assert_eq!(clock_offset_ms(1_000, 1_000, 900), (0, 0));
+    }
+}
@@ -1,13 +1,13 @@
 use anyhow::{anyhow, bail, Context, Error};
 use futures::{SinkExt, StreamExt};
-use tokio::net::TcpStream;
 use tokio_util::codec::{Framed, LengthDelimitedCodec};
 use tracing::trace;
 
 use super::network::NetMessage;
+use crate::utils::noise::NodeStream;
 
 pub async fn read_stream<T: bincode::Decode>(
-    stream: &mut Framed<TcpStream, LengthDelimitedCodec>,
+    stream: &mut Framed<NodeStream, LengthDelimitedCodec>,
 ) -> Result<T, Error> {
     trace!("Waiting for data");
     if let Some(result) = stream.next().await {
@@ -25,7 +25,7 @@ pub async fn read_stream<T: bincode::Decode>(
 }
 
 pub async fn send_net_message(
-    stream: &mut Framed<TcpStream, LengthDelimitedCodec>,
+    stream: &mut Framed<NodeStream, LengthDelimitedCodec>,
     msg: NetMessage,
 ) -> Result<(), Error> {
     stream
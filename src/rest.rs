@@ -1,5 +1,7 @@
 //! Public API for interacting with the node.
 
+use std::{collections::BTreeMap, sync::Arc};
+
 use anyhow::{Context, Result};
 pub use axum::Router;
 use axum::{
@@ -13,19 +15,28 @@ use axum::{
 };
 use axum_otel_metrics::HttpMetricsLayer;
 use hyle_model::api::*;
+use hyle_model::errors::{ErrorCode, HyleError};
 use hyle_model::*;
 use prometheus::{Encoder, TextEncoder};
 use reqwest::StatusCode;
 use tokio::time::Instant;
-use tracing::info;
+use tracing::{info, warn};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
+use crate::utils::conf::{Conf, RestAuthConf, RestCorsConf, RestRateLimitConf, SharedConf};
+use crate::utils::logger;
 use crate::utils::modules::Module;
 use crate::{bus::SharedMessageBus, module_handle_messages, utils::modules::module_bus_client};
 
 pub use client_sdk::rest_client as client;
 
+pub mod auth;
+pub mod cors;
+pub mod etag;
+pub mod health;
+pub mod rate_limit;
+
 module_bus_client! {
     struct RestBusClient {
     }
@@ -39,10 +50,15 @@ pub struct RestApiRunContext {
     pub metrics_layer: Option<HttpMetricsLayer>,
     pub max_body_size: usize,
     pub openapi: utoipa::openapi::OpenApi,
+    pub auth: RestAuthConf,
+    pub rate_limit: RestRateLimitConf,
+    pub cors: RestCorsConf,
+    pub config: SharedConf,
 }
 
 pub struct RouterState {
     info: NodeInfo,
+    config: SharedConf,
 }
 
 pub struct RestApi {
@@ -69,20 +85,53 @@ impl Module for RestApi {
     type Context = RestApiRunContext;
 
     async fn build(ctx: Self::Context) -> Result<Self> {
-        let app = ctx.router.merge(
+        let (health_router, health_api) = health::api(&ctx.bus).await;
+        let mut openapi = ctx.openapi;
+        openapi.merge(health_api);
+        let app = ctx.router.merge(health_router).merge(
             Router::new()
-                .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ctx.openapi))
+                .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", openapi))
                 .route("/v1/info", get(get_info))
                 .route("/v1/metrics", get(get_metrics))
-                .with_state(RouterState { info: ctx.info }),
+                // Usual path of Prometheus scrapes
+                .route("/metrics", get(get_metrics))
+                .route("/v1/admin/config", get(get_config))
+                .route(
+                    "/v1/admin/log/modules",
+                    get(get_log_modules).put(set_log_modules),
+                )
+                .with_state(RouterState {
+                    info: ctx.info,
+                    config: ctx.config,
+                }),
         );
         let app = match ctx.metrics_layer {
             Some(ml) => app.layer(ml),
             None => app,
         };
+        let auth = auth::ApiAuth::new(&ctx.auth);
+        if !auth.is_enabled() {
+            warn!(
+                "No API keys nor JWT secret configured, routes under {} are disabled",
+                auth::ADMIN_ROUTES_PREFIX
+            );
+        }
+        let app = app.layer(axum::middleware::from_fn_with_state(
+            auth,
+            auth::require_role,
+        ));
+        let limiter = rate_limit::RateLimiter::new(ctx.info.id.clone(), &ctx.rate_limit);
+        let app = if limiter.is_enabled() {
+            app.layer(axum::middleware::from_fn_with_state(
+                Arc::new(limiter),
+                rate_limit::rate_limit,
+            ))
+        } else {
+            app
+        };
         let app = app
             .layer(DefaultBodyLimit::max(ctx.max_body_size)) // 10 MB
-            .layer(tower_http::cors::CorsLayer::permissive())
+            .layer(cors::cors_layer(&ctx.cors))
             .layer(axum::middleware::from_fn(request_logger))
             //.layer(TraceLayer::new_for_http())
         ;
@@ -126,6 +175,25 @@ pub async fn get_info(State(state): State<RouterState>) -> Result<impl IntoRespo
     Ok(Json(state.info))
 }
 
+/// Effective configuration of the node, without its secrets.
+pub async fn get_config(State(state): State<RouterState>) -> Json<Conf> {
+    Json(state.config.redacted())
+}
+
+/// Log levels by module applied over RUST_LOG.
+pub async fn get_log_modules() -> Json<BTreeMap<String, String>> {
+    Json(logger::log_modules())
+}
+
+/// Replaces the log levels by module, e.g. `{"data_availability": "debug"}`.
+pub async fn set_log_modules(
+    Json(modules): Json<BTreeMap<String, String>>,
+) -> Result<Json<BTreeMap<String, String>>, AppError> {
+    logger::set_log_modules(modules)
+        .map_err(|e| AppError::with_code(ErrorCode::BadRequest, format!("{e:#}")))?;
+    Ok(Json(logger::log_modules()))
+}
+
 pub async fn get_metrics(State(_): State<RouterState>) -> Result<impl IntoResponse, AppError> {
     let mut buffer = Vec::new();
     let encoder = TextEncoder::new();
@@ -148,6 +216,7 @@ impl RestApi {
                     .context("Starting rest server")?,
                 #[allow(clippy::expect_used, reason="incorrect setup logic")]
                 self.app.take().expect("app is not set")
+                    .into_make_service_with_connect_info::<std::net::SocketAddr>()
             ) => { }
         };
 
@@ -159,6 +228,7 @@ impl Clone for RouterState {
     fn clone(&self) -> Self {
         Self {
             info: self.info.clone(),
+            config: self.config.clone(),
         }
     }
 }
@@ -166,10 +236,28 @@ impl Clone for RouterState {
 // Make our own error that wraps `anyhow::Error`.
 pub struct AppError(pub StatusCode, pub anyhow::Error);
 
+impl AppError {
+    /// Error with a stable code, returned with the HTTP status that goes with it.
+    pub fn with_code(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self(
+            StatusCode::from_u16(code.http_status()).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+            HyleError::new(code, message).into(),
+        )
+    }
+}
+
 // Tell axum how to convert `AppError` into a response.
+// Errors without a specific code get the generic one for their status.
 impl IntoResponse for AppError {
     fn into_response(self) -> Response {
-        (self.0, format!("{}", self.1)).into_response()
+        let body = match self.1.downcast_ref::<HyleError>() {
+            Some(err) => err.clone(),
+            None => HyleError::new(
+                ErrorCode::from_http_status(self.0.as_u16()),
+                format!("{}", self.1),
+            ),
+        };
+        (self.0, Json(body)).into_response()
     }
 }
 
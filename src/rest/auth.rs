@@ -0,0 +1,251 @@
+//! Authentication of the REST API: routes are grouped by the role they require.
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header::AUTHORIZATION, HeaderMap, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use hyle_model::{errors::ErrorCode, utils::get_current_timestamp};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use super::AppError;
+use crate::utils::{conf::RestAuthConf, crypto::constant_time_eq};
+
+/// Prefix of the routes restricted to admins.
+pub const ADMIN_ROUTES_PREFIX: &str = "/v1/admin/";
+
+/// Role required by a group of routes, or granted by credentials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiRole {
+    /// Read routes and transaction submission, open to everyone
+    Public,
+    /// Operations on the node: pruning, snapshots, peer management…
+    Admin,
+}
+
+impl ApiRole {
+    pub fn required_for(path: &str) -> Self {
+        if path.starts_with(ADMIN_ROUTES_PREFIX) {
+            ApiRole::Admin
+        } else {
+            ApiRole::Public
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct JwtHeader {
+    alg: String,
+}
+
+#[derive(Deserialize)]
+struct JwtClaims {
+    role: String,
+    /// Expiration, as seconds since the UNIX epoch
+    exp: u64,
+}
+
+/// Checks the credentials of requests against the API keys and JWT secret of the configuration.
+#[derive(Clone, Default)]
+pub struct ApiAuth {
+    admin_api_keys: Arc<Vec<String>>,
+    jwt_secret: Option<Arc<String>>,
+}
+
+impl ApiAuth {
+    pub fn new(conf: &RestAuthConf) -> Self {
+        Self {
+            admin_api_keys: Arc::new(conf.admin_api_keys.clone()),
+            jwt_secret: conf.jwt_secret.clone().map(Arc::new),
+        }
+    }
+
+    /// Without any credentials configured, admin routes are refused.
+    pub fn is_enabled(&self) -> bool {
+        !self.admin_api_keys.is_empty() || self.jwt_secret.is_some()
+    }
+
+    /// Role granted by an API key in the `x-api-key` header, or by an API key or a JWT
+    /// sent as `Authorization: Bearer <token>`.
+    pub fn role_of(&self, headers: &HeaderMap) -> ApiRole {
+        let bearer = headers
+            .get(AUTHORIZATION)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|h| h.strip_prefix("Bearer "));
+        let api_key = headers.get("x-api-key").and_then(|h| h.to_str().ok());
+
+        if api_key.or(bearer).is_some_and(|key| self.is_admin_key(key))
+            || bearer.is_some_and(|token| self.is_admin_jwt(token))
+        {
+            ApiRole::Admin
+        } else {
+            ApiRole::Public
+        }
+    }
+
+    fn is_admin_key(&self, key: &str) -> bool {
+        self.admin_api_keys
+            .iter()
+            .any(|k| constant_time_eq(k.as_bytes(), key.as_bytes()))
+    }
+
+    /// Only HS256 tokens with an `admin` role that haven't expired are accepted.
+    fn is_admin_jwt(&self, token: &str) -> bool {
+        let Some(secret) = &self.jwt_secret else {
+            return false;
+        };
+        let mut parts = token.split('.');
+        let (Some(header), Some(payload), Some(signature), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return false;
+        };
+        let Some(jwt_header) = decode_json::<JwtHeader>(header) else {
+            return false;
+        };
+        if jwt_header.alg != "HS256" {
+            return false;
+        }
+        let Ok(signature) = URL_SAFE_NO_PAD.decode(signature) else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(format!("{header}.{payload}").as_bytes());
+        if mac.verify_slice(&signature).is_err() {
+            return false;
+        }
+        let Some(claims) = decode_json::<JwtClaims>(payload) else {
+            return false;
+        };
+        claims.role == "admin" && claims.exp > get_current_timestamp()
+    }
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(part: &str) -> Option<T> {
+    let bytes = URL_SAFE_NO_PAD.decode(part).ok()?;
+    serde_json::from_slice(&bytes).ok()
+}
+
+/// Rejects requests to routes whose role isn't granted by their credentials.
+pub async fn require_role(State(auth): State<ApiAuth>, req: Request<Body>, next: Next) -> Response {
+    let required = ApiRole::required_for(req.uri().path());
+    if required == ApiRole::Public || auth.role_of(req.headers()) == required {
+        return next.run(req).await;
+    }
+    let message = if auth.is_enabled() {
+        "Admin credentials required"
+    } else {
+        "Admin routes are disabled: no API key nor JWT secret configured"
+    };
+    AppError::with_code(ErrorCode::Unauthorized, message).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::{HeaderValue, StatusCode},
+        routing::get,
+        Router,
+    };
+    use axum_test::TestServer;
+
+    use super::*;
+    use crate::utils::conf::Conf;
+
+    fn jwt(secret: &str, claims: serde_json::Value) -> String {
+        let header = URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#);
+        let payload = URL_SAFE_NO_PAD.encode(claims.to_string());
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(format!("{header}.{payload}").as_bytes());
+        let signature = URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes());
+        format!("{header}.{payload}.{signature}")
+    }
+
+    fn headers(name: &'static str, value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(name, HeaderValue::from_str(value).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_route_groups() {
+        assert_eq!(ApiRole::required_for("/v1/admin/da/peers"), ApiRole::Admin);
+        assert_eq!(ApiRole::required_for("/v1/contract/c1"), ApiRole::Public);
+        assert_eq!(ApiRole::required_for("/v1/administrator"), ApiRole::Public);
+    }
+
+    #[test]
+    fn test_api_keys() {
+        let auth = ApiAuth::new(&RestAuthConf {
+            admin_api_keys: vec!["key".to_string()],
+            jwt_secret: None,
+        });
+        assert!(auth.is_enabled());
+        assert_eq!(auth.role_of(&headers("x-api-key", "key")), ApiRole::Admin);
+        assert_eq!(
+            auth.role_of(&headers("authorization", "Bearer key")),
+            ApiRole::Admin
+        );
+        assert_eq!(auth.role_of(&headers("x-api-key", "nope")), ApiRole::Public);
+        assert_eq!(auth.role_of(&HeaderMap::new()), ApiRole::Public);
+        assert!(!ApiAuth::default().is_enabled());
+    }
+
+    #[tokio::test]
+    async fn test_admin_routes_refused_by_default() {
+        let conf = Conf::new(None, None, None).unwrap();
+        let router = Router::new()
+            .route("/v1/admin/config", get(|| async { "config" }))
+            .route("/v1/info", get(|| async { "info" }))
+            .layer(axum::middleware::from_fn_with_state(
+                ApiAuth::new(&conf.rest_auth),
+                require_role,
+            ));
+        let server = TestServer::new(router).unwrap();
+
+        server.get("/v1/info").await.assert_status_ok();
+        server
+            .get("/v1/admin/config")
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+        server
+            .get("/v1/admin/config")
+            .add_header("x-api-key", HeaderValue::from_static(""))
+            .await
+            .assert_status(StatusCode::UNAUTHORIZED);
+    }
+
+    #[test]
+    fn test_jwt() {
+        let auth = ApiAuth::new(&RestAuthConf {
+            admin_api_keys: vec![],
+            jwt_secret: Some("secret".to_string()),
+        });
+        let exp = get_current_timestamp() + 60;
+        let bearer = |token: String| headers("authorization", &format!("Bearer {token}"));
+
+        let valid = jwt("secret", serde_json::json!({ "role": "admin", "exp": exp }));
+        assert_eq!(auth.role_of(&bearer(valid)), ApiRole::Admin);
+
+        let wrong_secret = jwt("other", serde_json::json!({ "role": "admin", "exp": exp }));
+        assert_eq!(auth.role_of(&bearer(wrong_secret)), ApiRole::Public);
+
+        let expired = jwt("secret", serde_json::json!({ "role": "admin", "exp": 1 }));
+        assert_eq!(auth.role_of(&bearer(expired)), ApiRole::Public);
+
+        let reader = jwt(
+            "secret",
+            serde_json::json!({ "role": "reader", "exp": exp }),
+        );
+        assert_eq!(auth.role_of(&bearer(reader)), ApiRole::Public);
+    }
+}
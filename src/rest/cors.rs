@@ -0,0 +1,106 @@
+//! CORS policy of the REST API, so that browser dapps can call it directly.
+
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue};
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+use tracing::warn;
+
+use crate::utils::conf::RestCorsConf;
+
+/// Builds the CORS layer of the configuration. `*` allows any origin, or any header.
+pub fn cors_layer(conf: &RestCorsConf) -> CorsLayer {
+    let allow_origin = if conf.allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(conf.allowed_origins.iter().filter_map(|origin| {
+            HeaderValue::from_str(origin)
+                .inspect_err(|_| warn!("Ignoring invalid CORS origin {}", origin))
+                .ok()
+        }))
+    };
+    let allow_headers = if conf.allowed_headers.iter().any(|h| h == "*") {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(conf.allowed_headers.iter().filter_map(|header| {
+            HeaderName::from_bytes(header.as_bytes())
+                .inspect_err(|_| warn!("Ignoring invalid CORS header {}", header))
+                .ok()
+        }))
+    };
+
+    let layer = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_headers(allow_headers)
+        .allow_methods(Any)
+        .expose_headers(Any);
+    if conf.max_age > 0 {
+        layer.max_age(Duration::from_secs(conf.max_age))
+    } else {
+        layer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{
+        http::{
+            header::{
+                ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_MAX_AGE, ACCESS_CONTROL_REQUEST_METHOD,
+                ORIGIN,
+            },
+            Method,
+        },
+        routing::get,
+        Router,
+    };
+    use axum_test::TestServer;
+
+    use super::*;
+
+    fn server(allowed_origins: &[&str]) -> TestServer {
+        let conf = RestCorsConf {
+            allowed_origins: allowed_origins.iter().map(|o| o.to_string()).collect(),
+            allowed_headers: vec!["content-type".to_string()],
+            max_age: 600,
+        };
+        let router = Router::new()
+            .route("/v1/info", get(|| async { "info" }))
+            .layer(cors_layer(&conf));
+        TestServer::new(router).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_allowed_origins() {
+        let server = server(&["https://app.example"]);
+
+        let response = server
+            .method(Method::OPTIONS, "/v1/info")
+            .add_header(ORIGIN, HeaderValue::from_static("https://app.example"))
+            .add_header(
+                ACCESS_CONTROL_REQUEST_METHOD,
+                HeaderValue::from_static("GET"),
+            )
+            .await;
+        assert_eq!(
+            response.header(ACCESS_CONTROL_ALLOW_ORIGIN),
+            "https://app.example"
+        );
+        assert_eq!(response.header(ACCESS_CONTROL_MAX_AGE), "600");
+
+        let response = server
+            .get("/v1/info")
+            .add_header(ORIGIN, HeaderValue::from_static("https://evil.example"))
+            .await;
+        assert!(response.maybe_header(ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_any_origin() {
+        let response = server(&["*"])
+            .get("/v1/info")
+            .add_header(ORIGIN, HeaderValue::from_static("https://app.example"))
+            .await;
+        assert_eq!(response.header(ACCESS_CONTROL_ALLOW_ORIGIN), "*");
+    }
+}
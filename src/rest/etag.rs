@@ -0,0 +1,155 @@
+//! ETags derived from response bodies, so that polling clients get a `304 Not Modified`
+//! instead of the same payload again.
+
+use anyhow::anyhow;
+use axum::{
+    body::{Body, HttpBody},
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_NONE_MATCH},
+        HeaderValue, Method, Request, StatusCode,
+    },
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha3::{Digest, Sha3_256};
+
+use super::AppError;
+
+/// Largest response body tagged. Bodies are buffered to be hashed, so larger ones and those
+/// of unknown size (streamed) are passed through untagged.
+const MAX_TAGGED_BODY_SIZE: usize = 8 * 1024 * 1024;
+
+fn matches(if_none_match: &HeaderValue, etag: &str) -> bool {
+    if_none_match.to_str().is_ok_and(|value| {
+        value
+            .split(',')
+            .map(str::trim)
+            .any(|tag| tag == "*" || tag == etag || tag.strip_prefix("W/") == Some(etag))
+    })
+}
+
+/// Tags successful GET responses with a hash of their body, and answers `304 Not Modified`
+/// when the client already holds that version.
+pub async fn etag(req: Request<Body>, next: Next) -> Response {
+    if req.method() != Method::GET {
+        return next.run(req).await;
+    }
+    let if_none_match = req.headers().get(IF_NONE_MATCH).cloned();
+
+    let response = next.run(req).await;
+    if response.status() != StatusCode::OK || response.headers().contains_key(ETAG) {
+        return response;
+    }
+    let declared_length = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|length| length.to_str().ok()?.parse::<u64>().ok());
+    let taggable = |length: u64| length <= MAX_TAGGED_BODY_SIZE as u64;
+    if !response.body().size_hint().exact().is_some_and(taggable)
+        || declared_length.is_some_and(|length| !taggable(length))
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_TAGGED_BODY_SIZE).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return AppError(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                anyhow!("Buffering response: {}", e),
+            )
+            .into_response()
+        }
+    };
+    let etag = format!("\"{}\"", hex::encode(Sha3_256::digest(&bytes)));
+    let Ok(etag_value) = HeaderValue::from_str(&etag) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    parts.headers.insert(ETAG, etag_value);
+
+    if if_none_match.is_some_and(|value| matches(&value, &etag)) {
+        parts.status = StatusCode::NOT_MODIFIED;
+        parts.headers.remove(CONTENT_LENGTH);
+        parts.headers.remove(CONTENT_TYPE);
+        return Response::from_parts(parts, Body::empty());
+    }
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{routing::get, Router};
+    use axum_test::TestServer;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_not_modified() {
+        let router = Router::new()
+            .route("/block", get(|| async { "block" }))
+            .route(
+                "/missing",
+                get(|| async { (StatusCode::NOT_FOUND, "missing") }),
+            )
+            .layer(axum::middleware::from_fn(etag));
+        let server = TestServer::new(router).unwrap();
+
+        let response = server.get("/block").await;
+        response.assert_status_ok();
+        let tag = response.header(ETAG);
+        assert_eq!(response.text(), "block");
+
+        let response = server
+            .get("/block")
+            .add_header(IF_NONE_MATCH, tag.clone())
+            .await;
+        response.assert_status(StatusCode::NOT_MODIFIED);
+        assert!(response.text().is_empty());
+        assert_eq!(response.header(ETAG), tag);
+
+        let response = server
+            .get("/block")
+            .add_header(IF_NONE_MATCH, HeaderValue::from_static("\"other\""))
+            .await;
+        response.assert_status_ok();
+        assert_eq!(response.text(), "block");
+
+        let response = server.get("/missing").await;
+        assert!(response.maybe_header(ETAG).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_untagged_bodies() {
+        let large = "a".repeat(MAX_TAGGED_BODY_SIZE + 1);
+        let router = Router::new()
+            .route(
+                "/large",
+                get({
+                    let large = large.clone();
+                    || async move { large }
+                }),
+            )
+            .route(
+                "/stream",
+                get(|| async {
+                    Body::from_stream(futures::stream::iter([
+                        Ok::<_, std::io::Error>("bl"),
+                        Ok("ock"),
+                    ]))
+                }),
+            )
+            .layer(axum::middleware::from_fn(etag));
+        let server = TestServer::new(router).unwrap();
+
+        let response = server.get("/large").await;
+        response.assert_status_ok();
+        assert!(response.maybe_header(ETAG).is_none());
+        assert_eq!(response.text().len(), large.len());
+
+        let response = server.get("/stream").await;
+        response.assert_status_ok();
+        assert!(response.maybe_header(ETAG).is_none());
+        assert_eq!(response.text(), "block");
+    }
+}
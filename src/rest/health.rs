@@ -0,0 +1,303 @@
+//! Liveness and sync status of the node, for load balancer health checks and probes.
+
+use std::{collections::BTreeMap, time::Duration};
+
+use anyhow::{anyhow, Result};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json, Router};
+use hyle_model::api::{APIBusChannel, APIModuleStatus, APINodeHealth, APINodeStatus};
+use tokio::sync::broadcast;
+use utoipa::OpenApi;
+use utoipa_axum::{router::OpenApiRouter, routes};
+
+use crate::{
+    bus::{
+        bus_client,
+        command_response::{CmdRespClient, Query},
+        metrics::BusMetrics,
+        SharedMessageBus,
+    },
+    consensus::QueryConsensusInfo,
+    data_availability::api::{QueryDaDiskUsage, QueryDaLastHeight},
+    indexer::QueryIndexerHeight,
+    mempool::{PendingData, QueryPendingData},
+    model::{BlockHeight, ConsensusInfo},
+    node_state::module::QueryBlockHeight,
+    rest::AppError,
+    utils::{modules::QueryModulesStatus, static_type_map::Pick},
+};
+
+/// Modules taking longer than this to answer are considered dead.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+bus_client! {
+struct HealthBusClient {
+    sender(Query<QueryConsensusInfo, ConsensusInfo>),
+    sender(Query<QueryPendingData, PendingData>),
+    sender(Query<QueryDaLastHeight, Option<BlockHeight>>),
+    sender(Query<QueryDaDiskUsage, Option<u64>>),
+    sender(Query<QueryBlockHeight, BlockHeight>),
+    sender(Query<QueryIndexerHeight, Option<BlockHeight>>),
+    sender(Query<QueryModulesStatus, Vec<APIModuleStatus>>),
+}
+}
+
+pub struct RouterState {
+    bus: HealthBusClient,
+}
+
+#[derive(OpenApi)]
+struct HealthAPI;
+
+pub async fn api(bus: &SharedMessageBus) -> (Router<()>, utoipa::openapi::OpenApi) {
+    let state = RouterState {
+        bus: HealthBusClient::new_from_bus(bus.new_handle()).await,
+    };
+
+    let (router, api) = OpenApiRouter::with_openapi(HealthAPI::openapi())
+        .routes(routes!(get_health))
+        .routes(routes!(get_status))
+        .routes(routes!(get_modules))
+        .routes(routes!(get_bus_channels))
+        .split_for_parts();
+
+    (router.with_state(state), api)
+}
+
+/// Queries a module, unless it doesn't run on this node.
+async fn probe<Cmd, Res>(bus: &mut HealthBusClient, cmd: Cmd) -> Option<Result<Res>>
+where
+    Cmd: Clone + Send + Sync + 'static,
+    Res: Clone + Send + Sync + 'static,
+    HealthBusClient: Pick<broadcast::Sender<Query<Cmd, Res>>> + CmdRespClient<Cmd, Res>,
+{
+    if Pick::<broadcast::Sender<Query<Cmd, Res>>>::get(bus).receiver_count() == 0 {
+        return None;
+    }
+    Some(bus.request_with_timeout(cmd, PROBE_TIMEOUT).await)
+}
+
+async fn node_status(bus: &mut HealthBusClient) -> APINodeStatus {
+    let consensus = probe(bus, QueryConsensusInfo {}).await;
+    let mempool = probe(bus, QueryPendingData {}).await;
+    let da = probe(bus, QueryDaLastHeight).await;
+    let da_disk_usage = match &da {
+        Some(Ok(_)) => probe(bus, QueryDaDiskUsage).await,
+        _ => None,
+    };
+    let node_state = probe(bus, QueryBlockHeight {}).await;
+    let indexer = probe(bus, QueryIndexerHeight).await;
+
+    let modules: BTreeMap<String, bool> = [
+        ("consensus", consensus.as_ref().map(Result::is_ok)),
+        ("mempool", mempool.as_ref().map(Result::is_ok)),
+        ("data_availability", da.as_ref().map(Result::is_ok)),
+        ("node_state", node_state.as_ref().map(Result::is_ok)),
+        ("indexer", indexer.as_ref().map(Result::is_ok)),
+    ]
+    .into_iter()
+    .filter_map(|(name, alive)| alive.map(|alive| (name.to_string(), alive)))
+    .collect();
+
+    let consensus_height = consensus
+        .and_then(Result::ok)
+        .map(|info| BlockHeight(info.slot));
+    let pending = mempool.and_then(Result::ok);
+    let da_height = da.and_then(Result::ok).flatten();
+    let node_state_height = node_state.and_then(Result::ok);
+    let indexer_height = indexer.and_then(Result::ok).flatten();
+
+    APINodeStatus {
+        health: APINodeHealth {
+            healthy: modules.values().all(|alive| *alive),
+            modules,
+        },
+        consensus_height,
+        da_height,
+        da_lag: consensus_height
+            .zip(da_height)
+            .map(|(c, d)| c.0.saturating_sub(d.0)),
+        da_disk_usage: da_disk_usage.and_then(Result::ok).flatten(),
+        node_state_height,
+        indexer_height,
+        indexer_lag: node_state_height
+            .zip(indexer_height)
+            .map(|(n, i)| n.0.saturating_sub(i.0)),
+        mempool_pending_txs: pending.as_ref().map(|p| p.txs),
+        mempool_pending_bytes: pending.map(|p| p.bytes),
+    }
+}
+
+fn status_code(health: &APINodeHealth) -> StatusCode {
+    if health.healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/health",
+    tag = "Node",
+    responses(
+        (status = OK, body = APINodeHealth),
+        (status = SERVICE_UNAVAILABLE, body = APINodeHealth)
+    )
+)]
+pub async fn get_health(State(mut state): State<RouterState>) -> impl IntoResponse {
+    let health = node_status(&mut state.bus).await.health;
+    (status_code(&health), Json(health))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/status",
+    tag = "Node",
+    responses(
+        (status = OK, body = APINodeStatus),
+        (status = SERVICE_UNAVAILABLE, body = APINodeStatus)
+    )
+)]
+pub async fn get_status(State(mut state): State<RouterState>) -> impl IntoResponse {
+    let status = node_status(&mut state.bus).await;
+    (status_code(&status.health), Json(status))
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/admin/modules",
+    tag = "Node",
+    responses(
+        (status = OK, body = [APIModuleStatus])
+    )
+)]
+pub async fn get_modules(
+    State(mut state): State<RouterState>,
+) -> Result<impl IntoResponse, AppError> {
+    match probe(&mut state.bus, QueryModulesStatus).await {
+        Some(Ok(modules)) => Ok(Json(modules)),
+        Some(Err(e)) => Err(AppError(StatusCode::SERVICE_UNAVAILABLE, e)),
+        None => Err(AppError(
+            StatusCode::SERVICE_UNAVAILABLE,
+            anyhow!("Modules are not running"),
+        )),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/v1/admin/bus",
+    tag = "Node",
+    responses(
+        (status = OK, body = [APIBusChannel])
+    )
+)]
+pub async fn get_bus_channels(State(state): State<RouterState>) -> Json<Vec<APIBusChannel>> {
+    Json(Pick::<BusMetrics>::get(&state.bus).diagnostics().channels())
+}
+
+impl Clone for RouterState {
+    fn clone(&self) -> Self {
+        Self {
+            bus: HealthBusClient::new(
+                Pick::<BusMetrics>::get(&self.bus).clone(),
+                Pick::<broadcast::Sender<Query<QueryConsensusInfo, ConsensusInfo>>>::get(&self.bus)
+                    .clone(),
+                Pick::<broadcast::Sender<Query<QueryPendingData, PendingData>>>::get(&self.bus)
+                    .clone(),
+                Pick::<broadcast::Sender<Query<QueryDaLastHeight, Option<BlockHeight>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<broadcast::Sender<Query<QueryDaDiskUsage, Option<u64>>>>::get(&self.bus)
+                    .clone(),
+                Pick::<broadcast::Sender<Query<QueryBlockHeight, BlockHeight>>>::get(&self.bus)
+                    .clone(),
+                Pick::<broadcast::Sender<Query<QueryIndexerHeight, Option<BlockHeight>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+                Pick::<broadcast::Sender<Query<QueryModulesStatus, Vec<APIModuleStatus>>>>::get(
+                    &self.bus,
+                )
+                .clone(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bus::bus_client, handle_messages};
+
+    bus_client! {
+    struct NodeStateMock {
+        receiver(Query<QueryBlockHeight, BlockHeight>),
+    }
+    }
+
+    bus_client! {
+    struct IndexerMock {
+        receiver(Query<QueryIndexerHeight, Option<BlockHeight>>),
+    }
+    }
+
+    #[tokio::test]
+    async fn test_status_of_running_modules() {
+        let shared_bus = SharedMessageBus::default();
+        let mut bus = HealthBusClient::new_from_bus(shared_bus.new_handle()).await;
+
+        let status = node_status(&mut bus).await;
+        assert!(status.health.healthy);
+        assert!(status.health.modules.is_empty());
+
+        let mut node_state = NodeStateMock::new_from_bus(shared_bus.new_handle()).await;
+        tokio::spawn(async move {
+            handle_messages! {
+                on_bus node_state,
+                command_response<QueryBlockHeight, BlockHeight> _ => {
+                    Ok(BlockHeight(10))
+                }
+            }
+        });
+        let mut indexer = IndexerMock::new_from_bus(shared_bus.new_handle()).await;
+        tokio::spawn(async move {
+            handle_messages! {
+                on_bus indexer,
+                command_response<QueryIndexerHeight, Option<BlockHeight>> _ => {
+                    Ok(Some(BlockHeight(7)))
+                }
+            }
+        });
+
+        let status = node_status(&mut bus).await;
+        assert!(status.health.healthy);
+        assert_eq!(
+            status.health.modules,
+            BTreeMap::from([
+                ("indexer".to_string(), true),
+                ("node_state".to_string(), true)
+            ])
+        );
+        assert_eq!(status.node_state_height, Some(BlockHeight(10)));
+        assert_eq!(status.indexer_lag, Some(3));
+        assert_eq!(status.consensus_height, None);
+    }
+
+    #[tokio::test]
+    async fn test_unresponsive_module() {
+        let shared_bus = SharedMessageBus::default();
+        let mut bus = HealthBusClient::new_from_bus(shared_bus.new_handle()).await;
+        // Subscribed, but never answers
+        let _node_state = NodeStateMock::new_from_bus(shared_bus.new_handle()).await;
+
+        let status = node_status(&mut bus).await;
+        assert!(!status.health.healthy);
+        assert_eq!(
+            status.health.modules,
+            BTreeMap::from([("node_state".to_string(), false)])
+        );
+        assert_eq!(status_code(&status.health), StatusCode::SERVICE_UNAVAILABLE);
+    }
+}
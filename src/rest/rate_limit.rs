@@ -0,0 +1,244 @@
+//! Per client IP rate limiting of the REST API, with a request budget per route group.
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyle_model::errors::ErrorCode;
+use opentelemetry::{metrics::Counter, InstrumentationScope, KeyValue};
+use tokio::time::Instant;
+
+use super::AppError;
+use crate::utils::conf::{RestRateLimitConf, RouteRateLimit};
+
+/// Most buckets tracked. Once reached, buckets that are full again are forgotten, and new
+/// clients are rejected if none is.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+/// Least time between two sweeps of the full buckets, which go through all of them.
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+#[derive(Debug, Default)]
+struct Buckets {
+    by_client: HashMap<(usize, IpAddr), Bucket>,
+    last_cleanup: Option<Instant>,
+}
+
+/// Token buckets per route group and client IP.
+#[derive(Debug)]
+pub struct RateLimiter {
+    groups: Vec<RouteRateLimit>,
+    buckets: Mutex<Buckets>,
+    rejected_requests: Counter<u64>,
+}
+
+impl RateLimiter {
+    pub fn new(node_name: String, conf: &RestRateLimitConf) -> Self {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        let mut groups: Vec<_> = conf
+            .groups
+            .iter()
+            .filter(|g| g.requests_per_sec > 0)
+            .cloned()
+            .collect();
+        // The most specific prefix wins
+        groups.sort_by_key(|g| std::cmp::Reverse(g.prefix.len()));
+
+        RateLimiter {
+            groups,
+            buckets: Mutex::new(Buckets::default()),
+            rejected_requests: my_meter.u64_counter("rest_rate_limited").build(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.groups.is_empty()
+    }
+
+    /// Takes a token from the bucket of the client for the group of `path`.
+    /// Paths outside any group are not limited.
+    pub fn check(&self, path: &str, ip: IpAddr) -> bool {
+        self.check_at(path, ip, Instant::now())
+    }
+
+    fn check_at(&self, path: &str, ip: IpAddr, now: Instant) -> bool {
+        let Some((index, group)) = self
+            .groups
+            .iter()
+            .enumerate()
+            .find(|(_, g)| path.starts_with(&g.prefix))
+        else {
+            return true;
+        };
+        let burst = f64::from(group.burst.max(1));
+        let rate = f64::from(group.requests_per_sec);
+
+        let mut buckets = match self.buckets.lock() {
+            Ok(buckets) => buckets,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        let buckets = &mut *buckets;
+        if buckets.by_client.len() >= MAX_TRACKED_CLIENTS
+            && !buckets.by_client.contains_key(&(index, ip))
+        {
+            if buckets
+                .last_cleanup
+                .is_none_or(|last| now.duration_since(last) >= CLEANUP_INTERVAL)
+            {
+                buckets.last_cleanup = Some(now);
+                let groups = &self.groups;
+                buckets.by_client.retain(|(index, _), bucket| {
+                    groups.get(*index).is_some_and(|g| {
+                        let refilled = bucket.tokens
+                            + now.duration_since(bucket.last_refill).as_secs_f64()
+                                * f64::from(g.requests_per_sec);
+                        refilled < f64::from(g.burst.max(1))
+                    })
+                });
+            }
+            if buckets.by_client.len() >= MAX_TRACKED_CLIENTS {
+                self.rejected_requests
+                    .add(1, &[KeyValue::new("group", group.prefix.clone())]);
+                return false;
+            }
+        }
+
+        let bucket = buckets.by_client.entry((index, ip)).or_insert(Bucket {
+            tokens: burst,
+            last_refill: now,
+        });
+        bucket.tokens = (bucket.tokens
+            + now.duration_since(bucket.last_refill).as_secs_f64() * rate)
+            .min(burst);
+        bucket.last_refill = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            true
+        } else {
+            self.rejected_requests
+                .add(1, &[KeyValue::new("group", group.prefix.clone())]);
+            false
+        }
+    }
+}
+
+/// Rejects requests of clients that exhausted the budget of the route group.
+pub async fn rate_limit(
+    State(limiter): State<Arc<RateLimiter>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let ip = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Some(ip) = ip {
+        if !limiter.check(req.uri().path(), ip) {
+            return AppError::with_code(
+                ErrorCode::TooManyRequests,
+                format!("Too many requests from {}", ip),
+            )
+            .into_response();
+        }
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn limiter() -> RateLimiter {
+        RateLimiter::new(
+            "test".to_string(),
+            &RestRateLimitConf {
+                groups: vec![
+                    RouteRateLimit {
+                        prefix: "/v1/indexer/".to_string(),
+                        requests_per_sec: 2,
+                        burst: 3,
+                    },
+                    RouteRateLimit {
+                        prefix: "/v1/indexer/contract/".to_string(),
+                        requests_per_sec: 1,
+                        burst: 1,
+                    },
+                ],
+            },
+        )
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = limiter();
+        let ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(5, 6, 7, 8));
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.check_at("/v1/indexer/blocks", ip, now));
+        }
+        assert!(!limiter.check_at("/v1/indexer/blocks", ip, now));
+        // Other clients and routes outside any group are not affected
+        assert!(limiter.check_at("/v1/indexer/blocks", other_ip, now));
+        assert!(limiter.check_at("/v1/info", ip, now));
+
+        // 2 requests per second
+        let later = now + Duration::from_millis(500);
+        assert!(limiter.check_at("/v1/indexer/blocks", ip, later));
+        assert!(!limiter.check_at("/v1/indexer/blocks", ip, later));
+    }
+
+    #[test]
+    fn test_tracked_clients_bound() {
+        let limiter = limiter();
+        let now = Instant::now();
+        let ip = |i: usize| IpAddr::V4(Ipv4Addr::from(u32::try_from(i).unwrap()));
+
+        for i in 0..MAX_TRACKED_CLIENTS {
+            assert!(limiter.check_at("/v1/indexer/blocks", ip(i), now));
+        }
+        // No bucket is full again: new clients are rejected, known ones still served
+        assert!(!limiter.check_at("/v1/indexer/blocks", ip(MAX_TRACKED_CLIENTS), now));
+
+        // Buckets refilled by then are only forgotten on the next sweep
+        let later = now + Duration::from_millis(600);
+        assert!(!limiter.check_at("/v1/indexer/blocks", ip(MAX_TRACKED_CLIENTS), later));
+        assert!(limiter.check_at("/v1/indexer/blocks", ip(0), later));
+        let later = now + CLEANUP_INTERVAL;
+        assert!(limiter.check_at("/v1/indexer/blocks", ip(MAX_TRACKED_CLIENTS), later));
+        assert_eq!(limiter.buckets.lock().unwrap().by_client.len(), 2);
+    }
+
+    #[test]
+    fn test_most_specific_group() {
+        let limiter = limiter();
+        let ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let now = Instant::now();
+
+        assert!(limiter.check_at("/v1/indexer/contract/c1", ip, now));
+        assert!(!limiter.check_at("/v1/indexer/contract/c1", ip, now));
+        // The contract routes have their own budget
+        assert!(limiter.check_at("/v1/indexer/blocks", ip, now));
+    }
+}
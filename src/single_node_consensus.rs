@@ -2,7 +2,9 @@ use std::path::PathBuf;
 
 use crate::bus::command_response::{CmdRespClient, Query};
 use crate::bus::BusClientSender;
-use crate::consensus::{CommittedConsensusProposal, ConsensusEvent, QueryConsensusInfo};
+use crate::consensus::{
+    is_empty_cut, CommittedConsensusProposal, ConsensusEvent, QueryConsensusInfo,
+};
 use crate::data_availability::DataEvent;
 use crate::genesis::GenesisEvent;
 use crate::mempool::QueryNewCut;
@@ -42,6 +44,8 @@ pub struct SingleNodeConsensus {
     config: SharedConf,
     store: SingleNodeConsensusStore,
     file: Option<PathBuf>,
+    /// Timestamp of the last block produced since startup, in milliseconds
+    last_block_timestamp: u64,
 }
 
 /// The `SingleNodeConsensus` module listens to and sends the same messages as the `Consensus` module.
@@ -78,6 +82,7 @@ impl Module for SingleNodeConsensus {
             config: ctx.common.config.clone(),
             store,
             file: Some(file),
+            last_block_timestamp: 0,
         })
     }
 
@@ -155,6 +160,7 @@ impl SingleNodeConsensus {
     }
     async fn handle_new_slot_tick(&mut self) -> Result<()> {
         debug!("New slot tick");
+        let timestamp = get_current_timestamp_ms();
         // Query a new cut to Mempool in order to create a new CommitCut
         match self
             .bus
@@ -162,6 +168,14 @@ impl SingleNodeConsensus {
             .await
         {
             Ok(cut) => {
+                let interval = self.config.consensus.empty_block_interval;
+                if interval > 0
+                    && is_empty_cut(&cut, &self.store.last_cut)
+                    && timestamp < self.last_block_timestamp + interval
+                {
+                    debug!("Nothing new to commit, skipping slot");
+                    return Ok(());
+                }
                 self.store.last_cut = cut.clone();
             }
             Err(err) => {
@@ -173,11 +187,13 @@ impl SingleNodeConsensus {
         let consensus_proposal = ConsensusProposal {
             slot: new_slot,
             view: 0,
-            timestamp: get_current_timestamp_ms(),
+            timestamp,
             round_leader: self.crypto.validator_pubkey().clone(),
             cut: self.store.last_cut.clone(),
             staking_actions: vec![],
             parent_hash: std::mem::take(&mut self.store.last_consensus_proposal_hash),
+            production_reason: Default::default(),
+            epoch: None,
         };
 
         self.store.last_consensus_proposal_hash = consensus_proposal.hash();
@@ -196,6 +212,7 @@ impl SingleNodeConsensus {
         ))?;
 
         self.store.last_slot = new_slot;
+        self.last_block_timestamp = timestamp;
 
         Ok(())
     }
@@ -227,9 +244,13 @@ mod tests {
 
     impl TestContext {
         pub async fn new(name: &str) -> Self {
+            Self::with_conf(name, Conf::default()).await
+        }
+
+        pub async fn with_conf(name: &str, conf: Conf) -> Self {
             let crypto = BlstCrypto::new(name.into()).unwrap();
             let shared_bus = SharedMessageBus::new(BusMetrics::global("global".to_string()));
-            let conf = Arc::new(Conf::default());
+            let conf = Arc::new(conf);
             let store = SingleNodeConsensusStore::default();
 
             let consensus_event_receiver = get_receiver::<ConsensusEvent>(&shared_bus).await;
@@ -242,6 +263,7 @@ mod tests {
                 config: conf,
                 store,
                 file: None,
+                last_block_timestamp: 0,
             };
 
             let mut new_cut_query_receiver = TestBusClient::new_from_bus(shared_bus).await;
@@ -273,6 +295,7 @@ mod tests {
                     consensus_proposal,
                     ..
                 }) => consensus_proposal.cut,
+                other => panic!("{err}: unexpected {other:?}"),
             }
         }
     }
@@ -288,4 +311,21 @@ mod tests {
 
         Ok(())
     }
+
+    #[test_log::test(tokio::test)]
+    async fn test_skips_empty_blocks() -> Result<()> {
+        let mut conf = Conf::default();
+        conf.consensus.empty_block_interval = 60_000;
+        let mut ctx = TestContext::with_conf("single_node_consensus", conf).await;
+
+        ctx.single_node_consensus.handle_new_slot_tick().await?;
+        ctx.assert_commit_cut("CommitCut");
+
+        // Mempool returns the same cut again
+        ctx.single_node_consensus.handle_new_slot_tick().await?;
+        assert!(ctx.consensus_event_receiver.try_recv().is_err());
+        assert_eq!(ctx.single_node_consensus.store.last_slot, 1);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,125 @@
+//! Helpers to run a node in-process from integration tests, along with transaction fixtures.
+//!
+//! Enable the `testkit` feature to use them from another crate, e.g. to test a contract against
+//! a single node:
+//!
+//! ```ignore
+//! let mut node = NodeIntegrationCtxBuilder::new().await.in_memory().build().await?;
+//! node.wait_for_processed_genesis().await?;
+//! ```
+
+use hyle_contract_sdk::{flatten_blobs, BlobIndex, HyleOutput, TxHash};
+
+use crate::model::{
+    AggregateSignature, Blob, BlobData, BlobProofOutput, BlobTransaction, ConsensusProposal,
+    ContractAction, ContractName, DataProposal, Hashable, ProgramId, ProofData,
+    RegisterContractAction, SignedBlock, StateDigest, Transaction, ValidatorPublicKey,
+    VerifiedProofTransaction,
+};
+
+pub use crate::data_availability::testkit::DataAvailabilityTestCtx;
+pub use crate::utils::integration_test::{
+    find_available_port, NodeIntegrationCtx, NodeIntegrationCtxBuilder,
+};
+
+/// Registers a contract using the `test` verifier, which accepts any proof.
+pub fn make_register_contract_tx(name: ContractName) -> BlobTransaction {
+    BlobTransaction {
+        identity: "hyle.hyle".into(),
+        blobs: vec![RegisterContractAction {
+            verifier: "test".into(),
+            program_id: ProgramId(vec![]),
+            state_digest: StateDigest(vec![0, 1, 2, 3]),
+            contract_name: name,
+        }
+        .as_blob("hyle".into(), None, None)],
+    }
+}
+
+/// A blob transaction from the identity, with an empty blob for contract `c1`.
+pub fn make_blob_tx(identity: &str) -> Transaction {
+    make_sized_blob_tx(identity, "c1", 0)
+}
+
+/// A blob transaction from the identity, with a blob of `size` bytes for the contract.
+pub fn make_sized_blob_tx(identity: &str, contract_name: &str, size: usize) -> Transaction {
+    BlobTransaction {
+        identity: identity.into(),
+        blobs: vec![Blob {
+            contract_name: contract_name.into(),
+            data: BlobData(vec![0; size]),
+        }],
+    }
+    .into()
+}
+
+/// A proof for the `test` verifier, carrying the given output.
+pub fn new_proof_tx(
+    contract: &ContractName,
+    hyle_output: &HyleOutput,
+    blob_tx_hash: &TxHash,
+) -> VerifiedProofTransaction {
+    let proof = ProofData(
+        bincode::encode_to_vec(vec![hyle_output.clone()], bincode::config::standard())
+            .unwrap_or_default(),
+    );
+    VerifiedProofTransaction {
+        contract_name: contract.clone(),
+        proven_blobs: vec![BlobProofOutput {
+            hyle_output: hyle_output.clone(),
+            program_id: ProgramId(vec![]),
+            blob_tx_hash: blob_tx_hash.clone(),
+            original_proof_hash: proof.hash(),
+        }],
+        proof_hash: proof.hash(),
+        proof_size: proof.0.len(),
+        proof: Some(proof),
+        is_recursive: false,
+    }
+}
+
+/// A successful output for the blob, moving the contract from `[0, 1, 2, 3]` to `[4, 5, 6]`.
+pub fn make_hyle_output(blob_tx: BlobTransaction, blob_index: BlobIndex) -> HyleOutput {
+    make_hyle_output_with_state(blob_tx, blob_index, &[0, 1, 2, 3], &[4, 5, 6])
+}
+
+pub fn make_hyle_output_with_state(
+    blob_tx: BlobTransaction,
+    blob_index: BlobIndex,
+    initial_state: &[u8],
+    next_state: &[u8],
+) -> HyleOutput {
+    HyleOutput {
+        version: 1,
+        identity: blob_tx.identity.clone(),
+        index: blob_index,
+        blobs: flatten_blobs(&blob_tx.blobs),
+        initial_state: StateDigest(initial_state.to_vec()),
+        next_state: StateDigest(next_state.to_vec()),
+        success: true,
+        tx_hash: blob_tx.hash(),
+        tx_ctx: None,
+        registered_contracts: vec![],
+        program_outputs: vec![],
+    }
+}
+
+/// A block at the given slot, with a single data proposal holding the transactions.
+/// It isn't signed, so the node must not verify certificates.
+pub fn craft_signed_block(height: u64, txs: Vec<Transaction>) -> SignedBlock {
+    SignedBlock {
+        certificate: AggregateSignature::default(),
+        consensus_proposal: ConsensusProposal {
+            slot: height,
+            ..ConsensusProposal::default()
+        },
+        data_proposals: vec![(
+            ValidatorPublicKey::default(),
+            vec![DataProposal {
+                id: 1,
+                parent_data_proposal_hash: None,
+                txs,
+            }],
+        )],
+    }
+}
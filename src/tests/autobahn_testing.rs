@@ -859,7 +859,7 @@ async fn autobahn_rejoin_flow() {
         .setup_for_joining(&[&node1.consensus_ctx, &node2.consensus_ctx]);
 
     // Let's setup a DataAvailability on this bus
-    let mut da = crate::data_availability::tests::DataAvailabilityTestCtx::new(
+    let mut da = crate::data_availability::testkit::DataAvailabilityTestCtx::new(
         joining_node.shared_bus.new_handle(),
     )
     .await;
@@ -11,8 +11,7 @@ use tracing::info;
 
 use crate::{
     model::{Blob, BlobData},
-    node_state::test::make_hyle_output_with_state,
-    utils::integration_test::NodeIntegrationCtxBuilder,
+    testkit::{make_hyle_output_with_state, NodeIntegrationCtxBuilder},
 };
 
 use anyhow::Result;
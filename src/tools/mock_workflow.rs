@@ -165,7 +165,7 @@ impl MockWorkflowHandler {
         let tx_register_blob = BlobTransaction {
             identity: Identity::new("id"),
             blobs: vec![RegisterContractAction {
-                verifier: "verifier".into(),
+                verifier: "test".into(),
                 program_id: ProgramId(vec![]),
                 state_digest: StateDigest(vec![]),
                 contract_name: ContractName::new("contract"),
@@ -1,8 +1,8 @@
 #![allow(dead_code, unused_variables)]
 
-use std::sync::Arc;
+use std::{io::Write, path::Path, sync::Arc};
 
-use anyhow::{anyhow, bail, Error, Result};
+use anyhow::{anyhow, bail, Context, Error, Result};
 use blst::min_pk::{
     AggregatePublicKey, AggregateSignature as BlstAggregateSignature, PublicKey, SecretKey,
     Signature as BlstSignature,
@@ -19,6 +19,15 @@ pub struct BlstCrypto {
 }
 pub type SharedBlstCrypto = Arc<BlstCrypto>;
 
+impl std::fmt::Debug for BlstCrypto {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Never print the secret key
+        f.debug_struct("BlstCrypto")
+            .field("validator_pubkey", &self.validator_pubkey)
+            .finish_non_exhaustive()
+    }
+}
+
 #[derive(Default)]
 struct Aggregates {
     sigs: Vec<BlstSignature>,
@@ -57,6 +66,43 @@ impl BlstCrypto {
         Self::new(id.as_str().into())
     }
 
+    /// Loads the secret key stored in `path`, or generates a random one and stores it there.
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            let hex_sk = std::fs::read_to_string(path)
+                .with_context(|| format!("Reading validator key {}", path.display()))?;
+            let bytes = hex::decode(hex_sk.trim()).context("Decoding validator key")?;
+            let sk = SecretKey::from_bytes(&bytes)
+                .map_err(|e| anyhow!("Invalid validator key in {}: {:?}", path.display(), e))?;
+            return Ok(Self::from_secret_key(sk));
+        }
+
+        let mut ikm = [0u8; 32];
+        rand::rng().fill(&mut ikm);
+        let sk = SecretKey::key_gen(&ikm, &[])
+            .map_err(|e| anyhow!("Could not generate key: {:?}", e))?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Creating validator key {}", path.display()))?;
+        file.write_all(hex::encode(sk.to_bytes()).as_bytes())?;
+        Ok(Self::from_secret_key(sk))
+    }
+
+    fn from_secret_key(sk: SecretKey) -> Self {
+        let validator_pubkey = as_validator_pubkey(sk.sk_to_pk());
+        BlstCrypto {
+            sk,
+            validator_pubkey,
+        }
+    }
+
     pub fn validator_pubkey(&self) -> &ValidatorPublicKey {
         &self.validator_pubkey
     }
@@ -228,12 +274,35 @@ fn as_validator_pubkey(pk: PublicKey) -> ValidatorPublicKey {
     ValidatorPublicKey(pk.compress().as_slice().to_vec())
 }
 
+/// Compares secrets (API keys, tokens…) in a time independent of where they differ.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
 #[cfg(test)]
 mod tests {
 
     use crate::p2p::network::HandshakeNetMessage;
 
     use super::*;
+    #[test]
+    fn test_load_or_generate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keys").join("validator.key");
+        let crypto = BlstCrypto::load_or_generate(&path).unwrap();
+        let loaded = BlstCrypto::load_or_generate(&path).unwrap();
+        assert_eq!(crypto.validator_pubkey(), loaded.validator_pubkey());
+        assert_ne!(
+            crypto.validator_pubkey(),
+            BlstCrypto::load_or_generate(&dir.path().join("other.key"))
+                .unwrap()
+                .validator_pubkey()
+        );
+
+        std::fs::write(&path, "not a key").unwrap();
+        assert!(BlstCrypto::load_or_generate(&path).is_err());
+    }
+
     #[test]
     fn test_sign_bytes() {
         let crypto = BlstCrypto::new_random().unwrap();
@@ -247,7 +316,7 @@ mod tests {
     fn test_sign() {
         let crypto = BlstCrypto::new_random().unwrap();
         let pub_key = ValidatorPublicKey(crypto.sk.sk_to_pk().to_bytes().as_slice().to_vec());
-        let msg = HandshakeNetMessage::Ping;
+        let msg = HandshakeNetMessage::Ping(0);
         let signed = crypto.sign(&msg).unwrap();
         let valid = BlstCrypto::verify(&signed).unwrap();
         assert!(valid);
@@ -263,15 +332,15 @@ mod tests {
 
     #[test]
     fn test_sign_aggregate() {
-        let (s1, pk1) = new_signed(HandshakeNetMessage::Ping);
-        let (s2, pk2) = new_signed(HandshakeNetMessage::Ping);
-        let (s3, pk3) = new_signed(HandshakeNetMessage::Ping);
-        let (_, pk4) = new_signed(HandshakeNetMessage::Ping);
+        let (s1, pk1) = new_signed(HandshakeNetMessage::Ping(0));
+        let (s2, pk2) = new_signed(HandshakeNetMessage::Ping(0));
+        let (s3, pk3) = new_signed(HandshakeNetMessage::Ping(0));
+        let (_, pk4) = new_signed(HandshakeNetMessage::Ping(0));
 
         let crypto = BlstCrypto::new_random().unwrap();
         let aggregates = vec![&s1, &s2, &s3];
         let mut signed = crypto
-            .sign_aggregate(HandshakeNetMessage::Ping, aggregates.as_slice())
+            .sign_aggregate(HandshakeNetMessage::Ping(0), aggregates.as_slice())
             .unwrap();
 
         assert_eq!(
@@ -316,13 +385,16 @@ mod tests {
 
     #[test]
     fn test_sign_aggregate_wrong_message() {
-        let (s1, pk1) = new_signed(HandshakeNetMessage::Ping);
-        let (s2, pk2) = new_signed(HandshakeNetMessage::Ping);
-        let (s3, pk3) = new_signed(HandshakeNetMessage::Pong); // different message
+        let (s1, pk1) = new_signed(HandshakeNetMessage::Ping(0));
+        let (s2, pk2) = new_signed(HandshakeNetMessage::Ping(0));
+        let (s3, pk3) = new_signed(HandshakeNetMessage::Pong {
+            ping_timestamp: 0,
+            pong_timestamp: 0,
+        }); // different message
 
         let crypto = BlstCrypto::new_random().unwrap();
         let aggregates = vec![&s1, &s2, &s3];
-        let signed = crypto.sign_aggregate(HandshakeNetMessage::Ping, aggregates.as_slice());
+        let signed = crypto.sign_aggregate(HandshakeNetMessage::Ping(0), aggregates.as_slice());
 
         assert!(signed.is_err_and(|e| {
             e.to_string()
@@ -332,15 +404,15 @@ mod tests {
 
     #[test]
     fn test_sign_aggregate_overlap() {
-        let (s1, pk1) = new_signed(HandshakeNetMessage::Ping);
-        let (s2, pk2) = new_signed(HandshakeNetMessage::Ping);
-        let (s3, pk3) = new_signed(HandshakeNetMessage::Ping);
-        let (s4, pk4) = new_signed(HandshakeNetMessage::Ping);
+        let (s1, pk1) = new_signed(HandshakeNetMessage::Ping(0));
+        let (s2, pk2) = new_signed(HandshakeNetMessage::Ping(0));
+        let (s3, pk3) = new_signed(HandshakeNetMessage::Ping(0));
+        let (s4, pk4) = new_signed(HandshakeNetMessage::Ping(0));
 
         let crypto = BlstCrypto::new_random().unwrap();
         let aggregates = vec![&s1, &s2, &s3, &s2, &s3, &s4];
         let signed = crypto
-            .sign_aggregate(HandshakeNetMessage::Ping, aggregates.as_slice())
+            .sign_aggregate(HandshakeNetMessage::Ping(0), aggregates.as_slice())
             .unwrap();
         assert!(BlstCrypto::verify_aggregate(&signed).unwrap());
 
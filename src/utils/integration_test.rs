@@ -1,4 +1,9 @@
-#![cfg(test)]
+#![cfg(any(test, feature = "testkit"))]
+#![allow(
+    clippy::unwrap_used,
+    clippy::expect_used,
+    reason = "Test harness, failing to set up the node should fail the test"
+)]
 
 use std::any::TypeId;
 use std::collections::HashMap;
@@ -26,7 +31,7 @@ use crate::p2p::P2P;
 use crate::rest::{RestApi, RestApiRunContext};
 use crate::single_node_consensus::SingleNodeConsensus;
 use crate::tcp_server::TcpServer;
-use crate::utils::conf::Conf;
+use crate::utils::conf::{Conf, DaStorage};
 use crate::utils::crypto::BlstCrypto;
 use crate::utils::modules::ModulesHandler;
 
@@ -128,6 +133,12 @@ impl NodeIntegrationCtxBuilder {
         self.with_mock::<T, MockModule<T>>()
     }
 
+    /// Keeps the blocks in memory instead of the data directory.
+    pub fn in_memory(mut self) -> Self {
+        self.conf.da_storage = DaStorage::Memory;
+        self
+    }
+
     pub async fn build(self) -> Result<NodeIntegrationCtx> {
         let conf = Arc::new(self.conf);
         let mut node_modules = NodeIntegrationCtx::start_node(
@@ -297,6 +308,10 @@ impl NodeIntegrationCtx {
             &mut handler,
             &ctx,
             RestApiRunContext {
+                auth: ctx.common.config.rest_auth.clone(),
+                cors: ctx.common.config.rest_cors.clone(),
+                rate_limit: ctx.common.config.rest_rate_limit.clone(),
+                config: ctx.common.config.clone(),
                 rest_addr: ctx.common.config.rest.clone(),
                 max_body_size: ctx.common.config.rest_max_body_size,
                 info: NodeInfo {
@@ -339,21 +354,25 @@ impl NodeIntegrationCtx {
         Ok(())
     }
     pub async fn wait_for_processed_genesis(&mut self) -> Result<()> {
-        let _: NodeStateEvent = self.bus_client.recv().await?;
-        Ok(())
+        self.wait_for_n_blocks(1).await
     }
     pub async fn wait_for_n_blocks(&mut self, n: u32) -> Result<()> {
-        for _ in 0..n {
-            let _: NodeStateEvent = self.bus_client.recv().await?;
+        let mut blocks = 0;
+        while blocks < n {
+            let event: NodeStateEvent = self.bus_client.recv().await?;
+            if let NodeStateEvent::NewBlock(_) = event {
+                blocks += 1;
+            }
         }
         Ok(())
     }
     pub async fn wait_for_settled_tx(&mut self, tx: TxHash) -> Result<()> {
         loop {
             let event: NodeStateEvent = self.bus_client.recv().await?;
-            let NodeStateEvent::NewBlock(block) = event;
-            if block.successful_txs.iter().any(|h| h == &tx) {
-                break;
+            if let NodeStateEvent::TxSettled { tx_hash, .. } = event {
+                if tx_hash == tx {
+                    break;
+                }
             }
         }
         Ok(())
@@ -1,12 +1,24 @@
-use anyhow::Result;
-use std::fmt::Display;
+use anyhow::{Context, Result};
+use opentelemetry::{trace::TracerProvider as _, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{
+    runtime,
+    trace::{Tracer, TracerProvider},
+    Resource,
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    sync::{Mutex, OnceLock},
+};
 use tracing::{error, warn};
 use tracing::{level_filters::LevelFilter, Subscriber};
 use tracing_subscriber::{
+    filter::{Directive, Targets},
     fmt::{format, FormatEvent, FormatFields},
     prelude::*,
     registry::LookupSpan,
-    EnvFilter,
+    reload, EnvFilter, Registry,
 };
 
 // A simple way to log without interrupting fluency
@@ -77,9 +89,98 @@ pub enum TracingMode {
     NodeName,
 }
 
+/// Handle on the filter of the stdout subscriber, to change the module levels at runtime
+static LOG_FILTER: OnceLock<reload::Handle<EnvFilter, Registry>> = OnceLock::new();
+/// Module levels currently applied over RUST_LOG
+static LOG_MODULES: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
 /// Setup tracing - stdout subscriber
 /// stdout defaults to INFO to INFO even if RUST_LOG is set to e.g. debug
-pub fn setup_tracing(mode: TracingMode, node_name: String) -> Result<()> {
+/// `log_modules` are levels by module (e.g. "data_availability" => "debug") overriding RUST_LOG,
+/// they can be changed afterwards with [set_log_modules].
+/// If `otlp_endpoint` is set, the spans of the node are also exported there over OTLP (gRPC),
+/// e.g. to Jaeger or Tempo.
+pub fn setup_tracing(
+    mode: TracingMode,
+    node_name: String,
+    otlp_endpoint: Option<String>,
+    log_modules: &BTreeMap<String, String>,
+) -> Result<()> {
+    let filter = build_filter(log_modules)?;
+    *log_modules_guard() = log_modules.clone();
+
+    let tracer = otlp_endpoint
+        .map(|endpoint| otlp_tracer(endpoint, node_name.clone()))
+        .transpose()?;
+
+    // Can't use match inline because these are different return types
+    match mode {
+        TracingMode::Full => {
+            register_global_subscriber(filter, tracing_subscriber::fmt::layer(), tracer)
+        }
+        TracingMode::Json => register_global_subscriber(
+            filter,
+            tracing_subscriber::fmt::layer().event_format(tracing_subscriber::fmt::format().json()),
+            tracer,
+        ),
+        TracingMode::NodeName => register_global_subscriber(
+            filter,
+            tracing_subscriber::fmt::layer().event_format(NodeNameFormatter {
+                node_name,
+                base_formatter: tracing_subscriber::fmt::format(),
+            }),
+            tracer,
+        ),
+    };
+
+    Ok(())
+}
+
+/// Module levels currently applied over RUST_LOG.
+pub fn log_modules() -> BTreeMap<String, String> {
+    log_modules_guard().clone()
+}
+
+/// Replaces the module levels applied over RUST_LOG, without restarting the node.
+pub fn set_log_modules(log_modules: BTreeMap<String, String>) -> Result<()> {
+    let handle = LOG_FILTER
+        .get()
+        .context("Tracing was not set up with a reloadable filter")?;
+    let filter = build_filter(&log_modules)?;
+    handle.reload(filter).context("Reloading log filter")?;
+    *log_modules_guard() = log_modules;
+    Ok(())
+}
+
+fn log_modules_guard() -> std::sync::MutexGuard<'static, BTreeMap<String, String>> {
+    match LOG_MODULES.lock() {
+        Ok(guard) => guard,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Directives of the module levels. Modules of the node can be given without the crate name,
+/// e.g. "data_availability" for "hyle::data_availability".
+fn module_directives(log_modules: &BTreeMap<String, String>) -> Result<Vec<Directive>> {
+    log_modules
+        .iter()
+        .map(|(module, level)| {
+            let level: LevelFilter = level
+                .parse()
+                .context(format!("Invalid log level {level} for module {module}"))?;
+            let target = if module.contains("::") || module == "hyle" {
+                module.clone()
+            } else {
+                format!("hyle::{module}")
+            };
+            format!("{target}={level}")
+                .parse()
+                .context(format!("Invalid log module {module}"))
+        })
+        .collect()
+}
+
+fn build_filter(log_modules: &BTreeMap<String, String>) -> Result<EnvFilter> {
     let mut filter = EnvFilter::builder()
         .with_default_directive(LevelFilter::INFO.into())
         .from_env()?;
@@ -106,34 +207,77 @@ pub fn setup_tracing(mode: TracingMode, node_name: String) -> Result<()> {
         );
         filter = filter.add_directive("risc0_zkvm=warn".parse()?);
     }
+    for directive in module_directives(log_modules)? {
+        filter = filter.add_directive(directive);
+    }
+    Ok(filter)
+}
 
-    // Can't use match inline because these are different return types
-    match mode {
-        TracingMode::Full => register_global_subscriber(filter, tracing_subscriber::fmt::layer()),
-        TracingMode::Json => register_global_subscriber(
-            filter,
-            tracing_subscriber::fmt::layer().event_format(tracing_subscriber::fmt::format().json()),
-        ),
-        TracingMode::NodeName => register_global_subscriber(
-            filter,
-            tracing_subscriber::fmt::layer().event_format(NodeNameFormatter {
-                node_name,
-                base_formatter: tracing_subscriber::fmt::format(),
-            }),
-        ),
-    };
+/// Flushes the spans not exported yet, to call before exiting.
+pub fn shutdown_tracing() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
 
-    Ok(())
+fn otlp_tracer(endpoint: String, node_name: String) -> Result<Tracer> {
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_tonic()
+        .with_endpoint(endpoint)
+        .build()
+        .context("Building OTLP span exporter")?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, runtime::Tokio)
+        .with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            node_name,
+        )]))
+        .build();
+    let tracer = provider.tracer("hyle");
+    opentelemetry::global::set_tracer_provider(provider);
+    Ok(tracer)
 }
 
-fn register_global_subscriber<T, S>(filter: EnvFilter, fmt_layer: T)
+fn register_global_subscriber<T>(filter: EnvFilter, fmt_layer: T, tracer: Option<Tracer>)
 where
-    S: Subscriber,
-    T: tracing_subscriber::Layer<S> + Send + Sync,
-    tracing_subscriber::filter::Filtered<T, tracing_subscriber::EnvFilter, S>:
-        tracing_subscriber::Layer<tracing_subscriber::Registry>,
+    T: tracing_subscriber::Layer<Registry> + Send + Sync,
 {
+    let (filter, handle) = reload::Layer::new(filter);
+    _ = LOG_FILTER.set(handle);
+    // Only the spans of the node are exported, not the ones of its dependencies
+    let otlp_layer = tracer.map(|tracer| {
+        tracing_opentelemetry::layer()
+            .with_tracer(tracer)
+            .with_filter(Targets::new().with_target("hyle", LevelFilter::INFO))
+    });
     tracing_subscriber::registry()
         .with(fmt_layer.with_filter(filter))
+        .with(otlp_layer)
         .init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_module_directives() {
+        let modules = BTreeMap::from([
+            ("data_availability".to_string(), "debug".to_string()),
+            ("hyle_verifiers::risc0".to_string(), "WARN".to_string()),
+        ]);
+        let directives: Vec<String> = module_directives(&modules)
+            .unwrap()
+            .iter()
+            .map(|d| d.to_string())
+            .collect();
+        assert_eq!(
+            directives,
+            vec![
+                "hyle::data_availability=debug",
+                "hyle_verifiers::risc0=warn"
+            ]
+        );
+
+        let invalid = BTreeMap::from([("mempool".to_string(), "verbose".to_string())]);
+        assert!(module_directives(&invalid).is_err());
+    }
+}
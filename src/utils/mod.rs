@@ -4,5 +4,7 @@ pub mod crypto;
 pub mod integration_test;
 pub mod logger;
 pub mod modules;
+pub mod noise;
 pub mod serde;
 pub mod static_type_map;
+pub mod ws_limits;
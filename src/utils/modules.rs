@@ -1,24 +1,31 @@
 use std::{
     any::type_name,
-    fs,
+    collections::{HashMap, HashSet},
     future::Future,
-    io::{BufWriter, Write},
     path::Path,
     pin::Pin,
-    time::Duration,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    bus::{bus_client, BusClientSender, SharedMessageBus},
+    bus::{bus_client, command_response::Query, BusClientSender, SharedMessageBus},
     genesis::Genesis,
     handle_messages,
-    utils::logger::LogMe,
+    utils::{
+        conf::{Conf, RestartMode, RestartPolicy},
+        logger::LogMe,
+    },
 };
 use anyhow::{Context, Error, Result};
-use rand::{distr::Alphanumeric, Rng};
+use hyle_model::api::{APIModuleState, APIModuleStatus};
+use opentelemetry::{metrics::Counter, InstrumentationScope, KeyValue};
 use signal::ShutdownCompleted;
 use tokio::task::JoinHandle;
-use tracing::{debug, info};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, warn};
+
+mod snapshot;
 
 /// Module trait to define startup dependencies
 pub trait Module
@@ -30,18 +37,44 @@ where
     fn build(ctx: Self::Context) -> impl futures::Future<Output = Result<Self>> + Send;
     fn run(&mut self) -> impl futures::Future<Output = Result<()>> + Send;
 
+    /// Modules that must be ready before this one starts, by type name.
+    /// Dependencies that are not run by the [ModulesHandler] are ignored.
+    fn dependencies() -> Vec<&'static str> {
+        vec![]
+    }
+
+    /// Modules whose messages this one handles, by type name. They are shut down right before
+    /// it, so that it processes what they sent while stopping instead of losing it.
+    fn drains() -> Vec<&'static str> {
+        vec![]
+    }
+
+    /// Called before each `run`. Modules depending on this one are started once it returns,
+    /// so this is where to bind sockets or open storage they expect.
+    fn ready(&mut self) -> impl futures::Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Called once `run` has returned, whether it failed or was asked to shut down.
+    /// Modules flush their storage and close their connections here.
+    fn on_shutdown(&mut self) -> impl futures::Future<Output = Result<()>> + Send {
+        async { Ok(()) }
+    }
+
+    /// Loads the last snapshot saved with [Module::save_on_disk], falling back on the
+    /// previous ones if it can't be decoded.
     fn load_from_disk<S>(file: &Path) -> Option<S>
     where
         S: bincode::Decode,
     {
-        match fs::File::open(file) {
-            Ok(mut reader) => {
+        match snapshot::read(file) {
+            Some(store) => {
                 info!("Loaded data from disk {}", file.to_string_lossy());
-                bincode::decode_from_std_read(&mut reader, bincode::config::standard())
+                store
                     .log_error(format!("Loading and decoding {}", file.to_string_lossy()))
                     .ok()
             }
-            Err(_) => {
+            None => {
                 info!(
                     "File {} not found for module {} (using default)",
                     file.to_string_lossy(),
@@ -59,41 +92,243 @@ where
         Self::load_from_disk(file).unwrap_or(S::default())
     }
 
+    /// Saves a snapshot of `store`, unless a more recent one was saved concurrently.
+    /// The previous few snapshots are kept as backups.
     fn save_on_disk<S>(file: &Path, store: &S) -> Result<()>
     where
         S: bincode::Encode,
     {
-        // TODO/FIXME: Concurrent writes can happen, and an older state can override a newer one
-        // Example:
-        // State 1 starts creating a tmp file data.state1.tmp
-        // State 2 starts creating a tmp file data.state2.tmp
-        // rename data.state2.tmp into store (atomic override)
-        // renemae data.state1.tmp into
-        let salt: String = rand::rng()
-            .sample_iter(&Alphanumeric)
-            .take(8)
-            .map(char::from)
-            .collect();
-        let tmp = file.with_extension(format!("{}.tmp", salt));
-        debug!("Saving on disk in a tmp file {:?}", tmp.clone());
-        let mut buf_writer =
-            BufWriter::new(fs::File::create(tmp.as_path()).log_error("Create file")?);
-        bincode::encode_into_std_write(store, &mut buf_writer, bincode::config::standard())
-            .log_error("Serializing Ctx chain")?;
-
-        buf_writer.flush().log_error(format!(
-            "Flushing Buffer writer for store {}",
-            type_name::<S>()
-        ))?;
-        debug!("Renaming {:?} to {:?}", &tmp, &file);
-        fs::rename(tmp, file).log_error("Rename file")?;
+        let sequence = snapshot::next_sequence(file);
+        snapshot::write(file, store, sequence).with_context(|| {
+            format!(
+                "Saving snapshot of store {} in {}",
+                type_name::<S>(),
+                file.to_string_lossy()
+            )
+        })?;
         Ok(())
     }
 }
 
+/// How long a module may take to get ready before the modules depending on it are given up.
+const MODULE_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+type ModuleFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>;
+
 struct ModuleStarter {
     pub name: &'static str,
-    starter: Pin<Box<dyn Future<Output = Result<(), Error>> + Send + 'static>>,
+    dependencies: Vec<&'static str>,
+    drains: Vec<&'static str>,
+    starter: Box<dyn FnOnce(ModuleSupervisor) -> ModuleFuture + Send + 'static>,
+}
+
+/// Lists the modules run by the [ModulesHandler], with their state.
+#[derive(Clone)]
+pub struct QueryModulesStatus;
+
+struct ModuleStatus {
+    name: &'static str,
+    state: APIModuleState,
+    started_at: Option<Instant>,
+    restarts: u32,
+    last_error: Option<String>,
+}
+
+impl From<&ModuleStatus> for APIModuleStatus {
+    fn from(status: &ModuleStatus) -> Self {
+        APIModuleStatus {
+            name: status.name.to_string(),
+            state: status.state,
+            uptime: status.started_at.map(|at| at.elapsed().as_secs()),
+            restarts: status.restarts,
+            last_error: status.last_error.clone(),
+        }
+    }
+}
+
+/// Status of every started module, in start order.
+type ModuleStatuses = Arc<Mutex<Vec<ModuleStatus>>>;
+
+/// Announces when a module is ready, and restarts it once it exits according to its policy.
+struct ModuleSupervisor {
+    policy: RestartPolicy,
+    bus: ModuleSupervisorClient,
+    /// Cancelled once the node is shutting down, so that modules are not restarted anymore
+    shutdown: CancellationToken,
+    restarts: Option<Counter<u64>>,
+    statuses: ModuleStatuses,
+    /// Index of the module in `statuses`
+    index: usize,
+}
+
+impl ModuleSupervisor {
+    fn update_status(&self, update: impl FnOnce(&mut ModuleStatus)) {
+        if let Ok(mut statuses) = self.statuses.lock() {
+            if let Some(status) = statuses.get_mut(self.index) {
+                update(status);
+            }
+        }
+    }
+
+    fn notify_ready(&mut self, module_name: &'static str) {
+        debug!("Module {} is ready", module_name);
+        self.update_status(|status| {
+            status.state = APIModuleState::Running;
+            status.started_at = Some(Instant::now());
+        });
+        _ = self.bus.send(signal::ModuleReady {
+            module: module_name.to_string(),
+        });
+    }
+
+    /// Waits for the backoff of the next restart, or returns false if the module stays down.
+    async fn should_restart(
+        &mut self,
+        module_name: &'static str,
+        result: &Result<()>,
+        attempt: u32,
+    ) -> bool {
+        let restart = self.decide_restart(result, attempt);
+        self.update_status(|status| {
+            status.started_at = None;
+            if let Err(e) = result {
+                status.last_error = Some(format!("{:#}", e));
+            }
+            if restart {
+                status.state = APIModuleState::Restarting;
+                status.restarts += 1;
+            } else {
+                status.state = APIModuleState::Exited;
+            }
+        });
+        if !restart {
+            return false;
+        }
+
+        let backoff = restart_backoff(&self.policy, attempt);
+        warn!(
+            "Restarting module {} in {:?} (attempt {})",
+            module_name, backoff, attempt
+        );
+        if let Some(restarts) = &self.restarts {
+            restarts.add(1, &[KeyValue::new("module", short_name(module_name))]);
+        }
+        _ = self.bus.send(signal::ModuleRestarted {
+            module: module_name.to_string(),
+            attempt,
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+        });
+
+        let restart = tokio::select! {
+            _ = tokio::time::sleep(backoff) => !self.shutdown.is_cancelled(),
+            _ = self.shutdown.cancelled() => false,
+        };
+        if restart {
+            self.update_status(|status| status.state = APIModuleState::Starting);
+        } else {
+            self.update_status(|status| status.state = APIModuleState::Exited);
+        }
+        restart
+    }
+
+    fn decide_restart(&self, result: &Result<()>, attempt: u32) -> bool {
+        let restart = match self.policy.mode {
+            RestartMode::Never => false,
+            RestartMode::OnFailure => result.is_err(),
+            RestartMode::Always => true,
+        };
+        restart
+            && !self.shutdown.is_cancelled()
+            && (self.policy.max_restarts == 0 || attempt <= self.policy.max_restarts)
+    }
+}
+
+/// Orders modules so that each one comes after its dependencies, keeping the order they
+/// were added in otherwise. Dependencies on modules that are not run are dropped.
+fn start_order(modules: Vec<ModuleStarter>) -> Result<Vec<ModuleStarter>> {
+    let names: HashSet<&'static str> = modules.iter().map(|m| m.name).collect();
+    let mut pending: Vec<ModuleStarter> = modules
+        .into_iter()
+        .map(|mut module| {
+            module.dependencies.retain(|dependency| {
+                let known = names.contains(dependency);
+                if !known {
+                    debug!(
+                        "Module {} depends on {}, which is not run",
+                        module.name, dependency
+                    );
+                }
+                known
+            });
+            module
+        })
+        .collect();
+
+    let mut ordered: Vec<ModuleStarter> = Vec::with_capacity(pending.len());
+    while !pending.is_empty() {
+        let Some(index) = pending.iter().position(|module| {
+            module
+                .dependencies
+                .iter()
+                .all(|dependency| ordered.iter().any(|m| m.name == *dependency))
+        }) else {
+            let names: Vec<_> = pending.iter().map(|m| m.name).collect();
+            anyhow::bail!("Circular dependencies between modules {:?}", names);
+        };
+        ordered.push(pending.remove(index));
+    }
+    Ok(ordered)
+}
+
+/// Reverses the start order, moving the modules drained by another one right before it.
+fn shutdown_order(
+    started: &[&'static str],
+    drains: &HashMap<&'static str, Vec<&'static str>>,
+) -> Vec<&'static str> {
+    fn visit(
+        name: &'static str,
+        started: &[&'static str],
+        drains: &HashMap<&'static str, Vec<&'static str>>,
+        visited: &mut HashSet<&'static str>,
+        order: &mut Vec<&'static str>,
+    ) {
+        if !visited.insert(name) {
+            return;
+        }
+        for drained in drains.get(name).into_iter().flatten() {
+            if started.contains(drained) {
+                visit(*drained, started, drains, visited, order);
+            }
+        }
+        order.push(name);
+    }
+
+    let mut visited = HashSet::new();
+    let mut order = Vec::with_capacity(started.len());
+    for name in started.iter().rev() {
+        visit(*name, started, drains, &mut visited, &mut order);
+    }
+    order
+}
+
+/// Backoff before the given restart attempt, starting at 1.
+fn restart_backoff(policy: &RestartPolicy, attempt: u32) -> Duration {
+    let factor = 2u64.saturating_pow(attempt.saturating_sub(1));
+    Duration::from_millis(
+        policy
+            .backoff
+            .saturating_mul(factor)
+            .min(policy.max_backoff.max(policy.backoff)),
+    )
+}
+
+/// Name of a module type without its path nor generics, as used in the configuration.
+fn short_name(module_name: &str) -> &str {
+    let without_generics = module_name.split('<').next().unwrap_or(module_name);
+    without_generics
+        .rsplit("::")
+        .next()
+        .unwrap_or(without_generics)
 }
 
 pub mod signal {
@@ -106,9 +341,25 @@ pub mod signal {
     pub struct ShutdownCompleted {
         pub module: String,
     }
+    /// A module is ready, and the modules depending on it can start.
+    #[derive(Clone, Debug)]
+    pub struct ModuleReady {
+        pub module: String,
+    }
+    /// A module exited and is about to be restarted by its policy.
+    #[derive(Clone, Debug)]
+    pub struct ModuleRestarted {
+        pub module: String,
+        /// Consecutive restarts of the module, starting at 1
+        pub attempt: u32,
+        /// Error the module exited with, if any
+        pub error: Option<String>,
+    }
 
     impl BusMessage for ShutdownModule {}
     impl BusMessage for ShutdownCompleted {}
+    impl BusMessage for ModuleReady {}
+    impl BusMessage for ModuleRestarted {}
 
     pub async fn async_receive_shutdown<T>(
         should_shutdown: &mut bool,
@@ -180,6 +431,54 @@ bus_client! {
     }
 }
 
+bus_client! {
+    struct ModuleSupervisorClient {
+        sender(signal::ModuleReady),
+        sender(signal::ModuleRestarted),
+    }
+}
+
+bus_client! {
+    struct ModulesStatusClient {
+        receiver(Query<QueryModulesStatus, Vec<APIModuleStatus>>),
+    }
+}
+
+bus_client! {
+    struct ReadinessClient {
+        receiver(signal::ModuleReady),
+        receiver(signal::ShutdownCompleted),
+    }
+}
+
+impl ReadinessClient {
+    /// Waits until the module is ready, given the modules already known to be ready.
+    async fn wait_until_ready(
+        &mut self,
+        ready_modules: &mut HashSet<String>,
+        module_name: &'static str,
+    ) -> Result<()> {
+        if ready_modules.contains(module_name) {
+            return Ok(());
+        }
+        handle_messages! {
+            on_bus *self,
+            listen<signal::ModuleReady> msg => {
+                ready_modules.insert(msg.module);
+                if ready_modules.contains(module_name) {
+                    break;
+                }
+            }
+            listen<ShutdownCompleted> msg => {
+                if msg.module == module_name {
+                    anyhow::bail!("Module {} exited before being ready", module_name);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
 impl ShutdownClient {
     pub async fn shutdown_module(&mut self, module_name: &str) {
         _ = self
@@ -204,6 +503,14 @@ pub struct ModulesHandler {
     bus: SharedMessageBus,
     modules: Vec<ModuleStarter>,
     started_modules: Vec<&'static str>,
+    /// Modules drained by each started module, see [Module::drains]
+    drains: HashMap<&'static str, Vec<&'static str>>,
+    /// Modules whose task is over, that don't need to be asked to shut down
+    exited_modules: Arc<Mutex<HashSet<&'static str>>>,
+    restart_policies: HashMap<String, RestartPolicy>,
+    restarts: Option<Counter<u64>>,
+    shutdown: CancellationToken,
+    statuses: ModuleStatuses,
 }
 
 impl ModulesHandler {
@@ -214,32 +521,104 @@ impl ModulesHandler {
             bus: shared_message_bus,
             modules: vec![],
             started_modules: vec![],
+            drains: HashMap::new(),
+            exited_modules: Arc::new(Mutex::new(HashSet::new())),
+            restart_policies: HashMap::new(),
+            restarts: None,
+            shutdown: CancellationToken::new(),
+            statuses: Arc::new(Mutex::new(vec![])),
         }
     }
 
+    /// Modules without a policy in the configuration are never restarted.
+    pub fn set_restart_policies(&mut self, conf: &Conf) {
+        let scope = InstrumentationScope::builder(conf.id.clone()).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        self.restart_policies = conf.module_restart.clone();
+        self.restarts = Some(my_meter.u64_counter("module_restarts").build());
+    }
+
+    /// Runs all modules, returning as soon as one of them exits.
+    /// A module that failed makes this return its error, so that the caller can shut the
+    /// other modules down with [ModulesHandler::shutdown_modules].
+    ///
+    /// Modules start in the order they were added, except that a module only starts once
+    /// its [Module::dependencies] are ready.
     pub async fn start_modules(&mut self) -> Result<()> {
         let mut tasks: Vec<JoinHandle<Result<()>>> = vec![];
+        let modules = start_order(self.modules.drain(..).collect())?;
+        let mut readiness = ReadinessClient::new_from_bus(self.bus.new_handle()).await;
+        let mut ready_modules = HashSet::new();
+
+        let first_index = match self.statuses.lock() {
+            Ok(mut statuses) => {
+                let first_index = statuses.len();
+                statuses.extend(modules.iter().map(|module| ModuleStatus {
+                    name: module.name,
+                    state: APIModuleState::Starting,
+                    started_at: None,
+                    restarts: 0,
+                    last_error: None,
+                }));
+                first_index
+            }
+            Err(_) => 0,
+        };
+        self.answer_status_queries().await?;
+
+        for (index, module) in modules.into_iter().enumerate() {
+            for dependency in module.dependencies.iter().copied() {
+                debug!("Module {} waits for {}", module.name, dependency);
+                tokio::time::timeout(
+                    MODULE_READY_TIMEOUT,
+                    readiness.wait_until_ready(&mut ready_modules, dependency),
+                )
+                .await
+                .map_err(|_| anyhow::anyhow!("Timed out"))
+                .and_then(|ready| ready)
+                .with_context(|| {
+                    format!("Waiting for {} before starting {}", dependency, module.name)
+                })?;
+            }
 
-        for module in self.modules.drain(..) {
             self.started_modules.push(module.name);
+            self.drains.insert(module.name, module.drains.clone());
             let mut shutdown_client = ShutdownClient::new_from_bus(self.bus.new_handle()).await;
+            let exited_modules = Arc::clone(&self.exited_modules);
+            let supervisor = ModuleSupervisor {
+                policy: self
+                    .restart_policies
+                    .get(short_name(module.name))
+                    .cloned()
+                    .unwrap_or_default(),
+                bus: ModuleSupervisorClient::new_from_bus(self.bus.new_handle()).await,
+                shutdown: self.shutdown.clone(),
+                restarts: self.restarts.clone(),
+                statuses: Arc::clone(&self.statuses),
+                index: first_index + index,
+            };
 
             debug!("Starting module {}", module.name);
             let handle = tokio::task::Builder::new()
                 .name(module.name)
                 .spawn(async move {
-                    match module.starter.await {
+                    let result = (module.starter)(supervisor).await;
+                    match &result {
                         Ok(_) => tracing::debug!("Module {} exited with no error.", module.name),
                         Err(e) => {
                             tracing::error!("Module {} exited with error: {:?}", module.name, e);
                         }
                     }
+                    if let Ok(mut exited_modules) = exited_modules.lock() {
+                        exited_modules.insert(module.name);
+                    }
                     _ = shutdown_client
                         .send(signal::ShutdownCompleted {
                             module: module.name.to_string(),
                         })
                         .log_error("Sending ShutdownCompleted message");
-                    Ok(())
+                    result.with_context(|| format!("Running module {}", module.name))
                 })?;
 
             tasks.push(handle);
@@ -252,11 +631,50 @@ impl ModulesHandler {
             .context("Joining error")?
     }
 
-    /// Shutdown modules in reverse order (start A, B, C, shutdown C, B, A)
+    /// Answers [QueryModulesStatus] until the modules are shut down.
+    async fn answer_status_queries(&self) -> Result<()> {
+        let mut status_client = ModulesStatusClient::new_from_bus(self.bus.new_handle()).await;
+        let statuses = Arc::clone(&self.statuses);
+        let shutdown = self.shutdown.clone();
+
+        tokio::task::Builder::new()
+            .name("modules_status")
+            .spawn(async move {
+                handle_messages! {
+                    on_bus status_client,
+                    command_response<QueryModulesStatus, Vec<APIModuleStatus>> _ => {
+                        match statuses.lock() {
+                            Ok(statuses) => Ok(statuses.iter().map(APIModuleStatus::from).collect()),
+                            Err(_) => Err(anyhow::anyhow!("Module statuses are unavailable")),
+                        }
+                    }
+                    _ = shutdown.cancelled() => {
+                        break;
+                    }
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Shutdown modules in reverse order (start A, B, C, shutdown C, B, A), except that the
+    /// modules one [drains](Module::drains) are shut down right before it.
+    ///
+    /// Each module is given `timeout` to leave its loop, handle the messages still queued for
+    /// it and run its [Module::on_shutdown] hook.
     pub async fn shutdown_modules(&mut self, timeout: Duration) -> Result<()> {
+        self.shutdown.cancel();
         let mut shutdown_client = ShutdownClient::new_from_bus(self.bus.new_handle()).await;
 
-        for module_name in self.started_modules.drain(..).rev() {
+        let started: Vec<_> = self.started_modules.drain(..).collect();
+        for module_name in shutdown_order(&started, &self.drains) {
+            let exited = self
+                .exited_modules
+                .lock()
+                .is_ok_and(|exited_modules| exited_modules.contains(module_name));
+            if exited {
+                debug!("Module {} already exited", module_name);
+                continue;
+            }
             if ![std::any::type_name::<Genesis>()].contains(&module_name) {
                 _ = tokio::time::timeout(timeout, shutdown_client.shutdown_module(module_name))
                     .await
@@ -267,11 +685,37 @@ impl ModulesHandler {
         Ok(())
     }
 
-    async fn run_module<M>(mut module: M) -> Result<()>
+    async fn run_module<M>(mut module: M, mut supervisor: ModuleSupervisor) -> Result<()>
     where
         M: Module,
     {
-        module.run().await
+        let mut attempt = 0;
+        loop {
+            let started_at = Instant::now();
+            let result = match module.ready().await {
+                Ok(()) => {
+                    supervisor.notify_ready(type_name::<M>());
+                    module.run().await
+                }
+                Err(e) => Err(e.context("Getting ready")),
+            };
+            _ = module
+                .on_shutdown()
+                .await
+                .log_error(format!("Shutting down module {}", type_name::<M>()));
+
+            // A module that ran for a while before exiting starts over with a short backoff
+            if started_at.elapsed() > Duration::from_millis(supervisor.policy.max_backoff) {
+                attempt = 0;
+            }
+            attempt += 1;
+            if !supervisor
+                .should_restart(type_name::<M>(), &result, attempt)
+                .await
+            {
+                return result;
+            }
+        }
     }
 
     pub async fn build_module<M>(&mut self, ctx: M::Context) -> Result<()>
@@ -290,7 +734,11 @@ impl ModulesHandler {
     {
         self.modules.push(ModuleStarter {
             name: type_name::<M>(),
-            starter: Box::pin(Self::run_module(module)),
+            dependencies: M::dependencies(),
+            drains: M::drains(),
+            starter: Box::new(move |supervisor: ModuleSupervisor| -> ModuleFuture {
+                Box::pin(Self::run_module(module, supervisor))
+            }),
         });
         Ok(())
     }
@@ -298,7 +746,10 @@ impl ModulesHandler {
 
 #[cfg(test)]
 mod tests {
-    use crate::bus::{dont_use_this::get_receiver, metrics::BusMetrics, BusMessage};
+    use crate::bus::{
+        command_response::CmdRespClient, dont_use_this::get_receiver, metrics::BusMetrics,
+        BusMessage,
+    };
 
     use super::*;
     use crate::bus::SharedMessageBus;
@@ -323,6 +774,12 @@ mod tests {
         struct TestBusClient { sender(usize), }
     }
 
+    bus_client! {
+        struct StatusQueryClient {
+            sender(Query<QueryModulesStatus, Vec<APIModuleStatus>>),
+        }
+    }
+
     macro_rules! test_module {
         ($bus_client:ty, $tag:ty) => {
             impl Module for TestModule<$tag> {
@@ -360,6 +817,98 @@ mod tests {
     test_module!(TestBusClient, usize);
     test_module!(TestBusClient, bool);
 
+    struct FailingModule {
+        bus: TestBusClient,
+    }
+
+    impl Module for FailingModule {
+        type Context = TestBusClient;
+        async fn build(ctx: Self::Context) -> Result<Self> {
+            Ok(FailingModule { bus: ctx })
+        }
+
+        async fn run(&mut self) -> Result<()> {
+            anyhow::bail!("Module failure")
+        }
+
+        async fn on_shutdown(&mut self) -> Result<()> {
+            self.bus.send(42usize)?;
+            Ok(())
+        }
+    }
+
+    struct DependentModule;
+
+    impl Module for DependentModule {
+        type Context = ();
+        fn dependencies() -> Vec<&'static str> {
+            vec![type_name::<TestModule<String>>()]
+        }
+
+        async fn build(_ctx: Self::Context) -> Result<Self> {
+            Ok(DependentModule)
+        }
+
+        async fn run(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct NotReadyModule;
+
+    impl Module for NotReadyModule {
+        type Context = ();
+        async fn build(_ctx: Self::Context) -> Result<Self> {
+            Ok(NotReadyModule)
+        }
+
+        async fn ready(&mut self) -> Result<()> {
+            anyhow::bail!("Cannot get ready")
+        }
+
+        async fn run(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct BlockedModule;
+
+    impl Module for BlockedModule {
+        type Context = ();
+        fn dependencies() -> Vec<&'static str> {
+            vec![type_name::<NotReadyModule>()]
+        }
+
+        async fn build(_ctx: Self::Context) -> Result<Self> {
+            Ok(BlockedModule)
+        }
+
+        async fn run(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    struct FlakyModule {
+        bus: TestBusClient,
+        runs: usize,
+    }
+
+    impl Module for FlakyModule {
+        type Context = TestBusClient;
+        async fn build(ctx: Self::Context) -> Result<Self> {
+            Ok(FlakyModule { bus: ctx, runs: 0 })
+        }
+
+        async fn run(&mut self) -> Result<()> {
+            self.runs += 1;
+            if self.runs < 3 {
+                anyhow::bail!("Transient failure");
+            }
+            self.bus.send(self.runs)?;
+            Ok(())
+        }
+    }
+
     #[test]
     fn test_load_from_disk_or_default() {
         let dir = tempdir().unwrap();
@@ -558,4 +1107,211 @@ mod tests {
         assert_eq!(cancellation_counter_receiver.try_recv().expect("1"), 1);
         assert_eq!(cancellation_counter_receiver.try_recv().expect("1"), 1);
     }
+
+    #[tokio::test]
+    async fn test_failing_module_shuts_others_down() {
+        let shared_bus = SharedMessageBus::new(BusMetrics::global("id".to_string()));
+        let mut usize_receiver = get_receiver::<usize>(&shared_bus).await;
+        let mut shutdown_receiver = get_receiver::<ShutdownModule>(&shared_bus).await;
+        let mut handler = ModulesHandler::new(&shared_bus).await;
+
+        handler
+            .build_module::<TestModule<String>>(
+                TestBusClient::new_from_bus(shared_bus.new_handle()).await,
+            )
+            .await
+            .unwrap();
+        handler
+            .build_module::<FailingModule>(
+                TestBusClient::new_from_bus(shared_bus.new_handle()).await,
+            )
+            .await
+            .unwrap();
+
+        assert!(handler.start_modules().await.is_err());
+        // The hook ran even though the module failed
+        assert_eq!(usize_receiver.try_recv().unwrap(), 42);
+
+        _ = handler.shutdown_modules(Duration::from_secs(1)).await;
+
+        // Only the module still running is asked to shut down
+        assert_eq!(
+            shutdown_receiver.recv().await.unwrap().module,
+            std::any::type_name::<TestModule<String>>().to_string()
+        );
+        assert!(shutdown_receiver.try_recv().is_err());
+        assert_eq!(usize_receiver.recv().await.unwrap(), 1);
+    }
+
+    #[test]
+    fn test_restart_backoff() {
+        let policy = RestartPolicy {
+            mode: RestartMode::OnFailure,
+            max_restarts: 0,
+            backoff: 100,
+            max_backoff: 1000,
+        };
+        assert_eq!(restart_backoff(&policy, 1), Duration::from_millis(100));
+        assert_eq!(restart_backoff(&policy, 2), Duration::from_millis(200));
+        assert_eq!(restart_backoff(&policy, 4), Duration::from_millis(800));
+        assert_eq!(restart_backoff(&policy, 5), Duration::from_millis(1000));
+        assert_eq!(restart_backoff(&policy, 100), Duration::from_millis(1000));
+
+        assert_eq!(short_name(type_name::<FlakyModule>()), "FlakyModule");
+        assert_eq!(short_name(type_name::<TestModule<usize>>()), "TestModule");
+    }
+
+    #[tokio::test]
+    async fn test_restart_on_failure() {
+        let shared_bus = SharedMessageBus::new(BusMetrics::global("id".to_string()));
+        let mut usize_receiver = get_receiver::<usize>(&shared_bus).await;
+        let mut restarted_receiver = get_receiver::<signal::ModuleRestarted>(&shared_bus).await;
+        let mut handler = ModulesHandler::new(&shared_bus).await;
+        handler.restart_policies.insert(
+            "FlakyModule".to_string(),
+            RestartPolicy {
+                mode: RestartMode::OnFailure,
+                max_restarts: 5,
+                backoff: 1,
+                max_backoff: 10,
+            },
+        );
+        handler
+            .build_module::<FlakyModule>(TestBusClient::new_from_bus(shared_bus.new_handle()).await)
+            .await
+            .unwrap();
+
+        assert!(handler.start_modules().await.is_ok());
+        assert_eq!(usize_receiver.try_recv().unwrap(), 3);
+        for attempt in 1..=2 {
+            let restarted = restarted_receiver.try_recv().unwrap();
+            assert_eq!(restarted.module, type_name::<FlakyModule>());
+            assert_eq!(restarted.attempt, attempt);
+            assert!(restarted.error.is_some());
+        }
+        assert!(restarted_receiver.try_recv().is_err());
+
+        let mut status_client = StatusQueryClient::new_from_bus(shared_bus.new_handle()).await;
+        let statuses = status_client.request(QueryModulesStatus).await.unwrap();
+        assert_eq!(
+            statuses,
+            vec![APIModuleStatus {
+                name: type_name::<FlakyModule>().to_string(),
+                state: APIModuleState::Exited,
+                uptime: None,
+                restarts: 2,
+                last_error: Some("Transient failure".to_string()),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_restart_gives_up() {
+        let shared_bus = SharedMessageBus::new(BusMetrics::global("id".to_string()));
+        let mut handler = ModulesHandler::new(&shared_bus).await;
+        handler.restart_policies.insert(
+            "FlakyModule".to_string(),
+            RestartPolicy {
+                mode: RestartMode::OnFailure,
+                max_restarts: 1,
+                backoff: 1,
+                max_backoff: 10,
+            },
+        );
+        handler
+            .build_module::<FlakyModule>(TestBusClient::new_from_bus(shared_bus.new_handle()).await)
+            .await
+            .unwrap();
+
+        assert!(handler.start_modules().await.is_err());
+    }
+
+    fn starter(name: &'static str, dependencies: Vec<&'static str>) -> ModuleStarter {
+        ModuleStarter {
+            name,
+            dependencies,
+            drains: vec![],
+            starter: Box::new(|_: ModuleSupervisor| -> ModuleFuture { Box::pin(async { Ok(()) }) }),
+        }
+    }
+
+    #[test]
+    fn test_start_order() {
+        let order = start_order(vec![
+            starter("indexer", vec!["node_state"]),
+            starter("mempool", vec![]),
+            starter("node_state", vec!["da", "not_run"]),
+            starter("da", vec![]),
+            starter("rest", vec![]),
+        ])
+        .unwrap();
+        let names: Vec<_> = order.iter().map(|m| m.name).collect();
+        assert_eq!(
+            names,
+            vec!["mempool", "da", "node_state", "indexer", "rest"]
+        );
+
+        assert!(start_order(vec![
+            starter("a", vec!["b"]),
+            starter("b", vec!["a"]),
+            starter("c", vec![]),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_shutdown_order() {
+        let started = ["mempool", "da", "node_state", "indexer", "rest"];
+        let drains = HashMap::from([
+            ("indexer", vec!["node_state"]),
+            ("node_state", vec!["da", "not_run"]),
+        ]);
+        assert_eq!(
+            shutdown_order(&started, &drains),
+            vec!["rest", "da", "node_state", "indexer", "mempool"]
+        );
+
+        let drains = HashMap::from([("a", vec!["b"]), ("b", vec!["a"])]);
+        assert_eq!(shutdown_order(&["a", "b"], &drains), vec!["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn test_start_after_dependencies_are_ready() {
+        let shared_bus = SharedMessageBus::new(BusMetrics::global("id".to_string()));
+        let mut ready_receiver = get_receiver::<signal::ModuleReady>(&shared_bus).await;
+        let mut handler = ModulesHandler::new(&shared_bus).await;
+
+        handler.build_module::<DependentModule>(()).await.unwrap();
+        handler
+            .build_module::<TestModule<String>>(
+                TestBusClient::new_from_bus(shared_bus.new_handle()).await,
+            )
+            .await
+            .unwrap();
+
+        // The dependent module exits right away
+        assert!(handler.start_modules().await.is_ok());
+        assert_eq!(
+            ready_receiver.try_recv().unwrap().module,
+            type_name::<TestModule<String>>()
+        );
+        assert_eq!(
+            ready_receiver.try_recv().unwrap().module,
+            type_name::<DependentModule>()
+        );
+
+        _ = handler.shutdown_modules(Duration::from_secs(1)).await;
+    }
+
+    #[tokio::test]
+    async fn test_dependency_never_ready() {
+        let shared_bus = SharedMessageBus::new(BusMetrics::global("id".to_string()));
+        let mut handler = ModulesHandler::new(&shared_bus).await;
+
+        handler.build_module::<BlockedModule>(()).await.unwrap();
+        handler.build_module::<NotReadyModule>(()).await.unwrap();
+
+        assert!(handler.start_modules().await.is_err());
+        assert_eq!(handler.started_modules, vec![type_name::<NotReadyModule>()]);
+    }
 }
@@ -0,0 +1,251 @@
+//! Versioned snapshots of module state on disk.
+//!
+//! Each snapshot starts with a sequence number, handed out when the state is captured. A snapshot
+//! only replaces the current file if it is newer, so that concurrent saves can't override a newer
+//! state with an older one. The previous snapshots are kept next to it to recover from a
+//! corrupted file.
+
+use std::{
+    collections::HashMap,
+    fs,
+    io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::{Context, Result};
+use rand::{distr::Alphanumeric, Rng};
+use tracing::{debug, warn};
+
+use crate::utils::logger::LogMe;
+
+/// Snapshots start with these bytes, followed by their sequence number.
+/// Files without them are decoded as they were written before versioning.
+const SNAPSHOT_MAGIC: &[u8; 8] = b"HYLESNAP";
+
+/// Number of previous snapshots kept next to the current one.
+pub const SNAPSHOTS_KEPT: usize = 3;
+
+/// Last sequence number handed out for each snapshot file. Also held while replacing files.
+static SEQUENCES: LazyLock<Mutex<HashMap<PathBuf, u64>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+fn sequences() -> std::sync::MutexGuard<'static, HashMap<PathBuf, u64>> {
+    match SEQUENCES.lock() {
+        Ok(sequences) => sequences,
+        Err(poisoned) => poisoned.into_inner(),
+    }
+}
+
+/// Sequence number of the next snapshot of `file`, above any snapshot already on disk.
+pub fn next_sequence(file: &Path) -> u64 {
+    let mut sequences = sequences();
+    let sequence = sequences.entry(file.to_path_buf()).or_insert_with(|| {
+        std::iter::once(read_sequence(file).unwrap_or(0))
+            .chain(backups(file).into_iter().map(|(sequence, _)| sequence))
+            .max()
+            .unwrap_or(0)
+    });
+    *sequence += 1;
+    *sequence
+}
+
+/// Writes the snapshot to a synced temporary file, then moves it in place of `file` unless a
+/// newer snapshot was saved meanwhile. Returns whether the snapshot replaced the current one.
+pub fn write<S: bincode::Encode>(file: &Path, store: &S, sequence: u64) -> Result<bool> {
+    let salt: String = rand::rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(char::from)
+        .collect();
+    let tmp = file.with_extension(format!("{}.tmp", salt));
+    debug!("Saving on disk in a tmp file {:?}", tmp.clone());
+    let mut buf_writer = BufWriter::new(fs::File::create(tmp.as_path()).log_error("Create file")?);
+    buf_writer.write_all(SNAPSHOT_MAGIC)?;
+    buf_writer.write_all(&sequence.to_le_bytes())?;
+    bincode::encode_into_std_write(store, &mut buf_writer, bincode::config::standard())
+        .log_error("Serializing Ctx chain")?;
+    buf_writer
+        .into_inner()
+        .context("Flushing snapshot")?
+        .sync_all()
+        .log_error(format!("Syncing {}", tmp.to_string_lossy()))?;
+
+    let _sequences = sequences();
+    let current = read_sequence(file);
+    if current.is_some_and(|current| current >= sequence) {
+        debug!(
+            "Snapshot {} of {:?} is older than the one on disk, dropping it",
+            sequence, file
+        );
+        fs::remove_file(&tmp).log_error("Removing outdated snapshot")?;
+        return Ok(false);
+    }
+    if let Some(current) = current {
+        fs::rename(file, backup_path(file, current)).log_error("Backing up snapshot")?;
+    }
+    debug!("Renaming {:?} to {:?}", &tmp, &file);
+    fs::rename(&tmp, file).log_error("Rename file")?;
+    if let Some(dir) = file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        _ = fs::File::open(dir).and_then(|dir| dir.sync_all());
+    }
+
+    for (_, old) in backups(file).into_iter().skip(SNAPSHOTS_KEPT) {
+        _ = fs::remove_file(&old).log_warn(format!("Removing old snapshot {:?}", old));
+    }
+    Ok(true)
+}
+
+/// Decodes the current snapshot of `file`, or the most recent backup that can be decoded.
+/// Returns `None` if there is none.
+pub fn read<S: bincode::Decode>(file: &Path) -> Option<Result<S>> {
+    let mut candidates = file
+        .exists()
+        .then(|| file.to_path_buf())
+        .into_iter()
+        .chain(backups(file).into_iter().map(|(_, path)| path))
+        .peekable();
+    candidates.peek()?;
+
+    let mut last_error = None;
+    for path in candidates {
+        match decode(&path) {
+            Ok(store) => {
+                if path != file {
+                    warn!("Recovered {:?} from backup {:?}", file, path);
+                }
+                return Some(Ok(store));
+            }
+            Err(e) => {
+                warn!("Could not decode snapshot {:?}: {:#}", path, e);
+                last_error = Some(e);
+            }
+        }
+    }
+    last_error.map(Err)
+}
+
+fn decode<S: bincode::Decode>(path: &Path) -> Result<S> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut header = [0u8; 16];
+    let versioned = reader.read_exact(&mut header).is_ok() && header.starts_with(SNAPSHOT_MAGIC);
+    if !versioned {
+        reader.seek(SeekFrom::Start(0))?;
+    }
+    Ok(bincode::decode_from_std_read(
+        &mut reader,
+        bincode::config::standard(),
+    )?)
+}
+
+/// Sequence number of a snapshot, 0 for files written before versioning.
+fn read_sequence(path: &Path) -> Option<u64> {
+    let mut header = [0u8; 16];
+    let mut file = fs::File::open(path).ok()?;
+    if file.read_exact(&mut header).is_err() {
+        return Some(0);
+    }
+    let (magic, sequence) = header.split_at(SNAPSHOT_MAGIC.len());
+    if magic != SNAPSHOT_MAGIC {
+        return Some(0);
+    }
+    Some(u64::from_le_bytes(sequence.try_into().ok()?))
+}
+
+fn backup_path(file: &Path, sequence: u64) -> PathBuf {
+    let mut name = file.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{}", sequence));
+    file.with_file_name(name)
+}
+
+/// Backups of `file` with their sequence number, most recent first.
+fn backups(file: &Path) -> Vec<(u64, PathBuf)> {
+    let Some(name) = file.file_name().and_then(|name| name.to_str()) else {
+        return vec![];
+    };
+    let dir = match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let Ok(entries) = fs::read_dir(dir) else {
+        return vec![];
+    };
+
+    let mut backups: Vec<_> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let sequence = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix(name)?
+                .strip_prefix('.')?
+                .parse::<u64>()
+                .ok()?;
+            Some((sequence, file.with_file_name(entry.file_name())))
+        })
+        .collect();
+    backups.sort_by_key(|(sequence, _)| std::cmp::Reverse(*sequence));
+    backups
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::tempdir;
+
+    use super::*;
+
+    #[test]
+    fn test_older_snapshot_does_not_override_newer() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("state.bin");
+
+        let older = next_sequence(&file);
+        let newer = next_sequence(&file);
+        assert!(newer > older);
+
+        assert!(write(&file, &2u32, newer).unwrap());
+        assert!(!write(&file, &1u32, older).unwrap());
+        assert_eq!(read::<u32>(&file).unwrap().unwrap(), 2);
+        assert_eq!(read_sequence(&file), Some(newer));
+    }
+
+    #[test]
+    fn test_keeps_last_snapshots() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("state.bin");
+
+        for value in 1..=6u32 {
+            write(&file, &value, next_sequence(&file)).unwrap();
+        }
+        let kept: Vec<_> = backups(&file).into_iter().map(|(seq, _)| seq).collect();
+        assert_eq!(kept, vec![5, 4, 3]);
+        // No temporary file is left behind
+        assert_eq!(
+            fs::read_dir(dir.path()).unwrap().count(),
+            SNAPSHOTS_KEPT + 1
+        );
+
+        // A corrupted snapshot falls back on the last backup
+        fs::write(&file, b"HYLESNAP\x07\0\0\0\0\0\0\0").unwrap();
+        assert_eq!(read::<u32>(&file).unwrap().unwrap(), 5);
+    }
+
+    #[test]
+    fn test_reads_unversioned_files() {
+        let dir = tempdir().unwrap();
+        let file = dir.path().join("state.bin");
+        assert!(read::<u32>(&file).is_none());
+
+        fs::write(
+            &file,
+            bincode::encode_to_vec(42u32, bincode::config::standard()).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(read_sequence(&file), Some(0));
+        assert_eq!(read::<u32>(&file).unwrap().unwrap(), 42);
+
+        write(&file, &43u32, next_sequence(&file)).unwrap();
+        assert_eq!(read::<u32>(&file).unwrap().unwrap(), 43);
+        assert_eq!(read::<u32>(&backup_path(&file, 0)).unwrap().unwrap(), 42);
+    }
+}
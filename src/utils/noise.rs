@@ -0,0 +1,376 @@
+//! Encrypted transport for connections between nodes, for the p2p layer and the DA stream.
+//!
+//! Both sides run a Noise `NN` handshake, then sign its hash with their validator key so that
+//! the channel is bound to the validator on the other end. A side without a validator key (e.g.
+//! an indexer following the DA stream) stays anonymous, the connection is still encrypted.
+
+use std::{
+    io,
+    pin::Pin,
+    task::{ready, Context, Poll},
+    time::Duration,
+};
+
+use anyhow::{bail, Context as _, Result};
+use bincode::{Decode, Encode};
+use bytes::{Buf, BytesMut};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf},
+    net::TcpStream,
+};
+
+use super::crypto::{BlstCrypto, SignedByValidator};
+use crate::model::ValidatorPublicKey;
+
+const NOISE_PARAMS: &str = "Noise_NN_25519_ChaChaPoly_BLAKE2s";
+/// Largest Noise message, ciphertext included
+const MAX_NOISE_MESSAGE: usize = 65535;
+const TAG_SIZE: usize = 16;
+const MAX_PAYLOAD: usize = MAX_NOISE_MESSAGE - TAG_SIZE;
+const MAX_IDENTITY_SIZE: usize = 1024;
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Hash of the Noise handshake, signed by a side to prove its identity on this connection.
+#[derive(Debug, Clone, Encode, Decode)]
+struct HandshakeHash(Vec<u8>);
+
+/// A TCP connection to another node, encrypted or not depending on the configuration.
+pub enum NodeStream {
+    Plain(TcpStream),
+    Noise(Box<NoiseStream>),
+}
+
+impl NodeStream {
+    /// Runs the handshake as the side that opened the connection if `encrypted`, and returns the
+    /// validator proven by the other side, if any.
+    pub async fn connect(
+        stream: TcpStream,
+        encrypted: bool,
+        crypto: Option<&BlstCrypto>,
+    ) -> Result<(Self, Option<ValidatorPublicKey>)> {
+        Self::handshake(stream, encrypted, crypto, true).await
+    }
+
+    /// Runs the handshake as the side that accepted the connection if `encrypted`.
+    pub async fn accept(
+        stream: TcpStream,
+        encrypted: bool,
+        crypto: Option<&BlstCrypto>,
+    ) -> Result<(Self, Option<ValidatorPublicKey>)> {
+        Self::handshake(stream, encrypted, crypto, false).await
+    }
+
+    async fn handshake(
+        stream: TcpStream,
+        encrypted: bool,
+        crypto: Option<&BlstCrypto>,
+        initiator: bool,
+    ) -> Result<(Self, Option<ValidatorPublicKey>)> {
+        if !encrypted {
+            return Ok((NodeStream::Plain(stream), None));
+        }
+        let (stream, peer) = tokio::time::timeout(
+            HANDSHAKE_TIMEOUT,
+            noise_handshake(stream, crypto, initiator),
+        )
+        .await
+        .context("Noise handshake timed out")??;
+        Ok((NodeStream::Noise(Box::new(stream)), peer))
+    }
+
+    pub fn peer_addr(&self) -> io::Result<std::net::SocketAddr> {
+        match self {
+            NodeStream::Plain(stream) => stream.peer_addr(),
+            NodeStream::Noise(stream) => stream.inner.peer_addr(),
+        }
+    }
+}
+
+async fn noise_handshake(
+    mut stream: TcpStream,
+    crypto: Option<&BlstCrypto>,
+    initiator: bool,
+) -> Result<(NoiseStream, Option<ValidatorPublicKey>)> {
+    let builder = snow::Builder::new(NOISE_PARAMS.parse()?);
+    let mut handshake = if initiator {
+        builder.build_initiator()?
+    } else {
+        builder.build_responder()?
+    };
+    let mut buf = vec![0u8; MAX_NOISE_MESSAGE];
+    // -> e, <- e, ee
+    if initiator {
+        let len = handshake.write_message(&[], &mut buf)?;
+        write_frame(&mut stream, buf.get(..len).unwrap_or_default()).await?;
+        let msg = read_frame(&mut stream).await?;
+        handshake.read_message(&msg, &mut buf)?;
+    } else {
+        let msg = read_frame(&mut stream).await?;
+        handshake.read_message(&msg, &mut buf)?;
+        let len = handshake.write_message(&[], &mut buf)?;
+        write_frame(&mut stream, buf.get(..len).unwrap_or_default()).await?;
+    }
+    if !handshake.is_handshake_finished() {
+        bail!("Noise handshake not finished");
+    }
+    let hash = HandshakeHash(handshake.get_handshake_hash().to_vec());
+    let mut stream = NoiseStream::new(stream, handshake.into_transport_mode()?);
+
+    // Both sides send their identity before reading the other's
+    let identity = crypto.map(|crypto| crypto.sign(hash.clone())).transpose()?;
+    let identity = bincode::encode_to_vec(&identity, bincode::config::standard())?;
+    stream.write_u32(identity.len() as u32).await?;
+    stream.write_all(&identity).await?;
+    stream.flush().await?;
+
+    let len = stream.read_u32().await? as usize;
+    if len > MAX_IDENTITY_SIZE {
+        bail!("Identity of {} bytes is too large", len);
+    }
+    let mut identity = vec![0u8; len];
+    stream.read_exact(&mut identity).await?;
+    let (identity, _): (Option<SignedByValidator<HandshakeHash>>, _) =
+        bincode::decode_from_slice(&identity, bincode::config::standard())
+            .context("Decoding peer identity")?;
+    let peer = match identity {
+        None => None,
+        Some(signed) => {
+            if signed.msg.0 != hash.0 || !BlstCrypto::verify(&signed)? {
+                bail!(
+                    "Invalid identity proof for validator {}",
+                    signed.signature.validator
+                );
+            }
+            Some(signed.signature.validator)
+        }
+    };
+    Ok((stream, peer))
+}
+
+async fn write_frame(stream: &mut TcpStream, data: &[u8]) -> Result<()> {
+    stream.write_u16(data.len() as u16).await?;
+    stream.write_all(data).await?;
+    Ok(())
+}
+
+async fn read_frame(stream: &mut TcpStream) -> Result<Vec<u8>> {
+    let len = stream.read_u16().await? as usize;
+    let mut data = vec![0u8; len];
+    stream.read_exact(&mut data).await?;
+    Ok(data)
+}
+
+/// TCP stream whose bytes are sent as Noise messages, each prefixed by its length on 2 bytes.
+pub struct NoiseStream {
+    inner: TcpStream,
+    transport: snow::TransportState,
+    /// Bytes read but not decrypted yet
+    read_buf: BytesMut,
+    /// Bytes decrypted but not read yet
+    plaintext: BytesMut,
+    /// Encrypted bytes not written yet
+    write_buf: BytesMut,
+}
+
+impl NoiseStream {
+    fn new(inner: TcpStream, transport: snow::TransportState) -> Self {
+        NoiseStream {
+            inner,
+            transport,
+            read_buf: BytesMut::new(),
+            plaintext: BytesMut::new(),
+            write_buf: BytesMut::new(),
+        }
+    }
+
+    /// Decrypts the next message if fully read.
+    fn decrypt_message(&mut self) -> io::Result<bool> {
+        let Some(&[high, low]) = self.read_buf.get(..2) else {
+            return Ok(false);
+        };
+        let len = u16::from_be_bytes([high, low]) as usize;
+        if self.read_buf.len() < 2 + len {
+            return Ok(false);
+        }
+        self.read_buf.advance(2);
+        let message = self.read_buf.split_to(len);
+        let mut payload = vec![0u8; len];
+        let len = self
+            .transport
+            .read_message(&message, &mut payload)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.plaintext
+            .extend_from_slice(payload.get(..len).unwrap_or_default());
+        Ok(true)
+    }
+
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.write_buf.is_empty() {
+            let written = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.write_buf))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+            self.write_buf.advance(written);
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncRead for NoiseStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if !this.plaintext.is_empty() {
+                let len = this.plaintext.len().min(buf.remaining());
+                buf.put_slice(&this.plaintext.split_to(len));
+                return Poll::Ready(Ok(()));
+            }
+            if this.decrypt_message()? {
+                continue;
+            }
+            let mut chunk = [0u8; 8192];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                if !this.read_buf.is_empty() {
+                    return Poll::Ready(Err(io::ErrorKind::UnexpectedEof.into()));
+                }
+                return Poll::Ready(Ok(()));
+            }
+            this.read_buf.extend_from_slice(chunk_buf.filled());
+        }
+    }
+}
+
+impl AsyncWrite for NoiseStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        // Only one message is buffered at a time
+        ready!(this.poll_write_pending(cx))?;
+        let payload = data.get(..data.len().min(MAX_PAYLOAD)).unwrap_or_default();
+        let mut message = vec![0u8; payload.len() + TAG_SIZE];
+        let len = this
+            .transport
+            .write_message(payload, &mut message)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        this.write_buf
+            .extend_from_slice(&(len as u16).to_be_bytes());
+        this.write_buf
+            .extend_from_slice(message.get(..len).unwrap_or_default());
+        Poll::Ready(Ok(payload.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_pending(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+impl AsyncRead for NodeStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            NodeStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            NodeStream::Noise(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for NodeStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        data: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            NodeStream::Plain(stream) => Pin::new(stream).poll_write(cx, data),
+            NodeStream::Noise(stream) => Pin::new(stream.as_mut()).poll_write(cx, data),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            NodeStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            NodeStream::Noise(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            NodeStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            NodeStream::Noise(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+    use super::*;
+
+    async fn connect_pair(
+        client: Option<BlstCrypto>,
+        server: BlstCrypto,
+    ) -> Result<(
+        (NodeStream, Option<ValidatorPublicKey>),
+        (NodeStream, Option<ValidatorPublicKey>),
+    )> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let accept = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await?;
+            NodeStream::accept(stream, true, Some(&server)).await
+        });
+        let client =
+            NodeStream::connect(TcpStream::connect(addr).await?, true, client.as_ref()).await?;
+        Ok((client, accept.await??))
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_noise_stream() -> Result<()> {
+        let validator = BlstCrypto::new("validator".into())?;
+        let other = BlstCrypto::new("other".into())?;
+        let ((client, server_id), (server, client_id)) =
+            connect_pair(Some(other.clone()), validator.clone()).await?;
+        assert_eq!(server_id.as_ref(), Some(validator.validator_pubkey()));
+        assert_eq!(client_id.as_ref(), Some(other.validator_pubkey()));
+
+        // Frames larger than a Noise message go through
+        let mut client = Framed::new(client, LengthDelimitedCodec::new());
+        let mut server = Framed::new(server, LengthDelimitedCodec::new());
+        let big = vec![42u8; 3 * MAX_NOISE_MESSAGE];
+        client.send(big.clone().into()).await?;
+        client.send(b"small".to_vec().into()).await?;
+        assert_eq!(server.next().await.unwrap()?.to_vec(), big);
+        assert_eq!(server.next().await.unwrap()?.to_vec(), b"small".to_vec());
+        server.send(b"back".to_vec().into()).await?;
+        assert_eq!(client.next().await.unwrap()?.to_vec(), b"back".to_vec());
+
+        // Clients without a validator key stay anonymous
+        let ((_, server_id), (_, client_id)) = connect_pair(None, validator.clone()).await?;
+        assert_eq!(server_id.as_ref(), Some(validator.validator_pubkey()));
+        assert_eq!(client_id, None);
+        Ok(())
+    }
+}
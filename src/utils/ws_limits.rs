@@ -0,0 +1,190 @@
+//! Limits on the number of websocket connections a module accepts.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+
+use opentelemetry::{
+    metrics::{Counter, Gauge},
+    InstrumentationScope, KeyValue,
+};
+
+use crate::utils::conf::WebSocketConf;
+
+#[derive(Debug)]
+struct ConnectionCounts {
+    total: usize,
+    by_ip: HashMap<IpAddr, usize>,
+}
+
+/// Keeps track of active websocket connections, globally and per IP.
+#[derive(Debug)]
+pub struct WsConnectionLimiter {
+    max_connections: usize,
+    max_connections_per_ip: usize,
+    counts: Mutex<ConnectionCounts>,
+    active_connections: Gauge<u64>,
+    rejected_connections: Counter<u64>,
+}
+
+/// Held for as long as the websocket connection lives. Releases its slot on drop.
+#[derive(Debug)]
+pub struct WsConnectionPermit {
+    limiter: Arc<WsConnectionLimiter>,
+    ip: IpAddr,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WsLimitError {
+    TooManyConnections,
+    TooManyConnectionsForIp,
+}
+
+impl WsConnectionLimiter {
+    /// Metrics are named after `prefix`, e.g. `indexer_ws_active_connections`.
+    pub fn new(node_name: String, prefix: &str, conf: &WebSocketConf) -> Self {
+        let scope = InstrumentationScope::builder(node_name).build();
+        let my_meter = opentelemetry::global::meter_with_scope(scope);
+
+        WsConnectionLimiter {
+            max_connections: conf.max_connections,
+            max_connections_per_ip: conf.max_connections_per_ip,
+            counts: Mutex::new(ConnectionCounts {
+                total: 0,
+                by_ip: HashMap::new(),
+            }),
+            active_connections: my_meter
+                .u64_gauge(format!("{prefix}_ws_active_connections"))
+                .build(),
+            rejected_connections: my_meter
+                .u64_counter(format!("{prefix}_ws_rejected_connections"))
+                .build(),
+        }
+    }
+
+    /// Reserve a connection slot for `ip`, or fail if a limit is reached.
+    pub fn try_acquire(self: &Arc<Self>, ip: IpAddr) -> Result<WsConnectionPermit, WsLimitError> {
+        let mut counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        let ip_count = counts.by_ip.get(&ip).copied().unwrap_or(0);
+        let res = if counts.total >= self.max_connections {
+            Err(WsLimitError::TooManyConnections)
+        } else if ip_count >= self.max_connections_per_ip {
+            Err(WsLimitError::TooManyConnectionsForIp)
+        } else {
+            Ok(())
+        };
+
+        if let Err(e) = res {
+            let reason = match e {
+                WsLimitError::TooManyConnections => "global",
+                WsLimitError::TooManyConnectionsForIp => "per_ip",
+            };
+            self.rejected_connections
+                .add(1, &[KeyValue::new("reason", reason)]);
+            return Err(e);
+        }
+
+        counts.total += 1;
+        counts.by_ip.insert(ip, ip_count + 1);
+        self.active_connections.record(counts.total as u64, &[]);
+
+        Ok(WsConnectionPermit {
+            limiter: Arc::clone(self),
+            ip,
+        })
+    }
+
+    /// Number of currently active connections.
+    pub fn active(&self) -> usize {
+        match self.counts.lock() {
+            Ok(counts) => counts.total,
+            Err(poisoned) => poisoned.into_inner().total,
+        }
+    }
+
+    fn release(&self, ip: &IpAddr) {
+        let mut counts = match self.counts.lock() {
+            Ok(counts) => counts,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        counts.total = counts.total.saturating_sub(1);
+        if let Some(count) = counts.by_ip.get_mut(ip) {
+            *count = count.saturating_sub(1);
+            if *count == 0 {
+                counts.by_ip.remove(ip);
+            }
+        }
+        self.active_connections.record(counts.total as u64, &[]);
+    }
+}
+
+impl Drop for WsConnectionPermit {
+    fn drop(&mut self) {
+        self.limiter.release(&self.ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    fn limiter(max_connections: usize, max_connections_per_ip: usize) -> Arc<WsConnectionLimiter> {
+        Arc::new(WsConnectionLimiter::new(
+            "test".to_string(),
+            "test",
+            &WebSocketConf {
+                max_connections,
+                max_connections_per_ip,
+            },
+        ))
+    }
+
+    #[test]
+    fn test_per_ip_limit() {
+        let limiter = limiter(10, 2);
+        let ip = IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4));
+        let other_ip = IpAddr::V4(Ipv4Addr::new(5, 6, 7, 8));
+
+        let p1 = limiter.try_acquire(ip).unwrap();
+        let _p2 = limiter.try_acquire(ip).unwrap();
+        assert_eq!(
+            limiter.try_acquire(ip).unwrap_err(),
+            WsLimitError::TooManyConnectionsForIp
+        );
+        let _p3 = limiter.try_acquire(other_ip).unwrap();
+
+        drop(p1);
+        let _p4 = limiter.try_acquire(ip).unwrap();
+        assert_eq!(limiter.active(), 3);
+    }
+
+    #[test]
+    fn test_global_limit() {
+        let limiter = limiter(2, 2);
+        let _p1 = limiter
+            .try_acquire(IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1)))
+            .unwrap();
+        let p2 = limiter
+            .try_acquire(IpAddr::V4(Ipv4Addr::new(2, 2, 2, 2)))
+            .unwrap();
+        assert_eq!(
+            limiter
+                .try_acquire(IpAddr::V4(Ipv4Addr::new(3, 3, 3, 3)))
+                .unwrap_err(),
+            WsLimitError::TooManyConnections
+        );
+        drop(p2);
+        assert_eq!(limiter.active(), 1);
+        let _p3 = limiter
+            .try_acquire(IpAddr::V4(Ipv4Addr::new(3, 3, 3, 3)))
+            .unwrap();
+    }
+}
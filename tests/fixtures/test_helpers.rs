@@ -51,7 +51,7 @@ impl Default for ConfMaker {
         default.tcp_server_address = Some(format!("localhost:{}", random_port + 2000));
         default.rest = format!("localhost:{}", random_port + 3000);
         default.run_indexer = false; // disable indexer by default to avoid needed PG
-        default.log_format = "node".to_string(); // Activate node name in logs for convenience in tests.
+        default.log.format = "node".to_string(); // Activate node name in logs for convenience in tests.
         info!("Default conf: {:?}", default);
         default.consensus = Consensus {
             slot_duration: 1,
@@ -61,6 +61,7 @@ impl Default for ConfMaker {
                 stakers.insert("node-2".to_owned(), 100);
                 stakers
             },
+            ..Default::default()
         };
         info!("Default conf: {:?}", default);
         Self {